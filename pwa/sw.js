@@ -0,0 +1,61 @@
+// Keeps the app shell and the example gallery for offline use. Every name
+// here is stable because Trunk.toml turns off file hashing; bump CACHE when
+// the list changes.
+const CACHE = "convolution-v1";
+const SHELL = [
+  "./",
+  "index.html",
+  "manifest.webmanifest",
+  "icon-192.png",
+  "icon-512.png",
+  "convolution_wasm.js",
+  "convolution_wasm_bg.wasm",
+  "convolution_worker.js",
+  "convolution_worker_bg.wasm",
+  "convolution_worker_loader.js",
+  "gallery/edges_3x3.png",
+  "gallery/gabor_15x15.png",
+  "gallery/blobs_11x11.png",
+];
+
+self.addEventListener("install", (event) => {
+  // A missing file, such as the worker in builds without one, must not
+  // keep the rest from being cached.
+  event.waitUntil(
+    caches
+      .open(CACHE)
+      .then((cache) => Promise.allSettled(SHELL.map((url) => cache.add(url))))
+      .then(() => self.skipWaiting()),
+  );
+});
+
+self.addEventListener("activate", (event) => {
+  event.waitUntil(
+    caches
+      .keys()
+      .then((keys) => Promise.all(keys.filter((k) => k !== CACHE).map((k) => caches.delete(k))))
+      .then(() => self.clients.claim()),
+  );
+});
+
+// Network first, so a deployed update shows up on the next load, and the
+// cached copy when offline.
+self.addEventListener("fetch", (event) => {
+  const request = event.request;
+  if (request.method !== "GET" || new URL(request.url).origin !== self.location.origin) {
+    return;
+  }
+  event.respondWith(
+    fetch(request)
+      .then((response) => {
+        if (response.ok) {
+          const copy = response.clone();
+          caches.open(CACHE).then((cache) => cache.put(request, copy));
+        }
+        return response;
+      })
+      .catch(() =>
+        caches.match(request, { ignoreSearch: true }).then((cached) => cached || Response.error()),
+      ),
+  );
+});
@@ -0,0 +1,123 @@
+use crate::stats::{CompensatedSum, compensated_sum};
+
+/// 2-D autocorrelation of a response map over lags in `-max_lag..=max_lag`.
+pub struct Autocorrelation {
+    /// Side length of `values` (`2 * max_lag + 1`).
+    pub size: usize,
+    /// Row-major correlation coefficients; the zero lag sits in the center
+    /// and equals 1.
+    pub values: Vec<f32>,
+    /// Strongest off-center peak as `(dx, dy)` lag in map pixels, if any.
+    pub peak: Option<(isize, isize)>,
+}
+
+impl Autocorrelation {
+    /// Length of the dominant repeat, in map pixels.
+    pub fn period(&self) -> Option<f32> {
+        self.peak
+            .map(|(dx, dy)| ((dx * dx + dy * dy) as f32).sqrt())
+    }
+
+    /// Direction of the dominant repeat in degrees, in `[0, 180)`.
+    pub fn period_angle_deg(&self) -> Option<f32> {
+        self.peak
+            .map(|(dx, dy)| (dy as f32).atan2(dx as f32).to_degrees().rem_euclid(180.0))
+    }
+}
+
+/// Computes the mean-removed, variance-normalized autocorrelation by direct
+/// summation. Intended for maps already downsampled to a few hundred pixels.
+pub fn autocorrelation(
+    values: &[f32],
+    width: usize,
+    height: usize,
+    max_lag: usize,
+) -> Autocorrelation {
+    let max_lag = max_lag.min(width.max(height).saturating_sub(1));
+    let size = 2 * max_lag + 1;
+    let n = values.len().max(1) as f64;
+    let mean = compensated_sum(values.iter().map(|&v| v as f64)) / n;
+    let centered: Vec<f64> = values.iter().map(|&v| v as f64 - mean).collect();
+    let variance = compensated_sum(centered.iter().map(|v| v * v)) / n;
+
+    let mut out = vec![0.0f32; size * size];
+    if variance > 0.0 {
+        for (li, lag_y) in (-(max_lag as isize)..=max_lag as isize).enumerate() {
+            for (lj, lag_x) in (-(max_lag as isize)..=max_lag as isize).enumerate() {
+                let mut acc = CompensatedSum::default();
+                let mut count = 0usize;
+                for y in 0..height as isize {
+                    let y2 = y + lag_y;
+                    if y2 < 0 || y2 >= height as isize {
+                        continue;
+                    }
+                    for x in 0..width as isize {
+                        let x2 = x + lag_x;
+                        if x2 < 0 || x2 >= width as isize {
+                            continue;
+                        }
+                        acc.add(
+                            centered[y as usize * width + x as usize]
+                                * centered[y2 as usize * width + x2 as usize],
+                        );
+                        count += 1;
+                    }
+                }
+                if count > 0 {
+                    out[li * size + lj] = (acc.value() / count as f64 / variance) as f32;
+                }
+            }
+        }
+    }
+
+    let peak = dominant_peak(&out, max_lag);
+    Autocorrelation {
+        size,
+        values: out,
+        peak,
+    }
+}
+
+/// Finds the highest autocorrelation value outside the central lobe, where
+/// the central lobe extends out to the first radius whose mean correlation
+/// stops decreasing.
+fn dominant_peak(values: &[f32], max_lag: usize) -> Option<(isize, isize)> {
+    let size = 2 * max_lag + 1;
+    let c = max_lag as isize;
+    let mut radial_sum = vec![0.0f32; max_lag + 2];
+    let mut radial_count = vec![0u32; max_lag + 2];
+    for y in 0..size as isize {
+        for x in 0..size as isize {
+            let r = (((x - c).pow(2) + (y - c).pow(2)) as f32).sqrt().round() as usize;
+            if r < radial_sum.len() {
+                radial_sum[r] += values[y as usize * size + x as usize];
+                radial_count[r] += 1;
+            }
+        }
+    }
+    let radial: Vec<f32> = radial_sum
+        .iter()
+        .zip(&radial_count)
+        .map(|(s, &n)| if n > 0 { s / n as f32 } else { 0.0 })
+        .collect();
+    let lobe = (1..=max_lag).find(|&r| radial[r + 1] >= radial[r])?;
+
+    let mut best: Option<((isize, isize), f32)> = None;
+    for y in 0..size as isize {
+        for x in 0..size as isize {
+            let (dx, dy) = (x - c, y - c);
+            if ((dx * dx + dy * dy) as f32).sqrt() < lobe as f32 {
+                continue;
+            }
+            // Lags (dx, dy) and (-dx, -dy) are equivalent; keep one half.
+            if dy < 0 || (dy == 0 && dx < 0) {
+                continue;
+            }
+            let v = values[y as usize * size + x as usize];
+            if best.is_none_or(|(_, b)| v > b) {
+                best = Some(((dx, dy), v));
+            }
+        }
+    }
+    best.filter(|&(_, v)| v > 0.0).map(|(lag, _)| lag)
+}
@@ -1,34 +1,480 @@
 use eframe::egui;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::mpsc::{self, Receiver};
+
 use egui::{ColorImage, TextureHandle, TextureOptions};
-use image::GrayImage;
+use image::{GrayImage, RgbImage};
+#[cfg(not(target_arch = "wasm32"))]
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use web_time::{Duration, Instant};
+
+use crate::analysis::{self, Autocorrelation};
+use crate::backend::{self, Backend, BackendChoice, BackendProfile, Benchmark, Sampling};
+use crate::border::{self, BorderMode};
+#[cfg(target_arch = "wasm32")]
+use crate::browser_cache;
+use crate::calibration::Calibration;
+use crate::channels::{self, ChannelMode};
+#[cfg(target_arch = "wasm32")]
+use crate::chunked::ChunkedRun;
+use crate::config::{self, CONFIG_FILE, Profile, Startup};
+use crate::control::{self, ControlSlide, RATIO_OCTAVES};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::cpu_run::{self, CpuRun};
+use crate::export::{self, CsvDelimiter, CsvFormat, DecimalSeparator, ScoreRow};
+use crate::figure::{self, FigureFont, FigureFormat, FigureStyle};
+use crate::file_dialog::{self, KERNELS_FILTER, PickedFile, SESSION_FILTER, SLIDE_FILTER};
+use crate::flythrough::{self, Keyframe, VideoFormat};
+use crate::gallery::{self, GALLERY};
+#[cfg(target_arch = "wasm32")]
+use crate::gpu::BrowserGpu;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::gpu::NativeGpu;
+use crate::gpu::{GpuConvolver, GpuRun};
+use crate::groups;
+use crate::imaging::{Roi, downsample_box, gray_to_f32};
+use crate::kernel::{
+    self, Kernel, KernelChange, KernelNormalization, KernelOperation, Provenance, RevisionDiff,
+    SheetRemainder,
+};
+use crate::kernel_file;
+use crate::morphology::FilterMode;
+use crate::notify;
+use crate::numbers::NumberFormat;
+use crate::orientation;
+use crate::palette::{Colormap, OverlayColors};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::parallel;
+use crate::peaks::{self, Peak, PeakMarker};
+use crate::pipeline::{self, Pipeline};
+use crate::presets::PresetOptions;
+#[cfg(target_arch = "wasm32")]
+use crate::pwa;
+use crate::quick;
+use crate::registration::{self, RigidTransform};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::response_store::ResponseStore;
+use crate::scoring::{self, ScoreBaseline, ScoreNormalization, Significance};
+use crate::seams::{self, SeamReport};
+use crate::selftest::{self, SelfTestReport};
+use crate::session::{self, SESSION_EXTENSION, Session, SessionImage};
+use crate::share::{self, SharedRun, SharedScore};
+use crate::sparse::{self, SparseMap, SparseStore};
+use crate::stain::{self, StainNormalization, StainReference};
+use crate::stats::{self, ResponseHighlights, ResponseStats};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::streaming::{self, StreamJob};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::taskbar::TaskbarProgress;
+use crate::teaching::{Lesson, MAX_REGION_SIDE};
+use crate::triage;
+use crate::usage::{Stage, UsageLog};
+use crate::winners::{self, Winners};
 
+pub const APP_TITLE: &str = "WASM Convolution Explorer";
+/// Self-test label of the wgpu path.
+#[cfg(target_arch = "wasm32")]
+const GPU_CHECK_LABEL: &str = "WebGPU";
+#[cfg(not(target_arch = "wasm32"))]
+const GPU_CHECK_LABEL: &str = "GPU";
 const PREVIEW_MAX_SIZE: usize = 256;
+/// Kernels the winner map's legend lists, those winning most pixels first.
+const WINNER_LEGEND_ROWS: usize = 12;
+/// Least zoom of the slide viewer on a peak picked from the list.
+const PEAK_ZOOM: f32 = 4.0;
+/// Longest side of the thumbnails in the preview grid, in pixels.
+const THUMBNAIL_SIZE: usize = 64;
+/// Zoom factor of image viewers per point of scrolling, as an exponent.
+const ZOOM_PER_SCROLL_POINT: f32 = 0.005;
+/// Largest viewer zoom over the fitted image.
+const MAX_ZOOM: f32 = 64.0;
+/// Extension of project files: pipelines saved for the installed web app
+/// to open from the file manager.
+const PROJECT_EXTENSION: &str = ".convproj";
+/// Storage key of [`UiState`], next to the settings under
+/// [`eframe::APP_KEY`].
+const UI_STATE_KEY: &str = "ui_state";
+/// Viewport width, in points, below which the automatic layout is compact:
+/// phones, and tablets held upright.
+const COMPACT_BELOW_WIDTH: f32 = 760.0;
+/// Downsampling factor applied to the slide in draft mode.
+const DRAFT_FACTOR: usize = 4;
+/// Responses are downsampled to at most this size before autocorrelation.
+const AUTOCORRELATION_MAP_SIZE: usize = 128;
+const AUTOCORRELATION_MAX_LAG: usize = 32;
+const FLYTHROUGH_FRAME_WIDTH: u32 = 640;
+const SCORE_PLOT_HEIGHT: f32 = 140.0;
+/// Side of a cell of the weight inspector, in points.
+const WEIGHT_CELL_SIZE: f32 = 30.0;
+/// Width of a typed-value cell, wide enough for three decimals and a sign.
+const NUMERIC_CELL_WIDTH: f32 = 48.0;
+/// Weight change per point of scrolling or vertical dragging over a cell.
+const SCROLL_WEIGHT_STEP: f32 = 0.001;
+const DRAG_WEIGHT_STEP: f32 = 0.01;
+/// Quiet time after the last weight edit before the response is updated.
+const EDIT_DEBOUNCE: Duration = Duration::from_millis(250);
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub enum KernelShape {
-    ThreeBySix,
-    SixByThree,
+/// Longest kernel side the shape editor accepts.
+const MAX_KERNEL_SIDE: usize = 64;
+
+/// Size of the tiles a kernels sheet is split into. Stored as `"WxH"`, the
+/// form the two original shapes were written in, so older settings, configs
+/// and pipelines still load.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct KernelShape {
+    width: usize,
+    height: usize,
 }
 
 impl KernelShape {
-    fn width(self) -> usize {
+    /// Shapes offered as one-click presets.
+    const PRESETS: [Self; 5] = [
+        Self::of(3, 6),
+        Self::of(6, 3),
+        Self::of(5, 5),
+        Self::of(7, 7),
+        Self::of(11, 11),
+    ];
+
+    pub(crate) fn new(width: usize, height: usize) -> Result<Self, String> {
+        let side = 1..=MAX_KERNEL_SIDE;
+        if !side.contains(&width) || !side.contains(&height) {
+            return Err(format!(
+                "Kernel size {width}x{height} is outside 1 to {MAX_KERNEL_SIDE} per side."
+            ));
+        }
+        Ok(Self { width, height })
+    }
+
+    const fn of(width: usize, height: usize) -> Self {
+        Self { width, height }
+    }
+
+    pub(crate) fn width(self) -> usize {
+        self.width
+    }
+
+    pub(crate) fn height(self) -> usize {
+        self.height
+    }
+
+    fn label(self) -> String {
+        format!("{} x {}", self.width, self.height)
+    }
+
+    /// Columns and rows of pixels a sheet of `sheet_width` x `sheet_height`
+    /// leaves past its last whole tiles of this shape.
+    fn sheet_remainder(self, sheet_width: u32, sheet_height: u32) -> (u32, u32) {
+        let (kw, kh) = (self.width as u32, self.height as u32);
+        (sheet_width % kw, sheet_height % kh)
+    }
+}
+
+impl Default for KernelShape {
+    fn default() -> Self {
+        Self::PRESETS[0]
+    }
+}
+
+impl From<KernelShape> for String {
+    fn from(shape: KernelShape) -> Self {
+        format!("{}x{}", shape.width, shape.height)
+    }
+}
+
+impl TryFrom<String> for KernelShape {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, String> {
+        let parse = |side: &str| side.trim().parse::<usize>().ok();
+        if let Some((w, h)) = text.split_once(['x', 'X'])
+            && let (Some(w), Some(h)) = (parse(w), parse(h))
+        {
+            return Self::new(w, h);
+        }
+        Err(format!("Kernel shape \"{text}\" is not of the form WxH."))
+    }
+}
+
+/// Cells that follow an edited weight in the inspector.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum MirrorEdit {
+    #[default]
+    Off,
+    /// Left-right mirror image.
+    Horizontal,
+    /// Top-bottom mirror image.
+    Vertical,
+    Both,
+}
+
+impl MirrorEdit {
+    const ALL: [Self; 4] = [Self::Off, Self::Horizontal, Self::Vertical, Self::Both];
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Off => "Off",
+            Self::Horizontal => "Mirror left-right",
+            Self::Vertical => "Mirror top-bottom",
+            Self::Both => "Both",
+        }
+    }
+
+    /// The edited cell and its mirror images, possibly repeated.
+    fn cells(self, x: usize, y: usize, kw: usize, kh: usize) -> Vec<(usize, usize)> {
+        let (mx, my) = (kw - 1 - x, kh - 1 - y);
+        match self {
+            Self::Off => vec![(x, y)],
+            Self::Horizontal => vec![(x, y), (mx, y)],
+            Self::Vertical => vec![(x, y), (x, my)],
+            Self::Both => vec![(x, y), (mx, y), (x, my), (mx, my)],
+        }
+    }
+}
+
+/// Image slot a loaded file is assigned to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Slot {
+    Slide,
+    KernelsSheet,
+    /// Serial section compared against the slide after registration.
+    SecondSlide,
+}
+
+impl Slot {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Slide => "Slide",
+            Self::KernelsSheet => "Kernels sheet",
+            Self::SecondSlide => "Second slide",
+        }
+    }
+
+    /// Name the slot's image is kept under in browser storage.
+    #[cfg(target_arch = "wasm32")]
+    fn cache_key(self) -> &'static str {
+        match self {
+            Self::Slide => "slide",
+            Self::KernelsSheet => "kernels_sheet",
+            Self::SecondSlide => "second_slide",
+        }
+    }
+
+    fn texture_name(self) -> &'static str {
         match self {
-            Self::ThreeBySix => 3,
-            Self::SixByThree => 6,
+            Self::Slide => "slide_texture",
+            Self::KernelsSheet => "kernel_texture",
+            Self::SecondSlide => "second_slide_texture",
+        }
+    }
+}
+
+/// Browser storage names of every slot, in the order they are restored.
+#[cfg(target_arch = "wasm32")]
+const CACHE_KEYS: [&str; 3] = ["slide", "kernels_sheet", "second_slide"];
+
+/// What a lesson was built from; a change rebuilds it from the start.
+#[derive(Clone, PartialEq)]
+struct LessonKey {
+    kernel: usize,
+    slide: u64,
+    region: Roi,
+    border: BorderMode,
+    weights: Vec<f32>,
+    mask: Option<Vec<bool>>,
+}
+
+/// Teaching mode, which animates a kernel sliding over a few pixels of the
+/// slide.
+struct Teaching {
+    open: bool,
+    /// Top-left corner of the region, in slide pixels.
+    origin: [usize; 2],
+    side: usize,
+    playing: bool,
+    taps_per_second: f32,
+    /// Part of a tap left over from the last frame's advance.
+    carry: f32,
+    lesson: Option<(LessonKey, Lesson)>,
+}
+
+impl Default for Teaching {
+    fn default() -> Self {
+        Self {
+            open: false,
+            origin: [0, 0],
+            side: 8,
+            playing: false,
+            taps_per_second: 8.0,
+            carry: 0.0,
+            lesson: None,
         }
     }
+}
+
+/// How the window is laid out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+enum LayoutMode {
+    /// Compact on viewports narrower than [`COMPACT_BELOW_WIDTH`].
+    #[default]
+    Auto,
+    /// Controls beside the images, which sit side by side.
+    Wide,
+    /// Controls collapse behind a button, images stack in one scrolling
+    /// column, and widgets grow to touch size.
+    Compact,
+}
+
+impl LayoutMode {
+    const ALL: [Self; 3] = [Self::Auto, Self::Wide, Self::Compact];
 
-    fn height(self) -> usize {
+    fn label(self) -> &'static str {
         match self {
-            Self::ThreeBySix => 6,
-            Self::SixByThree => 3,
+            Self::Auto => "Auto",
+            Self::Wide => "Wide",
+            Self::Compact => "Compact",
+        }
+    }
+}
+
+/// Zoom and pan of an image viewer. At zoom 1 the whole image fits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ZoomPan {
+    zoom: f32,
+    /// Image point shown at the middle of the viewer, in `0..=1` on both
+    /// axes.
+    center: egui::Vec2,
+}
+
+impl Default for ZoomPan {
+    fn default() -> Self {
+        Self {
+            zoom: 1.0,
+            center: egui::vec2(0.5, 0.5),
         }
     }
+}
+
+impl ZoomPan {
+    /// Part of the image in view, in `0..=1` on both axes.
+    fn visible(&self) -> egui::Rect {
+        egui::Rect::from_center_size(self.center.to_pos2(), egui::Vec2::splat(1.0 / self.zoom))
+    }
+}
+
+/// Viewer positions and look saved under a name, to come back to a finding.
+#[derive(Clone)]
+struct Bookmark {
+    name: String,
+    slide_view: ZoomPan,
+    preview_view: ZoomPan,
+    link_zoom: bool,
+    kernel: usize,
+    overlay: bool,
+    opacity: f32,
+    colormap: Colormap,
+}
+
+/// How a zoomed-in preview region was drawn, to redraw it only when the view
+/// or the colors change.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct DetailKey {
+    /// Full-resolution pixels `x0, y0, x1, y1` in view.
+    region: [usize; 4],
+    /// Texture size; regions wider than the viewer are subsampled.
+    size: [usize; 2],
+    preview_colormap: Colormap,
+    colormap: Colormap,
+    overlay: Option<u32>,
+    calibration: Calibration,
+}
+
+/// Breakdown of one pixel of a kernel's map into the products its taps
+/// summed.
+struct Explanation {
+    kernel: usize,
+    /// Pixel of the map, which covers only the region of runs that have one.
+    pixel: (usize, usize),
+    lesson: Lesson,
+    /// The map's value there, when the full-resolution map is at hand.
+    map_value: Option<f32>,
+}
+
+/// Full-resolution map of the selected kernel, kept while its preview is
+/// zoomed in past the preview's own resolution.
+struct ZoomDetail {
+    kernel: usize,
+    response: Vec<f32>,
+    width: usize,
+    height: usize,
+    drawn: Option<(DetailKey, TextureHandle)>,
+}
+
+/// Column the score table is sorted by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ScoreColumn {
+    Kernel,
+    Score,
+    MeanAbs,
+    MaxAbs,
+    StdDev,
+    Energy,
+    Sparsity,
+}
+
+impl ScoreColumn {
+    const ALL: [Self; 7] = [
+        Self::Kernel,
+        Self::Score,
+        Self::MeanAbs,
+        Self::MaxAbs,
+        Self::StdDev,
+        Self::Energy,
+        Self::Sparsity,
+    ];
 
     fn label(self) -> &'static str {
         match self {
-            Self::ThreeBySix => "3 x 6",
-            Self::SixByThree => "6 x 3",
+            Self::Kernel => "Kernel",
+            Self::Score => "Score",
+            Self::MeanAbs => "Mean |r|",
+            Self::MaxAbs => "Max |r|",
+            Self::StdDev => "Std",
+            Self::Energy => "Energy",
+            Self::Sparsity => "Sparsity",
+        }
+    }
+
+    fn hover_text(self) -> &'static str {
+        match self {
+            Self::Kernel => "Kernel index",
+            Self::Score => "Score under the selected normalization",
+            Self::MeanAbs => "Mean absolute response",
+            Self::MaxAbs => "Largest absolute response",
+            Self::StdDev => "Standard deviation of the response",
+            Self::Energy => "Mean squared response",
+            Self::Sparsity => {
+                "Hoyer sparsity: 0 when every pixel responds equally, 1 when one pixel carries \
+                 the whole response"
+            }
+        }
+    }
+
+    fn value(self, index: usize, preview: &ConvolutionPreview) -> f32 {
+        match self {
+            Self::Kernel => index as f32,
+            Self::Score => preview.score,
+            Self::MeanAbs => preview.stats.mean_abs,
+            Self::MaxAbs => preview.stats.max_abs,
+            Self::StdDev => preview.stats.std_dev,
+            Self::Energy => preview.stats.energy,
+            Self::Sparsity => preview.stats.sparsity,
         }
     }
 }
@@ -36,289 +482,9061 @@ impl KernelShape {
 #[derive(Default)]
 struct LoadedImage {
     name: String,
+    /// Full path on native builds when known, the file name otherwise.
+    source: String,
+    /// Content hash of the file, recorded in exported pipelines.
+    hash: u64,
     gray: Option<GrayImage>,
+    /// Color pixels of color images, for Macenko normalization, channel
+    /// modes and color kernels.
+    rgb: Option<RgbImage>,
     texture: Option<TextureHandle>,
 }
 
 #[derive(Clone)]
 struct ConvolutionPreview {
+    /// `stats.mean_abs` under the current score normalization.
     score: f32,
+    /// 95% bootstrap interval of `stats.mean_abs` over tiles of the slide.
+    raw_interval: [f32; 2],
+    /// `raw_interval` under the current score normalization.
+    interval: [f32; 2],
+    stats: ResponseStats,
+    /// Peak and orientation, in full-resolution slide coordinates.
+    highlights: ResponseHighlights,
     width: usize,
     height: usize,
     bytes: Vec<u8>,
+    /// Response interval spread over the 0 to 255 of `bytes`.
+    range: (f32, f32),
+    /// False while the preview comes from the downsampled draft pass.
+    exact: bool,
+    /// Hash of the response bits; only meaningful for exact previews.
+    checksum: u64,
+    /// Permutation test result, once requested.
+    significance: Option<Significance>,
+    /// Colored image and texture of the preview, made the first time it is
+    /// shown and kept until its look changes.
+    display: Option<PreviewDisplay>,
 }
 
-pub struct ConvolutionApp {
-    slide: LoadedImage,
-    kernels_sheet: LoadedImage,
-    kernel_shape: KernelShape,
-    kernels: Vec<Vec<f32>>,
-    kernel_rows: usize,
-    kernel_cols: usize,
-    previews: Vec<ConvolutionPreview>,
-    selected_kernel: usize,
-    status: String,
+/// Settings a preview's colors depend on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct PreviewLook {
+    colormap: Colormap,
+    calibration: Calibration,
+    /// Slide hash, run region and opacity bits of the response overlay.
+    overlay: Option<(u64, Option<Roi>, u32)>,
 }
 
-impl Default for ConvolutionApp {
-    fn default() -> Self {
-        Self {
-            slide: LoadedImage::default(),
-            kernels_sheet: LoadedImage::default(),
-            kernel_shape: KernelShape::ThreeBySix,
-            kernels: Vec::new(),
-            kernel_rows: 0,
-            kernel_cols: 0,
-            previews: Vec::new(),
-            selected_kernel: 0,
-            status: "Drop two PNG files in the window: first the histological slide, then the kernels sheet.".to_owned(),
-        }
-    }
+#[derive(Clone)]
+struct PreviewDisplay {
+    look: PreviewLook,
+    image: ColorImage,
+    texture: TextureHandle,
+    /// Shrunk copy for the thumbnail grid, made when first shown there.
+    thumbnail: Option<TextureHandle>,
 }
 
-impl ConvolutionApp {
-    pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        Self::default()
+/// Full-resolution inputs kept after a run so drafts can be refined a
+/// kernel at a time between frames and analyses can recompute responses
+/// that were not kept.
+struct RunContext {
+    /// Intensities single-channel code reads; the mean of `planes` when
+    /// they are set. Shared with background runs.
+    input: Arc<[f32]>,
+    /// Red, green and blue planes that kernels run over in multi-channel
+    /// modes; empty otherwise, and for gray slides.
+    planes: Arc<[Vec<f32>]>,
+    channels: ChannelMode,
+    /// `width` x `height` is the region of interest's size when `roi` is
+    /// set, and the responses cover only that region.
+    width: usize,
+    height: usize,
+    roi: Option<Roi>,
+    /// NaN and infinite input pixels replaced with 0 before the run.
+    repaired: usize,
+    /// Tile size of the sheet; kernels built in the app may differ.
+    kw: usize,
+    kh: usize,
+    backend: Backend,
+    mode: FilterMode,
+    border: BorderMode,
+    /// Pixels the maps keep and the spacing of their taps.
+    sampling: Sampling,
+    /// `input` at the pixels the maps keep, which their statistics pair
+    /// with; `input` itself in dense runs.
+    map_input: Arc<[f32]>,
+    activation_k: f32,
+    strict: bool,
+    /// Reference slide the input was normalized to, if any.
+    stain_reference: Option<String>,
+    image_std: f32,
+    /// Random-kernel score distributions per kernel shape and mask, computed
+    /// the first time a baseline-relative normalization is used.
+    baselines: Vec<(BaselineSupport, ScoreBaseline)>,
+    /// Exact responses computed so far, when kept on disk.
+    #[cfg(not(target_arch = "wasm32"))]
+    responses: Option<ResponseStore>,
+    /// Active pixels of the exact maps computed so far, with sparse maps on.
+    sparse: Option<SparseStore>,
+}
+
+/// The run's input and parameters that bare weights are convolved with.
+#[derive(Clone)]
+struct WeightsConvolver {
+    input: Arc<[f32]>,
+    width: usize,
+    height: usize,
+    backend: Backend,
+    border: BorderMode,
+    sampling: Sampling,
+}
+
+impl WeightsConvolver {
+    fn convolve(&self, weights: &[f32], kw: usize, kh: usize) -> Vec<f32> {
+        let sampling = self.sampling;
+        border::filter_image_strided(
+            self.border,
+            &self.input,
+            self.width,
+            self.height,
+            sampling.extent(kw, kh),
+            sampling.stride,
+            |i, w, h| {
+                self.backend
+                    .convolve_sampled(i, w, h, weights, kw, kh, sampling)
+            },
+        )
     }
+}
 
-    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
-        let dropped = ctx.input(|i| i.raw.dropped_files.clone());
-        if dropped.is_empty() {
-            return;
+impl RunContext {
+    /// Keeps kernel `index`'s exact map in the stores the run has.
+    fn keep(&mut self, index: usize, response: &[f32]) {
+        let width = self.map_size().0;
+        if let Some(sparse) = &mut self.sparse {
+            sparse.insert(index, SparseMap::active(response, width, self.activation_k));
         }
+        #[cfg(not(target_arch = "wasm32"))]
+        keep_response(&mut self.responses, index, response);
+    }
 
-        for file in dropped {
-            if let Some(bytes) = extract_bytes(&file) {
-                if self.slide.gray.is_none() {
-                    self.load_png_into_slot(ctx, bytes, file.name, true);
-                } else if self.kernels_sheet.gray.is_none() {
-                    self.load_png_into_slot(ctx, bytes, file.name, false);
-                } else {
-                    self.status = "Both image slots are already filled. Use Reset to load different files.".to_owned();
-                }
-            } else {
-                self.status = "Could not read dropped file bytes.".to_owned();
-            }
+    /// Forgets kernel `index`'s kept map, which no longer matches it.
+    fn forget(&mut self, index: usize) {
+        if let Some(sparse) = &mut self.sparse {
+            sparse.remove(index);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(responses) = &mut self.responses {
+            responses.remove(index);
         }
     }
 
-    fn load_png_into_slot(
-        &mut self,
-        ctx: &egui::Context,
-        bytes: Vec<u8>,
-        file_name: String,
-        is_slide: bool,
-    ) {
-        match image::load_from_memory(&bytes) {
-            Ok(img) => {
-                let gray = img.to_luma8();
-                let color = gray_to_color_image(&gray);
-                let texture = ctx.load_texture(
-                    if is_slide { "slide_texture" } else { "kernel_texture" },
-                    color,
-                    TextureOptions::LINEAR,
-                );
+    /// Size of the maps: the input's, or smaller with a stride.
+    fn map_size(&self) -> (usize, usize) {
+        self.sampling.output_size(self.width, self.height)
+    }
 
-                let target = if is_slide {
-                    &mut self.slide
-                } else {
-                    &mut self.kernels_sheet
-                };
+    /// Response of bare `weights`, a `kw` x `kh` window, read through the
+    /// run's border at its sampling, as shuffles and baselines are scored.
+    fn convolve_weights(&self, weights: &[f32], kw: usize, kh: usize) -> Vec<f32> {
+        self.weights_convolver().convolve(weights, kw, kh)
+    }
 
-                target.name = file_name;
-                target.gray = Some(gray);
-                target.texture = Some(texture);
-                self.kernels.clear();
-                self.previews.clear();
-                self.selected_kernel = 0;
-                self.status = "Image loaded. Choose kernel shape and press Split kernels.".to_owned();
-            }
-            Err(e) => {
-                self.status = format!("Failed to decode PNG: {e}");
-            }
+    /// What [`RunContext::convolve_weights`] reads, for other threads.
+    fn weights_convolver(&self) -> WeightsConvolver {
+        WeightsConvolver {
+            input: self.input.clone(),
+            width: self.width,
+            height: self.height,
+            backend: self.backend,
+            border: self.border,
+            sampling: self.sampling,
         }
     }
 
-    fn split_kernels(&mut self) {
-        let Some(sheet) = self.kernels_sheet.gray.as_ref() else {
-            self.status = "Load the kernels sheet first.".to_owned();
-            return;
-        };
+    /// Side in map pixels of `tile`-pixel blocks of the slide; `None` when
+    /// the stride does not divide it, as blocks would then split map pixels.
+    fn map_tile(&self, tile: usize) -> Option<usize> {
+        let stride = self.sampling.stride;
+        tile.is_multiple_of(stride).then(|| tile / stride)
+    }
 
-        let kw = self.kernel_shape.width() as u32;
-        let kh = self.kernel_shape.height() as u32;
-        if sheet.width() % kw != 0 || sheet.height() % kh != 0 {
-            self.status = format!(
-                "Kernel sheet size {}x{} is not divisible by kernel size {}x{}.",
-                sheet.width(),
-                sheet.height(),
-                kw,
-                kh
-            );
-            return;
+    /// Asks for a tile size the stride divides, when `tile` is not one.
+    fn check_tile(&self, tile: usize) -> Result<(), String> {
+        match self.map_tile(tile) {
+            Some(_) => Ok(()),
+            None => Err(format!(
+                "Pick a tile size that is a multiple of the stride ({}).",
+                self.sampling.stride
+            )),
         }
+    }
 
-        self.kernel_cols = (sheet.width() / kw) as usize;
-        self.kernel_rows = (sheet.height() / kh) as usize;
-        self.kernels.clear();
-        self.previews.clear();
-        self.selected_kernel = 0;
+    fn exact_preview(&self, response: &[f32]) -> ConvolutionPreview {
+        let size = (self.width, self.height);
+        build_exact_preview(
+            response,
+            &self.map_input,
+            size,
+            self.sampling,
+            self.activation_k,
+        )
+    }
 
-        for row in 0..self.kernel_rows {
-            for col in 0..self.kernel_cols {
-                let mut kernel = Vec::with_capacity((kw * kh) as usize);
-                for ky in 0..kh {
-                    for kx in 0..kw {
-                        let px = sheet.get_pixel(col as u32 * kw + kx, row as u32 * kh + ky)[0];
-                        let centered = (px as f32 / 255.0) * 2.0 - 1.0;
-                        kernel.push(centered);
-                    }
-                }
-                self.kernels.push(kernel);
-            }
+    /// Full-resolution response of `kernel` on the CPU.
+    fn respond(&self, kernel: &Kernel) -> Vec<f32> {
+        self.respond_to(kernel, &self.input, &self.planes, self.width, self.height)
+    }
+
+    /// Browser run of `kernels` with the run's sampling and color planes.
+    #[cfg(target_arch = "wasm32")]
+    fn chunked_run(&self, kernels: Vec<(usize, Kernel)>) -> ChunkedRun {
+        ChunkedRun::new(kernels, self.border, self.width, self.height).sampled(
+            self.sampling,
+            self.channels,
+            self.planes.clone(),
+        )
+    }
+
+    /// Response of `kernel` to `input`, or to `planes` when set, in the
+    /// run's mode.
+    fn respond_to(
+        &self,
+        kernel: &Kernel,
+        input: &[f32],
+        planes: &[Vec<f32>],
+        width: usize,
+        height: usize,
+    ) -> Vec<f32> {
+        let (mode, backend, border, sampling) =
+            (self.mode, self.backend, self.border, self.sampling);
+        if planes.is_empty() {
+            return kernel.filter_sampled(mode, backend, border, sampling, input, width, height);
         }
+        channels::filter(
+            kernel,
+            self.channels,
+            mode,
+            backend,
+            border,
+            sampling,
+            planes,
+            width,
+            height,
+        )
+    }
 
-        self.status = format!(
-            "Split into {} kernels ({} rows x {} cols).",
-            self.kernels.len(),
-            self.kernel_rows,
-            self.kernel_cols
+    /// Whether the maps of `previous`, the run over the region before it
+    /// moved, carry over to `self`: both are dense single-plane runs in one
+    /// mode on a direct CPU backend over regions of one size, which read the
+    /// same intensities where they overlap. They do not when stain
+    /// normalization fitted each region on its own, nor on the FFT, whose
+    /// rounding depends on the whole region.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn carries_over(&self, previous: &RunContext) -> bool {
+        let (Some(to), Some(from)) = (self.roi, previous.roi) else {
+            return false;
+        };
+        let plain = |run: &RunContext| {
+            run.planes.is_empty() && run.sampling.is_dense() && run.backend != Backend::Fft
+        };
+        if !plain(self)
+            || !plain(previous)
+            || self.mode != previous.mode
+            || (self.width, self.height) != (previous.width, previous.height)
+        {
+            return false;
+        }
+        let (x0, x1) = (to.x.max(from.x), (to.x + to.width).min(from.x + from.width));
+        let (y0, y1) = (
+            to.y.max(from.y),
+            (to.y + to.height).min(from.y + from.height),
         );
+        if x0 >= x1 || y0 >= y1 {
+            return false;
+        }
+        let row = |roi: Roi, y: usize| {
+            let start = (y - roi.y) * self.width + x0 - roi.x;
+            start..start + x1 - x0
+        };
+        (y0..y1).all(|y| self.input[row(to, y)] == previous.input[row(from, y)])
     }
 
-    fn run_all_convolutions(&mut self) {
-        let Some(slide) = self.slide.gray.as_ref() else {
-            self.status = "Load the histological slide first.".to_owned();
+    /// Input and planes at 1/`DRAFT_FACTOR` resolution, with their size.
+    fn draft(&self) -> (Vec<f32>, Vec<Vec<f32>>, usize, usize) {
+        let (draft, dw, dh) = downsample_box(&self.input, self.width, self.height, DRAFT_FACTOR);
+        let planes = self
+            .planes
+            .iter()
+            .map(|plane| downsample_box(plane, self.width, self.height, DRAFT_FACTOR).0)
+            .collect();
+        (draft, planes, dw, dh)
+    }
+}
+
+/// Parameters and per-kernel results of a run, exported next to its outputs
+/// so they can be traced back and reproduced.
+#[derive(Serialize)]
+struct RunManifest {
+    app_version: &'static str,
+    platform: String,
+    slide: String,
+    kernels_sheet: String,
+    /// Content hashes of the slide and the kernels sheet or file, as in
+    /// exported pipelines.
+    slide_hash: String,
+    kernels_sheet_hash: String,
+    image_width: usize,
+    image_height: usize,
+    /// Slide region the maps cover, when not the whole slide.
+    roi: Option<Roi>,
+    kernel_width: usize,
+    kernel_height: usize,
+    backend: &'static str,
+    strict_reproducibility: bool,
+    mode: &'static str,
+    border: BorderMode,
+    /// Gain, offset and scale of the exported maps, when calibrated.
+    calibration: Option<Calibration>,
+    /// Spacing of the kept pixels and of the kernels' taps; both 1 in dense
+    /// runs, whose maps are the slide's size.
+    stride: usize,
+    dilation: usize,
+    map_width: usize,
+    map_height: usize,
+    /// Normalization of the tiles when the sheet was split.
+    kernel_normalization: &'static str,
+    activation_k: f32,
+    score_normalization: &'static str,
+    stain_normalization: &'static str,
+    stain_reference: Option<String>,
+    /// Color channels convolved; luma for gray slides in any mode.
+    channels: &'static str,
+    /// NaN and infinite input pixels replaced with 0.
+    repaired_pixels: usize,
+    kernels: Vec<ManifestKernel>,
+    bookmarks: Vec<ManifestBookmark>,
+}
+
+/// Bookmarked slide position, so the manifest points reports at findings.
+#[derive(Serialize)]
+struct ManifestBookmark {
+    name: String,
+    /// Slide point at the middle of the viewer, in `0..=1` on both axes.
+    center: [f32; 2],
+    zoom: f32,
+    kernel: usize,
+    score: Option<f32>,
+}
+
+#[derive(Serialize)]
+struct ManifestKernel {
+    index: usize,
+    name: String,
+    group: String,
+    provenance: String,
+    width: usize,
+    height: usize,
+    separable: bool,
+    /// Taps inside the kernel's mask; all of them when it has none.
+    support: usize,
+    /// False for muted kernels, which the other exports leave out.
+    enabled: bool,
+    score: f32,
+    exact: bool,
+    checksum: Option<String>,
+    p_value: Option<f32>,
+    permutations: Option<usize>,
+    /// NaN and infinite pixels of the map, left out of its statistics.
+    non_finite_pixels: usize,
+}
+
+/// Window size and mask that random baseline kernels are drawn on.
+pub(crate) type BaselineSupport = ((usize, usize), Option<Vec<bool>>);
+
+pub(crate) fn baseline_support(kernel: &Kernel) -> BaselineSupport {
+    ((kernel.width, kernel.height), kernel.mask.clone())
+}
+
+/// Kernels and pins of the sheet that was replaced, kept until the new sheet
+/// is split so annotations can be carried over.
+struct PreviousRevision {
+    kernels: Vec<Kernel>,
+    pinned: BTreeSet<usize>,
+}
+
+/// Selected-kernel responses of both slides in the slide's coordinates.
+struct ComparisonView {
+    kernel: usize,
+    transform: RigidTransform,
+    scores: [f32; 2],
+    textures: [TextureHandle; 2],
+}
+
+/// Per-pixel maximum |r| over the kernels of a group.
+struct GroupMapView {
+    group: String,
+    texture: TextureHandle,
+}
+
+/// Orientation field of the slide from an x/y derivative kernel pair.
+struct OrientationView {
+    /// X and Y derivative kernels.
+    kernels: (usize, usize),
+    coherence: f32,
+    orientation: f32,
+    mean_magnitude: f32,
+    texture: TextureHandle,
+}
+
+/// Which enabled kernel responds most strongly at each pixel.
+struct WinnerMapView {
+    winners: Winners,
+    shares: Vec<winners::Share>,
+    /// Whether each kernel's |r| was divided by its response std first.
+    scaled: bool,
+    texture: TextureHandle,
+}
+
+/// Local maxima of one kernel's map and the peaks picked from them.
+struct PeakView {
+    kernel: usize,
+    /// Every local maximum of the map, strongest first, in map pixels.
+    maxima: Vec<Peak>,
+    mean: f32,
+    std: f32,
+    /// Slide pixels between map pixels, and the slide pixel of the map's
+    /// first one: the corner of the run's region.
+    stride: usize,
+    origin: (usize, usize),
+    /// Response every peak reaches.
+    threshold: f32,
+    /// Strongest first, in slide pixels.
+    peaks: Vec<Peak>,
+}
+
+impl PeakView {
+    /// Picks the peaks again under the detection settings of `settings`.
+    fn pick(&mut self, settings: &Settings) {
+        self.threshold = self.mean + settings.peak_threshold * self.std;
+        let spacing = settings.peak_spacing.div_ceil(self.stride);
+        self.peaks = peaks::suppress(&self.maxima, self.threshold, spacing, settings.peak_count)
+            .into_iter()
+            .map(|peak| Peak {
+                x: self.origin.0 + peak.x * self.stride,
+                y: self.origin.1 + peak.y * self.stride,
+                ..peak
+            })
+            .collect();
+    }
+}
+
+/// Tile-by-tile recomputation of one kernel's map, checked against it.
+struct SeamView {
+    kernel: usize,
+    report: SeamReport,
+}
+
+/// Selected-kernel response of the slide relative to the control.
+struct ControlRatioView {
+    kernel: usize,
+    texture: TextureHandle,
+}
+
+/// Score of one kernel on each block of the slide, drawn over it.
+struct TileHeatmapView {
+    kernel: usize,
+    /// Block side in slide pixels.
+    tile: usize,
+    /// Top-left slide pixel of the region the grid covers.
+    origin: (usize, usize),
+    cols: usize,
+    scores: Vec<f32>,
+    range: (f32, f32),
+    texture: TextureHandle,
+}
+
+/// Slide blocks flagged by the triage threshold, highest score first.
+struct TriageResult {
+    tile: usize,
+    threshold: f32,
+    tiles: Vec<triage::FlaggedTile>,
+}
+
+struct AutocorrelationView {
+    kernel: usize,
+    result: Autocorrelation,
+    /// Input pixels per autocorrelation lag step.
+    scale: usize,
+    texture: TextureHandle,
+}
+
+struct FlythroughState {
+    keyframes: Vec<Keyframe>,
+    frames_per_segment: usize,
+    fps: u32,
+    opacity: f32,
+    format: VideoFormat,
+}
+
+impl Default for FlythroughState {
+    fn default() -> Self {
+        Self {
+            keyframes: vec![
+                Keyframe::default(),
+                Keyframe {
+                    center: [0.5, 0.5],
+                    zoom: 4.0,
+                },
+            ],
+            frames_per_segment: 48,
+            fps: 24,
+            opacity: 0.5,
+            format: VideoFormat::default(),
+        }
+    }
+}
+
+/// Work that a Reset, a load over a filled slot or closing the window
+/// would lose.
+#[derive(Clone, Copy, Default)]
+struct UnsavedWork {
+    /// Kernel weights or masks were edited since the kernels were loaded.
+    edits: bool,
+    /// A run finished and neither its scores nor all its maps were saved.
+    results: bool,
+}
+
+impl UnsavedWork {
+    fn any(self) -> bool {
+        self.edits || self.results
+    }
+
+    fn describe(self) -> &'static str {
+        match (self.edits, self.results) {
+            (true, true) => "edited kernels and the results of a run that were not exported",
+            (true, false) => "edited kernels",
+            _ => "the results of a run that were not exported",
+        }
+    }
+}
+
+/// Action held back until the user agrees to discard the unsaved work.
+enum Discard {
+    Reset,
+    /// A dropped file that replaces a slot's contents or the kernels.
+    Drop {
+        bytes: Vec<u8>,
+        name: String,
+        source: String,
+    },
+    /// A file chosen with an "Open" button for a filled slot.
+    Pick {
+        slot: Slot,
+        file: PickedFile,
+    },
+    /// Splitting the sheet again, which replaces edited kernels.
+    Split,
+    /// Loading an example sheet over the kernels.
+    Gallery(usize),
+    /// Closing the native window.
+    #[cfg(not(target_arch = "wasm32"))]
+    Close,
+}
+
+impl Discard {
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Reset => "Reset",
+            Self::Drop { .. } | Self::Pick { .. } | Self::Gallery(_) => "Load anyway",
+            Self::Split => "Split anyway",
+            #[cfg(not(target_arch = "wasm32"))]
+            Self::Close => "Close anyway",
+        }
+    }
+}
+
+/// Where the app was left, persisted with the settings so it reopens there
+/// but kept out of them, as profiles and Reset leave it alone.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct UiState {
+    kernel_shape: KernelShape,
+    /// Kernel selected in the kernels sheet or file with this content hash,
+    /// selected again once that sheet is split or that file loaded.
+    selected_kernel: Option<(u64, usize)>,
+    /// Folder of the last file opened, where native file dialogs start.
+    open_dir: String,
+}
+
+/// Preferences that survive Reset and are persisted between sessions.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct Settings {
+    backend_choice: BackendChoice,
+    backend_profiles: Vec<BackendProfile>,
+    draft_mode: bool,
+    /// Pixels further than this many standard deviations from the mean
+    /// response count as active.
+    activation_k: f32,
+    /// Folder receiving exported files on native builds.
+    export_dir: String,
+    notify_on_completion: bool,
+    /// Runs shorter than this do not trigger a notification.
+    notify_min_seconds: f32,
+    /// Keeps loaded images in browser storage, so a reload restores them.
+    #[cfg(target_arch = "wasm32")]
+    remember_images: bool,
+    /// Export the scores table after every completed run.
+    autosave_scores: bool,
+    /// Number of top-scoring full-resolution maps exported after every
+    /// completed run; 0 disables map export.
+    autosave_top_maps: usize,
+    /// Map exports also write the raw float response as a TIFF.
+    export_raw_tiff: bool,
+    /// File names of exported maps, with placeholders; empty for
+    /// `<prefix>_kernel<index>`.
+    map_name_template: String,
+    /// Gain, offset and fixed scale of drawn and exported responses.
+    calibration: Calibration,
+    csv_format: CsvFormat,
+    number_format: NumberFormat,
+    /// Ramp of tile heatmaps, response overlays and flythroughs.
+    colormap: Colormap,
+    /// Ramp of the response previews.
+    preview_colormap: Colormap,
+    overlay_colors: OverlayColors,
+    /// Pins the run to the scalar backend, whose fixed summation order makes
+    /// outputs bit-identical across runs and machines.
+    strict_reproducibility: bool,
+    /// Runs replace NaN and infinite input pixels and kernel weights with 0
+    /// instead of letting them spread through the maps.
+    repair_non_finite: bool,
+    /// Convolution or rank filter computed by the next run.
+    filter_mode: FilterMode,
+    score_normalization: ScoreNormalization,
+    /// Weight shuffles per kernel in the permutation test.
+    permutations: usize,
+    /// Random patches a quick score is computed on.
+    quick_patches: usize,
+    /// Block side of the tile score heatmap, in slide pixels.
+    heatmap_tile: usize,
+    heatmap_opacity: f32,
+    /// The preview column draws the selected response over the slide.
+    response_overlay: bool,
+    response_overlay_opacity: f32,
+    /// Tiles scoring above this for any enabled kernel are flagged.
+    triage_threshold: f32,
+    /// Peaks detected on a map: at most this many, at least this many
+    /// slide pixels apart in x or y, and this many standard deviations
+    /// above the map's mean.
+    peak_count: usize,
+    peak_spacing: usize,
+    peak_threshold: f32,
+    peak_marker: PeakMarker,
+    /// Saved sessions also keep the last run's full-resolution maps.
+    session_maps: bool,
+    /// Updates a kernel's response as soon as its weights are edited;
+    /// otherwise edits wait for "Re-run this kernel".
+    rerun_on_edit: bool,
+    figure_format: FigureFormat,
+    figure_style: FigureStyle,
+    profiles: Vec<Profile>,
+    /// Name of the profile last saved or switched to.
+    active_profile: String,
+    usage: UsageLog,
+    /// Dropped PNG slides with more pixels than this are streamed from disk
+    /// instead of loaded; native builds only.
+    stream_above_megapixels: u32,
+    /// Output rows convolved per streaming step.
+    stream_band_rows: usize,
+    /// Columns of each tile a streaming step convolves.
+    stream_tile_cols: usize,
+    /// Keep exact responses in memory-mapped temporary files so exports and
+    /// analyses read them instead of convolving again; native builds only.
+    maps_on_disk: bool,
+    /// Keep only the active pixels of exact maps, for the activation
+    /// threshold of the run, in place of the full maps.
+    sparse_maps: bool,
+    /// Run exact passes on the GPU when one is available. On by default in
+    /// the browser; native builds probe the GPU once it is turned on.
+    use_gpu: bool,
+    /// Muted tiles of each kernels sheet seen, by the sheet's content hash.
+    muted_kernels: BTreeMap<String, BTreeSet<usize>>,
+    stain_normalization: StainNormalization,
+    /// Color channels of color slides that runs convolve.
+    channel_mode: ChannelMode,
+    /// Slide whose intensities other slides are normalized to.
+    stain_reference: Option<StainReference>,
+    border_mode: BorderMode,
+    /// Stride and dilation of the convolutions.
+    sampling: Sampling,
+    /// Normalization applied to the tiles when a sheet is split.
+    kernel_normalization: KernelNormalization,
+    /// Splits sheets at their constant-intensity separator lines, falling
+    /// back to fixed tiles of the kernel shape when they have none.
+    detect_separators: bool,
+    /// What splitting does with a sheet that does not divide into tiles.
+    sheet_remainder: SheetRemainder,
+    kernel_presets: PresetOptions,
+    /// Folder or URL the example kernel sheets are read from.
+    gallery_url: String,
+    /// Address of the web app that share links made here open; the web
+    /// build uses its own.
+    #[cfg(not(target_arch = "wasm32"))]
+    share_url: String,
+    layout: LayoutMode,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            backend_choice: BackendChoice::default(),
+            backend_profiles: Vec::new(),
+            draft_mode: false,
+            activation_k: 2.0,
+            export_dir: "exports".to_owned(),
+            notify_on_completion: false,
+            notify_min_seconds: 10.0,
+            #[cfg(target_arch = "wasm32")]
+            remember_images: false,
+            autosave_scores: false,
+            autosave_top_maps: 0,
+            export_raw_tiff: false,
+            map_name_template: String::new(),
+            calibration: Calibration::default(),
+            csv_format: CsvFormat::default(),
+            number_format: NumberFormat::default(),
+            colormap: Colormap::default(),
+            preview_colormap: Colormap::Gray,
+            overlay_colors: OverlayColors::default(),
+            strict_reproducibility: false,
+            repair_non_finite: false,
+            filter_mode: FilterMode::default(),
+            score_normalization: ScoreNormalization::default(),
+            permutations: 99,
+            quick_patches: 32,
+            heatmap_tile: 128,
+            heatmap_opacity: 0.5,
+            response_overlay: false,
+            response_overlay_opacity: 0.5,
+            triage_threshold: 0.1,
+            peak_count: 50,
+            peak_spacing: 16,
+            peak_threshold: 3.0,
+            peak_marker: PeakMarker::default(),
+            session_maps: false,
+            rerun_on_edit: true,
+            figure_format: FigureFormat::default(),
+            figure_style: FigureStyle::default(),
+            profiles: Vec::new(),
+            active_profile: String::new(),
+            usage: UsageLog::default(),
+            stream_above_megapixels: 200,
+            stream_band_rows: 256,
+            stream_tile_cols: 2048,
+            maps_on_disk: true,
+            sparse_maps: false,
+            use_gpu: cfg!(target_arch = "wasm32"),
+            muted_kernels: BTreeMap::new(),
+            stain_normalization: StainNormalization::None,
+            channel_mode: ChannelMode::Luma,
+            stain_reference: None,
+            border_mode: BorderMode::Zero,
+            sampling: Sampling::DENSE,
+            kernel_normalization: KernelNormalization::None,
+            detect_separators: false,
+            sheet_remainder: SheetRemainder::Skip,
+            kernel_presets: PresetOptions::default(),
+            gallery_url: gallery::DEFAULT_URL.to_owned(),
+            #[cfg(not(target_arch = "wasm32"))]
+            share_url: String::new(),
+            layout: LayoutMode::Auto,
+        }
+    }
+}
+
+impl Settings {
+    /// Fields that describe this machine or the profiles themselves rather
+    /// than an analysis setup, left out of profiles.
+    const NOT_IN_PROFILES: [&str; 4] = ["backend_profiles", "profiles", "active_profile", "usage"];
+
+    fn profile_for(&self, kw: usize, kh: usize) -> Option<&BackendProfile> {
+        self.backend_profiles
+            .iter()
+            .find(|p| p.kernel_width == kw && p.kernel_height == kh)
+    }
+}
+
+pub struct ConvolutionApp {
+    settings: Settings,
+    /// Kernel selected when the app last closed, until its kernels are back.
+    restored_selection: Option<(u64, usize)>,
+    /// Folder of the last file opened, where native file dialogs start.
+    open_dir: String,
+    slide: LoadedImage,
+    kernels_sheet: LoadedImage,
+    second_slide: LoadedImage,
+    registration: Option<RigidTransform>,
+    comparison: Option<ComparisonView>,
+    /// Survives Reset so the following slides of a screen can be compared.
+    control: Option<ControlSlide>,
+    control_ratio: Option<ControlRatioView>,
+    group_map: Option<GroupMapView>,
+    orientation: Option<OrientationView>,
+    winner_map: Option<WinnerMapView>,
+    peaks: Option<PeakView>,
+    /// Whether the detected peaks are marked on the slide.
+    peaks_visible: bool,
+    seam_check: Option<SeamView>,
+    /// Tile side of the seam check, in slide pixels.
+    seam_tile: usize,
+    /// Whether the seam check's tile grid is drawn over the preview.
+    seams_visible: bool,
+    kernel_shape: KernelShape,
+    kernels: Vec<Kernel>,
+    previous_revision: Option<PreviousRevision>,
+    revision_diff: Option<RevisionDiff>,
+    kernel_rows: usize,
+    kernel_cols: usize,
+    /// Normalization the current kernels were split with.
+    split_normalization: KernelNormalization,
+    /// The current kernels are the blocks between the sheet's separator
+    /// lines rather than fixed tiles.
+    split_at_separators: bool,
+    /// What the split did with the sheet's partial tiles.
+    split_remainder: SheetRemainder,
+    previews: Vec<ConvolutionPreview>,
+    selected_kernel: usize,
+    pinned_kernels: BTreeSet<usize>,
+    /// Kernels ticked in the statistics table for "Run selected".
+    checked_kernels: BTreeSet<usize>,
+    /// Kernels waiting for a permutation test.
+    significance_queue: VecDeque<usize>,
+    /// Permutation tests on a background thread, with the kernels tested in
+    /// the order of their results.
+    #[cfg(not(target_arch = "wasm32"))]
+    significance_run: Option<(Vec<usize>, CpuRun<Significance>)>,
+    /// Permutation test of one kernel in the web worker or over frames: the
+    /// kernel, the maps of its weights and then of each shuffle, and their
+    /// scores so far.
+    #[cfg(target_arch = "wasm32")]
+    significance_run: Option<(usize, ChunkedRun, Vec<f32>)>,
+    run: Option<RunContext>,
+    autocorrelation: Option<AutocorrelationView>,
+    tile_heatmap: Option<TileHeatmapView>,
+    /// Whether the tile heatmap is drawn over the slide.
+    heatmap_visible: bool,
+    triage: Option<TriageResult>,
+    flythrough: FlythroughState,
+    /// Hides the control panels and shows only the slide and response.
+    presentation: bool,
+    /// Whether the controls are shown in the compact layout, whose images
+    /// they would otherwise cover.
+    controls_open: bool,
+    /// Layout the widget spacing was last set for, compact or not.
+    touch_spacing: Option<bool>,
+    /// Preview shown in its own OS window (embedded window on wasm).
+    detached_preview: bool,
+    /// Stats table shown in its own OS window (embedded window on wasm).
+    detached_stats: bool,
+    mirror_edit: MirrorEdit,
+    slide_view: ZoomPan,
+    preview_view: ZoomPan,
+    /// The preview viewer follows the zoom and pan of the slide viewer.
+    link_zoom: bool,
+    /// Kept for the session; Reset clears them.
+    bookmarks: Vec<Bookmark>,
+    bookmark_name: String,
+    /// Bookmark last jumped to, where Previous and Next go on from.
+    current_bookmark: Option<usize>,
+    zoom_detail: Option<ZoomDetail>,
+    explanation: Option<Explanation>,
+    /// Slide shrunk to the preview size for the response overlay, with the
+    /// hash of the slide and the region it was made from.
+    overlay_slide: Option<(u64, Option<Roi>, GrayImage)>,
+    /// Region of the slide runs convolve; the whole slide when unset.
+    roi: Option<Roi>,
+    /// Gallery sheet being downloaded, by its index in [`GALLERY`].
+    gallery_download: Option<(usize, gallery::Download)>,
+    /// Content hashes and names of the slides run since the last Reset, to
+    /// warn when one comes round again.
+    processed_slides: Vec<(u64, String)>,
+    /// Slot that dropped images go to, replacing its file; slots fill in
+    /// order when unset.
+    drop_slot: Option<Slot>,
+    /// Open dialog waiting for a file, with the slot it fills.
+    file_pick: Option<(Slot, file_dialog::Pick)>,
+    /// Files the operating system opens with the installed web app.
+    #[cfg(target_arch = "wasm32")]
+    launches: Option<pwa::Launches>,
+    /// Images being read back from browser storage after a reload.
+    #[cfg(target_arch = "wasm32")]
+    restore: Option<browser_cache::Restore>,
+    /// Slots whose image, by content hash, browser storage already holds.
+    #[cfg(target_arch = "wasm32")]
+    cached: Vec<(Slot, u64)>,
+    /// Run opened from a share link, compared with the local scores.
+    shared: Option<SharedRun>,
+    /// Share link pasted into the Exports section.
+    share_input: String,
+    about_open: bool,
+    teaching: Teaching,
+    self_test: Option<SelfTestReport>,
+    /// The self-test's fixtures on the GPU, with their maps read back so
+    /// far.
+    self_test_gpu: Option<(GpuRun, Vec<Vec<f32>>)>,
+    /// Drags on the slide draw the region of interest instead of panning.
+    selecting_roi: bool,
+    /// Slide pixels the region drag started at and is at now.
+    roi_drag: Option<([f32; 2], [f32; 2])>,
+    /// Drags on the slide move the region of interest instead of panning.
+    moving_roi: bool,
+    /// Slide pixels the region has been dragged by so far.
+    roi_move: Option<egui::Vec2>,
+    /// Score table column and whether it sorts in descending order.
+    score_sort: (ScoreColumn, bool),
+    /// Clicks in the weights grid toggle mask cells instead of editing.
+    mask_edit: bool,
+    /// The weights grid shows typed numeric fields instead of scroll cells.
+    numeric_weights: bool,
+    /// Kernels edited since their response was last computed.
+    dirty_kernels: BTreeSet<usize>,
+    unsaved: UnsavedWork,
+    /// Action waiting for the user to confirm it may discard `unsaved`.
+    pending_discard: Option<Discard>,
+    /// Kernel whose weights were edited and when, until its response is
+    /// updated.
+    pending_edit: Option<(usize, Instant)>,
+    /// Operation and operand kernels of the arithmetic panel.
+    arithmetic: (KernelOperation, usize, usize),
+    /// Kernels taken as the x and y derivatives for orientation maps.
+    derivative_pair: (usize, usize),
+    /// Divide each kernel's |r| by its response std before the winner map
+    /// compares them.
+    winner_scaled: bool,
+    /// Taps typed into the 1-D kernel panel.
+    taps_text: String,
+    /// Kernel indices with their quick scores, best first.
+    quick_scores: Vec<(usize, f32)>,
+    /// Number of top quick-scored kernels the pin button pins.
+    quick_pin_count: usize,
+    /// Title last sent to the window or browser tab.
+    window_title: String,
+    /// A job was running on the previous frame.
+    was_busy: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    taskbar: TaskbarProgress,
+    /// A job finished while the window was in the background.
+    finished_unseen: bool,
+    /// Start of the run whose completion has not been reported yet.
+    run_started: Option<Instant>,
+    /// Slide too large to load, waiting to be streamed.
+    #[cfg(not(target_arch = "wasm32"))]
+    streamed_slide: Option<std::path::PathBuf>,
+    #[cfg(not(target_arch = "wasm32"))]
+    stream_job: Option<StreamJob>,
+    /// Kernel whose mosaic the Large slides section shows.
+    #[cfg(not(target_arch = "wasm32"))]
+    stream_preview: usize,
+    /// Mosaic of the previewed kernel, with the kernel and the rows it
+    /// showed when drawn.
+    #[cfg(not(target_arch = "wasm32"))]
+    stream_mosaic: Option<((usize, usize), TextureHandle)>,
+    #[cfg(target_arch = "wasm32")]
+    gpu: BrowserGpu,
+    #[cfg(not(target_arch = "wasm32"))]
+    gpu: NativeGpu,
+    /// Exact run in progress on the GPU; its maps arrive over several frames.
+    gpu_run: Option<GpuRun>,
+    /// Exact CPU run on a background thread.
+    #[cfg(not(target_arch = "wasm32"))]
+    cpu_run: Option<ExactRun>,
+    /// Maps of the region before it moved, which `cpu_run` copies from
+    /// while it moves them.
+    #[cfg(not(target_arch = "wasm32"))]
+    carry_over: Option<Arc<CarryOver>>,
+    /// Draft refinement on a background thread, with the kernels it refines
+    /// in the order of its results.
+    #[cfg(not(target_arch = "wasm32"))]
+    refining: Option<(Vec<usize>, ExactRun)>,
+    /// CPU convolutions time-sliced over frames, for an exact run or a draft
+    /// refinement.
+    #[cfg(target_arch = "wasm32")]
+    chunked: Option<ChunkedRun>,
+    /// Backend benchmark on a thread of its own.
+    #[cfg(not(target_arch = "wasm32"))]
+    benchmark: Option<Receiver<BackendProfile>>,
+    /// Backend benchmark stepped once per frame, and the WebGPU timing run
+    /// with its start once the CPU backends are timed.
+    #[cfg(target_arch = "wasm32")]
+    benchmark: Option<(Benchmark, Option<(GpuRun, Instant)>)>,
+    status: String,
+}
+
+impl Default for ConvolutionApp {
+    fn default() -> Self {
+        Self {
+            settings: Settings::default(),
+            restored_selection: None,
+            open_dir: String::new(),
+            slide: LoadedImage::default(),
+            kernels_sheet: LoadedImage::default(),
+            second_slide: LoadedImage::default(),
+            registration: None,
+            comparison: None,
+            control: None,
+            control_ratio: None,
+            group_map: None,
+            orientation: None,
+            winner_map: None,
+            peaks: None,
+            peaks_visible: true,
+            seam_check: None,
+            seam_tile: 256,
+            seams_visible: true,
+            kernel_shape: KernelShape::default(),
+            kernels: Vec::new(),
+            previous_revision: None,
+            revision_diff: None,
+            kernel_rows: 0,
+            kernel_cols: 0,
+            split_normalization: KernelNormalization::None,
+            split_at_separators: false,
+            split_remainder: SheetRemainder::Skip,
+            previews: Vec::new(),
+            selected_kernel: 0,
+            pinned_kernels: BTreeSet::new(),
+            checked_kernels: BTreeSet::new(),
+            significance_queue: VecDeque::new(),
+            significance_run: None,
+            run: None,
+            autocorrelation: None,
+            tile_heatmap: None,
+            heatmap_visible: true,
+            triage: None,
+            flythrough: FlythroughState::default(),
+            presentation: false,
+            controls_open: false,
+            touch_spacing: None,
+            detached_preview: false,
+            detached_stats: false,
+            mirror_edit: MirrorEdit::Off,
+            slide_view: ZoomPan::default(),
+            preview_view: ZoomPan::default(),
+            link_zoom: true,
+            bookmarks: Vec::new(),
+            bookmark_name: String::new(),
+            current_bookmark: None,
+            zoom_detail: None,
+            explanation: None,
+            overlay_slide: None,
+            roi: None,
+            gallery_download: None,
+            processed_slides: Vec::new(),
+            drop_slot: None,
+            file_pick: None,
+            #[cfg(target_arch = "wasm32")]
+            launches: None,
+            #[cfg(target_arch = "wasm32")]
+            restore: None,
+            #[cfg(target_arch = "wasm32")]
+            cached: Vec::new(),
+            shared: None,
+            share_input: String::new(),
+            about_open: false,
+            teaching: Teaching::default(),
+            self_test: None,
+            self_test_gpu: None,
+            selecting_roi: false,
+            roi_drag: None,
+            moving_roi: false,
+            roi_move: None,
+            score_sort: (ScoreColumn::Kernel, false),
+            mask_edit: false,
+            numeric_weights: false,
+            dirty_kernels: BTreeSet::new(),
+            unsaved: UnsavedWork::default(),
+            pending_discard: None,
+            pending_edit: None,
+            arithmetic: (KernelOperation::Sum, 0, 0),
+            derivative_pair: (0, 1),
+            winner_scaled: false,
+            taps_text: "1, 2, 1".to_owned(),
+            quick_scores: Vec::new(),
+            quick_pin_count: 5,
+            window_title: APP_TITLE.to_owned(),
+            was_busy: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            taskbar: TaskbarProgress::default(),
+            finished_unseen: false,
+            run_started: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            streamed_slide: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            stream_job: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            stream_preview: 0,
+            #[cfg(not(target_arch = "wasm32"))]
+            stream_mosaic: None,
+            #[cfg(target_arch = "wasm32")]
+            gpu: BrowserGpu::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            gpu: NativeGpu::default(),
+            gpu_run: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            cpu_run: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            carry_over: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            refining: None,
+            #[cfg(target_arch = "wasm32")]
+            chunked: None,
+            benchmark: None,
+            status: "Drop two PNG files in the window: first the histological slide, then the kernels sheet.".to_owned(),
+        }
+    }
+}
+
+impl ConvolutionApp {
+    /// Restores saved settings and where the app was left, then applies the
+    /// lab-wide defaults of [`CONFIG_FILE`] found at startup on top of them.
+    pub fn new(cc: &eframe::CreationContext<'_>, startup: Startup) -> Self {
+        let mut settings: Settings = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
+            .unwrap_or_default();
+        let ui_state: UiState = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, UI_STATE_KEY))
+            .unwrap_or_default();
+        let mut errors = startup.errors;
+        if let Err(e) = startup.config.apply(&mut settings) {
+            errors.push(e);
+        }
+        let mut app = Self {
+            settings,
+            kernel_shape: ui_state.kernel_shape,
+            restored_selection: ui_state.selected_kernel,
+            open_dir: ui_state.open_dir,
+            #[cfg(not(target_arch = "wasm32"))]
+            taskbar: TaskbarProgress::new(cc),
+            ..Self::default()
+        };
+        #[cfg(target_arch = "wasm32")]
+        {
+            app.gpu = BrowserGpu::detect(cc.gl.clone());
+            app.launches = Some(pwa::launched_files(cc.egui_ctx.clone()));
+            if app.settings.remember_images {
+                app.restore = Some(browser_cache::restore(&CACHE_KEYS, cc.egui_ctx.clone()));
+            }
+            if let Some(href) = page_address() {
+                app.open_share_link(&href);
+            }
+        }
+        if let Some(shape) = startup.config.kernels.shape {
+            app.kernel_shape = shape;
+        }
+        if let Some((name, path, bytes)) = startup.kernel_sheet {
+            app.load_png_into_slot(&cc.egui_ctx, bytes, name, path, Slot::KernelsSheet);
+            app.split_kernels();
+            app.status = format!(
+                "{} Kernel sheet from {CONFIG_FILE}; drop the histological slide.",
+                app.status
+            );
+        } else if startup.source.is_some() {
+            app.status = format!("Defaults loaded from {CONFIG_FILE}. {}", app.status);
+        }
+        if !errors.is_empty() {
+            app.status = errors.join(" ");
+        }
+        app
+    }
+
+    /// Backend for convolving `pixels` pixels at a time with `kw` x `kh`
+    /// kernels. The automatic choice goes by the benchmark of that kernel
+    /// size, which starts in the background the first time the size is
+    /// seen; until it finishes, large work goes to the FFT.
+    fn resolve_backend(&mut self, kw: usize, kh: usize, pixels: usize) -> Backend {
+        if self.settings.strict_reproducibility {
+            return Backend::Scalar;
+        }
+        match self.settings.backend_choice {
+            BackendChoice::Fixed(backend) => backend,
+            BackendChoice::Auto => {
+                if let Some(profile) = self.settings.profile_for(kw, kh) {
+                    return profile.fastest_for(pixels);
+                }
+                self.start_benchmark(kw, kh);
+                if backend::prefers_fft(pixels, kw * kh) {
+                    Backend::Fft
+                } else {
+                    Backend::Vectorized
+                }
+            }
+        }
+    }
+
+    fn rerun_benchmark(&mut self) {
+        let kw = self.kernel_shape.width();
+        let kh = self.kernel_shape.height();
+        self.settings
+            .backend_profiles
+            .retain(|p| p.kernel_width != kw || p.kernel_height != kh);
+        self.benchmark = None;
+        self.start_benchmark(kw, kh);
+        self.status = format!("Benchmarking {kw}x{kh} kernels...");
+    }
+
+    /// Starts timing the backends on `kw` x `kh` kernels, with the GPU when
+    /// one is ready, unless a benchmark is already running.
+    fn start_benchmark(&mut self, kw: usize, kh: usize) {
+        if self.benchmark.is_some() {
+            return;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let gpu = self.gpu.shared();
+            let (sender, receiver) = mpsc::channel();
+            std::thread::spawn(move || {
+                let mut benchmark = Benchmark::new(kw, kh);
+                while !benchmark.step() {}
+                if let Some(gpu) = gpu {
+                    benchmark.time_gpu(|input, w, h, kernel, kw, kh| {
+                        gpu.convolve(input, w, h, kernel, kw, kh)
+                    });
+                }
+                let _ = sender.send(benchmark.finish());
+            });
+            self.benchmark = Some(receiver);
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.benchmark = Some((Benchmark::new(kw, kh), None));
+        }
+    }
+
+    /// Stores a finished benchmark. Returns true while one is running.
+    fn poll_benchmark(&mut self) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        let profile = {
+            let Some(receiver) = &self.benchmark else {
+                return false;
+            };
+            match receiver.try_recv() {
+                Err(mpsc::TryRecvError::Empty) => return true,
+                Ok(profile) => Some(profile),
+                Err(mpsc::TryRecvError::Disconnected) => None,
+            }
+        };
+        #[cfg(target_arch = "wasm32")]
+        let profile = {
+            let Some((benchmark, gpu_run)) = &mut self.benchmark else {
+                return false;
+            };
+            if let Some((run, started)) = gpu_run {
+                match self.gpu.convolver().map(|gpu| run.poll(gpu)) {
+                    Some(Ok(None)) => return true,
+                    Some(Ok(Some(_))) => benchmark.record_gpu(started.elapsed()),
+                    Some(Err(e)) => log::warn!("GPU benchmark failed: {e}"),
+                    None => {}
+                }
+            } else if !benchmark.step() {
+                return true;
+            } else if let BrowserGpu::WebGl(gl, _) = &self.gpu {
+                benchmark.time_gpu(|input, w, h, kernel, kw, kh| {
+                    gl.convolve(input, w, h, kernel, kw, kh)
+                });
+            } else if let Some(gpu) = self.gpu.convolver() {
+                // WebGPU maps come back asynchronously, so the timing runs
+                // over frames and includes their wait.
+                let (input, w, h, kernel, kw, kh) = benchmark.workload();
+                match GpuRun::new(gpu, input, w, h, vec![(kernel.to_vec(), (kw, kh))]) {
+                    Ok(run) => {
+                        *gpu_run = Some((run, Instant::now()));
+                        return true;
+                    }
+                    Err(e) => log::warn!("GPU benchmark failed: {e}"),
+                }
+            }
+            self.benchmark
+                .take()
+                .map(|(benchmark, _)| benchmark.finish())
+        };
+        self.benchmark = None;
+        if let Some(profile) = profile {
+            let (kw, kh) = (profile.kernel_width, profile.kernel_height);
+            self.settings
+                .backend_profiles
+                .retain(|p| p.kernel_width != kw || p.kernel_height != kh);
+            self.status = format!(
+                "Benchmarked {kw}x{kh} kernels: {} is fastest.",
+                profile.fastest.label()
+            );
+            self.settings.backend_profiles.push(profile);
+        }
+        false
+    }
+
+    /// Starts over, keeping the preferences, the control slide, the GPU, the
+    /// taskbar entry, the folder file dialogs open in and the files the
+    /// operating system opens with the web app.
+    fn reset(&mut self) {
+        let settings = std::mem::take(&mut self.settings);
+        let control = self.control.take();
+        let gpu = std::mem::take(&mut self.gpu);
+        let open_dir = std::mem::take(&mut self.open_dir);
+        #[cfg(not(target_arch = "wasm32"))]
+        let taskbar = std::mem::take(&mut self.taskbar);
+        #[cfg(target_arch = "wasm32")]
+        let launches = self.launches.take();
+        *self = Self {
+            settings,
+            control,
+            gpu,
+            open_dir,
+            #[cfg(not(target_arch = "wasm32"))]
+            taskbar,
+            #[cfg(target_arch = "wasm32")]
+            launches,
+            ..Self::default()
+        };
+    }
+
+    /// Selects the kernel selected when the app last closed, once the
+    /// kernels it was selected in are back.
+    fn restore_selection(&mut self) {
+        if let Some((hash, index)) = self.restored_selection
+            && hash == self.kernels_sheet.hash
+            && index < self.kernels.len()
+        {
+            self.selected_kernel = index;
+            self.restored_selection = None;
+        }
+    }
+
+    /// Holds a close request back while there is unsaved work, asking the
+    /// user first.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn hold_close(&mut self, ctx: &egui::Context) {
+        if ctx.input(|i| i.viewport().close_requested()) && self.unsaved.any() {
+            ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            self.pending_discard = Some(Discard::Close);
+        }
+    }
+
+    /// Asks whether the held-back action may discard the unsaved work, and
+    /// carries it out once the user agrees.
+    fn discard_window(&mut self, ctx: &egui::Context) {
+        let Some(action) = &self.pending_discard else {
+            return;
+        };
+        let label = action.label();
+        let mut confirmed = None;
+        egui::Window::new("Unsaved work")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(format!("This discards {}.", self.unsaved.describe()));
+                ui.horizontal(|ui| {
+                    if ui.button(label).clicked() {
+                        confirmed = Some(true);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        confirmed = Some(false);
+                    }
+                });
+            });
+        if confirmed.is_none() && ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+            confirmed = Some(false);
+        }
+        let Some(confirmed) = confirmed else {
+            return;
+        };
+        let Some(action) = self.pending_discard.take() else {
+            return;
+        };
+        if !confirmed {
+            return;
+        }
+        match action {
+            Discard::Reset => self.reset(),
+            Discard::Drop {
+                bytes,
+                name,
+                source,
+            } => self.load_dropped(ctx, bytes, name, source),
+            Discard::Pick { slot, file } => self.load_picked(ctx, slot, file),
+            Discard::Split => self.split_kernels(),
+            Discard::Gallery(index) => self.download_example(index),
+            #[cfg(not(target_arch = "wasm32"))]
+            Discard::Close => {
+                self.unsaved = UnsavedWork::default();
+                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            }
+        }
+    }
+
+    /// Drops everything derived from the current kernels.
+    fn clear_results(&mut self) {
+        self.previews.clear();
+        self.selected_kernel = 0;
+        self.pinned_kernels.clear();
+        self.checked_kernels.clear();
+        self.dirty_kernels.clear();
+        self.unsaved.results = false;
+        self.significance_queue.clear();
+        self.significance_run = None;
+        self.run = None;
+        self.gpu_run = None;
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.cpu_run = None;
+            self.carry_over = None;
+            self.refining = None;
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.chunked = None;
+        }
+        self.autocorrelation = None;
+        self.tile_heatmap = None;
+        self.triage = None;
+        self.comparison = None;
+        self.control_ratio = None;
+        self.group_map = None;
+        self.orientation = None;
+        self.winner_map = None;
+        self.peaks = None;
+        self.seam_check = None;
+        self.zoom_detail = None;
+        self.explanation = None;
+        self.quick_scores.clear();
+    }
+
+    /// Stores the current settings and kernel shape under `name`, replacing a
+    /// profile of the same name.
+    fn save_profile(&mut self, name: String) {
+        let name = name.trim().to_owned();
+        if name.is_empty() {
+            self.status = "Enter a profile name first.".to_owned();
+            return;
+        }
+        match Profile::capture(
+            name.clone(),
+            self.kernel_shape,
+            &self.settings,
+            &Settings::NOT_IN_PROFILES,
+        ) {
+            Ok(profile) => self.add_profile(profile),
+            Err(e) => self.status = e,
+        }
+    }
+
+    fn add_profile(&mut self, profile: Profile) {
+        self.settings.profiles.retain(|p| p.name != profile.name);
+        self.status = format!("Saved profile \"{}\".", profile.name);
+        self.settings.active_profile = profile.name.clone();
+        self.settings.profiles.push(profile);
+        self.settings.profiles.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    fn apply_profile(&mut self, index: usize) {
+        let Some(profile) = self.settings.profiles.get(index).cloned() else {
+            return;
+        };
+        if let Err(e) = config::overlay(&mut self.settings, &profile.settings) {
+            self.status = format!("Profile \"{}\" is invalid: {e}", profile.name);
+            return;
+        }
+        if let Some(shape) = profile.shape {
+            self.kernel_shape = shape;
+        }
+        self.rescore();
+        self.status = format!("Switched to profile \"{}\".", profile.name);
+        self.settings.active_profile = profile.name;
+    }
+
+    fn export_profile(&mut self, index: usize) {
+        let Some(profile) = self.settings.profiles.get(index) else {
+            return;
+        };
+        let file_name = format!("{}.toml", profile.name.replace(['/', '\\', ' '], "_"));
+        self.status = match profile.to_toml().and_then(|toml| {
+            export::save_file(&self.settings.export_dir, &file_name, toml.as_bytes())
+        }) {
+            Ok(dest) => format!("Exported profile to {dest}."),
+            Err(e) => format!("Profile export failed: {e}"),
+        };
+    }
+
+    fn import_profile(&mut self, file_name: &str, bytes: &[u8]) {
+        let name = file_name.trim_end_matches(".toml").to_owned();
+        match Profile::from_toml(name, &String::from_utf8_lossy(bytes)) {
+            Ok(profile) => {
+                self.add_profile(profile);
+                self.status = format!("{} Imported from {file_name}.", self.status);
+            }
+            Err(e) => self.status = e,
+        }
+    }
+
+    fn share_panel(&mut self, ui: &mut egui::Ui) {
+        ui.label(format!(
+            "Links carry the settings and the best {} scores, not the images.",
+            share::MAX_SHARED_KERNELS
+        ));
+        #[cfg(not(target_arch = "wasm32"))]
+        ui.horizontal(|ui| {
+            ui.label("Web app address");
+            ui.text_edit_singleline(&mut self.settings.share_url);
+        });
+        if ui
+            .add_enabled(
+                !self.previews.is_empty(),
+                egui::Button::new("Copy share link"),
+            )
+            .clicked()
+        {
+            self.copy_share_link(&ui.ctx().clone());
+        }
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.share_input)
+                .on_hover_text("Paste a share link here.");
+            if ui.button("Open").clicked() {
+                let link = std::mem::take(&mut self.share_input);
+                self.open_share_link(&link);
+            }
+        });
+    }
+
+    /// Scores of the shared run next to this machine's, with whether the
+    /// loaded images are the ones it was run on.
+    fn shared_run_panel(&mut self, ui: &mut egui::Ui) {
+        let Some(run) = &self.shared else {
+            return;
+        };
+        for (input, image) in [
+            (&run.pipeline.slide, &self.slide),
+            (&run.pipeline.kernels.sheet, &self.kernels_sheet),
+        ] {
+            let state = if image.gray.is_none() {
+                "not loaded"
+            } else if input.hash == pipeline::format_hash(image.hash) {
+                "loaded"
+            } else {
+                "a different file is loaded"
+            };
+            ui.label(format!("{}: {state}", input.path));
+        }
+        let format = self.settings.number_format;
+        let mut select = None;
+        egui::Grid::new("shared_scores")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong("Kernel");
+                ui.strong("Name");
+                ui.strong("Shared");
+                ui.strong("Here");
+                ui.end_row();
+                for score in &run.scores {
+                    let label = if score.index == run.selected {
+                        format!("{} (selected)", score.index)
+                    } else {
+                        score.index.to_string()
+                    };
+                    if ui.link(label).clicked() {
+                        select = Some(score.index);
+                    }
+                    ui.label(&score.name);
+                    ui.label(format.format(score.score));
+                    let here = self
+                        .previews
+                        .get(score.index)
+                        .filter(|_| self.kernels.len() == run.kernels);
+                    ui.label(here.map_or("-".to_owned(), |p| format.format(p.score)));
+                    ui.end_row();
+                }
+            });
+        if let Some(index) = select
+            && index < self.kernels.len()
+        {
+            self.selected_kernel = index;
+        }
+        if ui.button("Dismiss").clicked() {
+            self.shared = None;
+        }
+    }
+
+    fn usage_panel(&mut self, ui: &mut egui::Ui) {
+        let usage = &mut self.settings.usage;
+        ui.checkbox(&mut usage.enabled, "Record stage timings")
+            .on_hover_text("Kept in this machine's settings only; never uploaded.");
+        if usage.records().is_empty() {
+            ui.label("No timings recorded yet.");
+            return;
+        }
+        egui::Grid::new("usage_table").striped(true).show(ui, |ui| {
+            ui.strong("Stage");
+            ui.strong("Backend");
+            ui.strong("Runs");
+            ui.strong("Mean");
+            ui.strong("Last");
+            ui.strong("Mtaps/s");
+            ui.end_row();
+            for summary in usage.summaries() {
+                ui.label(summary.stage.label());
+                ui.label(summary.backend.map_or("-", Backend::label));
+                ui.label(summary.runs.to_string());
+                ui.label(format!("{:.2} s", summary.mean_seconds));
+                ui.label(format!("{:.2} s", summary.last_seconds));
+                ui.label(
+                    summary
+                        .mega_taps_per_second
+                        .map_or("-".to_owned(), |t| format!("{t:.1}")),
+                );
+                ui.end_row();
+            }
+        });
+        let recent: Vec<String> = usage
+            .records()
+            .iter()
+            .rev()
+            .filter(|r| r.stage == Stage::Convolution)
+            .take(5)
+            .map(|r| format!("{:.2} s", r.seconds))
+            .collect();
+        if !recent.is_empty() {
+            ui.label(format!("Last convolution runs: {}", recent.join(", ")));
+        }
+        if ui.button("Clear history").clicked() {
+            usage.clear();
+        }
+    }
+
+    fn number_format_panel(&mut self, ui: &mut egui::Ui) {
+        let format = &mut self.settings.number_format;
+        ui.add(
+            egui::Slider::new(&mut format.significant_digits, 1..=NumberFormat::MAX_DIGITS)
+                .text("significant digits"),
+        );
+        ui.add(
+            egui::Slider::new(&mut format.scientific_below, 1e-12..=1.0)
+                .logarithmic(true)
+                .text("scientific below"),
+        );
+        ui.add(
+            egui::Slider::new(&mut format.scientific_above, 1.0..=1e12)
+                .logarithmic(true)
+                .text("scientific from"),
+        );
+        ui.checkbox(&mut format.in_exports, "Also in the scores CSV")
+            .on_hover_text("Otherwise the CSV keeps every digit.");
+        let samples: Vec<String> = [0.000_012_34, 0.123_456_7, 42.195, 1_234_567.0]
+            .iter()
+            .map(|&v| format.format(v))
+            .collect();
+        ui.label(format!("For example: {}", samples.join(", ")));
+    }
+
+    fn calibration_panel(&mut self, ui: &mut egui::Ui) {
+        let fmt = self.settings.number_format;
+        let calibration = &mut self.settings.calibration;
+        ui.checkbox(
+            &mut calibration.enabled,
+            "Calibrate drawn and exported maps",
+        )
+        .on_hover_text(
+            "Draw gain x r + offset on one fixed scale instead of stretching each map \
+                 over its own extremes, so maps of different sessions compare by color. \
+                 Scores keep using the raw responses.",
+        );
+        ui.add_enabled_ui(calibration.enabled, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Gain");
+                ui.add(egui::DragValue::new(&mut calibration.gain).speed(0.01));
+                ui.label("Offset");
+                ui.add(egui::DragValue::new(&mut calibration.offset).speed(0.01));
+            });
+            let (lo, hi) = &mut calibration.range;
+            ui.horizontal(|ui| {
+                ui.label("Scale from");
+                ui.add(egui::DragValue::new(lo).speed(0.05));
+                ui.label("to");
+                ui.add(egui::DragValue::new(hi).speed(0.05));
+            });
+            if *hi <= *lo {
+                *hi = *lo + 0.1;
+            }
+        });
+        if ui
+            .add_enabled(
+                self.run.is_some(),
+                egui::Button::new("Calibrate on the preview in view"),
+            )
+            .on_hover_text(
+                "Set the gain and offset so the selected kernel's responses in the part \
+                 of its preview in view have mean 0 and standard deviation 1. Zoom the \
+                 preview onto a reference structure first.",
+            )
+            .clicked()
+        {
+            self.calibrate_on_view();
+        }
+        let calibration = self.settings.calibration;
+        if calibration.enabled {
+            ui.weak(format!(
+                "Maps show {} x r + {} over {} to {}.",
+                fmt.format(calibration.gain),
+                fmt.format(calibration.offset),
+                fmt.format(calibration.range.0),
+                fmt.format(calibration.range.1)
+            ));
+        }
+    }
+
+    /// Calibrates on the responses of the selected kernel in the part of the
+    /// preview viewer in view.
+    fn calibrate_on_view(&mut self) {
+        let index = self.selected_kernel;
+        let Some((response, width, height)) = self.full_response(index) else {
+            self.status = "Run the convolutions first.".to_owned();
+            return;
+        };
+        let visible = self.preview_view.visible();
+        let span = |lo: f32, hi: f32, n: usize| {
+            let start = ((lo.clamp(0.0, 1.0) * n as f32).floor() as usize).min(n - 1);
+            let end = ((hi.clamp(0.0, 1.0) * n as f32).ceil() as usize).clamp(start + 1, n);
+            start..end
+        };
+        let (xs, ys) = (
+            span(visible.min.x, visible.max.x, width),
+            span(visible.min.y, visible.max.y, height),
+        );
+        let region: Vec<f32> = ys
+            .clone()
+            .flat_map(|y| {
+                response[y * width + xs.start..y * width + xs.end]
+                    .iter()
+                    .copied()
+            })
+            .collect();
+        self.status = match self.settings.calibration.fitted_to(&region) {
+            Ok(calibration) => {
+                self.settings.calibration = calibration;
+                format!(
+                    "Calibrated on {}x{} map pixels of kernel {index}.",
+                    xs.len(),
+                    ys.len()
+                )
+            }
+            Err(e) => e,
+        };
+    }
+
+    fn session_panel(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.settings.session_maps, "Keep the maps")
+            .on_hover_text(
+                "Also save the last run's full-resolution maps, so the results come back \
+                 without running again. Sessions then grow by about 4 bytes per map pixel.",
+            );
+        ui.horizontal(|ui| {
+            if ui
+                .button("Save session")
+                .on_hover_text(
+                    "One file with the images, the kernels as edited and the parameters, \
+                     to resume the analysis later or on another machine.",
+                )
+                .clicked()
+            {
+                self.save_session();
+            }
+            if ui
+                .add_enabled(self.file_pick.is_none(), egui::Button::new("Open session…"))
+                .clicked()
+            {
+                let pick = file_dialog::pick("Choose a session", SESSION_FILTER, &self.open_dir);
+                self.file_pick = Some((Slot::Slide, pick));
+            }
+        });
+    }
+
+    fn colors_panel(&mut self, ui: &mut egui::Ui) {
+        egui::ComboBox::from_label("Colormap")
+            .selected_text(self.settings.colormap.label())
+            .show_ui(ui, |ui| {
+                for colormap in Colormap::ALL {
+                    ui.selectable_value(&mut self.settings.colormap, colormap, colormap.label());
+                }
+            });
+        let (rect, _) = ui.allocate_exact_size(
+            egui::vec2(ui.available_width().min(240.0), 12.0),
+            egui::Sense::hover(),
+        );
+        const STEPS: usize = 64;
+        for step in 0..STEPS {
+            let [r, g, b] = self
+                .settings
+                .colormap
+                .sample_rgb(step as f32 / (STEPS - 1) as f32);
+            let x = rect.width() / STEPS as f32;
+            let cell = egui::Rect::from_min_size(
+                rect.min + egui::vec2(step as f32 * x, 0.0),
+                egui::vec2(x.ceil(), rect.height()),
+            );
+            ui.painter()
+                .rect_filled(cell, 0.0, egui::Color32::from_rgb(r, g, b));
+        }
+        let colors = &mut self.settings.overlay_colors;
+        egui::Grid::new("overlay_colors").show(ui, |ui| {
+            for (label, color) in [
+                ("Flagged tiles", &mut colors.marker),
+                ("Tile grid", &mut colors.grid),
+                ("Seam mismatches", &mut colors.mismatch),
+                ("Region of interest", &mut colors.roi),
+                ("Peaks", &mut colors.peak),
+                ("Positive weights", &mut colors.positive),
+                ("Negative weights", &mut colors.negative),
+            ] {
+                ui.label(label);
+                ui.color_edit_button_srgb(color);
+                ui.end_row();
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui
+                .button("Color-blind safe")
+                .on_hover_text("Cividis with Okabe-Ito overlay colors.")
+                .clicked()
+            {
+                self.settings.colormap = Colormap::Cividis;
+                self.settings.overlay_colors = OverlayColors::color_blind_safe();
+            }
+            if ui.button("Defaults").clicked() {
+                self.settings.colormap = Colormap::default();
+                self.settings.overlay_colors = OverlayColors::default();
+            }
+        });
+        ui.label("Tile heatmaps are redrawn in a new colormap when next computed.");
+    }
+
+    fn profiles_panel(&mut self, ui: &mut egui::Ui) {
+        let mut switch_to = None;
+        egui::ComboBox::from_label("Profile")
+            .selected_text(if self.settings.active_profile.is_empty() {
+                "(none)"
+            } else {
+                &self.settings.active_profile
+            })
+            .show_ui(ui, |ui| {
+                for (i, profile) in self.settings.profiles.iter().enumerate() {
+                    let active = profile.name == self.settings.active_profile;
+                    if ui.selectable_label(active, &profile.name).clicked() {
+                        switch_to = Some(i);
+                    }
+                }
+            });
+        if let Some(index) = switch_to {
+            self.apply_profile(index);
+        }
+        ui.horizontal(|ui| {
+            let id = ui.id().with("profile_name");
+            let mut name = ui.data_mut(|d| d.get_temp::<String>(id).unwrap_or_default());
+            ui.text_edit_singleline(&mut name);
+            if ui.button("Save current").clicked() {
+                self.save_profile(std::mem::take(&mut name));
+            }
+            ui.data_mut(|d| d.insert_temp(id, name));
+        });
+        let active = self
+            .settings
+            .profiles
+            .iter()
+            .position(|p| p.name == self.settings.active_profile);
+        if let Some(index) = active {
+            ui.horizontal(|ui| {
+                if ui.button("Export as TOML").clicked() {
+                    self.export_profile(index);
+                }
+                if ui.button("Delete").clicked() {
+                    self.settings.profiles.remove(index);
+                    self.settings.active_profile.clear();
+                }
+            });
+        }
+        ui.label("Drop a profile .toml file on the window to import it.");
+    }
+
+    /// Intensities of a slide for convolution: its gray levels normalized
+    /// to the stain reference when one is set, or the color channels of the
+    /// channel mode. `rgb` holds the slide's color pixels, if it has any.
+    fn slide_input(&self, gray: &GrayImage, rgb: Option<&RgbImage>) -> Vec<f32> {
+        match (self.settings.channel_mode, rgb) {
+            (ChannelMode::Luma, _) | (_, None) => stain::normalized_input(
+                gray,
+                rgb,
+                self.settings.stain_normalization,
+                self.settings.stain_reference.as_ref(),
+            ),
+            (mode, Some(rgb)) => channels::intensity(rgb, mode),
+        }
+    }
+
+    /// Name of the slide runs are normalized to, when normalization applies
+    /// to a slide that is in `color` or not.
+    fn stain_reference_name(&self, color: bool) -> Option<String> {
+        let reference = self.settings.stain_reference.as_ref();
+        match self.settings.stain_normalization {
+            _ if color && self.settings.channel_mode != ChannelMode::Luma => None,
+            StainNormalization::None => None,
+            StainNormalization::Macenko if !color => None,
+            StainNormalization::Macenko => Some(match reference {
+                Some(r) if r.stains.is_some() => r.name.clone(),
+                _ => stain::STANDARD_TARGET.to_owned(),
+            }),
+            StainNormalization::HistogramMatching | StainNormalization::MeanStd => {
+                reference.map(|r| r.name.clone())
+            }
+        }
+    }
+
+    /// Width and height editors with presets. Returns why the loaded sheet
+    /// cannot be split into the chosen shape, if it cannot.
+    fn kernel_shape_panel(&mut self, ui: &mut egui::Ui) -> Option<String> {
+        ui.group(|ui| {
+            ui.label("Kernel shape");
+            ui.horizontal(|ui| {
+                let (mut width, mut height) =
+                    (self.kernel_shape.width(), self.kernel_shape.height());
+                ui.label("Width");
+                ui.add(egui::DragValue::new(&mut width).range(1..=MAX_KERNEL_SIDE));
+                ui.label("Height");
+                ui.add(egui::DragValue::new(&mut height).range(1..=MAX_KERNEL_SIDE));
+                if let Ok(shape) = KernelShape::new(width, height) {
+                    self.kernel_shape = shape;
+                }
+            });
+            ui.horizontal_wrapped(|ui| {
+                for preset in KernelShape::PRESETS {
+                    ui.selectable_value(&mut self.kernel_shape, preset, preset.label());
+                }
+            });
+            let sheet = self.kernels_sheet.gray.as_ref().map(|s| s.dimensions());
+            let (kw, kh) = (self.kernel_shape.width(), self.kernel_shape.height());
+            // Only padding makes a tile of a sheet smaller than one.
+            let mismatch = sheet
+                .filter(|&(w, h)| (w as usize) < kw || (h as usize) < kh)
+                .filter(|_| self.settings.sheet_remainder != SheetRemainder::Pad)
+                .map(|(w, h)| format!("The {w}x{h} sheet is smaller than one {kw}x{kh} tile."));
+            if let Some((w, h)) = sheet {
+                let (rx, ry) = self.kernel_shape.sheet_remainder(w, h);
+                if (rx, ry) != (0, 0) {
+                    ui.label(format!(
+                        "The {w}x{h} sheet leaves {rx} columns and {ry} rows past whole {kw}x{kh} tiles."
+                    ));
+                    egui::ComboBox::from_label("Partial tiles")
+                        .selected_text(self.settings.sheet_remainder.label())
+                        .show_ui(ui, |ui| {
+                            for remainder in SheetRemainder::ALL {
+                                ui.selectable_value(
+                                    &mut self.settings.sheet_remainder,
+                                    remainder,
+                                    remainder.label(),
+                                );
+                            }
+                        });
+                }
+            }
+            ui.checkbox(
+                &mut self.settings.detect_separators,
+                "Split at separator lines",
+            )
+            .on_hover_text(
+                "Cuts the sheet between rows and columns of constant intensity, however \
+                     irregularly spaced; sheets without any fall back to tiles of this shape.",
+            );
+            // Sheets with separators need not divide into tiles.
+            let mismatch = mismatch.filter(|_| !self.settings.detect_separators);
+            if let Some(problem) = &mismatch {
+                ui.colored_label(ui.visuals().warn_fg_color, problem);
+            }
+            mismatch
+        })
+        .inner
+    }
+
+    fn border_combo(&mut self, ui: &mut egui::Ui) {
+        let current = self.settings.border_mode;
+        egui::ComboBox::from_label("Border")
+            .selected_text(current.label())
+            .show_ui(ui, |ui| {
+                for border in BorderMode::ALL {
+                    let selected = current.same_kind(border);
+                    if ui.selectable_label(selected, border.label()).clicked() && !selected {
+                        self.settings.border_mode = border;
+                    }
+                }
+            })
+            .response
+            .on_hover_text(
+                "What kernels read past the slide's edges. Zero darkens responses along \
+                 the edges; rank filters leave those taps out instead. GPU passes always \
+                 use zero and fall back to the CPU for other borders.",
+            );
+        if let BorderMode::Constant(value) = &mut self.settings.border_mode {
+            ui.horizontal(|ui| {
+                ui.label("Border intensity");
+                ui.add(egui::DragValue::new(value).speed(0.01).range(0.0..=1.0));
+            });
+        }
+    }
+
+    fn sampling_controls(&mut self, ui: &mut egui::Ui) {
+        let range = 1..=backend::MAX_SAMPLING_FACTOR;
+        let sampling = &mut self.settings.sampling;
+        ui.horizontal(|ui| {
+            ui.label("Stride");
+            ui.add(egui::DragValue::new(&mut sampling.stride).range(range.clone()))
+                .on_hover_text(
+                    "Keep every nth pixel of each map in both axes, starting at the top \
+                     left, so maps are n times smaller each way and runs that much faster.",
+                );
+            ui.label("Dilation");
+            ui.add(egui::DragValue::new(&mut sampling.dilation).range(range))
+                .on_hover_text(
+                    "Space the kernel's taps n pixels apart, so it covers n times the area \
+                     with the same weights, as atrous convolutions do.",
+                );
+        });
+        if !sampling.is_dense() {
+            ui.weak("Strided and dilated runs stay on the CPU at full resolution.");
+        }
+    }
+
+    /// Map file name template, with the name it gives the selected kernel's
+    /// map.
+    fn map_name_field(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Map file names");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.settings.map_name_template)
+                    .hint_text("maps_<time>_kernel<index>"),
+            )
+            .on_hover_text(format!(
+                "Names of exported and autosaved maps, without extension: \
+                 {}. Maps named by a template replace earlier exports of the same \
+                 name; leave it empty for time-stamped names.",
+                export::MAP_NAME_PLACEHOLDERS
+            ));
+        });
+        let index = self.selected_kernel;
+        if self.settings.map_name_template.is_empty() || index >= self.kernels.len() {
+            return;
+        }
+        match self.map_file_stems("", &[index]) {
+            Ok(names) => ui.weak(format!("Kernel {index}: {}.png", names[0])),
+            Err(e) => ui.colored_label(ui.visuals().error_fg_color, e),
+        };
+    }
+
+    fn stain_panel(&mut self, ui: &mut egui::Ui) {
+        egui::ComboBox::from_label("Normalization")
+            .selected_text(self.settings.stain_normalization.label())
+            .show_ui(ui, |ui| {
+                for mode in StainNormalization::ALL {
+                    ui.selectable_value(&mut self.settings.stain_normalization, mode, mode.label());
+                }
+            })
+            .response
+            .on_hover_text(
+                "Maps the intensities of every slide onto the reference's before convolution, \
+                 so scores of differently stained slides can be compared. Applies on the next run.",
+            );
+        match &self.settings.stain_reference {
+            Some(reference) => ui.label(format!("Reference: {}", reference.name)),
+            None => ui.label("No reference slide yet."),
+        };
+        if self.settings.stain_normalization == StainNormalization::Macenko {
+            let stains = self
+                .settings
+                .stain_reference
+                .as_ref()
+                .and_then(|r| r.stains);
+            ui.label(match stains {
+                Some(_) => "Stains are matched to the reference's H&E vectors.".to_owned(),
+                None => format!(
+                    "Without a color reference, stains are matched to the {}.",
+                    stain::STANDARD_TARGET
+                ),
+            });
+            if self.slide.gray.is_some() && self.slide.rgb.is_none() {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    "The slide is grayscale, so Macenko leaves it unchanged.",
+                );
+            }
+        }
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(
+                    self.slide.gray.is_some(),
+                    egui::Button::new("Use the slide as reference"),
+                )
+                .clicked()
+                && let Some(gray) = &self.slide.gray
+            {
+                let (name, rgb) = (self.slide.name.clone(), self.slide.rgb.as_ref());
+                let reference = StainReference::new(name, gray, rgb);
+                self.status = format!("{} is now the stain reference.", reference.name);
+                self.settings.stain_reference = Some(reference);
+            }
+            if ui
+                .add_enabled(
+                    self.settings.stain_reference.is_some(),
+                    egui::Button::new("Clear reference"),
+                )
+                .clicked()
+            {
+                self.settings.stain_reference = None;
+            }
+        });
+    }
+
+    /// Describes the loaded inputs and current parameters as a pipeline the
+    /// headless mode can run.
+    fn pipeline(&self) -> Result<Pipeline, String> {
+        if self.slide.gray.is_none() || self.kernels_sheet.name.is_empty() {
+            return Err("Load the slide and the kernels sheet first.".to_owned());
+        }
+        if self.kernels_sheet.gray.is_none() {
+            return Err(format!(
+                "Pipelines describe the kernels by a PNG sheet; {} cannot be referenced yet.",
+                self.kernels_sheet.name
+            ));
+        }
+        Ok(self.pipeline_parameters())
+    }
+
+    /// The loaded inputs and current parameters as a pipeline, whatever
+    /// the kernels were loaded from.
+    fn pipeline_parameters(&self) -> Pipeline {
+        let kw = self.kernel_shape.width();
+        let kh = self.kernel_shape.height();
+        let backend = match (self.run.as_ref(), self.settings.backend_choice) {
+            (Some(run), _) => run.backend,
+            (None, BackendChoice::Fixed(backend)) => backend,
+            (None, BackendChoice::Auto) => self
+                .settings
+                .profile_for(kw, kh)
+                .map_or(Backend::Scalar, |p| p.fastest),
+        };
+        let input = |image: &LoadedImage| pipeline::InputRef {
+            path: image.source.clone(),
+            hash: pipeline::format_hash(image.hash),
+        };
+        Pipeline {
+            version: pipeline::PIPELINE_VERSION,
+            slide: input(&self.slide),
+            kernels: pipeline::KernelBank {
+                sheet: input(&self.kernels_sheet),
+                shape: self.kernel_shape,
+                normalization: self.split_normalization,
+                separators: self.split_at_separators,
+                remainder: self.split_remainder,
+                muted: self.muted_tiles().into_iter().collect(),
+            },
+            convolution: pipeline::ConvolutionParams {
+                backend,
+                strict_reproducibility: self.settings.strict_reproducibility,
+                mode: self.settings.filter_mode,
+                stain: self.settings.stain_normalization,
+                stain_reference: self.settings.stain_reference.clone(),
+                channels: self.settings.channel_mode,
+                border: self.settings.border_mode,
+                sampling: self.settings.sampling,
+                roi: self.roi,
+            },
+            scoring: pipeline::ScoringParams {
+                normalization: self.settings.score_normalization,
+                activation_k: self.settings.activation_k,
+                permutations: if self.previews.iter().any(|p| p.significance.is_some()) {
+                    self.settings.permutations
+                } else {
+                    0
+                },
+            },
+            outputs: pipeline::Outputs {
+                dir: self.settings.export_dir.clone(),
+                scores_csv: true,
+                top_maps: self.settings.autosave_top_maps,
+                map_names: self.settings.map_name_template.clone(),
+                calibration: self.settings.calibration,
+                csv: self.settings.csv_format,
+            },
+        }
+    }
+
+    fn export_pipeline(&mut self) {
+        // The web build saves projects, which the installed app opens from
+        // the file manager.
+        let extension = if cfg!(target_arch = "wasm32") {
+            PROJECT_EXTENSION
+        } else {
+            ".json"
+        };
+        let file_name = format!("pipeline_{}{extension}", unix_timestamp());
+        self.status = match self.pipeline().and_then(|p| p.to_json()).and_then(|json| {
+            export::save_file(&self.settings.export_dir, &file_name, json.as_bytes())
+        }) {
+            Ok(dest) => format!("Saved pipeline to {dest}."),
+            Err(e) => format!("Pipeline export failed: {e}"),
+        };
+    }
+
+    /// Applies a pipeline's parameters and, where the files are reachable,
+    /// loads its inputs. `source` locates the pipeline file itself.
+    fn import_pipeline(&mut self, ctx: &egui::Context, bytes: &[u8], source: &str) {
+        let pipeline = match Pipeline::from_json(&String::from_utf8_lossy(bytes)) {
+            Ok(pipeline) => pipeline,
+            Err(e) => {
+                self.status = e;
+                return;
+            }
+        };
+        self.apply_pipeline_parameters(&pipeline);
+        self.apply_pipeline_outputs(&pipeline.outputs);
+        self.load_pipeline_inputs(ctx, &pipeline, source);
+        // Loading the slide clears the region, so it is restored afterwards.
+        self.roi = pipeline.convolution.roi;
+    }
+
+    fn apply_pipeline_outputs(&mut self, outputs: &pipeline::Outputs) {
+        let settings = &mut self.settings;
+        settings.export_dir = outputs.dir.clone();
+        settings.autosave_scores = outputs.scores_csv;
+        settings.autosave_top_maps = outputs.top_maps;
+        settings.map_name_template = outputs.map_names.clone();
+        settings.calibration = outputs.calibration;
+    }
+
+    /// The loaded images, the kernel bank and the parameters as a session,
+    /// with the last run's maps when `maps` is set and there is a run.
+    fn session(&self, maps: bool) -> Result<Session, String> {
+        let Some(gray) = &self.slide.gray else {
+            return Err(
+                "Load the slide first; streamed slides are too large for a session.".to_owned(),
+            );
+        };
+        if self.kernels.is_empty() {
+            return Err("Split the kernels first.".to_owned());
+        }
+        let image = |image: &LoadedImage| match &image.gray {
+            Some(gray) => SessionImage::new(
+                &image.name,
+                &image.source,
+                image.hash,
+                gray,
+                image.rgb.as_ref(),
+            ),
+            None => Ok(SessionImage::named(&image.name, &image.source, image.hash)),
+        };
+        let mut pipeline = self.pipeline_parameters();
+        let mut responses = Vec::new();
+        if maps && let Some(run) = &self.run {
+            for index in 0..self.kernels.len() {
+                let Some((response, _, _)) = self.full_response(index) else {
+                    break;
+                };
+                responses.push(session::encode_map(&response));
+            }
+            // The maps come back under the parameters they were computed
+            // with, whatever was changed since.
+            let convolution = &mut pipeline.convolution;
+            convolution.mode = run.mode;
+            convolution.border = run.border;
+            convolution.sampling = run.sampling;
+            convolution.channels = run.channels;
+            convolution.strict_reproducibility = run.strict;
+            convolution.roi = run.roi;
+            pipeline.scoring.activation_k = run.activation_k;
+        }
+        Ok(Session {
+            version: session::SESSION_VERSION,
+            pipeline,
+            slide: SessionImage::new(
+                &self.slide.name,
+                &self.slide.source,
+                self.slide.hash,
+                gray,
+                self.slide.rgb.as_ref(),
+            )?,
+            kernels_sheet: image(&self.kernels_sheet)?,
+            second_slide: self
+                .second_slide
+                .gray
+                .is_some()
+                .then(|| image(&self.second_slide))
+                .transpose()?,
+            kernels: self.kernels.clone(),
+            grid: (self.kernel_rows, self.kernel_cols),
+            selected: self.selected_kernel,
+            maps: responses,
+        })
+    }
+
+    fn save_session(&mut self) {
+        let started = Instant::now();
+        let session = match self.session(self.settings.session_maps) {
+            Ok(session) => session,
+            Err(e) => {
+                self.status = format!("Session not saved: {e}");
+                return;
+            }
+        };
+        let file_name = format!("session_{}{SESSION_EXTENSION}", unix_timestamp());
+        let saved = session.to_json().and_then(|json| {
+            export::save_file(&self.settings.export_dir, &file_name, json.as_bytes())
+        });
+        self.status = match saved {
+            Ok(dest) => {
+                self.unsaved.edits = false;
+                if !session.maps.is_empty() {
+                    self.unsaved.results = false;
+                }
+                format!(
+                    "Saved the session{} to {dest} in {:.1} s.",
+                    if session.maps.is_empty() {
+                        ""
+                    } else {
+                        " with its maps"
+                    },
+                    started.elapsed().as_secs_f32()
+                )
+            }
+            Err(e) => format!("Session not saved: {e}"),
+        };
+    }
+
+    /// Resumes a saved session: its images, kernels and parameters replace
+    /// the loaded ones, and its maps, if it keeps them, become the results
+    /// of a run.
+    fn load_session(&mut self, ctx: &egui::Context, bytes: &[u8]) {
+        let session = match Session::from_json(bytes) {
+            Ok(session) => session,
+            Err(e) => {
+                self.status = e;
+                return;
+            }
+        };
+        let images = [
+            (Slot::Slide, Some(&session.slide)),
+            (Slot::KernelsSheet, Some(&session.kernels_sheet)),
+            (Slot::SecondSlide, session.second_slide.as_ref()),
+        ];
+        for (slot, image) in images {
+            let Some(image) = image else {
+                *self.loaded_mut(slot) = LoadedImage::default();
+                continue;
+            };
+            match image.png() {
+                Ok(Some(png)) => {
+                    self.load_png_into_slot(
+                        ctx,
+                        png,
+                        image.name.clone(),
+                        image.source.clone(),
+                        slot,
+                    );
+                    if self.loaded(slot).gray.is_none() {
+                        return;
+                    }
+                }
+                Ok(None) if slot == Slot::KernelsSheet => {
+                    self.kernels_sheet = LoadedImage {
+                        name: image.name.clone(),
+                        source: image.source.clone(),
+                        ..LoadedImage::default()
+                    };
+                }
+                Ok(None) => {
+                    self.status = "The session holds no slide.".to_owned();
+                    return;
+                }
+                Err(e) => {
+                    self.status = e;
+                    return;
+                }
+            }
+            // Pipelines exported later name the original files.
+            self.loaded_mut(slot).hash = image.hash;
+        }
+        let pipeline = &session.pipeline;
+        self.apply_pipeline_parameters(pipeline);
+        self.apply_pipeline_outputs(&pipeline.outputs);
+        self.split_normalization = pipeline.kernels.normalization;
+        self.split_at_separators = pipeline.kernels.separators;
+        self.split_remainder = pipeline.kernels.remainder;
+        self.previous_revision = None;
+        self.pinned_kernels.clear();
+        self.kernels = session.kernels;
+        (self.kernel_rows, self.kernel_cols) = session.grid;
+        self.selected_kernel = session.selected.min(self.kernels.len() - 1);
+        self.unsaved = UnsavedWork::default();
+        // Loading the slide cleared the region.
+        self.roi = pipeline.convolution.roi;
+        let resumed = format!(
+            "Resumed the session of {} with {} kernels",
+            self.slide.name,
+            self.kernels.len()
+        );
+        self.status = if session.maps.is_empty() {
+            format!("{resumed}; press Run for the results.")
+        } else {
+            match self.restore_maps(&session.maps) {
+                Ok(()) => format!("{resumed} and their maps."),
+                Err(e) => format!("{resumed}, but not their maps: {e}"),
+            }
+        };
+    }
+
+    /// Takes a session's maps, one per kernel, as the results of a run of
+    /// the loaded slide and kernels under the current parameters.
+    fn restore_maps(&mut self, maps: &[String]) -> Result<(), String> {
+        let Some(mut job) = self.prepare_run() else {
+            return Err(std::mem::take(&mut self.status));
+        };
+        let (width, height) = job.map_size();
+        for (index, map) in maps.iter().enumerate() {
+            let response = match session::decode_map(map, index, width * height) {
+                Ok(response) => response,
+                Err(e) => {
+                    self.previews.clear();
+                    return Err(e);
+                }
+            };
+            job.keep(index, &response);
+            self.previews.push(job.exact_preview(&response));
+        }
+        self.run = Some(job);
+        self.rescore();
+        Ok(())
+    }
+
+    /// Applies a pipeline's kernel shape, convolution and scoring
+    /// parameters and muted tiles, leaving its inputs and outputs aside.
+    fn apply_pipeline_parameters(&mut self, pipeline: &Pipeline) {
+        self.kernel_shape = pipeline.kernels.shape;
+        let settings = &mut self.settings;
+        settings.kernel_normalization = pipeline.kernels.normalization;
+        settings.detect_separators = pipeline.kernels.separators;
+        settings.sheet_remainder = pipeline.kernels.remainder;
+        settings.backend_choice = BackendChoice::Fixed(pipeline.convolution.backend);
+        settings.strict_reproducibility = pipeline.convolution.strict_reproducibility;
+        settings.filter_mode = pipeline.convolution.mode;
+        settings.stain_normalization = pipeline.convolution.stain;
+        settings.channel_mode = pipeline.convolution.channels;
+        settings.border_mode = pipeline.convolution.border;
+        settings.sampling = pipeline.convolution.sampling;
+        if let Some(reference) = &pipeline.convolution.stain_reference {
+            settings.stain_reference = Some(reference.clone());
+        }
+        settings.score_normalization = pipeline.scoring.normalization;
+        settings.activation_k = pipeline.scoring.activation_k;
+        if pipeline.scoring.permutations > 0 {
+            settings.permutations = pipeline.scoring.permutations;
+        }
+        // Splitting the sheet applies the mutes stored for its hash.
+        let sheet = pipeline.kernels.sheet.hash.clone();
+        if pipeline.kernels.muted.is_empty() {
+            settings.muted_kernels.remove(&sheet);
+        } else if !sheet.is_empty() {
+            let muted = pipeline.kernels.muted.iter().copied().collect();
+            settings.muted_kernels.insert(sheet, muted);
+        }
+    }
+
+    /// The current run as a [`SharedRun`]: its pipeline, with the inputs'
+    /// file names only, and the scores of its best enabled kernels.
+    fn shared_run(&self) -> Result<SharedRun, String> {
+        if self.previews.is_empty() {
+            return Err("Run the kernels before sharing the results.".to_owned());
+        }
+        let mut pipeline = self.pipeline()?;
+        // Full paths say nothing to the recipient and may say too much about
+        // this machine.
+        pipeline.slide.path = self.slide.name.clone();
+        pipeline.kernels.sheet.path = self.kernels_sheet.name.clone();
+        pipeline.outputs.dir.clear();
+        let mut ranked: Vec<usize> = (0..self.previews.len())
+            .filter(|&i| self.kernels[i].enabled)
+            .collect();
+        ranked.sort_by(|&a, &b| self.previews[b].score.total_cmp(&self.previews[a].score));
+        let scores = ranked
+            .into_iter()
+            .take(share::MAX_SHARED_KERNELS)
+            .map(|index| SharedScore {
+                index,
+                name: self.kernels[index].name.clone(),
+                score: self.previews[index].score,
+            })
+            .collect();
+        Ok(SharedRun {
+            pipeline,
+            kernels: self.kernels.len(),
+            scores,
+            selected: self.selected_kernel,
+        })
+    }
+
+    fn copy_share_link(&mut self, ctx: &egui::Context) {
+        #[cfg(target_arch = "wasm32")]
+        let base = page_address().unwrap_or_default();
+        #[cfg(not(target_arch = "wasm32"))]
+        let base = self.settings.share_url.trim().to_owned();
+        if base.is_empty() {
+            self.status = "Enter the web app's address first.".to_owned();
+            return;
+        }
+        self.status = match self.shared_run().and_then(|run| run.link(&base)) {
+            Ok(link) => {
+                let status = format!(
+                    "Copied a share link of {} characters. The recipient loads {} and {} themselves.",
+                    link.len(),
+                    self.slide.name,
+                    self.kernels_sheet.name
+                );
+                ctx.copy_text(link);
+                status
+            }
+            Err(e) => e,
+        };
+    }
+
+    /// Applies the run in the fragment of `link`, if it has one, and keeps
+    /// its scores for comparison with this machine's.
+    fn open_share_link(&mut self, link: &str) {
+        let run = match SharedRun::from_link(link) {
+            Ok(Some(run)) => run,
+            Ok(None) => {
+                // The page's own address has no fragment most of the time.
+                if link.contains('#') {
+                    self.status = "That link holds no shared run.".to_owned();
+                }
+                return;
+            }
+            Err(e) => {
+                self.status = e;
+                return;
+            }
+        };
+        self.apply_pipeline_parameters(&run.pipeline);
+        self.rescore();
+        self.status = format!(
+            "Opened a shared run of {} on {}. Load both images to compare scores.",
+            run.pipeline.kernels.sheet.path, run.pipeline.slide.path
+        );
+        self.shared = Some(run);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn load_pipeline_inputs(&mut self, ctx: &egui::Context, pipeline: &Pipeline, source: &str) {
+        let base = std::path::Path::new(source)
+            .parent()
+            .unwrap_or(std::path::Path::new("."));
+        let inputs = [
+            (&pipeline.slide, Slot::Slide),
+            (&pipeline.kernels.sheet, Slot::KernelsSheet),
+        ];
+        for (input, slot) in inputs {
+            match pipeline::read_input(input, base) {
+                Ok(bytes) => {
+                    let path = base.join(&input.path);
+                    let name = path
+                        .file_name()
+                        .map_or(input.path.clone(), |n| n.to_string_lossy().into_owned());
+                    self.load_png_into_slot(ctx, bytes, name, path.display().to_string(), slot);
+                }
+                Err(e) => {
+                    self.status = format!("Pipeline parameters applied, but: {e}");
+                    return;
+                }
+            }
+        }
+        self.split_kernels();
+        self.status = format!("{} Loaded pipeline inputs; press Run.", self.status);
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn load_pipeline_inputs(&mut self, _ctx: &egui::Context, pipeline: &Pipeline, _source: &str) {
+        self.status = format!(
+            "Pipeline parameters applied. Drop {} (slide) and {} (kernels sheet) after Reset.",
+            pipeline.slide.path, pipeline.kernels.sheet.path
+        );
+    }
+
+    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+        let dropped = ctx.input(|i| i.raw.dropped_files.clone());
+        if dropped.is_empty() {
+            return;
+        }
+
+        for file in dropped {
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some(dir) = file.path.as_ref().and_then(|path| path.parent()) {
+                self.open_dir = dir.display().to_string();
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some(path) = &file.path
+                && self.is_too_large_to_load(path)
+            {
+                self.streamed_slide = Some(path.clone());
+                self.status = format!(
+                    "{} is too large to load; stream it from the Large slides section.",
+                    file.name
+                );
+                continue;
+            }
+            if let Some(bytes) = extract_bytes(&file) {
+                let source = file
+                    .path
+                    .as_ref()
+                    .map_or(file.name.clone(), |p| p.display().to_string());
+                self.open_dropped(ctx, bytes, file.name, source);
+            } else {
+                self.status = "Could not read dropped file bytes.".to_owned();
+            }
+        }
+    }
+
+    /// Opens a dropped file, or one the operating system handed the
+    /// installed web app, unless it would replace unsaved work, in which
+    /// case it waits for the user to confirm.
+    fn open_dropped(&mut self, ctx: &egui::Context, bytes: Vec<u8>, name: String, source: String) {
+        let overwrites = if name.ends_with(".toml") {
+            false
+        } else if is_kernel_file(&name, &bytes) {
+            !self.kernels.is_empty()
+        } else if is_pipeline_file(&name) || name.ends_with(SESSION_EXTENSION) {
+            self.is_filled(Slot::Slide) || self.is_filled(Slot::KernelsSheet)
+        } else {
+            self.drop_slot.is_some_and(|slot| self.is_filled(slot))
+        };
+        if overwrites && self.unsaved.any() {
+            self.pending_discard = Some(Discard::Drop {
+                bytes,
+                name,
+                source,
+            });
+        } else {
+            self.load_dropped(ctx, bytes, name, source);
+        }
+    }
+
+    /// Opens a dropped file by its extension: profiles, kernel files,
+    /// pipelines and projects, sessions, or images for the next empty slot
+    /// unless drops go to a chosen one.
+    fn load_dropped(&mut self, ctx: &egui::Context, bytes: Vec<u8>, name: String, source: String) {
+        if name.ends_with(".toml") {
+            self.import_profile(&name, &bytes);
+        } else if is_kernel_file(&name, &bytes) {
+            self.load_kernel_file(&bytes, name, source);
+        } else if is_pipeline_file(&name) {
+            self.import_pipeline(ctx, &bytes, &source);
+        } else if name.ends_with(SESSION_EXTENSION) {
+            self.load_session(ctx, &bytes);
+        } else if let Some(slot) = self.drop_slot {
+            self.load_png_into_slot(ctx, bytes, name, source, slot);
+        } else if self.slide.gray.is_none() {
+            self.load_png_into_slot(ctx, bytes, name, source, Slot::Slide);
+        } else if self.kernels_sheet.name.is_empty() {
+            self.load_png_into_slot(ctx, bytes, name, source, Slot::KernelsSheet);
+        } else if self.second_slide.gray.is_none() {
+            self.load_png_into_slot(ctx, bytes, name, source, Slot::SecondSlide);
+        } else {
+            self.status = "All image slots are already filled. Choose a slot under \
+                           \"Drops go to\" to replace its file."
+                .to_owned();
+        }
+    }
+
+    /// Opens the files the operating system launched the installed web app
+    /// with, as if they had been dropped.
+    #[cfg(target_arch = "wasm32")]
+    fn poll_launches(&mut self, ctx: &egui::Context) {
+        let launched: Vec<_> = self.launches.iter().flat_map(|l| l.try_iter()).collect();
+        for result in launched {
+            match result {
+                Ok(file) => {
+                    let source = file.name.clone();
+                    self.open_dropped(ctx, file.bytes, file.name, source);
+                }
+                Err(e) => self.status = e,
+            }
+        }
+    }
+
+    /// Loads the images read back from browser storage into their slots.
+    #[cfg(target_arch = "wasm32")]
+    fn poll_restore(&mut self, ctx: &egui::Context) {
+        let restored: Vec<_> = self.restore.iter().flat_map(|r| r.try_iter()).collect();
+        for result in restored {
+            let file = match result {
+                Ok(file) => file,
+                Err(e) => {
+                    self.status = format!("Cannot restore images: {e}");
+                    continue;
+                }
+            };
+            let slots = [Slot::Slide, Slot::KernelsSheet, Slot::SecondSlide];
+            let Some(slot) = slots.into_iter().find(|s| s.cache_key() == file.key) else {
+                continue;
+            };
+            self.cached.retain(|(s, _)| *s != slot);
+            self.cached.push((slot, stats::content_hash(&file.bytes)));
+            let name = file.name.clone();
+            self.load_png_into_slot(ctx, file.bytes, file.name, name.clone(), slot);
+            self.status = format!("Restored {name} from browser storage. {}", self.status);
+        }
+    }
+
+    /// Keeps the image just loaded into `slot` in browser storage, unless
+    /// it is already there.
+    #[cfg(target_arch = "wasm32")]
+    fn remember_image(&mut self, slot: Slot, bytes: Vec<u8>) {
+        let loaded = self.loaded(slot);
+        let hash = loaded.hash;
+        if !self.settings.remember_images || self.cached.contains(&(slot, hash)) {
+            return;
+        }
+        browser_cache::store(slot.cache_key(), loaded.name.clone(), bytes);
+        self.cached.retain(|(s, _)| *s != slot);
+        self.cached.push((slot, hash));
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn browser_storage_panel(&mut self, ui: &mut egui::Ui) {
+        let remember = ui
+            .checkbox(
+                &mut self.settings.remember_images,
+                "Remember images across reloads",
+            )
+            .on_hover_text(
+                "Keeps the images loaded from now on in the browser's private storage, \
+                 so a reload restores them without dropping them again.",
+            );
+        if remember.changed() && !self.settings.remember_images {
+            self.forget_images();
+        }
+        if ui.button("Forget stored images").clicked() {
+            self.forget_images();
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn forget_images(&mut self) {
+        browser_cache::forget(&CACHE_KEYS);
+        self.cached.clear();
+        self.status = "Stored images deleted from browser storage.".to_owned();
+    }
+
+    /// Where dropped files go, "Open" buttons filling the slide and kernel
+    /// slots, and a swap for files dropped the wrong way round.
+    fn slot_bar(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Drops go to:");
+            ui.selectable_value(&mut self.drop_slot, None, "Next empty slot")
+                .on_hover_text("Fill the slide, then the kernels sheet, then the second slide.");
+            for slot in [Slot::Slide, Slot::KernelsSheet, Slot::SecondSlide] {
+                ui.selectable_value(&mut self.drop_slot, Some(slot), slot.label())
+                    .on_hover_text("Dropped images replace what this slot holds.");
+            }
+            ui.separator();
+            let waiting = self.file_pick.as_ref().map(|(slot, _)| *slot);
+            let choices = [
+                (Slot::Slide, "Open slide…", "Choose the slide", SLIDE_FILTER),
+                (
+                    Slot::KernelsSheet,
+                    "Open kernels…",
+                    "Choose a kernel sheet or file",
+                    KERNELS_FILTER,
+                ),
+            ];
+            for (slot, label, title, filter) in choices {
+                let button = ui.add_enabled(waiting.is_none(), egui::Button::new(label));
+                if button.clicked() {
+                    self.file_pick = Some((slot, file_dialog::pick(title, filter, &self.open_dir)));
+                }
+                if waiting == Some(slot) {
+                    ui.spinner();
+                }
+            }
+            ui.separator();
+            let swappable = self.slide.gray.is_some() && self.kernels_sheet.gray.is_some();
+            if ui
+                .add_enabled(swappable, egui::Button::new("Swap slide and sheet"))
+                .on_disabled_hover_text("Load both images first.")
+                .clicked()
+            {
+                self.swap_slide_and_sheet();
+            }
+        });
+    }
+
+    /// Exchanges the slide and the kernels sheet. The new slide is gray
+    /// only, as sheets keep no color.
+    fn swap_slide_and_sheet(&mut self) {
+        std::mem::swap(&mut self.slide, &mut self.kernels_sheet);
+        self.kernels_sheet.rgb = None;
+        self.comparison = None;
+        self.registration = None;
+        self.roi = None;
+        self.slide_view = ZoomPan::default();
+        self.revision_diff = None;
+        self.kernels.clear();
+        self.clear_results();
+        self.status = format!(
+            "{} is now the slide and {} the kernels sheet. Split the kernels again.",
+            self.slide.name, self.kernels_sheet.name
+        );
+    }
+
+    /// Takes the file chosen in an open dialog once it closes. Returns
+    /// whether the dialog is still open.
+    fn poll_file_pick(&mut self, ctx: &egui::Context) -> bool {
+        let Some((slot, receiver)) = &self.file_pick else {
+            return false;
+        };
+        let slot = *slot;
+        let file = match receiver.try_recv() {
+            Err(std::sync::mpsc::TryRecvError::Empty) => return true,
+            Ok(file) => file,
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                self.status = "The file dialog closed unexpectedly.".to_owned();
+                None
+            }
+        };
+        self.file_pick = None;
+        if let Some(file) = file {
+            self.open_picked(ctx, slot, file);
+        }
+        false
+    }
+
+    /// Loads a file chosen with an "Open" button into `slot`, once the
+    /// user confirms when that replaces unsaved work.
+    fn open_picked(&mut self, ctx: &egui::Context, slot: Slot, file: PickedFile) {
+        if self.is_filled(slot) && self.unsaved.any() {
+            self.pending_discard = Some(Discard::Pick { slot, file });
+        } else {
+            self.load_picked(ctx, slot, file);
+        }
+    }
+
+    /// Loads a file chosen with an "Open" button into `slot`, replacing
+    /// what it held. Kernel files in CSV or JSON replace the kernels sheet.
+    fn load_picked(&mut self, ctx: &egui::Context, slot: Slot, file: PickedFile) {
+        #[cfg(not(target_arch = "wasm32"))]
+        let (source, bytes) = {
+            if let Some(dir) = file.path.parent() {
+                self.open_dir = dir.display().to_string();
+            }
+            if slot == Slot::Slide && self.is_too_large_to_load(&file.path) {
+                self.status = format!(
+                    "{} is too large to load; stream it from the Large slides section.",
+                    file.name
+                );
+                self.streamed_slide = Some(file.path);
+                return;
+            }
+            match std::fs::read(&file.path) {
+                Ok(bytes) => (file.path.display().to_string(), bytes),
+                Err(e) => {
+                    self.status = format!("Cannot read {}: {e}", file.path.display());
+                    return;
+                }
+            }
+        };
+        #[cfg(target_arch = "wasm32")]
+        let (source, bytes) = (file.name.clone(), file.bytes);
+        let kernel_file = file.name.ends_with(".csv") || file.name.ends_with(".json");
+        if file.name.ends_with(SESSION_EXTENSION) {
+            self.load_session(ctx, &bytes);
+        } else if slot == Slot::KernelsSheet && kernel_file {
+            self.load_kernel_file(&bytes, file.name, source);
+        } else {
+            self.load_png_into_slot(ctx, bytes, file.name, source, slot);
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn is_too_large_to_load(&self, path: &std::path::Path) -> bool {
+        let is_png = path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("png"));
+        is_png
+            && streaming::png_size(path).is_ok_and(|(w, h)| {
+                w as u64 * h as u64 > self.settings.stream_above_megapixels as u64 * 1_000_000
+            })
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn start_stream(&mut self) {
+        let Some(path) = self.streamed_slide.clone() else {
+            return;
+        };
+        if !self.settings.sampling.is_dense() {
+            self.status =
+                "Streamed slides are convolved at every pixel; set stride and dilation to 1."
+                    .to_owned();
+            return;
+        }
+        let (kw, kh) = (self.kernel_shape.width(), self.kernel_shape.height());
+        // Each step convolves one tile of a band, not the whole slide.
+        let band_width = streaming::png_size(&path).map_or(0, |(w, _)| w as usize);
+        let tile = (
+            self.settings.stream_band_rows,
+            self.settings.stream_tile_cols,
+        );
+        let backend = self.resolve_backend(kw, kh, band_width.min(tile.1) * tile.0);
+        match StreamJob::new(
+            &path,
+            &self.kernels,
+            backend,
+            self.settings.filter_mode,
+            self.settings.border_mode,
+            tile,
+            &self.settings.export_dir,
+        ) {
+            Ok(job) => {
+                self.status = format!(
+                    "Streaming {} kernels over {} ({}x{}) with the {} backend...",
+                    job.kernel_count(),
+                    job.slide,
+                    job.width,
+                    job.height,
+                    backend.label()
+                );
+                self.stream_job = Some(job);
+                self.stream_mosaic = None;
+            }
+            Err(e) => self.status = e,
+        }
+    }
+
+    /// Advances the streaming job by one step. Returns whether work remains.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn stream_next_step(&mut self) -> bool {
+        let Some(job) = &mut self.stream_job else {
+            return false;
+        };
+        match job.step() {
+            Ok(true) => return true,
+            Ok(false) => {}
+            Err(e) => {
+                self.status = e;
+                self.stream_job = None;
+                return false;
+            }
+        }
+        let Some(job) = self.stream_job.take() else {
+            return false;
+        };
+        let (started, backend, work) = (job.started, job.backend, job.work());
+        self.settings
+            .usage
+            .record(Stage::StreamedRun, started, Some(backend), work);
+        self.status = match job.finish(self.settings.score_normalization, self.settings.csv_format)
+        {
+            Ok(summary) => summary,
+            Err(e) => e,
+        };
+        let elapsed = started.elapsed().as_secs_f32();
+        if self.settings.notify_on_completion
+            && elapsed >= self.settings.notify_min_seconds
+            && let Err(e) = notify::notify(APP_TITLE, &self.status)
+        {
+            log::warn!("{e}");
+        }
+        false
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn large_slides_panel(&mut self, ui: &mut egui::Ui) {
+        ui.add(
+            egui::DragValue::new(&mut self.settings.stream_above_megapixels)
+                .prefix("Stream slides above ")
+                .suffix(" MP"),
+        );
+        ui.add(
+            egui::DragValue::new(&mut self.settings.stream_band_rows)
+                .range(1..=65536)
+                .prefix("Band of ")
+                .suffix(" rows"),
+        );
+        ui.add(
+            egui::DragValue::new(&mut self.settings.stream_tile_cols)
+                .range(16..=1 << 20)
+                .prefix("Tiles of ")
+                .suffix(" columns"),
+        );
+        self.stream_mosaic_view(ui);
+        if let Some(job) = &self.stream_job {
+            ui.add(egui::ProgressBar::new(job.progress()).show_percentage());
+            if ui.button("Cancel").clicked() {
+                self.stream_job = None;
+                self.status = "Streaming cancelled; partial outputs were left on disk.".to_owned();
+            }
+            return;
+        }
+        let Some(path) = &self.streamed_slide else {
+            ui.label("Drop a PNG above the size limit to stream it.");
+            return;
+        };
+        ui.label(format!("Slide: {}", path.display()));
+        let ready = !self.kernels.is_empty();
+        if ui
+            .add_enabled(ready, egui::Button::new("Stream to disk"))
+            .on_disabled_hover_text("Load and split a kernel sheet first.")
+            .clicked()
+        {
+            self.start_stream();
+        }
+    }
+
+    /// Downsampled map of one kernel of the streaming job, as far as it has
+    /// been streamed, and of the last job once it is done.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn stream_mosaic_view(&mut self, ui: &mut egui::Ui) {
+        if let Some(job) = &self.stream_job {
+            let kernels = self.kernels.len().max(1);
+            ui.add(
+                egui::DragValue::new(&mut self.stream_preview)
+                    .range(0..=kernels - 1)
+                    .prefix("Preview kernel "),
+            );
+            let key = (self.stream_preview, job.rows_done(self.stream_preview));
+            let stale = self.stream_mosaic.as_ref().is_none_or(|(k, _)| *k != key);
+            if stale && let Some(mosaic) = job.mosaic(self.stream_preview) {
+                let size = [mosaic.width() as usize, mosaic.height() as usize];
+                let rgba: Vec<u8> = mosaic
+                    .pixels()
+                    .flat_map(|p| [p[0], p[0], p[0], p[1]])
+                    .collect();
+                let color = ColorImage::from_rgba_unmultiplied(size, &rgba);
+                let texture = ui
+                    .ctx()
+                    .load_texture("stream_mosaic", color, TextureOptions::LINEAR);
+                self.stream_mosaic = Some((key, texture));
+            }
+        }
+        if let Some(((kernel, _), texture)) = &self.stream_mosaic {
+            let size = texture.size_vec2();
+            let scale = (ui.available_width() / size.x).min(1.0);
+            ui.label(format!("Mosaic of kernel {kernel}:"));
+            ui.image((texture.id(), size * scale));
+        }
+    }
+
+    fn load_png_into_slot(
+        &mut self,
+        ctx: &egui::Context,
+        bytes: Vec<u8>,
+        file_name: String,
+        source: String,
+        slot: Slot,
+    ) {
+        let started = Instant::now();
+        match image::load_from_memory(&bytes) {
+            Ok(img) => {
+                let gray = img.to_luma8();
+                self.settings
+                    .usage
+                    .record(Stage::LoadImage, started, None, 0);
+                let color = gray_to_color_image(&gray);
+                let texture = ctx.load_texture(slot.texture_name(), color, TextureOptions::LINEAR);
+
+                let target = self.loaded_mut(slot);
+
+                target.name = file_name;
+                target.source = source;
+                target.hash = stats::content_hash(&bytes);
+                target.gray = Some(gray);
+                target.rgb = img.color().has_color().then(|| img.to_rgb8());
+                target.texture = Some(texture);
+                #[cfg(target_arch = "wasm32")]
+                self.remember_image(slot, bytes);
+                self.comparison = None;
+                if slot != Slot::KernelsSheet {
+                    self.registration = None;
+                }
+                if slot == Slot::Slide {
+                    // A shared run's region applies to its own slide only.
+                    let hash = pipeline::format_hash(self.slide.hash);
+                    self.roi = self
+                        .shared
+                        .as_ref()
+                        .filter(|run| run.pipeline.slide.hash == hash)
+                        .and_then(|run| run.pipeline.convolution.roi);
+                    self.slide_view = ZoomPan::default();
+                }
+                let warning = self.duplicate_warning(slot);
+                if slot == Slot::SecondSlide {
+                    self.status =
+                        "Second slide loaded. Register it against the slide to compare responses."
+                            .to_owned();
+                    self.status.extend(warning);
+                    return;
+                }
+                if slot == Slot::KernelsSheet && !self.kernels.is_empty() {
+                    self.previous_revision = Some(PreviousRevision {
+                        kernels: std::mem::take(&mut self.kernels),
+                        pinned: std::mem::take(&mut self.pinned_kernels),
+                    });
+                }
+                self.revision_diff = None;
+                self.kernels.clear();
+                self.clear_results();
+                self.status = "Image loaded. Choose kernel shape and press Split kernels.".to_owned();
+                self.status.extend(warning);
+            }
+            Err(e) => {
+                self.status = format!("Failed to decode PNG: {e}");
+            }
+        }
+    }
+
+    /// Whether loading into `slot` replaces an image, or for the kernels
+    /// sheet the kernels.
+    fn is_filled(&self, slot: Slot) -> bool {
+        !self.loaded(slot).name.is_empty()
+            || (slot == Slot::KernelsSheet && !self.kernels.is_empty())
+    }
+
+    fn loaded(&self, slot: Slot) -> &LoadedImage {
+        match slot {
+            Slot::Slide => &self.slide,
+            Slot::KernelsSheet => &self.kernels_sheet,
+            Slot::SecondSlide => &self.second_slide,
+        }
+    }
+
+    fn loaded_mut(&mut self, slot: Slot) -> &mut LoadedImage {
+        match slot {
+            Slot::Slide => &mut self.slide,
+            Slot::KernelsSheet => &mut self.kernels_sheet,
+            Slot::SecondSlide => &mut self.second_slide,
+        }
+    }
+
+    /// Why the image just loaded into `slot` may be a mistake: another slot
+    /// holds the same content, or the slide was already run since the last
+    /// Reset.
+    fn duplicate_warning(&self, slot: Slot) -> Option<String> {
+        let hash = self.loaded(slot).hash;
+        for other in [Slot::Slide, Slot::KernelsSheet, Slot::SecondSlide] {
+            let image = self.loaded(other);
+            if other != slot && image.gray.is_some() && image.hash == hash {
+                return Some(format!(
+                    " Warning: this is the same image as the {} ({}).",
+                    other.label().to_lowercase(),
+                    image.name
+                ));
+            }
+        }
+        let (_, name) = self
+            .processed_slides
+            .iter()
+            .find(|(processed, _)| slot == Slot::Slide && *processed == hash)?;
+        Some(format!(
+            " Warning: this slide was already run since the last Reset, as {name}."
+        ))
+    }
+
+    /// Replaces the bank with the kernels of a CSV or JSON kernel file,
+    /// which takes the kernels sheet's place.
+    fn load_kernel_file(&mut self, bytes: &[u8], file_name: String, source: String) {
+        let started = Instant::now();
+        let text = String::from_utf8_lossy(bytes);
+        let parsed = if file_name.ends_with(".csv") {
+            kernel_file::parse_csv(&text, &file_name, MAX_KERNEL_SIDE)
+        } else {
+            kernel_file::parse_json(&text, &file_name, MAX_KERNEL_SIDE)
+        };
+        let kernels = match parsed {
+            Ok(kernels) => kernels,
+            Err(e) => {
+                self.status = e;
+                return;
+            }
+        };
+        if !self.kernels.is_empty() {
+            self.previous_revision = Some(PreviousRevision {
+                kernels: std::mem::take(&mut self.kernels),
+                pinned: std::mem::take(&mut self.pinned_kernels),
+            });
+        }
+        self.kernels_sheet = LoadedImage {
+            name: file_name,
+            source,
+            hash: stats::content_hash(bytes),
+            ..LoadedImage::default()
+        };
+        self.kernels = kernels;
+        self.unsaved.edits = false;
+        self.kernel_rows = 0;
+        self.kernel_cols = 0;
+        // Kernels of one size also set the shape used for benchmarks.
+        let (width, height) = (self.kernels[0].width, self.kernels[0].height);
+        if self
+            .kernels
+            .iter()
+            .all(|k| (k.width, k.height) == (width, height))
+            && let Ok(shape) = KernelShape::new(width, height)
+        {
+            self.kernel_shape = shape;
+        }
+        self.revision_diff = None;
+        self.comparison = None;
+        self.clear_results();
+        self.settings
+            .usage
+            .record(Stage::SplitKernels, started, None, 0);
+        self.status = format!(
+            "Loaded {} kernels from {}.",
+            self.kernels.len(),
+            self.kernels_sheet.name
+        );
+        let key = pipeline::format_hash(self.kernels_sheet.hash);
+        for &index in self.settings.muted_kernels.get(&key).into_iter().flatten() {
+            if let Some(kernel) = self.kernels.get_mut(index) {
+                kernel.enabled = false;
+            }
+        }
+        self.apply_previous_revision();
+        self.store_muted();
+        self.restore_selection();
+        let hash = pipeline::format_hash(self.kernels_sheet.hash);
+        if let Some(run) = &self.shared
+            && run.pipeline.kernels.sheet.hash == hash
+            && run.selected < self.kernels.len()
+        {
+            self.selected_kernel = run.selected;
+        }
+    }
+
+    fn split_kernels(&mut self) {
+        let Some(sheet) = self.kernels_sheet.gray.as_ref() else {
+            self.status = "Load the kernels sheet first.".to_owned();
+            return;
+        };
+
+        let started = Instant::now();
+        let kw = self.kernel_shape.width() as u32;
+        let kh = self.kernel_shape.height() as u32;
+        let unscaled;
+        let separated = if self.settings.detect_separators {
+            kernel::split_at_separators(sheet, &self.kernels_sheet.name, MAX_KERNEL_SIDE)
+        } else {
+            Ok(None)
+        };
+        let split = match separated {
+            Ok(Some(split)) => Ok((split, true)),
+            Ok(None) => {
+                let remainder = self.settings.sheet_remainder;
+                kernel::split_sheet(sheet, &self.kernels_sheet.name, (kw, kh), remainder)
+                    .map(|split| (split, false))
+            }
+            Err(e) => Err(e),
+        };
+        match split {
+            Ok(((mut kernels, rows, cols), at_separators)) => {
+                if let Some(rgb) = &self.kernels_sheet.rgb {
+                    kernel::add_color_weights(&mut kernels, rgb);
+                }
+                let normalization = self.settings.kernel_normalization;
+                unscaled = normalization.apply(&mut kernels);
+                self.split_normalization = normalization;
+                self.split_at_separators = at_separators;
+                self.split_remainder = self.settings.sheet_remainder;
+                self.kernels = kernels;
+                self.unsaved.edits = false;
+                self.kernel_rows = rows;
+                self.kernel_cols = cols;
+            }
+            Err(e) => {
+                self.status = e;
+                return;
+            }
+        }
+        self.clear_results();
+        self.settings
+            .usage
+            .record(Stage::SplitKernels, started, None, 0);
+
+        self.status = format!(
+            "Split into {} kernels ({} rows x {} cols).",
+            self.kernels.len(),
+            self.kernel_rows,
+            self.kernel_cols
+        );
+        if self.split_at_separators {
+            let first = (self.kernels[0].width, self.kernels[0].height);
+            let uniform = self.kernels.iter().all(|k| (k.width, k.height) == first);
+            // Blocks of one size stand for the kernel shape, which picks the
+            // backend and is recorded in pipelines.
+            if uniform && let Ok(shape) = KernelShape::new(first.0, first.1) {
+                self.kernel_shape = shape;
+            }
+            self.status = format!(
+                "Split into {} kernels between separator lines ({} rows x {} cols{}).",
+                self.kernels.len(),
+                self.kernel_rows,
+                self.kernel_cols,
+                if uniform { "" } else { ", of several sizes" }
+            );
+        } else if self.settings.detect_separators {
+            self.status = format!("{} The sheet has no separator lines.", self.status);
+        }
+        let (sw, sh) = self
+            .kernels_sheet
+            .gray
+            .as_ref()
+            .map_or((0, 0), |s| s.dimensions());
+        let (rx, ry) = self.kernel_shape.sheet_remainder(sw, sh);
+        if !self.split_at_separators && (rx, ry) != (0, 0) {
+            let note = match self.split_remainder {
+                SheetRemainder::Skip => "skipped",
+                SheetRemainder::Crop => "cropped evenly from the edges",
+                SheetRemainder::Pad => "padded into tiles with zeros",
+            };
+            self.status = format!(
+                "{} The {rx} leftover columns and {ry} rows were {note}.",
+                self.status
+            );
+        }
+        if unscaled > 0 {
+            self.status = format!(
+                "{} {unscaled} kernels sum to about zero and were not normalized.",
+                self.status
+            );
+        }
+        let key = pipeline::format_hash(self.kernels_sheet.hash);
+        for &index in self.settings.muted_kernels.get(&key).into_iter().flatten() {
+            if let Some(kernel) = self.kernels.get_mut(index) {
+                kernel.enabled = false;
+            }
+        }
+        self.apply_previous_revision();
+        self.store_muted();
+        self.restore_selection();
+    }
+
+    /// Sheet tiles, or kernels of a kernel file, that are muted. Kernels
+    /// built in the app are not part of the sheet, so their flags are not
+    /// remembered.
+    fn muted_tiles(&self) -> BTreeSet<usize> {
+        self.kernels
+            .iter()
+            .enumerate()
+            .filter(|(_, k)| {
+                !k.enabled
+                    && matches!(
+                        k.provenance,
+                        Provenance::SheetTile { .. } | Provenance::File { .. }
+                    )
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Remembers the loaded sheet's muted tiles across sessions.
+    fn store_muted(&mut self) {
+        let key = pipeline::format_hash(self.kernels_sheet.hash);
+        let muted = self.muted_tiles();
+        if muted.is_empty() {
+            self.settings.muted_kernels.remove(&key);
+        } else {
+            self.settings.muted_kernels.insert(key, muted);
+        }
+    }
+
+    /// Mutes or unmutes the kernels ticked in the statistics table.
+    fn set_checked_enabled(&mut self, enabled: bool) {
+        for &index in &self.checked_kernels {
+            if let Some(kernel) = self.kernels.get_mut(index) {
+                kernel.enabled = enabled;
+            }
+        }
+        self.store_muted();
+        self.status = format!(
+            "{} {} kernels.",
+            if enabled { "Unmuted" } else { "Muted" },
+            self.checked_kernels.len()
+        );
+    }
+
+    /// Matches the freshly split kernels against the replaced sheet and
+    /// carries names, notes, mutes and pins over to matched kernels. The
+    /// previous revision is kept if its kernel size differs, e.g. while the
+    /// user is still picking the right shape.
+    fn apply_previous_revision(&mut self) {
+        let size = self.kernels.first().map(|k| k.weights.len());
+        let Some(previous) = self
+            .previous_revision
+            .take_if(|p| p.kernels.first().map(|k| k.weights.len()) == size)
+        else {
+            return;
+        };
+        let diff = kernel::match_revisions(&previous.kernels, &self.kernels);
+        for (i, change) in diff.changes.iter().enumerate() {
+            let Some(old) = change.previous() else {
+                continue;
+            };
+            let kernel = &mut self.kernels[i];
+            kernel.name = previous.kernels[old].name.clone();
+            kernel.note = previous.kernels[old].note.clone();
+            kernel.group = previous.kernels[old].group.clone();
+            kernel.mask = previous.kernels[old].mask.clone();
+            kernel.enabled &= previous.kernels[old].enabled;
+            kernel.history = previous.kernels[old].history.clone();
+            kernel.history.push(format!(
+                "Matched to kernel {old} of the previous sheet ({}).",
+                previous.kernels[old].provenance
+            ));
+            if previous.pinned.contains(&old) {
+                self.pinned_kernels.insert(i);
+            }
+        }
+        let count = |f: fn(&KernelChange) -> bool| diff.changes.iter().filter(|c| f(c)).count();
+        self.status = format!(
+            "{} Compared to the previous sheet: {} unchanged, {} changed, {} added, {} removed.",
+            self.status,
+            count(|c| matches!(c, KernelChange::Unchanged { .. })),
+            count(|c| matches!(c, KernelChange::Changed { .. })),
+            count(|c| matches!(c, KernelChange::Added)),
+            diff.removed.len()
+        );
+        self.revision_diff = Some(diff);
+    }
+
+    /// Replaces NaN and infinite input pixels and kernel weights with 0
+    /// when repair is on, returning how many pixels were replaced. Fails on
+    /// non-finite weights left in place, which spoil every pixel of their
+    /// maps; non-finite pixels only spoil their neighbourhoods and are
+    /// counted in each map's statistics.
+    fn guard_non_finite(&mut self, input: &mut [f32]) -> Result<usize, String> {
+        let bad: Vec<usize> = (0..self.kernels.len())
+            .filter(|&i| stats::non_finite(&self.kernels[i].weights) > 0)
+            .collect();
+        if !self.settings.repair_non_finite {
+            if let Some(first) = bad.first() {
+                return Err(format!(
+                    "{} kernels, starting with kernel {first}, have NaN or infinite weights. \
+                     Fix them or turn on Repair NaN and Inf.",
+                    bad.len()
+                ));
+            }
+            let pixels = stats::non_finite(input);
+            if pixels > 0 {
+                log::warn!("{pixels} input pixels are NaN or infinite.");
+            }
+            return Ok(0);
+        }
+        for i in bad {
+            let kernel = &mut self.kernels[i];
+            let replaced = stats::replace_non_finite(&mut kernel.weights);
+            if let Some((row, column)) = &mut kernel.separable {
+                stats::replace_non_finite(row);
+                stats::replace_non_finite(column);
+            }
+            for channel in kernel.color.iter_mut().flatten() {
+                stats::replace_non_finite(channel);
+            }
+            kernel.history.push(format!(
+                "Replaced {replaced} NaN or infinite weights with 0."
+            ));
+        }
+        Ok(stats::replace_non_finite(input))
+    }
+
+    /// Run context of the slide's next run, as the run panel sets it up,
+    /// with the previous run's results dropped. `None`, with the reason in
+    /// the status, when the slide cannot be run.
+    fn prepare_run(&mut self) -> Option<RunContext> {
+        let Some(slide) = self.slide.gray.as_ref() else {
+            self.status = "Load the histological slide first.".to_owned();
+            return None;
+        };
+        if self.kernels.is_empty() {
+            self.status = "Split kernels first.".to_owned();
+            return None;
+        }
+
+        let roi = self
+            .roi
+            .filter(|roi| roi.fits((slide.width() as usize, slide.height() as usize)));
+        let cropped = roi.map(|roi| {
+            (
+                roi.crop(slide),
+                self.slide.rgb.as_ref().map(|c| roi.crop(c)),
+            )
+        });
+        let (slide, rgb) = match &cropped {
+            Some((gray, rgb)) => (gray, rgb.as_ref()),
+            None => (slide, self.slide.rgb.as_ref()),
+        };
+        let mut input = self.slide_input(slide, rgb);
+        let channels = self.settings.channel_mode;
+        let planes = match rgb {
+            Some(rgb) if channels.is_multichannel() => channels::planes(rgb),
+            _ => Vec::new(),
+        };
+        let (width, height) = (slide.width() as usize, slide.height() as usize);
+        let repaired = match self.guard_non_finite(&mut input) {
+            Ok(repaired) => repaired,
+            Err(e) => {
+                self.status = e;
+                return None;
+            }
+        };
+        if !self
+            .processed_slides
+            .iter()
+            .any(|(h, _)| *h == self.slide.hash)
+        {
+            let slide = (self.slide.hash, self.slide.name.clone());
+            self.processed_slides.push(slide);
+        }
+        let kw = self.kernel_shape.width();
+        let kh = self.kernel_shape.height();
+        let backend = self.resolve_backend(kw, kh, width * height);
+        let activation_k = self.settings.activation_k;
+        let mode = self.settings.filter_mode;
+        let sampling = match self.settings.sampling.checked() {
+            Ok(sampling) => sampling,
+            Err(e) => {
+                self.status = e;
+                return None;
+            }
+        };
+        self.previews.clear();
+        self.previews.reserve(self.kernels.len());
+        self.significance_queue.clear();
+        self.significance_run = None;
+        self.autocorrelation = None;
+        // Frees the previous run's kept maps before new ones are written.
+        self.run = None;
+        self.gpu_run = None;
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.cpu_run = None;
+            self.carry_over = None;
+            self.refining = None;
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.chunked = None;
+        }
+        let map_input: Arc<[f32]> = sampling.subsample(&input, width, height).into();
+        Some(RunContext {
+            image_std: stats::mean_std(&input).1 as f32,
+            input: input.into(),
+            map_input,
+            planes: planes.into(),
+            channels,
+            width,
+            height,
+            roi,
+            repaired,
+            kw,
+            kh,
+            backend,
+            mode,
+            border: self.settings.border_mode,
+            sampling,
+            activation_k,
+            strict: self.settings.strict_reproducibility,
+            stain_reference: self.stain_reference_name(self.slide.rgb.is_some()),
+            baselines: Vec::new(),
+            // Sparse maps take the place of the full ones.
+            #[cfg(not(target_arch = "wasm32"))]
+            responses: (self.settings.maps_on_disk && !self.settings.sparse_maps)
+                .then(|| {
+                    let (map_width, map_height) = sampling.output_size(width, height);
+                    ResponseStore::new(self.kernels.len(), map_width * map_height)
+                })
+                .and_then(|store| {
+                    store
+                        .inspect_err(|e| log::warn!("{e}; responses will be recomputed."))
+                        .ok()
+                }),
+            sparse: self.settings.sparse_maps.then(SparseStore::default),
+        })
+    }
+
+    fn run_all_convolutions(&mut self) {
+        if let Some(job) = self.prepare_run() {
+            self.run_all(job);
+        }
+    }
+
+    /// Runs every kernel for `job`, a context [`Self::prepare_run`] set up.
+    fn run_all(&mut self, job: RunContext) {
+        let started = Instant::now();
+        self.run_started = Some(started);
+        let (width, height) = (job.width, job.height);
+        let (backend, activation_k, sampling) = (job.backend, job.activation_k, job.sampling);
+        let taps = self.kernels.iter().map(Kernel::taps).sum::<usize>() as u64;
+
+        // Drafts stand in for dense maps, so strided runs go straight to
+        // their exact maps.
+        if self.settings.draft_mode && sampling.is_dense() {
+            let (draft, draft_planes, dw, dh) = job.draft();
+            let draft_preview = |kernel: &Kernel| {
+                let response = job.respond_to(kernel, &draft, &draft_planes, dw, dh);
+                build_draft_preview(&response, &draft, dw, dh, width, height, activation_k)
+            };
+            #[cfg(not(target_arch = "wasm32"))]
+            let previews = self.kernels.par_iter().map(draft_preview).collect();
+            #[cfg(target_arch = "wasm32")]
+            let previews = self.kernels.iter().map(draft_preview).collect();
+            self.previews = previews;
+            self.run = Some(job);
+            self.rescore();
+            self.settings.usage.record(
+                Stage::Convolution,
+                started,
+                Some(backend),
+                (dw * dh) as u64 * taps,
+            );
+            self.status = format!(
+                "Computed {} draft maps at 1/{} resolution ({} backend); refining selected and pinned kernels.",
+                self.previews.len(),
+                DRAFT_FACTOR,
+                backend.label()
+            );
+            return;
+        }
+
+        if let Some(gpu_run) = self.start_gpu_run(&job) {
+            self.status = format!(
+                "Running {} kernels on {}...",
+                self.kernels.len(),
+                self.gpu.label()
+            );
+            self.gpu_run = Some(gpu_run);
+            self.run = Some(job);
+            return;
+        }
+        #[cfg(target_arch = "wasm32")]
+        if self.webgl(&job).is_none() {
+            let kernels = self.kernels.iter().cloned();
+            self.chunked = Some(job.chunked_run(kernels.enumerate().collect()));
+            self.status = format!(
+                "Running {} kernels ({} backend)...",
+                self.kernels.len(),
+                backend.label()
+            );
+            self.run = Some(job);
+            return;
+        }
+
+        // Natively the CPU runs on a background thread, which reports
+        // progress and can be cancelled.
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.cpu_run = Some(start_cpu_run(self.kernels.clone(), 0, &job, None, None));
+            self.status = format!(
+                "Running {} kernels ({} backend)...",
+                self.kernels.len(),
+                backend.label()
+            );
+            self.run = Some(job);
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            let mut job = job;
+            let (map_width, map_height) = job.map_size();
+            for index in 0..self.kernels.len() {
+                let response = self.convolve_exact(&job, index);
+                job.keep(index, &response);
+                self.previews.push(job.exact_preview(&response));
+            }
+            self.run = Some(job);
+            self.rescore();
+            self.settings.usage.record(
+                Stage::Convolution,
+                started,
+                Some(backend),
+                (map_width * map_height) as u64 * taps,
+            );
+            self.status = format!(
+                "Computed {} convolution maps ({} backend).",
+                self.previews.len(),
+                backend.label()
+            );
+            self.finish_run();
+        }
+    }
+
+    /// Moves the region of interest to `roi`. When the last run covered the
+    /// region it moved from, the maps follow it: natively, with the maps
+    /// kept and the run on a direct CPU backend, only the pixels the old
+    /// maps cannot give are convolved; otherwise every kernel runs again.
+    fn move_roi(&mut self, roi: Roi) {
+        let from = self.roi.replace(roi);
+        if from.is_none() || self.run.as_ref().is_none_or(|run| run.roi != from) {
+            self.status = format!(
+                "Runs convolve the {}x{} region at ({}, {}); run again to apply.",
+                roi.width, roi.height, roi.x, roi.y
+            );
+            return;
+        }
+        // Taken before the run is reset, which would delete the maps the new
+        // ones are made from.
+        #[cfg(not(target_arch = "wasm32"))]
+        let previous = self.run.take();
+        let Some(job) = self.prepare_run() else {
+            return;
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(mut previous) = previous
+            && job.carries_over(&previous)
+            && self.gpu_for(&job).is_none()
+            && self.gpu_for(&previous).is_none()
+            && let (Some(store), Some(from)) = (previous.responses.take(), previous.roi)
+        {
+            self.carry_over_maps(store, from, job);
+            return;
+        }
+        self.run_all(job);
+    }
+
+    /// Starts the background run that makes the maps of `job`'s region from
+    /// those of the region `from` in `store`, as [`respond_moved`] does.
+    /// Kernels whose map was not kept, or that were edited since, are
+    /// convolved in full.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn carry_over_maps(&mut self, store: ResponseStore, from: Roi, job: RunContext) {
+        let Some(to) = job.roi else {
+            return;
+        };
+        let carry = Arc::new(CarryOver {
+            store,
+            from,
+            to,
+            edited: self.dirty_kernels.clone(),
+            convolved: AtomicUsize::new(0),
+            work: AtomicU64::new(0),
+        });
+        let kernels = self.kernels.clone();
+        self.cpu_run = Some(start_cpu_run(kernels, 0, &job, None, Some(carry.clone())));
+        self.carry_over = Some(carry);
+        self.status = format!(
+            "Moving the maps of {} kernels to ({}, {})...",
+            self.kernels.len(),
+            to.x,
+            to.y
+        );
+        self.run = Some(job);
+    }
+
+    /// Full-resolution response of one kernel for `job`: in WebGL when that is
+    /// the browser's GPU path, on the run's CPU backend otherwise.
+    #[cfg(target_arch = "wasm32")]
+    fn convolve_exact(&self, job: &RunContext, index: usize) -> Vec<f32> {
+        let kernel = &self.kernels[index];
+        if let Some(gl) = self.webgl(job) {
+            let (kw, kh) = (kernel.width, kernel.height);
+            let oriented = kernel.oriented(job.mode);
+            let weights = oriented.masked_weights();
+            match gl.convolve(&job.input, job.width, job.height, &weights, kw, kh) {
+                Ok(response) => return response,
+                Err(e) => log::warn!("{e}; using the CPU."),
+            }
+        }
+        job.respond(kernel)
+    }
+
+    /// WebGL convolver to use for `job`, when that is the browser's GPU path.
+    /// Modes other than the weighted sums, borders other than zero, strided
+    /// or dilated runs and multi-channel runs always run on the CPU.
+    #[cfg(target_arch = "wasm32")]
+    fn webgl(&self, job: &RunContext) -> Option<&crate::webgl::GlConvolver> {
+        match &self.gpu {
+            BrowserGpu::WebGl(gl, _)
+                if self.gpu_wanted(job)
+                    && !job.strict
+                    && job.mode.is_linear()
+                    && job.border == BorderMode::Zero
+                    && job.sampling.is_dense()
+                    && job.planes.is_empty() =>
+            {
+                Some(gl)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether `job` should use a GPU path: it is turned on and, with the
+    /// automatic backend, the benchmark did not measure it slower than the
+    /// CPU on runs of this size.
+    fn gpu_wanted(&self, job: &RunContext) -> bool {
+        if !self.settings.use_gpu {
+            return false;
+        }
+        let (kw, kh) = (self.kernel_shape.width(), self.kernel_shape.height());
+        match (
+            self.settings.backend_choice,
+            self.settings.profile_for(kw, kh),
+        ) {
+            (BackendChoice::Auto, Some(profile)) if profile.gpu.is_some() => {
+                profile.gpu_fastest_for(job.width * job.height)
+            }
+            _ => true,
+        }
+    }
+
+    /// WebGPU or native GPU device to run `job` on, when the GPU is turned on
+    /// and the run can use it. The shaders pad with zeros, read one plane and
+    /// write every pixel, so other borders, multi-channel runs and strided or
+    /// dilated ones run on the CPU.
+    fn gpu_for(&self, job: &RunContext) -> Option<&GpuConvolver> {
+        if !self.gpu_wanted(job)
+            || job.strict
+            || !job.mode.is_linear()
+            || job.border != BorderMode::Zero
+            || !job.sampling.is_dense()
+            || !job.planes.is_empty()
+        {
+            return None;
+        }
+        self.gpu.convolver()
+    }
+
+    /// Uploads `job` to the GPU when that is the run's path.
+    fn start_gpu_run(&self, job: &RunContext) -> Option<GpuRun> {
+        let gpu = self.gpu_for(job)?;
+        let kernels = self
+            .kernels
+            .iter()
+            .map(|k| {
+                let weights = k.oriented(job.mode).masked_weights().into_owned();
+                (weights, (k.width, k.height))
+            })
+            .collect();
+        GpuRun::new(gpu, &job.input, job.width, job.height, kernels)
+            .inspect_err(|e| log::warn!("{e}; using the CPU."))
+            .ok()
+    }
+
+    /// Convolves the slide with the selected kernel on the run's CPU backend
+    /// and on the GPU, and reports both timings and how far the maps differ.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn compare_gpu(&mut self) {
+        let (Some(job), Some(gpu)) = (self.run.as_ref(), self.gpu.convolver()) else {
+            self.status = "Run the kernels once, with the GPU detected, to compare.".to_owned();
+            return;
+        };
+        let Some(kernel) = self.kernels.get(self.selected_kernel) else {
+            return;
+        };
+        let (kw, kh) = (kernel.width, kernel.height);
+        let oriented = kernel.oriented(job.mode);
+        let weights = oriented.masked_weights();
+        let started = Instant::now();
+        let cpu = job
+            .backend
+            .convolve(&job.input, job.width, job.height, &weights, kw, kh);
+        let cpu_ms = started.elapsed().as_secs_f64() * 1000.0;
+        let started = Instant::now();
+        let gpu_map = match gpu.convolve(&job.input, job.width, job.height, &weights, kw, kh) {
+            Ok(map) => map,
+            Err(e) => {
+                self.status = e;
+                return;
+            }
+        };
+        let gpu_ms = started.elapsed().as_secs_f64() * 1000.0;
+        let (max_difference, max_abs) = cpu
+            .iter()
+            .zip(&gpu_map)
+            .fold((0.0f32, 0.0f32), |(d, m), (c, g)| {
+                (d.max((c - g).abs()), m.max(c.abs()))
+            });
+        self.status = format!(
+            "Kernel {}: {} {cpu_ms:.1} ms, GPU {gpu_ms:.1} ms; largest difference {max_difference:.2e} \
+             ({:.2e} of the largest |r|).",
+            self.selected_kernel,
+            job.backend.label(),
+            max_difference / max_abs.max(f32::MIN_POSITIVE)
+        );
+    }
+
+    /// Collects GPU maps as they arrive. Returns true while the run is in
+    /// progress.
+    fn poll_gpu_run(&mut self) -> bool {
+        let (Some(gpu_run), Some(gpu), Some(job)) =
+            (&mut self.gpu_run, self.gpu.convolver(), &mut self.run)
+        else {
+            return false;
+        };
+        match gpu_run.poll(gpu) {
+            Ok(Some((index, response))) if index == self.previews.len() => {
+                job.keep(index, &response);
+                self.previews.push(job.exact_preview(&response));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                #[cfg(target_arch = "wasm32")]
+                {
+                    let remaining = (self.previews.len()..self.kernels.len())
+                        .map(|i| (i, self.kernels[i].clone()))
+                        .collect();
+                    self.chunked = Some(job.chunked_run(remaining));
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    let first = self.previews.len();
+                    let kernels = self.kernels[first..].to_vec();
+                    self.cpu_run = Some(start_cpu_run(kernels, first, job, None, None));
+                }
+                self.gpu_run = None;
+                self.status = format!("{e}; finishing on the CPU.");
+                return true;
+            }
+        }
+        if !gpu_run.is_done() {
+            return true;
+        }
+        let work = gpu_run.work();
+        self.gpu_run = None;
+        self.rescore();
+        if let Some(started) = self.run_started {
+            self.settings
+                .usage
+                .record(Stage::Convolution, started, None, work);
+        }
+        self.status = format!(
+            "Computed {} convolution maps ({}).",
+            self.previews.len(),
+            self.gpu.label()
+        );
+        false
+    }
+
+    /// Collects the maps of the background CPU run as they arrive. Returns
+    /// true while the run is in progress.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn poll_cpu_run(&mut self) -> bool {
+        let (Some(cpu_run), Some(job)) = (&mut self.cpu_run, &mut self.run) else {
+            return false;
+        };
+        for (_, (response, preview)) in cpu_run.poll() {
+            job.keep(self.previews.len(), &response);
+            self.previews.push(preview);
+        }
+        if !cpu_run.is_done() {
+            return true;
+        }
+        let started = self.run_started.unwrap_or(cpu_run.started);
+        let taps = self.kernels.iter().map(Kernel::taps).sum::<usize>();
+        let (map_width, map_height) = job.map_size();
+        let (backend, mut work) = (job.backend, (map_width * map_height * taps) as u64);
+        self.status = format!(
+            "Computed {} convolution maps ({} backend).",
+            self.previews.len(),
+            backend.label()
+        );
+        if let Some(carry) = self.carry_over.take() {
+            let total = map_width * map_height * self.kernels.len();
+            let convolved = carry.convolved.load(Ordering::Relaxed);
+            work = carry.work.load(Ordering::Relaxed);
+            self.status = format!(
+                "Moved the region to ({}, {}), convolving {:.1}% of its maps again ({:.2} s).",
+                carry.to.x,
+                carry.to.y,
+                100.0 * convolved as f64 / total.max(1) as f64,
+                started.elapsed().as_secs_f32()
+            );
+        }
+        self.cpu_run = None;
+        self.rescore();
+        self.settings
+            .usage
+            .record(Stage::Convolution, started, Some(backend), work);
+        false
+    }
+
+    /// Advances the time-sliced CPU convolutions by one frame's budget. Maps
+    /// of an exact run are appended; refined drafts replace their preview.
+    /// Returns true while work remains.
+    #[cfg(target_arch = "wasm32")]
+    fn poll_chunked_run(&mut self) -> bool {
+        let (Some(chunked), Some(job)) = (&mut self.chunked, &mut self.run) else {
+            return false;
+        };
+        let completed = chunked.step(&job.input, job.width, job.height, job.backend, job.mode);
+        let done = chunked.is_done();
+        let (started, work) = (chunked.started, chunked.work());
+        let backend = job.backend;
+        let mut refined = false;
+        for (index, response) in completed {
+            job.keep(index, &response);
+            let preview = job.exact_preview(&response);
+            if let Some(draft) = self.previews.get_mut(index) {
+                *draft = ConvolutionPreview {
+                    significance: draft.significance,
+                    ..preview
+                };
+                refined = true;
+            } else if index == self.previews.len() {
+                self.previews.push(preview);
+            }
+        }
+        if !done {
+            return true;
+        }
+        self.chunked = None;
+        self.rescore();
+        if refined {
+            self.settings
+                .usage
+                .record(Stage::DraftRefinement, started, Some(backend), work);
+            return false;
+        }
+        let started = self.run_started.unwrap_or(started);
+        self.settings
+            .usage
+            .record(Stage::Convolution, started, Some(backend), work);
+        self.status = format!(
+            "Computed {} convolution maps ({} backend).",
+            self.previews.len(),
+            backend.label()
+        );
+        false
+    }
+
+    /// Reports completion of the pending run with a notification when it
+    /// took long enough and notifications are enabled.
+    fn finish_run(&mut self) {
+        let fmt = self.settings.number_format;
+        let Some(started) = self.run_started.take() else {
+            return;
+        };
+        self.unsaved.results = true;
+        self.run_autosave_hooks();
+        let elapsed = started.elapsed().as_secs_f32();
+        if !self.settings.notify_on_completion || elapsed < self.settings.notify_min_seconds {
+            return;
+        }
+        let top = self
+            .previews
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| self.kernels[i].enabled)
+            .max_by(|a, b| a.1.score.total_cmp(&b.1.score));
+        let body = match top {
+            Some((i, p)) => format!(
+                "{} maps in {:.1} s. Top kernel: {} (score {}).",
+                self.previews.len(),
+                elapsed,
+                i,
+                fmt.format(p.score)
+            ),
+            None => format!("Finished in {elapsed:.1} s."),
+        };
+        if let Err(e) = notify::notify(APP_TITLE, &body) {
+            log::warn!("{e}");
+        }
+    }
+
+    /// Replaces draft previews with their full-resolution versions off the
+    /// UI thread. The selected kernel goes first, then pinned kernels in
+    /// index order. Returns true while drafts remain to refine.
+    fn refine_next_draft(&mut self) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.refining.is_some() {
+            return self.poll_refinement();
+        }
+        let Some(job) = self.run.as_ref() else {
+            return false;
+        };
+        let is_draft = |i: &usize| self.previews.get(*i).is_some_and(|p| !p.exact);
+        let mut targets: Vec<usize> = Vec::new();
+        for index in
+            std::iter::once(self.selected_kernel).chain(self.pinned_kernels.iter().copied())
+        {
+            if is_draft(&index) && !targets.contains(&index) {
+                targets.push(index);
+            }
+        }
+        if targets.is_empty() {
+            return false;
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let kernels = targets.iter().map(|&i| self.kernels[i].clone()).collect();
+            let gpu = self.gpu_for(job).and_then(|_| self.gpu.shared());
+            self.refining = Some((targets, start_cpu_run(kernels, 0, job, gpu, None)));
+            true
+        }
+        // Without a GPU the browser refines over several frames.
+        #[cfg(target_arch = "wasm32")]
+        {
+            let index = targets[0];
+            if self.webgl(job).is_none() {
+                if self.chunked.is_none() {
+                    let kernel = vec![(index, self.kernels[index].clone())];
+                    self.chunked = Some(job.chunked_run(kernel));
+                }
+                return true;
+            }
+            let started = Instant::now();
+            let response = self.convolve_exact(job, index);
+            let significance = self.previews[index].significance;
+            self.previews[index] = ConvolutionPreview {
+                significance,
+                ..job.exact_preview(&response)
+            };
+            let (map_width, map_height) = job.map_size();
+            let (backend, work) = (
+                job.backend,
+                (map_width * map_height * self.kernels[index].taps()) as u64,
+            );
+            if let Some(run) = self.run.as_mut() {
+                run.keep(index, &response);
+            }
+            self.rescore();
+            self.settings
+                .usage
+                .record(Stage::DraftRefinement, started, Some(backend), work);
+            true
+        }
+    }
+
+    /// Swaps in the refined maps that arrived. Returns true while the
+    /// refinement runs.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn poll_refinement(&mut self) -> bool {
+        let (Some((targets, refining)), Some(job)) = (&mut self.refining, &mut self.run) else {
+            return false;
+        };
+        let mut refined = false;
+        for (i, (response, preview)) in refining.poll() {
+            let index = targets[i];
+            // A kernel edited meanwhile has been convolved again.
+            if let Some(draft) = self.previews.get_mut(index).filter(|p| !p.exact) {
+                job.keep(index, &response);
+                *draft = ConvolutionPreview {
+                    significance: draft.significance,
+                    ..preview
+                };
+                refined = true;
+            }
+        }
+        let done = refining.is_done();
+        let started = refining.started;
+        let (map_width, map_height) = job.map_size();
+        let taps: usize = targets.iter().map(|&i| self.kernels[i].taps()).sum();
+        let (backend, work) = (job.backend, (map_width * map_height * taps) as u64);
+        if refined {
+            self.rescore();
+        }
+        if done {
+            self.refining = None;
+            self.settings
+                .usage
+                .record(Stage::DraftRefinement, started, Some(backend), work);
+        }
+        true
+    }
+
+    /// Runs the permutation tests of the queued kernels on the full slide,
+    /// off the UI thread. Returns true while kernels are queued or tested.
+    fn test_next_significance(&mut self) -> bool {
+        if self.significance_run.is_some() {
+            return self.poll_significance();
+        }
+        let Some(job) = self.run.as_ref() else {
+            self.significance_queue.clear();
+            return false;
+        };
+        if self.significance_queue.is_empty() {
+            return false;
+        }
+        let permutations = self.settings.permutations;
+        // Shuffled weights are no longer separable, so every shuffle runs the
+        // full window, with the kernel's index as the seed.
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let indices: Vec<usize> = self.significance_queue.drain(..).collect();
+            let kernels: Vec<Kernel> = indices.iter().map(|&i| self.kernels[i].clone()).collect();
+            let seeds = indices.clone();
+            let (convolver, mode) = (job.weights_convolver(), job.mode);
+            let run = CpuRun::start(kernels.len(), 0, permutations + 1, move |i, progress| {
+                let kernel = &kernels[i];
+                let (kw, kh) = (kernel.width, kernel.height);
+                let windows =
+                    scoring::permutation_windows(kernel, mode, permutations, seeds[i] as u64);
+                let mut scores = Vec::with_capacity(permutations + 1);
+                for weights in windows {
+                    if progress.is_cancelled() {
+                        return None;
+                    }
+                    let response = convolver.convolve(&weights, kw, kh);
+                    scores.push(scoring::raw_score(&response));
+                    progress.add_rows(1);
+                }
+                Some(Significance::from_null(scores[0], &scores[1..]))
+            });
+            self.significance_run = Some((indices, run));
+        }
+        // The worker convolves densely, so dilated weights are spread out
+        // beforehand and strided maps subsampled as they arrive.
+        #[cfg(target_arch = "wasm32")]
+        if let Some(index) = self.significance_queue.pop_front() {
+            let kernel = &self.kernels[index];
+            let (kw, kh) = (kernel.width, kernel.height);
+            let maps = scoring::permutation_windows(kernel, job.mode, permutations, index as u64)
+                .map(|weights| {
+                    let (dilated, dw, dh) = job.sampling.dilate(&weights, kw, kh);
+                    Kernel::new(dilated, (dw, dh), Provenance::Entered)
+                })
+                .enumerate()
+                .collect();
+            let chunked = ChunkedRun::new(maps, job.border, job.width, job.height);
+            self.significance_run = Some((index, chunked, Vec::new()));
+        }
+        true
+    }
+
+    /// Stores the permutation tests that finished. Returns true while tests
+    /// run or wait.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn poll_significance(&mut self) -> bool {
+        let (Some((indices, run)), Some(job)) = (&mut self.significance_run, &self.run) else {
+            return false;
+        };
+        for (i, significance) in run.poll() {
+            if let Some(preview) = self.previews.get_mut(indices[i]) {
+                preview.significance = Some(significance);
+            }
+        }
+        if !run.is_done() {
+            return true;
+        }
+        let taps: usize = indices.iter().map(|&i| self.kernels[i].taps()).sum();
+        let (map_width, map_height) = job.map_size();
+        let work = ((self.settings.permutations + 1) * map_width * map_height * taps) as u64;
+        self.settings
+            .usage
+            .record(Stage::PermutationTest, run.started, Some(job.backend), work);
+        self.significance_run = None;
+        true
+    }
+
+    /// Scores the maps of the kernel under test that arrived, and stores its
+    /// test once every shuffle is scored. Returns true while tests run or
+    /// wait.
+    #[cfg(target_arch = "wasm32")]
+    fn poll_significance(&mut self) -> bool {
+        let (Some((index, chunked, scores)), Some(job)) = (&mut self.significance_run, &self.run)
+        else {
+            return false;
+        };
+        let (width, height) = (job.width, job.height);
+        let maps = chunked.step(
+            &job.input,
+            width,
+            height,
+            job.backend,
+            FilterMode::Correlation,
+        );
+        for (_, response) in maps {
+            let response = job.sampling.subsample(&response, width, height);
+            scores.push(scoring::raw_score(&response));
+        }
+        if !chunked.is_done() {
+            return true;
+        }
+        let significance = Significance::from_null(scores[0], &scores[1..]);
+        if let Some(preview) = self.previews.get_mut(*index) {
+            preview.significance = Some(significance);
+        }
+        self.settings.usage.record(
+            Stage::PermutationTest,
+            chunked.started,
+            Some(job.backend),
+            chunked.work(),
+        );
+        self.significance_run = None;
+        true
+    }
+
+    /// Fraction of the running permutation tests done.
+    fn significance_progress(&self) -> Option<f32> {
+        self.significance_run.as_ref().map(|run| run.1.progress())
+    }
+
+    fn queue_significance(&mut self, kernels: impl IntoIterator<Item = usize>) {
+        // Rank filters ignore the weights, so shuffling them changes nothing.
+        if self.run.as_ref().is_some_and(|job| !job.mode.is_linear()) {
+            self.status = "The permutation test needs a convolution run.".to_owned();
+            return;
+        }
+        for index in kernels {
+            if !self.significance_queue.contains(&index) {
+                self.significance_queue.push_back(index);
+            }
+        }
+    }
+
+    fn significance_panel(&mut self, ui: &mut egui::Ui) {
+        let fmt = self.settings.number_format;
+        ui.horizontal(|ui| {
+            ui.label("Shuffles per kernel");
+            ui.add(egui::DragValue::new(&mut self.settings.permutations).range(19..=9999));
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Test selected kernel").clicked() {
+                self.queue_significance([self.selected_kernel]);
+            }
+            if ui.button("Test all kernels").clicked() {
+                self.queue_significance(0..self.previews.len());
+            }
+        });
+        if let Some(fraction) = self.significance_progress() {
+            ui.horizontal(|ui| {
+                let text = format!(
+                    "{:.0}%, {} more kernel(s) queued",
+                    fraction * 100.0,
+                    self.significance_queue.len()
+                );
+                ui.add(
+                    egui::ProgressBar::new(fraction)
+                        .text(text)
+                        .desired_width(220.0),
+                );
+                if ui.button("Cancel").clicked() {
+                    self.significance_run = None;
+                    self.significance_queue.clear();
+                    self.status = "Permutation tests cancelled.".to_owned();
+                }
+            });
+        }
+        match self
+            .previews
+            .get(self.selected_kernel)
+            .and_then(|p| p.significance)
+        {
+            Some(s) => ui.label(format!(
+                "Kernel {}: mean |r| {}, p = {}, z = {:.2} over {} shuffles of its weights.",
+                self.selected_kernel,
+                fmt.format(s.score),
+                fmt.format(s.p_value),
+                s.z_score,
+                s.permutations
+            )),
+            None => ui.label("Selected kernel not tested yet."),
+        };
+    }
+
+    /// Recomputes every score from its raw mean-abs response with the current
+    /// normalization, convolving the random baseline kernels on first use.
+    fn rescore(&mut self) {
+        let Some(job) = self.run.as_mut() else {
+            return;
+        };
+        let normalization = self.settings.score_normalization.for_mode(job.mode);
+        if normalization == ScoreNormalization::BaselineZ {
+            for kernel in &self.kernels {
+                let support = baseline_support(kernel);
+                if job.baselines.iter().any(|(s, _)| *s == support) {
+                    continue;
+                }
+                let (kw, kh) = (kernel.width, kernel.height);
+                let baseline = ScoreBaseline::measure(kernel.support(), |weights| {
+                    let weights = kernel.scatter_support(weights);
+                    let response = job.convolve_weights(&weights, kw, kh);
+                    scoring::raw_score(&response)
+                });
+                job.baselines.push((support, baseline));
+            }
+        }
+        for (preview, kernel) in self.previews.iter_mut().zip(&self.kernels) {
+            let support = baseline_support(kernel);
+            let baseline = job
+                .baselines
+                .iter()
+                .find(|(s, _)| *s == support)
+                .map(|(_, baseline)| baseline);
+            let weights = kernel.masked_weights();
+            let normalize = |raw: f32| normalization.apply(raw, &weights, job.image_std, baseline);
+            preview.score = normalization.score(&preview.stats, &weights, job.image_std, baseline);
+            preview.interval = if normalization.is_tissue() {
+                [preview.score; 2]
+            } else {
+                preview.raw_interval.map(normalize)
+            };
+        }
+    }
+
+    /// Writes the exports configured as post-run hooks. Files are prefixed
+    /// with the run's Unix timestamp so successive runs never overwrite each
+    /// other.
+    fn run_autosave_hooks(&mut self) {
+        if !self.settings.autosave_scores && self.settings.autosave_top_maps == 0 {
+            return;
+        }
+        let started = Instant::now();
+        let prefix = format!("run_{}", unix_timestamp());
+        let mut written = 0;
+        let mut errors = Vec::new();
+
+        match self.save_manifest(&format!("{prefix}_manifest.json")) {
+            Ok(_) => written += 1,
+            Err(e) => errors.push(e),
+        }
+
+        if self.settings.autosave_scores {
+            let csv = self.settings.csv_format.apply(&self.scores_csv());
+            match export::save_file(
+                &self.settings.export_dir,
+                &format!("{prefix}_scores.csv"),
+                csv.as_bytes(),
+            ) {
+                Ok(_) => {
+                    written += 1;
+                    self.unsaved.results = false;
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+
+        let mut ranked: Vec<usize> = (0..self.previews.len())
+            .filter(|&i| self.kernels[i].enabled)
+            .collect();
+        ranked.sort_by(|&a, &b| self.previews[b].score.total_cmp(&self.previews[a].score));
+        ranked.truncate(self.settings.autosave_top_maps);
+        let names = match self.map_file_stems(&prefix, &ranked) {
+            Ok(names) => names,
+            Err(e) => {
+                errors.push(e);
+                Vec::new()
+            }
+        };
+        for (&index, name) in ranked.iter().zip(names) {
+            let Some((response, width, height)) = self.full_response(index) else {
+                continue;
+            };
+            let calibration = self.settings.calibration;
+            let result = calibration
+                .map_png(&response, width, height)
+                .and_then(|png| {
+                    export::save_file(&self.settings.export_dir, &format!("{name}.png"), &png)
+                });
+            match result {
+                Ok(_) => written += 1,
+                Err(e) => errors.push(e),
+            }
+        }
+
+        self.settings
+            .usage
+            .record(Stage::Autosave, started, None, 0);
+        self.status = match errors.first() {
+            None => format!("{} Autosaved {written} file(s).", self.status),
+            Some(e) => format!("{} Autosave failed: {e}", self.status),
+        };
+    }
+
+    fn run_manifest(&self) -> Option<RunManifest> {
+        let run = self.run.as_ref()?;
+        let (map_width, map_height) = run.map_size();
+        Some(RunManifest {
+            app_version: env!("CARGO_PKG_VERSION"),
+            platform: format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH),
+            slide: self.slide.name.clone(),
+            kernels_sheet: self.kernels_sheet.name.clone(),
+            slide_hash: pipeline::format_hash(self.slide.hash),
+            kernels_sheet_hash: pipeline::format_hash(self.kernels_sheet.hash),
+            image_width: run.width,
+            image_height: run.height,
+            roi: run.roi,
+            kernel_width: run.kw,
+            kernel_height: run.kh,
+            backend: run.backend.label(),
+            strict_reproducibility: run.strict,
+            mode: run.mode.label(),
+            border: run.border,
+            calibration: Some(self.settings.calibration).filter(|c| c.enabled),
+            stride: run.sampling.stride,
+            dilation: run.sampling.dilation,
+            map_width,
+            map_height,
+            kernel_normalization: self.split_normalization.label(),
+            activation_k: run.activation_k,
+            score_normalization: self.settings.score_normalization.for_mode(run.mode).label(),
+            stain_normalization: match run.stain_reference {
+                Some(_) => self.settings.stain_normalization.label(),
+                None => StainNormalization::None.label(),
+            },
+            stain_reference: run.stain_reference.clone(),
+            channels: match run.planes.len() {
+                0 if self.slide.rgb.is_none() => ChannelMode::Luma.label(),
+                _ => run.channels.label(),
+            },
+            repaired_pixels: run.repaired,
+            kernels: self
+                .previews
+                .iter()
+                .zip(&self.kernels)
+                .enumerate()
+                .map(|(index, (p, k))| ManifestKernel {
+                    index,
+                    name: k.name.clone(),
+                    group: k.group.clone(),
+                    provenance: k.provenance.to_string(),
+                    width: k.width,
+                    height: k.height,
+                    separable: k.separable.is_some(),
+                    support: k.support(),
+                    enabled: k.enabled,
+                    score: p.score,
+                    exact: p.exact,
+                    checksum: p.exact.then(|| format!("{:016x}", p.checksum)),
+                    p_value: p.significance.map(|s| s.p_value),
+                    permutations: p.significance.map(|s| s.permutations),
+                    non_finite_pixels: p.stats.non_finite,
+                })
+                .collect(),
+            bookmarks: self
+                .bookmarks
+                .iter()
+                .map(|b| ManifestBookmark {
+                    name: b.name.clone(),
+                    center: b.slide_view.center.into(),
+                    zoom: b.slide_view.zoom,
+                    kernel: b.kernel,
+                    score: self.previews.get(b.kernel).map(|p| p.score),
+                })
+                .collect(),
+        })
+    }
+
+    fn save_manifest(&self, file_name: &str) -> Result<String, String> {
+        let manifest = self.run_manifest().ok_or("Run the convolutions first.")?;
+        let json = serde_json::to_string_pretty(&manifest)
+            .map_err(|e| format!("Cannot serialize manifest: {e}"))?;
+        export::save_file(&self.settings.export_dir, file_name, json.as_bytes())
+    }
+
+    fn scores_csv(&self) -> String {
+        export::scores_csv(
+            self.previews
+                .iter()
+                .zip(&self.kernels)
+                .enumerate()
+                .filter(|(_, (_, kernel))| kernel.enabled)
+                .map(|(index, (p, kernel))| ScoreRow {
+                    index,
+                    kernel,
+                    score: p.score,
+                    exact: p.exact,
+                    stats: &p.stats,
+                    significance: p.significance,
+                    interval: p.interval,
+                }),
+            self.settings.number_format,
+        )
+    }
+
+    /// Completed and total work items of the running background job, if any.
+    fn progress(&self) -> Option<(usize, usize)> {
+        self.run.as_ref()?;
+        if let Some((done, total, _)) = self.run_progress() {
+            return Some((done, total));
+        }
+        let targets: BTreeSet<usize> = std::iter::once(self.selected_kernel)
+            .chain(self.pinned_kernels.iter().copied())
+            .filter(|&i| i < self.previews.len())
+            .collect();
+        let done = targets.iter().filter(|&&i| self.previews[i].exact).count();
+        (done < targets.len()).then_some((done, targets.len()))
+    }
+
+    /// Kernels completed and total of the running exact run, and the
+    /// fraction of its work done, counting rows of the kernels in progress
+    /// where the run reports them.
+    fn run_progress(&self) -> Option<(usize, usize, f32)> {
+        let (done, total) = (self.previews.len(), self.kernels.len());
+        let kernels = |done: usize| done as f32 / total.max(1) as f32;
+        if let Some(gpu_run) = &self.gpu_run {
+            return Some((gpu_run.completed(), total, kernels(gpu_run.completed())));
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(cpu_run) = &self.cpu_run {
+            // After a GPU failure the CPU run covers only the kernels left.
+            let left = cpu_run.progress() * (total - cpu_run.first()) as f32;
+            return Some((
+                done,
+                total,
+                (cpu_run.first() as f32 + left) / total.max(1) as f32,
+            ));
+        }
+        #[cfg(target_arch = "wasm32")]
+        if let Some(chunked) = &self.chunked
+            && done < total
+        {
+            let fraction =
+                (total - chunked.len()) as f32 + chunked.progress() * chunked.len() as f32;
+            return Some((done, total, fraction / total.max(1) as f32));
+        }
+        None
+    }
+
+    /// Progress bar of the running exact run, with a Cancel button.
+    fn run_progress_bar(&mut self, ui: &mut egui::Ui) {
+        let Some((done, total, fraction)) = self.run_progress() else {
+            return;
+        };
+        ui.horizontal(|ui| {
+            let text = format!("{done} of {total} kernels, {:.0}%", fraction * 100.0);
+            ui.add(
+                egui::ProgressBar::new(fraction)
+                    .text(text)
+                    .desired_width(220.0),
+            );
+            if ui.button("Cancel").clicked() {
+                self.cancel_run();
+            }
+        });
+    }
+
+    /// Stops the running exact run, keeping the maps it completed.
+    fn cancel_run(&mut self) {
+        self.gpu_run = None;
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.cpu_run = None;
+            self.carry_over = None;
+            self.refining = None;
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.chunked = None;
+        }
+        // Cancelled runs neither autosave nor notify.
+        self.run_started = None;
+        self.rescore();
+        self.status = format!(
+            "Run cancelled after {} of {} kernels; their maps are kept.",
+            self.previews.len(),
+            self.kernels.len()
+        );
+    }
+
+    /// Mirrors job progress in the window or browser tab title and, natively,
+    /// on the taskbar entry, and flags completion until the window is focused
+    /// again.
+    fn update_title(&mut self, ctx: &egui::Context) {
+        let progress = self.progress();
+        let focused = ctx.input(|i| i.viewport().focused.unwrap_or(true));
+        if progress.is_some() {
+            self.was_busy = true;
+        } else if self.was_busy {
+            self.was_busy = false;
+            self.finished_unseen = !focused;
+            self.finish_run();
+        }
+        if focused {
+            self.finished_unseen = false;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        self.taskbar
+            .set(progress.map(|(done, total)| done as f32 / total.max(1) as f32));
+
+        let title = match progress {
+            Some((done, total)) => format!("[{}%] {APP_TITLE}", done * 100 / total.max(1)),
+            None if self.finished_unseen => format!("[done] {APP_TITLE}"),
+            None => APP_TITLE.to_owned(),
+        };
+        if title == self.window_title {
+            return;
+        }
+        #[cfg(target_arch = "wasm32")]
+        if let Some(document) = web_sys::window().and_then(|w| w.document()) {
+            document.set_title(&title);
+        }
+        ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.clone()));
+        self.window_title = title;
+    }
+
+    /// Full-resolution response of one kernel from the last run, read back
+    /// when kept on disk and recomputed otherwise.
+    fn full_response(&self, index: usize) -> Option<(Vec<f32>, usize, usize)> {
+        let run = self.run.as_ref()?;
+        let kernel = self.kernels.get(index)?;
+        let (width, height) = run.map_size();
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(response) = run.responses.as_ref().and_then(|store| store.get(index)) {
+            return Some((response, width, height));
+        }
+        Some((run.respond(kernel), width, height))
+    }
+
+    /// File names, without extension, of the maps of kernels `indices` from
+    /// the map name template, or `<prefix>_kernel<index>` without one.
+    fn map_file_stems(&self, prefix: &str, indices: &[usize]) -> Result<Vec<String>, String> {
+        let template = match self.settings.map_name_template.as_str() {
+            "" => format!("{prefix}_kernel{{kernel_index}}"),
+            template => template.to_owned(),
+        };
+        let maps = indices.iter().map(|&index| export::MapNameFields {
+            slide: &self.slide.name,
+            index,
+            kernel: &self.kernels[index],
+            score: self.previews.get(index).map_or(f32::NAN, |p| p.score),
+        });
+        export::map_file_stems(&template, maps)
+    }
+
+    /// Writes the active pixels of the enabled kernels' kept maps, at slide
+    /// positions, as a CSV.
+    fn export_active_pixels(&mut self) {
+        let Some((run, store)) = self
+            .run
+            .as_ref()
+            .and_then(|run| Some((run, run.sparse.as_ref()?)))
+        else {
+            self.status = "Run the convolutions with sparse maps on first.".to_owned();
+            return;
+        };
+        let origin = run.roi.map_or((0, 0), |roi| (roi.x, roi.y));
+        let stride = run.sampling.stride;
+        let maps: Vec<(usize, &str, &SparseMap)> = (0..self.previews.len())
+            .filter(|&i| self.kernels[i].enabled)
+            .filter_map(|i| Some((i, self.kernels[i].name.as_str(), store.get(i)?)))
+            .collect();
+        let pixels: usize = maps.iter().map(|(_, _, map)| map.len()).sum();
+        let kernels = maps.len();
+        let csv = self
+            .settings
+            .csv_format
+            .apply(&sparse::active_csv(maps.into_iter(), |x, y| {
+                (origin.0 + x * stride, origin.1 + y * stride)
+            }));
+        let name = format!("active_{}.csv", unix_timestamp());
+        self.status = match export::save_file(&self.settings.export_dir, &name, csv.as_bytes()) {
+            Ok(path) => format!("Exported {pixels} active pixels of {kernels} kernels to {path}."),
+            Err(e) => format!("Active pixel export failed: {e}"),
+        };
+    }
+
+    /// Writes the full-resolution maps of kernels `indices`, min-max
+    /// normalized to 8-bit PNG and, if requested, raw as float TIFF.
+    fn export_maps(&mut self, indices: &[usize]) {
+        if self.run.is_none() {
+            self.status = "Run the convolutions first.".to_owned();
+            return;
+        }
+        let started = Instant::now();
+        let prefix = format!("maps_{}", unix_timestamp());
+        let names = match self.map_file_stems(&prefix, indices) {
+            Ok(names) => names,
+            Err(e) => {
+                self.status = e;
+                return;
+            }
+        };
+        let calibration = self.settings.calibration;
+        let mut written = 0;
+        for (&index, name) in indices.iter().zip(names) {
+            let Some((response, width, height)) = self.full_response(index) else {
+                continue;
+            };
+            let mut files = vec![(
+                format!("{name}.png"),
+                calibration.map_png(&response, width, height),
+            )];
+            if self.settings.export_raw_tiff {
+                let values = calibration.calibrated(&response);
+                files.push((
+                    format!("{name}.tiff"),
+                    export::response_tiff(&values, width, height),
+                ));
+            }
+            for (file_name, bytes) in files {
+                let saved = bytes.and_then(|bytes| {
+                    export::save_file(&self.settings.export_dir, &file_name, &bytes)
+                });
+                match saved {
+                    Ok(_) => written += 1,
+                    Err(e) => {
+                        self.status = format!("Map export failed: {e}");
+                        return;
+                    }
+                }
+            }
+        }
+        if (0..self.previews.len()).all(|i| !self.kernels[i].enabled || indices.contains(&i)) {
+            self.unsaved.results = false;
+        }
+        self.status = format!(
+            "Exported {written} map file(s) for {} kernel(s) in {:.1} s.",
+            indices.len(),
+            started.elapsed().as_secs_f32()
+        );
+    }
+
+    fn export_score_plot(&mut self) {
+        if self.previews.is_empty() {
+            self.status = "Run the convolutions first.".to_owned();
+            return;
+        }
+        let scores: Vec<(f32, [f32; 2])> = self
+            .previews
+            .iter()
+            .map(|p| (p.score, p.interval))
+            .collect();
+        let figure = figure::score_plot(
+            self.settings.figure_style,
+            &scores,
+            Some(self.selected_kernel),
+            self.settings.score_normalization.label(),
+        );
+        self.save_figure(&figure, "scores");
+    }
+
+    fn export_histogram(&mut self) {
+        let index = self.selected_kernel;
+        let Some((response, _, _)) = self.full_response(index) else {
+            self.status = "Run the convolutions first.".to_owned();
+            return;
+        };
+        let title = format!("Kernel {index} response");
+        let figure = figure::histogram(self.settings.figure_style, &response, &title);
+        self.save_figure(&figure, &format!("kernel{index}_histogram"));
+    }
+
+    fn save_figure(&mut self, figure: &figure::Figure, name: &str) {
+        let format = self.settings.figure_format;
+        let file_name = format!("{name}.{}", format.extension());
+        self.status = match export::save_file(
+            &self.settings.export_dir,
+            &file_name,
+            &format.encode(figure),
+        ) {
+            Ok(dest) => format!("Saved {} figure to {dest}.", format.label()),
+            Err(e) => format!("Figure export failed: {e}"),
+        };
+    }
+
+    fn figures_panel(&mut self, ui: &mut egui::Ui) {
+        let style = &mut self.settings.figure_style;
+        egui::ComboBox::from_label("Format")
+            .selected_text(self.settings.figure_format.label())
+            .show_ui(ui, |ui| {
+                for format in FigureFormat::ALL {
+                    ui.selectable_value(&mut self.settings.figure_format, format, format.label());
+                }
+            });
+        ui.horizontal(|ui| {
+            ui.label("Size");
+            ui.add(
+                egui::DragValue::new(&mut style.width)
+                    .range(72.0..=1440.0)
+                    .suffix(" pt"),
+            );
+            ui.label("x");
+            ui.add(
+                egui::DragValue::new(&mut style.height)
+                    .range(72.0..=1440.0)
+                    .suffix(" pt"),
+            );
+        });
+        egui::ComboBox::from_label("Font")
+            .selected_text(style.font.label())
+            .show_ui(ui, |ui| {
+                for font in FigureFont::ALL {
+                    ui.selectable_value(&mut style.font, font, font.label());
+                }
+            });
+        ui.horizontal(|ui| {
+            ui.label("Font size");
+            ui.add(
+                egui::DragValue::new(&mut style.font_size)
+                    .range(4.0..=24.0)
+                    .speed(0.25)
+                    .suffix(" pt"),
+            );
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Export score plot").clicked() {
+                self.export_score_plot();
+            }
+            if ui.button("Export histogram").clicked() {
+                self.export_histogram();
+            }
+        });
+    }
+
+    fn compute_autocorrelation(&mut self, ctx: &egui::Context) {
+        let index = self.selected_kernel;
+        let Some((response, width, height)) = self.full_response(index) else {
+            return;
+        };
+        let scale = width.max(height).div_ceil(AUTOCORRELATION_MAP_SIZE).max(1);
+        let (map, mw, mh) = downsample_box(&response, width, height, scale);
+        let result = analysis::autocorrelation(&map, mw, mh, AUTOCORRELATION_MAX_LAG);
+        let (pw, ph, bytes, _) =
+            build_preview(&result.values, result.size, result.size, result.size);
+        let texture = ctx.load_texture(
+            "autocorrelation",
+            ColorImage::from_gray([pw, ph], &bytes),
+            TextureOptions::NEAREST,
+        );
+        self.autocorrelation = Some(AutocorrelationView {
+            kernel: index,
+            result,
+            scale,
+            texture,
+        });
+    }
+
+    /// Scores the selected kernel on each block of the slide with the current
+    /// score, for triage at block granularity. The tissue scores have no
+    /// per-block version and give the raw score.
+    fn compute_tile_heatmap(&mut self, ctx: &egui::Context) {
+        let index = self.selected_kernel;
+        let tile = self.settings.heatmap_tile;
+        if let Some(Err(e)) = self.run.as_ref().map(|job| job.check_tile(tile)) {
+            self.status = e;
+            return;
+        }
+        let Some((cols, rows, scores)) = self.tile_scores(index, tile) else {
+            self.status = "Run the convolutions first.".to_owned();
+            return;
+        };
+        let range = min_max(&scores);
+        let span = (range.1 - range.0).max(1e-12);
+        let pixels = scores
+            .iter()
+            .map(|&score| {
+                let [r, g, b] = self.settings.colormap.sample_rgb((score - range.0) / span);
+                egui::Color32::from_rgb(r, g, b)
+            })
+            .collect();
+        let texture = ctx.load_texture(
+            "tile_heatmap",
+            ColorImage {
+                size: [cols, rows],
+                pixels,
+            },
+            TextureOptions::NEAREST,
+        );
+        let origin = self
+            .run
+            .as_ref()
+            .and_then(|job| job.roi)
+            .map_or((0, 0), |roi| (roi.x, roi.y));
+        self.tile_heatmap = Some(TileHeatmapView {
+            kernel: index,
+            tile,
+            origin,
+            cols,
+            scores,
+            range,
+            texture,
+        });
+    }
+
+    /// Kernel `index`'s score on each `tile`-pixel block of the slide, with
+    /// the grid's columns and rows.
+    fn tile_scores(&self, index: usize, tile: usize) -> Option<(usize, usize, Vec<f32>)> {
+        let (job, kernel) = (self.run.as_ref()?, self.kernels.get(index)?);
+        let map_tile = job.map_tile(tile)?;
+        let (response, width, height) = self.full_response(index)?;
+        let (cols, rows, raw) = scoring::block_mean_abs(&response, width, height, map_tile);
+        let normalization = self.settings.score_normalization.for_mode(job.mode);
+        let support = baseline_support(kernel);
+        let baseline = job
+            .baselines
+            .iter()
+            .find(|(s, _)| *s == support)
+            .map(|(_, baseline)| baseline);
+        let weights = kernel.masked_weights();
+        let scores = raw
+            .iter()
+            .map(|&r| normalization.apply(r, &weights, job.image_std, baseline))
+            .collect();
+        Some((cols, rows, scores))
+    }
+
+    /// Flags the slide blocks where any enabled kernel scores above the
+    /// triage threshold, using the heatmap's tile size.
+    fn flag_suspicious_tiles(&mut self) {
+        let Some(job) = self.run.as_ref() else {
+            self.status = "Run the convolutions first.".to_owned();
+            return;
+        };
+        let (size, tile) = ((job.width, job.height), self.settings.heatmap_tile);
+        let origin = job.roi.map_or((0, 0), |roi| (roi.x, roi.y));
+        if let Err(e) = job.check_tile(tile) {
+            self.status = e;
+            return;
+        }
+        let started = Instant::now();
+        let mut cols = 0;
+        let grids: Vec<(usize, Vec<f32>)> = (0..self.previews.len())
+            .filter(|&i| self.kernels[i].enabled)
+            .filter_map(|i| {
+                let (grid_cols, _, scores) = self.tile_scores(i, tile)?;
+                cols = grid_cols;
+                Some((i, scores))
+            })
+            .collect();
+        let threshold = self.settings.triage_threshold;
+        let tiles = triage::flag_tiles(&grids, cols, tile, size, origin, threshold);
+        self.status = format!(
+            "Flagged {} tile(s) scoring above {threshold} for any of {} kernels in {:.1} s.",
+            tiles.len(),
+            grids.len(),
+            started.elapsed().as_secs_f32()
+        );
+        self.triage = Some(TriageResult {
+            tile,
+            threshold,
+            tiles,
+        });
+    }
+
+    /// Writes the ranked table of flagged tiles and a PNG crop of each, in
+    /// color for color slides.
+    fn export_flagged_tiles(&mut self) {
+        let (Some(result), Some(gray)) = (&self.triage, &self.slide.gray) else {
+            return;
+        };
+        let prefix = format!("triage_{}", unix_timestamp());
+        let crop_name = |rank: usize| {
+            let t = &result.tiles[rank];
+            format!("{prefix}_tile{}_c{}_r{}.png", rank + 1, t.col, t.row)
+        };
+        let csv = self
+            .settings
+            .csv_format
+            .apply(&triage::flagged_csv(&result.tiles, crop_name));
+        let mut saved = export::save_file(
+            &self.settings.export_dir,
+            &format!("{prefix}_tiles.csv"),
+            csv.as_bytes(),
+        );
+        for (rank, tile) in result.tiles.iter().enumerate() {
+            if saved.is_err() {
+                break;
+            }
+            saved = triage::crop_png(gray, self.slide.rgb.as_ref(), tile).and_then(|png| {
+                export::save_file(&self.settings.export_dir, &crop_name(rank), &png)
+            });
+        }
+        self.status = match saved {
+            Ok(_) => format!(
+                "Exported {} flagged tile(s) to {}.",
+                result.tiles.len(),
+                self.settings.export_dir
+            ),
+            Err(e) => e,
+        };
+    }
+
+    /// Saves the viewers' zoom and pan, the selected kernel and the overlay
+    /// settings under a name.
+    fn add_bookmark(&mut self) {
+        let name = match self.bookmark_name.trim() {
+            "" => format!("Bookmark {}", self.bookmarks.len() + 1),
+            name => name.to_owned(),
+        };
+        self.bookmarks.push(Bookmark {
+            name,
+            slide_view: self.slide_view,
+            preview_view: self.preview_view,
+            link_zoom: self.link_zoom,
+            kernel: self.selected_kernel,
+            overlay: self.settings.response_overlay,
+            opacity: self.settings.response_overlay_opacity,
+            colormap: self.settings.preview_colormap,
+        });
+        self.bookmark_name.clear();
+        self.current_bookmark = Some(self.bookmarks.len() - 1);
+    }
+
+    fn go_to_bookmark(&mut self, index: usize) {
+        let Some(bookmark) = self.bookmarks.get(index).cloned() else {
+            return;
+        };
+        self.slide_view = bookmark.slide_view;
+        self.preview_view = bookmark.preview_view;
+        self.link_zoom = bookmark.link_zoom;
+        self.settings.response_overlay = bookmark.overlay;
+        self.settings.response_overlay_opacity = bookmark.opacity;
+        self.settings.preview_colormap = bookmark.colormap;
+        self.current_bookmark = Some(index);
+        self.status = if bookmark.kernel < self.previews.len() {
+            self.selected_kernel = bookmark.kernel;
+            format!("Showing {}.", bookmark.name)
+        } else {
+            format!(
+                "Showing {}; its kernel {} has no map in this run.",
+                bookmark.name, bookmark.kernel
+            )
+        };
+    }
+
+    fn bookmarks_panel(&mut self, ui: &mut egui::Ui) {
+        ui.collapsing("Bookmarks", |ui| {
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut self.bookmark_name)
+                    .on_hover_text("Name of the next bookmark; numbered when left empty.");
+                if ui.button("Bookmark this view").clicked() {
+                    self.add_bookmark();
+                }
+            });
+            let mut go = None;
+            let mut remove = None;
+            for (i, bookmark) in self.bookmarks.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    let current = self.current_bookmark == Some(i);
+                    if ui
+                        .selectable_label(current, &bookmark.name)
+                        .on_hover_text(format!(
+                            "Zoom {:.1}x at ({:.3}, {:.3}), kernel {}",
+                            bookmark.slide_view.zoom,
+                            bookmark.slide_view.center.x,
+                            bookmark.slide_view.center.y,
+                            bookmark.kernel
+                        ))
+                        .clicked()
+                    {
+                        go = Some(i);
+                    }
+                    if ui.small_button("x").clicked() {
+                        remove = Some(i);
+                    }
+                });
+            }
+            if let Some(i) = remove {
+                self.bookmarks.remove(i);
+                self.current_bookmark = None;
+            }
+            if self.bookmarks.is_empty() {
+                ui.weak("Bookmarks are kept until Reset and listed in the run manifest.");
+                return;
+            }
+            let count = self.bookmarks.len();
+            ui.horizontal(|ui| {
+                if ui.button("Previous").clicked() {
+                    go = Some(
+                        self.current_bookmark
+                            .map_or(count - 1, |i| (i + count - 1) % count),
+                    );
+                }
+                if ui.button("Next").clicked() {
+                    go = Some(self.current_bookmark.map_or(0, |i| (i + 1) % count));
+                }
+                if ui
+                    .add_enabled(count >= 2, egui::Button::new("Use as flythrough keyframes"))
+                    .clicked()
+                {
+                    self.flythrough.keyframes = self
+                        .bookmarks
+                        .iter()
+                        .map(|b| Keyframe {
+                            center: b.slide_view.center.into(),
+                            zoom: b.slide_view.zoom,
+                        })
+                        .collect();
+                    self.status = format!("The flythrough now visits the {count} bookmarks.");
+                }
+            });
+            if let Some(i) = go {
+                self.go_to_bookmark(i);
+            }
+        });
+    }
+
+    fn triage_panel(&mut self, ui: &mut egui::Ui) {
+        let fmt = self.settings.number_format;
+        ui.collapsing("Triage", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Threshold");
+                ui.add(egui::DragValue::new(&mut self.settings.triage_threshold).speed(0.001));
+                if ui.button("Flag tiles above threshold").clicked() {
+                    self.flag_suspicious_tiles();
+                }
+            });
+            ui.label(format!(
+                "Scores every enabled kernel on {0}x{0} px tiles (the heatmap's tile size).",
+                self.settings.heatmap_tile
+            ));
+            let Some(result) = &self.triage else {
+                return;
+            };
+            ui.label(format!(
+                "{} tile(s) of {}x{} px above {}, outlined on the slide:",
+                result.tiles.len(),
+                result.tile,
+                result.tile,
+                result.threshold
+            ));
+            let mut export = false;
+            if !result.tiles.is_empty() {
+                egui::ScrollArea::vertical()
+                    .id_salt("triage_tiles")
+                    .max_height(160.0)
+                    .show(ui, |ui| {
+                        for (rank, t) in result.tiles.iter().enumerate() {
+                            let others = t.kernels.len() - 1;
+                            ui.label(format!(
+                                "{}. column {}, row {} (x {}, y {}): {} by kernel {}{}",
+                                rank + 1,
+                                t.col,
+                                t.row,
+                                t.x,
+                                t.y,
+                                fmt.format(t.score),
+                                t.kernel,
+                                if others > 0 {
+                                    format!(" and {others} more")
+                                } else {
+                                    String::new()
+                                }
+                            ));
+                        }
+                    });
+                export = ui.button("Export flagged tiles").clicked();
+            }
+            if export {
+                self.export_flagged_tiles();
+            }
+        });
+    }
+
+    /// Outlines the flagged tiles on the slide spanning `rect` at `scale`
+    /// screen points per slide pixel, within the viewer `clip`.
+    fn triage_outlines(&self, ui: &egui::Ui, clip: egui::Rect, rect: egui::Rect, scale: f32) {
+        let Some(result) = &self.triage else {
+            return;
+        };
+        let [r, g, b] = self.settings.overlay_colors.marker;
+        let stroke = egui::Stroke::new(1.5, egui::Color32::from_rgb(r, g, b));
+        for t in &result.tiles {
+            let tile = egui::Rect::from_min_size(
+                rect.min + egui::vec2(t.x as f32, t.y as f32) * scale,
+                egui::vec2(t.width as f32, t.height as f32) * scale,
+            );
+            ui.painter()
+                .with_clip_rect(clip)
+                .rect_stroke(tile, 0.0, stroke);
+        }
+    }
+
+    /// Finds the local maxima of the selected kernel's map and picks the
+    /// peaks among them.
+    fn detect_peaks(&mut self) {
+        let index = self.selected_kernel;
+        let Some((response, width, height)) = self.full_response(index) else {
+            self.status = "Run the convolutions first.".to_owned();
+            return;
+        };
+        let Some(run) = &self.run else {
+            return;
+        };
+        let started = Instant::now();
+        let (mean, std) = stats::mean_std(&response);
+        let mut view = PeakView {
+            kernel: index,
+            maxima: peaks::local_maxima(&response, width, height),
+            mean: mean as f32,
+            std: std as f32,
+            stride: run.sampling.stride,
+            origin: run.roi.map_or((0, 0), |roi| (roi.x, roi.y)),
+            threshold: 0.0,
+            peaks: Vec::new(),
+        };
+        view.pick(&self.settings);
+        self.status = format!(
+            "Found {} local maxima of kernel {index} in {:.1} s; {} are peaks.",
+            view.maxima.len(),
+            started.elapsed().as_secs_f32(),
+            view.peaks.len()
+        );
+        self.peaks = Some(view);
+    }
+
+    fn export_peaks(&mut self) {
+        let Some(view) = &self.peaks else {
+            return;
+        };
+        let csv = self
+            .settings
+            .csv_format
+            .apply(&peaks::peaks_csv(&view.peaks));
+        let name = format!("peaks_kernel{}_{}.csv", view.kernel, unix_timestamp());
+        self.status = match export::save_file(&self.settings.export_dir, &name, csv.as_bytes()) {
+            Ok(path) => format!("Exported {} peaks to {path}.", view.peaks.len()),
+            Err(e) => format!("Peak export failed: {e}"),
+        };
+    }
+
+    /// Detection controls and the peaks found. Clicking a peak centres the
+    /// slide viewer on it.
+    fn peaks_panel(&mut self, ui: &mut egui::Ui) {
+        let fmt = self.settings.number_format;
+        ui.collapsing("Peaks", |ui| {
+            let settings = &mut self.settings;
+            let mut changed = false;
+            ui.horizontal(|ui| {
+                ui.label("Top");
+                changed |= ui
+                    .add(egui::DragValue::new(&mut settings.peak_count).range(1..=10_000))
+                    .changed();
+                ui.label("at least");
+                changed |= ui
+                    .add(
+                        egui::DragValue::new(&mut settings.peak_spacing)
+                            .range(1..=4096)
+                            .suffix(" px apart"),
+                    )
+                    .changed();
+            });
+            changed |= ui
+                .add(
+                    egui::Slider::new(&mut settings.peak_threshold, -3.0..=10.0)
+                        .text("σ above the mean"),
+                )
+                .changed();
+            ui.horizontal(|ui| {
+                for marker in PeakMarker::ALL {
+                    ui.selectable_value(&mut settings.peak_marker, marker, marker.label());
+                }
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Detect on selected kernel").clicked() {
+                    self.detect_peaks();
+                }
+                if ui
+                    .add_enabled(self.peaks.is_some(), egui::Button::new("Export"))
+                    .on_hover_text("CSV of the peaks in slide pixels, strongest first.")
+                    .clicked()
+                {
+                    self.export_peaks();
+                }
+            });
+            let Some(view) = &mut self.peaks else {
+                return;
+            };
+            if changed {
+                view.pick(&self.settings);
+            }
+            ui.checkbox(&mut self.peaks_visible, "Mark on the slide");
+            ui.label(format!(
+                "Kernel {}: {} peaks of {} local maxima reach {} ({}σ above the mean).",
+                view.kernel,
+                view.peaks.len(),
+                view.maxima.len(),
+                fmt.format(view.threshold),
+                fmt.format(self.settings.peak_threshold)
+            ));
+            let mut centre = None;
+            egui::ScrollArea::vertical()
+                .id_salt("peaks")
+                .max_height(160.0)
+                .show(ui, |ui| {
+                    for (rank, peak) in view.peaks.iter().enumerate() {
+                        let text = format!(
+                            "{}. ({}, {}): {}",
+                            rank + 1,
+                            peak.x,
+                            peak.y,
+                            fmt.format(peak.value)
+                        );
+                        if ui.selectable_label(false, text).clicked() {
+                            centre = Some(*peak);
+                        }
+                    }
+                });
+            if let (Some(peak), Some(size)) =
+                (centre, self.slide.texture.as_ref().map(|t| t.size_vec2()))
+            {
+                let view = &mut self.slide_view;
+                view.center = egui::vec2(peak.x as f32 + 0.5, peak.y as f32 + 0.5) / size;
+                view.zoom = view.zoom.max(PEAK_ZOOM);
+            }
+        });
+    }
+
+    /// Marks the detected peaks on the slide spanning `rect` at `scale`
+    /// screen points per slide pixel, within the viewer `clip`.
+    fn peak_markers(&self, ui: &egui::Ui, clip: egui::Rect, rect: egui::Rect, scale: f32) {
+        let Some(view) = self.peaks.as_ref().filter(|_| self.peaks_visible) else {
+            return;
+        };
+        const RADIUS: f32 = 6.0;
+        let [r, g, b] = self.settings.overlay_colors.peak;
+        let stroke = egui::Stroke::new(1.5, egui::Color32::from_rgb(r, g, b));
+        let painter = ui.painter().with_clip_rect(clip);
+        for peak in &view.peaks {
+            let at = rect.min + egui::vec2(peak.x as f32 + 0.5, peak.y as f32 + 0.5) * scale;
+            if !clip.expand(RADIUS).contains(at) {
+                continue;
+            }
+            match self.settings.peak_marker {
+                PeakMarker::Circle => {
+                    painter.circle_stroke(at, RADIUS, stroke);
+                }
+                PeakMarker::Cross => {
+                    painter.line_segment(
+                        [at - egui::vec2(RADIUS, 0.0), at + egui::vec2(RADIUS, 0.0)],
+                        stroke,
+                    );
+                    painter.line_segment(
+                        [at - egui::vec2(0.0, RADIUS), at + egui::vec2(0.0, RADIUS)],
+                        stroke,
+                    );
+                }
+            }
+        }
+    }
+
+    fn tile_heatmap_panel(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let fmt = self.settings.number_format;
+        ui.collapsing("Tile score heatmap", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Tile size");
+                ui.add(
+                    egui::DragValue::new(&mut self.settings.heatmap_tile)
+                        .range(8..=4096)
+                        .suffix(" px"),
+                );
+                if ui.button("Compute for selected kernel").clicked() {
+                    self.compute_tile_heatmap(ctx);
+                }
+            });
+            let Some(view) = &self.tile_heatmap else {
+                return;
+            };
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.heatmap_visible, "Overlay on the slide");
+                ui.add(
+                    egui::Slider::new(&mut self.settings.heatmap_opacity, 0.1..=1.0)
+                        .text("opacity"),
+                );
+            });
+            ui.label(format!(
+                "Kernel {}, {}x{} px tiles: scores {} (black) to {} (white). Hover the \
+                 slide for a tile's score.",
+                view.kernel,
+                view.tile,
+                view.tile,
+                fmt.format(view.range.0),
+                fmt.format(view.range.1)
+            ));
+            if view.kernel != self.selected_kernel {
+                ui.label("Recompute to show the selected kernel.");
+            }
+        });
+    }
+
+    /// Window with the version and platform, and the self-test that checks
+    /// the backends against built-in fixtures.
+    fn about_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.about_open;
+        egui::Window::new("About")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(format!("{APP_TITLE} {}", env!("CARGO_PKG_VERSION")));
+                ui.label(format!(
+                    "{}-{}",
+                    std::env::consts::OS,
+                    std::env::consts::ARCH
+                ));
+                ui.separator();
+                if ui
+                    .add_enabled(
+                        self.self_test_gpu.is_none(),
+                        egui::Button::new("Run self-test"),
+                    )
+                    .on_hover_text(
+                        "Checks every CPU backend, and the GPU path when one is detected, \
+                         against built-in fixtures with known outputs. The GPU only takes \
+                         the weighted sums with the zero border.",
+                    )
+                    .clicked()
+                {
+                    self.run_self_test();
+                }
+                if self.self_test_gpu.is_some() {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("Checking the GPU...");
+                    });
+                } else if let Some(report) = &self.self_test {
+                    if report.passed() {
+                        ui.label(report.summary());
+                    } else {
+                        ui.colored_label(ui.visuals().error_fg_color, report.summary());
+                    }
+                }
+            });
+        self.about_open = open;
+    }
+
+    /// Runs the fixtures on the CPU backends, then on the GPU path: WebGL
+    /// right away, and a WebGPU or native GPU run over the next frames.
+    fn run_self_test(&mut self) {
+        let mut report = selftest::run();
+        let kernels = selftest::gpu_kernels();
+        let (image, width, height) = (selftest::fixture_image(), selftest::WIDTH, selftest::HEIGHT);
+        #[cfg(target_arch = "wasm32")]
+        if let BrowserGpu::WebGl(gl, _) = &self.gpu {
+            let maps = kernels
+                .iter()
+                .map(|(weights, (kw, kh))| gl.convolve(&image, width, height, weights, *kw, *kh))
+                .collect();
+            report.add_gpu("WebGL", maps);
+            self.self_test = Some(report);
+            return;
+        }
+        match self.gpu.convolver() {
+            Some(gpu) => match GpuRun::new(gpu, &image, width, height, kernels) {
+                Ok(run) => self.self_test_gpu = Some((run, Vec::new())),
+                Err(e) => report.add_gpu(GPU_CHECK_LABEL, Err(e)),
+            },
+            None => report.gpu_skipped = Some(self.gpu.label()),
+        }
+        self.self_test = Some(report);
+    }
+
+    /// Reads back the self-test's GPU maps. Returns true while some are
+    /// missing.
+    fn poll_self_test(&mut self) -> bool {
+        let Some((run, maps)) = &mut self.self_test_gpu else {
+            return false;
+        };
+        let result = match self.gpu.convolver() {
+            Some(gpu) => run.poll(gpu),
+            None => Err("the GPU was lost".to_owned()),
+        };
+        let maps = match result {
+            Ok(Some((_, map))) => {
+                maps.push(map);
+                if !run.is_done() {
+                    return true;
+                }
+                Ok(std::mem::take(maps))
+            }
+            Ok(None) => return true,
+            Err(e) => Err(e),
+        };
+        self.self_test_gpu = None;
+        if let Some(report) = &mut self.self_test {
+            report.add_gpu(GPU_CHECK_LABEL, maps);
+        }
+        false
+    }
+
+    fn teaching_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.teaching.open;
+        egui::Window::new("Teaching mode")
+            .open(&mut open)
+            .default_width(760.0)
+            .show(ctx, |ui| self.teaching_panel(ui));
+        self.teaching.open = open;
+    }
+
+    fn teaching_panel(&mut self, ui: &mut egui::Ui) {
+        let (Some(slide), Some(kernel)) =
+            (&self.slide.gray, self.kernels.get(self.selected_kernel))
+        else {
+            ui.label("Load a slide and split the kernels to watch one convolve the slide.");
+            return;
+        };
+        // The lesson walks the weights as the run's mode applies them.
+        let kernel = &*kernel.oriented(self.settings.filter_mode);
+        let teaching = &mut self.teaching;
+        let (width, height) = (slide.width() as usize, slide.height() as usize);
+        let name = if kernel.name.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", kernel.name)
+        };
+        ui.label(format!(
+            "Kernel {}{name}; pick another in the score table.",
+            self.selected_kernel
+        ));
+        ui.horizontal(|ui| {
+            ui.add(egui::Slider::new(&mut teaching.side, 2..=MAX_REGION_SIDE).text("pixels"));
+            let [x, y] = &mut teaching.origin;
+            ui.add(egui::DragValue::new(x).range(0..=width - 1).prefix("x "));
+            ui.add(egui::DragValue::new(y).range(0..=height - 1).prefix("y "));
+        });
+        let region = Roi {
+            x: teaching.origin[0].min(width.saturating_sub(teaching.side)),
+            y: teaching.origin[1].min(height.saturating_sub(teaching.side)),
+            width: teaching.side.min(width),
+            height: teaching.side.min(height),
+        };
+        let key = LessonKey {
+            kernel: self.selected_kernel,
+            slide: self.slide.hash,
+            region,
+            border: self.settings.border_mode,
+            weights: kernel.weights.clone(),
+            mask: kernel.mask.clone(),
+        };
+        if teaching.lesson.as_ref().is_none_or(|(k, _)| *k != key) {
+            let lesson = Lesson::new(kernel, (width, height), region, key.border, |x, y| {
+                slide.get_pixel(x as u32, y as u32)[0] as f32 / 255.0
+            });
+            teaching.lesson = Some((key, lesson));
+            teaching.playing = false;
+        }
+        let Some((_, lesson)) = &mut teaching.lesson else {
+            return;
+        };
+
+        ui.horizontal(|ui| {
+            let label = if teaching.playing { "Pause" } else { "Play" };
+            if ui
+                .add_enabled(!lesson.is_done(), egui::Button::new(label))
+                .clicked()
+            {
+                teaching.playing = !teaching.playing;
+                teaching.carry = 0.0;
+            }
+            if ui.button("Next tap").clicked() {
+                lesson.advance(1);
+            }
+            if ui.button("Next pixel").clicked() {
+                lesson.finish_pixel();
+            }
+            if ui.button("Restart").clicked() {
+                lesson.restart();
+            }
+            ui.add(
+                egui::Slider::new(&mut teaching.taps_per_second, 1.0..=1000.0)
+                    .logarithmic(true)
+                    .text("taps/s"),
+            );
+        });
+        if teaching.playing {
+            teaching.carry += ui.input(|i| i.stable_dt) * teaching.taps_per_second;
+            let taps = teaching.carry as usize;
+            lesson.advance(taps);
+            teaching.carry -= taps as f32;
+            teaching.playing = !lesson.is_done();
+            ui.ctx().request_repaint();
+        }
+        if !self.settings.filter_mode.is_linear() {
+            ui.label(format!(
+                "Runs use the {} filter; this shows the kernel's weighted sum.",
+                self.settings.filter_mode.label()
+            ));
+        }
+        lesson_view(
+            ui,
+            lesson,
+            &self.settings.overlay_colors,
+            self.settings.preview_colormap,
+        );
+    }
+
+    /// Explains the selected kernel's response at `pos` of a preview drawn
+    /// over `rect`.
+    fn explain_pixel(&mut self, pos: egui::Pos2, rect: egui::Rect) {
+        let index = self.selected_kernel;
+        let (Some(run), Some(kernel)) = (&self.run, self.kernels.get(index)) else {
+            return;
+        };
+        if !run.sampling.is_dense() {
+            self.status = "Responses are explained for runs without stride or dilation.".to_owned();
+            return;
+        }
+        let (width, height) = (run.width, run.height);
+        let at = (pos - rect.min) / rect.size();
+        if !(0.0..1.0).contains(&at.x) || !(0.0..1.0).contains(&at.y) {
+            return;
+        }
+        let (x, y) = (
+            ((at.x * width as f32) as usize).min(width - 1),
+            ((at.y * height as f32) as usize).min(height - 1),
+        );
+        let region = Roi {
+            x,
+            y,
+            width: 1,
+            height: 1,
+        };
+        let input = &run.input;
+        let kernel = kernel.oriented(run.mode);
+        let mut lesson = Lesson::new(&kernel, (width, height), region, run.border, |x, y| {
+            input[y * width + x]
+        });
+        lesson.advance(lesson.steps());
+        let detail = self
+            .zoom_detail
+            .as_ref()
+            .filter(|d| d.kernel == index && (d.width, d.height) == (width, height));
+        let map_value = detail.map(|d| d.response[y * width + x]);
+        #[cfg(not(target_arch = "wasm32"))]
+        let map_value = map_value.or_else(|| {
+            let store = run.responses.as_ref()?;
+            Some(store.get(index)?[y * width + x])
+        });
+        self.explanation = Some(Explanation {
+            kernel: index,
+            pixel: (x, y),
+            lesson,
+            map_value,
+        });
+    }
+
+    fn explanation_window(&mut self, ctx: &egui::Context) {
+        let (Some(explanation), Some(run)) = (&self.explanation, &self.run) else {
+            return;
+        };
+        let mut open = true;
+        let (x, y) = explanation.pixel;
+        let (left, top) = run.roi.map_or((0, 0), |roi| (roi.x, roi.y));
+        egui::Window::new("Explain response")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "Kernel {} at slide pixel ({}, {}); click the preview to pick another.",
+                    explanation.kernel,
+                    left + x,
+                    top + y
+                ));
+                if self.dirty_kernels.contains(&explanation.kernel) {
+                    ui.colored_label(
+                        ui.visuals().warn_fg_color,
+                        "The kernel was edited after the run; the map shows its old weights.",
+                    );
+                }
+                if !run.mode.is_linear() {
+                    ui.label(format!(
+                        "The run used the {} filter; this is the kernel's weighted sum.",
+                        run.mode.label()
+                    ));
+                }
+                explanation_view(
+                    ui,
+                    &explanation.lesson,
+                    &self.settings.overlay_colors,
+                    self.settings.number_format,
+                );
+                if let Some(value) = explanation.map_value {
+                    ui.label(format!(
+                        "Map value: {} ({} backend)",
+                        self.settings.number_format.format(value),
+                        run.backend.label()
+                    ));
+                }
+            });
+        if !open {
+            self.explanation = None;
+        }
+    }
+
+    /// Buttons that select, move and clear the region of interest, once a
+    /// slide is loaded.
+    fn roi_controls(&mut self, ui: &mut egui::Ui) {
+        if self.slide.gray.is_none() {
+            return;
+        }
+        ui.horizontal(|ui| {
+            if ui
+                .toggle_value(&mut self.selecting_roi, "Select region")
+                .on_hover_text("Drag on the slide to convolve only that region.")
+                .clicked()
+            {
+                self.moving_roi = false;
+            }
+            if let Some(roi) = self.roi {
+                ui.label(format!(
+                    "Region: {}x{} at ({}, {})",
+                    roi.width, roi.height, roi.x, roi.y
+                ));
+                if ui
+                    .toggle_value(&mut self.moving_roi, "Move")
+                    .on_hover_text(
+                        "Drag on the slide to move the region. The last run's maps follow it, \
+                         and natively only the strips the move uncovered are convolved again.",
+                    )
+                    .clicked()
+                {
+                    self.selecting_roi = false;
+                }
+                if ui.button("Whole slide").clicked() {
+                    self.roi = None;
+                    self.status = "Runs convolve the whole slide again.".to_owned();
+                }
+            }
+        });
+    }
+
+    /// Draws the region of interest on the slide viewer `image`, where the
+    /// whole slide spans `rect` at `scale` screen points per slide pixel,
+    /// and reads a new region from drags while selecting one.
+    fn roi_overlay(&mut self, ui: &egui::Ui, image: &egui::Response, rect: egui::Rect, scale: f32) {
+        let Some(gray) = &self.slide.gray else {
+            return;
+        };
+        let size = (gray.width() as usize, gray.height() as usize);
+        let to_pixel = |pos: egui::Pos2| {
+            let pixel = (pos - rect.min) / scale;
+            [pixel.x, pixel.y]
+        };
+        if self.selecting_roi {
+            if let Some(pos) = image.interact_pointer_pos() {
+                let corner = to_pixel(pos);
+                if image.drag_started() {
+                    self.roi_drag = Some((corner, corner));
+                } else if let Some((_, current)) = &mut self.roi_drag {
+                    *current = corner;
+                }
+            }
+            if image.drag_stopped()
+                && let Some((anchor, corner)) = self.roi_drag.take()
+            {
+                self.roi = Roi::from_corners(anchor, corner, size).or(self.roi);
+                self.selecting_roi = false;
+                if let Some(roi) = self.roi {
+                    self.status = format!(
+                        "Runs convolve the {}x{} region at ({}, {}); run again to apply.",
+                        roi.width, roi.height, roi.x, roi.y
+                    );
+                }
+            }
+        } else {
+            self.roi_drag = None;
+        }
+        match self.roi {
+            Some(roi) if self.moving_roi => {
+                if image.dragged() {
+                    *self.roi_move.get_or_insert(egui::Vec2::ZERO) += image.drag_delta() / scale;
+                }
+                if image.drag_stopped()
+                    && let Some(offset) = self.roi_move.take()
+                {
+                    let moved =
+                        roi.moved((offset.x.round() as isize, offset.y.round() as isize), size);
+                    if moved != roi {
+                        self.move_roi(moved);
+                    }
+                }
+            }
+            // Loading another slide drops the region, and moving with it.
+            None => (self.moving_roi, self.roi_move) = (false, None),
+            Some(_) => self.roi_move = None,
+        }
+        let [r, g, b] = self.settings.overlay_colors.roi;
+        let color = egui::Color32::from_rgb(r, g, b);
+        let painter = ui.painter().with_clip_rect(image.rect);
+        let screen = |[x0, y0, x1, y1]: [f32; 4]| {
+            egui::Rect::from_min_max(
+                rect.min + egui::vec2(x0, y0) * scale,
+                rect.min + egui::vec2(x1, y1) * scale,
+            )
+        };
+        if let Some((a, b)) = self.roi_drag {
+            let dragged = screen([a[0], a[1], b[0], b[1]]);
+            painter.rect_stroke(dragged, 0.0, egui::Stroke::new(1.0, color));
+        }
+        if let (Some(roi), Some(offset)) = (self.roi, self.roi_move) {
+            let moved = roi.moved((offset.x.round() as isize, offset.y.round() as isize), size);
+            let [x, y, w, h] = [moved.x, moved.y, moved.width, moved.height].map(|v| v as f32);
+            painter.rect_stroke(
+                screen([x, y, x + w, y + h]),
+                0.0,
+                egui::Stroke::new(1.0, color),
+            );
+        }
+        if let Some(roi) = self.roi {
+            let [x, y, w, h] = [roi.x, roi.y, roi.width, roi.height].map(|v| v as f32);
+            painter.rect_stroke(
+                screen([x, y, x + w, y + h]),
+                0.0,
+                egui::Stroke::new(2.0, color),
+            );
+        }
+    }
+
+    /// Draws the tile heatmap over the slide viewer `image`, in which the
+    /// whole slide spans `rect` at `scale` screen points per slide pixel.
+    fn tile_heatmap_overlay(
+        &self,
+        ui: &egui::Ui,
+        image: egui::Response,
+        rect: egui::Rect,
+        scale: f32,
+    ) {
+        let fmt = self.settings.number_format;
+        let Some(view) = self.tile_heatmap.as_ref().filter(|_| self.heatmap_visible) else {
+            return;
+        };
+        let rows = view.scores.len() / view.cols.max(1);
+        let origin = egui::vec2(view.origin.0 as f32, view.origin.1 as f32);
+        // Partial edge tiles extend past the slide; the clip trims them.
+        let grid = egui::Rect::from_min_size(
+            rect.min + origin * scale,
+            egui::vec2(view.cols as f32, rows as f32) * view.tile as f32 * scale,
+        );
+        let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+        let tint = egui::Color32::from_white_alpha((self.settings.heatmap_opacity * 255.0) as u8);
+        ui.painter()
+            .with_clip_rect(image.rect)
+            .image(view.texture.id(), grid, uv, tint);
+        if let Some(pos) = image.hover_pos().filter(|&pos| grid.contains(pos)) {
+            let pixel = (pos - grid.min) / scale;
+            let (col, row) = (pixel.x as usize / view.tile, pixel.y as usize / view.tile);
+            if let Some(score) = view
+                .scores
+                .get(row * view.cols + col)
+                .filter(|_| col < view.cols)
+            {
+                image.on_hover_text(format!(
+                    "Tile column {col}, row {row} (x {}, y {}): score {}",
+                    view.origin.0 + col * view.tile,
+                    view.origin.1 + row * view.tile,
+                    fmt.format(*score)
+                ));
+            }
+        }
+    }
+
+    /// Recomputes the selected kernel's map tile by tile, with the halos the
+    /// tiled paths (browser bands, web worker) use, and compares it with the
+    /// map the run produced.
+    fn check_tile_seams(&mut self) {
+        let index = self.selected_kernel;
+        let Some((whole, width, height)) = self.full_response(index) else {
+            self.status = "Run the convolutions first.".to_owned();
+            return;
+        };
+        let (Some(job), Some(kernel)) = (self.run.as_ref(), self.kernels.get(index)) else {
+            return;
+        };
+        if !job.planes.is_empty() {
+            self.status = "Tile seams are checked on single-channel runs.".to_owned();
+            return;
+        }
+        // The tiled paths only ever compute dense maps.
+        if !job.sampling.is_dense() {
+            self.status = "Tile seams are checked on runs without stride or dilation.".to_owned();
+            return;
+        }
+        let started = Instant::now();
+        let report = seams::check_seams(
+            job.border,
+            &job.input,
+            (width, height),
+            (kernel.width, kernel.height),
+            self.seam_tile,
+            &whole,
+            |tile, tw, th| kernel.filter(job.mode, job.backend, BorderMode::Zero, tile, tw, th),
+        );
+        self.status = format!(
+            "Checked {} tiles of kernel {index} in {:.1} s: {} differ from the run's map.",
+            report.discrepancies.len(),
+            started.elapsed().as_secs_f32(),
+            report.mismatched()
+        );
+        self.seam_check = Some(SeamView {
+            kernel: index,
+            report,
+        });
+    }
+
+    fn seams_panel(&mut self, ui: &mut egui::Ui) {
+        ui.collapsing("Tile seams (debug)", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Tile size");
+                ui.add(
+                    egui::DragValue::new(&mut self.seam_tile)
+                        .range(8..=4096)
+                        .suffix(" px"),
+                );
+                if ui.button("Check selected kernel").clicked() {
+                    self.check_tile_seams();
+                }
+            });
+            let Some(view) = &self.seam_check else {
+                return;
+            };
+            ui.checkbox(
+                &mut self.seams_visible,
+                "Show tile boundaries on the preview",
+            );
+            let report = &view.report;
+            let tiles = report.discrepancies.len();
+            if report.mismatched() == 0 {
+                ui.label(format!(
+                    "Kernel {}: all {tiles} tiles ({} x {}) match the untiled map bit for bit.",
+                    view.kernel, report.cols, report.rows
+                ));
+            } else {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    format!(
+                        "Kernel {}: {} of {tiles} tiles differ, by up to {:.3e}; they are \
+                         shaded red.",
+                        view.kernel,
+                        report.mismatched(),
+                        report.max()
+                    ),
+                );
+            }
+        });
+    }
+
+    /// Draws the seam check's tile grid over the preview viewer `image`, in
+    /// which the whole `width` x `height` map spans `rect`, shading tiles
+    /// that differ from the untiled map.
+    fn seams_overlay(
+        &self,
+        ui: &egui::Ui,
+        image: egui::Response,
+        rect: egui::Rect,
+        (width, height): (usize, usize),
+    ) {
+        let Some(view) = self
+            .seam_check
+            .as_ref()
+            .filter(|v| self.seams_visible && v.kernel == self.selected_kernel)
+        else {
+            return;
+        };
+        let report = &view.report;
+        let scale = rect.width() / width.max(1) as f32;
+        let painter = ui.painter().with_clip_rect(image.rect);
+        let colors = self.settings.overlay_colors;
+        let [r, g, b] = colors.grid;
+        let line = egui::Stroke::new(1.0, egui::Color32::from_rgba_unmultiplied(r, g, b, 160));
+        let [r, g, b] = colors.mismatch;
+        let mismatch = egui::Color32::from_rgba_unmultiplied(r, g, b, 90);
+        for (i, &discrepancy) in report.discrepancies.iter().enumerate() {
+            let (col, row) = (i % report.cols, i / report.cols);
+            let min = rect.min
+                + egui::vec2((col * report.tile) as f32, (row * report.tile) as f32) * scale;
+            let size = egui::vec2(
+                report.tile.min(width - col * report.tile) as f32,
+                report.tile.min(height - row * report.tile) as f32,
+            ) * scale;
+            let tile = egui::Rect::from_min_size(min, size);
+            if discrepancy != 0.0 {
+                painter.rect_filled(tile, 0.0, mismatch);
+            }
+            painter.rect_stroke(tile, 0.0, line);
+        }
+        if let Some(pos) = image.hover_pos() {
+            let pixel = (pos - rect.min) / scale;
+            let (col, row) = (
+                pixel.x as usize / report.tile,
+                pixel.y as usize / report.tile,
+            );
+            if col < report.cols
+                && let Some(discrepancy) = report.discrepancies.get(row * report.cols + col)
+            {
+                image.on_hover_text(format!(
+                    "Tile column {col}, row {row}: largest discrepancy {discrepancy:.3e}"
+                ));
+            }
+        }
+    }
+
+    fn autocorrelation_panel(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        ui.collapsing("Autocorrelation", |ui| {
+            if ui.button("Compute for selected kernel").clicked() {
+                self.compute_autocorrelation(ctx);
+            }
+            let Some(view) = &self.autocorrelation else {
+                return;
+            };
+            if view.kernel != self.selected_kernel {
+                ui.label(format!(
+                    "Showing kernel {} (recompute to update).",
+                    view.kernel
+                ));
+            }
+            ui.image((view.texture.id(), egui::vec2(256.0, 256.0)));
+            match (view.result.period(), view.result.period_angle_deg()) {
+                (Some(period), Some(angle)) => ui.label(format!(
+                    "Dominant period: {:.1} px at {:.0}°",
+                    period * view.scale as f32,
+                    angle
+                )),
+                _ => ui.label("No dominant period found."),
+            };
+        });
+    }
+
+    fn export_flythrough(&mut self) {
+        let Some(slide) = self.slide.gray.as_ref() else {
+            return;
+        };
+        let cropped = self
+            .run
+            .as_ref()
+            .and_then(|run| run.roi)
+            .map(|roi| roi.crop(slide));
+        let slide = cropped.as_ref().unwrap_or(slide);
+        let Some((response, width, height)) = self.full_response(self.selected_kernel) else {
+            self.status = "Run the convolutions first.".to_owned();
+            return;
+        };
+        let fly = &self.flythrough;
+        if fly.keyframes.len() < 2 {
+            self.status = "A flythrough needs at least two keyframes.".to_owned();
+            return;
+        }
+
+        // Strided maps are stretched back over the slide they were sampled from.
+        let (slide_width, slide_height) = (slide.width() as usize, slide.height() as usize);
+        let response = if (width, height) == (slide_width, slide_height) {
+            response
+        } else {
+            resize_nearest(&response, width, height, slide_width, slide_height)
+        };
+        let (width, height) = (slide_width, slide_height);
+        let calibration = self.settings.calibration;
+        let response = calibration.calibrated(&response);
+        let range = calibration.display_range(min_max(&response));
+        let out_w = FLYTHROUGH_FRAME_WIDTH;
+        // Even dimensions keep yuv420p encoders happy.
+        let out_h = (((out_w as usize * height / width) as u32).max(2) + 1) & !1;
+        let frames: Vec<_> = flythrough::camera_path(&fly.keyframes, fly.frames_per_segment)
+            .into_iter()
+            .map(|camera| {
+                flythrough::render_frame(
+                    slide,
+                    &response,
+                    range,
+                    camera,
+                    out_w,
+                    out_h,
+                    fly.opacity,
+                    self.settings.colormap,
+                )
+            })
+            .collect();
+        let frame_count = frames.len();
+
+        let encoded = match fly.format {
+            VideoFormat::WebP => flythrough::encode_webp(&frames, fly.fps),
+            VideoFormat::Gif => flythrough::encode_gif(frames, fly.fps),
+            #[cfg(not(target_arch = "wasm32"))]
+            format => flythrough::encode_with_ffmpeg(&frames, fly.fps, format),
+        };
+        let file_name = format!(
+            "flythrough_kernel{}.{}",
+            self.selected_kernel,
+            fly.format.extension()
+        );
+        self.status = match encoded
+            .and_then(|bytes| export::save_file(&self.settings.export_dir, &file_name, &bytes))
+        {
+            Ok(dest) => format!("Exported {frame_count}-frame flythrough to {dest}."),
+            Err(e) => format!("Flythrough export failed: {e}"),
+        };
+    }
+
+    fn flythrough_panel(&mut self, ui: &mut egui::Ui) {
+        ui.label("Keyframes (center x, center y, zoom):");
+        let mut remove = None;
+        for (i, key) in self.flythrough.keyframes.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(format!("{i}:"));
+                ui.add(
+                    egui::DragValue::new(&mut key.center[0])
+                        .range(0.0..=1.0)
+                        .speed(0.005),
+                );
+                ui.add(
+                    egui::DragValue::new(&mut key.center[1])
+                        .range(0.0..=1.0)
+                        .speed(0.005),
+                );
+                ui.add(
+                    egui::DragValue::new(&mut key.zoom)
+                        .range(1.0..=64.0)
+                        .speed(0.05),
+                );
+                if ui.small_button("x").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove {
+            self.flythrough.keyframes.remove(i);
+        }
+        if ui.button("Add keyframe").clicked() {
+            let last = self
+                .flythrough
+                .keyframes
+                .last()
+                .copied()
+                .unwrap_or_default();
+            self.flythrough.keyframes.push(last);
+        }
+
+        let fly = &mut self.flythrough;
+        ui.add(egui::Slider::new(&mut fly.frames_per_segment, 1..=240).text("Frames per segment"));
+        ui.add(egui::Slider::new(&mut fly.fps, 1..=60).text("FPS"));
+        ui.add(egui::Slider::new(&mut fly.opacity, 0.0..=1.0).text("Overlay opacity"));
+        egui::ComboBox::from_label("Format")
+            .selected_text(fly.format.label())
+            .show_ui(ui, |ui| {
+                for &format in VideoFormat::ALL {
+                    ui.selectable_value(&mut fly.format, format, format.label());
+                }
+            });
+        if ui.button("Export flythrough").clicked() {
+            self.export_flythrough();
+        }
+    }
+
+    /// Scores of the kernel groups, from the current kernel scores.
+    fn group_scores(&self) -> Vec<groups::GroupScore> {
+        let scores: Vec<f32> = self.previews.iter().map(|p| p.score).collect();
+        groups::group_scores(&self.kernels, &scores)
+    }
+
+    /// Combines the full-resolution responses of a group's kernels into
+    /// their per-pixel maximum |r|.
+    fn show_group_map(&mut self, ctx: &egui::Context, group: &str) {
+        let members: Vec<usize> = (0..self.previews.len())
+            .filter(|&i| self.kernels[i].group == group && self.kernels[i].enabled)
+            .collect();
+        let mut combined = Vec::new();
+        let (mut width, mut height) = (0, 0);
+        for index in members {
+            let Some((response, w, h)) = self.full_response(index) else {
+                return;
+            };
+            groups::accumulate_max_abs(&mut combined, &response);
+            (width, height) = (w, h);
+        }
+        let (lo, hi) = min_max(&combined);
+        let (pw, ph) = preview_size(width, height, PREVIEW_MAX_SIZE);
+        let bytes = quantize(&resize_nearest(&combined, width, height, pw, ph), lo, hi);
+        self.group_map = Some(GroupMapView {
+            group: group.to_owned(),
+            texture: ctx.load_texture(
+                "group_map",
+                ColorImage::from_gray([pw, ph], &bytes),
+                TextureOptions::LINEAR,
+            ),
+        });
+    }
+
+    fn groups_panel(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let fmt = self.settings.number_format;
+        ui.horizontal(|ui| {
+            if ui.button("Group by sheet rows").clicked() {
+                groups::group_by_rows(&mut self.kernels);
+                self.group_map = None;
+            }
+            if ui.button("Clear groups").clicked() {
+                for kernel in &mut self.kernels {
+                    kernel.group.clear();
+                }
+                self.group_map = None;
+            }
+        });
+        let group_scores = self.group_scores();
+        if group_scores.is_empty() {
+            ui.label("Type a group name for the selected kernel, or group by sheet rows.");
+            return;
+        }
+        let mut show = None;
+        egui::Grid::new("group_table").striped(true).show(ui, |ui| {
+            for title in ["Group", "Kernels", "Mean", "Max", "Best"] {
+                ui.strong(title);
+            }
+            ui.end_row();
+            for group in &group_scores {
+                let shown = self
+                    .group_map
+                    .as_ref()
+                    .is_some_and(|m| m.group == group.name);
+                if ui
+                    .selectable_label(shown, &group.name)
+                    .on_hover_text("Show the group's max |r| map")
+                    .clicked()
+                {
+                    show = Some(group.name.clone());
+                }
+                ui.label(group.members.len().to_string());
+                ui.label(fmt.format(group.mean));
+                ui.label(fmt.format(group.max));
+                if ui.selectable_label(false, group.best.to_string()).clicked() {
+                    self.selected_kernel = group.best;
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(group) = show {
+            self.show_group_map(ctx, &group);
+        }
+        if ui.button("Save group scores CSV").clicked() {
+            let csv = self
+                .settings
+                .csv_format
+                .apply(&groups::group_scores_csv(&group_scores));
+            self.status = match export::save_file(
+                &self.settings.export_dir,
+                "group_scores.csv",
+                csv.as_bytes(),
+            ) {
+                Ok(path) => format!("Saved group scores to {path}."),
+                Err(e) => e,
+            };
+        }
+        if let Some(view) = &self.group_map {
+            let size = view.texture.size_vec2();
+            let scale = (ui.available_width() / size.x).min(1.0);
+            ui.image((view.texture.id(), size * scale));
+            ui.label(format!(
+                "{}: strongest |r| of any member kernel",
+                view.group
+            ));
+        }
+    }
+
+    /// Heat map of the selected kernel's weights. Scrolling or dragging
+    /// vertically over a cell changes its weight, double-clicking zeroes it;
+    /// with "Type values" each cell is a numeric field instead.
+    fn weights_panel(&mut self, ui: &mut egui::Ui) {
+        let index = self.selected_kernel;
+        let Some(kernel) = self.kernels.get_mut(index) else {
+            ui.label("Load a kernels sheet to edit its weights.");
+            return;
+        };
+        let (kw, kh) = (kernel.width, kernel.height);
+        egui::ComboBox::from_label("Symmetric edits")
+            .selected_text(self.mirror_edit.label())
+            .show_ui(ui, |ui| {
+                for mode in MirrorEdit::ALL {
+                    ui.selectable_value(&mut self.mirror_edit, mode, mode.label());
+                }
+            });
+        let mut mask_changed = false;
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.mask_edit, "Edit mask");
+            ui.add_enabled(
+                !self.mask_edit,
+                egui::Checkbox::new(&mut self.numeric_weights, "Type values"),
+            );
+            if ui.button("Circular mask").clicked() {
+                kernel.mask = Some(kernel::circular_mask(kw, kh));
+                mask_changed = true;
+            }
+            if ui
+                .add_enabled(kernel.mask.is_some(), egui::Button::new("Clear mask"))
+                .clicked()
+            {
+                kernel.mask = None;
+                mask_changed = true;
+            }
+        });
+        // Colors saturate at the largest magnitude, or at 1 for faint kernels.
+        let range = kernel.weights.iter().fold(1.0f32, |m, w| m.max(w.abs()));
+        let colors = self.settings.overlay_colors;
+        let mut edit = None;
+        let mut toggle = None;
+        egui::Grid::new("weight_grid")
+            .spacing([2.0, 2.0])
+            .show(ui, |ui| {
+                for y in 0..kh {
+                    for x in 0..kw {
+                        let weight = kernel.weights[y * kw + x];
+                        let inside = kernel.mask.as_ref().is_none_or(|m| m[y * kw + x]);
+                        let fill = if inside {
+                            signed_fill(&colors, weight, range)
+                        } else {
+                            egui::Color32::DARK_GRAY
+                        };
+                        if self.numeric_weights && !self.mask_edit {
+                            let mut value = weight;
+                            let field = ui.scope(|ui| {
+                                let visuals = ui.visuals_mut();
+                                visuals.override_text_color = Some(if inside {
+                                    egui::Color32::BLACK
+                                } else {
+                                    egui::Color32::GRAY
+                                });
+                                visuals.widgets.inactive.weak_bg_fill = fill;
+                                visuals.widgets.hovered.weak_bg_fill = fill;
+                                ui.add_sized(
+                                    [NUMERIC_CELL_WIDTH, WEIGHT_CELL_SIZE],
+                                    egui::DragValue::new(&mut value)
+                                        .speed(DRAG_WEIGHT_STEP)
+                                        .fixed_decimals(3),
+                                )
+                            });
+                            field
+                                .inner
+                                .on_hover_text(format!("({x}, {y}): {weight:.6}"));
+                            if value != weight {
+                                edit = Some((x, y, value));
+                            }
+                            continue;
+                        }
+                        let (rect, response) = ui.allocate_exact_size(
+                            egui::vec2(WEIGHT_CELL_SIZE, WEIGHT_CELL_SIZE),
+                            egui::Sense::click_and_drag(),
+                        );
+                        ui.painter().rect_filled(rect, 2.0, fill);
+                        ui.painter().text(
+                            rect.center(),
+                            egui::Align2::CENTER_CENTER,
+                            format!("{weight:.2}"),
+                            egui::FontId::proportional(9.0),
+                            if inside {
+                                egui::Color32::BLACK
+                            } else {
+                                egui::Color32::GRAY
+                            },
+                        );
+                        if self.mask_edit {
+                            if response.clicked() {
+                                toggle = Some((x, y, !inside));
+                            }
+                            let state = if inside { "in mask" } else { "masked out" };
+                            response.on_hover_text(format!("({x}, {y}): {state}"));
+                            continue;
+                        }
+                        let mut value = weight;
+                        if response.hovered() {
+                            value += ui.input(|i| i.smooth_scroll_delta.y) * SCROLL_WEIGHT_STEP;
+                        }
+                        if response.dragged() {
+                            value -= response.drag_delta().y * DRAG_WEIGHT_STEP;
+                        }
+                        if response.double_clicked() {
+                            value = 0.0;
+                        }
+                        response.on_hover_text(format!("({x}, {y}): {weight:.4}"));
+                        if value != weight {
+                            edit = Some((x, y, value));
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+        if self.mask_edit {
+            ui.label(format!(
+                "Click a cell to add it to or remove it from the mask; {} of {} taps take part.",
+                kernel.support(),
+                kernel.weights.len()
+            ));
+        } else if self.numeric_weights {
+            ui.label("Click a cell to type its weight, or drag it sideways.");
+        } else {
+            ui.label("Scroll or drag a cell to change it; double-click sets it to 0.");
+        }
+        if let Some((x, y, inside)) = toggle {
+            let mask = kernel.mask.get_or_insert_with(|| vec![true; kw * kh]);
+            for (cx, cy) in self.mirror_edit.cells(x, y, kw, kh) {
+                mask[cy * kw + cx] = inside;
+            }
+            mask_changed = true;
+        }
+        if mask_changed {
+            let note = match &kernel.mask {
+                Some(_) => format!("Mask set to {} of {} taps.", kernel.support(), kw * kh),
+                None => "Mask cleared.".to_owned(),
+            };
+            kernel.history.push(note);
+        }
+        // Typed values such as "inf" parse but would spoil the whole map.
+        let edit = edit.filter(|(_, _, value): &(usize, usize, f32)| value.is_finite());
+        if let Some((x, y, value)) = edit {
+            for (cx, cy) in self.mirror_edit.cells(x, y, kw, kh) {
+                kernel.weights[cy * kw + cx] = value;
+            }
+            // A single edit generally breaks the outer-product structure, so
+            // the factors are looked for again, and leaves the sheet's color
+            // weights behind.
+            kernel.separable = None;
+            kernel.detect_separable();
+            kernel.color = None;
+            let note = "Weights edited in the inspector.";
+            if kernel.history.last().is_none_or(|last| last != note) {
+                kernel.history.push(note.to_owned());
+            }
+        }
+        if mask_changed || edit.is_some() {
+            self.mark_edited(index);
+        }
+        let dirty = self.dirty_kernels.contains(&index);
+        let mut rerun = false;
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.settings.rerun_on_edit, "Update on edit")
+                .on_hover_text("Off, edits wait for \"Re-run this kernel\".");
+            rerun = ui
+                .add_enabled(dirty, egui::Button::new("Re-run this kernel"))
+                .clicked();
+        });
+        if dirty && self.pending_edit.is_none() {
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                "Edited since its last run; the response shown is out of date.",
+            );
+        }
+        if rerun {
+            self.pending_edit = None;
+            if self.redraft(index) {
+                self.status = format!("Re-running kernel {index} with its edited weights.");
+            } else {
+                self.status = "Run the convolutions first.".to_owned();
+            }
+        }
+    }
+
+    /// Marks kernel `index` as out of date and, with "Update on edit", has
+    /// its response updated once the edits settle.
+    fn mark_edited(&mut self, index: usize) {
+        self.dirty_kernels.insert(index);
+        self.unsaved.edits = true;
+        if self.settings.rerun_on_edit {
+            self.pending_edit = Some((index, Instant::now()));
+        }
+    }
+
+    /// Once edits have settled, replaces the edited kernel's preview with a
+    /// draft of its new response. Returns true while an edit is pending.
+    fn apply_pending_edit(&mut self) -> bool {
+        let Some((index, edited)) = self.pending_edit else {
+            return false;
+        };
+        if edited.elapsed() < EDIT_DEBOUNCE {
+            return true;
+        }
+        self.pending_edit = None;
+        self.redraft(index)
+    }
+
+    /// Replaces, or appends for a new kernel, the preview of kernel `index`
+    /// with a draft of its current weights; refinement then brings it to
+    /// full resolution. Returns false without a run to preview it with.
+    fn redraft(&mut self, index: usize) -> bool {
+        let Some(job) = self.run.as_mut() else {
+            return false;
+        };
+        if index > self.previews.len() {
+            return false;
+        }
+        let (draft, planes, dw, dh) = job.draft();
+        let response = job.respond_to(&self.kernels[index], &draft, &planes, dw, dh);
+        let preview = build_draft_preview(
+            &response,
+            &draft,
+            dw,
+            dh,
+            job.width,
+            job.height,
+            job.activation_k,
+        );
+        if index == self.previews.len() {
+            self.previews.push(preview);
+        } else {
+            self.previews[index] = preview;
+        }
+        job.forget(index);
+        // A refinement in flight still uses the old weights.
+        #[cfg(target_arch = "wasm32")]
+        if self.previews.len() == self.kernels.len() {
+            self.chunked = None;
+        }
+        self.dirty_kernels.remove(&index);
+        self.forget_views(index);
+        self.rescore();
+        true
+    }
+
+    /// Closes the views computed from kernel `index`'s previous map.
+    fn forget_views(&mut self, index: usize) {
+        self.autocorrelation = self.autocorrelation.take().filter(|v| v.kernel != index);
+        self.tile_heatmap = self.tile_heatmap.take().filter(|v| v.kernel != index);
+        self.triage = None;
+        self.comparison = self.comparison.take().filter(|v| v.kernel != index);
+        self.control_ratio = self.control_ratio.take().filter(|v| v.kernel != index);
+        self.seam_check = self.seam_check.take().filter(|v| v.kernel != index);
+        self.zoom_detail = self.zoom_detail.take().filter(|v| v.kernel != index);
+        self.explanation = self.explanation.take().filter(|v| v.kernel != index);
+        self.group_map = None;
+        self.winner_map = None;
+        self.peaks = self.peaks.take().filter(|v| v.kernel != index);
+        self.orientation = self
+            .orientation
+            .take()
+            .filter(|v| v.kernels.0 != index && v.kernels.1 != index);
+    }
+
+    /// Recomputes the kernels ticked in the statistics table at full
+    /// resolution, keeping the rest of the current run, so a few edited
+    /// kernels of a large bank do not need a full rerun.
+    fn run_selected(&mut self) {
+        if self.run.is_none() {
+            self.status = "Run all kernels once first; draft mode is enough.".to_owned();
+            return;
+        }
+        let indices: Vec<usize> = self
+            .checked_kernels
+            .iter()
+            .copied()
+            .filter(|&i| i < self.previews.len())
+            .collect();
+        if indices.is_empty() {
+            self.status = "Tick the kernels to recompute in the statistics table.".to_owned();
+            return;
+        }
+        for &index in &indices {
+            self.forget_views(index);
+        }
+        let Some(job) = self.run.as_ref() else {
+            return;
+        };
+
+        // Without a GPU the browser computes them over several frames.
+        #[cfg(target_arch = "wasm32")]
+        if job.planes.is_empty() && job.sampling.is_dense() && self.webgl(job).is_none() {
+            let kernels = indices.iter().map(|&i| (i, self.kernels[i].clone()));
+            self.chunked = Some(ChunkedRun::new(
+                kernels.collect(),
+                job.border,
+                job.width,
+                job.height,
+            ));
+            self.status = format!("Recomputing {} checked kernels...", indices.len());
+            return;
+        }
+        let started = Instant::now();
+        let (map_width, map_height) = job.map_size();
+        let (backend, pixels) = (job.backend, map_width * map_height);
+        #[cfg(target_arch = "wasm32")]
+        let previews = {
+            let mut previews = Vec::with_capacity(indices.len());
+            for &index in &indices {
+                let Some(job) = self.run.as_ref() else {
+                    return;
+                };
+                let response = self.convolve_exact(job, index);
+                let Some(job) = self.run.as_mut() else {
+                    return;
+                };
+                job.keep(index, &response);
+                previews.push(job.exact_preview(&response));
+            }
+            previews
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        let previews = {
+            let Some(job) = self.run.as_mut() else {
+                return;
+            };
+            exact_previews(&self.kernels, &indices, job)
+        };
+        let mut taps = 0;
+        for (&index, preview) in indices.iter().zip(previews) {
+            let significance = self.previews[index].significance;
+            self.previews[index] = ConvolutionPreview {
+                significance,
+                ..preview
+            };
+            taps += self.kernels[index].taps();
+        }
+        self.rescore();
+        self.settings.usage.record(
+            Stage::Convolution,
+            started,
+            Some(backend),
+            (pixels * taps) as u64,
+        );
+        self.status = format!(
+            "Recomputed {} checked kernels ({} backend).",
+            indices.len(),
+            backend.label()
+        );
+    }
+
+    /// Appends the combination of kernels A and B to the bank and selects it.
+    fn add_derived_kernel(&mut self) {
+        let (operation, a, b) = self.arithmetic;
+        let (Some(ka), Some(kb)) = (self.kernels.get(a), self.kernels.get(b)) else {
+            self.status = format!("The bank has no kernel {}.", a.max(b));
+            return;
+        };
+        let mut kernel = match operation.combine((a, ka), (b, kb)) {
+            Ok(kernel) => kernel,
+            Err(error) => {
+                self.status = error;
+                return;
+            }
+        };
+        kernel.name = kernel.provenance.to_string();
+        let index = self.kernels.len();
+        self.kernels.push(kernel);
+        self.redraft(index);
+        self.selected_kernel = index;
+        self.status = format!("Added kernel {index}: {}.", self.kernels[index].name);
+    }
+
+    fn arithmetic_panel(&mut self, ui: &mut egui::Ui) {
+        let (operation, a, b) = &mut self.arithmetic;
+        let last = self.kernels.len().saturating_sub(1);
+        egui::ComboBox::from_label("Operation")
+            .selected_text(operation.label())
+            .show_ui(ui, |ui| {
+                for op in KernelOperation::ALL {
+                    ui.selectable_value(operation, op, op.label());
+                }
+            });
+        ui.horizontal(|ui| {
+            ui.add(egui::DragValue::new(a).range(0..=last).prefix("A: kernel "));
+            ui.add(egui::DragValue::new(b).range(0..=last).prefix("B: kernel "));
+        });
+        if ui.button("Add to bank").clicked() {
+            self.add_derived_kernel();
+        }
+    }
+
+    /// Maps edge orientation and strength over the slide from the derivative
+    /// pair's full-resolution responses.
+    fn compute_orientation(&mut self, ctx: &egui::Context) {
+        let (x, y) = self.derivative_pair;
+        if x == y {
+            self.status = "Pick two different kernels as the derivative pair.".to_owned();
+            return;
+        }
+        let (Some((gx, width, height)), Some((gy, _, _))) =
+            (self.full_response(x), self.full_response(y))
+        else {
+            self.status = "Run the convolutions first.".to_owned();
+            return;
+        };
+        let map = orientation::orientation_map(&gx, &gy, width, height, PREVIEW_MAX_SIZE);
+        let texture = ctx.load_texture(
+            "orientation",
+            ColorImage::from_rgb([map.width, map.height], &map.rgb),
+            TextureOptions::LINEAR,
+        );
+        self.orientation = Some(OrientationView {
+            kernels: (x, y),
+            coherence: map.coherence,
+            orientation: map.orientation,
+            mean_magnitude: map.mean_magnitude,
+            texture,
+        });
+    }
+
+    fn orientation_panel(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let fmt = self.settings.number_format;
+        let last = self.kernels.len().saturating_sub(1);
+        let (x, y) = &mut self.derivative_pair;
+        ui.horizontal(|ui| {
+            ui.add(egui::DragValue::new(x).range(0..=last).prefix("X: kernel "));
+            ui.add(egui::DragValue::new(y).range(0..=last).prefix("Y: kernel "));
+        });
+        let named = |name: &str| self.kernels.iter().position(|k| k.name == name);
+        let sobel = named("Sobel X").zip(named("Sobel Y"));
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(sobel.is_some(), egui::Button::new("Use the Sobel presets"))
+                .on_disabled_hover_text("Add the Sobel kernels from the kernel presets.")
+                .clicked()
+                && let Some(pair) = sobel
+            {
+                self.derivative_pair = pair;
+            }
+            if ui.button("Compute orientation map").clicked() {
+                self.compute_orientation(ctx);
+            }
+        });
+        let Some(view) = &self.orientation else {
+            return;
+        };
+        let size = view.texture.size_vec2();
+        let scale = (ui.available_width() / size.x).min(1.0);
+        ui.image((view.texture.id(), size * scale));
+        ui.label(format!(
+            "Kernels {} and {}: coherence {:.3}, dominant orientation {:.0}°, mean gradient \
+             magnitude {}",
+            view.kernels.0,
+            view.kernels.1,
+            view.coherence,
+            view.orientation,
+            fmt.format(view.mean_magnitude)
+        ));
+        ui.label(
+            "Hue gives the edge orientation (red 0°, yellow-green 45°, cyan 90°, violet \
+             135°), saturation its local coherence and brightness the gradient magnitude.",
+        );
+    }
+
+    /// Colors each pixel by the enabled kernel with the strongest |r| there,
+    /// brighter where it is stronger, from their full-resolution responses.
+    fn compute_winner_map(&mut self, ctx: &egui::Context) {
+        let enabled: Vec<usize> = (0..self.previews.len())
+            .filter(|&i| self.kernels[i].enabled)
+            .collect();
+        if enabled.is_empty() {
+            self.status = "Run the convolutions first.".to_owned();
+            return;
+        }
+        let started = Instant::now();
+        let scaled = self.winner_scaled;
+        let mut winners: Option<Winners> = None;
+        for &index in &enabled {
+            let Some((response, width, height)) = self.full_response(index) else {
+                return;
+            };
+            let std = self.previews[index].stats.std_dev;
+            let scale = if scaled {
+                1.0 / std.max(f32::MIN_POSITIVE)
+            } else {
+                1.0
+            };
+            winners
+                .get_or_insert_with(|| Winners::new(width, height))
+                .fold(index, &response, scale);
+        }
+        let Some(winners) = winners else {
+            return;
+        };
+        let (pw, ph) = preview_size(winners.width, winners.height, PREVIEW_MAX_SIZE);
+        let texture = ctx.load_texture(
+            "winner_map",
+            ColorImage::from_rgb([pw, ph], &winners.rgb(pw, ph)),
+            TextureOptions::NEAREST,
+        );
+        let shares = winners.shares();
+        self.status = format!(
+            "Compared {} kernels at every pixel in {:.1} s; {} of them win somewhere.",
+            enabled.len(),
+            started.elapsed().as_secs_f32(),
+            shares.len()
+        );
+        self.winner_map = Some(WinnerMapView {
+            winners,
+            shares,
+            scaled,
+            texture,
+        });
+    }
+
+    fn export_winner_map(&mut self) {
+        let Some(view) = &self.winner_map else {
+            return;
+        };
+        let kernels = &self.kernels;
+        let name = |index: usize| kernels.get(index).map_or(String::new(), |k| k.name.clone());
+        let csv = self
+            .settings
+            .csv_format
+            .apply(&winners::shares_csv(&view.shares, name));
+        let dir = &self.settings.export_dir;
+        let saved = view
+            .winners
+            .png()
+            .and_then(|png| export::save_file(dir, "winner_map.png", &png))
+            .and_then(|_| export::save_file(dir, "winner_map.csv", csv.as_bytes()));
+        self.status = match saved {
+            Ok(path) => format!("Exported the winner map and its legend to {path}."),
+            Err(e) => format!("Winner map export failed: {e}"),
+        };
+    }
+
+    fn winner_panel(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let fmt = self.settings.number_format;
+        ui.checkbox(
+            &mut self.winner_scaled,
+            "Divide each kernel by its response std",
+        )
+        .on_hover_text(
+            "Compare how unusual each response is rather than its raw size, so \
+                 kernels with large weights do not win everywhere.",
+        );
+        ui.horizontal(|ui| {
+            if ui.button("Compute winner map").clicked() {
+                self.compute_winner_map(ctx);
+            }
+            if ui
+                .add_enabled(self.winner_map.is_some(), egui::Button::new("Export"))
+                .on_hover_text("Full-resolution PNG and a CSV legend.")
+                .clicked()
+            {
+                self.export_winner_map();
+            }
+        });
+        let Some(view) = &self.winner_map else {
+            return;
+        };
+        let size = view.texture.size_vec2();
+        let scale = (ui.available_width() / size.x).min(1.0);
+        ui.image((view.texture.id(), size * scale));
+        ui.label(format!(
+            "Hue names the kernel with the strongest {} at each pixel and brightness \
+             its size; black pixels have no response.",
+            if view.scaled { "|r| / std" } else { "|r|" }
+        ));
+        let mut select = None;
+        for share in view.shares.iter().take(WINNER_LEGEND_ROWS) {
+            let [r, g, b] = winners::kernel_color(share.index);
+            ui.horizontal(|ui| {
+                ui.colored_label(egui::Color32::from_rgb(r, g, b), "■");
+                let name = self
+                    .kernels
+                    .get(share.index)
+                    .map_or("", |k| k.name.as_str());
+                let text = format!(
+                    "Kernel {} {name}: {}% of pixels",
+                    share.index,
+                    fmt.format(share.fraction * 100.0)
+                );
+                if ui
+                    .selectable_label(share.index == self.selected_kernel, text)
+                    .clicked()
+                {
+                    select = Some(share.index);
+                }
+            });
+        }
+        if let Some(rest) = view
+            .shares
+            .len()
+            .checked_sub(WINNER_LEGEND_ROWS)
+            .filter(|&n| n > 0)
+        {
+            ui.weak(format!(
+                "and {rest} more kernels; the exported legend lists them all."
+            ));
+        }
+        if let Some(index) = select {
+            self.selected_kernel = index;
+        }
+    }
+
+    /// Appends the selected classical filters to the bank, after any sheet
+    /// tiles, so they are scored against them on the same slide.
+    fn add_preset_kernels(&mut self) {
+        let kernels = match self.settings.kernel_presets.kernels(MAX_KERNEL_SIDE) {
+            Ok(kernels) if kernels.is_empty() => {
+                self.status = "Select at least one preset.".to_owned();
+                return;
+            }
+            Ok(kernels) => kernels,
+            Err(error) => {
+                self.status = format!("{error}.");
+                return;
+            }
+        };
+        let first = self.kernels.len();
+        for kernel in kernels {
+            let index = self.kernels.len();
+            self.kernels.push(kernel);
+            self.redraft(index);
+        }
+        self.selected_kernel = first;
+        self.status = format!(
+            "Added {} preset kernels as kernels {first} to {}.",
+            self.kernels.len() - first,
+            self.kernels.len() - 1
+        );
+    }
+
+    fn gallery_panel(&mut self, ui: &mut egui::Ui) {
+        let busy = self.gallery_download.as_ref().map(|(index, _)| *index);
+        for (index, entry) in GALLERY.iter().enumerate() {
+            ui.horizontal(|ui| {
+                let (width, height) = entry.shape;
+                ui.label(format!("{} ({width}x{height})", entry.name))
+                    .on_hover_text(entry.description);
+                if busy == Some(index) {
+                    ui.spinner();
+                } else if ui
+                    .add_enabled(busy.is_none(), egui::Button::new("Load"))
+                    .clicked()
+                {
+                    if self.unsaved.any() {
+                        self.pending_discard = Some(Discard::Gallery(index));
+                    } else {
+                        self.download_example(index);
+                    }
+                }
+            });
+        }
+        ui.horizontal(|ui| {
+            ui.label("From");
+            ui.text_edit_singleline(&mut self.settings.gallery_url)
+                .on_hover_text("URL or, on native builds, folder holding the sheets.");
+        });
+    }
+
+    fn download_example(&mut self, index: usize) {
+        let url = gallery::entry_url(&self.settings.gallery_url, &GALLERY[index]);
+        self.status = format!("Downloading {url}...");
+        self.gallery_download = Some((index, gallery::download(url)));
+    }
+
+    /// Takes the downloaded gallery sheet, if it arrived, as the kernels
+    /// sheet and splits it. Returns true while still downloading.
+    fn poll_gallery(&mut self, ctx: &egui::Context) -> bool {
+        let Some((index, receiver)) = &self.gallery_download else {
+            return false;
+        };
+        let index = *index;
+        let result = match receiver.try_recv() {
+            Err(std::sync::mpsc::TryRecvError::Empty) => return true,
+            Ok(result) => result,
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                Err("The download stopped unexpectedly.".to_owned())
+            }
+        };
+        self.gallery_download = None;
+        let entry = &GALLERY[index];
+        let bytes = match result {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                self.status = e;
+                return false;
+            }
+        };
+        let (width, height) = entry.shape;
+        match KernelShape::new(width, height) {
+            Ok(shape) => self.kernel_shape = shape,
+            Err(e) => {
+                self.status = e;
+                return false;
+            }
+        }
+        let url = gallery::entry_url(&self.settings.gallery_url, entry);
+        self.load_png_into_slot(ctx, bytes, entry.file.to_owned(), url, Slot::KernelsSheet);
+        if self.kernels_sheet.name == entry.file {
+            self.split_kernels();
+        }
+        false
+    }
+
+    fn presets_panel(&mut self, ui: &mut egui::Ui) {
+        let presets = &mut self.settings.kernel_presets;
+        ui.checkbox(&mut presets.sobel, "Sobel X and Y");
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut presets.gaussian, "Gaussian");
+            ui.checkbox(&mut presets.laplacian_of_gaussian, "Laplacian of Gaussian");
+        });
+        ui.horizontal(|ui| {
+            ui.label("Sigmas");
+            ui.text_edit_singleline(&mut presets.sigmas);
+        });
+        ui.checkbox(&mut presets.gabor, "Gabor bank");
+        ui.horizontal(|ui| {
+            ui.label("Orientations");
+            ui.add(egui::DragValue::new(&mut presets.gabor_orientations).range(1..=16));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Wavelengths");
+            ui.text_edit_singleline(&mut presets.gabor_wavelengths);
+        });
+        if ui.button("Add to bank").clicked() {
+            self.add_preset_kernels();
+        }
+        ui.label(
+            "Scales are in pixels, separated by commas or spaces. Presets are appended after the \
+             sheet's kernels, so standard detectors are ranked next to learned ones.",
+        );
+    }
+
+    /// Adds the typed taps as a one-row (`along_rows`) or one-column kernel.
+    fn add_entered_kernel(&mut self, along_rows: bool) {
+        let taps = match kernel::parse_taps(&self.taps_text) {
+            Ok(taps) => taps,
+            Err(error) => {
+                self.status = format!("{error}.");
+                return;
+            }
+        };
+        let size = if along_rows {
+            (taps.len(), 1)
+        } else {
+            (1, taps.len())
+        };
+        let mut kernel = Kernel::new(taps, size, Provenance::Entered);
+        kernel.name = format!("{}x{} entered kernel", size.0, size.1);
+        let index = self.kernels.len();
+        self.kernels.push(kernel);
+        self.redraft(index);
+        self.selected_kernel = index;
+        self.status = format!("Added kernel {index}: {}.", self.kernels[index].name);
+    }
+
+    fn taps_panel(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Taps");
+            ui.text_edit_singleline(&mut self.taps_text);
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Add row kernel").clicked() {
+                self.add_entered_kernel(true);
+            }
+            if ui.button("Add column kernel").clicked() {
+                self.add_entered_kernel(false);
+            }
+        });
+        ui.label(
+            "Row kernels filter along rows, column kernels along columns. Combine one of each \
+             with the outer product for a separable 2-D filter.",
+        );
+    }
+
+    /// Ranks the kernels by their response on a sample of slide patches, a
+    /// cheap estimate of the full run's ranking.
+    fn quick_score(&mut self) {
+        let Some(slide) = self.slide.gray.as_ref() else {
+            self.status = "Load the histological slide first.".to_owned();
+            return;
+        };
+        if self.kernels.is_empty() {
+            self.status = "Split kernels first.".to_owned();
+            return;
+        }
+        let started = Instant::now();
+        let input = self.slide_input(slide, self.slide.rgb.as_ref());
+        let (width, height) = (slide.width() as usize, slide.height() as usize);
+        let corners = quick::patch_corners(width, height, self.settings.quick_patches);
+        let (kw, kh) = (self.kernel_shape.width(), self.kernel_shape.height());
+        let patch_pixels = quick::PATCH_SIZE.min(width) * quick::PATCH_SIZE.min(height);
+        let backend = self.resolve_backend(kw, kh, patch_pixels);
+        let (mode, border) = (self.settings.filter_mode, self.settings.border_mode);
+        let normalization = self.settings.score_normalization.for_mode(mode);
+        let image_std = stats::mean_std(&input).1 as f32;
+        let mut scores: Vec<(usize, f32)> = self
+            .kernels
+            .iter()
+            .enumerate()
+            .filter(|(_, kernel)| kernel.enabled)
+            .map(|(index, kernel)| {
+                let raw = quick::quick_mean_abs(
+                    kernel, mode, backend, border, &input, width, height, &corners,
+                );
+                let weights = kernel.masked_weights();
+                (index, normalization.apply(raw, &weights, image_std, None))
+            })
+            .collect();
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let taps = self.kernels.iter().filter(|k| k.enabled).map(Kernel::taps);
+        let work = (corners.len() * patch_pixels * taps.sum::<usize>()) as u64;
+        self.settings
+            .usage
+            .record(Stage::QuickScore, started, Some(backend), work);
+        self.status = format!(
+            "Quick-scored {} kernels on {} patches in {:.2} s.",
+            scores.len(),
+            corners.len(),
+            started.elapsed().as_secs_f32()
+        );
+        self.quick_scores = scores;
+    }
+
+    fn quick_score_panel(&mut self, ui: &mut egui::Ui) {
+        let fmt = self.settings.number_format;
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::DragValue::new(&mut self.settings.quick_patches)
+                    .range(1..=4096)
+                    .suffix(" patches"),
+            );
+            if ui.button("Quick score").clicked() {
+                self.quick_score();
+            }
+        });
+        if self.quick_scores.is_empty() {
+            ui.label(format!(
+                "Scores every kernel on random {0}x{0} patches of the slide to pre-rank \
+                 the bank before a full run.",
+                quick::PATCH_SIZE
+            ));
+            return;
+        }
+        egui::ScrollArea::vertical()
+            .id_salt("quick_scores")
+            .max_height(160.0)
+            .show(ui, |ui| {
+                egui::Grid::new("quick_scores")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.strong("Rank");
+                        ui.strong("Kernel");
+                        ui.strong("Quick score");
+                        ui.end_row();
+                        for (rank, &(index, score)) in self.quick_scores.iter().enumerate() {
+                            ui.label((rank + 1).to_string());
+                            let name = self.kernels.get(index).map_or("", |k| k.name.as_str());
+                            if ui
+                                .selectable_label(
+                                    self.selected_kernel == index,
+                                    format!("{index} {name}"),
+                                )
+                                .clicked()
+                            {
+                                self.selected_kernel = index;
+                            }
+                            ui.label(fmt.format(score));
+                            ui.end_row();
+                        }
+                    });
+            });
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::DragValue::new(&mut self.quick_pin_count)
+                    .range(1..=self.quick_scores.len().max(1)),
+            );
+            if ui.button("Pin the top kernels").clicked() {
+                let top = self.quick_scores.iter().take(self.quick_pin_count);
+                self.pinned_kernels.extend(top.map(|&(index, _)| index));
+                self.status = format!(
+                    "Pinned the top {} kernels; a draft-mode run refines only them at full \
+                     resolution.",
+                    self.quick_pin_count.min(self.quick_scores.len())
+                );
+            }
+        });
+    }
+
+    /// Bar chart of the current scores with their 95% bootstrap intervals as
+    /// error bars; clicking a bar selects its kernel.
+    fn score_plot(&mut self, ui: &mut egui::Ui) {
+        let fmt = self.settings.number_format;
+        let width = ui.available_width().max(120.0);
+        let (response, painter) =
+            ui.allocate_painter(egui::vec2(width, SCORE_PLOT_HEIGHT), egui::Sense::click());
+        let rect = response.rect;
+        let enabled = |i: usize| self.kernels.get(i).is_some_and(|k| k.enabled);
+        let (lo, hi) = self
+            .previews
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| enabled(i))
+            .fold((0.0f32, 0.0f32), |(lo, hi), (_, p)| {
+                (
+                    lo.min(p.interval[0]).min(p.score),
+                    hi.max(p.interval[1]).max(p.score),
+                )
+            });
+        let span = (hi - lo).max(1e-6);
+        let y_of = |v: f32| rect.bottom() - (v - lo) / span * rect.height();
+        let count = self.previews.len().max(1);
+        let step = rect.width() / count as f32;
+        let visuals = ui.visuals();
+        let whisker = egui::Stroke::new(1.0, visuals.text_color());
+
+        painter.rect_filled(rect, 0.0, visuals.extreme_bg_color);
+        painter.hline(
+            rect.x_range(),
+            y_of(0.0),
+            visuals.widgets.noninteractive.bg_stroke,
+        );
+        // Muted kernels keep their slot, left empty, so bars stay at their index.
+        for (i, preview) in self.previews.iter().enumerate() {
+            if !enabled(i) {
+                continue;
+            }
+            let x0 = rect.left() + i as f32 * step;
+            let (top, base) = (y_of(preview.score), y_of(0.0));
+            let bar = egui::Rect::from_x_y_ranges(
+                x0 + step * 0.15..=x0 + step * 0.85,
+                top.min(base)..=top.max(base),
+            );
+            let fill = if i == self.selected_kernel {
+                visuals.selection.bg_fill
+            } else {
+                visuals.widgets.inactive.bg_fill
+            };
+            painter.rect_filled(bar, 0.0, fill);
+            let cx = x0 + step * 0.5;
+            let (upper, lower) = (y_of(preview.interval[1]), y_of(preview.interval[0]));
+            painter.vline(cx, upper..=lower, whisker);
+            painter.hline(cx - step * 0.2..=cx + step * 0.2, upper, whisker);
+            painter.hline(cx - step * 0.2..=cx + step * 0.2, lower, whisker);
+        }
+
+        let index_at = |x: f32| (((x - rect.left()) / step) as usize).min(count - 1);
+        if response.clicked()
+            && let Some(pos) = response.interact_pointer_pos()
+        {
+            self.selected_kernel = index_at(pos.x);
+        }
+        if let Some(preview) = response
+            .hover_pos()
+            .map(|pos| index_at(pos.x))
+            .filter(|&i| enabled(i))
+            .and_then(|i| self.previews.get(i))
+        {
+            response.on_hover_text(format!(
+                "Score {}, 95% interval {} to {}",
+                fmt.format(preview.score),
+                fmt.format(preview.interval[0]),
+                fmt.format(preview.interval[1])
+            ));
+        }
+    }
+
+    /// Whether the window is laid out compactly this frame, with the widget
+    /// spacing updated to match.
+    fn compact_layout(&mut self, ctx: &egui::Context) -> bool {
+        let compact = match self.settings.layout {
+            LayoutMode::Auto => ctx.screen_rect().width() < COMPACT_BELOW_WIDTH,
+            LayoutMode::Wide => false,
+            LayoutMode::Compact => true,
+        };
+        if self.touch_spacing != Some(compact) {
+            self.touch_spacing = Some(compact);
+            ctx.all_styles_mut(|style| {
+                style.spacing = if compact {
+                    touch_spacing()
+                } else {
+                    egui::style::Spacing::default()
+                };
+            });
+        }
+        compact
+    }
+
+    fn set_presentation(&mut self, ctx: &egui::Context, enabled: bool) {
+        self.presentation = enabled;
+        ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(enabled));
+    }
+
+    /// Slide and selected response side by side at the same scale, with a
+    /// one-line caption.
+    fn presentation_view(&mut self, ctx: &egui::Context) {
+        let fmt = self.settings.number_format;
+        let preview_tex = self.preview_texture(ctx, self.selected_kernel);
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let preview = self.previews.get(self.selected_kernel);
+            let caption = match preview {
+                Some(p) => format!(
+                    "{} | kernel {} | score {}    (F11 / Esc to exit)",
+                    self.slide.name,
+                    self.selected_kernel,
+                    fmt.format(p.score)
+                ),
+                None => format!("{}    (F11 / Esc to exit)", self.slide.name),
+            };
+            ui.vertical_centered(|ui| ui.label(caption));
+
+            let Some(slide_tex) = &self.slide.texture else {
+                ui.centered_and_justified(|ui| ui.label("Slide not loaded."));
+                return;
+            };
+
+            let aspect = slide_tex.size_vec2();
+            let panes = if preview_tex.is_some() { 2.0 } else { 1.0 };
+            let avail = ui.available_size();
+            let spacing = ui.spacing().item_spacing.x;
+            let scale = ((avail.x - spacing) / panes / aspect.x).min(avail.y / aspect.y);
+            let size = aspect * scale.max(0.0);
+            ui.horizontal_centered(|ui| {
+                ui.image((slide_tex.id(), size));
+                if let Some(tex) = &preview_tex {
+                    ui.image((tex.id(), size));
+                }
+            });
+        });
+    }
+
+    fn detached_viewports(&mut self, ctx: &egui::Context) {
+        if self.detached_preview {
+            ctx.show_viewport_immediate(
+                egui::ViewportId::from_hash_of("detached_preview"),
+                egui::ViewportBuilder::default()
+                    .with_title("Convolution preview")
+                    .with_inner_size([560.0, 620.0]),
+                |ctx, class| {
+                    let open = show_in_viewport(ctx, class, "Convolution preview", |ui| {
+                        self.detached_preview_contents(ui);
+                    });
+                    self.detached_preview &= open;
+                },
+            );
+        }
+        if self.detached_stats {
+            ctx.show_viewport_immediate(
+                egui::ViewportId::from_hash_of("detached_stats"),
+                egui::ViewportBuilder::default()
+                    .with_title("Kernel statistics")
+                    .with_inner_size([640.0, 480.0]),
+                |ctx, class| {
+                    let open = show_in_viewport(ctx, class, "Kernel statistics", |ui| {
+                        self.stats_table(ui);
+                    });
+                    self.detached_stats &= open;
+                },
+            );
+        }
+    }
+
+    fn detached_preview_contents(&mut self, ui: &mut egui::Ui) {
+        if self.previews.is_empty() {
+            ui.label("No convolution result yet.");
+            return;
+        }
+        self.selected_kernel = self.selected_kernel.min(self.previews.len() - 1);
+        ui.add(
+            egui::Slider::new(&mut self.selected_kernel, 0..=self.previews.len() - 1)
+                .text("Kernel index"),
+        );
+        let Some(tex) = self.preview_texture(ui.ctx(), self.selected_kernel) else {
+            return;
+        };
+        let preview = &self.previews[self.selected_kernel];
+        let size = tex.size_vec2();
+        let avail = ui.available_size();
+        let k = self
+            .run
+            .as_ref()
+            .map_or(self.settings.activation_k, |run| run.activation_k);
+        let caption = preview_caption(preview, k, self.settings.number_format);
+        let line = ui.text_style_height(&egui::TextStyle::Body) + ui.spacing().item_spacing.y;
+        let scale = (avail.x / size.x).min((avail.y - line) / size.y).max(0.0);
+        ui.image((tex.id(), size * scale));
+        ui.label(caption);
+    }
+
+    fn register_second_slide(&mut self) {
+        let (Some(fixed), Some(moving)) =
+            (self.slide.gray.as_ref(), self.second_slide.gray.as_ref())
+        else {
+            self.status = "Load both the slide and the second slide first.".to_owned();
+            return;
+        };
+        let started = Instant::now();
+        let transform = registration::register(
+            &gray_to_f32(fixed),
+            fixed.width() as usize,
+            fixed.height() as usize,
+            &gray_to_f32(moving),
+            moving.width() as usize,
+            moving.height() as usize,
+        );
+        self.status = format!(
+            "Registered second slide: shift ({:.0}, {:.0}) px, rotation {:.0}°, peak {:.3}.",
+            transform.shift[0], transform.shift[1], transform.angle_deg, transform.confidence
+        );
+        self.registration = Some(transform);
+        self.comparison = None;
+        self.settings
+            .usage
+            .record(Stage::Registration, started, None, 0);
+    }
+
+    fn compare_selected_kernel(&mut self, ctx: &egui::Context) {
+        let (Some(transform), Some(moving), Some(run)) = (
+            self.registration,
+            self.second_slide.gray.as_ref(),
+            self.run.as_ref(),
+        ) else {
+            self.status = "Run the convolutions and register the second slide first.".to_owned();
+            return;
+        };
+        let index = self.selected_kernel;
+        let Some((fixed_response, width, height)) = self.full_response(index) else {
+            return;
+        };
+        let (mw, mh) = (moving.width() as usize, moving.height() as usize);
+        let moving_input = self.slide_input(moving, self.second_slide.rgb.as_ref());
+        // The moving slide is convolved densely, with the run's dilation, and
+        // sampled at the run's stride once it lies over the fixed one.
+        let dilated = Sampling {
+            stride: 1,
+            ..run.sampling
+        };
+        let (mode, backend, border) = (run.mode, run.backend, run.border);
+        let moving_response = self.kernels[index].filter_sampled(
+            mode,
+            backend,
+            border,
+            dilated,
+            &moving_input,
+            mw,
+            mh,
+        );
+        let (full_width, full_height) = (run.width, run.height);
+        let registered = registration::warp_into(
+            &moving_response,
+            mw,
+            mh,
+            &transform,
+            full_width,
+            full_height,
+        );
+        let registered = run.sampling.subsample(&registered, full_width, full_height);
+
+        // A shared value range keeps both previews on the same intensity scale.
+        let (lo_a, hi_a) = min_max(&fixed_response);
+        let (lo_b, hi_b) = min_max(&registered);
+        let (lo, hi) = (lo_a.min(lo_b), hi_a.max(hi_b));
+        let texture = |name: &str, response: &[f32]| {
+            let (pw, ph) = preview_size(width, height, PREVIEW_MAX_SIZE);
+            let resized = resize_nearest(response, width, height, pw, ph);
+            let bytes = quantize(&resized, lo, hi);
+            ctx.load_texture(
+                name,
+                ColorImage::from_gray([pw, ph], &bytes),
+                TextureOptions::LINEAR,
+            )
+        };
+        self.comparison = Some(ComparisonView {
+            kernel: index,
+            transform,
+            scores: [
+                stats::response_stats(&fixed_response, &run.map_input, 0.0).mean_abs,
+                stats::response_stats(&registered, &run.map_input, 0.0).mean_abs,
+            ],
+            textures: [
+                texture("comparison_fixed", &fixed_response),
+                texture("comparison_moving", &registered),
+            ],
+        });
+    }
+
+    fn comparison_panel(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let fmt = self.settings.number_format;
+        ui.collapsing("Two-slide comparison", |ui| {
+            if self.second_slide.gray.is_none() {
+                ui.label("Drop a third PNG to load a second slide (e.g. a serial IHC section).");
+                return;
+            }
+            ui.label(format!("Second slide: {}", self.second_slide.name));
+            ui.horizontal(|ui| {
+                if ui.button("Register").clicked() {
+                    self.register_second_slide();
+                }
+                if ui.button("Compare selected kernel").clicked() {
+                    self.compare_selected_kernel(ctx);
+                }
+            });
+            if let Some(t) = &self.registration {
+                ui.label(format!(
+                    "Shift ({:.0}, {:.0}) px, rotation {:.0}°, peak {:.3}",
+                    t.shift[0], t.shift[1], t.angle_deg, t.confidence
+                ));
+            }
+            let Some(view) = &self.comparison else {
+                return;
+            };
+            if view.kernel != self.selected_kernel || Some(view.transform) != self.registration {
+                ui.label(format!(
+                    "Showing kernel {} (compare again to update).",
+                    view.kernel
+                ));
+            }
+            ui.columns(2, |columns| {
+                for (i, (column, caption)) in columns
+                    .iter_mut()
+                    .zip(["Slide", "Second slide (registered)"])
+                    .enumerate()
+                {
+                    let tex = &view.textures[i];
+                    let size = tex.size_vec2();
+                    let scale = (column.available_width() / size.x).min(1.0);
+                    column.image((tex.id(), size * scale));
+                    column.label(format!(
+                        "{caption}: mean |r| {}",
+                        fmt.format(view.scores[i])
+                    ));
+                }
+            });
+        });
+    }
+
+    /// Remembers the current slide's scores as the control of the screen.
+    fn set_control(&mut self) {
+        if self.previews.len() < self.kernels.len() || self.kernels.is_empty() {
+            self.status = "Run all kernels on the control slide first.".to_owned();
+            return;
+        }
+        let drafts = self.previews.iter().filter(|p| !p.exact).count();
+        self.control = Some(ControlSlide {
+            name: self.slide.name.clone(),
+            slide_hash: self.slide.hash,
+            sheet_hash: self.kernels_sheet.hash,
+            kernel_size: (self.kernel_shape.width(), self.kernel_shape.height()),
+            mode: self
+                .run
+                .as_ref()
+                .map_or(self.settings.filter_mode, |run| run.mode),
+            normalization: self.settings.score_normalization,
+            scores: self.previews.iter().map(|p| p.score).collect(),
+            mean_abs: self.previews.iter().map(|p| p.stats.mean_abs).collect(),
+            drafts,
+        });
+        self.control_ratio = None;
+        self.status = format!(
+            "{} is the control; drop the next slide after pressing Next slide.",
+            self.slide.name
+        );
+    }
+
+    /// Empties the slide slot but keeps the kernels sheet, so the next
+    /// dropped PNG becomes the slide.
+    fn next_slide(&mut self) {
+        self.slide = LoadedImage::default();
+        self.roi = None;
+        self.clear_results();
+        self.status = "Drop the next slide, then split the kernels and run.".to_owned();
+    }
+
+    /// Whether the current results can be compared with the control.
+    fn control_matches(&self) -> bool {
+        self.control.as_ref().is_some_and(|control| {
+            control.matches(
+                self.kernels_sheet.hash,
+                (self.kernel_shape.width(), self.kernel_shape.height()),
+                self.run
+                    .as_ref()
+                    .map_or(self.settings.filter_mode, |run| run.mode),
+                self.kernels.len(),
+            )
+        })
+    }
+
+    fn control_differences(&self) -> Vec<control::KernelDifference> {
+        let Some(control) = &self.control else {
+            return Vec::new();
+        };
+        let scores: Vec<f32> = self.previews.iter().map(|p| p.score).collect();
+        let mean_abs: Vec<f32> = self.previews.iter().map(|p| p.stats.mean_abs).collect();
+        control.differences(&scores, &mean_abs)
+    }
+
+    fn show_control_ratio(&mut self, ctx: &egui::Context) {
+        let index = self.selected_kernel;
+        let (Some(control), Some((response, width, height))) =
+            (self.control.as_ref(), self.full_response(index))
+        else {
+            return;
+        };
+        let ratio = control.log_ratio_map(index, &response);
+        let (pw, ph) = preview_size(width, height, PREVIEW_MAX_SIZE);
+        let resized = resize_nearest(&ratio, width, height, pw, ph);
+        let bytes = quantize(&resized, -RATIO_OCTAVES, RATIO_OCTAVES);
+        self.control_ratio = Some(ControlRatioView {
+            kernel: index,
+            texture: ctx.load_texture(
+                "control_ratio",
+                ColorImage::from_gray([pw, ph], &bytes),
+                TextureOptions::LINEAR,
+            ),
+        });
+    }
+
+    fn save_control_csv(&mut self) {
+        let Some(control) = &self.control else {
+            return;
+        };
+        let csv = self.settings.csv_format.apply(&control::differences_csv(
+            &control.name,
+            &self.control_differences(),
+            &self.kernels,
+        ));
+        self.status = match export::save_file(
+            &self.settings.export_dir,
+            "control_differences.csv",
+            csv.as_bytes(),
+        ) {
+            Ok(path) => format!("Saved differences to {path}."),
+            Err(e) => e,
+        };
+    }
+
+    fn control_panel(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let fmt = self.settings.number_format;
+        ui.collapsing("Control slide", |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Set as control").clicked() {
+                    self.set_control();
+                }
+                if ui
+                    .add_enabled(self.control.is_some(), egui::Button::new("Next slide"))
+                    .clicked()
+                {
+                    self.next_slide();
+                }
+            });
+            let Some(control) = &self.control else {
+                ui.label("Run the control slide and set it as control to compare later slides.");
+                return;
+            };
+            ui.label(format!(
+                "Control: {} ({} kernels, {} scored from drafts)",
+                control.name,
+                control.scores.len(),
+                control.drafts
+            ));
+            if control.slide_hash == self.slide.hash {
+                return;
+            }
+            if !self.control_matches() {
+                ui.label("The control was run with another kernels sheet, kernel shape or mode.");
+                return;
+            }
+            if control.normalization != self.settings.score_normalization {
+                ui.label(format!(
+                    "Control scores use {}; score differences mix normalizations.",
+                    control.normalization.label()
+                ));
+            }
+
+            let mut rows = self.control_differences();
+            rows.sort_by(|a, b| b.difference.abs().total_cmp(&a.difference.abs()));
+            egui::ScrollArea::vertical()
+                .max_height(240.0)
+                .show(ui, |ui| {
+                    egui::Grid::new("control_table").striped(true).show(ui, |ui| {
+                        for title in ["Kernel", "Score", "Control", "Difference", "|r| ratio"] {
+                            ui.strong(title);
+                        }
+                        ui.end_row();
+                        for row in &rows {
+                            let label = format!("{}", row.index);
+                            if ui
+                                .selectable_label(row.index == self.selected_kernel, label)
+                                .clicked()
+                            {
+                                self.selected_kernel = row.index;
+                            }
+                            ui.label(fmt.format(row.score));
+                            ui.label(fmt.format(row.control_score));
+                            let sign = if row.difference > 0.0 { "+" } else { "" };
+                            ui.label(format!("{sign}{}", fmt.format(row.difference)));
+                            ui.label(format!("{:.3}", row.ratio));
+                            ui.end_row();
+                        }
+                    });
+                });
+            ui.horizontal(|ui| {
+                if ui.button("Ratio map of selected kernel").clicked() {
+                    self.show_control_ratio(ctx);
+                }
+                if ui.button("Save differences CSV").clicked() {
+                    self.save_control_csv();
+                }
+            });
+            if let Some(view) = &self.control_ratio {
+                let tex = &view.texture;
+                let size = tex.size_vec2();
+                let scale = (ui.available_width() / size.x).min(1.0);
+                ui.image((tex.id(), size * scale));
+                ui.label(format!(
+                    "Kernel {}: |r| over the control's mean |r|, log scale from 1/{1} (black) to {1}x (white); mid grey matches the control.",
+                    view.kernel,
+                    2f32.powf(RATIO_OCTAVES)
+                ));
+            }
+        });
+    }
+
+    /// Every kernel's score and response metrics; clicking a header sorts by
+    /// that column and clicking a row selects the kernel.
+    fn score_table(&mut self, ui: &mut egui::Ui) {
+        let fmt = self.settings.number_format;
+        let (column, descending) = self.score_sort;
+        let mut order: Vec<usize> = (0..self.previews.len()).collect();
+        order.sort_by(|&a, &b| {
+            let (va, vb) = (
+                column.value(a, &self.previews[a]),
+                column.value(b, &self.previews[b]),
+            );
+            let ordering = va.total_cmp(&vb).then(a.cmp(&b));
+            if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+        egui::ScrollArea::vertical()
+            .id_salt("score_table")
+            .max_height(200.0)
+            .show(ui, |ui| {
+                egui::Grid::new("score_table").striped(true).show(ui, |ui| {
+                    for header in ScoreColumn::ALL {
+                        let label = match (header == column, descending) {
+                            (true, true) => format!("{} ⬇", header.label()),
+                            (true, false) => format!("{} ⬆", header.label()),
+                            (false, _) => header.label().to_owned(),
+                        };
+                        if ui
+                            .selectable_label(header == column, label)
+                            .on_hover_text(header.hover_text())
+                            .clicked()
+                        {
+                            // Metrics start with the largest value, the index
+                            // with the first kernel.
+                            self.score_sort = if header == column {
+                                (header, !descending)
+                            } else {
+                                (header, header != ScoreColumn::Kernel)
+                            };
+                        }
+                    }
+                    ui.end_row();
+                    for &i in &order {
+                        let preview = &self.previews[i];
+                        let label = if self.kernels[i].enabled {
+                            i.to_string()
+                        } else {
+                            format!("{i} (muted)")
+                        };
+                        if ui
+                            .selectable_label(self.selected_kernel == i, label)
+                            .clicked()
+                        {
+                            self.selected_kernel = i;
+                        }
+                        let score = ui.label(fmt.format(preview.score));
+                        if !preview.exact {
+                            score.on_hover_text("Draft score; selecting the kernel refines it.");
+                        }
+                        for metric in &ScoreColumn::ALL[2..] {
+                            ui.label(fmt.format(metric.value(i, preview)));
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+        ui.label(format!(
+            "Scores: {}",
+            self.settings.score_normalization.label()
+        ));
+    }
+
+    /// Settings the previews are currently colored with.
+    fn preview_look(&self) -> PreviewLook {
+        let overlay = self.settings.response_overlay && self.slide.gray.is_some();
+        PreviewLook {
+            calibration: self.settings.calibration,
+            colormap: if overlay {
+                self.settings.colormap
+            } else {
+                self.settings.preview_colormap
+            },
+            overlay: overlay.then(|| {
+                (
+                    self.slide.hash,
+                    self.run.as_ref().and_then(|run| run.roi),
+                    self.settings.response_overlay_opacity.to_bits(),
+                )
+            }),
+        }
+    }
+
+    /// The slide with its region and overlays, and the kernels sheet.
+    fn inputs_column(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        ui.heading("Input images");
+        if let Some(tex) = &self.slide.texture {
+            ui.label(format!("Slide: {}", self.slide.name));
+            ui.weak("Scroll or pinch to zoom, drag to pan, double-click to fit.");
+            let (texture, size) = (tex.id(), tex.size_vec2());
+            self.roi_controls(ui);
+            let fit = fit_scale(ui, size, 420.0);
+            let (image, rect) = zoomable_image(
+                ui,
+                texture,
+                size * fit,
+                &mut self.slide_view,
+                !self.selecting_roi && !self.moving_roi,
+            );
+            let (clip, scale) = (image.rect, fit * self.slide_view.zoom);
+            self.roi_overlay(ui, &image, rect, scale);
+            self.tile_heatmap_overlay(ui, image, rect, scale);
+            self.triage_outlines(ui, clip, rect, scale);
+            self.peak_markers(ui, clip, rect, scale);
+            self.tile_heatmap_panel(ui, ctx);
+            self.triage_panel(ui);
+            self.bookmarks_panel(ui);
+            self.peaks_panel(ui);
+        } else {
+            ui.label("Slide not loaded.");
+        }
+        ui.separator();
+        if let Some(tex) = &self.kernels_sheet.texture {
+            ui.label(format!("Kernels sheet: {}", self.kernels_sheet.name));
+            let size = tex.size_vec2();
+            let scale = fit_scale(ui, size, 420.0);
+            ui.image((tex.id(), size * scale));
+        } else if !self.kernels_sheet.name.is_empty() {
+            ui.label(format!("Kernel file: {}", self.kernels_sheet.name));
+        } else {
+            ui.label("Kernels sheet not loaded.");
+        }
+    }
+
+    /// The selected kernel's preview and the analyses drawn under it.
+    fn preview_column(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        ui.heading("Convolution preview");
+        if self.detached_preview {
+            ui.label("The preview is shown in a separate window.");
+            if ui.button("Reattach preview").clicked() {
+                self.detached_preview = false;
+            }
+        } else if let Some(tex) = self.preview_texture(ctx, self.selected_kernel) {
+            ui.horizontal(|ui| {
+                // The overlay is drawn in the colormap of the Colors
+                // section instead.
+                if !self.settings.response_overlay {
+                    egui::ComboBox::from_id_salt("preview_colormap")
+                        .selected_text(self.settings.preview_colormap.label())
+                        .show_ui(ui, |ui| {
+                            for colormap in Colormap::ALL {
+                                ui.selectable_value(
+                                    &mut self.settings.preview_colormap,
+                                    colormap,
+                                    colormap.label(),
+                                );
+                            }
+                        });
+                }
+                ui.checkbox(&mut self.settings.response_overlay, "Overlay on the slide");
+                ui.checkbox(&mut self.link_zoom, "Zoom with the slide");
+                if self.settings.response_overlay {
+                    ui.add(
+                        egui::Slider::new(&mut self.settings.response_overlay_opacity, 0.0..=1.0)
+                            .text("opacity"),
+                    );
+                    if self.slide.gray.is_none() {
+                        ui.label("(streamed slides have no pixels to draw it on)");
+                    }
+                }
+            });
+            let size = tex.size_vec2();
+            let fit = fit_scale(ui, size, 520.0);
+            // A region's maps do not line up with the whole slide.
+            let linked = self.link_zoom && self.run.as_ref().is_none_or(|r| r.roi.is_none());
+            let view = if linked {
+                &mut self.slide_view
+            } else {
+                &mut self.preview_view
+            };
+            let (image, rect) = zoomable_image(ui, tex.id(), size * fit, view, true);
+            let view = *view;
+            self.draw_zoom_detail(ui, &image, rect, view);
+            if image.clicked()
+                && let Some(pos) = image.interact_pointer_pos()
+            {
+                self.explain_pixel(pos, rect);
+            }
+            if let Some(run) = &self.run {
+                self.seams_overlay(ui, image, rect, (run.width, run.height));
+            }
+            let preview = &self.previews[self.selected_kernel];
+            let k = self
+                .run
+                .as_ref()
+                .map_or(self.settings.activation_k, |run| run.activation_k);
+            ui.label(preview_caption(preview, k, self.settings.number_format));
+            if preview.stats.non_finite > 0 {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    format!(
+                        "{} pixels of this map are NaN or infinite and left out of its \
+                         statistics. Repair NaN and Inf replaces non-finite inputs with 0.",
+                        preview.stats.non_finite
+                    ),
+                );
+            }
+            if let Some(run) = self.run.as_ref().filter(|run| run.repaired > 0) {
+                ui.weak(format!(
+                    "{} NaN or infinite input pixels were replaced with 0.",
+                    run.repaired
+                ));
+            }
+            ui.label(format!(
+                "Kernel {} preview size: {}x{} ({})",
+                self.selected_kernel,
+                preview.width,
+                preview.height,
+                if preview.exact { "exact" } else { "draft" }
+            ));
+            if ui.button("Pop out preview").clicked() {
+                self.detached_preview = true;
+            }
+            ui.collapsing("All previews", |ui| self.thumbnail_grid(ui, ctx));
+            self.autocorrelation_panel(ui, ctx);
+            self.seams_panel(ui);
+            self.comparison_panel(ui, ctx);
+            self.control_panel(ui, ctx);
+        } else {
+            ui.label("No convolution result yet.");
+        }
+    }
+
+    /// Texture of kernel `index`'s preview, colored and uploaded only when
+    /// the preview is new or its look changed.
+    fn preview_texture(&mut self, ctx: &egui::Context, index: usize) -> Option<TextureHandle> {
+        Some(self.preview_display(ctx, index)?.texture.clone())
+    }
+
+    /// Cached image and texture of kernel `index`'s preview, rebuilt when
+    /// the colormap or the overlay changed since they were made.
+    fn preview_display(
+        &mut self,
+        ctx: &egui::Context,
+        index: usize,
+    ) -> Option<&mut PreviewDisplay> {
+        let look = self.preview_look();
+        let cached = self.previews.get(index)?.display.as_ref();
+        if !cached.is_some_and(|d| d.look == look) {
+            let image = self.preview_image(index)?;
+            let texture = ctx.load_texture(
+                format!("preview_{index}"),
+                image.clone(),
+                TextureOptions::LINEAR,
+            );
+            self.previews[index].display = Some(PreviewDisplay {
+                look,
+                image,
+                texture,
+                thumbnail: None,
+            });
+        }
+        self.previews[index].display.as_mut()
+    }
+
+    /// Preview of kernel `index` in the preview colormap, or blended over
+    /// the slide in the overlay colormap when the response overlay is on
+    /// and the slide pixels are loaded.
+    fn preview_image(&mut self, index: usize) -> Option<ColorImage> {
+        let preview = self.previews.get(index)?;
+        let size = [preview.width, preview.height];
+        let slide = self
+            .slide
+            .gray
+            .as_ref()
+            .filter(|_| self.settings.response_overlay);
+        let Some(slide) = slide else {
+            let colormap = self.settings.preview_colormap;
+            let pixels = preview_positions(preview, colormap, self.settings.calibration)
+                .map(|t| shade(colormap, t, None))
+                .collect();
+            return Some(ColorImage { size, pixels });
+        };
+        let roi = self.run.as_ref().and_then(|run| run.roi);
+        let stale = self
+            .overlay_slide
+            .as_ref()
+            .is_none_or(|(hash, made_for, base)| {
+                *hash != self.slide.hash
+                    || *made_for != roi
+                    || [base.width() as usize, base.height() as usize] != size
+            });
+        if stale {
+            let cropped = roi.map(|roi| roi.crop(slide));
+            let slide = cropped.as_ref().unwrap_or(slide);
+            let base = image::imageops::thumbnail(slide, size[0] as u32, size[1] as u32);
+            self.overlay_slide = Some((self.slide.hash, roi, base));
+        }
+        let (_, _, base) = self.overlay_slide.as_ref()?;
+        let opacity = self.settings.response_overlay_opacity;
+        let colormap = self.settings.colormap;
+        let pixels = base
+            .as_raw()
+            .iter()
+            .zip(preview_positions(
+                preview,
+                colormap,
+                self.settings.calibration,
+            ))
+            .map(|(&gray, t)| shade(colormap, t, Some((gray, opacity))))
+            .collect();
+        Some(ColorImage { size, pixels })
+    }
+
+    /// Redraws the part of the selected kernel's map in view from its
+    /// full-resolution response once the preview viewer `image` is zoomed in
+    /// beyond the preview's own pixels; `rect` is where the whole map spans.
+    fn draw_zoom_detail(
+        &mut self,
+        ui: &egui::Ui,
+        image: &egui::Response,
+        rect: egui::Rect,
+        view: ZoomPan,
+    ) {
+        let index = self.selected_kernel;
+        let (Some(preview), Some(run)) = (self.previews.get(index), self.run.as_ref()) else {
+            return;
+        };
+        if rect.width() <= preview.width as f32 {
+            return;
+        }
+        let (width, height) = run.map_size();
+        let (left, top) = run.roi.map_or((0, 0), |roi| (roi.x, roi.y));
+        let (slide_width, slide_height, stride) = (run.width, run.height, run.sampling.stride);
+        let calibration = self.settings.calibration;
+        let range = calibration.display_range(preview.range);
+        let visible = view.visible();
+        let low = |t: f32, n: usize| ((t * n as f32).floor() as usize).min(n - 1);
+        let high = |t: f32, n: usize| ((t * n as f32).ceil() as usize).clamp(1, n);
+        let region = [
+            low(visible.min.x, width),
+            low(visible.min.y, height),
+            high(visible.max.x, width),
+            high(visible.max.y, height),
+        ];
+        let [x0, y0, x1, y1] = region;
+        let (region_width, region_height) = (x1.saturating_sub(x0), y1.saturating_sub(y0));
+        if region_width == 0 || region_height == 0 {
+            return;
+        }
+        let size = [
+            region_width.min(image.rect.width().ceil() as usize),
+            region_height.min(image.rect.height().ceil() as usize),
+        ];
+        let slide = self.slide.gray.as_ref().filter(|g| {
+            self.settings.response_overlay
+                && g.width() as usize >= left + slide_width
+                && g.height() as usize >= top + slide_height
+        });
+        let key = DetailKey {
+            region,
+            size,
+            preview_colormap: self.settings.preview_colormap,
+            colormap: self.settings.colormap,
+            overlay: slide.map(|_| self.settings.response_overlay_opacity.to_bits()),
+            calibration,
+        };
+
+        if self.zoom_detail.as_ref().is_none_or(|d| d.kernel != index) {
+            let Some((response, width, height)) = self.full_response(index) else {
+                return;
+            };
+            self.zoom_detail = Some(ZoomDetail {
+                kernel: index,
+                response,
+                width,
+                height,
+                drawn: None,
+            });
+        }
+        let Some(detail) = self.zoom_detail.as_mut() else {
             return;
         };
-        if self.kernels.is_empty() {
-            self.status = "Split kernels first.".to_owned();
+        if (detail.width, detail.height) != (width, height) {
             return;
         }
+        if detail.drawn.as_ref().is_none_or(|(drawn, _)| *drawn != key) {
+            let (colormap, opacity) = match key.overlay {
+                Some(_) => (key.colormap, self.settings.response_overlay_opacity),
+                None => (key.preview_colormap, 1.0),
+            };
+            let mut pixels = Vec::with_capacity(size[0] * size[1]);
+            for oy in 0..size[1] {
+                let y = y0 + oy * region_height / size[1];
+                for ox in 0..size[0] {
+                    let x = x0 + ox * region_width / size[0];
+                    let value = calibration.apply(detail.response[y * width + x]);
+                    let t = colormap.position(value, range);
+                    let under = slide.map(|g| {
+                        (
+                            g.get_pixel((left + x * stride) as u32, (top + y * stride) as u32)
+                                .0[0],
+                            opacity,
+                        )
+                    });
+                    pixels.push(shade(colormap, t, under));
+                }
+            }
+            let texture = ui.ctx().load_texture(
+                "preview_detail",
+                ColorImage { size, pixels },
+                TextureOptions::NEAREST,
+            );
+            detail.drawn = Some((key, texture));
+        }
+        let Some((_, texture)) = &detail.drawn else {
+            return;
+        };
+        let scale = egui::vec2(rect.width() / width as f32, rect.height() / height as f32);
+        let shown = egui::Rect::from_min_max(
+            rect.min + egui::vec2(x0 as f32, y0 as f32) * scale,
+            rect.min + egui::vec2(x1 as f32, y1 as f32) * scale,
+        );
+        let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+        ui.painter().with_clip_rect(image.rect).image(
+            texture.id(),
+            shown,
+            uv,
+            egui::Color32::WHITE,
+        );
+    }
 
-        let input = gray_to_f32(slide);
-        let width = slide.width() as usize;
-        let height = slide.height() as usize;
-        let kw = self.kernel_shape.width();
-        let kh = self.kernel_shape.height();
+    /// Thumbnail of kernel `index`'s preview, shrunk from its cached image.
+    fn thumbnail(&mut self, ctx: &egui::Context, index: usize) -> Option<TextureHandle> {
+        let display = self.preview_display(ctx, index)?;
+        if let Some(thumbnail) = &display.thumbnail {
+            return Some(thumbnail.clone());
+        }
+        let [full_width, full_height] = display.image.size;
+        let (width, height) = preview_size(full_width, full_height, THUMBNAIL_SIZE);
+        let pixels = (0..width * height)
+            .map(|i| {
+                let x = i % width * full_width / width;
+                let y = i / width * full_height / height;
+                display.image.pixels[y * full_width + x]
+            })
+            .collect();
+        let texture = ctx.load_texture(
+            format!("thumbnail_{index}"),
+            ColorImage {
+                size: [width, height],
+                pixels,
+            },
+            TextureOptions::LINEAR,
+        );
+        display.thumbnail = Some(texture.clone());
+        Some(texture)
+    }
 
-        self.previews.clear();
-        self.previews.reserve(self.kernels.len());
+    /// Every kernel's preview with its score; clicking one shows it in the
+    /// large view.
+    fn thumbnail_grid(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let fmt = self.settings.number_format;
+        egui::ScrollArea::vertical()
+            .id_salt("thumbnails")
+            .max_height(320.0)
+            .show(ui, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    for index in 0..self.previews.len() {
+                        let Some(texture) = self.thumbnail(ctx, index) else {
+                            continue;
+                        };
+                        let selected = index == self.selected_kernel;
+                        let kernel = self.kernels.get(index);
+                        let enabled = kernel.is_none_or(|k| k.enabled);
+                        let name = kernel.map_or("", |k| k.name.as_str());
+                        let score = self.previews[index].score;
+                        let clicked = ui
+                            .vertical(|ui| {
+                                let button = ui
+                                    .add(
+                                        egui::ImageButton::new((texture.id(), texture.size_vec2()))
+                                            .selected(selected),
+                                    )
+                                    .on_hover_text(format!("Kernel {index} {name}"));
+                                let caption = format!("{index}: {}", fmt.format(score));
+                                if enabled {
+                                    ui.small(caption);
+                                } else {
+                                    ui.weak(format!("{caption} (muted)"));
+                                }
+                                button.clicked()
+                            })
+                            .inner;
+                        if clicked {
+                            self.selected_kernel = index;
+                        }
+                    }
+                });
+            });
+    }
 
-        for kernel in &self.kernels {
-            let response = convolve_same(&input, width, height, kernel, kw, kh);
-            let score = response.iter().map(|v| v.abs()).sum::<f32>() / response.len() as f32;
-            let (pw, ph, bytes) = build_preview(&response, width, height, PREVIEW_MAX_SIZE);
-            self.previews.push(ConvolutionPreview {
-                score,
-                width: pw,
-                height: ph,
-                bytes,
+    fn stats_table(&mut self, ui: &mut egui::Ui) {
+        let fmt = self.settings.number_format;
+        ui.horizontal(|ui| {
+            ui.label("Active if |r - mean| >");
+            ui.add(
+                egui::DragValue::new(&mut self.settings.activation_k)
+                    .range(0.0..=10.0)
+                    .speed(0.05),
+            );
+            ui.label("std (applies on next run)");
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Check all").clicked() {
+                self.checked_kernels = (0..self.previews.len()).collect();
+            }
+            if ui.button("Check pinned").clicked() {
+                self.checked_kernels = self.pinned_kernels.clone();
+            }
+            if ui.button("Check none").clicked() {
+                self.checked_kernels.clear();
+            }
+            if ui.button("Mute checked").clicked() {
+                self.set_checked_enabled(false);
+            }
+            if ui.button("Unmute checked").clicked() {
+                self.set_checked_enabled(true);
+            }
+        });
+        egui::ScrollArea::vertical()
+            .max_height(240.0)
+            .show(ui, |ui| {
+                egui::Grid::new("stats_table").striped(true).show(ui, |ui| {
+                    ui.strong("Run");
+                    ui.strong("Kernel");
+                    ui.strong("Mean");
+                    ui.strong("Mean |r|");
+                    ui.strong("Std");
+                    ui.strong("Active %");
+                    ui.strong("Gini");
+                    ui.strong("H (bits)");
+                    ui.strong("MI (bits)");
+                    ui.strong("Tissue |r|");
+                    ui.strong("Glass |r|");
+                    ui.strong("p");
+                    ui.strong("Revision");
+                    ui.end_row();
+                    for (i, preview) in self.previews.iter().enumerate() {
+                        let mut checked = self.checked_kernels.contains(&i);
+                        if ui.checkbox(&mut checked, "").changed() {
+                            if checked {
+                                self.checked_kernels.insert(i);
+                            } else {
+                                self.checked_kernels.remove(&i);
+                            }
+                        }
+                        let label = if self.kernels[i].enabled {
+                            i.to_string()
+                        } else {
+                            format!("{i} (muted)")
+                        };
+                        if ui
+                            .selectable_label(self.selected_kernel == i, label)
+                            .clicked()
+                        {
+                            self.selected_kernel = i;
+                        }
+                        ui.label(fmt.format(preview.stats.mean));
+                        ui.label(fmt.format(preview.stats.mean_abs));
+                        ui.label(fmt.format(preview.stats.std_dev));
+                        ui.label(format!("{:.2}", preview.stats.activation_rate * 100.0));
+                        ui.label(format!("{:.3}", preview.stats.gini));
+                        ui.label(format!("{:.2}", preview.stats.entropy));
+                        let mi = ui.label(format!("{:.2}", preview.stats.mutual_information));
+                        mi.on_hover_text(format!(
+                            "{:.0}% of the input entropy; values near 100% mean the kernel mostly reproduces brightness.",
+                            preview.stats.brightness_share * 100.0
+                        ));
+                        ui.label(fmt.format(preview.stats.tissue_mean_abs));
+                        ui.label(fmt.format(preview.stats.background_mean_abs));
+                        match preview.significance {
+                            Some(s) => ui
+                                .label(fmt.format(s.p_value))
+                                .on_hover_text(format!(
+                                    "z = {:.2} over {} weight shuffles",
+                                    s.z_score, s.permutations
+                                )),
+                            None => ui.label("-"),
+                        };
+                        let change = self.revision_diff.as_ref().and_then(|d| d.changes.get(i));
+                        match change {
+                            Some(KernelChange::Changed { previous }) => ui.colored_label(
+                                ui.visuals().warn_fg_color,
+                                format!("changed (was {previous})"),
+                            ),
+                            Some(KernelChange::Added) => {
+                                ui.colored_label(ui.visuals().error_fg_color, "added")
+                            }
+                            Some(KernelChange::Unchanged { previous }) if *previous != i => {
+                                ui.label(format!("moved (was {previous})"))
+                            }
+                            _ => ui.label(""),
+                        };
+                        ui.end_row();
+                    }
+                });
             });
+        if let Some(diff) = self
+            .revision_diff
+            .as_ref()
+            .filter(|d| !d.removed.is_empty())
+        {
+            let removed: Vec<String> = diff.removed.iter().map(|i| i.to_string()).collect();
+            ui.label(format!(
+                "Removed since the previous sheet: {}",
+                removed.join(", ")
+            ));
         }
-
-        self.status = format!("Computed {} convolution maps.", self.previews.len());
     }
 }
 
 impl eframe::App for ConvolutionApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, eframe::APP_KEY, &self.settings);
+        let selected = (!self.kernels.is_empty())
+            .then_some((self.kernels_sheet.hash, self.selected_kernel))
+            .or(self.restored_selection);
+        let ui_state = UiState {
+            kernel_shape: self.kernel_shape,
+            selected_kernel: selected,
+            open_dir: self.open_dir.clone(),
+        };
+        eframe::set_value(storage, UI_STATE_KEY, &ui_state);
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         self.handle_dropped_files(ctx);
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.poll_launches(ctx);
+            self.poll_restore(ctx);
+        }
+        if self.apply_pending_edit()
+            || self.refine_next_draft()
+            || self.test_next_significance()
+            || self.poll_benchmark()
+            || self.poll_self_test()
+        {
+            ctx.request_repaint();
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.stream_next_step() {
+            ctx.request_repaint();
+        }
+        #[cfg(target_arch = "wasm32")]
+        if self.gpu.update() || self.poll_gpu_run() || self.poll_chunked_run() {
+            ctx.request_repaint();
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.gpu.update(self.settings.use_gpu) || self.poll_gpu_run() || self.poll_cpu_run() {
+            ctx.request_repaint();
+        }
+        if self.poll_gallery(ctx) || self.poll_file_pick(ctx) {
+            ctx.request_repaint();
+        }
+        self.update_title(ctx);
+        #[cfg(not(target_arch = "wasm32"))]
+        self.hold_close(ctx);
+        self.discard_window(ctx);
+
+        let toggle = ctx.input(|i| {
+            i.key_pressed(egui::Key::F11) || (self.presentation && i.key_pressed(egui::Key::Escape))
+        });
+        if toggle {
+            self.set_presentation(ctx, !self.presentation);
+        }
+        if self.presentation {
+            self.presentation_view(ctx);
+            return;
+        }
+        let compact = self.compact_layout(ctx);
+        self.detached_viewports(ctx);
+        self.about_window(ctx);
+        self.teaching_window(ctx);
+        self.explanation_window(ctx);
 
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
-            ui.heading("WASM Convolution Explorer");
-            ui.label("Drop PNG files in order: 1) lame histologique 2) kernels sheet.");
+            ui.horizontal_wrapped(|ui| {
+                if compact {
+                    ui.toggle_value(&mut self.controls_open, "Controls");
+                }
+                ui.heading(APP_TITLE);
+                if ui.button("About").clicked() {
+                    self.about_open = true;
+                }
+                ui.toggle_value(&mut self.teaching.open, "Teaching mode")
+                    .on_hover_text("Step through a kernel convolving a few pixels of the slide.");
+                egui::ComboBox::from_label("Layout")
+                    .selected_text(self.settings.layout.label())
+                    .show_ui(ui, |ui| {
+                        for layout in LayoutMode::ALL {
+                            ui.selectable_value(&mut self.settings.layout, layout, layout.label());
+                        }
+                    })
+                    .response
+                    .on_hover_text(
+                        "Compact collapses the controls, stacks the images and enlarges \
+                         widgets for touch; Auto switches to it on narrow screens.",
+                    );
+            });
+            if !compact {
+                ui.label("Drop PNG files in order: 1) lame histologique 2) kernels sheet 3) optional second slide, or choose where they go:");
+            }
+            self.slot_bar(ui);
             ui.label(format!("Status: {}", self.status));
         });
 
-        egui::SidePanel::left("controls").show(ctx, |ui| {
-            ui.group(|ui| {
-                ui.label("Kernel shape");
-                ui.radio_value(
-                    &mut self.kernel_shape,
-                    KernelShape::ThreeBySix,
-                    KernelShape::ThreeBySix.label(),
+        let controls = if compact {
+            let width = (ctx.screen_rect().width() - 24.0).max(160.0);
+            egui::SidePanel::left("controls")
+                .resizable(false)
+                .exact_width(width)
+        } else {
+            egui::SidePanel::left("controls")
+        };
+        controls.show_animated(ctx, !compact || self.controls_open, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+            let mismatch = self.kernel_shape_panel(ui);
+            if ui
+                .add_enabled(mismatch.is_none(), egui::Button::new("Split kernels"))
+                .clicked()
+            {
+                if self.unsaved.any() {
+                    self.pending_discard = Some(Discard::Split);
+                } else {
+                    self.split_kernels();
+                }
+            }
+            egui::ComboBox::from_label("Normalize tiles")
+                .selected_text(self.settings.kernel_normalization.label())
+                .show_ui(ui, |ui| {
+                    for normalization in KernelNormalization::ALL {
+                        ui.selectable_value(
+                            &mut self.settings.kernel_normalization,
+                            normalization,
+                            normalization.label(),
+                        );
+                    }
+                })
+                .response
+                .on_hover_text(
+                    "Applied when the sheet is split, so every kernel scores on the same \
+                     footing; the manifest records it.",
+                );
+            ui.collapsing("Kernel presets", |ui| self.presets_panel(ui));
+            ui.collapsing("Example kernels", |ui| self.gallery_panel(ui));
+            ui.checkbox(
+                &mut self.settings.draft_mode,
+                "Draft mode (1/4 resolution first)",
+            );
+            egui::ComboBox::from_label("Mode")
+                .selected_text(self.settings.filter_mode.label())
+                .show_ui(ui, |ui| {
+                    for mode in FilterMode::ALL {
+                        ui.selectable_value(&mut self.settings.filter_mode, mode, mode.label());
+                    }
+                })
+                .response
+                .on_hover_text(
+                    "Correlation weights pixels with the kernel as laid out, as CNNs do; \
+                     convolution flips it in both axes first. Normalized cross-correlation \
+                     matches the kernel as a template, scoring -1 to 1 in bright and dark \
+                     regions alike. Median, min and max use the kernel's support (its mask, \
+                     or the whole window) and ignore its weights.",
+                );
+            self.border_combo(ui);
+            self.sampling_controls(ui);
+            egui::ComboBox::from_label("Channels")
+                .selected_text(self.settings.channel_mode.label())
+                .show_ui(ui, |ui| {
+                    for mode in ChannelMode::ALL {
+                        ui.selectable_value(&mut self.settings.channel_mode, mode, mode.label());
+                    }
+                })
+                .response
+                .on_hover_text(
+                    "Color channels of color slides that kernels run over. Per channel \
+                     averages each kernel's responses to red, green and blue; all channels \
+                     convolves each with the matching channel of a color kernels sheet. \
+                     Stain normalization applies to luma only, and multi-channel runs stay \
+                     on the CPU.",
                 );
-                ui.radio_value(
-                    &mut self.kernel_shape,
-                    KernelShape::SixByThree,
-                    KernelShape::SixByThree.label(),
+            ui.checkbox(&mut self.settings.repair_non_finite, "Repair NaN and Inf")
+                .on_hover_text(
+                    "Replace NaN and infinite input pixels and kernel weights with 0 before \
+                     each run. Without it, runs refuse such weights and leave such pixels out \
+                     of the statistics.",
                 );
+            ui.collapsing("Stain normalization", |ui| self.stain_panel(ui));
+            ui.horizontal(|ui| {
+                if ui.button("Run all convolutions").clicked() {
+                    self.run_all_convolutions();
+                }
+                let checked = self.checked_kernels.len();
+                if ui
+                    .add_enabled(
+                        checked > 0,
+                        egui::Button::new(format!("Run selected ({checked})")),
+                    )
+                    .on_hover_text(
+                        "Recompute only the kernels ticked in the statistics table, at full \
+                         resolution, keeping the other results of the current run.",
+                    )
+                    .clicked()
+                {
+                    self.run_selected();
+                }
             });
-
-            if ui.button("Split kernels").clicked() {
-                self.split_kernels();
+            self.run_progress_bar(ui);
+            ui.collapsing("Quick score", |ui| self.quick_score_panel(ui));
+            let before = self.settings.score_normalization;
+            egui::ComboBox::from_label("Score")
+                .selected_text(before.label())
+                .show_ui(ui, |ui| {
+                    for normalization in ScoreNormalization::ALL {
+                        ui.selectable_value(
+                            &mut self.settings.score_normalization,
+                            normalization,
+                            normalization.label(),
+                        );
+                    }
+                });
+            if self.settings.score_normalization != before {
+                self.rescore();
             }
-            if ui.button("Run all convolutions").clicked() {
-                self.run_all_convolutions();
+            ui.horizontal(|ui| {
+                let toggle = ui.checkbox(
+                    &mut self.settings.notify_on_completion,
+                    "Notify after runs over",
+                );
+                if toggle.changed() && self.settings.notify_on_completion {
+                    notify::request_permission();
+                }
+                ui.add(
+                    egui::DragValue::new(&mut self.settings.notify_min_seconds)
+                        .range(0.0..=3600.0)
+                        .suffix(" s"),
+                );
+            });
+            if ui.button("Presentation mode (F11)").clicked() {
+                self.set_presentation(ctx, true);
             }
             if ui.button("Reset").clicked() {
-                *self = Self::default();
+                if self.unsaved.any() {
+                    self.pending_discard = Some(Discard::Reset);
+                } else {
+                    self.reset();
+                }
             }
 
-            ui.separator();
-            ui.label(format!(
-                "Kernels: {} ({} rows x {} cols)",
-                self.kernels.len(),
-                self.kernel_rows,
-                self.kernel_cols
-            ));
+            ui.collapsing("Profiles", |ui| self.profiles_panel(ui));
+            ui.collapsing("Number format", |ui| self.number_format_panel(ui));
+            ui.collapsing("Colors", |ui| self.colors_panel(ui));
+            ui.collapsing("Calibration", |ui| self.calibration_panel(ui));
 
-            if !self.previews.is_empty() {
-                self.selected_kernel = self
-                    .selected_kernel
-                    .min(self.previews.len().saturating_sub(1));
+            ui.collapsing("Exports", |ui| {
+                #[cfg(not(target_arch = "wasm32"))]
+                ui.horizontal(|ui| {
+                    ui.label("Export folder");
+                    ui.text_edit_singleline(&mut self.settings.export_dir);
+                });
+                ui.label("After every run:");
+                ui.checkbox(&mut self.settings.autosave_scores, "Save scores CSV");
+                ui.label("A run manifest is saved along with any autosaved file.");
                 ui.add(
-                    egui::Slider::new(&mut self.selected_kernel, 0..=self.previews.len() - 1)
-                        .text("Kernel index"),
+                    egui::Slider::new(&mut self.settings.autosave_top_maps, 0..=50)
+                        .text("top maps as PNG"),
                 );
-                let score = self.previews[self.selected_kernel].score;
-                ui.label(format!("Selected score (mean abs response): {:.5}", score));
+                self.map_name_field(ui);
+                ui.horizontal(|ui| {
+                    if ui.button("Export selected map").clicked() {
+                        self.export_maps(&[self.selected_kernel]);
+                    }
+                    if ui
+                        .button("Export all maps")
+                        .on_hover_text("Every enabled kernel, at full resolution.")
+                        .clicked()
+                    {
+                        let enabled: Vec<usize> = (0..self.previews.len())
+                            .filter(|&i| self.kernels[i].enabled)
+                            .collect();
+                        self.export_maps(&enabled);
+                    }
+                });
+                ui.checkbox(&mut self.settings.export_raw_tiff, "Also raw f32 TIFF");
+                let sparse = self.run.as_ref().is_some_and(|run| run.sparse.is_some());
+                if ui
+                    .add_enabled(sparse, egui::Button::new("Export active pixels"))
+                    .on_hover_text(
+                        "The pixels sparse maps kept for every enabled kernel, one row each, \
+                         at slide positions.",
+                    )
+                    .on_disabled_hover_text("Run with sparse maps on to keep active pixels.")
+                    .clicked()
+                {
+                    self.export_active_pixels();
+                }
+                let csv = &mut self.settings.csv_format;
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_label("CSV delimiter")
+                        .selected_text(csv.delimiter.label())
+                        .show_ui(ui, |ui| {
+                            for delimiter in CsvDelimiter::ALL {
+                                ui.selectable_value(&mut csv.delimiter, delimiter, delimiter.label());
+                            }
+                        });
+                    egui::ComboBox::from_label("decimals")
+                        .selected_text(csv.decimal.label())
+                        .show_ui(ui, |ui| {
+                            for decimal in DecimalSeparator::ALL {
+                                ui.selectable_value(&mut csv.decimal, decimal, decimal.label());
+                            }
+                        });
+                });
+                ui.collapsing("Figures", |ui| self.figures_panel(ui));
+                if ui
+                    .button("Export pipeline JSON")
+                    .on_hover_text("Run it headless with --pipeline <file>; drop it here to load it back.")
+                    .clicked()
+                {
+                    self.export_pipeline();
+                }
+                if ui.button("Save run manifest").clicked() {
+                    let file_name = format!("run_{}_manifest.json", unix_timestamp());
+                    self.status = match self.save_manifest(&file_name) {
+                        Ok(dest) => format!("Saved manifest to {dest}."),
+                        Err(e) => format!("Manifest export failed: {e}"),
+                    };
+                }
+                ui.collapsing("Session", |ui| self.session_panel(ui));
+                ui.collapsing("Share link", |ui| self.share_panel(ui));
+            });
+            if self.shared.is_some() {
+                ui.collapsing("Shared run", |ui| self.shared_run_panel(ui));
             }
-        });
 
-        egui::CentralPanel::default().show(ctx, |ui| {
-            ui.columns(2, |columns| {
-                columns[0].heading("Input images");
-                if let Some(tex) = &self.slide.texture {
-                    columns[0].label(format!("Slide: {}", self.slide.name));
-                    let size = tex.size_vec2();
-                    let scale = (420.0 / size.x.max(size.y)).min(1.0);
-                    columns[0].image((tex.id(), size * scale));
+            ui.collapsing("Performance", |ui| {
+                ui.checkbox(
+                    &mut self.settings.strict_reproducibility,
+                    "Strict reproducibility",
+                )
+                .on_hover_text(
+                    "Scalar backend only, with its fixed summation order. Recorded in the manifest.",
+                );
+                let kw = self.kernel_shape.width();
+                let kh = self.kernel_shape.height();
+                egui::ComboBox::from_label("Backend")
+                    .selected_text(self.settings.backend_choice.label())
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(
+                            &mut self.settings.backend_choice,
+                            BackendChoice::Auto,
+                            BackendChoice::Auto.label(),
+                        );
+                        for backend in Backend::ALL {
+                            ui.selectable_value(
+                                &mut self.settings.backend_choice,
+                                BackendChoice::Fixed(backend),
+                                backend.label(),
+                            );
+                        }
+                    })
+                    .response
+                    .on_hover_text(format!(
+                        "Auto picks the backend the benchmark expects to be fastest at the \
+                         slide's size, scaling direct timings with the pixel count and the \
+                         FFT's with n log n. Until a kernel size is benchmarked, it switches to \
+                         the FFT for kernels of at least {} taps once pixels x taps reaches {}.",
+                        backend::FFT_MIN_TAPS,
+                        backend::FFT_MIN_WORK
+                    ));
+                if let Some(profile) = self.settings.profile_for(kw, kh) {
+                    ui.label(format!(
+                        "Fastest for {}x{}: {}",
+                        kw,
+                        kh,
+                        profile.fastest.label()
+                    ));
+                    for (backend, ms) in &profile.timings {
+                        ui.label(format!("  {}: {:.2} ms", backend.label(), ms));
+                    }
+                    if let Some(ms) = profile.gpu {
+                        ui.label(format!("  GPU: {ms:.2} ms"));
+                    }
+                } else if self.benchmark.is_some() {
+                    ui.label(format!("Benchmarking {}x{} kernels...", kw, kh));
                 } else {
-                    columns[0].label("Slide not loaded.");
+                    ui.label(format!("No benchmark yet for {}x{} kernels.", kw, kh));
                 }
-                columns[0].separator();
-                if let Some(tex) = &self.kernels_sheet.texture {
-                    columns[0].label(format!("Kernels sheet: {}", self.kernels_sheet.name));
-                    let size = tex.size_vec2();
-                    let scale = (420.0 / size.x.max(size.y)).min(1.0);
-                    columns[0].image((tex.id(), size * scale));
-                } else {
-                    columns[0].label("Kernels sheet not loaded.");
+                if ui.button("Run benchmark").clicked() {
+                    self.rerun_benchmark();
+                }
+                #[cfg(target_arch = "wasm32")]
+                ui.label(format!("Browser GPU: {}", self.gpu.label()));
+                #[cfg(not(target_arch = "wasm32"))]
+                ui.label(format!("GPU: {}", self.gpu.label()));
+                ui.horizontal(|ui| {
+                    ui.label("Exact runs on");
+                    ui.selectable_value(&mut self.settings.use_gpu, false, "CPU");
+                    ui.selectable_value(&mut self.settings.use_gpu, true, "GPU")
+                        .on_hover_text(
+                            "Falls back to the CPU backend above when no GPU path is available, \
+                             and with the Auto backend when the benchmark measured the CPU \
+                             faster. GPU maps agree with the CPU up to float rounding.",
+                        );
+                });
+                #[cfg(not(target_arch = "wasm32"))]
+                if ui
+                    .add_enabled(
+                        self.gpu.convolver().is_some(),
+                        egui::Button::new("Compare CPU and GPU"),
+                    )
+                    .on_hover_text(
+                        "Times the selected kernel on both and reports the largest difference \
+                         between their maps.",
+                    )
+                    .clicked()
+                {
+                    self.compare_gpu();
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                ui.checkbox(
+                    &mut self.settings.maps_on_disk,
+                    "Keep full maps in temporary files",
+                )
+                .on_hover_text(
+                    "Exports and analyses read exact maps back instead of convolving again. \
+                     The files are memory-mapped, so they do not have to fit in RAM.",
+                );
+                ui.checkbox(
+                    &mut self.settings.sparse_maps,
+                    "Keep only the maps' active pixels",
+                )
+                .on_hover_text(
+                    "Keeps the pixels beyond the run's activation threshold, 8 bytes each, \
+                     in place of the full maps, for banks too big to keep whole. Active pixels \
+                     export as a list; map exports and analyses convolve again.",
+                );
+                if let Some(store) = self.run.as_ref().and_then(|run| run.sparse.as_ref()) {
+                    let (maps, pixels) = store.count();
+                    let (width, height) = self.run.as_ref().map_or((0, 0), RunContext::map_size);
+                    ui.label(format!(
+                        "{pixels} active pixels of {maps} maps: {:.1} MB, {:.1} MB as full maps",
+                        store.bytes() as f64 / 1e6,
+                        (maps * width * height * 4) as f64 / 1e6
+                    ));
                 }
+                #[cfg(not(target_arch = "wasm32"))]
+                ui.collapsing("Large slides", |ui| self.large_slides_panel(ui));
+                #[cfg(target_arch = "wasm32")]
+                ui.collapsing("Browser storage", |ui| self.browser_storage_panel(ui));
+                ui.collapsing("Usage statistics", |ui| self.usage_panel(ui));
+            });
 
-                columns[1].heading("Convolution preview");
-                if let Some(preview) = self.previews.get(self.selected_kernel) {
-                    let color = ColorImage::from_gray([preview.width, preview.height], &preview.bytes);
-                    let tex = ctx.load_texture(
-                        format!("preview_{}", self.selected_kernel),
-                        color,
-                        TextureOptions::LINEAR,
-                    );
-                    let size = tex.size_vec2();
-                    let scale = (520.0 / size.x.max(size.y)).min(1.0);
-                    columns[1].image((tex.id(), size * scale));
-                    columns[1].label(format!(
-                        "Kernel {} preview size: {}x{}",
-                        self.selected_kernel, preview.width, preview.height
+            ui.separator();
+            if self.kernel_rows > 0 {
+                ui.label(format!(
+                    "Kernels: {} ({} rows x {} cols)",
+                    self.kernels.len(),
+                    self.kernel_rows,
+                    self.kernel_cols
+                ));
+            } else {
+                ui.label(format!("Kernels: {}", self.kernels.len()));
+            }
+
+            if !self.previews.is_empty() {
+                self.selected_kernel = self
+                    .selected_kernel
+                    .min(self.previews.len().saturating_sub(1));
+                self.score_table(ui);
+                let group_names = groups::group_names(&self.kernels);
+                let kernel = &mut self.kernels[self.selected_kernel];
+                ui.horizontal(|ui| {
+                    ui.label("Name");
+                    ui.text_edit_singleline(&mut kernel.name);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Group");
+                    ui.text_edit_singleline(&mut kernel.group);
+                    egui::ComboBox::from_id_salt("existing_groups")
+                        .selected_text("Existing")
+                        .show_ui(ui, |ui| {
+                            for name in group_names {
+                                if ui.selectable_label(kernel.group == name, &name).clicked() {
+                                    kernel.group = name;
+                                }
+                            }
+                        });
+                });
+                ui.label("Note");
+                ui.text_edit_multiline(&mut kernel.note);
+                ui.label(format!("Source: {}", kernel.provenance));
+                if let (Some((row, column)), None) = (&kernel.separable, &kernel.mask) {
+                    ui.label(format!(
+                        "Separable: two 1-D passes of {} and {} taps",
+                        row.len(),
+                        column.len()
                     ));
-                } else {
-                    columns[1].label("No convolution result yet.");
                 }
+                if !kernel.history.is_empty() {
+                    ui.collapsing("History", |ui| {
+                        for entry in &kernel.history {
+                            ui.label(entry);
+                        }
+                    });
+                }
+                let mut pinned = self.pinned_kernels.contains(&self.selected_kernel);
+                if ui.checkbox(&mut pinned, "Pin for refinement").changed() {
+                    if pinned {
+                        self.pinned_kernels.insert(self.selected_kernel);
+                    } else {
+                        self.pinned_kernels.remove(&self.selected_kernel);
+                    }
+                }
+                let kernel = &mut self.kernels[self.selected_kernel];
+                if ui
+                    .checkbox(&mut kernel.enabled, "Enabled")
+                    .on_hover_text(
+                        "Muted kernels stay in the bank and are still convolved, but exports, \
+                         score summaries, quick scores and exported pipelines leave them out.",
+                    )
+                    .changed()
+                {
+                    self.store_muted();
+                }
+
+                ui.collapsing("Weights", |ui| self.weights_panel(ui));
+
+                ui.separator();
+                ui.collapsing("Score plot", |ui| self.score_plot(ui));
+                ui.collapsing("Groups", |ui| self.groups_panel(ui, ctx));
+                ui.collapsing("Kernel arithmetic", |ui| self.arithmetic_panel(ui));
+                ui.collapsing("Orientation", |ui| self.orientation_panel(ui, ctx));
+                ui.collapsing("Winner map", |ui| self.winner_panel(ui, ctx));
+                ui.collapsing("1-D kernels", |ui| self.taps_panel(ui));
+                ui.collapsing("Statistics", |ui| {
+                    if self.detached_stats {
+                        ui.label("The table is shown in a separate window.");
+                        if ui.button("Reattach table").clicked() {
+                            self.detached_stats = false;
+                        }
+                    } else {
+                        if ui.button("Pop out table").clicked() {
+                            self.detached_stats = true;
+                        }
+                        self.stats_table(ui);
+                    }
+                });
+                ui.collapsing("Significance", |ui| self.significance_panel(ui));
+                ui.collapsing("Flythrough export", |ui| self.flythrough_panel(ui));
+            }
             });
         });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            if compact {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    self.inputs_column(ui, ctx);
+                    ui.separator();
+                    self.preview_column(ui, ctx);
+                });
+            } else {
+                ui.columns(2, |columns| {
+                    self.inputs_column(&mut columns[0], ctx);
+                    self.preview_column(&mut columns[1], ctx);
+                });
+            }
+        });
+    }
+}
+
+/// Spacing with buttons, sliders and checkboxes large enough to hit with a
+/// finger, about 9 mm high.
+fn touch_spacing() -> egui::style::Spacing {
+    let default = egui::style::Spacing::default();
+    egui::style::Spacing {
+        item_spacing: egui::vec2(10.0, 8.0),
+        button_padding: egui::vec2(12.0, 8.0),
+        interact_size: egui::vec2(48.0, 36.0),
+        slider_width: 200.0,
+        icon_width: 24.0,
+        icon_width_inner: 14.0,
+        icon_spacing: 8.0,
+        ..default
+    }
+}
+
+/// Draws `add_contents` into a detached viewport, or into a floating window
+/// when the backend cannot open extra OS windows. Returns false once the
+/// user closes it.
+fn show_in_viewport(
+    ctx: &egui::Context,
+    class: egui::ViewportClass,
+    title: &str,
+    add_contents: impl FnOnce(&mut egui::Ui),
+) -> bool {
+    if class == egui::ViewportClass::Embedded {
+        let mut open = true;
+        egui::Window::new(title)
+            .open(&mut open)
+            .show(ctx, add_contents);
+        return open;
+    }
+    egui::CentralPanel::default().show(ctx, add_contents);
+    !ctx.input(|i| i.viewport().close_requested())
+}
+
+/// Scale that fits a `size` image in a square of side `side`, or in the
+/// width left in `ui` when that is narrower, without enlarging it.
+fn fit_scale(ui: &egui::Ui, size: egui::Vec2, side: f32) -> f32 {
+    (side.min(ui.available_width()) / size.x.max(size.y)).min(1.0)
+}
+
+/// Draws `texture` in a `size` viewer that zooms with the scroll wheel or a
+/// pinch about the pointer or the fingers, pans when dragged (with one
+/// finger, or two while pinching) unless `pan` is off, and fits the image
+/// again on a double click. Returns the viewer's response and the
+/// screen rectangle the whole image spans, which outgrows the viewer when
+/// zoomed in.
+fn zoomable_image(
+    ui: &mut egui::Ui,
+    texture: egui::TextureId,
+    size: egui::Vec2,
+    view: &mut ZoomPan,
+    pan: bool,
+) -> (egui::Response, egui::Rect) {
+    let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click_and_drag());
+    if response.double_clicked() {
+        *view = ZoomPan::default();
+    }
+    let touch = ui
+        .input(|i| i.multi_touch())
+        .filter(|touch| rect.contains(touch.center_pos));
+    if pan && response.dragged() && touch.is_none() {
+        view.center -= response.drag_delta() / (size * view.zoom);
+    }
+    if let Some(touch) = touch {
+        // Fingers pinch about their centre and drag the image along.
+        if pan {
+            view.center -= touch.translation_delta / (size * view.zoom);
+        }
+        let zoom = (view.zoom * touch.zoom_delta).clamp(1.0, MAX_ZOOM);
+        let offset = (touch.center_pos - rect.center()) / size;
+        view.center += offset / view.zoom - offset / zoom;
+        view.zoom = zoom;
+    } else if let Some(pointer) = response.hover_pos() {
+        let (scroll, pinch) = ui.input(|i| (i.smooth_scroll_delta.y, i.zoom_delta()));
+        let zoom =
+            (view.zoom * pinch * (scroll * ZOOM_PER_SCROLL_POINT).exp()).clamp(1.0, MAX_ZOOM);
+        if zoom != view.zoom {
+            // Keeps the image point under the pointer where it is.
+            let offset = (pointer - rect.center()) / size;
+            view.center += offset / view.zoom - offset / zoom;
+            view.zoom = zoom;
+        }
     }
+    let half = 0.5 / view.zoom;
+    view.center = view
+        .center
+        .clamp(egui::Vec2::splat(half), egui::Vec2::splat(1.0 - half));
+    let image_rect = egui::Rect::from_min_size(
+        rect.center() - view.center * size * view.zoom,
+        size * view.zoom,
+    );
+    let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+    ui.painter()
+        .with_clip_rect(rect)
+        .image(texture, image_rect, uv, egui::Color32::WHITE);
+    (response, image_rect)
 }
 
 fn gray_to_color_image(gray: &GrayImage) -> ColorImage {
@@ -326,59 +9544,651 @@ fn gray_to_color_image(gray: &GrayImage) -> ColorImage {
     ColorImage::from_gray([gray.width() as usize, gray.height() as usize], bytes)
 }
 
-fn gray_to_f32(gray: &GrayImage) -> Vec<f32> {
-    gray.pixels().map(|p| p[0] as f32 / 255.0).collect()
+/// Full-resolution previews of the kernels at `indices`, in that order, with
+/// their maps kept in `job`'s store. One kernel per core at a time, which
+/// bounds the maps held at once.
+#[cfg(not(target_arch = "wasm32"))]
+fn exact_previews(
+    kernels: &[Kernel],
+    indices: &[usize],
+    job: &mut RunContext,
+) -> Vec<ConvolutionPreview> {
+    let mut previews = Vec::with_capacity(indices.len());
+    for batch in indices.chunks(parallel::threads()) {
+        let results: Vec<_> = batch
+            .par_iter()
+            .map(|&index| {
+                let response = job.respond(&kernels[index]);
+                let preview = job.exact_preview(&response);
+                (response, preview)
+            })
+            .collect();
+        for (&index, (response, preview)) in batch.iter().zip(results) {
+            job.keep(index, &response);
+            previews.push(preview);
+        }
+    }
+    previews
+}
+
+/// Maps and previews computed on a background thread.
+#[cfg(not(target_arch = "wasm32"))]
+type ExactRun = CpuRun<(Vec<f32>, ConvolutionPreview)>;
+
+/// Background run of `kernels`, the bank's from index `first` on, previews
+/// included. Their maps come from `gpu` when given, for runs it can take,
+/// from `carry`'s maps of the region before it moved when those carry over,
+/// and from the run's CPU backend otherwise.
+#[cfg(not(target_arch = "wasm32"))]
+fn start_cpu_run(
+    kernels: Vec<Kernel>,
+    first: usize,
+    job: &RunContext,
+    gpu: Option<Arc<GpuConvolver>>,
+    carry: Option<Arc<CarryOver>>,
+) -> ExactRun {
+    let (input, map_input, planes) = (job.input.clone(), job.map_input.clone(), job.planes.clone());
+    let (channels, mode, backend, border) = (job.channels, job.mode, job.backend, job.border);
+    let (width, height, sampling, activation_k) =
+        (job.width, job.height, job.sampling, job.activation_k);
+    CpuRun::start(kernels.len(), first, height, move |index, progress| {
+        let kernel = &kernels[index];
+        let on_gpu = gpu.as_ref().and_then(|gpu| {
+            let oriented = kernel.oriented(mode);
+            let (kw, kh) = (kernel.width, kernel.height);
+            gpu.convolve(&input, width, height, &oriented.masked_weights(), kw, kh)
+                .inspect_err(|e| log::warn!("{e}; using the CPU."))
+                .ok()
+        });
+        let moved = carry.as_ref().and_then(|carry| {
+            let index = first + index;
+            let previous = carry
+                .store
+                .get(index)
+                .filter(|_| !carry.edited.contains(&index))?;
+            let (response, convolved) = respond_moved(
+                kernel,
+                mode,
+                backend,
+                border,
+                &input,
+                &previous,
+                (carry.from, carry.to),
+            );
+            carry.add(convolved, kernel);
+            Some(response)
+        });
+        if moved.is_none()
+            && let Some(carry) = &carry
+        {
+            carry.add(width * height, kernel);
+        }
+        let response = if let Some(response) = on_gpu.or(moved) {
+            cpu_run::whole(response, height, progress)?
+        } else if planes.is_empty() && sampling.is_dense() {
+            cpu_run::respond_in_bands(
+                kernel, mode, backend, border, &input, width, height, progress,
+            )?
+        } else if planes.is_empty() {
+            let response =
+                kernel.filter_sampled(mode, backend, border, sampling, &input, width, height);
+            cpu_run::whole(response, height, progress)?
+        } else {
+            let response = channels::filter(
+                kernel, channels, mode, backend, border, sampling, &planes, width, height,
+            );
+            cpu_run::whole(response, height, progress)?
+        };
+        let preview = build_exact_preview(
+            &response,
+            &map_input,
+            (width, height),
+            sampling,
+            activation_k,
+        );
+        Some((response, preview))
+    })
+}
+
+/// Maps a run over the region `from` kept, which the run over `to`, the
+/// region moved, copies where both give the same value.
+#[cfg(not(target_arch = "wasm32"))]
+struct CarryOver {
+    store: ResponseStore,
+    from: Roi,
+    to: Roi,
+    /// Kernels edited since their maps were kept, which are convolved in
+    /// full.
+    edited: BTreeSet<usize>,
+    /// Pixels convolved so far over all kernels, and their taps.
+    convolved: AtomicUsize,
+    work: AtomicU64,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl CarryOver {
+    fn add(&self, pixels: usize, kernel: &Kernel) {
+        self.convolved.fetch_add(pixels, Ordering::Relaxed);
+        let work = (pixels * kernel.taps()) as u64;
+        self.work.fetch_add(work, Ordering::Relaxed);
+    }
+}
+
+/// `kernel`'s map of `input`, the slide's region `to`, made from
+/// `previous`, its map of the region `from` of the same size: the pixels
+/// both regions give the same value are copied, and the strips the move
+/// uncovered, with those within the kernel's reach of either region's
+/// edges, convolved. Returns the map and the number of its pixels
+/// convolved.
+#[cfg(not(target_arch = "wasm32"))]
+fn respond_moved(
+    kernel: &Kernel,
+    mode: FilterMode,
+    backend: Backend,
+    border: BorderMode,
+    input: &[f32],
+    previous: &[f32],
+    (from, to): (Roi, Roi),
+) -> (Vec<f32>, usize) {
+    let (width, height) = (to.width, to.height);
+    let Some((cols, rows)) = to.shared_interior(&from, (kernel.width, kernel.height)) else {
+        let response = kernel.filter(mode, backend, border, input, width, height);
+        return (response, width * height);
+    };
+    let mut response = vec![0.0; width * height];
+    let old_x = to.x + cols.start - from.x;
+    for y in rows.clone() {
+        let old_y = to.y + y - from.y;
+        response[y * width + cols.start..][..cols.len()]
+            .copy_from_slice(&previous[old_y * width + old_x..][..cols.len()]);
+    }
+    let strips = [
+        (0..width, 0..rows.start),
+        (0..width, rows.end..height),
+        (0..cols.start, rows.clone()),
+        (cols.end..width, rows),
+    ];
+    let mut convolved = 0;
+    for (strip_cols, strip_rows) in strips {
+        if strip_cols.is_empty() || strip_rows.is_empty() {
+            continue;
+        }
+        let strip = border::filter_region(
+            border,
+            input,
+            width,
+            height,
+            strip_cols.clone(),
+            strip_rows.clone(),
+            (kernel.width, kernel.height),
+            |input, width, height| {
+                kernel.filter(mode, backend, BorderMode::Zero, input, width, height)
+            },
+        );
+        for (y, values) in strip_rows.zip(strip.chunks_exact(strip_cols.len())) {
+            response[y * width + strip_cols.start..][..strip_cols.len()].copy_from_slice(values);
+        }
+        convolved += strip.len();
+    }
+    (response, convolved)
+}
+
+/// Stores `response` in `store`, giving up on the store if that fails so
+/// later lookups fall back to recomputing.
+#[cfg(not(target_arch = "wasm32"))]
+fn keep_response(store: &mut Option<ResponseStore>, index: usize, response: &[f32]) {
+    if let Some(responses) = store
+        && let Err(e) = responses.insert(index, response)
+    {
+        log::warn!("{e}; responses will be recomputed.");
+        *store = None;
+    }
+}
+
+/// Preview of the map `response` of a `width` x `height` input taken at
+/// `sampling`, whose kept pixels are `input`. The peak is placed on the
+/// input, so captions of strided maps name slide pixels.
+fn build_exact_preview(
+    response: &[f32],
+    input: &[f32],
+    (width, height): (usize, usize),
+    sampling: Sampling,
+    activation_k: f32,
+) -> ConvolutionPreview {
+    let (width, height) = sampling.output_size(width, height);
+    let (pw, ph, bytes, range) = build_preview(response, width, height, PREVIEW_MAX_SIZE);
+    let stats = stats::response_stats(response, input, activation_k);
+    let interval = scoring::bootstrap_interval(&scoring::tile_sums(response, width, height));
+    let mut highlights = stats::highlights(response, width, height);
+    let (x, y) = highlights.peak_at;
+    highlights.peak_at = (x * sampling.stride, y * sampling.stride);
+    ConvolutionPreview {
+        score: stats.mean_abs,
+        raw_interval: interval,
+        interval,
+        stats,
+        highlights,
+        width: pw,
+        height: ph,
+        bytes,
+        range,
+        exact: true,
+        checksum: stats::checksum(response),
+        significance: None,
+        display: None,
+    }
 }
 
-fn convolve_same(
+/// Builds a preview from a downsampled response, sized as if it had been
+/// computed at `full_w` x `full_h` so drafts and exact previews line up.
+fn build_draft_preview(
+    response: &[f32],
     input: &[f32],
     width: usize,
     height: usize,
-    kernel: &[f32],
-    kw: usize,
-    kh: usize,
-) -> Vec<f32> {
-    let mut output = vec![0.0; width * height];
-    let kcx = kw / 2;
-    let kcy = kh / 2;
-
-    for y in 0..height {
-        for x in 0..width {
-            let mut acc = 0.0;
-            for ky in 0..kh {
-                for kx in 0..kw {
-                    let ix = x as isize + kx as isize - kcx as isize;
-                    let iy = y as isize + ky as isize - kcy as isize;
-                    if ix >= 0 && iy >= 0 && ix < width as isize && iy < height as isize {
-                        let i = iy as usize * width + ix as usize;
-                        let k = ky * kw + kx;
-                        acc += input[i] * kernel[k];
-                    }
-                }
-            }
-            output[y * width + x] = acc;
+    full_w: usize,
+    full_h: usize,
+    activation_k: f32,
+) -> ConvolutionPreview {
+    let (out_w, out_h) = preview_size(full_w, full_h, PREVIEW_MAX_SIZE);
+    let resized = resize_nearest(response, width, height, out_w, out_h);
+    let (pw, ph, bytes, range) = build_preview(&resized, out_w, out_h, PREVIEW_MAX_SIZE);
+    let stats = stats::response_stats(response, input, activation_k);
+    let interval = scoring::bootstrap_interval(&scoring::tile_sums(response, width, height));
+    let mut highlights = stats::highlights(response, width, height);
+    let (x, y) = highlights.peak_at;
+    highlights.peak_at = (x * full_w / width, y * full_h / height);
+    ConvolutionPreview {
+        score: stats.mean_abs,
+        raw_interval: interval,
+        interval,
+        stats,
+        highlights,
+        width: pw,
+        height: ph,
+        bytes,
+        range,
+        exact: false,
+        checksum: 0,
+        significance: None,
+        display: None,
+    }
+}
+
+/// Positions on the `colormap` ramp of the preview's pixels, calibrated by
+/// `calibration`.
+fn preview_positions(
+    preview: &ConvolutionPreview,
+    colormap: Colormap,
+    calibration: Calibration,
+) -> impl Iterator<Item = f32> + '_ {
+    let (min, max) = preview.range;
+    let step = (max - min).max(1e-6) / 255.0;
+    let range = calibration.display_range(preview.range);
+    preview.bytes.iter().map(move |&b| {
+        let value = calibration.apply(min + b as f32 * step);
+        colormap.position(value, range)
+    })
+}
+
+/// Fill of `value` in the weights grid's colors, fading to white as it
+/// shrinks from a magnitude of `range`.
+fn signed_fill(colors: &OverlayColors, value: f32, range: f32) -> egui::Color32 {
+    let t = (value / range).clamp(-1.0, 1.0);
+    let full = if t >= 0.0 {
+        colors.positive
+    } else {
+        colors.negative
+    };
+    let [r, g, b] = full.map(|c| (255.0 + (c as f32 - 255.0) * t.abs()) as u8);
+    egui::Color32::from_rgb(r, g, b)
+}
+
+/// Side of a lesson grid's cells for a grid `cells` wide or high.
+fn lesson_cell(cells: usize, extent: f32) -> f32 {
+    (extent / cells as f32).clamp(4.0, 40.0)
+}
+
+/// Paints a `columns` x `rows` grid of cells with `fill`, writing `text`
+/// in cells large enough to read it.
+fn paint_cells(
+    ui: &mut egui::Ui,
+    (columns, rows): (usize, usize),
+    cell: f32,
+    fill: impl Fn(usize) -> egui::Color32,
+    text: impl Fn(usize) -> Option<String>,
+) -> (egui::Painter, egui::Rect) {
+    let size = egui::vec2(columns as f32, rows as f32) * cell;
+    let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    for i in 0..columns * rows {
+        let min = rect.min + egui::vec2((i % columns) as f32, (i / columns) as f32) * cell;
+        let cell_rect = egui::Rect::from_min_size(min, egui::vec2(cell, cell));
+        let color = fill(i);
+        painter.rect_filled(cell_rect, 0.0, color);
+        if cell >= 28.0
+            && let Some(text) = text(i)
+        {
+            let dark = color.r() as u32 + color.g() as u32 + color.b() as u32 > 384;
+            painter.text(
+                cell_rect.center(),
+                egui::Align2::CENTER_CENTER,
+                text,
+                egui::FontId::proportional(cell * 0.3),
+                if dark {
+                    egui::Color32::BLACK
+                } else {
+                    egui::Color32::WHITE
+                },
+            );
         }
     }
-    output
+    (painter, rect)
+}
+
+/// The slide pixels a lesson reads with the kernel over them, the products
+/// of the pixel being summed, and the responses summed so far.
+fn lesson_view(ui: &mut egui::Ui, lesson: &Lesson, colors: &OverlayColors, colormap: Colormap) {
+    let (kw, kh) = lesson.kernel_size();
+    let region = lesson.region;
+    let (window, ww, wh) = lesson.window();
+    let current = lesson.current();
+    let stroke = |color: [u8; 3]| {
+        let [r, g, b] = color;
+        egui::Stroke::new(2.0, egui::Color32::from_rgb(r, g, b))
+    };
+    let products = current.map(|(index, _)| lesson.products(index));
+    let outputs = lesson.outputs();
+    ui.horizontal_top(|ui| {
+        ui.vertical(|ui| {
+            ui.label("Slide");
+            let cell = lesson_cell(ww.max(wh), 320.0);
+            let inside = |i: usize| {
+                let (x, y) = (i % ww, i / ww);
+                (kw / 2..kw / 2 + region.width).contains(&x)
+                    && (kh / 2..kh / 2 + region.height).contains(&y)
+            };
+            let (painter, rect) = paint_cells(
+                ui,
+                (ww, wh),
+                cell,
+                |i| {
+                    let level = (window[i].clamp(0.0, 1.0) * 255.0) as u8;
+                    let level = if inside(i) { level } else { level / 2 };
+                    egui::Color32::from_gray(level)
+                },
+                |i| Some(format!("{:.2}", window[i])),
+            );
+            if let Some((index, done)) = current {
+                let (x, y) = (index % region.width, index / region.width);
+                let at = |cx: usize, cy: usize, w: usize, h: usize| {
+                    egui::Rect::from_min_size(
+                        rect.min + egui::vec2(cx as f32, cy as f32) * cell,
+                        egui::vec2(w as f32, h as f32) * cell,
+                    )
+                };
+                let tap = done - 1;
+                painter.rect_stroke(at(x, y, kw, kh), 0.0, stroke(colors.roi));
+                painter.rect_stroke(
+                    at(x + tap % kw, y + tap / kw, 1, 1),
+                    0.0,
+                    stroke(colors.marker),
+                );
+                painter.rect_stroke(at(x + kw / 2, y + kh / 2, 1, 1), 0.0, stroke(colors.grid));
+            }
+        });
+        ui.vertical(|ui| {
+            ui.label("Weight × pixel");
+            let cell = lesson_cell(kw.max(kh), 240.0);
+            let done = current.map_or(0, |(_, done)| done);
+            let range = products
+                .iter()
+                .flatten()
+                .fold(1e-6f32, |m, p| m.max(p.abs()));
+            let (painter, rect) = paint_cells(
+                ui,
+                (kw, kh),
+                cell,
+                |i| match &products {
+                    Some(products) if i < done => signed_fill(colors, products[i], range),
+                    _ => egui::Color32::DARK_GRAY,
+                },
+                |i| {
+                    let products = products.as_ref().filter(|_| i < done)?;
+                    Some(format!("{:.2}", products[i]))
+                },
+            );
+            if done > 0 {
+                let tap = done - 1;
+                let min = rect.min + egui::vec2((tap % kw) as f32, (tap / kw) as f32) * cell;
+                let tap_rect = egui::Rect::from_min_size(min, egui::vec2(cell, cell));
+                painter.rect_stroke(tap_rect, 0.0, stroke(colors.marker));
+            }
+        });
+        ui.vertical(|ui| {
+            ui.label("Response");
+            let cell = lesson_cell(region.width.max(region.height), 240.0);
+            let range = outputs
+                .iter()
+                .flatten()
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &v| {
+                    (min.min(v), max.max(v))
+                });
+            let (painter, rect) = paint_cells(
+                ui,
+                (region.width, region.height),
+                cell,
+                |i| match outputs[i] {
+                    Some(v) => shade(colormap, colormap.position(v, range), None),
+                    None => egui::Color32::DARK_GRAY,
+                },
+                |i| outputs[i].map(|v| format!("{v:.2}")),
+            );
+            if let Some((index, _)) = current {
+                let (x, y) = (index % region.width, index / region.width);
+                let min = rect.min + egui::vec2(x as f32, y as f32) * cell;
+                let pixel = egui::Rect::from_min_size(min, egui::vec2(cell, cell));
+                painter.rect_stroke(pixel, 0.0, stroke(colors.grid));
+            }
+        });
+    });
+    let Some(((index, done), products)) = current.zip(products) else {
+        ui.label("Press Play or Next tap to start.");
+        return;
+    };
+    let (x, y) = (
+        region.x + index % region.width,
+        region.y + index / region.width,
+    );
+    let tap = done - 1;
+    let sum: f32 = products[..done].iter().sum();
+    ui.label(format!(
+        "Pixel ({x}, {y}), tap {done} of {}: weight {:.4} × pixel {:.4} = {:.4}",
+        kw * kh,
+        lesson.weights()[tap],
+        lesson.inputs(index)[tap],
+        products[tap]
+    ));
+    if done == kw * kh {
+        ui.strong(format!("Response at ({x}, {y}) = {sum:.4}"));
+    } else {
+        ui.label(format!("Running sum: {sum:.4}"));
+    }
+    ui.label(format!("Step {} of {}.", lesson.step(), lesson.steps()));
+}
+
+/// The input patch under a lesson's single pixel, the kernel, their
+/// products and the sum accumulating tap by tap, with the largest
+/// contributions spelled out.
+fn explanation_view(ui: &mut egui::Ui, lesson: &Lesson, colors: &OverlayColors, fmt: NumberFormat) {
+    let (kw, kh) = lesson.kernel_size();
+    let (inputs, weights, products) = (lesson.inputs(0), lesson.weights(), lesson.products(0));
+    let cell = lesson_cell(kw.max(kh), 200.0);
+    let magnitude = |values: &[f32]| values.iter().fold(1e-6f32, |m, v| m.max(v.abs()));
+    ui.horizontal_top(|ui| {
+        ui.vertical(|ui| {
+            ui.label("Input patch");
+            paint_cells(
+                ui,
+                (kw, kh),
+                cell,
+                |i| egui::Color32::from_gray((inputs[i].clamp(0.0, 1.0) * 255.0) as u8),
+                |i| Some(format!("{:.2}", inputs[i])),
+            );
+        });
+        ui.vertical(|ui| {
+            ui.label("Kernel");
+            let range = magnitude(weights);
+            paint_cells(
+                ui,
+                (kw, kh),
+                cell,
+                |i| signed_fill(colors, weights[i], range),
+                |i| Some(format!("{:.2}", weights[i])),
+            );
+        });
+        ui.vertical(|ui| {
+            ui.label("Weight × pixel");
+            let range = magnitude(&products);
+            paint_cells(
+                ui,
+                (kw, kh),
+                cell,
+                |i| signed_fill(colors, products[i], range),
+                |i| Some(format!("{:.2}", products[i])),
+            );
+        });
+    });
+
+    let partial: Vec<f32> = products
+        .iter()
+        .scan(0.0f32, |sum, p| {
+            *sum += p;
+            Some(*sum)
+        })
+        .collect();
+    let sum = partial.last().copied().unwrap_or(0.0);
+    ui.label("Sum after each tap, in reading order");
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(3.0 * 200.0, 90.0), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+    let (low, high) = partial.iter().fold((0.0f32, 0.0f32), |(low, high), &v| {
+        (low.min(v), high.max(v))
+    });
+    let span = (high - low).max(1e-6);
+    let point = |i: usize, v: f32| {
+        egui::pos2(
+            rect.left() + rect.width() * (i + 1) as f32 / partial.len() as f32,
+            rect.bottom() - rect.height() * (v - low) / span,
+        )
+    };
+    let zero = point(0, 0.0).y;
+    painter.hline(
+        rect.x_range(),
+        zero,
+        egui::Stroke::new(1.0, ui.visuals().weak_text_color()),
+    );
+    let line: Vec<egui::Pos2> = std::iter::once(egui::pos2(rect.left(), zero))
+        .chain(partial.iter().enumerate().map(|(i, &v)| point(i, v)))
+        .collect();
+    painter.add(egui::Shape::line(
+        line,
+        egui::Stroke::new(1.5, ui.visuals().strong_text_color()),
+    ));
+    ui.strong(format!(
+        "Response = {} over {} taps",
+        fmt.format(sum),
+        kw * kh
+    ));
+
+    let mut order: Vec<usize> = (0..products.len()).collect();
+    order.sort_by(|&a, &b| products[b].abs().total_cmp(&products[a].abs()));
+    ui.label("Largest contributions, by offset from the centre:");
+    for &tap in order.iter().take(3).filter(|&&tap| products[tap] != 0.0) {
+        let (dx, dy) = (
+            (tap % kw) as isize - (kw / 2) as isize,
+            (tap / kw) as isize - (kh / 2) as isize,
+        );
+        ui.label(format!(
+            "({dx:+}, {dy:+}): {} × {} = {} ({:.0}% of the total magnitude)",
+            fmt.format(weights[tap]),
+            fmt.format(inputs[tap]),
+            fmt.format(products[tap]),
+            100.0 * products[tap].abs() / products.iter().map(|p| p.abs()).sum::<f32>()
+        ));
+    }
+}
+
+/// Color of ramp position `t` in `colormap`, blended over a slide pixel of
+/// the given gray level at the given opacity when `under` is set.
+fn shade(colormap: Colormap, t: f32, under: Option<(u8, f32)>) -> egui::Color32 {
+    let [r, g, b] = match under {
+        None => colormap.sample_rgb(t),
+        Some((gray, opacity)) => {
+            let gray = gray as f32 / 255.0;
+            colormap
+                .sample(t)
+                .map(|c| (((1.0 - opacity) * gray + opacity * c) * 255.0) as u8)
+        }
+    };
+    egui::Color32::from_rgb(r, g, b)
+}
+
+/// One-line summary shown under a preview, e.g. "peak 3.2 at (1042, 511);
+/// 0.8% of pixels beyond 2σ; dominant orientation 45°". `k` is the
+/// activation threshold the preview's stats were computed with.
+fn preview_caption(preview: &ConvolutionPreview, k: f32, fmt: NumberFormat) -> String {
+    let ResponseHighlights {
+        peak,
+        peak_at: (x, y),
+        orientation,
+    } = preview.highlights;
+    let orientation = match orientation {
+        Some(degrees) => format!("dominant orientation {degrees:.0}°"),
+        None => "no dominant orientation".to_owned(),
+    };
+    format!(
+        "peak {} at ({x}, {y}); {:.1}% of pixels beyond {k}σ; {orientation}",
+        fmt.format(peak),
+        preview.stats.activation_rate * 100.0
+    )
 }
 
+/// Gray levels of `src` shrunk to fit `max_dim`, with their size and the
+/// value interval they span.
 fn build_preview(
     src: &[f32],
     width: usize,
     height: usize,
     max_dim: usize,
-) -> (usize, usize, Vec<u8>) {
+) -> (usize, usize, Vec<u8>, (f32, f32)) {
+    let (out_w, out_h) = preview_size(width, height, max_dim);
+    let resized = resize_nearest(src, width, height, out_w, out_h);
+    let (min_v, max_v) = min_max(&resized);
+    (
+        out_w,
+        out_h,
+        quantize(&resized, min_v, max_v),
+        (min_v, max_v),
+    )
+}
+
+/// Size of a preview fitting in `max_dim` while keeping the aspect ratio;
+/// images are never upscaled.
+fn preview_size(width: usize, height: usize, max_dim: usize) -> (usize, usize) {
     let scale = (max_dim as f32 / width.max(height) as f32).min(1.0);
     let out_w = ((width as f32 * scale).round() as usize).max(1);
     let out_h = ((height as f32 * scale).round() as usize).max(1);
-    let resized = resize_nearest(src, width, height, out_w, out_h);
-    let (min_v, max_v) = min_max(&resized);
+    (out_w, out_h)
+}
+
+/// Maps `[min_v, max_v]` linearly onto `0..=255`.
+fn quantize(values: &[f32], min_v: f32, max_v: f32) -> Vec<u8> {
     let range = (max_v - min_v).max(1e-6);
-    let bytes = resized
-        .into_iter()
-        .map(|v| (((v - min_v) / range) * 255.0).clamp(0.0, 255.0) as u8)
-        .collect();
-    (out_w, out_h, bytes)
+    values
+        .iter()
+        .map(|&v| (((v - min_v) / range) * 255.0).clamp(0.0, 255.0) as u8)
+        .collect()
 }
 
 fn resize_nearest(src: &[f32], src_w: usize, src_h: usize, dst_w: usize, dst_h: usize) -> Vec<f32> {
@@ -393,10 +10203,11 @@ fn resize_nearest(src: &[f32], src_w: usize, src_h: usize, dst_w: usize, dst_h:
     out
 }
 
+/// Range of the finite values, `(0, 0)` when there are none.
 fn min_max(values: &[f32]) -> (f32, f32) {
     let mut min_v = f32::INFINITY;
     let mut max_v = f32::NEG_INFINITY;
-    for &v in values {
+    for &v in values.iter().filter(|v| v.is_finite()) {
         if v < min_v {
             min_v = v;
         }
@@ -411,6 +10222,35 @@ fn min_max(values: &[f32]) -> (f32, f32) {
     }
 }
 
+/// Quotes a CSV field when it contains separators, quotes or line breaks.
+fn unix_timestamp() -> u64 {
+    web_time::SystemTime::now()
+        .duration_since(web_time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Address of the page the web app runs in, fragment included.
+#[cfg(target_arch = "wasm32")]
+fn page_address() -> Option<String> {
+    let window = web_sys::window()?;
+    let location = js_sys::Reflect::get(&window, &"location".into()).ok()?;
+    js_sys::Reflect::get(&location, &"href".into())
+        .ok()?
+        .as_string()
+}
+
+/// Whether a dropped file holds kernels: a CSV file, or JSON in the kernel
+/// file layout rather than a pipeline.
+fn is_kernel_file(name: &str, bytes: &[u8]) -> bool {
+    name.ends_with(".csv")
+        || (name.ends_with(".json") && kernel_file::is_kernel_json(&String::from_utf8_lossy(bytes)))
+}
+
+fn is_pipeline_file(name: &str) -> bool {
+    name.ends_with(".json") || name.ends_with(PROJECT_EXTENSION)
+}
+
 fn extract_bytes(file: &egui::DroppedFile) -> Option<Vec<u8>> {
     if let Some(bytes) = &file.bytes {
         return Some(bytes.to_vec());
@@ -423,3 +10263,63 @@ fn extract_bytes(file: &egui::DroppedFile) -> Option<Vec<u8>> {
 
     None
 }
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::kernel::Provenance;
+
+    #[test]
+    fn moved_maps_match_a_fresh_run_for_every_border() {
+        let (slide_width, slide_height) = (90, 70);
+        let slide: Vec<f32> = (0..slide_width * slide_height)
+            .map(|i| ((i * 7919) % 251) as f32 / 250.0)
+            .collect();
+        let crop = |roi: Roi| -> Vec<f32> {
+            (roi.y..roi.y + roi.height)
+                .flat_map(|y| &slide[y * slide_width + roi.x..][..roi.width])
+                .copied()
+                .collect()
+        };
+        // Even height, so the kernel reaches further up than down.
+        let weights = (0..5 * 4)
+            .map(|i| ((i * 31) % 17) as f32 / 8.0 - 1.0)
+            .collect();
+        let kernel = Kernel::new(weights, (5, 4), Provenance::Entered);
+        let from = Roi {
+            x: 20,
+            y: 15,
+            width: 48,
+            height: 40,
+        };
+        let mode = FilterMode::Correlation;
+        for to in [
+            from.moved((7, -5), (slide_width, slide_height)),
+            from.moved((-20, 15), (slide_width, slide_height)),
+        ] {
+            for border in BorderMode::ALL {
+                for backend in [Backend::Scalar, Backend::Vectorized, Backend::Gemm] {
+                    let filter = |roi: Roi| {
+                        kernel.filter(mode, backend, border, &crop(roi), roi.width, roi.height)
+                    };
+                    let (moved, convolved) = respond_moved(
+                        &kernel,
+                        mode,
+                        backend,
+                        border,
+                        &crop(to),
+                        &filter(from),
+                        (from, to),
+                    );
+                    assert!(convolved < to.width * to.height);
+                    let bits = |map: &[f32]| map.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+                    assert_eq!(
+                        bits(&moved),
+                        bits(&filter(to)),
+                        "{to:?}, {border:?}, {backend:?}"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,778 @@
+use rustfft::FftPlanner;
+use rustfft::num_complex::Complex;
+use serde::{Deserialize, Serialize};
+use web_time::{Duration, Instant};
+
+use crate::registration::fft2;
+
+/// Edge length of the synthetic image used to time backends.
+const BENCHMARK_SIZE: usize = 256;
+/// Timed repetitions per backend; the fastest repetition is kept.
+const BENCHMARK_REPEATS: usize = 3;
+/// Auto selection considers the FFT once pixels times taps reaches this,
+/// a tenth of a second or more of direct summation.
+pub const FFT_MIN_WORK: usize = 1 << 28;
+/// Smaller kernels keep the direct sum: on a 4-megapixel slide the
+/// vectorized backend is still faster at 11x11 and the FFT from 15x15.
+pub const FFT_MIN_TAPS: usize = 160;
+/// Values in the patch matrix the GEMM backend unrolls at a time.
+const GEMM_BLOCK: usize = 1 << 20;
+/// Largest stride and dilation offered.
+pub const MAX_SAMPLING_FACTOR: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Backend {
+    /// Straightforward per-pixel loop over the kernel taps.
+    Scalar,
+    /// Tap-major row sweeps that the compiler can auto-vectorize.
+    Vectorized,
+    /// Product of Fourier transforms, whose cost does not grow with the
+    /// kernel size.
+    Fft,
+    /// Patches unrolled into a matrix (im2col) and multiplied by the kernel.
+    Gemm,
+}
+
+impl Backend {
+    pub const ALL: [Backend; 4] = [
+        Backend::Scalar,
+        Backend::Vectorized,
+        Backend::Fft,
+        Backend::Gemm,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Scalar => "Scalar",
+            Self::Vectorized => "Vectorized",
+            Self::Fft => "FFT",
+            Self::Gemm => "GEMM",
+        }
+    }
+
+    /// Zero-padded "same" response, split over all cores in native builds
+    /// when the image is large enough.
+    pub fn convolve(
+        self,
+        input: &[f32],
+        width: usize,
+        height: usize,
+        kernel: &[f32],
+        kw: usize,
+        kh: usize,
+    ) -> Vec<f32> {
+        #[cfg(not(target_arch = "wasm32"))]
+        return crate::parallel::convolve_rows(self, input, width, height, kernel, kw, kh);
+        #[cfg(target_arch = "wasm32")]
+        self.convolve_serial(input, width, height, kernel, kw, kh)
+    }
+
+    /// [`Backend::convolve`] on the calling thread.
+    pub fn convolve_serial(
+        self,
+        input: &[f32],
+        width: usize,
+        height: usize,
+        kernel: &[f32],
+        kw: usize,
+        kh: usize,
+    ) -> Vec<f32> {
+        self.convolve_serial_sampled(input, width, height, kernel, kw, kh, Sampling::DENSE)
+    }
+
+    /// Zero-padded "same" response at the pixels `sampling` keeps, with its
+    /// taps spread by its dilation. Dense sampling is [`Backend::convolve`];
+    /// other samplings run on the calling thread.
+    #[allow(clippy::too_many_arguments)]
+    pub fn convolve_sampled(
+        self,
+        input: &[f32],
+        width: usize,
+        height: usize,
+        kernel: &[f32],
+        kw: usize,
+        kh: usize,
+        sampling: Sampling,
+    ) -> Vec<f32> {
+        if sampling.is_dense() {
+            return self.convolve(input, width, height, kernel, kw, kh);
+        }
+        self.convolve_serial_sampled(input, width, height, kernel, kw, kh, sampling)
+    }
+
+    /// Only the pixels `sampling` keeps are computed by the direct backends;
+    /// the FFT computes the dense response of the dilated kernel and keeps
+    /// them.
+    #[allow(clippy::too_many_arguments)]
+    fn convolve_serial_sampled(
+        self,
+        input: &[f32],
+        width: usize,
+        height: usize,
+        kernel: &[f32],
+        kw: usize,
+        kh: usize,
+        sampling: Sampling,
+    ) -> Vec<f32> {
+        match self {
+            Self::Scalar => convolve_same(input, width, height, kernel, kw, kh, sampling),
+            Self::Vectorized => {
+                convolve_same_vectorized(input, width, height, kernel, kw, kh, sampling)
+            }
+            Self::Fft if sampling.is_dense() => {
+                convolve_same_fft(input, width, height, kernel, kw, kh)
+            }
+            Self::Fft => {
+                let (dilated, dw, dh) = sampling.dilate(kernel, kw, kh);
+                let dense = convolve_same_fft(input, width, height, &dilated, dw, dh);
+                sampling.subsample(&dense, width, height)
+            }
+            Self::Gemm => convolve_same_gemm(input, width, height, kernel, kw, kh, sampling),
+        }
+    }
+}
+
+/// Which pixels a convolution computes and how far apart its taps are: every
+/// `stride`-th pixel along both axes, from the top-left one, with taps
+/// `dilation` pixels apart, as in strided and atrous CNN layers. A dilated
+/// kernel responds as its window with `dilation - 1` zeros between taps
+/// would, anchored at that window's centre.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Sampling {
+    pub stride: usize,
+    pub dilation: usize,
+}
+
+impl Default for Sampling {
+    fn default() -> Self {
+        Self::DENSE
+    }
+}
+
+impl Sampling {
+    /// Every pixel, with adjacent taps: the plain "same" convolution.
+    pub const DENSE: Self = Self {
+        stride: 1,
+        dilation: 1,
+    };
+
+    pub fn is_dense(self) -> bool {
+        self == Self::DENSE
+    }
+
+    /// `self`, when both factors lie in `1..=MAX_SAMPLING_FACTOR`.
+    pub fn checked(self) -> Result<Self, String> {
+        let range = 1..=MAX_SAMPLING_FACTOR;
+        if range.contains(&self.stride) && range.contains(&self.dilation) {
+            Ok(self)
+        } else {
+            Err(format!(
+                "Stride {} and dilation {} must each be between 1 and {MAX_SAMPLING_FACTOR}.",
+                self.stride, self.dilation
+            ))
+        }
+    }
+
+    /// Size of the map of a `width` x `height` image.
+    pub fn output_size(self, width: usize, height: usize) -> (usize, usize) {
+        (width.div_ceil(self.stride), height.div_ceil(self.stride))
+    }
+
+    /// Window a `kw` x `kh` kernel spans once dilated.
+    pub fn extent(self, kw: usize, kh: usize) -> (usize, usize) {
+        let spread = |side: usize| (side.max(1) - 1) * self.dilation + 1;
+        (spread(kw), spread(kh))
+    }
+
+    /// `kernel` with zeros between its taps, and the size of that window.
+    pub fn dilate(self, kernel: &[f32], kw: usize, kh: usize) -> (Vec<f32>, usize, usize) {
+        let (dw, dh) = self.extent(kw, kh);
+        let mut dilated = vec![0.0; dw * dh];
+        for ky in 0..kh {
+            for kx in 0..kw {
+                dilated[ky * self.dilation * dw + kx * self.dilation] = kernel[ky * kw + kx];
+            }
+        }
+        (dilated, dw, dh)
+    }
+
+    /// The pixels of a dense `width` x `height` map that `self` keeps.
+    pub fn subsample(self, values: &[f32], width: usize, height: usize) -> Vec<f32> {
+        if self.stride == 1 {
+            return values.to_vec();
+        }
+        (0..height)
+            .step_by(self.stride)
+            .flat_map(|y| {
+                values[y * width..(y + 1) * width]
+                    .iter()
+                    .step_by(self.stride)
+            })
+            .copied()
+            .collect()
+    }
+}
+
+/// Whether automatic selection runs an image of `pixels` with a kernel of
+/// `taps` through the FFT while that kernel size has no benchmark yet.
+pub fn prefers_fft(pixels: usize, taps: usize) -> bool {
+    taps >= FFT_MIN_TAPS && pixels.saturating_mul(taps) >= FFT_MIN_WORK
+}
+
+/// Backend selection as configured in the performance panel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BackendChoice {
+    /// Use the fastest backend measured for the current kernel size.
+    #[default]
+    Auto,
+    Fixed(Backend),
+}
+
+impl BackendChoice {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Auto => "Auto (benchmarked)",
+            Self::Fixed(backend) => backend.label(),
+        }
+    }
+}
+
+/// Benchmark outcome for one kernel size, remembered across sessions.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BackendProfile {
+    pub kernel_width: usize,
+    pub kernel_height: usize,
+    /// Fastest CPU backend on the benchmark image.
+    pub fastest: Backend,
+    pub timings: Vec<(Backend, f64)>,
+    /// Milliseconds the GPU took on the benchmark image, when one was
+    /// detected.
+    #[serde(default)]
+    pub gpu: Option<f64>,
+}
+
+impl BackendProfile {
+    /// CPU backend expected to be fastest over `pixels` pixels. Direct
+    /// timings grow with the pixel count and the FFT's with n log n, so the
+    /// FFT can win on a whole slide while losing on the benchmark image.
+    pub fn fastest_for(&self, pixels: usize) -> Backend {
+        self.timings
+            .iter()
+            .map(|&(backend, ms)| (backend, estimate(backend, ms, pixels)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map_or(self.fastest, |(backend, _)| backend)
+    }
+
+    /// Whether the GPU is expected to beat every CPU backend over `pixels`
+    /// pixels.
+    pub fn gpu_fastest_for(&self, pixels: usize) -> bool {
+        let cpu = self.fastest_for(pixels);
+        let Some(&(_, cpu_ms)) = self.timings.iter().find(|(b, _)| *b == cpu) else {
+            return self.gpu.is_some();
+        };
+        self.gpu.is_some_and(|gpu_ms| {
+            // The shader sums every tap per pixel, as the direct backends do.
+            linear(gpu_ms, pixels) < estimate(cpu, cpu_ms, pixels)
+        })
+    }
+}
+
+/// Milliseconds `backend` is expected to take over `pixels` pixels when it
+/// took `ms` on the benchmark image.
+fn estimate(backend: Backend, ms: f64, pixels: usize) -> f64 {
+    match backend {
+        Backend::Fft => {
+            let measured = (BENCHMARK_SIZE * BENCHMARK_SIZE) as f64;
+            let pixels = pixels.max(2) as f64;
+            ms * pixels * pixels.log2() / (measured * measured.log2())
+        }
+        Backend::Scalar | Backend::Vectorized | Backend::Gemm => linear(ms, pixels),
+    }
+}
+
+/// `ms` on the benchmark image scaled to `pixels` pixels.
+fn linear(ms: f64, pixels: usize) -> f64 {
+    ms * pixels as f64 / (BENCHMARK_SIZE * BENCHMARK_SIZE) as f64
+}
+
+/// Timing of every CPU backend, and optionally the GPU, on a synthetic
+/// image with one kernel size. Each [`Benchmark::step`] times a single
+/// repetition, so the browser can spread the benchmark over frames.
+pub struct Benchmark {
+    kw: usize,
+    kh: usize,
+    input: Vec<f32>,
+    kernel: Vec<f32>,
+    /// Index in [`Backend::ALL`] of the backend being timed, and the
+    /// repetitions it has had.
+    next: usize,
+    repeat: usize,
+    best: Vec<(Backend, Duration)>,
+    gpu: Option<f64>,
+}
+
+impl Benchmark {
+    pub fn new(kw: usize, kh: usize) -> Self {
+        let input = (0..BENCHMARK_SIZE * BENCHMARK_SIZE)
+            .map(|i| ((i * 7919) % 251) as f32 / 250.0)
+            .collect();
+        let kernel = (0..kw * kh)
+            .map(|i| ((i * 31) % 17) as f32 / 8.0 - 1.0)
+            .collect();
+        Self {
+            kw,
+            kh,
+            input,
+            kernel,
+            next: 0,
+            repeat: 0,
+            best: Vec::new(),
+            gpu: None,
+        }
+    }
+
+    /// Times one repetition of the next CPU backend. Returns true once every
+    /// backend has been timed.
+    pub fn step(&mut self) -> bool {
+        let Some(&backend) = Backend::ALL.get(self.next) else {
+            return true;
+        };
+        let start = Instant::now();
+        let out = backend.convolve_serial(
+            &self.input,
+            BENCHMARK_SIZE,
+            BENCHMARK_SIZE,
+            &self.kernel,
+            self.kw,
+            self.kh,
+        );
+        std::hint::black_box(out);
+        let elapsed = start.elapsed();
+        match self.best.last_mut() {
+            Some((b, best)) if *b == backend => *best = (*best).min(elapsed),
+            _ => self.best.push((backend, elapsed)),
+        }
+        self.repeat += 1;
+        if self.repeat == BENCHMARK_REPEATS {
+            self.repeat = 0;
+            self.next += 1;
+        }
+        self.next == Backend::ALL.len()
+    }
+
+    /// Times `convolve`, a GPU path, the same number of times as the CPU
+    /// backends. A failing GPU is left out of the profile.
+    pub fn time_gpu(
+        &mut self,
+        mut convolve: impl FnMut(&[f32], usize, usize, &[f32], usize, usize) -> Result<Vec<f32>, String>,
+    ) {
+        let mut best = None::<Duration>;
+        for _ in 0..BENCHMARK_REPEATS {
+            let start = Instant::now();
+            match convolve(
+                &self.input,
+                BENCHMARK_SIZE,
+                BENCHMARK_SIZE,
+                &self.kernel,
+                self.kw,
+                self.kh,
+            ) {
+                Ok(out) => std::hint::black_box(out),
+                Err(e) => {
+                    log::warn!("GPU benchmark failed: {e}");
+                    return;
+                }
+            };
+            let elapsed = start.elapsed();
+            best = Some(best.map_or(elapsed, |b| b.min(elapsed)));
+        }
+        self.record_gpu(best.unwrap_or(Duration::ZERO));
+    }
+
+    /// Records `elapsed` as the GPU's time on the benchmark image.
+    pub fn record_gpu(&mut self, elapsed: Duration) {
+        self.gpu = Some(elapsed.as_secs_f64() * 1000.0);
+    }
+
+    /// The benchmark image and kernel, for GPU paths timed asynchronously.
+    #[cfg(target_arch = "wasm32")]
+    pub fn workload(&self) -> (&[f32], usize, usize, &[f32], usize, usize) {
+        let size = BENCHMARK_SIZE;
+        (&self.input, size, size, &self.kernel, self.kw, self.kh)
+    }
+
+    pub fn finish(self) -> BackendProfile {
+        let timings: Vec<(Backend, f64)> = self
+            .best
+            .into_iter()
+            .map(|(backend, best)| (backend, best.as_secs_f64() * 1000.0))
+            .collect();
+        let fastest = timings
+            .iter()
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(backend, _)| *backend)
+            .unwrap_or(Backend::Scalar);
+        BackendProfile {
+            kernel_width: self.kw,
+            kernel_height: self.kh,
+            fastest,
+            timings,
+            gpu: self.gpu,
+        }
+    }
+}
+
+pub fn convolve_same(
+    input: &[f32],
+    width: usize,
+    height: usize,
+    kernel: &[f32],
+    kw: usize,
+    kh: usize,
+    sampling: Sampling,
+) -> Vec<f32> {
+    let Sampling { stride, dilation } = sampling;
+    let (ow, oh) = sampling.output_size(width, height);
+    let (dw, dh) = sampling.extent(kw, kh);
+    let mut output = vec![0.0; ow * oh];
+    let kcx = dw / 2;
+    let kcy = dh / 2;
+
+    for oy in 0..oh {
+        for ox in 0..ow {
+            let (x, y) = (ox * stride, oy * stride);
+            let mut acc = 0.0;
+            for ky in 0..kh {
+                for kx in 0..kw {
+                    let ix = x as isize + (kx * dilation) as isize - kcx as isize;
+                    let iy = y as isize + (ky * dilation) as isize - kcy as isize;
+                    if ix >= 0 && iy >= 0 && ix < width as isize && iy < height as isize {
+                        let i = iy as usize * width + ix as usize;
+                        let k = ky * kw + kx;
+                        acc += input[i] * kernel[k];
+                    }
+                }
+            }
+            output[oy * ow + ox] = acc;
+        }
+    }
+    output
+}
+
+/// Same output as [`convolve_same`], but accumulates one kernel tap at a time
+/// over row slices, contiguous without a stride. Taps are visited in the
+/// same order, so every pixel sums its terms identically to the scalar path.
+fn convolve_same_vectorized(
+    input: &[f32],
+    width: usize,
+    height: usize,
+    kernel: &[f32],
+    kw: usize,
+    kh: usize,
+    sampling: Sampling,
+) -> Vec<f32> {
+    let Sampling { stride, dilation } = sampling;
+    let (ow, oh) = sampling.output_size(width, height);
+    let (dw, dh) = sampling.extent(kw, kh);
+    let mut output = vec![0.0; ow * oh];
+    let kcx = (dw / 2) as isize;
+    let kcy = (dh / 2) as isize;
+    // Outputs `o` whose input pixel `o * stride + d` lies in `0..len`.
+    let kept = |d: isize, len: usize, outputs: usize| {
+        let first = (-d).max(0) as usize;
+        let end = (len as isize - d).max(0) as usize;
+        (
+            first.div_ceil(stride).min(outputs),
+            end.div_ceil(stride).min(outputs),
+        )
+    };
+
+    for ky in 0..kh {
+        let dy = (ky * dilation) as isize - kcy;
+        let (y0, y1) = kept(dy, height, oh);
+        for kx in 0..kw {
+            let dx = (kx * dilation) as isize - kcx;
+            let (x0, x1) = kept(dx, width, ow);
+            if x0 >= x1 {
+                continue;
+            }
+            let weight = kernel[ky * kw + kx];
+            for y in y0..y1 {
+                let iy = (y * stride) as isize + dy;
+                let out_row = &mut output[y * ow + x0..y * ow + x1];
+                let ix0 = iy as usize * width + ((x0 * stride) as isize + dx) as usize;
+                if stride == 1 {
+                    let in_row = &input[ix0..ix0 + (x1 - x0)];
+                    for (o, &v) in out_row.iter_mut().zip(in_row) {
+                        *o += v * weight;
+                    }
+                } else {
+                    let in_row = input[ix0..].iter().step_by(stride);
+                    for (o, &v) in out_row.iter_mut().zip(in_row) {
+                        *o += v * weight;
+                    }
+                }
+            }
+        }
+    }
+    output
+}
+
+/// Same output as [`convolve_same`], as the product of the patch matrix and
+/// the kernel. Patches are unrolled a block of rows at a time, so the matrix
+/// stays within [`GEMM_BLOCK`] values; taps outside the image are zeros,
+/// which leave each sum bit-identical to the scalar path.
+#[allow(clippy::too_many_arguments)]
+fn convolve_same_gemm(
+    input: &[f32],
+    width: usize,
+    height: usize,
+    kernel: &[f32],
+    kw: usize,
+    kh: usize,
+    sampling: Sampling,
+) -> Vec<f32> {
+    let Sampling { stride, dilation } = sampling;
+    let (ow, oh) = sampling.output_size(width, height);
+    let (dw, dh) = sampling.extent(kw, kh);
+    let (kcx, kcy) = ((dw / 2) as isize, (dh / 2) as isize);
+    let taps = kw * kh;
+    let mut output = vec![0.0; ow * oh];
+    if taps == 0 || ow == 0 {
+        return output;
+    }
+    let rows_per_block = (GEMM_BLOCK / (ow * taps)).max(1);
+    let mut patches = vec![0.0; rows_per_block * ow * taps];
+
+    for (block, out) in output.chunks_mut(rows_per_block * ow).enumerate() {
+        let first = block * rows_per_block;
+        // im2col: one row of `taps` values per output pixel.
+        for (p, patch) in patches.chunks_mut(taps).take(out.len()).enumerate() {
+            let (x, y) = ((p % ow) * stride, (first + p / ow) * stride);
+            for ky in 0..kh {
+                let iy = y as isize + (ky * dilation) as isize - kcy;
+                for kx in 0..kw {
+                    let ix = x as isize + (kx * dilation) as isize - kcx;
+                    let inside = ix >= 0 && iy >= 0 && ix < width as isize && iy < height as isize;
+                    patch[ky * kw + kx] = if inside {
+                        input[iy as usize * width + ix as usize]
+                    } else {
+                        0.0
+                    };
+                }
+            }
+        }
+        // Matrix product with the kernel column, summing taps in order.
+        for (o, patch) in out.iter_mut().zip(patches.chunks(taps)) {
+            *o = patch
+                .iter()
+                .zip(kernel)
+                .fold(0.0, |acc, (v, w)| acc + v * w);
+        }
+    }
+    output
+}
+
+/// Same output as [`convolve_same`] up to float rounding, computed as a
+/// product of spectra. The image and the flipped kernel are zero-padded
+/// past their combined extent, so the circular product holds the full
+/// linear result, and the "same" window is cropped from it.
+fn convolve_same_fft(
+    input: &[f32],
+    width: usize,
+    height: usize,
+    kernel: &[f32],
+    kw: usize,
+    kh: usize,
+) -> Vec<f32> {
+    if width == 0 || height == 0 {
+        return Vec::new();
+    }
+    let (pw, ph) = (fast_len(width + kw - 1), fast_len(height + kh - 1));
+    let mut image = vec![Complex::default(); pw * ph];
+    for y in 0..height {
+        for x in 0..width {
+            image[y * pw + x] = Complex::new(input[y * width + x], 0.0);
+        }
+    }
+    let mut flipped = vec![Complex::default(); pw * ph];
+    for ky in 0..kh {
+        for kx in 0..kw {
+            flipped[(kh - 1 - ky) * pw + kw - 1 - kx] = Complex::new(kernel[ky * kw + kx], 0.0);
+        }
+    }
+    let mut planner = FftPlanner::new();
+    let image = fft2(&mut planner, image, pw, ph, false);
+    let flipped = fft2(&mut planner, flipped, pw, ph, false);
+    let product = image.iter().zip(&flipped).map(|(a, b)| a * b).collect();
+    let full = fft2(&mut planner, product, pw, ph, true);
+
+    let scale = 1.0 / (pw * ph) as f32;
+    let (ox, oy) = (kw - 1 - kw / 2, kh - 1 - kh / 2);
+    (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| full[(y + oy) * pw + x + ox].re * scale)
+        .collect()
+}
+
+/// Smallest length of at least `n` with no prime factor above 5, which the
+/// FFT handles much faster than lengths with large prime factors.
+fn fast_len(n: usize) -> usize {
+    (n.max(1)..)
+        .find(|&len| {
+            let mut m = len;
+            for p in [2, 3, 5] {
+                while m % p == 0 {
+                    m /= p;
+                }
+            }
+            m == 1
+        })
+        .unwrap_or(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(len: usize, seed: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| ((i * 7919 + seed * 104_729) % 251) as f32 / 125.0 - 1.0)
+            .collect()
+    }
+
+    #[test]
+    fn fft_matches_direct_sum() {
+        // Odd and even kernels, and images narrower or shorter than them.
+        for (width, height, kw, kh) in [
+            (31, 23, 5, 5),
+            (31, 23, 4, 6),
+            (17, 20, 8, 3),
+            (3, 2, 7, 5),
+            (1, 9, 4, 4),
+        ] {
+            let input = pattern(width * height, 1);
+            let kernel = pattern(kw * kh, 2);
+            let direct = convolve_same(&input, width, height, &kernel, kw, kh, Sampling::DENSE);
+            let fft = convolve_same_fft(&input, width, height, &kernel, kw, kh);
+            assert_eq!(fft.len(), direct.len());
+            for (i, (a, b)) in fft.iter().zip(&direct).enumerate() {
+                assert!(
+                    (a - b).abs() < 1e-4,
+                    "{width}x{height}, {kw}x{kh} kernel, pixel {i}: {a} vs {b}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn gemm_matches_the_scalar_bits() {
+        let bits = |map: &[f32]| map.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+        // The last image spans several blocks of the patch matrix.
+        for (width, height, kw, kh) in [
+            (31, 23, 5, 5),
+            (17, 20, 8, 3),
+            (3, 2, 7, 5),
+            (300, 260, 9, 9),
+        ] {
+            let input = pattern(width * height, 5);
+            let kernel = pattern(kw * kh, 6);
+            for (stride, dilation) in [(1, 1), (2, 1), (1, 3)] {
+                let sampling = Sampling { stride, dilation };
+                let scalar = convolve_same(&input, width, height, &kernel, kw, kh, sampling);
+                let gemm = convolve_same_gemm(&input, width, height, &kernel, kw, kh, sampling);
+                assert_eq!(bits(&gemm), bits(&scalar), "{width}x{height}, {sampling:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn fast_len_rounds_up_to_5_smooth_lengths() {
+        let cases = [
+            (0, 1),
+            (1, 1),
+            (7, 8),
+            (11, 12),
+            (13, 15),
+            (17, 18),
+            (97, 100),
+            (121, 125),
+        ];
+        for (n, len) in cases {
+            assert_eq!(fast_len(n), len, "{n}");
+        }
+    }
+
+    #[test]
+    fn fft_is_preferred_from_both_thresholds_on() {
+        let pixels = FFT_MIN_WORK / FFT_MIN_TAPS;
+        assert!(prefers_fft(pixels + 1, FFT_MIN_TAPS));
+        assert!(!prefers_fft(pixels - 1, FFT_MIN_TAPS));
+        // Smaller kernels stay direct however large the image.
+        assert!(!prefers_fft(usize::MAX, FFT_MIN_TAPS - 1));
+        assert!(prefers_fft(usize::MAX, FFT_MIN_TAPS));
+    }
+
+    #[test]
+    fn strided_maps_keep_every_nth_pixel() {
+        for (stride, size) in [(2, (16, 11)), (4, (8, 6))] {
+            let sampling = Sampling {
+                stride,
+                dilation: 1,
+            };
+            assert_eq!(sampling.output_size(31, 21), size, "stride {stride}");
+        }
+        let values: Vec<f32> = (0..20).map(|i| i as f32).collect();
+        let sampling = Sampling {
+            stride: 2,
+            dilation: 1,
+        };
+        assert_eq!(
+            sampling.subsample(&values, 5, 4),
+            [0.0, 2.0, 4.0, 10.0, 12.0, 14.0]
+        );
+    }
+
+    #[test]
+    fn dilation_spreads_the_taps() {
+        let sampling = Sampling {
+            stride: 1,
+            dilation: 3,
+        };
+        // Reach of (side - 1) * dilation + 1, so 3 taps span 7 pixels.
+        assert_eq!(sampling.extent(3, 2), (7, 4));
+        assert_eq!(sampling.extent(1, 1), (1, 1));
+        let (dilated, dw, dh) = sampling.dilate(&[1.0, 2.0, 3.0, 4.0], 2, 2);
+        assert_eq!((dw, dh), (4, 4));
+        let mut expected = vec![0.0; 16];
+        for (i, w) in [(0, 1.0), (3, 2.0), (12, 3.0), (15, 4.0)] {
+            expected[i] = w;
+        }
+        assert_eq!(dilated, expected);
+    }
+
+    #[test]
+    fn sampled_maps_match_the_dense_map_of_the_dilated_kernel() {
+        let (width, height, kw, kh) = (29, 22, 3, 4);
+        let input = pattern(width * height, 3);
+        let kernel = pattern(kw * kh, 4);
+        for (stride, dilation) in [(2, 1), (4, 1), (1, 2), (2, 3)] {
+            let sampling = Sampling { stride, dilation };
+            let (dilated, dw, dh) = sampling.dilate(&kernel, kw, kh);
+            let dense = convolve_same(&input, width, height, &dilated, dw, dh, Sampling::DENSE);
+            let expected = sampling.subsample(&dense, width, height);
+            for backend in Backend::ALL {
+                let map =
+                    backend.convolve_sampled(&input, width, height, &kernel, kw, kh, sampling);
+                assert_eq!(map.len(), expected.len());
+                for (a, b) in map.iter().zip(&expected) {
+                    assert!(
+                        (a - b).abs() < 1e-4,
+                        "{backend:?}, {sampling:?}: {a} vs {b}"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use std::path::{Component, Path, PathBuf};
+
+use rayon::prelude::*;
+
+use crate::events::Event;
+use crate::export;
+use crate::pipeline::{self, InputRef, Pipeline, RunOutcome};
+use crate::stats;
+
+/// Result of a batch run over several slides.
+pub struct BatchReport {
+    /// One line per slide, then the aggregate table's location.
+    pub summary: String,
+    pub slides: usize,
+    pub failed: usize,
+}
+
+/// Files matching `pattern`, sorted. `*` matches any run of characters and
+/// `?` any one character within a path component; names starting with a
+/// dot only match components that start with one too.
+pub fn expand_glob(pattern: &str) -> Result<Vec<PathBuf>, String> {
+    let mut candidates = vec![PathBuf::new()];
+    let components: Vec<Component> = Path::new(pattern).components().collect();
+    for (i, component) in components.iter().enumerate() {
+        let last = i + 1 == components.len();
+        let name = component.as_os_str().to_string_lossy();
+        if !matches!(component, Component::Normal(_)) || !name.contains(['*', '?']) {
+            candidates = candidates.into_iter().map(|c| c.join(&*name)).collect();
+            continue;
+        }
+        let mut matched = Vec::new();
+        for dir in &candidates {
+            let listed = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            let Ok(entries) = std::fs::read_dir(listed) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let entry_name = entry.file_name().to_string_lossy().into_owned();
+                let hidden = entry_name.starts_with('.') && !name.starts_with('.');
+                let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+                if !hidden && (last || is_dir) && wildcard_match(&name, &entry_name) {
+                    matched.push(dir.join(entry_name));
+                }
+            }
+        }
+        candidates = matched;
+    }
+    let mut files: Vec<PathBuf> = candidates.into_iter().filter(|p| p.is_file()).collect();
+    files.sort();
+    if files.is_empty() {
+        return Err(format!("No slide matches {pattern}."));
+    }
+    Ok(files)
+}
+
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let (pattern, name): (Vec<char>, Vec<char>) =
+        (pattern.chars().collect(), name.chars().collect());
+    // Position after the last `*` and the name position it was tried at.
+    let (mut p, mut n, mut star) = (0, 0, None);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((after, tried)) => {
+                    (p, n) = (after, tried + 1);
+                    star = Some((after, tried + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Runs `pipeline` once per slide of `slides`, on at most `jobs` slides at
+/// a time. Each slide's outputs go to a folder named after it inside the
+/// pipeline's output folder, and `batch_scores.csv` next to those folders
+/// holds every slide's scores with a leading `slide` column. Fails only
+/// when nothing could be run; slides that fail are counted in the report.
+/// Each slide's progress goes to `events` as it runs, tagged with the
+/// slide's canonical path once it resolves.
+pub fn run_batch(
+    pipeline: &Pipeline,
+    base: &Path,
+    slides: &[PathBuf],
+    jobs: usize,
+    events: &(dyn Fn(&Event) + Sync),
+) -> Result<BatchReport, String> {
+    let names = folder_names(slides);
+    let jobs = jobs.max(1);
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs)
+        .build()
+        .map_err(|e| format!("Cannot start {jobs} worker threads: {e}"))?;
+    // Slides start `jobs` at a time, as threads waiting inside one slide's
+    // convolutions would otherwise steal further slides and their memory.
+    let indices: Vec<usize> = (0..slides.len()).collect();
+    let outcomes: Vec<_> = pool.install(|| {
+        indices
+            .chunks(jobs)
+            .flat_map(|batch| {
+                batch
+                    .par_iter()
+                    .map(|&i| {
+                        let outcome = run_slide(pipeline, base, &slides[i], &names[i], events);
+                        if let Err(error) = &outcome {
+                            let path = std::fs::canonicalize(&slides[i])
+                                .unwrap_or_else(|_| slides[i].clone());
+                            events(&Event::Failed {
+                                slide: path.to_string_lossy().into_owned(),
+                                error: error.clone(),
+                            });
+                        }
+                        outcome
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    });
+
+    let mut lines = Vec::new();
+    let mut rows: Vec<String> = Vec::new();
+    let mut failed = 0;
+    let mut seen: Vec<(String, &Path)> = Vec::new();
+    for ((slide, name), outcome) in slides.iter().zip(&names).zip(outcomes) {
+        match outcome {
+            Ok(outcome) => {
+                lines.push(format!("{}: {}", slide.display(), outcome.summary));
+                match seen.iter().find(|(hash, _)| *hash == outcome.slide_hash) {
+                    Some((_, earlier)) => {
+                        lines.push(format!(
+                            "{}: same content as {}",
+                            slide.display(),
+                            earlier.display()
+                        ));
+                        events(&Event::Duplicate {
+                            slide: slide.to_string_lossy().into_owned(),
+                            same_as: earlier.to_string_lossy().into_owned(),
+                        });
+                    }
+                    None => seen.push((outcome.slide_hash.clone(), slide)),
+                }
+                let records = export::with_leading_column(&outcome.scores, "slide", name);
+                if rows.is_empty() {
+                    rows.extend(records);
+                } else {
+                    rows.extend(records.into_iter().skip(1));
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                lines.push(format!("{}: FAILED: {e}", slide.display()));
+            }
+        }
+    }
+    if failed == slides.len() {
+        return Err(lines.join("\n"));
+    }
+    let csv = pipeline.outputs.csv.apply(&(rows.join("\n") + "\n"));
+    let out_dir = base.join(&pipeline.outputs.dir);
+    let path = export::save_file(
+        &out_dir.to_string_lossy(),
+        "batch_scores.csv",
+        csv.as_bytes(),
+    )?;
+    lines.push(format!(
+        "{} of {} slides scored; aggregate scores in {path}.",
+        slides.len() - failed,
+        slides.len()
+    ));
+    events(&Event::BatchFinished {
+        slides: slides.len(),
+        failed,
+        scores: path,
+    });
+    Ok(BatchReport {
+        summary: lines.join("\n"),
+        slides: slides.len(),
+        failed,
+    })
+}
+
+/// Runs `pipeline` on `slide`, writing to the `name` folder inside the
+/// pipeline's output folder.
+fn run_slide(
+    pipeline: &Pipeline,
+    base: &Path,
+    slide: &Path,
+    name: &str,
+    events: &(dyn Fn(&Event) + Sync),
+) -> Result<RunOutcome, String> {
+    let path = std::fs::canonicalize(slide)
+        .map_err(|e| format!("Cannot read {}: {e}", slide.display()))?;
+    let bytes = std::fs::read(&path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+    let mut slide_pipeline = pipeline.clone();
+    slide_pipeline.slide = InputRef {
+        path: path.to_string_lossy().into_owned(),
+        hash: pipeline::format_hash(stats::content_hash(&bytes)),
+    };
+    drop(bytes);
+    slide_pipeline.outputs.dir = Path::new(&pipeline.outputs.dir)
+        .join(name)
+        .to_string_lossy()
+        .into_owned();
+    pipeline::run(&slide_pipeline, base, events)
+}
+
+/// Output folder of each slide: its file stem, with the position in the
+/// batch appended when two slides share a stem.
+fn folder_names(slides: &[PathBuf]) -> Vec<String> {
+    let stems: Vec<String> = slides
+        .iter()
+        .map(|s| {
+            s.file_stem()
+                .map_or("slide".to_owned(), |n| n.to_string_lossy().into_owned())
+        })
+        .collect();
+    stems
+        .iter()
+        .enumerate()
+        .map(|(i, stem)| {
+            if stems.iter().filter(|s| *s == stem).count() > 1 {
+                format!("{stem}_{i}")
+            } else {
+                stem.clone()
+            }
+        })
+        .collect()
+}
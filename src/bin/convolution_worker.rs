@@ -0,0 +1,10 @@
+//! Web worker computing convolution maps off the browser's main thread; see
+//! `index.html`. It has nothing to do natively.
+
+#[cfg(target_arch = "wasm32")]
+fn main() {
+    convolution_wasm::worker_main();
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {}
@@ -0,0 +1,437 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+/// What a convolution reads for taps that fall outside the image.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum BorderMode {
+    /// Zero, which darkens responses along the slide's edges. Rank filters
+    /// leave such taps out instead.
+    #[default]
+    Zero,
+    /// The nearest edge pixel.
+    Replicate,
+    /// The image mirrored about its edge, edge pixel included (`dcba|abcd`).
+    Reflect,
+    /// The opposite side of the image, as if it tiled the plane.
+    Wrap,
+    /// A fixed intensity.
+    Constant(f32),
+}
+
+impl BorderMode {
+    /// Every mode, with a mid-gray constant.
+    pub const ALL: [Self; 5] = [
+        Self::Zero,
+        Self::Replicate,
+        Self::Reflect,
+        Self::Wrap,
+        Self::Constant(0.5),
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Zero => "Zero",
+            Self::Replicate => "Replicate",
+            Self::Reflect => "Reflect",
+            Self::Wrap => "Wrap",
+            Self::Constant(_) => "Constant",
+        }
+    }
+
+    /// Whether `self` and `other` are the same mode, whatever their
+    /// constants.
+    pub fn same_kind(self, other: Self) -> bool {
+        std::mem::discriminant(&self) == std::mem::discriminant(&other)
+    }
+
+    /// Pixel read for position `i` of a line of `n` pixels, or `None` when
+    /// the mode's constant is read.
+    fn source(self, i: isize, n: usize) -> Option<usize> {
+        let n = n as isize;
+        if (0..n).contains(&i) {
+            return Some(i as usize);
+        }
+        match self {
+            Self::Zero | Self::Constant(_) => None,
+            Self::Replicate => Some(i.clamp(0, n - 1) as usize),
+            Self::Reflect => {
+                let i = i.rem_euclid(2 * n);
+                Some(if i < n { i } else { 2 * n - 1 - i } as usize)
+            }
+            Self::Wrap => Some(i.rem_euclid(n) as usize),
+        }
+    }
+
+    /// Value read at `(x, y)` of a `width` x `height` image, whose pixels
+    /// `pixel` returns, with taps past the edges read through `self`.
+    pub fn sample(
+        self,
+        (x, y): (isize, isize),
+        (width, height): (usize, usize),
+        pixel: impl Fn(usize, usize) -> f32,
+    ) -> f32 {
+        match (self.source(x, width), self.source(y, height)) {
+            (Some(col), Some(row)) => pixel(col, row),
+            _ => self.constant(),
+        }
+    }
+
+    fn constant(self) -> f32 {
+        match self {
+            Self::Constant(value) => value,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Output of a `kw` x `kh` filter over the `cols` x `rows` region of
+/// `input`, with `border` around the image. `filter` is a zero-padded
+/// "same" filter; it receives the region together with the pixels its
+/// kernel reaches around it, read through `border`, and only the region is
+/// kept. With [`BorderMode::Zero`] those pixels stop at the image, so the
+/// zero padding (or the left-out taps of rank filters) lands on the edges.
+#[allow(clippy::too_many_arguments)]
+pub fn filter_region(
+    border: BorderMode,
+    input: &[f32],
+    width: usize,
+    height: usize,
+    cols: Range<usize>,
+    rows: Range<usize>,
+    (kw, kh): (usize, usize),
+    filter: impl FnOnce(&[f32], usize, usize) -> Vec<f32>,
+) -> Vec<f32> {
+    let (left, right, top, bottom) = halo_bounds(border, (width, height), &cols, &rows, (kw, kh));
+    if (left, top, right, bottom) == (0, 0, width as isize, height as isize) {
+        let response = filter(input, width, height);
+        return crop(&response, width, cols, rows);
+    }
+    filter_rows(
+        border,
+        |row| &input[row * width..(row + 1) * width],
+        width,
+        height,
+        cols,
+        rows,
+        (kw, kh),
+        filter,
+    )
+}
+
+/// [`filter_region`] over an image whose rows `row` returns, for images
+/// that are not held in memory as a whole. Only the rows the region's halo
+/// reads through `border` are asked for.
+#[allow(clippy::too_many_arguments)]
+pub fn filter_rows<'a>(
+    border: BorderMode,
+    row: impl Fn(usize) -> &'a [f32],
+    width: usize,
+    height: usize,
+    cols: Range<usize>,
+    rows: Range<usize>,
+    (kw, kh): (usize, usize),
+    filter: impl FnOnce(&[f32], usize, usize) -> Vec<f32>,
+) -> Vec<f32> {
+    let (left, right, top, bottom) = halo_bounds(border, (width, height), &cols, &rows, (kw, kh));
+    let window_width = (right - left) as usize;
+    let window = read_window(border, row, (width, height), left..right, top..bottom);
+    let response = filter(&window, window_width, (bottom - top) as usize);
+    let offset_x = (cols.start as isize - left) as usize;
+    let offset_y = (rows.start as isize - top) as usize;
+    crop(
+        &response,
+        window_width,
+        offset_x..offset_x + cols.len(),
+        offset_y..offset_y + rows.len(),
+    )
+}
+
+/// [`filter_region`] over the whole image.
+pub fn filter_image(
+    border: BorderMode,
+    input: &[f32],
+    width: usize,
+    height: usize,
+    kernel_size: (usize, usize),
+    filter: impl FnOnce(&[f32], usize, usize) -> Vec<f32>,
+) -> Vec<f32> {
+    if border == BorderMode::Zero {
+        return filter(input, width, height);
+    }
+    filter_region(
+        border,
+        input,
+        width,
+        height,
+        0..width,
+        0..height,
+        kernel_size,
+        filter,
+    )
+}
+
+/// [`filter_image`] for a filter that keeps every `stride`-th pixel of what
+/// it is given along both axes, from the first, for a kernel reaching over
+/// a `kw` x `kh` window. The halo before the image is rounded up to whole
+/// strides, so the pixels kept in the padded image are those kept in the
+/// image.
+pub fn filter_image_strided(
+    border: BorderMode,
+    input: &[f32],
+    width: usize,
+    height: usize,
+    (kw, kh): (usize, usize),
+    stride: usize,
+    filter: impl FnOnce(&[f32], usize, usize) -> Vec<f32>,
+) -> Vec<f32> {
+    if border == BorderMode::Zero {
+        return filter(input, width, height);
+    }
+    let (left, top) = (
+        (kw / 2).next_multiple_of(stride),
+        (kh / 2).next_multiple_of(stride),
+    );
+    let (right, bottom) = (kw - 1 - kw / 2, kh - 1 - kh / 2);
+    let (window_width, window_height) = (left + width + right, top + height + bottom);
+    let window = read_window(
+        border,
+        |row| &input[row * width..(row + 1) * width],
+        (width, height),
+        -(left as isize)..(width + right) as isize,
+        -(top as isize)..(height + bottom) as isize,
+    );
+    let response = filter(&window, window_width, window_height);
+    let (first_col, first_row) = (left / stride, top / stride);
+    crop(
+        &response,
+        window_width.div_ceil(stride),
+        first_col..first_col + width.div_ceil(stride),
+        first_row..first_row + height.div_ceil(stride),
+    )
+}
+
+/// Pixels at columns `xs` and rows `ys` of an image whose rows `row`
+/// returns, read through `border` past its edges, row-major.
+fn read_window<'a>(
+    border: BorderMode,
+    row: impl Fn(usize) -> &'a [f32],
+    (width, height): (usize, usize),
+    xs: Range<isize>,
+    ys: Range<isize>,
+) -> Vec<f32> {
+    let window_width = xs.len();
+    let mut window = Vec::with_capacity(window_width * ys.len());
+    for y in ys {
+        match border.source(y, height) {
+            Some(source) => {
+                let source = row(source);
+                window.extend(xs.clone().map(|x| {
+                    border
+                        .source(x, width)
+                        .map_or(border.constant(), |col| source[col])
+                }))
+            }
+            None => window.extend(std::iter::repeat_n(border.constant(), window_width)),
+        }
+    }
+    window
+}
+
+/// Left, right, top and bottom of the pixels a `kw` x `kh` filter reads
+/// around the `cols` x `rows` region, past the image's edges unless
+/// `border` is zero.
+fn halo_bounds(
+    border: BorderMode,
+    (width, height): (usize, usize),
+    cols: &Range<usize>,
+    rows: &Range<usize>,
+    (kw, kh): (usize, usize),
+) -> (isize, isize, isize, isize) {
+    let (before_x, after_x) = (kw / 2, kw - 1 - kw / 2);
+    let (before_y, after_y) = (kh / 2, kh - 1 - kh / 2);
+    if border == BorderMode::Zero {
+        (
+            cols.start.saturating_sub(before_x) as isize,
+            (cols.end + after_x).min(width) as isize,
+            rows.start.saturating_sub(before_y) as isize,
+            (rows.end + after_y).min(height) as isize,
+        )
+    } else {
+        (
+            cols.start as isize - before_x as isize,
+            (cols.end + after_x) as isize,
+            rows.start as isize - before_y as isize,
+            (rows.end + after_y) as isize,
+        )
+    }
+}
+
+fn crop(image: &[f32], width: usize, cols: Range<usize>, rows: Range<usize>) -> Vec<f32> {
+    if cols == (0..width) && rows.len() * width == image.len() {
+        return image.to_vec();
+    }
+    rows.flat_map(|row| image[row * width + cols.start..row * width + cols.end].iter())
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Zero-padded "same" box sum, as the filters passed in are.
+    fn box_sum(input: &[f32], width: usize, height: usize, (kw, kh): (usize, usize)) -> Vec<f32> {
+        let pixel = |x: isize, y: isize| {
+            let inside = (0..width as isize).contains(&x) && (0..height as isize).contains(&y);
+            if inside {
+                input[y as usize * width + x as usize]
+            } else {
+                0.0
+            }
+        };
+        window_sums(width, height, (kw, kh), pixel)
+    }
+
+    fn window_sums(
+        width: usize,
+        height: usize,
+        (kw, kh): (usize, usize),
+        pixel: impl Fn(isize, isize) -> f32,
+    ) -> Vec<f32> {
+        let mut output = Vec::with_capacity(width * height);
+        for y in 0..height as isize {
+            for x in 0..width as isize {
+                let mut sum = 0.0;
+                for ky in 0..kh as isize {
+                    for kx in 0..kw as isize {
+                        sum += pixel(x + kx - (kw / 2) as isize, y + ky - (kh / 2) as isize);
+                    }
+                }
+                output.push(sum);
+            }
+        }
+        output
+    }
+
+    /// Box sums with every tap read through `border`.
+    fn reference(border: BorderMode, input: &[f32], width: usize, height: usize) -> Vec<f32> {
+        window_sums(width, height, KERNEL, |x, y| {
+            border.sample((x, y), (width, height), |col, row| input[row * width + col])
+        })
+    }
+
+    /// Even along x, so the kernel reaches further left than right.
+    const KERNEL: (usize, usize) = (4, 3);
+    const WIDTH: usize = 7;
+    const HEIGHT: usize = 6;
+
+    fn image() -> Vec<f32> {
+        (0..WIDTH * HEIGHT)
+            .map(|i| ((i * 37) % 11) as f32)
+            .collect()
+    }
+
+    #[test]
+    fn window_reads_each_border_past_the_edges() {
+        let rows = [[1.0, 2.0, 3.0, 4.0], [5.0, 6.0, 7.0, 8.0]];
+        let read = |border, ys| read_window(border, |row| &rows[row][..], (4, 2), -2..6, ys);
+        let cases = [
+            (BorderMode::Zero, [0.0, 0.0, 1.0, 2.0, 3.0, 4.0, 0.0, 0.0]),
+            (
+                BorderMode::Replicate,
+                [1.0, 1.0, 1.0, 2.0, 3.0, 4.0, 4.0, 4.0],
+            ),
+            (
+                BorderMode::Reflect,
+                [2.0, 1.0, 1.0, 2.0, 3.0, 4.0, 4.0, 3.0],
+            ),
+            (BorderMode::Wrap, [3.0, 4.0, 1.0, 2.0, 3.0, 4.0, 1.0, 2.0]),
+            (
+                BorderMode::Constant(0.5),
+                [0.5, 0.5, 1.0, 2.0, 3.0, 4.0, 0.5, 0.5],
+            ),
+        ];
+        for (border, row) in cases {
+            assert_eq!(read(border, 0..1), row, "{border:?}");
+        }
+        // Rows above the image.
+        assert_eq!(
+            read(BorderMode::Replicate, -1..0),
+            read(BorderMode::Replicate, 0..1)
+        );
+        assert_eq!(
+            read(BorderMode::Reflect, -1..0),
+            read(BorderMode::Reflect, 0..1)
+        );
+        assert_eq!(read(BorderMode::Wrap, -1..0), read(BorderMode::Wrap, 1..2));
+        assert_eq!(read(BorderMode::Constant(0.5), -1..0), [0.5; 8]);
+    }
+
+    #[test]
+    fn filters_read_taps_through_the_border() {
+        let input = image();
+        let filter = |i: &[f32], w, h| box_sum(i, w, h, KERNEL);
+        for border in BorderMode::ALL {
+            let expected = reference(border, &input, WIDTH, HEIGHT);
+            let whole = filter_image(border, &input, WIDTH, HEIGHT, KERNEL, filter);
+            assert_eq!(whole, expected, "{border:?}");
+            let (cols, rows) = (1..6, 2..5);
+            let region = filter_region(
+                border,
+                &input,
+                WIDTH,
+                HEIGHT,
+                cols.clone(),
+                rows.clone(),
+                KERNEL,
+                filter,
+            );
+            assert_eq!(region, crop(&expected, WIDTH, cols, rows), "{border:?}");
+        }
+    }
+
+    #[test]
+    fn strided_filters_keep_the_image_pixels() {
+        let input = image();
+        for stride in [2, 3] {
+            let filter = |i: &[f32], w: usize, h: usize| {
+                let sums = box_sum(i, w, h, KERNEL);
+                (0..h)
+                    .step_by(stride)
+                    .flat_map(|y| (0..w).step_by(stride).map(move |x| y * w + x))
+                    .map(|i| sums[i])
+                    .collect()
+            };
+            for border in BorderMode::ALL {
+                let dense = reference(border, &input, WIDTH, HEIGHT);
+                let expected: Vec<f32> = (0..HEIGHT)
+                    .step_by(stride)
+                    .flat_map(|y| (0..WIDTH).step_by(stride).map(move |x| y * WIDTH + x))
+                    .map(|i| dense[i])
+                    .collect();
+                let strided =
+                    filter_image_strided(border, &input, WIDTH, HEIGHT, KERNEL, stride, filter);
+                assert_eq!(strided, expected, "stride {stride}, {border:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn halo_stops_at_the_image_only_for_zero() {
+        let (cols, rows) = (1..3, 0..2);
+        let halo = |border| halo_bounds(border, (4, 2), &cols, &rows, (5, 4));
+        assert_eq!(halo(BorderMode::Zero), (0, 4, 0, 2));
+        for border in &BorderMode::ALL[1..] {
+            assert_eq!(halo(*border), (-1, 5, -2, 3), "{border:?}");
+        }
+    }
+
+    #[test]
+    fn crop_copies_the_region() {
+        let image: Vec<f32> = (0..12).map(|i| i as f32).collect();
+        assert_eq!(crop(&image, 4, 0..4, 0..3), image);
+        assert_eq!(crop(&image, 4, 1..3, 1..3), [5.0, 6.0, 9.0, 10.0]);
+        assert_eq!(crop(&image, 4, 0..4, 2..3), [8.0, 9.0, 10.0, 11.0]);
+    }
+}
@@ -0,0 +1,230 @@
+use std::sync::mpsc::{self, Receiver};
+
+use js_sys::{Promise, Reflect, Uint8Array};
+use wasm_bindgen::JsCast;
+use wasm_bindgen::prelude::{Closure, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    FileSystemDirectoryHandle, FileSystemFileHandle, FileSystemGetFileOptions,
+    FileSystemWritableFileStream, IdbDatabase, IdbRequest, IdbTransactionMode,
+};
+
+use crate::file_dialog;
+
+/// Image kept in browser storage under `key`, with the name it was loaded
+/// under.
+pub struct CachedFile {
+    pub key: &'static str,
+    pub name: String,
+    pub bytes: Vec<u8>,
+}
+
+/// The cached images, as each is read back.
+pub type Restore = Receiver<Result<CachedFile, String>>;
+
+/// Keeps `bytes`, loaded as `name`, under `key` in the background, replacing
+/// what was kept there. The name is written last, so an interrupted write
+/// restores nothing rather than a truncated image.
+pub fn store(key: &'static str, name: String, bytes: Vec<u8>) {
+    wasm_bindgen_futures::spawn_local(async move {
+        let written = async {
+            let storage = Storage::open().await?;
+            storage.remove(&format!("{key}.name")).await;
+            storage.write(&format!("{key}.bin"), &bytes).await?;
+            storage.write(&format!("{key}.name"), name.as_bytes()).await
+        };
+        if let Err(e) = written.await {
+            log::warn!("Cannot keep {name} in browser storage: {e}");
+        }
+    });
+}
+
+/// Reads back what is kept under each of `keys`, skipping keys with nothing
+/// kept, and asks `ctx` to repaint as each arrives.
+pub fn restore(keys: &'static [&'static str], ctx: egui::Context) -> Restore {
+    let (sender, receiver) = mpsc::channel();
+    wasm_bindgen_futures::spawn_local(async move {
+        let storage = match Storage::open().await {
+            Ok(storage) => storage,
+            Err(e) => {
+                let _ = sender.send(Err(e));
+                return;
+            }
+        };
+        for &key in keys {
+            let Some(name) = storage.read(&format!("{key}.name")).await else {
+                continue;
+            };
+            let result = match storage.read(&format!("{key}.bin")).await {
+                Some(bytes) => Ok(CachedFile {
+                    key,
+                    name: String::from_utf8_lossy(&name).into_owned(),
+                    bytes,
+                }),
+                None => Err(format!("The stored copy of {key} is missing.")),
+            };
+            let _ = sender.send(result);
+            ctx.request_repaint();
+        }
+    });
+    receiver
+}
+
+/// Deletes what is kept under each of `keys`.
+pub fn forget(keys: &'static [&'static str]) {
+    wasm_bindgen_futures::spawn_local(async move {
+        let Ok(storage) = Storage::open().await else {
+            return;
+        };
+        for key in keys {
+            storage.remove(&format!("{key}.name")).await;
+            storage.remove(&format!("{key}.bin")).await;
+        }
+    });
+}
+
+/// Where images are kept: the origin private file system, or IndexedDB
+/// where the page cannot write files. Safari only offers `createWritable`
+/// to workers, so it always keeps them in IndexedDB.
+enum Storage {
+    Files(FileSystemDirectoryHandle),
+    Database(IdbDatabase),
+}
+
+/// Object store and database that hold the images under IndexedDB.
+const STORE: &str = "files";
+const DATABASE: &str = "convolution_wasm";
+
+impl Storage {
+    async fn open() -> Result<Self, String> {
+        let window = web_sys::window().ok_or("This page has no window.")?;
+        if writable_files() {
+            let root = wait(window.navigator().storage().get_directory()).await?;
+            return Ok(Self::Files(root.unchecked_into()));
+        }
+        let request = window
+            .indexed_db()
+            .ok()
+            .flatten()
+            .ok_or("This browser has no storage for pages.")?
+            .open_with_u32(DATABASE, 1)
+            .map_err(describe)?;
+        let upgrade = Closure::once_into_js({
+            let request = request.clone();
+            move || {
+                if let Ok(database) = request.result() {
+                    let _ = database
+                        .unchecked_into::<IdbDatabase>()
+                        .create_object_store(STORE);
+                }
+            }
+        });
+        request.set_onupgradeneeded(Some(upgrade.unchecked_ref()));
+        Ok(Self::Database(finish(&request).await?.unchecked_into()))
+    }
+
+    async fn write(&self, file: &str, bytes: &[u8]) -> Result<(), String> {
+        match self {
+            Self::Files(root) => {
+                let options = FileSystemGetFileOptions::new();
+                options.set_create(true);
+                let handle: FileSystemFileHandle =
+                    wait(root.get_file_handle_with_options(file, &options))
+                        .await?
+                        .unchecked_into();
+                let writable: FileSystemWritableFileStream =
+                    wait(handle.create_writable()).await?.unchecked_into();
+                wait(writable.write_with_u8_array(bytes).map_err(describe)?).await?;
+                wait(writable.close()).await.map(|_| ())
+            }
+            Self::Database(database) => {
+                let request = database
+                    .transaction_with_str_and_mode(STORE, IdbTransactionMode::Readwrite)
+                    .and_then(|t| t.object_store(STORE))
+                    .and_then(|s| s.put_with_key(&Uint8Array::from(bytes), &file.into()))
+                    .map_err(describe)?;
+                finish(&request).await.map(|_| ())
+            }
+        }
+    }
+
+    /// Contents of `file`, `None` when it does not exist or cannot be read.
+    async fn read(&self, file: &str) -> Option<Vec<u8>> {
+        match self {
+            Self::Files(root) => {
+                let handle: FileSystemFileHandle = wait(root.get_file_handle(file))
+                    .await
+                    .ok()?
+                    .unchecked_into();
+                let file = wait(handle.get_file()).await.ok()?;
+                file_dialog::read(&file.unchecked_into()).await.ok()
+            }
+            Self::Database(database) => {
+                let request = database
+                    .transaction_with_str(STORE)
+                    .and_then(|t| t.object_store(STORE))
+                    .and_then(|s| s.get(&file.into()))
+                    .ok()?;
+                let bytes = finish(&request).await.ok()?;
+                Some(bytes.dyn_into::<Uint8Array>().ok()?.to_vec())
+            }
+        }
+    }
+
+    async fn remove(&self, file: &str) {
+        match self {
+            Self::Files(root) => {
+                let _ = wait(root.remove_entry(file)).await;
+            }
+            Self::Database(database) => {
+                if let Ok(request) = database
+                    .transaction_with_str_and_mode(STORE, IdbTransactionMode::Readwrite)
+                    .and_then(|t| t.object_store(STORE))
+                    .and_then(|s| s.delete(&file.into()))
+                {
+                    let _ = finish(&request).await;
+                }
+            }
+        }
+    }
+}
+
+/// Whether the page can write files to the private file system. The
+/// bindings cannot tell a missing method from a failing one, so this looks
+/// for `createWritable` on the file handles' prototype.
+fn writable_files() -> bool {
+    Reflect::get(&js_sys::global(), &"FileSystemFileHandle".into())
+        .and_then(|class| Reflect::get(&class, &"prototype".into()))
+        .and_then(|prototype| Reflect::has(&prototype, &"createWritable".into()))
+        .unwrap_or(false)
+}
+
+async fn wait(promise: Promise) -> Result<JsValue, String> {
+    JsFuture::from(promise).await.map_err(describe)
+}
+
+/// Result of an IndexedDB request, once it succeeds.
+async fn finish(request: &IdbRequest) -> Result<JsValue, String> {
+    let promise = Promise::new(&mut |resolve, reject| {
+        let done = request.clone();
+        let success = Closure::once_into_js(move || {
+            let _ = resolve.call1(&JsValue::NULL, &done.result().unwrap_or_default());
+        });
+        let failed = request.clone();
+        let error = Closure::once_into_js(move || {
+            let error = failed.error().ok().flatten().map(JsValue::from);
+            let _ = reject.call1(&JsValue::NULL, &error.unwrap_or_default());
+        });
+        request.set_onsuccess(Some(success.unchecked_ref()));
+        request.set_onerror(Some(error.unchecked_ref()));
+    });
+    wait(promise).await
+}
+
+fn describe(error: JsValue) -> String {
+    error
+        .dyn_ref::<js_sys::Error>()
+        .map(|e| String::from(e.message()))
+        .or_else(|| error.as_string())
+        .unwrap_or_else(|| format!("{error:?}"))
+}
@@ -0,0 +1,86 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+use crate::export;
+use crate::stats;
+
+/// Gain and offset applied to every response before it is drawn or
+/// exported, with the calibrated values drawn on one fixed scale, so maps
+/// of different sessions and slides compare by their colors. Scores are
+/// computed from the raw responses either way.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Calibration {
+    pub enabled: bool,
+    pub gain: f32,
+    pub offset: f32,
+    /// Calibrated values spread over the colormap from end to end; values
+    /// beyond it are clamped.
+    pub range: (f32, f32),
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            gain: 1.0,
+            offset: 0.0,
+            range: (-3.0, 3.0),
+        }
+    }
+}
+
+impl Calibration {
+    /// Value drawn and exported for the raw response `r`.
+    pub fn apply(self, r: f32) -> f32 {
+        if self.enabled {
+            self.gain * r + self.offset
+        } else {
+            r
+        }
+    }
+
+    /// `response` calibrated; itself while calibration is off.
+    pub fn calibrated(self, response: &[f32]) -> Cow<'_, [f32]> {
+        if self.enabled {
+            Cow::Owned(response.iter().map(|&r| self.apply(r)).collect())
+        } else {
+            Cow::Borrowed(response)
+        }
+    }
+
+    /// Interval of drawn values spread over the colormap: the fixed range
+    /// while calibrated, `own`, the map's own extremes, otherwise.
+    pub fn display_range(self, own: (f32, f32)) -> (f32, f32) {
+        if self.enabled { self.range } else { own }
+    }
+
+    /// Turned on, with the gain and offset that bring the raw responses of
+    /// a reference region to mean 0 and standard deviation 1. The display
+    /// range is kept.
+    pub fn fitted_to(self, region: &[f32]) -> Result<Self, String> {
+        let (mean, std) = stats::mean_std(region);
+        if std.is_nan() || std <= 1e-12 {
+            return Err(
+                "The reference region responds uniformly; pick one with some texture.".to_owned(),
+            );
+        }
+        Ok(Self {
+            enabled: true,
+            gain: (1.0 / std) as f32,
+            offset: (-mean / std) as f32,
+            range: self.range,
+        })
+    }
+
+    /// PNG of `response` as exports write it: calibrated on the fixed range,
+    /// or stretched over its own extremes.
+    pub fn map_png(self, response: &[f32], width: usize, height: usize) -> Result<Vec<u8>, String> {
+        if self.enabled {
+            export::response_png_in(&self.calibrated(response), width, height, self.range)
+        } else {
+            export::response_png(response, width, height)
+        }
+    }
+}
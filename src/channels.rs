@@ -0,0 +1,124 @@
+use image::RgbImage;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::{Backend, Sampling};
+use crate::border::{self, BorderMode};
+use crate::kernel::Kernel;
+use crate::morphology::FilterMode;
+
+/// Color channels of a slide that runs convolve. Gray slides have only
+/// their gray levels, which every mode convolves.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChannelMode {
+    /// Gray levels, after stain normalization.
+    #[default]
+    Luma,
+    Red,
+    Green,
+    Blue,
+    /// Each kernel over red, green and blue in turn, the three responses
+    /// averaged.
+    PerChannel,
+    /// Each channel convolved with the same channel of a kernel cut from a
+    /// color sheet, the three responses averaged. Kernels without color
+    /// weights use their weights on every channel, and rank filters, which
+    /// have no weights to split, work as in [`ChannelMode::PerChannel`].
+    AllChannels,
+}
+
+impl ChannelMode {
+    pub const ALL: [Self; 6] = [
+        Self::Luma,
+        Self::Red,
+        Self::Green,
+        Self::Blue,
+        Self::PerChannel,
+        Self::AllChannels,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Luma => "Luma",
+            Self::Red => "Red",
+            Self::Green => "Green",
+            Self::Blue => "Blue",
+            Self::PerChannel => "Per channel",
+            Self::AllChannels => "All channels (color kernels)",
+        }
+    }
+
+    /// Whether runs convolve the three color planes separately.
+    pub fn is_multichannel(self) -> bool {
+        matches!(self, Self::PerChannel | Self::AllChannels)
+    }
+}
+
+/// Intensities in `[0, 1]` of `rgb`, row-major, that single-channel code
+/// reads in `mode`: the chosen channel, or the mean of the three for
+/// multi-channel modes and luma, whose stain-normalized gray levels come
+/// from the caller instead.
+pub fn intensity(rgb: &RgbImage, mode: ChannelMode) -> Vec<f32> {
+    let channel = match mode {
+        ChannelMode::Red => Some(0),
+        ChannelMode::Green => Some(1),
+        ChannelMode::Blue => Some(2),
+        ChannelMode::Luma | ChannelMode::PerChannel | ChannelMode::AllChannels => None,
+    };
+    rgb.pixels()
+        .map(|p| match channel {
+            Some(c) => p[c] as f32 / 255.0,
+            None => p.0.iter().map(|&v| v as f32).sum::<f32>() / (3.0 * 255.0),
+        })
+        .collect()
+}
+
+/// Red, green and blue planes of `rgb` in `[0, 1]`.
+pub fn planes(rgb: &RgbImage) -> Vec<Vec<f32>> {
+    (0..3)
+        .map(|c| rgb.pixels().map(|p| p[c] as f32 / 255.0).collect())
+        .collect()
+}
+
+/// Response of `kernel` in `mode` to the color `planes` of a multi-channel
+/// run in `channels`, at the pixels `sampling` keeps: the mean of its
+/// responses to each plane, with the plane's own weights in
+/// [`ChannelMode::AllChannels`].
+#[allow(clippy::too_many_arguments)]
+pub fn filter(
+    kernel: &Kernel,
+    channels: ChannelMode,
+    mode: FilterMode,
+    backend: Backend,
+    border: BorderMode,
+    sampling: Sampling,
+    planes: &[Vec<f32>],
+    width: usize,
+    height: usize,
+) -> Vec<f32> {
+    let own_weights = channels == ChannelMode::AllChannels && mode.is_linear();
+    let oriented = kernel.oriented(mode);
+    let (ow, oh) = sampling.output_size(width, height);
+    let mut sum = vec![0.0; ow * oh];
+    for (c, plane) in planes.iter().enumerate() {
+        let response = match oriented.channel_weights(c).filter(|_| own_weights) {
+            Some(weights) => {
+                let (kw, kh) = (kernel.width, kernel.height);
+                let extent = sampling.extent(kw, kh);
+                border::filter_image_strided(
+                    border,
+                    plane,
+                    width,
+                    height,
+                    extent,
+                    sampling.stride,
+                    |i, w, h| backend.convolve_sampled(i, w, h, &weights, kw, kh, sampling),
+                )
+            }
+            None => kernel.filter_sampled(mode, backend, border, sampling, plane, width, height),
+        };
+        sum.iter_mut().zip(response).for_each(|(s, r)| *s += r);
+    }
+    let planes = planes.len().max(1) as f32;
+    sum.iter_mut().for_each(|s| *s /= planes);
+    sum
+}
@@ -0,0 +1,237 @@
+use std::sync::Arc;
+
+use web_time::{Duration, Instant};
+
+use crate::backend::{Backend, Sampling};
+use crate::border::{self, BorderMode};
+use crate::channels::{self, ChannelMode};
+use crate::kernel::Kernel;
+use crate::morphology::FilterMode;
+use crate::worker::WorkerRun;
+
+/// CPU time spent per frame, leaving the rest of a 60 Hz frame for egui.
+const FRAME_BUDGET: Duration = Duration::from_millis(10);
+/// Each chunk aims at this duration so the budget is not overshot by much.
+const CHUNK_TARGET: Duration = Duration::from_millis(3);
+
+/// Full-resolution CPU convolutions in the browser. They run in a web worker
+/// when one starts, leaving the main thread free. Otherwise they are split
+/// into bands of rows and spread over frames on the main thread. Each band
+/// is convolved with the rows its kernel reaches above and below, so the
+/// maps are bit-identical to one [`Kernel::filter`] call. The band height
+/// adapts to the measured speed so that a frame never spends much more than
+/// [`FRAME_BUDGET`]. Strided and multi-channel runs are not split into bands:
+/// without a worker they convolve one whole kernel per frame.
+pub struct ChunkedRun {
+    /// Kernel indices with the kernels, in the order they are computed.
+    kernels: Vec<(usize, Kernel)>,
+    border: BorderMode,
+    sampling: Sampling,
+    channels: ChannelMode,
+    /// Color planes of a multi-channel run; empty otherwise.
+    planes: Arc<[Vec<f32>]>,
+    worker: WorkerState,
+    next_kernel: usize,
+    /// First output row of the next band.
+    next_row: usize,
+    output: Vec<f32>,
+    rows_per_chunk: usize,
+    width: usize,
+    pub started: Instant,
+}
+
+enum WorkerState {
+    NotStarted,
+    Running(WorkerRun),
+    /// Remaining kernels are convolved on the main thread.
+    Unavailable,
+}
+
+impl ChunkedRun {
+    pub fn new(
+        kernels: Vec<(usize, Kernel)>,
+        border: BorderMode,
+        width: usize,
+        height: usize,
+    ) -> Self {
+        Self {
+            kernels,
+            border,
+            sampling: Sampling::DENSE,
+            channels: ChannelMode::Luma,
+            planes: Arc::from([]),
+            worker: WorkerState::NotStarted,
+            next_kernel: 0,
+            next_row: 0,
+            output: vec![0.0; width * height],
+            rows_per_chunk: 1,
+            width,
+            started: Instant::now(),
+        }
+    }
+
+    /// Keeps the pixels and tap spacing of `sampling`, and convolves the
+    /// color `planes` in `channels` when there are any.
+    pub fn sampled(
+        mut self,
+        sampling: Sampling,
+        channels: ChannelMode,
+        planes: Arc<[Vec<f32>]>,
+    ) -> Self {
+        self.sampling = sampling;
+        self.channels = channels;
+        self.planes = planes;
+        self
+    }
+
+    /// Whether kernels are convolved band by band without a worker.
+    fn is_banded(&self) -> bool {
+        self.sampling.is_dense() && self.planes.is_empty()
+    }
+
+    /// Kernels in the run.
+    pub fn len(&self) -> usize {
+        self.kernels.len()
+    }
+
+    /// Fraction of the run's kernels convolved, counting the rows of the
+    /// kernel in progress on the main thread.
+    pub fn progress(&self) -> f32 {
+        let rows = self.next_row as f32 * self.width as f32 / self.output.len().max(1) as f32;
+        (self.next_kernel as f32 + rows) / self.kernels.len().max(1) as f32
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.next_kernel >= self.kernels.len()
+    }
+
+    /// Pixel-tap products of the kernels completed so far.
+    pub fn work(&self) -> u64 {
+        let taps: usize = self.kernels[..self.next_kernel]
+            .iter()
+            .map(|(_, k)| k.taps())
+            .sum();
+        let height = self.output.len() / self.width.max(1);
+        let (map_width, map_height) = self.sampling.output_size(self.width, height);
+        (taps * map_width * map_height) as u64
+    }
+
+    /// Collects the maps the worker finished or, without one, convolves
+    /// bands until the frame budget is spent. Returns the maps completed
+    /// meanwhile, with their kernel indices.
+    pub fn step(
+        &mut self,
+        input: &[f32],
+        width: usize,
+        height: usize,
+        backend: Backend,
+        mode: FilterMode,
+    ) -> Vec<(usize, Vec<f32>)> {
+        let mut completed = Vec::new();
+        if let WorkerState::NotStarted = self.worker {
+            let started = WorkerRun::start(
+                &self.kernels,
+                input,
+                &self.planes,
+                (width, height),
+                backend,
+                mode,
+                (self.border, self.sampling, self.channels),
+            );
+            self.worker = match started {
+                Ok(worker) => WorkerState::Running(worker),
+                Err(e) => {
+                    log::warn!("{e}; convolving on the main thread.");
+                    WorkerState::Unavailable
+                }
+            };
+        }
+        if let WorkerState::Running(worker) = &mut self.worker {
+            match worker.receive() {
+                Ok(maps) => {
+                    for response in maps {
+                        completed.push((self.kernels[self.next_kernel].0, response));
+                        self.next_kernel += 1;
+                    }
+                    return completed;
+                }
+                Err(e) => {
+                    log::warn!("{e}; convolving on the main thread.");
+                    self.worker = WorkerState::Unavailable;
+                }
+            }
+        }
+
+        if !self.is_banded() {
+            if let Some((index, kernel)) = self.kernels.get(self.next_kernel) {
+                let response = if self.planes.is_empty() {
+                    let (border, sampling) = (self.border, self.sampling);
+                    kernel.filter_sampled(mode, backend, border, sampling, input, width, height)
+                } else {
+                    channels::filter(
+                        kernel,
+                        self.channels,
+                        mode,
+                        backend,
+                        self.border,
+                        self.sampling,
+                        &self.planes,
+                        width,
+                        height,
+                    )
+                };
+                completed.push((*index, response));
+                self.next_kernel += 1;
+            }
+            return completed;
+        }
+
+        let frame = Instant::now();
+        let mut chunk_time = Duration::ZERO;
+        while !self.is_done() && frame.elapsed() + chunk_time <= FRAME_BUDGET {
+            let chunk = Instant::now();
+            let rows = self.rows_per_chunk.min(height - self.next_row);
+            self.convolve_band(input, width, height, backend, mode, rows);
+            chunk_time = chunk.elapsed();
+            // At most doubles per chunk, since coarse browser timers can
+            // report zero for short chunks.
+            let scaled =
+                rows as f64 * CHUNK_TARGET.as_secs_f64() / chunk_time.as_secs_f64().max(1e-6);
+            self.rows_per_chunk = (scaled as usize).clamp(1, (rows * 2).max(1));
+
+            if self.next_row == height {
+                let index = self.kernels[self.next_kernel].0;
+                let response = std::mem::replace(&mut self.output, vec![0.0; width * height]);
+                completed.push((index, response));
+                self.next_kernel += 1;
+                self.next_row = 0;
+            }
+        }
+        completed
+    }
+
+    fn convolve_band(
+        &mut self,
+        input: &[f32],
+        width: usize,
+        height: usize,
+        backend: Backend,
+        mode: FilterMode,
+        rows: usize,
+    ) {
+        let kernel = &self.kernels[self.next_kernel].1;
+        let (first, end) = (self.next_row, self.next_row + rows);
+        let band = border::filter_region(
+            self.border,
+            input,
+            width,
+            height,
+            0..width,
+            first..end,
+            (kernel.width, kernel.height),
+            |band, bw, bh| kernel.filter(mode, backend, BorderMode::Zero, band, bw, bh),
+        );
+        self.output[first * width..end * width].copy_from_slice(&band);
+        self.next_row = end;
+    }
+}
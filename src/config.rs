@@ -0,0 +1,309 @@
+use serde::{Deserialize, Serialize};
+
+use crate::app::KernelShape;
+
+/// Lab-wide defaults, read from the working directory on native builds and
+/// fetched next to the page on the web.
+pub const CONFIG_FILE: &str = "convolution.toml";
+
+/// Contents of [`CONFIG_FILE`]. Every field is optional; anything missing
+/// keeps the saved or built-in value.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub kernels: KernelsConfig,
+    /// Overrides for the persisted settings, using their field names.
+    pub settings: toml::Table,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KernelsConfig {
+    /// Kernel sheet loaded and split at startup: a path relative to the
+    /// config file on native, a URL relative to the page on the web.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sheet: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shape: Option<KernelShape>,
+}
+
+/// Named settings bundle, kept in the saved settings and shared as a file in
+/// the [`CONFIG_FILE`] layout, so a shared profile also works as lab-wide
+/// defaults.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    pub shape: Option<KernelShape>,
+    pub settings: toml::Table,
+}
+
+impl Profile {
+    /// Captures `settings` except the `excluded` top-level fields.
+    pub fn capture<T: Serialize>(
+        name: String,
+        shape: KernelShape,
+        settings: &T,
+        excluded: &[&str],
+    ) -> Result<Self, String> {
+        let mut table = toml::Table::try_from(settings)
+            .map_err(|e| format!("Cannot serialize settings: {e}"))?;
+        table.retain(|key, _| !excluded.contains(&key));
+        Ok(Self {
+            name,
+            shape: Some(shape),
+            settings: table,
+        })
+    }
+
+    pub fn to_toml(&self) -> Result<String, String> {
+        let config = Config {
+            kernels: KernelsConfig {
+                sheet: None,
+                shape: self.shape,
+            },
+            settings: self.settings.clone(),
+        };
+        toml::to_string(&config).map_err(|e| format!("Cannot write profile: {e}"))
+    }
+
+    pub fn from_toml(name: String, text: &str) -> Result<Self, String> {
+        let config = Config::parse(text)?;
+        Ok(Self {
+            name,
+            shape: config.kernels.shape,
+            settings: config.settings,
+        })
+    }
+}
+
+/// What was found at startup, handed to the app on creation.
+#[derive(Default)]
+pub struct Startup {
+    pub config: Config,
+    /// File name, path or URL, and bytes of the configured kernel sheet.
+    pub kernel_sheet: Option<(String, String, Vec<u8>)>,
+    /// Problems reading the config, reported in the status line.
+    pub errors: Vec<String>,
+    /// Where the config came from, if one was found.
+    pub source: Option<String>,
+}
+
+impl Config {
+    fn parse(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|e| format!("Invalid {CONFIG_FILE}: {e}"))
+    }
+
+    /// Overlays the `[settings]` table onto `settings`.
+    pub fn apply<T>(&self, settings: &mut T) -> Result<(), String>
+    where
+        T: Serialize + serde::de::DeserializeOwned,
+    {
+        overlay(settings, &self.settings)
+            .map_err(|e| format!("Invalid [settings] in {CONFIG_FILE}: {e}"))
+    }
+}
+
+/// Overlays `overrides` onto `settings`, merging nested tables key by key so
+/// a partial table only changes what it names.
+pub fn overlay<T>(settings: &mut T, overrides: &toml::Table) -> Result<(), String>
+where
+    T: Serialize + serde::de::DeserializeOwned,
+{
+    if overrides.is_empty() {
+        return Ok(());
+    }
+    let mut merged =
+        toml::Table::try_from(&*settings).map_err(|e| format!("Cannot serialize settings: {e}"))?;
+    merge(&mut merged, overrides);
+    *settings = merged.try_into().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn merge(base: &mut toml::Table, overrides: &toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overrides)) => {
+                merge(base, overrides)
+            }
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+fn file_name(path: &str) -> String {
+    path.rsplit(['/', '\\']).next().unwrap_or(path).to_owned()
+}
+
+/// Reads [`CONFIG_FILE`] from the working directory, if present, and the
+/// kernel sheet it names.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load() -> Startup {
+    let path = std::path::Path::new(CONFIG_FILE);
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Startup::default(),
+        Err(e) => {
+            return Startup {
+                errors: vec![format!("Cannot read {CONFIG_FILE}: {e}")],
+                ..Startup::default()
+            };
+        }
+    };
+    let mut startup = Startup {
+        source: Some(path.display().to_string()),
+        ..Startup::default()
+    };
+    match Config::parse(&text) {
+        Ok(config) => startup.config = config,
+        Err(e) => {
+            startup.errors.push(e);
+            return startup;
+        }
+    }
+    if let Some(sheet) = &startup.config.kernels.sheet {
+        let sheet_path = path
+            .parent()
+            .unwrap_or(std::path::Path::new("."))
+            .join(sheet);
+        match std::fs::read(&sheet_path) {
+            Ok(bytes) => {
+                startup.kernel_sheet =
+                    Some((file_name(sheet), sheet_path.display().to_string(), bytes))
+            }
+            Err(e) => startup.errors.push(format!(
+                "Cannot read kernel sheet {}: {e}",
+                sheet_path.display()
+            )),
+        }
+    }
+    startup
+}
+
+/// Fetches [`CONFIG_FILE`] next to the page, if served, and the kernel sheet
+/// it names.
+#[cfg(target_arch = "wasm32")]
+pub async fn load() -> Startup {
+    let text = match fetch(CONFIG_FILE).await {
+        Ok(Some(bytes)) => String::from_utf8_lossy(&bytes).into_owned(),
+        Ok(None) => return Startup::default(),
+        Err(e) => {
+            return Startup {
+                errors: vec![format!("Cannot fetch {CONFIG_FILE}: {e}")],
+                ..Startup::default()
+            };
+        }
+    };
+    let mut startup = Startup {
+        source: Some(CONFIG_FILE.to_owned()),
+        ..Startup::default()
+    };
+    match Config::parse(&text) {
+        Ok(config) => startup.config = config,
+        Err(e) => {
+            startup.errors.push(e);
+            return startup;
+        }
+    }
+    if let Some(sheet) = startup.config.kernels.sheet.clone() {
+        match fetch(&sheet).await {
+            Ok(Some(bytes)) => startup.kernel_sheet = Some((file_name(&sheet), sheet, bytes)),
+            Ok(None) => startup
+                .errors
+                .push(format!("Kernel sheet {sheet} not found")),
+            Err(e) => startup
+                .errors
+                .push(format!("Cannot fetch kernel sheet {sheet}: {e}")),
+        }
+    }
+    startup
+}
+
+/// Body of `url`, or `None` when the server answers with an error status.
+#[cfg(target_arch = "wasm32")]
+pub async fn fetch(url: &str) -> Result<Option<Vec<u8>>, String> {
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+
+    let window = web_sys::window().ok_or("No browser window")?;
+    let response = JsFuture::from(window.fetch_with_str(url))
+        .await
+        .map_err(|e| format!("{e:?}"))?
+        .dyn_into::<web_sys::Response>()
+        .map_err(|_| "Fetch did not return a response".to_owned())?;
+    if !response.ok() {
+        return Ok(None);
+    }
+    let buffer = JsFuture::from(response.array_buffer().map_err(|e| format!("{e:?}"))?)
+        .await
+        .map_err(|e| format!("{e:?}"))?;
+    Ok(Some(js_sys::Uint8Array::new(&buffer).to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Settings {
+        threshold: f32,
+        theme: String,
+        export: Export,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Export {
+        dpi: u32,
+        folder: String,
+    }
+
+    fn settings() -> Settings {
+        Settings {
+            threshold: 0.5,
+            theme: "dark".to_owned(),
+            export: Export {
+                dpi: 150,
+                folder: "maps".to_owned(),
+            },
+        }
+    }
+
+    #[test]
+    fn profiles_round_trip_without_excluded_fields() {
+        let shape = toml::from_str::<Config>("[kernels]\nshape = \"5x7\"")
+            .unwrap()
+            .kernels
+            .shape
+            .unwrap();
+        let profile = Profile::capture("Lab".to_owned(), shape, &settings(), &["theme"]).unwrap();
+        let text = profile.to_toml().unwrap();
+        let loaded = Profile::from_toml("Shared".to_owned(), &text).unwrap();
+        assert_eq!(loaded.shape, Some(shape));
+        assert!(!loaded.settings.contains_key("theme"));
+
+        // Applied elsewhere, the theme stays as it was there.
+        let mut other = Settings {
+            threshold: 2.0,
+            theme: "light".to_owned(),
+            ..settings()
+        };
+        overlay(&mut other, &loaded.settings).unwrap();
+        assert_eq!(other.threshold, 0.5);
+        assert_eq!(other.theme, "light");
+    }
+
+    #[test]
+    fn partial_tables_change_only_what_they_name() {
+        let config = Config::parse("[settings.export]\ndpi = 300").unwrap();
+        let mut merged = settings();
+        config.apply(&mut merged).unwrap();
+        assert_eq!(merged.export.dpi, 300);
+        assert_eq!(merged.export.folder, "maps");
+        assert_eq!(merged.threshold, 0.5);
+
+        let wrong = Config::parse("[settings]\nthreshold = \"high\"").unwrap();
+        assert!(wrong.apply(&mut merged).is_err());
+        assert!(Config::parse("[kernel]\nsheet = \"a.png\"").is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use crate::export::csv_field;
+use crate::kernel::Kernel;
+use crate::morphology::FilterMode;
+use crate::scoring::ScoreNormalization;
+
+/// Ratio maps span this many doublings either side of the control level.
+pub const RATIO_OCTAVES: f32 = 2.0;
+
+/// Per-kernel results of the slide designated as control, kept so later
+/// slides of a screen are compared against it without convolving it again.
+pub struct ControlSlide {
+    pub name: String,
+    pub slide_hash: u64,
+    /// Hash of the kernels sheet the control was run with.
+    pub sheet_hash: u64,
+    pub kernel_size: (usize, usize),
+    pub mode: FilterMode,
+    pub normalization: ScoreNormalization,
+    pub scores: Vec<f32>,
+    pub mean_abs: Vec<f32>,
+    /// Kernels whose control score still came from the draft pass.
+    pub drafts: usize,
+}
+
+/// One kernel of the current slide against the control.
+pub struct KernelDifference {
+    pub index: usize,
+    pub score: f32,
+    pub control_score: f32,
+    /// `score - control_score`.
+    pub difference: f32,
+    /// Mean |r| of the slide over that of the control; independent of the
+    /// score normalization.
+    pub ratio: f32,
+}
+
+impl ControlSlide {
+    /// Whether results of this sheet, kernel size and mode can be compared.
+    /// Kernels built after the control was set are appended, so they do not
+    /// matter.
+    pub fn matches(
+        &self,
+        sheet_hash: u64,
+        kernel_size: (usize, usize),
+        mode: FilterMode,
+        kernels: usize,
+    ) -> bool {
+        self.sheet_hash == sheet_hash
+            && self.kernel_size == kernel_size
+            && self.mode == mode
+            && self.scores.len() <= kernels
+    }
+
+    /// Differences for the kernels scored so far, in kernel order.
+    pub fn differences(&self, scores: &[f32], mean_abs: &[f32]) -> Vec<KernelDifference> {
+        scores
+            .iter()
+            .zip(mean_abs)
+            .zip(self.scores.iter().zip(&self.mean_abs))
+            .enumerate()
+            .map(
+                |(index, ((&score, &mean_abs), (&control_score, &control_mean_abs)))| {
+                    KernelDifference {
+                        index,
+                        score,
+                        control_score,
+                        difference: score - control_score,
+                        ratio: if control_mean_abs > 1e-12 {
+                            mean_abs / control_mean_abs
+                        } else {
+                            f32::NAN
+                        },
+                    }
+                },
+            )
+            .collect()
+    }
+
+    /// `log2(|r| / control mean |r|)` per pixel, clamped to
+    /// ±[`RATIO_OCTAVES`]: 0 where the slide responds as strongly as the
+    /// control does on average.
+    pub fn log_ratio_map(&self, index: usize, response: &[f32]) -> Vec<f32> {
+        let control = self.mean_abs.get(index).copied().unwrap_or(0.0).max(1e-12);
+        response
+            .iter()
+            .map(|r| {
+                (r.abs() / control)
+                    .log2()
+                    .clamp(-RATIO_OCTAVES, RATIO_OCTAVES)
+            })
+            .collect()
+    }
+}
+
+/// Differences table with one line per kernel.
+pub fn differences_csv(control: &str, rows: &[KernelDifference], kernels: &[Kernel]) -> String {
+    let mut csv =
+        String::from("kernel,name,score,control_score,difference,mean_abs_ratio,control\n");
+    for row in rows {
+        let name = kernels.get(row.index).map_or("", |k| k.name.as_str());
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{}\n",
+            row.index,
+            csv_field(name),
+            row.score,
+            row.control_score,
+            row.difference,
+            row.ratio,
+            csv_field(control)
+        ));
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn control() -> ControlSlide {
+        ControlSlide {
+            name: "control.png".to_owned(),
+            slide_hash: 1,
+            sheet_hash: 7,
+            kernel_size: (5, 5),
+            mode: FilterMode::Correlation,
+            normalization: ScoreNormalization::Raw,
+            scores: vec![1.0, 2.0],
+            mean_abs: vec![0.5, 0.0],
+            drafts: 0,
+        }
+    }
+
+    #[test]
+    fn slides_compare_kernel_by_kernel() {
+        let control = control();
+        assert!(control.matches(7, (5, 5), FilterMode::Correlation, 3));
+        assert!(!control.matches(8, (5, 5), FilterMode::Correlation, 3));
+        assert!(!control.matches(7, (5, 5), FilterMode::Median, 3));
+        assert!(!control.matches(7, (5, 5), FilterMode::Correlation, 1));
+
+        // The third kernel was built after the control was set.
+        let rows = control.differences(&[1.5, 1.0, 4.0], &[1.0, 0.2, 3.0]);
+        assert_eq!(rows.len(), 2);
+        assert_eq!((rows[0].difference, rows[0].ratio), (0.5, 2.0));
+        assert_eq!(rows[1].difference, -1.0);
+        assert!(rows[1].ratio.is_nan());
+    }
+
+    #[test]
+    fn ratio_maps_are_clamped_octaves() {
+        let map = control().log_ratio_map(0, &[0.5, -1.0, 0.25, 100.0, 0.0]);
+        assert_eq!(map, [0.0, 1.0, -1.0, RATIO_OCTAVES, -RATIO_OCTAVES]);
+    }
+}
@@ -0,0 +1,162 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+
+use rayon::prelude::*;
+use web_time::Instant;
+
+use crate::backend::Backend;
+use crate::border::{self, BorderMode};
+use crate::kernel::Kernel;
+use crate::morphology::FilterMode;
+use crate::parallel;
+
+/// Bands of rows each kernel's map is convolved in, so progress moves and a
+/// cancel takes effect within a kernel.
+const BANDS_PER_KERNEL: usize = 16;
+
+/// Cancel flag and rows convolved, or other steps of work done, shared with
+/// the background thread.
+#[derive(Default)]
+pub struct Progress {
+    cancelled: AtomicBool,
+    rows: AtomicUsize,
+}
+
+impl Progress {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn add_rows(&self, rows: usize) {
+        self.rows.fetch_add(rows, Ordering::Relaxed);
+    }
+}
+
+/// Full-resolution CPU convolutions on a background thread, so the window
+/// stays responsive and the run can be cancelled. Kernels are convolved a
+/// batch of one per core at a time, as the blocking run did, and their
+/// results arrive in index order. Dropping the run cancels it.
+pub struct CpuRun<T> {
+    receiver: Receiver<(usize, T)>,
+    progress: Arc<Progress>,
+    count: usize,
+    /// Index of the bank's first kernel in the run.
+    first: usize,
+    received: usize,
+    /// Rows of every kernel's map together.
+    total_rows: usize,
+    pub started: Instant,
+}
+
+impl<T: Send + 'static> CpuRun<T> {
+    /// Starts computing `run` for kernels `0..count` over maps `height` rows
+    /// tall; they are the bank's kernels from `first` on. `run` reports the
+    /// rows it convolves to its [`Progress`] and returns `None` once it sees
+    /// the run cancelled. Work that is not counted in rows passes its steps
+    /// per kernel as `height`.
+    pub fn start(
+        count: usize,
+        first: usize,
+        height: usize,
+        run: impl Fn(usize, &Progress) -> Option<T> + Send + Sync + 'static,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let progress = Arc::new(Progress::default());
+        let shared = progress.clone();
+        std::thread::spawn(move || {
+            let indices: Vec<usize> = (0..count).collect();
+            for batch in indices.chunks(parallel::threads()) {
+                let results: Vec<_> = batch.par_iter().map(|&i| run(i, &shared)).collect();
+                for (&index, result) in batch.iter().zip(results) {
+                    let Some(result) = result else {
+                        return;
+                    };
+                    if sender.send((index, result)).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        Self {
+            receiver,
+            progress,
+            count,
+            first,
+            received: 0,
+            total_rows: count * height,
+            started: Instant::now(),
+        }
+    }
+
+    /// Results that arrived since the last call, with their kernel indices.
+    pub fn poll(&mut self) -> Vec<(usize, T)> {
+        let results: Vec<_> = self.receiver.try_iter().collect();
+        self.received += results.len();
+        results
+    }
+
+    pub fn first(&self) -> usize {
+        self.first
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.received == self.count
+    }
+
+    /// Fraction of all kernels' rows convolved so far.
+    pub fn progress(&self) -> f32 {
+        let rows = self.progress.rows.load(Ordering::Relaxed);
+        rows as f32 / self.total_rows.max(1) as f32
+    }
+}
+
+impl<T> Drop for CpuRun<T> {
+    fn drop(&mut self) {
+        self.progress.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Response of `kernel` over the whole `width` x `height` `input`,
+/// convolved in bands of rows, each with the rows its kernel reaches above
+/// and below read through `border`. Direct backends give bit-identical maps
+/// and the FFT agrees up to rounding. `None` once `progress` is cancelled.
+#[allow(clippy::too_many_arguments)]
+pub fn respond_in_bands(
+    kernel: &Kernel,
+    mode: FilterMode,
+    backend: Backend,
+    border: BorderMode,
+    input: &[f32],
+    width: usize,
+    height: usize,
+    progress: &Progress,
+) -> Option<Vec<f32>> {
+    let rows = height.div_ceil(BANDS_PER_KERNEL).max(1);
+    let mut output = Vec::with_capacity(width * height);
+    for first in (0..height).step_by(rows) {
+        if progress.is_cancelled() {
+            return None;
+        }
+        let end = (first + rows).min(height);
+        output.extend(border::filter_region(
+            border,
+            input,
+            width,
+            height,
+            0..width,
+            first..end,
+            (kernel.width, kernel.height),
+            |band, bw, bh| kernel.filter(mode, backend, BorderMode::Zero, band, bw, bh),
+        ));
+        progress.add_rows(end - first);
+    }
+    Some(output)
+}
+
+/// Reports a map computed in one piece, such as a multi-channel one, as
+/// `height` rows done. `None` when the run was cancelled meanwhile.
+pub fn whole(response: Vec<f32>, height: usize, progress: &Progress) -> Option<Vec<f32>> {
+    progress.add_rows(height);
+    (!progress.is_cancelled()).then_some(response)
+}
@@ -0,0 +1,74 @@
+use serde::Serialize;
+
+/// Progress and results of a headless run, printed one JSON object per line
+/// on stdout with `--json` so workflow managers can follow a run without
+/// parsing the log. Every object has an `event` field naming its variant in
+/// snake case; per-slide events carry the slide's path as given to the run.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// The slide and kernel sheet were read; `kernels` enabled kernels
+    /// follow.
+    Started {
+        slide: String,
+        kernels: usize,
+        width: usize,
+        height: usize,
+        backend: String,
+    },
+    /// Kernel `kernel` was scored, the `done`th of `total`.
+    Kernel {
+        slide: String,
+        kernel: usize,
+        done: usize,
+        total: usize,
+        score: f32,
+    },
+    Written {
+        slide: String,
+        path: String,
+    },
+    Finished {
+        slide: String,
+        kernels: usize,
+        /// Best-scoring kernel and its score; absent when every kernel is
+        /// muted.
+        top_kernel: Option<usize>,
+        top_score: Option<f32>,
+        summary: String,
+    },
+    /// The run stopped; may come without `started` when the inputs could
+    /// not be read, and with an empty `slide` when the pipeline or the
+    /// slide pattern was at fault.
+    Failed {
+        slide: String,
+        error: String,
+    },
+    /// A slide of a batch has the same content as `same_as`, an earlier
+    /// one, so its scores repeat that slide's.
+    Duplicate {
+        slide: String,
+        same_as: String,
+    },
+    /// Every slide of a batch is done and the aggregate table is at
+    /// `scores`.
+    BatchFinished {
+        slides: usize,
+        failed: usize,
+        scores: String,
+    },
+}
+
+/// Prints `event` as one line of JSON. Lines from concurrent runs do not
+/// interleave, as each is written under the stdout lock. A reader that goes
+/// away does not stop the run.
+pub fn print_json(event: &Event) {
+    use std::io::Write;
+
+    // Serializing these plain structs cannot fail; non-finite scores become
+    // `null`.
+    if let Ok(line) = serde_json::to_string(event) {
+        let mut out = std::io::stdout().lock();
+        let _ = writeln!(out, "{line}").and_then(|()| out.flush());
+    }
+}
@@ -0,0 +1,445 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use image::{GrayImage, ImageFormat};
+use serde::{Deserialize, Serialize};
+
+use crate::kernel::{Kernel, Provenance};
+use crate::numbers::NumberFormat;
+use crate::scoring::Significance;
+use crate::stats::ResponseStats;
+
+/// One kernel's line of the scores table.
+pub struct ScoreRow<'a> {
+    /// Index of the kernel in the bank.
+    pub index: usize,
+    pub kernel: &'a Kernel,
+    pub score: f32,
+    pub exact: bool,
+    pub stats: &'a ResponseStats,
+    pub significance: Option<Significance>,
+    pub interval: [f32; 2],
+}
+
+/// Scores table with one line per kernel, its numbers written by `number`.
+pub fn scores_csv<'a>(
+    rows: impl IntoIterator<Item = ScoreRow<'a>>,
+    number: NumberFormat,
+) -> String {
+    let num = |value: f32| number.export(value);
+    let mut csv = String::from(
+        "kernel,name,provenance,history,score,exact,mean,std_dev,mean_abs,activation_rate,gini,entropy,mutual_information,p_value,permutation_z,score_ci_low,score_ci_high,group,tissue_mean_abs,background_mean_abs,max_abs,energy,sparsity\n",
+    );
+    for row in rows {
+        let s = row.stats;
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+            row.index,
+            csv_field(&row.kernel.name),
+            csv_field(&row.kernel.provenance.to_string()),
+            csv_field(&row.kernel.history.join("; ")),
+            num(row.score),
+            row.exact,
+            num(s.mean),
+            num(s.std_dev),
+            num(s.mean_abs),
+            num(s.activation_rate),
+            num(s.gini),
+            num(s.entropy),
+            num(s.mutual_information),
+            row.significance.map_or(String::new(), |s| num(s.p_value)),
+            row.significance.map_or(String::new(), |s| num(s.z_score)),
+            num(row.interval[0]),
+            num(row.interval[1]),
+            csv_field(&row.kernel.group),
+            num(s.tissue_mean_abs),
+            num(s.background_mean_abs),
+            num(s.max_abs),
+            num(s.energy),
+            num(s.sparsity)
+        ));
+    }
+    csv
+}
+
+/// Field separator of exported CSV files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CsvDelimiter {
+    #[default]
+    Comma,
+    Semicolon,
+    Tab,
+}
+
+impl CsvDelimiter {
+    pub const ALL: [Self; 3] = [Self::Comma, Self::Semicolon, Self::Tab];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Comma => "Comma",
+            Self::Semicolon => "Semicolon",
+            Self::Tab => "Tab",
+        }
+    }
+
+    fn char(self) -> char {
+        match self {
+            Self::Comma => ',',
+            Self::Semicolon => ';',
+            Self::Tab => '\t',
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DecimalSeparator {
+    #[default]
+    Point,
+    Comma,
+}
+
+impl DecimalSeparator {
+    pub const ALL: [Self; 2] = [Self::Point, Self::Comma];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Point => "Point (1.5)",
+            Self::Comma => "Comma (1,5)",
+        }
+    }
+}
+
+/// Layout of exported CSV files, for spreadsheets set to a locale other
+/// than English: European Excel installs expect `;` between fields and a
+/// decimal comma, and read `1.5` as a date or text.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CsvFormat {
+    pub delimiter: CsvDelimiter,
+    pub decimal: DecimalSeparator,
+}
+
+impl CsvFormat {
+    /// Rewrites `csv`, written with commas and decimal points as every
+    /// table of the app is, in this format. Numbers get the decimal
+    /// separator; other fields are left as they are, and quoted only where
+    /// the new delimiter requires it.
+    pub fn apply(&self, csv: &str) -> String {
+        if *self == Self::default() {
+            return csv.to_owned();
+        }
+        let delimiter = self.delimiter.char();
+        let mut out = String::with_capacity(csv.len());
+        for record in csv_records(csv) {
+            let fields: Vec<String> = record
+                .into_iter()
+                .map(|(field, quoted)| {
+                    let number = !quoted && field.contains('.') && field.parse::<f64>().is_ok();
+                    let field = if number && self.decimal == DecimalSeparator::Comma {
+                        field.replace('.', ",")
+                    } else {
+                        field
+                    };
+                    if field.contains([delimiter, '"', '\n', '\r']) {
+                        format!("\"{}\"", field.replace('"', "\"\""))
+                    } else {
+                        field
+                    }
+                })
+                .collect();
+            out.push_str(&fields.join(&delimiter.to_string()));
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Records of the comma-separated `csv`, each with a first column named
+/// `header` in the header record and holding `value` in the others.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn with_leading_column(csv: &str, header: &str, value: &str) -> Vec<String> {
+    csv_records(csv)
+        .into_iter()
+        .enumerate()
+        .map(|(i, record)| {
+            let first = if i == 0 { header } else { value };
+            std::iter::once(csv_field(first))
+                .chain(record.iter().map(|(field, _)| csv_field(field)))
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+        .collect()
+}
+
+/// Records of a comma-separated table, each field unquoted and flagged when
+/// it was quoted.
+fn csv_records(csv: &str) -> Vec<Vec<(String, bool)>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let (mut field, mut quoted, mut in_quotes) = (String::new(), false, false);
+    let mut chars = csv.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if in_quotes => in_quotes = false,
+            '"' if field.is_empty() => (in_quotes, quoted) = (true, true),
+            ',' if !in_quotes => {
+                record.push((std::mem::take(&mut field), std::mem::take(&mut quoted)))
+            }
+            '\n' if !in_quotes => {
+                record.push((std::mem::take(&mut field), std::mem::take(&mut quoted)));
+                records.push(std::mem::take(&mut record));
+            }
+            '\r' if !in_quotes => {}
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push((field, quoted));
+        records.push(record);
+    }
+    records
+}
+
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+/// Placeholders of map file name templates, as listed to users.
+pub const MAP_NAME_PLACEHOLDERS: &str =
+    "{slide} {kernel_index} {kernel_name} {score} {score:.3} {row} {col}";
+
+/// What a map file name template can name about one kernel's map.
+pub struct MapNameFields<'a> {
+    /// File name of the slide; its extension is dropped.
+    pub slide: &'a str,
+    pub index: usize,
+    pub kernel: &'a Kernel,
+    pub score: f32,
+}
+
+impl MapNameFields<'_> {
+    fn value(&self, placeholder: &str) -> Result<String, String> {
+        let (key, spec) = placeholder
+            .split_once(':')
+            .map_or((placeholder, None), |(key, spec)| (key, Some(spec)));
+        let tile = match &self.kernel.provenance {
+            Provenance::SheetTile { row, col, .. } => Some((*row, *col)),
+            _ => None,
+        };
+        let value = match key {
+            "slide" => Path::new(self.slide)
+                .file_stem()
+                .map_or(String::new(), |stem| stem.to_string_lossy().into_owned()),
+            "kernel_index" => self.index.to_string(),
+            "kernel_name" => self.kernel.name.clone(),
+            // Kernels not cut from a sheet have no tile position.
+            "row" => tile.map_or(String::new(), |(row, _)| row.to_string()),
+            "col" => tile.map_or(String::new(), |(_, col)| col.to_string()),
+            "score" => match spec {
+                None => self.score.to_string(),
+                Some(spec) => {
+                    let decimals = spec
+                        .strip_prefix('.')
+                        .and_then(|d| d.parse::<usize>().ok())
+                        .filter(|&d| d <= 9)
+                        .ok_or_else(|| format!("{{score:{spec}}} needs a precision like .3"))?;
+                    format!("{:.decimals$}", self.score)
+                }
+            },
+            _ => return Err(format!("unknown placeholder {{{placeholder}}}")),
+        };
+        if spec.is_some() && key != "score" {
+            return Err(format!("{{{key}}} takes no format"));
+        }
+        Ok(value)
+    }
+}
+
+/// File name, without extension, that `template` gives a map, with its
+/// placeholders in braces filled in. Characters other than letters, digits,
+/// `-`, `_` and `.` become `_`, so names neither leave the export folder
+/// nor need quoting in scripts.
+pub fn map_file_stem(template: &str, fields: &MapNameFields) -> Result<String, String> {
+    let invalid = |e: String| format!("Invalid map name template: {e}.");
+    let mut name = String::new();
+    let mut rest = template;
+    while let Some(at) = rest.find(['{', '}']) {
+        name.push_str(&rest[..at]);
+        let tail = &rest[at..];
+        if tail.starts_with('}') {
+            return Err(invalid("unmatched }".to_owned()));
+        }
+        let close = tail
+            .find('}')
+            .ok_or_else(|| invalid("unclosed {".to_owned()))?;
+        name.push_str(&fields.value(&tail[1..close]).map_err(invalid)?);
+        rest = &tail[close + 1..];
+    }
+    name.push_str(rest);
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            c if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') => c,
+            _ => '_',
+        })
+        .collect();
+    let name = name.trim_start_matches('.');
+    if name.is_empty() {
+        return Err(invalid("it gives an empty name".to_owned()));
+    }
+    Ok(name.to_owned())
+}
+
+/// Names `template` gives each of `maps`, in order. Fails when two maps
+/// would get the same name, rather than one overwriting the other.
+pub fn map_file_stems<'a>(
+    template: &str,
+    maps: impl IntoIterator<Item = MapNameFields<'a>>,
+) -> Result<Vec<String>, String> {
+    let mut names = Vec::new();
+    let mut seen = HashMap::new();
+    for fields in maps {
+        let name = map_file_stem(template, &fields)?;
+        if let Some(other) = seen.insert(name.clone(), fields.index) {
+            return Err(format!(
+                "The map name template names kernels {other} and {} both {name}; \
+                 add {{kernel_index}} to it.",
+                fields.index
+            ));
+        }
+        names.push(name);
+    }
+    Ok(names)
+}
+
+/// Encodes a response map as an 8-bit grayscale PNG, stretching its finite
+/// value range to the full 0..=255 scale. NaN pixels come out black.
+pub fn response_png(response: &[f32], width: usize, height: usize) -> Result<Vec<u8>, String> {
+    let range = response
+        .iter()
+        .filter(|v| v.is_finite())
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &v| {
+            (lo.min(v), hi.max(v))
+        });
+    response_png_in(response, width, height, range)
+}
+
+/// Encodes a response map as an 8-bit grayscale PNG with `lo..=hi` spread
+/// over 0..=255 and values beyond clamped, so maps written with the same
+/// interval share one scale.
+pub fn response_png_in(
+    response: &[f32],
+    width: usize,
+    height: usize,
+    (lo, hi): (f32, f32),
+) -> Result<Vec<u8>, String> {
+    let range = (hi - lo).max(1e-6);
+    let pixels = response
+        .iter()
+        .map(|&v| (((v - lo) / range) * 255.0).clamp(0.0, 255.0) as u8)
+        .collect();
+    let image = GrayImage::from_raw(width as u32, height as u32, pixels)
+        .ok_or("Response size does not match its dimensions")?;
+    let mut bytes = std::io::Cursor::new(Vec::new());
+    image
+        .write_to(&mut bytes, ImageFormat::Png)
+        .map_err(|e| format!("PNG encoding failed: {e}"))?;
+    Ok(bytes.into_inner())
+}
+
+/// Uncompressed single-channel 32-bit float TIFF of the raw `response`,
+/// for analysis in tools that read float images.
+pub fn response_tiff(response: &[f32], width: usize, height: usize) -> Result<Vec<u8>, String> {
+    const SHORT: u16 = 3;
+    const LONG: u16 = 4;
+    if response.len() != width * height {
+        return Err("Response size does not match its dimensions".to_owned());
+    }
+    let data_len = u32::try_from(response.len() * 4)
+        .map_err(|_| format!("A {width}x{height} map is too large for a TIFF file"))?;
+    // Tag, type and value, in ascending tag order as TIFF requires.
+    let entries: [(u16, u16, u32); 11] = [
+        (256, LONG, width as u32),  // ImageWidth
+        (257, LONG, height as u32), // ImageLength
+        (258, SHORT, 32),           // BitsPerSample
+        (259, SHORT, 1),            // Compression: none
+        (262, SHORT, 1),            // PhotometricInterpretation: black is zero
+        (273, LONG, 0),             // StripOffsets, set below
+        (277, SHORT, 1),            // SamplesPerPixel
+        (278, LONG, height as u32), // RowsPerStrip: a single strip
+        (279, LONG, data_len),      // StripByteCounts
+        (284, SHORT, 1),            // PlanarConfiguration: contiguous
+        (339, SHORT, 3),            // SampleFormat: IEEE float
+    ];
+    let data_offset = 8 + 2 + entries.len() as u32 * 12 + 4;
+    let mut bytes = Vec::with_capacity(data_offset as usize + data_len as usize);
+    bytes.extend_from_slice(b"II");
+    bytes.extend_from_slice(&42u16.to_le_bytes());
+    bytes.extend_from_slice(&8u32.to_le_bytes());
+    bytes.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    for (tag, kind, value) in entries {
+        let value = if tag == 273 { data_offset } else { value };
+        bytes.extend_from_slice(&tag.to_le_bytes());
+        bytes.extend_from_slice(&kind.to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        // Values shorter than four bytes are left-justified.
+        match kind {
+            SHORT => {
+                bytes.extend_from_slice(&(value as u16).to_le_bytes());
+                bytes.extend_from_slice(&[0, 0]);
+            }
+            _ => bytes.extend_from_slice(&value.to_le_bytes()),
+        }
+    }
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    bytes.extend(response.iter().flat_map(|v| v.to_le_bytes()));
+    Ok(bytes)
+}
+
+/// Writes `bytes` as `file_name` inside `dir`, creating the folder if needed.
+/// Returns the written path for status messages.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_file(dir: &str, file_name: &str, bytes: &[u8]) -> Result<String, String> {
+    let dir = std::path::Path::new(dir);
+    std::fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {e}", dir.display()))?;
+    let path = dir.join(file_name);
+    std::fs::write(&path, bytes).map_err(|e| format!("Cannot write {}: {e}", path.display()))?;
+    Ok(path.display().to_string())
+}
+
+/// Hands `bytes` to the browser as a download named `file_name`. The folder
+/// is chosen by the browser, so `dir` is ignored.
+#[cfg(target_arch = "wasm32")]
+pub fn save_file(_dir: &str, file_name: &str, bytes: &[u8]) -> Result<String, String> {
+    use wasm_bindgen::JsCast;
+
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(bytes));
+    let blob = web_sys::Blob::new_with_u8_array_sequence(&parts).map_err(js_error)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob).map_err(js_error)?;
+    let document = web_sys::window()
+        .and_then(|w| w.document())
+        .ok_or("No browser document")?;
+    let anchor = document
+        .create_element("a")
+        .map_err(js_error)?
+        .dyn_into::<web_sys::HtmlAnchorElement>()
+        .map_err(|_| "Element is not an anchor".to_owned())?;
+    anchor.set_href(&url);
+    anchor.set_download(file_name);
+    anchor.click();
+    web_sys::Url::revoke_object_url(&url).map_err(js_error)?;
+    Ok(format!("download {file_name}"))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn js_error(err: wasm_bindgen::JsValue) -> String {
+    format!("{err:?}")
+}
@@ -0,0 +1,549 @@
+use serde::{Deserialize, Serialize};
+
+/// Histogram bins of exported response histograms.
+const HISTOGRAM_BINS: usize = 64;
+/// Space around the plot area, in points: left, top, right, bottom.
+const MARGINS: [f32; 4] = [56.0, 28.0, 12.0, 40.0];
+const AXIS_TICKS: usize = 5;
+
+const BLACK: [u8; 3] = [0, 0, 0];
+const GRID: [u8; 3] = [210, 210, 210];
+const BAR: [u8; 3] = [120, 144, 176];
+const HIGHLIGHT: [u8; 3] = [214, 96, 48];
+
+/// Font families every SVG viewer and PDF reader can render without
+/// embedding a font file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FigureFont {
+    #[default]
+    Sans,
+    Serif,
+    Mono,
+}
+
+impl FigureFont {
+    pub const ALL: [Self; 3] = [Self::Sans, Self::Serif, Self::Mono];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Sans => "Helvetica / sans-serif",
+            Self::Serif => "Times / serif",
+            Self::Mono => "Courier / monospace",
+        }
+    }
+
+    fn svg_family(self) -> &'static str {
+        match self {
+            Self::Sans => "Helvetica, Arial, sans-serif",
+            Self::Serif => "Times New Roman, Times, serif",
+            Self::Mono => "Courier New, Courier, monospace",
+        }
+    }
+
+    /// One of the standard 14 PDF fonts.
+    fn pdf_base_font(self) -> &'static str {
+        match self {
+            Self::Sans => "Helvetica",
+            Self::Serif => "Times-Roman",
+            Self::Mono => "Courier",
+        }
+    }
+
+    /// Average glyph width as a fraction of the font size, used to align
+    /// centered and right-aligned PDF text.
+    fn average_advance(self) -> f32 {
+        match self {
+            Self::Sans => 0.52,
+            Self::Serif => 0.48,
+            Self::Mono => 0.6,
+        }
+    }
+}
+
+/// Page size and typography of exported figures, in points.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FigureStyle {
+    pub width: f32,
+    pub height: f32,
+    pub font: FigureFont,
+    pub font_size: f32,
+}
+
+impl Default for FigureStyle {
+    fn default() -> Self {
+        // A single journal column.
+        Self {
+            width: 252.0,
+            height: 180.0,
+            font: FigureFont::default(),
+            font_size: 8.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FigureFormat {
+    #[default]
+    Svg,
+    Pdf,
+}
+
+impl FigureFormat {
+    pub const ALL: [Self; 2] = [Self::Svg, Self::Pdf];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Svg => "SVG",
+            Self::Pdf => "PDF",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Svg => "svg",
+            Self::Pdf => "pdf",
+        }
+    }
+
+    pub fn encode(self, figure: &Figure) -> Vec<u8> {
+        match self {
+            Self::Svg => figure.to_svg().into_bytes(),
+            Self::Pdf => figure.to_pdf(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Anchor {
+    Start,
+    Middle,
+    End,
+}
+
+enum Shape {
+    Rect {
+        x: f32,
+        y: f32,
+        w: f32,
+        h: f32,
+        fill: [u8; 3],
+    },
+    Line {
+        from: [f32; 2],
+        to: [f32; 2],
+        stroke: [u8; 3],
+        width: f32,
+    },
+    Text {
+        at: [f32; 2],
+        text: String,
+        anchor: Anchor,
+        /// Relative to the style's font size.
+        scale: f32,
+    },
+}
+
+/// Vector drawing in points, origin at the top-left corner, rendered to
+/// SVG or PDF.
+pub struct Figure {
+    style: FigureStyle,
+    shapes: Vec<Shape>,
+}
+
+impl Figure {
+    fn new(style: FigureStyle) -> Self {
+        Self {
+            style,
+            shapes: Vec::new(),
+        }
+    }
+
+    fn rect(&mut self, x: f32, y: f32, w: f32, h: f32, fill: [u8; 3]) {
+        self.shapes.push(Shape::Rect { x, y, w, h, fill });
+    }
+
+    fn line(&mut self, from: [f32; 2], to: [f32; 2], stroke: [u8; 3], width: f32) {
+        self.shapes.push(Shape::Line {
+            from,
+            to,
+            stroke,
+            width,
+        });
+    }
+
+    fn text(&mut self, at: [f32; 2], text: impl Into<String>, anchor: Anchor, scale: f32) {
+        self.shapes.push(Shape::Text {
+            at,
+            text: text.into(),
+            anchor,
+            scale,
+        });
+    }
+
+    pub fn to_svg(&self) -> String {
+        let FigureStyle {
+            width,
+            height,
+            font,
+            font_size,
+        } = self.style;
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}pt\" height=\"{height}pt\" viewBox=\"0 0 {width} {height}\" font-family=\"{}\" font-size=\"{font_size}\">\n",
+            font.svg_family()
+        );
+        let hex = |c: [u8; 3]| format!("#{:02x}{:02x}{:02x}", c[0], c[1], c[2]);
+        for shape in &self.shapes {
+            match shape {
+                Shape::Rect { x, y, w, h, fill } => svg.push_str(&format!(
+                    "<rect x=\"{x:.2}\" y=\"{y:.2}\" width=\"{w:.2}\" height=\"{h:.2}\" fill=\"{}\"/>\n",
+                    hex(*fill)
+                )),
+                Shape::Line {
+                    from,
+                    to,
+                    stroke,
+                    width,
+                } => svg.push_str(&format!(
+                    "<line x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\" stroke=\"{}\" stroke-width=\"{width}\"/>\n",
+                    from[0],
+                    from[1],
+                    to[0],
+                    to[1],
+                    hex(*stroke)
+                )),
+                Shape::Text {
+                    at,
+                    text,
+                    anchor,
+                    scale,
+                } => {
+                    let anchor = match anchor {
+                        Anchor::Start => "start",
+                        Anchor::Middle => "middle",
+                        Anchor::End => "end",
+                    };
+                    svg.push_str(&format!(
+                        "<text x=\"{:.2}\" y=\"{:.2}\" text-anchor=\"{anchor}\" font-size=\"{:.2}\">{}</text>\n",
+                        at[0],
+                        at[1],
+                        font_size * scale,
+                        xml_escape(text)
+                    ));
+                }
+            }
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// Single-page PDF using a standard font, so no font data is embedded.
+    pub fn to_pdf(&self) -> Vec<u8> {
+        let FigureStyle {
+            width: page_w,
+            height: page_h,
+            font,
+            font_size,
+        } = self.style;
+        let rgb = |c: [u8; 3]| {
+            format!(
+                "{:.3} {:.3} {:.3}",
+                c[0] as f32 / 255.0,
+                c[1] as f32 / 255.0,
+                c[2] as f32 / 255.0
+            )
+        };
+        let mut content = String::new();
+        for shape in &self.shapes {
+            match shape {
+                Shape::Rect { x, y, w, h, fill } => content.push_str(&format!(
+                    "{} rg {x:.2} {:.2} {w:.2} {h:.2} re f\n",
+                    rgb(*fill),
+                    page_h - y - h
+                )),
+                Shape::Line {
+                    from,
+                    to,
+                    stroke,
+                    width,
+                } => content.push_str(&format!(
+                    "{} RG {width} w {:.2} {:.2} m {:.2} {:.2} l S\n",
+                    rgb(*stroke),
+                    from[0],
+                    page_h - from[1],
+                    to[0],
+                    page_h - to[1]
+                )),
+                Shape::Text {
+                    at,
+                    text,
+                    anchor,
+                    scale,
+                } => {
+                    let size = font_size * scale;
+                    let advance = text.chars().count() as f32 * size * font.average_advance();
+                    let x = match anchor {
+                        Anchor::Start => at[0],
+                        Anchor::Middle => at[0] - advance / 2.0,
+                        Anchor::End => at[0] - advance,
+                    };
+                    content.push_str(&format!(
+                        "0 0 0 rg BT /F1 {size:.2} Tf {x:.2} {:.2} Td ({}) Tj ET\n",
+                        page_h - at[1],
+                        pdf_escape(text)
+                    ));
+                }
+            }
+        }
+
+        let objects = [
+            "<< /Type /Catalog /Pages 2 0 R >>".to_owned(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_owned(),
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {page_w} {page_h}] /Resources << /Font << /F1 4 0 R >> >> /Contents 5 0 R >>"
+            ),
+            format!(
+                "<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>",
+                font.pdf_base_font()
+            ),
+            format!(
+                "<< /Length {} >>\nstream\n{content}endstream",
+                content.len()
+            ),
+        ];
+        let mut pdf = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::with_capacity(objects.len());
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend_from_slice(format!("{} 0 obj\n{object}\nendobj\n", i + 1).as_bytes());
+        }
+        let xref = pdf.len();
+        pdf.extend_from_slice(
+            format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes(),
+        );
+        for offset in offsets {
+            pdf.extend_from_slice(format!("{offset:010} 00000 n \n").as_bytes());
+        }
+        pdf.extend_from_slice(
+            format!(
+                "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+                objects.len() + 1
+            )
+            .as_bytes(),
+        );
+        pdf
+    }
+}
+
+/// Plot area of a figure and the value range mapped onto its height.
+struct Axes {
+    left: f32,
+    top: f32,
+    width: f32,
+    height: f32,
+    lo: f32,
+    hi: f32,
+}
+
+impl Axes {
+    fn new(style: &FigureStyle, lo: f32, hi: f32) -> Self {
+        let [ml, mt, mr, mb] = MARGINS.map(|m| m * style.font_size / 8.0);
+        Self {
+            left: ml,
+            top: mt,
+            width: (style.width - ml - mr).max(1.0),
+            height: (style.height - mt - mb).max(1.0),
+            lo,
+            hi: if hi > lo { hi } else { lo + 1.0 },
+        }
+    }
+
+    fn bottom(&self) -> f32 {
+        self.top + self.height
+    }
+
+    fn y(&self, value: f32) -> f32 {
+        self.bottom() - (value - self.lo) / (self.hi - self.lo) * self.height
+    }
+
+    /// Draws the value axis with grid lines, the baseline and the labels.
+    fn draw(&self, figure: &mut Figure, title: &str, x_label: &str, y_label: &str) {
+        let font_size = figure.style.font_size;
+        for tick in 0..=AXIS_TICKS {
+            let value = self.lo + (self.hi - self.lo) * tick as f32 / AXIS_TICKS as f32;
+            let y = self.y(value);
+            figure.line([self.left, y], [self.left + self.width, y], GRID, 0.5);
+            figure.text(
+                [self.left - 3.0, y + font_size * 0.3],
+                format_tick(value),
+                Anchor::End,
+                0.9,
+            );
+        }
+        figure.line(
+            [self.left, self.top],
+            [self.left, self.bottom()],
+            BLACK,
+            0.75,
+        );
+        figure.line(
+            [self.left, self.bottom()],
+            [self.left + self.width, self.bottom()],
+            BLACK,
+            0.75,
+        );
+        figure.text(
+            [self.left + self.width / 2.0, self.top - font_size * 0.8],
+            title,
+            Anchor::Middle,
+            1.1,
+        );
+        figure.text(
+            [
+                self.left + self.width / 2.0,
+                figure.style.height - font_size * 0.5,
+            ],
+            x_label,
+            Anchor::Middle,
+            1.0,
+        );
+        figure.text(
+            [2.0, self.top - font_size * 0.8],
+            y_label,
+            Anchor::Start,
+            1.0,
+        );
+    }
+}
+
+/// Score bar chart with confidence interval whiskers. `scores` holds each
+/// kernel's score and interval; `selected` is highlighted.
+pub fn score_plot(
+    style: FigureStyle,
+    scores: &[(f32, [f32; 2])],
+    selected: Option<usize>,
+    y_label: &str,
+) -> Figure {
+    let mut figure = Figure::new(style);
+    let (lo, hi) = scores
+        .iter()
+        .fold((0.0f32, 0.0f32), |(lo, hi), &(score, [a, b])| {
+            (lo.min(score).min(a), hi.max(score).max(b))
+        });
+    let axes = Axes::new(&style, lo, hi);
+    axes.draw(&mut figure, "Kernel scores", "Kernel", y_label);
+
+    let step = axes.width / scores.len().max(1) as f32;
+    let label_every = (scores.len() / 20).max(1);
+    for (i, &(score, [low, high])) in scores.iter().enumerate() {
+        let x0 = axes.left + i as f32 * step;
+        let (top, base) = (axes.y(score), axes.y(0.0f32.clamp(axes.lo, axes.hi)));
+        let fill = if selected == Some(i) { HIGHLIGHT } else { BAR };
+        figure.rect(
+            x0 + step * 0.15,
+            top.min(base),
+            step * 0.7,
+            (top - base).abs(),
+            fill,
+        );
+        let cx = x0 + step * 0.5;
+        let (upper, lower) = (axes.y(high), axes.y(low));
+        figure.line([cx, upper], [cx, lower], BLACK, 0.5);
+        figure.line(
+            [cx - step * 0.2, upper],
+            [cx + step * 0.2, upper],
+            BLACK,
+            0.5,
+        );
+        figure.line(
+            [cx - step * 0.2, lower],
+            [cx + step * 0.2, lower],
+            BLACK,
+            0.5,
+        );
+        if i % label_every == 0 {
+            figure.text(
+                [cx, axes.bottom() + style.font_size * 1.1],
+                i.to_string(),
+                Anchor::Middle,
+                0.9,
+            );
+        }
+    }
+    figure
+}
+
+/// Histogram of one response map over [`HISTOGRAM_BINS`] bins.
+pub fn histogram(style: FigureStyle, values: &[f32], title: &str) -> Figure {
+    let (min_v, max_v) = values
+        .iter()
+        .filter(|v| v.is_finite())
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &v| {
+            (lo.min(v), hi.max(v))
+        });
+    let range = (max_v - min_v).max(1e-6);
+    let mut counts = [0usize; HISTOGRAM_BINS];
+    for &v in values.iter().filter(|v| v.is_finite()) {
+        let bin = (((v - min_v) / range) * HISTOGRAM_BINS as f32) as usize;
+        counts[bin.min(HISTOGRAM_BINS - 1)] += 1;
+    }
+    let total = values.len().max(1) as f32;
+    let peak = counts.iter().copied().max().unwrap_or(0) as f32 / total;
+
+    let mut figure = Figure::new(style);
+    let axes = Axes::new(&style, 0.0, peak);
+    axes.draw(&mut figure, title, "Response", "Fraction of pixels");
+    let step = axes.width / HISTOGRAM_BINS as f32;
+    for (i, &count) in counts.iter().enumerate() {
+        let top = axes.y(count as f32 / total);
+        figure.rect(
+            axes.left + i as f32 * step,
+            top,
+            step,
+            axes.bottom() - top,
+            BAR,
+        );
+    }
+    let label_y = axes.bottom() + style.font_size * 1.1;
+    figure.text([axes.left, label_y], format_tick(min_v), Anchor::Start, 0.9);
+    figure.text(
+        [axes.left + axes.width, label_y],
+        format_tick(max_v),
+        Anchor::End,
+        0.9,
+    );
+    figure
+}
+
+fn format_tick(value: f32) -> String {
+    let magnitude = value.abs();
+    if magnitude != 0.0 && !(1e-3..1e4).contains(&magnitude) {
+        format!("{value:.2e}")
+    } else {
+        format!("{value:.3}")
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Escapes a PDF literal string; characters outside Latin-1 become `?`.
+fn pdf_escape(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '(' | ')' | '\\' => format!("\\{c}"),
+            c if (c as u32) < 0x100 && !c.is_control() => {
+                if c.is_ascii() {
+                    c.to_string()
+                } else {
+                    format!("\\{:03o}", c as u32)
+                }
+            }
+            _ => "?".to_owned(),
+        })
+        .collect()
+}
@@ -0,0 +1,100 @@
+use std::sync::mpsc::{self, Receiver};
+
+/// Kinds of file a dialog offers, by extension without the dot.
+#[derive(Clone, Copy)]
+pub struct Filter {
+    /// Label of the filter in native dialogs; browsers name it themselves.
+    pub name: &'static str,
+    pub extensions: &'static [&'static str],
+}
+
+pub const SLIDE_FILTER: Filter = Filter {
+    name: "Slides",
+    extensions: &["png", "gif"],
+};
+
+pub const KERNELS_FILTER: Filter = Filter {
+    name: "Kernel sheets and files",
+    extensions: &["png", "gif", "csv", "json"],
+};
+
+pub const SESSION_FILTER: Filter = Filter {
+    name: "Sessions",
+    extensions: &["convsession"],
+};
+
+/// File chosen in the dialog. Native builds leave reading it to the caller,
+/// which may stream a large slide instead.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct PickedFile {
+    pub name: String,
+    pub path: std::path::PathBuf,
+}
+
+/// File chosen in the dialog, read into memory.
+#[cfg(target_arch = "wasm32")]
+pub struct PickedFile {
+    pub name: String,
+    pub bytes: Vec<u8>,
+}
+
+/// The chosen file once the dialog closes, `None` when it was cancelled.
+pub type Pick = Receiver<Option<PickedFile>>;
+
+fn dialog(title: &str, filter: Filter) -> rfd::AsyncFileDialog {
+    rfd::AsyncFileDialog::new()
+        .set_title(title)
+        .add_filter(filter.name, filter.extensions)
+}
+
+/// Opens the platform's file dialog in the background: the desktop portal on
+/// Linux and the BSDs, the system dialogs on macOS and Windows. It starts in
+/// `dir` when that is a folder, and where the dialog chooses otherwise.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn pick(title: &'static str, filter: Filter, dir: &str) -> Pick {
+    let (sender, receiver) = mpsc::channel();
+    let mut dialog = dialog(title, filter);
+    let dir = std::path::Path::new(dir);
+    if dir.is_dir() {
+        dialog = dialog.set_directory(dir);
+    }
+    // Built here so that macOS runs the dialog on the main thread; only the
+    // wait moves to the background.
+    let picked = dialog.pick_file();
+    std::thread::spawn(move || {
+        let file = pollster::block_on(picked).map(|handle| PickedFile {
+            name: handle.file_name(),
+            path: handle.path().to_owned(),
+        });
+        let _ = sender.send(file);
+    });
+    receiver
+}
+
+/// Opens the browser's file picker. The browser picks the folder it opens
+/// in.
+#[cfg(target_arch = "wasm32")]
+pub fn pick(title: &'static str, filter: Filter, _dir: &str) -> Pick {
+    let (sender, receiver) = mpsc::channel();
+    let picked = dialog(title, filter).pick_file();
+    wasm_bindgen_futures::spawn_local(async move {
+        let file = match picked.await {
+            Some(handle) => Some(PickedFile {
+                name: handle.file_name(),
+                bytes: handle.read().await,
+            }),
+            None => None,
+        };
+        let _ = sender.send(file);
+    });
+    receiver
+}
+
+/// Contents of a browser file.
+#[cfg(target_arch = "wasm32")]
+pub async fn read(file: &web_sys::File) -> Result<Vec<u8>, String> {
+    let buffer = wasm_bindgen_futures::JsFuture::from(file.array_buffer())
+        .await
+        .map_err(|e| format!("Cannot read {}: {e:?}", file.name()))?;
+    Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+}
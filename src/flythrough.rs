@@ -0,0 +1,288 @@
+use image::{GrayImage, Rgba, RgbaImage};
+
+use crate::palette::Colormap;
+
+/// One stop of the camera path: `center` is in normalized slide coordinates
+/// (`0..=1` on both axes) and `zoom` is the magnification relative to the
+/// whole slide.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keyframe {
+    pub center: [f32; 2],
+    pub zoom: f32,
+}
+
+impl Default for Keyframe {
+    fn default() -> Self {
+        Self {
+            center: [0.5, 0.5],
+            zoom: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VideoFormat {
+    /// Lossless, so colormaps keep every color.
+    WebP,
+    /// Limited to a palette of 256 colors per frame.
+    Gif,
+    #[cfg(not(target_arch = "wasm32"))]
+    Mp4,
+    #[cfg(not(target_arch = "wasm32"))]
+    Webm,
+}
+
+impl VideoFormat {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub const ALL: &[VideoFormat] = &[Self::Mp4, Self::Webm, Self::WebP, Self::Gif];
+    #[cfg(target_arch = "wasm32")]
+    pub const ALL: &[VideoFormat] = &[Self::WebP, Self::Gif];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::WebP => "Animated WebP",
+            Self::Gif => "Animated GIF",
+            #[cfg(not(target_arch = "wasm32"))]
+            Self::Mp4 => "MP4 (ffmpeg)",
+            #[cfg(not(target_arch = "wasm32"))]
+            Self::Webm => "WebM (ffmpeg)",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::WebP => "webp",
+            Self::Gif => "gif",
+            #[cfg(not(target_arch = "wasm32"))]
+            Self::Mp4 => "mp4",
+            #[cfg(not(target_arch = "wasm32"))]
+            Self::Webm => "webm",
+        }
+    }
+}
+
+impl Default for VideoFormat {
+    fn default() -> Self {
+        Self::ALL[0]
+    }
+}
+
+/// Interpolates the camera between keyframes: linear in position and
+/// geometric in zoom so zooming feels uniform.
+pub fn camera_path(keyframes: &[Keyframe], frames_per_segment: usize) -> Vec<Keyframe> {
+    let steps = frames_per_segment.max(1);
+    let mut path = Vec::new();
+    for pair in keyframes.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        for step in 0..steps {
+            let t = step as f32 / steps as f32;
+            let lerp = |u: f32, v: f32| u + (v - u) * t;
+            path.push(Keyframe {
+                center: [
+                    lerp(a.center[0], b.center[0]),
+                    lerp(a.center[1], b.center[1]),
+                ],
+                zoom: lerp(a.zoom.max(1.0).ln(), b.zoom.max(1.0).ln()).exp(),
+            });
+        }
+    }
+    if let Some(&last) = keyframes.last() {
+        path.push(last);
+    }
+    path
+}
+
+/// Renders one frame: the slide region seen by `camera`, resampled to
+/// `out_w` x `out_h`, with `response` blended over it in `colormap`.
+/// `range` is the response interval mapped onto the colormap.
+#[allow(clippy::too_many_arguments)]
+pub fn render_frame(
+    slide: &GrayImage,
+    response: &[f32],
+    range: (f32, f32),
+    camera: Keyframe,
+    out_w: u32,
+    out_h: u32,
+    opacity: f32,
+    colormap: Colormap,
+) -> RgbaImage {
+    let (w, h) = (slide.width() as f32, slide.height() as f32);
+    let zoom = camera.zoom.max(1.0);
+    let (view_w, view_h) = (w / zoom, h / zoom);
+    let x0 = (camera.center[0] * w - view_w / 2.0).clamp(0.0, w - view_w);
+    let y0 = (camera.center[1] * h - view_h / 2.0).clamp(0.0, h - view_h);
+    let span = (range.1 - range.0).max(1e-6);
+
+    RgbaImage::from_fn(out_w, out_h, |ox, oy| {
+        let sx = ((x0 + (ox as f32 + 0.5) * view_w / out_w as f32) as u32).min(slide.width() - 1);
+        let sy = ((y0 + (oy as f32 + 0.5) * view_h / out_h as f32) as u32).min(slide.height() - 1);
+        let gray = slide.get_pixel(sx, sy)[0] as f32 / 255.0;
+        let t = (response[(sy * slide.width() + sx) as usize] - range.0) / span;
+        let heat = colormap.sample(t);
+        let mix = |c: f32| (((1.0 - opacity) * gray + opacity * c) * 255.0) as u8;
+        Rgba([mix(heat[0]), mix(heat[1]), mix(heat[2]), 255])
+    })
+}
+
+pub fn encode_gif(frames: Vec<RgbaImage>, fps: u32) -> Result<Vec<u8>, String> {
+    use image::codecs::gif::{GifEncoder, Repeat};
+
+    let mut bytes = Vec::new();
+    {
+        let mut encoder = GifEncoder::new_with_speed(&mut bytes, 10);
+        encoder
+            .set_repeat(Repeat::Infinite)
+            .map_err(|e| format!("GIF encoding failed: {e}"))?;
+        let delay = image::Delay::from_numer_denom_ms(1000, fps.max(1));
+        encoder
+            .encode_frames(
+                frames
+                    .into_iter()
+                    .map(|frame| image::Frame::from_parts(frame, 0, 0, delay)),
+            )
+            .map_err(|e| format!("GIF encoding failed: {e}"))?;
+    }
+    Ok(bytes)
+}
+
+/// Encodes frames as a looping animated WebP. Each frame is a lossless
+/// VP8L image, wrapped in the animation chunks of the WebP container.
+pub fn encode_webp(frames: &[RgbaImage], fps: u32) -> Result<Vec<u8>, String> {
+    use image::codecs::webp::WebPEncoder;
+
+    let Some(first) = frames.first() else {
+        return Err("A flythrough needs at least one frame.".to_owned());
+    };
+    let (width, height) = first.dimensions();
+    let duration = 1000 / fps.max(1);
+    let mut body = b"WEBP".to_vec();
+    let mut vp8x = vec![0x02, 0, 0, 0];
+    vp8x.extend_from_slice(&u24(width - 1));
+    vp8x.extend_from_slice(&u24(height - 1));
+    push_chunk(&mut body, b"VP8X", &vp8x);
+    // Opaque white background, looping forever.
+    push_chunk(&mut body, b"ANIM", &[0xff, 0xff, 0xff, 0xff, 0, 0]);
+    for frame in frames {
+        // Frames are opaque, so the RGB channels are all the encoder needs.
+        let rgb = image::DynamicImage::ImageRgba8(frame.clone()).into_rgb8();
+        let mut still = Vec::new();
+        WebPEncoder::new_lossless(&mut still)
+            .encode(rgb.as_raw(), width, height, image::ExtendedColorType::Rgb8)
+            .map_err(|e| format!("WebP encoding failed: {e}"))?;
+        // The still file is RIFF, size, WEBP, then its single VP8L chunk.
+        let image = still
+            .get(12..)
+            .ok_or("WebP encoding failed: truncated output")?;
+        let mut anmf = [
+            u24(0),
+            u24(0),
+            u24(width - 1),
+            u24(height - 1),
+            u24(duration),
+        ]
+        .concat();
+        // Frames replace the canvas rather than blending into it.
+        anmf.push(0x02);
+        anmf.extend_from_slice(image);
+        push_chunk(&mut body, b"ANMF", &anmf);
+    }
+    let mut bytes = b"RIFF".to_vec();
+    bytes.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    bytes.extend(body);
+    Ok(bytes)
+}
+
+/// `value`'s low 24 bits, little-endian, as WebP header fields store them.
+fn u24(value: u32) -> [u8; 3] {
+    let [a, b, c, _] = value.to_le_bytes();
+    [a, b, c]
+}
+
+/// Appends a RIFF chunk, padded to an even length.
+fn push_chunk(out: &mut Vec<u8>, tag: &[u8; 4], payload: &[u8]) {
+    out.extend_from_slice(tag);
+    out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    out.extend_from_slice(payload);
+    if payload.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+/// Encodes frames with an `ffmpeg` executable found on `PATH`, going
+/// through a temporary PNG sequence.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn encode_with_ffmpeg(
+    frames: &[RgbaImage],
+    fps: u32,
+    format: VideoFormat,
+) -> Result<Vec<u8>, String> {
+    let dir = std::env::temp_dir().join(format!("convolution_flythrough_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Cannot create {}: {e}", dir.display()))?;
+    let result = (|| {
+        for (i, frame) in frames.iter().enumerate() {
+            frame
+                .save(dir.join(format!("frame_{i:05}.png")))
+                .map_err(|e| format!("Cannot write frame {i}: {e}"))?;
+        }
+        let output = dir.join(format!("flythrough.{}", format.extension()));
+        let codec: &[&str] = match format {
+            VideoFormat::Webm => &["-c:v", "libvpx-vp9", "-b:v", "0", "-crf", "32"],
+            _ => &["-c:v", "libx264", "-pix_fmt", "yuv420p"],
+        };
+        let status = std::process::Command::new("ffmpeg")
+            .args([
+                "-y",
+                "-loglevel",
+                "error",
+                "-framerate",
+                &fps.to_string(),
+                "-i",
+            ])
+            .arg(dir.join("frame_%05d.png"))
+            .args(codec)
+            .arg(&output)
+            .status()
+            .map_err(|e| format!("Could not start ffmpeg: {e}"))?;
+        if !status.success() {
+            return Err(format!("ffmpeg exited with {status}"));
+        }
+        std::fs::read(&output).map_err(|e| format!("Cannot read encoded video: {e}"))
+    })();
+    let _ = std::fs::remove_dir_all(&dir);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::AnimationDecoder;
+    use image::codecs::webp::WebPDecoder;
+
+    use super::*;
+
+    #[test]
+    fn webp_frames_decode_losslessly() {
+        // More distinct colors than a GIF palette holds.
+        let frames: Vec<RgbaImage> = (0..3)
+            .map(|f| {
+                RgbaImage::from_fn(33, 20, |x, y| {
+                    Rgba([
+                        (x * 7 + f * 40) as u8,
+                        (y * 12) as u8,
+                        (x * y + f) as u8,
+                        255,
+                    ])
+                })
+            })
+            .collect();
+        let bytes = encode_webp(&frames, 10).unwrap();
+        let decoder = WebPDecoder::new(Cursor::new(bytes)).unwrap();
+        let decoded: Vec<_> = decoder.into_frames().collect_frames().unwrap();
+        assert_eq!(decoded.len(), frames.len());
+        for (frame, original) in decoded.iter().zip(&frames) {
+            assert_eq!(frame.delay().numer_denom_ms(), (100, 1));
+            assert_eq!(frame.buffer(), original);
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::sync::mpsc::{self, Receiver};
+
+/// Kernel sheet of the example gallery, stored in the repository's
+/// `gallery` folder.
+pub struct GalleryEntry {
+    pub name: &'static str,
+    pub file: &'static str,
+    /// Tile size the sheet splits into.
+    pub shape: (usize, usize),
+    pub description: &'static str,
+}
+
+pub const GALLERY: [GalleryEntry; 4] = [
+    GalleryEntry {
+        name: "Edge detectors",
+        file: "edges_3x3.png",
+        shape: (3, 3),
+        description: "Sobel, Prewitt and Scharr in x and y, and two Laplacians.",
+    },
+    GalleryEntry {
+        name: "Gabor bank",
+        file: "gabor_15x15.png",
+        shape: (15, 15),
+        description: "Even Gabor filters at 0, 45, 90 and 135 degrees, with wavelengths of \
+                      4 (top row) and 8 pixels.",
+    },
+    GalleryEntry {
+        name: "Blob detectors",
+        file: "blobs_11x11.png",
+        shape: (11, 11),
+        description: "Differences of Gaussians (top row) and negated Laplacians of \
+                      Gaussians at sigmas of 1, 1.6 and 2.4 pixels.",
+    },
+    GalleryEntry {
+        name: "Learned layer-1 filters",
+        file: "learned_7x7.png",
+        shape: (7, 7),
+        description: "Sixteen filters learned by spherical k-means on whitened 7x7 \
+                      patches of a synthetic tissue image of nuclei and fibres: \
+                      oriented edges, bars, corners and blobs, like the first layer \
+                      of a trained network.",
+    },
+];
+
+/// Contents of a sheet being downloaded, once they arrive.
+pub type Download = Receiver<Result<Vec<u8>, String>>;
+
+/// Where the gallery is read from by default: the repository on native
+/// builds, the `gallery` folder that trunk copies next to the page on the
+/// web.
+#[cfg(not(target_arch = "wasm32"))]
+pub const DEFAULT_URL: &str = "https://raw.githubusercontent.com/fmath92/convolution/main/gallery/";
+#[cfg(target_arch = "wasm32")]
+pub const DEFAULT_URL: &str = "gallery/";
+
+/// Location of `entry` under `base`, a URL or, on native builds, a folder.
+pub fn entry_url(base: &str, entry: &GalleryEntry) -> String {
+    if base.is_empty() || base.ends_with('/') {
+        format!("{base}{}", entry.file)
+    } else {
+        format!("{base}/{}", entry.file)
+    }
+}
+
+/// Starts reading `url` in the background. Native builds download over
+/// HTTP and read anything without a scheme from disk.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn download(url: String) -> Download {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let result = if url.contains("://") {
+            fetch(&url).map_err(|e| format!("Cannot download {url}: {e}"))
+        } else {
+            std::fs::read(&url).map_err(|e| format!("Cannot read {url}: {e}"))
+        };
+        let _ = sender.send(result);
+    });
+    receiver
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn fetch(url: &str) -> Result<Vec<u8>, String> {
+    use std::io::Read;
+
+    let response = ureq::get(url)
+        .timeout(std::time::Duration::from_secs(60))
+        .call()
+        .map_err(|e| e.to_string())?;
+    let mut bytes = Vec::new();
+    response
+        .into_reader()
+        .read_to_end(&mut bytes)
+        .map_err(|e| e.to_string())?;
+    Ok(bytes)
+}
+
+/// Starts fetching `url`, relative to the page unless absolute.
+#[cfg(target_arch = "wasm32")]
+pub fn download(url: String) -> Download {
+    let (sender, receiver) = mpsc::channel();
+    wasm_bindgen_futures::spawn_local(async move {
+        let result = match crate::config::fetch(&url).await {
+            Ok(Some(bytes)) => Ok(bytes),
+            Ok(None) => Err(format!("{url} not found")),
+            Err(e) => Err(format!("Cannot fetch {url}: {e}")),
+        };
+        let _ = sender.send(result);
+    });
+    receiver
+}
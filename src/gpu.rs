@@ -0,0 +1,482 @@
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+
+#[cfg(target_arch = "wasm32")]
+use eframe::glow;
+use wgpu::util::DeviceExt;
+
+#[cfg(target_arch = "wasm32")]
+use crate::webgl::GlConvolver;
+
+/// Zero-padded "same" convolution, one invocation per output pixel, visiting
+/// taps in the order of the CPU backends.
+const SHADER: &str = "
+struct Params {
+    width: u32,
+    height: u32,
+    kw: u32,
+    kh: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> input: array<f32>;
+@group(0) @binding(2) var<storage, read> kernel: array<f32>;
+@group(0) @binding(3) var<storage, read_write> output: array<f32>;
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.width || id.y >= params.height {
+        return;
+    }
+    let w = i32(params.width);
+    let h = i32(params.height);
+    var acc = 0.0;
+    for (var ky = 0u; ky < params.kh; ky++) {
+        let iy = i32(id.y) + i32(ky) - i32(params.kh / 2u);
+        if iy < 0 || iy >= h {
+            continue;
+        }
+        for (var kx = 0u; kx < params.kw; kx++) {
+            let ix = i32(id.x) + i32(kx) - i32(params.kw / 2u);
+            if ix < 0 || ix >= w {
+                continue;
+            }
+            acc += input[u32(iy * w + ix)] * kernel[ky * params.kw + kx];
+        }
+    }
+    output[id.y * params.width + id.x] = acc;
+}
+";
+
+/// Side of the shader's workgroups.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// A wgpu device with the convolution pipeline compiled: the browser's
+/// WebGPU, or Vulkan, Metal or DirectX 12 natively.
+pub struct GpuConvolver {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    /// Adapter name as reported by the driver; often empty in browsers.
+    pub adapter: String,
+}
+
+impl GpuConvolver {
+    /// Requests an adapter and device; `Err` when the browser has no
+    /// usable WebGPU or the machine no supported GPU.
+    pub async fn new() -> Result<Self, String> {
+        #[cfg(target_arch = "wasm32")]
+        if !wgpu::util::is_browser_webgpu_supported().await {
+            return Err("WebGPU is not available in this browser".to_owned());
+        }
+        let backends = if cfg!(target_arch = "wasm32") {
+            wgpu::Backends::BROWSER_WEBGPU
+        } else {
+            wgpu::Backends::PRIMARY
+        };
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends,
+            ..Default::default()
+        });
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                ..Default::default()
+            })
+            .await
+            .ok_or("No GPU adapter")?;
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("convolution"),
+                    required_features: wgpu::Features::empty(),
+                    required_limits: adapter.limits(),
+                    memory_hints: wgpu::MemoryHints::Performance,
+                },
+                None,
+            )
+            .await
+            .map_err(|e| format!("Cannot open the GPU device: {e}"))?;
+        device.on_uncaptured_error(Box::new(|e| log::error!("GPU error: {e}")));
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("convolve"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("convolve"),
+            layout: None,
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        Ok(Self {
+            device,
+            queue,
+            pipeline,
+            adapter: adapter.get_info().name,
+        })
+    }
+
+    /// Largest slide, in pixels, whose maps fit in one storage buffer.
+    fn max_pixels(&self) -> usize {
+        let limits = self.device.limits();
+        let bytes = (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size);
+        (bytes / 4) as usize
+    }
+
+    /// Map of one kernel, waiting for the GPU to finish it.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn convolve(
+        &self,
+        input: &[f32],
+        width: usize,
+        height: usize,
+        kernel: &[f32],
+        kw: usize,
+        kh: usize,
+    ) -> Result<Vec<f32>, String> {
+        let kernels = vec![(kernel.to_vec(), (kw, kh))];
+        let mut run = GpuRun::new(self, input, width, height, kernels)?;
+        loop {
+            if let Some((_, response)) = run.poll(self)? {
+                return Ok(response);
+            }
+            self.device.poll(wgpu::Maintain::Wait);
+        }
+    }
+}
+
+/// One kernel in flight: its map is being copied to `staging`, which the
+/// driver maps asynchronously.
+struct Pending {
+    index: usize,
+    mapped: Receiver<Result<(), wgpu::BufferAsyncError>>,
+}
+
+/// A run of every kernel over one slide on the GPU. The slide is uploaded
+/// once; kernels are dispatched one at a time and their maps read back as
+/// the GPU completes them, so [`GpuRun::poll`] never blocks a frame.
+pub struct GpuRun {
+    /// Weights and shape of every kernel.
+    kernels: Vec<(Vec<f32>, (usize, usize))>,
+    width: usize,
+    height: usize,
+    input: wgpu::Buffer,
+    output: wgpu::Buffer,
+    staging: wgpu::Buffer,
+    next: usize,
+    pending: Option<Pending>,
+}
+
+impl GpuRun {
+    pub fn new(
+        gpu: &GpuConvolver,
+        input: &[f32],
+        width: usize,
+        height: usize,
+        kernels: Vec<(Vec<f32>, (usize, usize))>,
+    ) -> Result<Self, String> {
+        if width * height > gpu.max_pixels() {
+            return Err(format!(
+                "The slide has more than the {} pixels a GPU buffer holds",
+                gpu.max_pixels()
+            ));
+        }
+        let groups = |n: usize| (n as u32).div_ceil(WORKGROUP_SIZE);
+        let max_groups = gpu.device.limits().max_compute_workgroups_per_dimension;
+        if groups(width) > max_groups || groups(height) > max_groups {
+            return Err("The slide is too wide for one GPU dispatch".to_owned());
+        }
+        let device = &gpu.device;
+        let input = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("input"),
+            contents: &f32_bytes(input),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let size = (width * height * 4) as u64;
+        let output = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("output"),
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("staging"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Ok(Self {
+            kernels,
+            width,
+            height,
+            input,
+            output,
+            staging,
+            next: 0,
+            pending: None,
+        })
+    }
+
+    /// Kernels whose maps have been read back.
+    pub fn completed(&self) -> usize {
+        self.next - usize::from(self.pending.is_some())
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.pending.is_none() && self.next == self.kernels.len()
+    }
+
+    /// Pixel-tap products of the whole run.
+    pub fn work(&self) -> u64 {
+        let taps: usize = self.kernels.iter().map(|(weights, _)| weights.len()).sum();
+        (self.width * self.height * taps) as u64
+    }
+
+    /// Dispatches the next kernel when the GPU is idle and returns the map
+    /// of the kernel in flight once it has been read back.
+    pub fn poll(&mut self, gpu: &GpuConvolver) -> Result<Option<(usize, Vec<f32>)>, String> {
+        let Some(pending) = &self.pending else {
+            if self.next < self.kernels.len() {
+                self.dispatch(gpu, self.next);
+                self.next += 1;
+            }
+            return Ok(None);
+        };
+        gpu.device.poll(wgpu::Maintain::Poll);
+        match pending.mapped.try_recv() {
+            Err(TryRecvError::Empty) => return Ok(None),
+            Err(TryRecvError::Disconnected) => return Err("GPU read-back was dropped".to_owned()),
+            Ok(Err(e)) => return Err(format!("GPU read-back failed: {e}")),
+            Ok(Ok(())) => {}
+        }
+        let index = pending.index;
+        self.pending = None;
+        let response = {
+            let view = self.staging.slice(..).get_mapped_range();
+            view.chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect()
+        };
+        self.staging.unmap();
+        Ok(Some((index, response)))
+    }
+
+    fn dispatch(&mut self, gpu: &GpuConvolver, index: usize) {
+        let device = &gpu.device;
+        let (weights, (kw, kh)) = &self.kernels[index];
+        let params = [self.width, self.height, *kw, *kh].map(|v| v as u32);
+        let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("params"),
+            contents: &params
+                .iter()
+                .flat_map(|v| v.to_le_bytes())
+                .collect::<Vec<_>>(),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let kernel = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("kernel"),
+            contents: &f32_bytes(weights),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("convolve"),
+            layout: &gpu.pipeline.get_bind_group_layout(0),
+            entries: &[&params, &self.input, &kernel, &self.output]
+                .iter()
+                .enumerate()
+                .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                    binding: binding as u32,
+                    resource: buffer.as_entire_binding(),
+                })
+                .collect::<Vec<_>>(),
+        });
+        let mut encoder = device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&gpu.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(
+                (self.width as u32).div_ceil(WORKGROUP_SIZE),
+                (self.height as u32).div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+        }
+        encoder.copy_buffer_to_buffer(&self.output, 0, &self.staging, 0, self.output.size());
+        gpu.queue.submit([encoder.finish()]);
+
+        let (sender, mapped) = mpsc::channel();
+        self.staging
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        self.pending = Some(Pending { index, mapped });
+    }
+}
+
+fn f32_bytes(values: &[f32]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+/// Which accelerator exact runs use in the browser: WebGPU when the browser
+/// offers it, else the WebGL2 fragment shader, else the CPU backends.
+#[cfg(target_arch = "wasm32")]
+pub enum BrowserGpu {
+    /// Waiting for the WebGPU adapter request; the GL context is the
+    /// fallback.
+    Detecting(
+        Receiver<Result<GpuConvolver, String>>,
+        Option<Arc<glow::Context>>,
+    ),
+    WebGpu(GpuConvolver),
+    /// With the reason WebGPU is unavailable.
+    WebGl(GlConvolver, String),
+    /// With the reason neither GPU path is available.
+    Cpu(String),
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Default for BrowserGpu {
+    fn default() -> Self {
+        Self::Cpu("not probed".to_owned())
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl BrowserGpu {
+    /// Starts the WebGPU request; `gl` is eframe's context.
+    pub fn detect(gl: Option<Arc<glow::Context>>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        wasm_bindgen_futures::spawn_local(async move {
+            let _ = sender.send(GpuConvolver::new().await);
+        });
+        Self::Detecting(receiver, gl)
+    }
+
+    /// Settles on a path once the WebGPU request has finished. Returns true
+    /// while still detecting.
+    pub fn update(&mut self) -> bool {
+        let Self::Detecting(receiver, gl) = self else {
+            return false;
+        };
+        let reason = match receiver.try_recv() {
+            Err(TryRecvError::Empty) => return true,
+            Ok(Ok(gpu)) => {
+                *self = Self::WebGpu(gpu);
+                return false;
+            }
+            Ok(Err(e)) => e,
+            Err(TryRecvError::Disconnected) => "WebGPU detection failed".to_owned(),
+        };
+        *self = match gl.take().ok_or_else(|| "no WebGL context".to_owned()) {
+            Ok(gl) => match GlConvolver::new(gl) {
+                Ok(convolver) => Self::WebGl(convolver, reason),
+                Err(e) => Self::Cpu(format!("{reason}; {e}")),
+            },
+            Err(e) => Self::Cpu(format!("{reason}; {e}")),
+        };
+        false
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Self::Detecting(..) => "detecting WebGPU...".to_owned(),
+            Self::WebGpu(gpu) if gpu.adapter.is_empty() => "WebGPU compute".to_owned(),
+            Self::WebGpu(gpu) => format!("WebGPU compute ({})", gpu.adapter),
+            Self::WebGl(_, reason) => format!("WebGL2 fragment shader ({reason})"),
+            Self::Cpu(reason) => format!("CPU only ({reason})"),
+        }
+    }
+
+    /// The WebGPU device, when that is the browser's path.
+    pub fn convolver(&self) -> Option<&GpuConvolver> {
+        match self {
+            Self::WebGpu(gpu) => Some(gpu),
+            _ => None,
+        }
+    }
+}
+
+/// The GPU of a native build. It is only probed once the GPU is turned on,
+/// on a thread of its own since drivers can take a while to start.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+pub enum NativeGpu {
+    #[default]
+    NotProbed,
+    Detecting(Receiver<Result<GpuConvolver, String>>),
+    /// Shared with background benchmarks.
+    Ready(Arc<GpuConvolver>),
+    /// With the reason no GPU is usable.
+    Unavailable(String),
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl NativeGpu {
+    /// Starts probing the GPU unless that has been done. Returns true while
+    /// detecting.
+    pub fn update(&mut self, wanted: bool) -> bool {
+        match self {
+            Self::NotProbed if wanted => {
+                let (sender, receiver) = mpsc::channel();
+                std::thread::spawn(move || {
+                    let _ = sender.send(block_on(GpuConvolver::new()));
+                });
+                *self = Self::Detecting(receiver);
+                true
+            }
+            Self::Detecting(receiver) => {
+                *self = match receiver.try_recv() {
+                    Err(TryRecvError::Empty) => return true,
+                    Ok(Ok(gpu)) => Self::Ready(Arc::new(gpu)),
+                    Ok(Err(e)) => Self::Unavailable(e),
+                    Err(TryRecvError::Disconnected) => {
+                        Self::Unavailable("GPU detection failed".to_owned())
+                    }
+                };
+                false
+            }
+            _ => false,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Self::NotProbed => "not probed".to_owned(),
+            Self::Detecting(_) => "detecting...".to_owned(),
+            Self::Ready(gpu) => format!("{} compute", gpu.adapter),
+            Self::Unavailable(reason) => format!("unavailable ({reason})"),
+        }
+    }
+
+    pub fn convolver(&self) -> Option<&GpuConvolver> {
+        match self {
+            Self::Ready(gpu) => Some(gpu),
+            _ => None,
+        }
+    }
+
+    /// The device, for use on another thread.
+    pub fn shared(&self) -> Option<Arc<GpuConvolver>> {
+        match self {
+            Self::Ready(gpu) => Some(gpu.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Runs `future` on the calling thread. Native wgpu resolves adapter and
+/// device requests without waking, so nothing has to drive them but polls.
+#[cfg(not(target_arch = "wasm32"))]
+fn block_on<T>(future: impl std::future::Future<Output = T>) -> T {
+    let mut future = std::pin::pin!(future);
+    let mut context = std::task::Context::from_waker(std::task::Waker::noop());
+    loop {
+        if let std::task::Poll::Ready(value) = future.as_mut().poll(&mut context) {
+            return value;
+        }
+        std::thread::yield_now();
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+
+use crate::export::csv_field;
+use crate::kernel::{Kernel, Provenance};
+
+/// Aggregated scores of the kernels sharing a group tag.
+pub struct GroupScore {
+    pub name: String,
+    pub members: Vec<usize>,
+    pub mean: f32,
+    pub max: f32,
+    /// Member with the highest score.
+    pub best: usize,
+}
+
+/// One summary per named group in name order; untagged and muted kernels
+/// are left out. `scores` may be shorter than `kernels` while a run is in
+/// progress.
+pub fn group_scores(kernels: &[Kernel], scores: &[f32]) -> Vec<GroupScore> {
+    let mut groups: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (i, kernel) in kernels.iter().enumerate().take(scores.len()) {
+        if !kernel.group.is_empty() && kernel.enabled {
+            groups.entry(&kernel.group).or_default().push(i);
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(name, members)| {
+            let best = members
+                .iter()
+                .copied()
+                .max_by(|&a, &b| scores[a].total_cmp(&scores[b]))
+                .unwrap_or(0);
+            let sum: f32 = members.iter().map(|&i| scores[i]).sum();
+            GroupScore {
+                name: name.to_owned(),
+                mean: sum / members.len() as f32,
+                max: scores[best],
+                best,
+                members,
+            }
+        })
+        .collect()
+}
+
+/// Tags every sheet tile with its row, overwriting existing tags. Kernels
+/// built in the app keep theirs.
+pub fn group_by_rows(kernels: &mut [Kernel]) {
+    for kernel in kernels {
+        if let Provenance::SheetTile { row, .. } = &kernel.provenance {
+            kernel.group = format!("row {row}");
+        }
+    }
+}
+
+/// Distinct tags in name order.
+pub fn group_names(kernels: &[Kernel]) -> Vec<String> {
+    let mut names: Vec<String> = kernels
+        .iter()
+        .filter(|k| !k.group.is_empty())
+        .map(|k| k.group.clone())
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Folds `response` into the per-pixel maximum of absolute responses, i.e.
+/// how strongly any kernel of the group responds there. `combined` starts
+/// empty.
+pub fn accumulate_max_abs(combined: &mut Vec<f32>, response: &[f32]) {
+    if combined.is_empty() {
+        combined.resize(response.len(), 0.0);
+    }
+    for (c, r) in combined.iter_mut().zip(response) {
+        *c = c.max(r.abs());
+    }
+}
+
+pub fn group_scores_csv(groups: &[GroupScore]) -> String {
+    let mut csv = String::from("group,kernels,mean_score,max_score,best_kernel,members\n");
+    for group in groups {
+        let members: Vec<String> = group.members.iter().map(usize::to_string).collect();
+        csv.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            csv_field(&group.name),
+            group.members.len(),
+            group.mean,
+            group.max,
+            group.best,
+            csv_field(&members.join(" "))
+        ));
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tagged(group: &str, enabled: bool) -> Kernel {
+        let mut kernel = Kernel::new(vec![1.0], (1, 1), Provenance::Entered);
+        kernel.group = group.to_owned();
+        kernel.enabled = enabled;
+        kernel
+    }
+
+    #[test]
+    fn groups_skip_untagged_muted_and_unscored_kernels() {
+        let kernels = [
+            tagged("nuclei", true),
+            tagged("", true),
+            tagged("edges", true),
+            tagged("nuclei", true),
+            tagged("nuclei", false),
+            tagged("edges", true),
+        ];
+        // The last kernel has no score yet.
+        let groups = group_scores(&kernels, &[2.0, 9.0, 1.0, 4.0, 8.0]);
+        let summary: Vec<_> = groups
+            .iter()
+            .map(|g| (g.name.as_str(), g.members.clone(), g.mean, g.max, g.best))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("edges", vec![2], 1.0, 1.0, 2),
+                ("nuclei", vec![0, 3], 3.0, 4.0, 3)
+            ]
+        );
+        assert_eq!(
+            group_scores_csv(&groups),
+            "group,kernels,mean_score,max_score,best_kernel,members\n\
+             edges,1,1,1,2,2\n\
+             nuclei,2,3,4,3,0 3\n"
+        );
+    }
+
+    #[test]
+    fn group_map_keeps_the_strongest_response() {
+        let mut combined = Vec::new();
+        accumulate_max_abs(&mut combined, &[1.0, -3.0, 0.5]);
+        accumulate_max_abs(&mut combined, &[-2.0, 1.0, 0.25]);
+        assert_eq!(combined, [2.0, 3.0, 0.5]);
+    }
+}
@@ -0,0 +1,143 @@
+use std::ops::Range;
+
+use image::{GrayImage, ImageBuffer, Pixel};
+use serde::{Deserialize, Serialize};
+
+/// Rectangular region of interest of a slide, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Roi {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Roi {
+    /// Region between two corners in slide pixels, clipped to a `width` x
+    /// `height` slide; `None` when less than a pixel is left.
+    pub fn from_corners(a: [f32; 2], b: [f32; 2], (width, height): (usize, usize)) -> Option<Self> {
+        let x0 = a[0].min(b[0]).max(0.0) as usize;
+        let y0 = a[1].min(b[1]).max(0.0) as usize;
+        let x1 = (a[0].max(b[0]).ceil().max(0.0) as usize).min(width);
+        let y1 = (a[1].max(b[1]).ceil().max(0.0) as usize).min(height);
+        (x1 > x0 && y1 > y0).then_some(Self {
+            x: x0,
+            y: y0,
+            width: x1 - x0,
+            height: y1 - y0,
+        })
+    }
+
+    /// The region lies within a `width` x `height` slide.
+    pub fn fits(&self, (width, height): (usize, usize)) -> bool {
+        self.width > 0
+            && self.height > 0
+            && self.x + self.width <= width
+            && self.y + self.height <= height
+    }
+
+    /// `self` moved by `(dx, dy)` pixels, its size kept, stopping at the
+    /// edges of a `width` x `height` slide.
+    pub fn moved(&self, (dx, dy): (isize, isize), (width, height): (usize, usize)) -> Self {
+        let shift = |start: usize, len: usize, by: isize, side: usize| {
+            (start as isize + by).clamp(0, side.saturating_sub(len) as isize) as usize
+        };
+        Self {
+            x: shift(self.x, self.width, dx, width),
+            y: shift(self.y, self.height, dy, height),
+            ..*self
+        }
+    }
+
+    /// Columns and rows of `self`, in its own pixels, where a `kw` x `kh`
+    /// window lies within both `self` and `other`, so a filter of that size
+    /// gives them the same value in either region whatever the border;
+    /// `None` when there are none.
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub fn shared_interior(
+        &self,
+        other: &Roi,
+        (kw, kh): (usize, usize),
+    ) -> Option<(Range<usize>, Range<usize>)> {
+        // Half the window on either side covers kernels anchored at either
+        // of their middle taps.
+        let span = |start: usize, len: usize, other_start: usize, other_len: usize, k: usize| {
+            let first = start.max(other_start) + k / 2;
+            let end = (start + len)
+                .min(other_start + other_len)
+                .saturating_sub(k / 2);
+            (first < end).then(|| first - start..end - start)
+        };
+        Some((
+            span(self.x, self.width, other.x, other.width, kw)?,
+            span(self.y, self.height, other.y, other.height, kh)?,
+        ))
+    }
+
+    /// Copy of the region of `image`, which it must fit.
+    pub fn crop<P: Pixel + 'static>(
+        &self,
+        image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    ) -> ImageBuffer<P, Vec<P::Subpixel>> {
+        let [x, y, w, h] = [self.x, self.y, self.width, self.height].map(|v| v as u32);
+        image::imageops::crop_imm(image, x, y, w, h).to_image()
+    }
+}
+
+/// Averages `factor` x `factor` blocks; edge blocks average whatever pixels
+/// they cover.
+pub fn downsample_box(
+    src: &[f32],
+    width: usize,
+    height: usize,
+    factor: usize,
+) -> (Vec<f32>, usize, usize) {
+    let out_w = width.div_ceil(factor);
+    let out_h = height.div_ceil(factor);
+    let mut sums = vec![0.0f32; out_w * out_h];
+    let mut counts = vec![0u32; out_w * out_h];
+    for y in 0..height {
+        for x in 0..width {
+            let o = (y / factor) * out_w + x / factor;
+            sums[o] += src[y * width + x];
+            counts[o] += 1;
+        }
+    }
+    let out = sums
+        .into_iter()
+        .zip(counts)
+        .map(|(sum, count)| sum / count as f32)
+        .collect();
+    (out, out_w, out_h)
+}
+
+/// Intensities in `[0, 1]`, row-major.
+pub fn gray_to_f32(gray: &GrayImage) -> Vec<f32> {
+    gray.pixels().map(|p| p[0] as f32 / 255.0).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_interior_keeps_half_the_window_inside_both_regions() {
+        let a = Roi {
+            x: 0,
+            y: 0,
+            width: 20,
+            height: 10,
+        };
+        let b = Roi { x: 4, y: 2, ..a };
+        // Odd: two taps either side of the centre.
+        assert_eq!(a.shared_interior(&b, (5, 5)), Some((6..18, 4..8)));
+        assert_eq!(b.shared_interior(&a, (5, 5)), Some((2..14, 2..6)));
+        // Even: half the size on both sides covers either middle tap.
+        assert_eq!(a.shared_interior(&b, (4, 6)), Some((6..18, 5..7)));
+        assert_eq!(b.shared_interior(&a, (4, 6)), Some((2..14, 3..5)));
+        // An overlap no wider than the window's reach shares nothing.
+        let c = Roi { x: 18, y: 0, ..a };
+        assert_eq!(a.shared_interior(&c, (3, 3)), None);
+        assert_eq!(a.shared_interior(&c, (1, 1)), Some((18..20, 0..10)));
+    }
+}
@@ -0,0 +1,1176 @@
+use std::borrow::Cow;
+use std::ops::Range;
+
+use image::{GrayImage, RgbImage};
+use serde::{Deserialize, Serialize};
+
+use crate::backend::{Backend, Sampling};
+use crate::border::{self, BorderMode};
+use crate::morphology::{self, FilterMode};
+
+/// Kernels of a new revision whose weights correlate at least this well with
+/// a kernel of the previous revision are considered the same kernel.
+const MATCH_THRESHOLD: f32 = 0.9;
+/// Matched kernels whose weights differ by less than this are unchanged.
+const UNCHANGED_TOLERANCE: f32 = 1e-6;
+/// Largest difference from an outer product, relative to the largest
+/// weight, of weights still convolved as separable.
+const SEPARABLE_TOLERANCE: f32 = 1e-5;
+/// Sheet rows and columns whose gray levels vary less than this, in squared
+/// levels, are separator lines.
+const SEPARATOR_MAX_VARIANCE: f64 = 1.0;
+/// Patches whose spread about their mean is below this fraction of their
+/// energy are flat, up to rounding, and correlate with no kernel.
+const FLAT_PATCH_TOLERANCE: f64 = 1e-5;
+
+/// One kernel of the bank with its row-major weights and user annotations.
+/// Kernels cut from a sheet share its tile size; 1-D and separable kernels
+/// built in the app have their own.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Kernel {
+    pub weights: Vec<f32>,
+    pub width: usize,
+    pub height: usize,
+    /// Row and column kernels whose outer product is `weights`, applied as
+    /// two 1-D passes instead of the full window.
+    pub separable: Option<(Vec<f32>, Vec<f32>)>,
+    /// Row-major support: only taps marked `true` take part in the
+    /// convolution. `None` uses the whole window.
+    pub mask: Option<Vec<bool>>,
+    /// Red, green and blue weights of a tile of a color sheet, row-major,
+    /// convolved channel by channel in all-channels runs; `weights` holds
+    /// the tile's gray levels.
+    pub color: Option<[Vec<f32>; 3]>,
+    /// Muted kernels stay in the bank but are left out of exports, score
+    /// summaries, quick scores and headless runs.
+    pub enabled: bool,
+    pub name: String,
+    pub note: String,
+    /// Tag shared by kernels scored together; empty when ungrouped.
+    pub group: String,
+    pub provenance: Provenance,
+    /// Human-readable log of everything applied after creation, oldest first.
+    pub history: Vec<String>,
+}
+
+impl Kernel {
+    pub fn new(weights: Vec<f32>, (width, height): (usize, usize), provenance: Provenance) -> Self {
+        Self {
+            weights,
+            width,
+            height,
+            separable: None,
+            mask: None,
+            color: None,
+            enabled: true,
+            name: String::new(),
+            note: String::new(),
+            group: String::new(),
+            provenance,
+            history: Vec::new(),
+        }
+    }
+
+    /// 2-D kernel applying `row` along rows, then `column` along columns.
+    pub fn separable(row: Vec<f32>, column: Vec<f32>, provenance: Provenance) -> Self {
+        let weights = column
+            .iter()
+            .flat_map(|&c| row.iter().map(move |&r| r * c))
+            .collect();
+        let size = (row.len(), column.len());
+        Self {
+            separable: Some((row, column)),
+            ..Self::new(weights, size, provenance)
+        }
+    }
+
+    /// Factors the weights into row and column kernels when they are an
+    /// outer product, as box, Gaussian and Sobel kernels are, so runs take the
+    /// two-pass path. Returns whether the kernel is separable.
+    pub fn detect_separable(&mut self) -> bool {
+        if self.separable.is_none() {
+            self.separable = rank_one_factors(&self.weights, self.width, self.height);
+        }
+        self.separable.is_some()
+    }
+
+    /// The kernel weighted in `mode`: flipped in both axes, with its mask,
+    /// factors and color weights, when the mode convolves rather than
+    /// correlates. Reversing a row-major window flips both axes at once.
+    pub fn oriented(&self, mode: FilterMode) -> Cow<'_, Kernel> {
+        if !mode.flips_kernel() {
+            return Cow::Borrowed(self);
+        }
+        let reversed = |values: &[f32]| values.iter().rev().copied().collect::<Vec<f32>>();
+        let mut kernel = self.clone();
+        kernel.weights = reversed(&self.weights);
+        if let Some(mask) = &mut kernel.mask {
+            mask.reverse();
+        }
+        if let Some((row, column)) = &mut kernel.separable {
+            row.reverse();
+            column.reverse();
+        }
+        for channel in kernel.color.iter_mut().flatten() {
+            channel.reverse();
+        }
+        Cow::Owned(kernel)
+    }
+
+    /// Multiply-adds per output pixel.
+    pub fn taps(&self) -> usize {
+        match (&self.separable, &self.mask) {
+            (Some((row, column)), None) => row.len() + column.len(),
+            _ => self.weights.len(),
+        }
+    }
+
+    /// The weights every backend convolves with: zero outside the mask.
+    pub fn masked_weights(&self) -> Cow<'_, [f32]> {
+        match &self.mask {
+            Some(mask) => Cow::Owned(
+                self.weights
+                    .iter()
+                    .zip(mask)
+                    .map(|(&w, &inside)| if inside { w } else { 0.0 })
+                    .collect(),
+            ),
+            None => Cow::Borrowed(&self.weights),
+        }
+    }
+
+    /// Weights of color channel `channel`, zero outside the mask, for
+    /// kernels with color weights.
+    pub fn channel_weights(&self, channel: usize) -> Option<Vec<f32>> {
+        let weights = self.color.as_ref()?.get(channel)?;
+        Some(match &self.mask {
+            Some(mask) => weights
+                .iter()
+                .zip(mask)
+                .map(|(&w, &inside)| if inside { w } else { 0.0 })
+                .collect(),
+            None => weights.clone(),
+        })
+    }
+
+    /// Number of taps inside the mask.
+    pub fn support(&self) -> usize {
+        self.mask.as_ref().map_or(self.weights.len(), |mask| {
+            mask.iter().filter(|&&m| m).count()
+        })
+    }
+
+    /// Weights of the in-mask taps, row-major.
+    pub fn support_weights(&self) -> Vec<f32> {
+        match &self.mask {
+            Some(mask) => self
+                .weights
+                .iter()
+                .zip(mask)
+                .filter(|&(_, &inside)| inside)
+                .map(|(&w, _)| w)
+                .collect(),
+            None => self.weights.clone(),
+        }
+    }
+
+    /// Full window holding `values` at the in-mask taps, in the order of
+    /// [`Kernel::support_weights`], and zero elsewhere.
+    pub fn scatter_support(&self, values: &[f32]) -> Vec<f32> {
+        let Some(mask) = &self.mask else {
+            return values.to_vec();
+        };
+        let mut values = values.iter();
+        mask.iter()
+            .map(|&inside| {
+                if inside {
+                    *values.next().unwrap_or(&0.0)
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+
+    /// Zero-padded "same" response of the kernel, in two 1-D passes when it
+    /// is separable. The passes round differently from the full window, so
+    /// separable maps match their dense equivalent up to float rounding. A
+    /// mask generally breaks separability, so masked kernels always run the
+    /// full window.
+    pub fn convolve(
+        &self,
+        backend: Backend,
+        input: &[f32],
+        width: usize,
+        height: usize,
+    ) -> Vec<f32> {
+        match (&self.separable, &self.mask) {
+            (Some((row, column)), None) => {
+                let rows = backend.convolve(input, width, height, row, row.len(), 1);
+                backend.convolve(&rows, width, height, column, 1, column.len())
+            }
+            _ => backend.convolve(
+                input,
+                width,
+                height,
+                &self.masked_weights(),
+                self.width,
+                self.height,
+            ),
+        }
+    }
+
+    /// [`Kernel::convolve`] at the pixels and tap spacing of `sampling`.
+    /// Sparse samplings always run the full window.
+    pub fn convolve_sampled(
+        &self,
+        backend: Backend,
+        input: &[f32],
+        width: usize,
+        height: usize,
+        sampling: Sampling,
+    ) -> Vec<f32> {
+        if sampling.is_dense() {
+            return self.convolve(backend, input, width, height);
+        }
+        let weights = self.masked_weights();
+        backend.convolve_sampled(
+            input,
+            width,
+            height,
+            &weights,
+            self.width,
+            self.height,
+            sampling,
+        )
+    }
+
+    /// Response of the kernel in `mode` with `border` around the image:
+    /// [`Kernel::convolve`] for the weighted sums, the normalized
+    /// cross-correlation, or otherwise a rank filter over the kernel's
+    /// support (the mask, or the whole window).
+    pub fn filter(
+        &self,
+        mode: FilterMode,
+        backend: Backend,
+        border: BorderMode,
+        input: &[f32],
+        width: usize,
+        height: usize,
+    ) -> Vec<f32> {
+        let size = (self.width, self.height);
+        border::filter_image(
+            border,
+            input,
+            width,
+            height,
+            size,
+            |input, width, height| {
+                self.filter_zero(mode, backend, Sampling::DENSE, input, width, height)
+            },
+        )
+    }
+
+    /// [`Kernel::filter`] at the pixels and tap spacing of `sampling`, giving
+    /// a map of `sampling.output_size(width, height)`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn filter_sampled(
+        &self,
+        mode: FilterMode,
+        backend: Backend,
+        border: BorderMode,
+        sampling: Sampling,
+        input: &[f32],
+        width: usize,
+        height: usize,
+    ) -> Vec<f32> {
+        if sampling.is_dense() {
+            return self.filter(mode, backend, border, input, width, height);
+        }
+        let extent = sampling.extent(self.width, self.height);
+        border::filter_image_strided(
+            border,
+            input,
+            width,
+            height,
+            extent,
+            sampling.stride,
+            |input, width, height| self.filter_zero(mode, backend, sampling, input, width, height),
+        )
+    }
+
+    /// [`Kernel::filter`] over the `cols` x `rows` region of an image that
+    /// is not held in memory as a whole, whose rows `row` returns.
+    #[allow(clippy::too_many_arguments)]
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub fn filter_rows<'a>(
+        &self,
+        mode: FilterMode,
+        backend: Backend,
+        border: BorderMode,
+        row: impl Fn(usize) -> &'a [f32],
+        (width, height): (usize, usize),
+        cols: Range<usize>,
+        rows: Range<usize>,
+    ) -> Vec<f32> {
+        let size = (self.width, self.height);
+        border::filter_rows(
+            border,
+            row,
+            width,
+            height,
+            cols,
+            rows,
+            size,
+            |input, width, height| {
+                self.filter_zero(mode, backend, Sampling::DENSE, input, width, height)
+            },
+        )
+    }
+
+    fn filter_zero(
+        &self,
+        mode: FilterMode,
+        backend: Backend,
+        sampling: Sampling,
+        input: &[f32],
+        width: usize,
+        height: usize,
+    ) -> Vec<f32> {
+        if mode.is_linear() {
+            return self
+                .oriented(mode)
+                .convolve_sampled(backend, input, width, height, sampling);
+        }
+        if mode == FilterMode::NormalizedCorrelation {
+            return self.normalized_correlation(backend, sampling, input, width, height);
+        }
+        let full;
+        let support = match &self.mask {
+            Some(mask) => mask.as_slice(),
+            None => {
+                full = vec![true; self.weights.len()];
+                &full
+            }
+        };
+        let (kw, kh) = (self.width, self.height);
+        morphology::rank_filter(input, width, height, support, kw, kh, mode, sampling)
+    }
+
+    /// Zero-normalized cross-correlation over the kernel's support, from
+    /// three window sums the backend computes: the mean-free kernel against
+    /// the patch, and the patch's sum and sum of squares. Flat patches, and
+    /// every patch of a flat kernel, give 0.
+    fn normalized_correlation(
+        &self,
+        backend: Backend,
+        sampling: Sampling,
+        input: &[f32],
+        width: usize,
+        height: usize,
+    ) -> Vec<f32> {
+        let weights = self.support_weights();
+        let taps = weights.len() as f64;
+        let mean = weights.iter().sum::<f32>() / weights.len() as f32;
+        let centred: Vec<f32> = weights.iter().map(|&w| w - mean).collect();
+        let energy = centred
+            .iter()
+            .map(|&w| w as f64 * w as f64)
+            .sum::<f64>()
+            .sqrt();
+        let window = |weights: &[f32], values: &[f32]| {
+            let weights = self.scatter_support(weights);
+            let (kw, kh) = (self.width, self.height);
+            backend.convolve_sampled(values, width, height, &weights, kw, kh, sampling)
+        };
+        let products = window(&centred, input);
+        let ones = vec![1.0; weights.len()];
+        let sums = window(&ones, input);
+        let squares: Vec<f32> = input.iter().map(|&v| v * v).collect();
+        let square_sums = window(&ones, &squares);
+        products
+            .iter()
+            .zip(sums)
+            .zip(square_sums)
+            .map(|((&product, sum), square_sum)| {
+                let square_sum = square_sum as f64;
+                let spread = square_sum - sum as f64 * sum as f64 / taps;
+                if energy == 0.0 || spread <= FLAT_PATCH_TOLERANCE * square_sum {
+                    return 0.0;
+                }
+                (product as f64 / (energy * spread.sqrt())).clamp(-1.0, 1.0) as f32
+            })
+            .collect()
+    }
+}
+
+/// Disc (ellipse for non-square windows) inscribed in a `width` x `height`
+/// window: the taps whose centres lie within it, for isotropic detection.
+pub fn circular_mask(width: usize, height: usize) -> Vec<bool> {
+    let (rx, ry) = (width as f32 / 2.0, height as f32 / 2.0);
+    (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let dx = (x as f32 + 0.5 - rx) / rx;
+            let dy = (y as f32 + 0.5 - ry) / ry;
+            dx * dx + dy * dy <= 1.0
+        })
+        .collect()
+}
+
+/// Taps of a 1-D kernel typed as numbers separated by commas or spaces.
+pub fn parse_taps(text: &str) -> Result<Vec<f32>, String> {
+    let taps = text
+        .split([',', ' ', ';'])
+        .filter(|t| !t.trim().is_empty())
+        .map(|t| {
+            t.trim()
+                .parse::<f32>()
+                .ok()
+                .filter(|t| t.is_finite())
+                .ok_or_else(|| format!("\"{}\" is not a finite number", t.trim()))
+        })
+        .collect::<Result<Vec<f32>, String>>()?;
+    if taps.is_empty() {
+        return Err("Type at least one tap".to_owned());
+    }
+    Ok(taps)
+}
+
+/// What splitting does with the pixels of a sheet that does not divide into
+/// whole tiles.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SheetRemainder {
+    /// Leaves out the partial tiles of the last row and column.
+    #[default]
+    Skip,
+    /// Splits the remainder between opposite edges and leaves it out, for
+    /// sheets drawn with a margin.
+    Crop,
+    /// Completes the partial tiles with zero weights.
+    Pad,
+}
+
+impl SheetRemainder {
+    pub const ALL: [Self; 3] = [Self::Skip, Self::Crop, Self::Pad];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Skip => "Skip partial tiles",
+            Self::Crop => "Crop the margins",
+            Self::Pad => "Pad with zeros",
+        }
+    }
+}
+
+/// Cuts a packed sheet into `kw` x `kh` kernels, row by row, mapping pixel
+/// intensities onto `[-1, 1]`, and deals with a remainder as `remainder`
+/// says. Returns the kernels with the grid's row and column counts.
+pub fn split_sheet(
+    sheet: &GrayImage,
+    file: &str,
+    (kw, kh): (u32, u32),
+    remainder: SheetRemainder,
+) -> Result<(Vec<Kernel>, usize, usize), String> {
+    let (width, height) = sheet.dimensions();
+    let (cols, rows, (left, top)) = match remainder {
+        SheetRemainder::Skip => (width / kw, height / kh, (0, 0)),
+        SheetRemainder::Crop => (width / kw, height / kh, (width % kw / 2, height % kh / 2)),
+        SheetRemainder::Pad => (width.div_ceil(kw), height.div_ceil(kh), (0, 0)),
+    };
+    if cols == 0 || rows == 0 {
+        return Err(format!(
+            "The {width}x{height} kernel sheet is smaller than one {kw}x{kh} tile."
+        ));
+    }
+
+    let (cols, rows) = (cols as usize, rows as usize);
+    let mut kernels = Vec::with_capacity(rows * cols);
+    for row in 0..rows {
+        for col in 0..cols {
+            let (x, y) = (left + col as u32 * kw, top + row as u32 * kh);
+            kernels.push(cut_tile(sheet, file, (row, col), x..x + kw, y..y + kh));
+        }
+    }
+    Ok((kernels, rows, cols))
+}
+
+/// Cuts a sheet whose kernels are set apart by constant-intensity lines into
+/// the blocks between them, row by row, mapping intensities as
+/// [`split_sheet`] does. Blocks follow the separators, so irregular spacing
+/// gives kernels of different sizes. `Ok(None)` when no line of the sheet
+/// is a separator, or nothing lies between them.
+pub fn split_at_separators(
+    sheet: &GrayImage,
+    file: &str,
+    max_side: usize,
+) -> Result<Option<(Vec<Kernel>, usize, usize)>, String> {
+    let (width, height) = sheet.dimensions();
+    let is_blank = |pixels: &mut dyn Iterator<Item = u8>| {
+        let (mut n, mut sum, mut squares) = (0.0f64, 0.0f64, 0.0f64);
+        for p in pixels {
+            let p = p as f64;
+            n += 1.0;
+            sum += p;
+            squares += p * p;
+        }
+        let mean = sum / n;
+        squares / n - mean * mean <= SEPARATOR_MAX_VARIANCE
+    };
+    let blank_rows: Vec<bool> = (0..height)
+        .map(|y| is_blank(&mut (0..width).map(|x| sheet.get_pixel(x, y)[0])))
+        .collect();
+    let blank_cols: Vec<bool> = (0..width)
+        .map(|x| is_blank(&mut (0..height).map(|y| sheet.get_pixel(x, y)[0])))
+        .collect();
+    if !blank_rows.iter().chain(&blank_cols).any(|&b| b) {
+        return Ok(None);
+    }
+    let (row_blocks, col_blocks) = (segments(&blank_rows), segments(&blank_cols));
+    if row_blocks.is_empty() || col_blocks.is_empty() {
+        return Ok(None);
+    }
+    let mut kernels = Vec::with_capacity(row_blocks.len() * col_blocks.len());
+    for (row, ys) in row_blocks.iter().enumerate() {
+        for (col, xs) in col_blocks.iter().enumerate() {
+            if xs.len() > max_side || ys.len() > max_side {
+                return Err(format!(
+                    "The block at ({}, {}) between separator lines is {}x{}, more than {max_side} per side.",
+                    xs.start,
+                    ys.start,
+                    xs.len(),
+                    ys.len()
+                ));
+            }
+            kernels.push(cut_tile(sheet, file, (row, col), xs.clone(), ys.clone()));
+        }
+    }
+    Ok(Some((kernels, row_blocks.len(), col_blocks.len())))
+}
+
+/// Runs of lines that are not `blank`.
+fn segments(blank: &[bool]) -> Vec<Range<u32>> {
+    let mut runs = Vec::new();
+    let mut start = None;
+    for (i, &b) in blank.iter().chain([&true]).enumerate() {
+        match (start, b) {
+            (None, false) => start = Some(i as u32),
+            (Some(first), true) => {
+                runs.push(first..i as u32);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    runs
+}
+
+/// Kernel of the sheet pixels in columns `xs` and rows `ys`, the tile at
+/// `row` and `col` of its grid. Taps past the sheet's edges weigh zero.
+fn cut_tile(
+    sheet: &GrayImage,
+    file: &str,
+    (row, col): (usize, usize),
+    xs: Range<u32>,
+    ys: Range<u32>,
+) -> Kernel {
+    let size = (xs.len(), ys.len());
+    let weights = ys
+        .clone()
+        .flat_map(|y| xs.clone().map(move |x| (x, y)))
+        .map(|(x, y)| {
+            sheet
+                .get_pixel_checked(x, y)
+                .map_or(0.0, |p| (p[0] as f32 / 255.0) * 2.0 - 1.0)
+        })
+        .collect();
+    let provenance = Provenance::SheetTile {
+        file: file.to_owned(),
+        row,
+        col,
+        x: xs.start,
+        y: ys.start,
+    };
+    let mut kernel = Kernel::new(weights, size, provenance);
+    kernel.detect_separable();
+    kernel
+}
+
+/// Rescaling of the tiles cut from a sheet. Mapping pixels onto `[-1, 1]`
+/// leaves each tile an arbitrary DC offset, which dominates mean-abs scores;
+/// normalized tiles score comparably.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KernelNormalization {
+    #[default]
+    None,
+    /// The mean weight subtracted, so flat regions give no response.
+    ZeroMean,
+    /// Divided by the L2 norm of the weights.
+    UnitL2,
+    /// Divided by the sum of the weights, as smoothing kernels are.
+    SumToOne,
+}
+
+impl KernelNormalization {
+    pub const ALL: [Self; 4] = [Self::None, Self::ZeroMean, Self::UnitL2, Self::SumToOne];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::None => "None",
+            Self::ZeroMean => "Zero mean",
+            Self::UnitL2 => "Unit L2 norm",
+            Self::SumToOne => "Sum to one",
+        }
+    }
+
+    /// Normalizes the weights of each of `kernels`, and each channel of
+    /// their color weights, and looks for their factors again. Kernels
+    /// whose norm or sum is about zero cannot be scaled and are left as
+    /// they are; returns how many.
+    pub fn apply(self, kernels: &mut [Kernel]) -> usize {
+        if self == Self::None {
+            return 0;
+        }
+        let mut skipped = 0;
+        for kernel in kernels {
+            let Some(weights) = self.normalized(&kernel.weights) else {
+                skipped += 1;
+                continue;
+            };
+            kernel.weights = weights;
+            for channel in kernel.color.iter_mut().flatten() {
+                if let Some(weights) = self.normalized(channel) {
+                    *channel = weights;
+                }
+            }
+            kernel.separable = None;
+            kernel.detect_separable();
+            kernel
+                .history
+                .push(format!("Normalized: {}.", self.label()));
+        }
+        skipped
+    }
+
+    fn normalized(self, weights: &[f32]) -> Option<Vec<f32>> {
+        let l1: f32 = weights.iter().map(|w| w.abs()).sum();
+        let divisor = match self {
+            Self::None => 1.0,
+            Self::ZeroMean => {
+                let mean = weights.iter().sum::<f32>() / weights.len().max(1) as f32;
+                return Some(weights.iter().map(|w| w - mean).collect());
+            }
+            Self::UnitL2 => weights.iter().map(|w| w * w).sum::<f32>().sqrt(),
+            Self::SumToOne => weights.iter().sum(),
+        };
+        // Relative to the weights' size, so a nearly balanced tile is not
+        // blown up by rounding.
+        (divisor.abs() > 1e-6 * l1).then(|| weights.iter().map(|w| w / divisor).collect())
+    }
+}
+
+/// Gives each tile of `kernels` cut from `sheet`'s gray levels the color
+/// weights of the same tile, mapped onto `[-1, 1]` as the gray ones are.
+pub fn add_color_weights(kernels: &mut [Kernel], sheet: &RgbImage) {
+    for kernel in kernels {
+        let Provenance::SheetTile { x, y, .. } = kernel.provenance else {
+            continue;
+        };
+        let (kw, kh) = (kernel.width as u32, kernel.height as u32);
+        if x + kw > sheet.width() || y + kh > sheet.height() {
+            continue;
+        }
+        kernel.color = Some(std::array::from_fn(|c| {
+            (0..kh)
+                .flat_map(|ky| (0..kw).map(move |kx| (kx, ky)))
+                .map(|(kx, ky)| sheet.get_pixel(x + kx, y + ky)[c] as f32 / 255.0 * 2.0 - 1.0)
+                .collect()
+        }));
+    }
+}
+
+/// Where a kernel's weights came from.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Provenance {
+    /// Tile of a packed PNG kernel sheet; `x`/`y` is the tile's top-left
+    /// pixel.
+    SheetTile {
+        file: String,
+        row: usize,
+        col: usize,
+        x: u32,
+        y: u32,
+    },
+    /// Kernel `index` of a CSV or JSON kernel file.
+    File { file: String, index: usize },
+    /// 1-D kernel typed in the app.
+    Entered,
+    /// Classical filter from the built-in kernel presets.
+    Preset,
+    /// Built in the app from two kernels of the bank.
+    Derived {
+        operation: KernelOperation,
+        a: usize,
+        b: usize,
+    },
+}
+
+/// Ways of combining two kernels of the same shape into a new one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KernelOperation {
+    #[default]
+    Sum,
+    Difference,
+    /// Kernel applying A and then B, cropped around its centre to the bank's
+    /// shape.
+    Convolution,
+    /// Separable kernel from the centre row of the first kernel and the centre
+    /// column of the second, e.g. a row and a column kernel.
+    OuterProduct,
+}
+
+impl KernelOperation {
+    pub const ALL: [Self; 4] = [
+        Self::Sum,
+        Self::Difference,
+        Self::Convolution,
+        Self::OuterProduct,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Sum => "A + B",
+            Self::Difference => "A - B",
+            Self::Convolution => "A * B (convolution)",
+            Self::OuterProduct => "row of A x column of B (separable)",
+        }
+    }
+
+    /// Kernel combining kernels `a` and `b` of the bank, with its provenance
+    /// recorded. Only the outer product accepts kernels of different shapes;
+    /// masked-out taps count as zero.
+    pub fn combine(
+        self,
+        (ia, a): (usize, &Kernel),
+        (ib, b): (usize, &Kernel),
+    ) -> Result<Kernel, String> {
+        let provenance = Provenance::Derived {
+            operation: self,
+            a: ia,
+            b: ib,
+        };
+        let (wa, wb) = (a.masked_weights(), b.masked_weights());
+        if self == Self::OuterProduct {
+            let row = wa[a.height / 2 * a.width..(a.height / 2 + 1) * a.width].to_vec();
+            let column = (0..b.height)
+                .map(|y| wb[y * b.width + b.width / 2])
+                .collect();
+            return Ok(Kernel::separable(row, column, provenance));
+        }
+        let (kw, kh) = (a.width, a.height);
+        if (kw, kh) != (b.width, b.height) {
+            return Err(format!(
+                "Kernels {ia} ({kw}x{kh}) and {ib} ({}x{}) differ in shape.",
+                b.width, b.height
+            ));
+        }
+        let (a, b) = (&*wa, &*wb);
+        let weights = match self {
+            Self::Sum => a.iter().zip(b).map(|(x, y)| x + y).collect(),
+            Self::Difference => a.iter().zip(b).map(|(x, y)| x - y).collect(),
+            _ => convolve_kernels(a, b, kw, kh),
+        };
+        let mut kernel = Kernel::new(weights, (kw, kh), provenance);
+        kernel.detect_separable();
+        Ok(kernel)
+    }
+}
+
+/// Row and column kernels whose outer product is the `width` x `height`
+/// `weights`, when it is one up to [`SEPARABLE_TOLERANCE`]: the row and
+/// column through the largest weight, the row divided by it. A rank-1
+/// matrix is exactly that product, so no decomposition is needed. Single
+/// rows and columns gain nothing from two passes and are not factored.
+fn rank_one_factors(weights: &[f32], width: usize, height: usize) -> Option<(Vec<f32>, Vec<f32>)> {
+    if width < 2 || height < 2 || weights.len() != width * height {
+        return None;
+    }
+    let (pivot, scale) = weights
+        .iter()
+        .copied()
+        .enumerate()
+        .max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))?;
+    if scale == 0.0 || !scale.is_finite() {
+        return None;
+    }
+    let (px, py) = (pivot % width, pivot / width);
+    let column: Vec<f32> = (0..height).map(|y| weights[y * width + px]).collect();
+    let row: Vec<f32> = weights[py * width..(py + 1) * width]
+        .iter()
+        .map(|&w| w / scale)
+        .collect();
+    let tolerance = SEPARABLE_TOLERANCE * scale.abs();
+    let factored = weights
+        .iter()
+        .enumerate()
+        .all(|(i, &w)| (column[i / width] * row[i % width] - w).abs() <= tolerance);
+    factored.then_some((row, column))
+}
+
+/// Kernel applying `a` and then `b`, both `kw` x `kh`, cropped around its
+/// centre to the same shape.
+fn convolve_kernels(a: &[f32], b: &[f32], kw: usize, kh: usize) -> Vec<f32> {
+    let (fw, fh) = (2 * kw - 1, 2 * kh - 1);
+    let mut full = vec![0.0; fw * fh];
+    for (i, &wa) in a.iter().enumerate() {
+        for (j, &wb) in b.iter().enumerate() {
+            let x = i % kw + j % kw;
+            let y = i / kw + j / kw;
+            full[y * fw + x] += wa * wb;
+        }
+    }
+    // The composite's centre tap is at the sum of both centres; the crop puts
+    // it at (kw / 2, kh / 2) like any other kernel.
+    let (ox, oy) = (kw / 2, kh / 2);
+    (0..kh)
+        .flat_map(|y| (0..kw).map(move |x| (x, y)))
+        .map(|(x, y)| full[(y + oy) * fw + x + ox])
+        .collect()
+}
+
+impl std::fmt::Display for Provenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SheetTile {
+                file,
+                row,
+                col,
+                x,
+                y,
+            } => write!(f, "{file} tile r{row} c{col} at ({x}, {y})"),
+            Self::File { file, index } => write!(f, "{file} kernel {index}"),
+            Self::Entered => write!(f, "entered in the app"),
+            Self::Preset => write!(f, "built-in preset"),
+            Self::Derived { operation, a, b } => match operation {
+                KernelOperation::Sum => write!(f, "kernel {a} + kernel {b}"),
+                KernelOperation::Difference => write!(f, "kernel {a} - kernel {b}"),
+                KernelOperation::Convolution => write!(f, "kernel {a} convolved with kernel {b}"),
+                KernelOperation::OuterProduct => {
+                    write!(f, "row of kernel {a} x column of kernel {b}")
+                }
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KernelChange {
+    Unchanged { previous: usize },
+    Changed { previous: usize },
+    Added,
+}
+
+impl KernelChange {
+    pub fn previous(self) -> Option<usize> {
+        match self {
+            Self::Unchanged { previous } | Self::Changed { previous } => Some(previous),
+            Self::Added => None,
+        }
+    }
+}
+
+/// How the kernels of a freshly split sheet relate to the previous revision.
+pub struct RevisionDiff {
+    /// One entry per new kernel.
+    pub changes: Vec<KernelChange>,
+    /// Indices of previous kernels without a counterpart.
+    pub removed: Vec<usize>,
+}
+
+/// Matches new kernels to previous ones by Pearson correlation of their
+/// weights, greedily pairing the most similar kernels first.
+pub fn match_revisions(previous: &[Kernel], current: &[Kernel]) -> RevisionDiff {
+    let mut pairs: Vec<(f32, usize, usize)> = Vec::new();
+    for (ci, c) in current.iter().enumerate() {
+        for (pi, p) in previous.iter().enumerate() {
+            if p.weights.len() == c.weights.len() {
+                let similarity = correlation(&p.weights, &c.weights);
+                if similarity >= MATCH_THRESHOLD {
+                    pairs.push((similarity, pi, ci));
+                }
+            }
+        }
+    }
+    pairs.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let mut changes = vec![KernelChange::Added; current.len()];
+    let mut taken = vec![false; previous.len()];
+    for (_, pi, ci) in pairs {
+        if taken[pi] || changes[ci] != KernelChange::Added {
+            continue;
+        }
+        taken[pi] = true;
+        let identical = previous[pi]
+            .weights
+            .iter()
+            .zip(&current[ci].weights)
+            .all(|(a, b)| (a - b).abs() < UNCHANGED_TOLERANCE);
+        changes[ci] = if identical {
+            KernelChange::Unchanged { previous: pi }
+        } else {
+            KernelChange::Changed { previous: pi }
+        };
+    }
+
+    let removed = (0..previous.len()).filter(|&i| !taken[i]).collect();
+    RevisionDiff { changes, removed }
+}
+
+/// Pearson correlation; two constant kernels correlate perfectly only when
+/// they are equal.
+fn correlation(a: &[f32], b: &[f32]) -> f32 {
+    let n = a.len().max(1) as f32;
+    let mean_a = a.iter().sum::<f32>() / n;
+    let mean_b = b.iter().sum::<f32>() / n;
+    let mut cov = 0.0;
+    let mut var_a = 0.0;
+    let mut var_b = 0.0;
+    for (&x, &y) in a.iter().zip(b) {
+        let (dx, dy) = (x - mean_a, y - mean_b);
+        cov += dx * dy;
+        var_a += dx * dx;
+        var_b += dy * dy;
+    }
+    if var_a <= 0.0 || var_b <= 0.0 {
+        return if (mean_a - mean_b).abs() < UNCHANGED_TOLERANCE {
+            1.0
+        } else {
+            0.0
+        };
+    }
+    cov / (var_a * var_b).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kernel(weights: Vec<f32>, size: (usize, usize)) -> Kernel {
+        Kernel::new(weights, size, Provenance::Entered)
+    }
+
+    fn sobel() -> Kernel {
+        kernel(vec![-1.0, 0.0, 1.0, -2.0, 0.0, 2.0, -1.0, 0.0, 1.0], (3, 3))
+    }
+
+    fn gaussian() -> Kernel {
+        let taps: Vec<f32> = (-3..=3)
+            .map(|i: i32| (-(i * i) as f32 / 4.5).exp())
+            .collect();
+        let weights = taps
+            .iter()
+            .flat_map(|&y| taps.iter().map(move |&x| x * y))
+            .collect();
+        kernel(weights, (7, 7))
+    }
+
+    #[test]
+    fn classical_kernels_are_separable() {
+        for mut kernel in [sobel(), kernel(vec![1.0 / 20.0; 20], (5, 4)), gaussian()] {
+            assert!(kernel.detect_separable(), "{:?}", kernel.weights);
+            let (row, column) = kernel.separable.clone().unwrap();
+            assert_eq!((row.len(), column.len()), (kernel.width, kernel.height));
+            for (i, &w) in kernel.weights.iter().enumerate() {
+                let product = column[i / kernel.width] * row[i % kernel.width];
+                assert!((product - w).abs() < 1e-6, "{product} vs {w}");
+            }
+            assert_eq!(kernel.taps(), kernel.width + kernel.height);
+        }
+    }
+
+    #[test]
+    fn other_kernels_are_not_factored() {
+        let weights = (0..25).map(|i| ((i * 7919) % 13) as f32 - 6.0).collect();
+        assert!(!kernel(weights, (5, 5)).detect_separable());
+        // A single row gains nothing from two passes.
+        assert!(!kernel(vec![1.0, 2.0, 1.0], (3, 1)).detect_separable());
+        assert!(!kernel(vec![0.0; 9], (3, 3)).detect_separable());
+    }
+
+    #[test]
+    fn two_passes_match_the_full_window() {
+        let (width, height) = (40, 31);
+        let input: Vec<f32> = (0..width * height)
+            .map(|i| ((i * 7919) % 251) as f32 / 250.0)
+            .collect();
+        for mut separable in [sobel(), gaussian()] {
+            let dense = separable.clone();
+            separable.detect_separable();
+            for backend in Backend::ALL {
+                let two_pass = separable.convolve(backend, &input, width, height);
+                let full = dense.convolve(backend, &input, width, height);
+                for (a, b) in two_pass.iter().zip(&full) {
+                    assert!((a - b).abs() < 1e-4, "{backend:?}: {a} vs {b}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn normalized_correlation_ignores_gain_and_offset() {
+        let (width, height) = (20, 16);
+        let weights: Vec<f32> = vec![0.2, -1.0, 0.5, 1.5, 0.0, -0.3, 0.7, 0.9, -1.2];
+        let ncc = kernel(weights.clone(), (3, 3));
+        let mut image: Vec<f32> = (0..width * height)
+            .map(|i| ((i * 7919) % 251) as f32 / 250.0)
+            .collect();
+        // The kernel at two gains, one negative, and offsets, centred on
+        // (10, 8) and (4, 4).
+        for ((cx, cy), gain, offset) in [((10, 8), 3.0, 0.4), ((4, 4), -2.0, 5.0)] {
+            for (i, &w) in weights.iter().enumerate() {
+                image[(cy + i / 3 - 1) * width + cx + i % 3 - 1] = gain * w + offset;
+            }
+        }
+        let map = |image: &[f32]| {
+            let (mode, border) = (FilterMode::NormalizedCorrelation, BorderMode::Zero);
+            ncc.filter(mode, Backend::Scalar, border, image, width, height)
+        };
+        let interior = || (1..height - 1).flat_map(|y| (1..width - 1).map(move |x| (x, y)));
+        let response = map(&image);
+        assert!((response[8 * width + 10] - 1.0).abs() < 1e-4);
+        assert!((response[4 * width + 4] + 1.0).abs() < 1e-4);
+        assert!(response.iter().all(|r| (-1.0..=1.0).contains(r)));
+        // Brightness and contrast of the whole image change nothing away
+        // from the zero border.
+        let rescaled: Vec<f32> = image.iter().map(|&v| 0.1 * v + 0.3).collect();
+        let rescaled = map(&rescaled);
+        for (x, y) in interior() {
+            let (a, b) = (rescaled[y * width + x], response[y * width + x]);
+            assert!((a - b).abs() < 1e-3, "({x}, {y}): {a} vs {b}");
+        }
+        // Flat patches and flat kernels give 0.
+        let flat = map(&vec![0.6; width * height]);
+        assert!(interior().all(|(x, y)| flat[y * width + x] == 0.0));
+        let box_kernel = kernel(vec![1.0; 9], (3, 3));
+        let mode = FilterMode::NormalizedCorrelation;
+        let flat_kernel = box_kernel.filter(
+            mode,
+            Backend::Scalar,
+            BorderMode::Zero,
+            &image,
+            width,
+            height,
+        );
+        assert!(flat_kernel.iter().all(|&r| r == 0.0));
+    }
+
+    #[test]
+    fn revisions_match_kernels_by_their_weights() {
+        let previous = [sobel(), gaussian(), kernel(vec![1.0; 9], (3, 3))];
+        let mut edited = sobel();
+        edited.weights[4] = 0.1;
+        let transposed = kernel(vec![-1.0, -2.0, -1.0, 0.0, 0.0, 0.0, 1.0, 2.0, 1.0], (3, 3));
+        // Reordered, one edited, one new and one gone.
+        let diff = match_revisions(&previous, &[gaussian(), transposed, edited]);
+        assert_eq!(
+            diff.changes,
+            [
+                KernelChange::Unchanged { previous: 1 },
+                KernelChange::Added,
+                KernelChange::Changed { previous: 0 },
+            ]
+        );
+        assert_eq!(diff.removed, [2]);
+    }
+
+    #[test]
+    fn combinations_build_kernels_from_two_of_the_bank() {
+        let delta = kernel(vec![0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0], (3, 3));
+        let combine = |operation: KernelOperation, a: &Kernel, b: &Kernel| {
+            operation.combine((0, a), (1, b)).map(|k| k.weights)
+        };
+        let sum = combine(KernelOperation::Sum, &delta, &sobel()).unwrap();
+        assert_eq!(sum, [-1.0, 0.0, 1.0, -2.0, 1.0, 2.0, -1.0, 0.0, 1.0]);
+        let difference = combine(KernelOperation::Difference, &sobel(), &sobel()).unwrap();
+        assert_eq!(difference, [0.0; 9]);
+        // The centred delta is the identity of the convolution.
+        let convolved = combine(KernelOperation::Convolution, &delta, &sobel()).unwrap();
+        assert_eq!(convolved, sobel().weights);
+        assert!(combine(KernelOperation::Sum, &delta, &gaussian()).is_err());
+
+        let row = kernel(vec![0.0, 0.0, 0.0, 1.0, 2.0, 3.0, 0.0, 0.0, 0.0], (3, 3));
+        let column = kernel(vec![0.0, 1.0, 0.0, 0.0, -1.0, 0.0, 0.0, 2.0, 0.0], (3, 3));
+        let outer = KernelOperation::OuterProduct
+            .combine((4, &row), (2, &column))
+            .unwrap();
+        assert_eq!(
+            outer.weights,
+            [1.0, 2.0, 3.0, -1.0, -2.0, -3.0, 2.0, 4.0, 6.0]
+        );
+        assert_eq!(
+            outer.provenance,
+            Provenance::Derived {
+                operation: KernelOperation::OuterProduct,
+                a: 4,
+                b: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn masks_zero_the_taps_they_leave_out() {
+        let disc = circular_mask(5, 5);
+        let corners = [0, 4, 20, 24];
+        assert!(corners.iter().all(|&i| !disc[i]));
+        assert_eq!(disc.iter().filter(|&&t| t).count(), 21);
+
+        let mut masked = kernel((1..=25).map(|w| w as f32).collect(), (5, 5));
+        masked.mask = Some(disc);
+        assert_eq!(masked.support(), 21);
+        let weights = masked.masked_weights();
+        assert!(corners.iter().all(|&i| weights[i] == 0.0));
+        assert_eq!(masked.scatter_support(&masked.support_weights()), *weights);
+
+        // The masked kernel responds as its zeroed window does.
+        let (width, height) = (12, 9);
+        let image: Vec<f32> = (0..width * height)
+            .map(|i| ((i * 7919) % 251) as f32 / 250.0)
+            .collect();
+        let zeroed = kernel(weights.to_vec(), (5, 5));
+        for border in BorderMode::ALL {
+            let map = |k: &Kernel| {
+                k.filter(
+                    FilterMode::Correlation,
+                    Backend::Scalar,
+                    border,
+                    &image,
+                    width,
+                    height,
+                )
+            };
+            assert_eq!(map(&masked), map(&zeroed), "{border:?}");
+        }
+    }
+
+    #[test]
+    fn typed_taps_make_one_dimensional_kernels() {
+        assert_eq!(parse_taps(" 1, -2;0.5  3 ").unwrap(), [1.0, -2.0, 0.5, 3.0]);
+        assert!(parse_taps("1, two").is_err());
+        assert!(parse_taps("1, inf").is_err());
+        assert!(parse_taps(" , ").is_err());
+
+        let row = kernel(parse_taps("1 2 1").unwrap(), (3, 1));
+        assert_eq!((row.width, row.height, row.taps()), (3, 1, 3));
+        let separable = Kernel::separable(
+            vec![1.0, 2.0, 1.0],
+            vec![-1.0, 0.0, 1.0],
+            Provenance::Entered,
+        );
+        assert_eq!((separable.width, separable.height), (3, 3));
+        assert_eq!(separable.taps(), 6);
+        assert_eq!(
+            separable.weights,
+            [-1.0, -2.0, -1.0, 0.0, 0.0, 0.0, 1.0, 2.0, 1.0]
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::kernel::{self, Kernel, Provenance};
+
+/// One kernel of a JSON kernel file. `weights` is either a list of rows, or
+/// a flat row-major list together with `width` (and optionally `height`).
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KernelEntry {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    note: String,
+    #[serde(default)]
+    group: String,
+    #[serde(default = "enabled_by_default")]
+    enabled: bool,
+    width: Option<usize>,
+    height: Option<usize>,
+    weights: Weights,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Weights {
+    Rows(Vec<Vec<f32>>),
+    Flat(Vec<f32>),
+}
+
+/// Whether a JSON document is a kernel file (a list of kernels, or an
+/// object whose `kernels` is one) rather than a pipeline.
+pub fn is_kernel_json(text: &str) -> bool {
+    match serde_json::from_str::<Value>(text) {
+        Ok(Value::Array(_)) => true,
+        Ok(Value::Object(object)) => object.get("kernels").is_some_and(Value::is_array),
+        _ => false,
+    }
+}
+
+/// Kernels of a JSON kernel file named `file`, with their float weights
+/// kept exactly. Fails on malformed entries and on sides outside 1 to
+/// `max_side`.
+pub fn parse_json(text: &str, file: &str, max_side: usize) -> Result<Vec<Kernel>, String> {
+    let document: Value =
+        serde_json::from_str(text).map_err(|e| format!("Invalid kernel file {file}: {e}"))?;
+    let entries = match document {
+        Value::Array(entries) => entries,
+        Value::Object(mut object) => match object.remove("kernels") {
+            Some(Value::Array(entries)) => entries,
+            _ => return Err(format!("{file} has no \"kernels\" list.")),
+        },
+        _ => return Err(format!("{file} is not a list of kernels.")),
+    };
+    let kernels = entries
+        .into_iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            let entry: KernelEntry =
+                serde_json::from_value(entry).map_err(|e| format!("{file} kernel {index}: {e}"))?;
+            let (weights, size) = match entry.weights {
+                Weights::Rows(rows) => rows_to_weights(&rows),
+                Weights::Flat(weights) => {
+                    let width = entry.width.unwrap_or(0);
+                    let height = entry.height.unwrap_or(weights.len() / width.max(1));
+                    if entry.width.is_none() {
+                        Err("a flat weight list needs a width".to_owned())
+                    } else if weights.len() != width * height {
+                        Err(format!(
+                            "{} weights do not fill {width}x{height}",
+                            weights.len()
+                        ))
+                    } else {
+                        Ok((weights, (width, height)))
+                    }
+                }
+            }
+            .and_then(|(weights, size)| {
+                let given = (
+                    entry.width.unwrap_or(size.0),
+                    entry.height.unwrap_or(size.1),
+                );
+                if given != size {
+                    return Err(format!(
+                        "the weights are {}x{} but the kernel is declared {}x{}",
+                        size.0, size.1, given.0, given.1
+                    ));
+                }
+                checked(weights, size, max_side)
+            })
+            .map_err(|e| format!("{file} kernel {index}: {e}"))?;
+            let mut kernel = Kernel::new(weights, size, provenance(file, index));
+            kernel.detect_separable();
+            kernel.name = entry.name;
+            kernel.note = entry.note;
+            kernel.group = entry.group;
+            kernel.enabled = entry.enabled;
+            Ok(kernel)
+        })
+        .collect::<Result<Vec<Kernel>, String>>()?;
+    non_empty(kernels, file)
+}
+
+/// Kernels of a CSV kernel file named `file`: each kernel is a block of
+/// rows of comma-separated weights, blocks are separated by blank lines,
+/// and a `#` line before a block names it.
+pub fn parse_csv(text: &str, file: &str, max_side: usize) -> Result<Vec<Kernel>, String> {
+    let mut kernels = Vec::new();
+    let mut name = String::new();
+    let mut rows: Vec<Vec<f32>> = Vec::new();
+    let mut finish = |name: &mut String, rows: &mut Vec<Vec<f32>>| -> Result<(), String> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let index = kernels.len();
+        let (weights, size) = rows_to_weights(rows)
+            .and_then(|(weights, size)| checked(weights, size, max_side))
+            .map_err(|e| format!("{file} kernel {index}: {e}"))?;
+        let mut kernel = Kernel::new(weights, size, provenance(file, index));
+        kernel.detect_separable();
+        kernel.name = std::mem::take(name);
+        kernels.push(kernel);
+        rows.clear();
+        Ok(())
+    };
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            finish(&mut name, &mut rows)?;
+        } else if let Some(comment) = line.strip_prefix('#') {
+            finish(&mut name, &mut rows)?;
+            name = comment.trim().to_owned();
+        } else {
+            let row =
+                kernel::parse_taps(line).map_err(|e| format!("{file} line {}: {e}", number + 1))?;
+            rows.push(row);
+        }
+    }
+    finish(&mut name, &mut rows)?;
+    non_empty(kernels, file)
+}
+
+fn provenance(file: &str, index: usize) -> Provenance {
+    Provenance::File {
+        file: file.to_owned(),
+        index,
+    }
+}
+
+/// Row-major weights and size of equally long `rows`.
+fn rows_to_weights(rows: &[Vec<f32>]) -> Result<(Vec<f32>, (usize, usize)), String> {
+    let width = rows.first().map_or(0, Vec::len);
+    if let Some((y, row)) = rows.iter().enumerate().find(|(_, r)| r.len() != width) {
+        return Err(format!(
+            "row {y} has {} weights, but row 0 has {width}",
+            row.len()
+        ));
+    }
+    Ok((rows.concat(), (width, rows.len())))
+}
+
+fn checked(
+    weights: Vec<f32>,
+    (width, height): (usize, usize),
+    max_side: usize,
+) -> Result<(Vec<f32>, (usize, usize)), String> {
+    let side = 1..=max_side;
+    if !side.contains(&width) || !side.contains(&height) {
+        return Err(format!(
+            "size {width}x{height} is outside 1 to {max_side} per side"
+        ));
+    }
+    if let Some(w) = weights.iter().find(|w| !w.is_finite()) {
+        return Err(format!("{w} is not a finite weight"));
+    }
+    Ok((weights, (width, height)))
+}
+
+fn non_empty(kernels: Vec<Kernel>, file: &str) -> Result<Vec<Kernel>, String> {
+    if kernels.is_empty() {
+        return Err(format!("{file} contains no kernels."));
+    }
+    Ok(kernels)
+}
@@ -1,6 +1,77 @@
+mod analysis;
 mod app;
+mod backend;
+#[cfg(not(target_arch = "wasm32"))]
+mod batch;
+mod border;
+#[cfg(target_arch = "wasm32")]
+mod browser_cache;
+mod calibration;
+mod channels;
+#[cfg(target_arch = "wasm32")]
+mod chunked;
+mod config;
+mod control;
+#[cfg(not(target_arch = "wasm32"))]
+mod cpu_run;
+#[cfg(not(target_arch = "wasm32"))]
+mod events;
+mod export;
+mod figure;
+mod file_dialog;
+mod flythrough;
+mod gallery;
+mod gpu;
+mod groups;
+mod imaging;
+mod kernel;
+mod kernel_file;
+mod morphology;
+mod notify;
+mod numbers;
+mod orientation;
+mod palette;
+#[cfg(not(target_arch = "wasm32"))]
+mod parallel;
+mod peaks;
+mod pipeline;
+mod presets;
+#[cfg(target_arch = "wasm32")]
+mod pwa;
+mod quick;
+mod registration;
+#[cfg(not(target_arch = "wasm32"))]
+mod response_store;
+mod scoring;
+mod seams;
+mod selftest;
+mod session;
+mod share;
+mod sparse;
+mod stain;
+mod stats;
+#[cfg(not(target_arch = "wasm32"))]
+mod streaming;
+#[cfg(not(target_arch = "wasm32"))]
+mod taskbar;
+mod teaching;
+mod triage;
+mod usage;
+#[cfg(target_arch = "wasm32")]
+mod webgl;
+mod winners;
+#[cfg(target_arch = "wasm32")]
+mod worker;
 
-pub use app::ConvolutionApp;
+pub use app::{APP_TITLE, ConvolutionApp};
+#[cfg(not(target_arch = "wasm32"))]
+pub use batch::BatchReport;
+#[cfg(not(target_arch = "wasm32"))]
+pub use events::{Event, print_json as print_event};
+#[cfg(not(target_arch = "wasm32"))]
+pub use selftest::{SelfTestReport, run_with_gpu as self_test};
+#[cfg(target_arch = "wasm32")]
+pub use worker::worker_main;
 
 #[cfg(target_arch = "wasm32")]
 pub fn main() {
@@ -18,12 +89,13 @@ pub fn main() {
             .dyn_into::<HtmlCanvasElement>()
             .expect("Element is not a canvas");
 
+        let startup = config::load().await;
         let web_options = eframe::WebOptions::default();
         let result = eframe::WebRunner::new()
             .start(
                 canvas,
                 web_options,
-                Box::new(|cc| Ok(Box::new(ConvolutionApp::new(cc)))),
+                Box::new(|cc| Ok(Box::new(ConvolutionApp::new(cc, startup)))),
             )
             .await;
 
@@ -33,12 +105,70 @@ pub fn main() {
     });
 }
 
+/// Runs the pipeline JSON at `path` without opening a window, reporting
+/// progress and failures to `events`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run_pipeline(path: &str, events: &(dyn Fn(&Event) + Sync)) -> Result<String, String> {
+    let (pipeline, base) = load_pipeline(path).map_err(|e| failed(events, "", e))?;
+    pipeline::run(&pipeline, base, events)
+        .map(|outcome| outcome.summary)
+        .map_err(|e| failed(events, &pipeline.slide.path, e))
+}
+
+/// Runs the pipeline JSON at `path` on every slide matching `slides`, on
+/// `jobs` slides at a time or one per core, reporting each slide's progress
+/// to `events`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run_batch(
+    path: &str,
+    slides: &str,
+    jobs: Option<usize>,
+    events: &(dyn Fn(&Event) + Sync),
+) -> Result<BatchReport, String> {
+    let (pipeline, base) = load_pipeline(path).map_err(|e| failed(events, "", e))?;
+    let slides = batch::expand_glob(slides).map_err(|e| failed(events, "", e))?;
+    batch::run_batch(
+        &pipeline,
+        base,
+        &slides,
+        jobs.unwrap_or_else(parallel::threads),
+        events,
+    )
+}
+
+/// Reads the pipeline JSON at `path` and the folder its relative paths
+/// start from.
+#[cfg(not(target_arch = "wasm32"))]
+fn load_pipeline(path: &str) -> Result<(pipeline::Pipeline, &std::path::Path), String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Cannot read {path}: {e}"))?;
+    let pipeline = pipeline::Pipeline::from_json(&text)?;
+    let base = std::path::Path::new(path)
+        .parent()
+        .unwrap_or(std::path::Path::new("."));
+    Ok((pipeline, base))
+}
+
+/// Reports `error` of the run on `slide`, empty before any slide is known,
+/// and passes it on.
+#[cfg(not(target_arch = "wasm32"))]
+fn failed(events: &(dyn Fn(&Event) + Sync), slide: &str, error: String) -> String {
+    events(&Event::Failed {
+        slide: slide.to_owned(),
+        error: error.clone(),
+    });
+    error
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 pub fn main() -> eframe::Result<()> {
-    let native_options = eframe::NativeOptions::default();
+    let startup = config::load();
+    let native_options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default().with_app_id(taskbar::APP_ID),
+        ..Default::default()
+    };
     eframe::run_native(
-        "WASM Convolution Explorer",
+        APP_TITLE,
         native_options,
-        Box::new(|cc| Ok(Box::new(ConvolutionApp::new(cc)))),
+        Box::new(|cc| Ok(Box::new(ConvolutionApp::new(cc, startup)))),
     )
 }
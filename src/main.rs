@@ -1,7 +1,89 @@
 #[cfg(not(target_arch = "wasm32"))]
-fn main() -> eframe::Result<()> {
+fn main() -> std::process::ExitCode {
+    use std::process::ExitCode;
+
     env_logger::init();
-    convolution_wasm::main()
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    // Headless runs print JSON-lines events on stdout instead of their
+    // summary; errors still go to stderr.
+    let json = args.iter().any(|a| a == "--json");
+    args.retain(|a| a != "--json");
+    let events: &(dyn Fn(&convolution_wasm::Event) + Sync) = if json {
+        &convolution_wasm::print_event
+    } else {
+        &|_| {}
+    };
+    let print = |summary: &str| {
+        if !json {
+            println!("{summary}");
+        }
+    };
+    match args.as_slice() {
+        [flag] if flag == "--self-test" => {
+            let report = convolution_wasm::self_test();
+            println!("{}", report.summary());
+            if report.passed() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+        [] if !json => match convolution_wasm::main() {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("{e}");
+                ExitCode::FAILURE
+            }
+        },
+        [flag, path] if flag == "--pipeline" => {
+            match convolution_wasm::run_pipeline(path, events) {
+                Ok(summary) => {
+                    print(&summary);
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("{e}");
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        [flag, path, slides_flag, slides, rest @ ..]
+            if flag == "--pipeline" && slides_flag == "--slides" =>
+        {
+            let jobs = match rest {
+                [] => None,
+                [jobs_flag, jobs] if jobs_flag == "--jobs" => match jobs.parse() {
+                    Ok(jobs) if jobs > 0 => Some(jobs),
+                    _ => return usage(),
+                },
+                _ => return usage(),
+            };
+            match convolution_wasm::run_batch(path, slides, jobs, events) {
+                Ok(report) => {
+                    print(&report.summary);
+                    if report.failed == 0 {
+                        ExitCode::SUCCESS
+                    } else {
+                        eprintln!("{} of {} slides failed.", report.failed, report.slides);
+                        ExitCode::from(3)
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{e}");
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        _ => usage(),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn usage() -> std::process::ExitCode {
+    eprintln!(
+        "Usage: convolution_wasm [--self-test | --pipeline <pipeline.json> [--slides <glob> [--jobs <n>]] [--json]]"
+    );
+    std::process::ExitCode::from(2)
 }
 
 #[cfg(target_arch = "wasm32")]
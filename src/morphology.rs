@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+
+use crate::backend::Sampling;
+
+/// What a run computes over each kernel's neighborhood: the weighted sum,
+/// its normalized form, or a rank filter over the taps of its support,
+/// ignoring the weights.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FilterMode {
+    /// Weighted sum with the kernel as laid out, as CNN frameworks compute
+    /// it. Earlier versions called this convolution.
+    #[default]
+    #[serde(alias = "Convolution")]
+    Correlation,
+    /// Weighted sum with the kernel flipped in both axes, the convolution of
+    /// signal processing. Even-sized kernels stay anchored at the tap right
+    /// of and below their centre, so their flipped response is shifted by a
+    /// pixel along even sides.
+    #[serde(rename = "FlippedConvolution")]
+    Convolution,
+    /// Correlation of the mean-free kernel with each mean-free patch,
+    /// divided by both their energies: the Pearson correlation in [-1, 1],
+    /// which matches the kernel as a template alike in bright and dark
+    /// regions.
+    NormalizedCorrelation,
+    /// Sliding median, which removes speckle while keeping edges.
+    Median,
+    /// Grayscale erosion.
+    Min,
+    /// Grayscale dilation.
+    Max,
+}
+
+impl FilterMode {
+    pub const ALL: [FilterMode; 6] = [
+        FilterMode::Correlation,
+        FilterMode::Convolution,
+        FilterMode::NormalizedCorrelation,
+        FilterMode::Median,
+        FilterMode::Min,
+        FilterMode::Max,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Correlation => "Correlation (kernel as is)",
+            Self::Convolution => "Convolution (kernel flipped)",
+            Self::NormalizedCorrelation => "Normalized cross-correlation",
+            Self::Median => "Median",
+            Self::Min => "Min (erosion)",
+            Self::Max => "Max (dilation)",
+        }
+    }
+
+    /// Whether responses depend linearly on the weights, which GPU paths,
+    /// weight-based normalizations and the permutation test assume.
+    pub fn is_linear(self) -> bool {
+        matches!(self, Self::Correlation | Self::Convolution)
+    }
+
+    /// Whether kernels are flipped in both axes before weighting.
+    pub fn flips_kernel(self) -> bool {
+        self == Self::Convolution
+    }
+}
+
+/// Median, minimum or maximum of `input` over the taps of `support`, a
+/// row-major `kw` x `kh` footprint centred like the convolution window, at
+/// the pixels and tap spacing of `sampling`.
+/// Taps outside the image are left out rather than read as zero, so
+/// borders are not darkened; pixels with no tap in the image get 0.
+/// The weighted sums are not rank filters and return the input unchanged.
+#[allow(clippy::too_many_arguments)]
+pub fn rank_filter(
+    input: &[f32],
+    width: usize,
+    height: usize,
+    support: &[bool],
+    kw: usize,
+    kh: usize,
+    mode: FilterMode,
+    sampling: Sampling,
+) -> Vec<f32> {
+    let (dw, dh) = sampling.extent(kw, kh);
+    let dilation = sampling.dilation;
+    let offsets: Vec<(isize, isize)> = (0..kh)
+        .flat_map(|ky| (0..kw).map(move |kx| (kx, ky)))
+        .filter(|&(kx, ky)| support[ky * kw + kx])
+        .map(|(kx, ky)| {
+            (
+                (kx * dilation) as isize - (dw / 2) as isize,
+                (ky * dilation) as isize - (dh / 2) as isize,
+            )
+        })
+        .collect();
+    let (ow, oh) = sampling.output_size(width, height);
+    let mut output = vec![0.0; ow * oh];
+    let mut values = Vec::with_capacity(offsets.len());
+    for oy in 0..oh {
+        for ox in 0..ow {
+            let (x, y) = (ox * sampling.stride, oy * sampling.stride);
+            values.clear();
+            for &(dx, dy) in &offsets {
+                let ix = x as isize + dx;
+                let iy = y as isize + dy;
+                if ix >= 0 && iy >= 0 && ix < width as isize && iy < height as isize {
+                    values.push(input[iy as usize * width + ix as usize]);
+                }
+            }
+            output[oy * ow + ox] = match mode {
+                _ if values.is_empty() => 0.0,
+                FilterMode::Correlation
+                | FilterMode::Convolution
+                | FilterMode::NormalizedCorrelation => input[y * width + x],
+                FilterMode::Median => median(&mut values),
+                FilterMode::Min => values.iter().copied().fold(f32::INFINITY, f32::min),
+                FilterMode::Max => values.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+            };
+        }
+    }
+    output
+}
+
+/// Middle value, or the mean of the two middle values for even counts.
+fn median(values: &mut [f32]) -> f32 {
+    let n = values.len();
+    let (_, &mut upper, _) = values.select_nth_unstable_by(n / 2, f32::total_cmp);
+    if n % 2 == 1 {
+        return upper;
+    }
+    let lower = values[..n / 2]
+        .iter()
+        .copied()
+        .fold(f32::NEG_INFINITY, f32::max);
+    (lower + upper) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(input: &[f32], support: &[bool], mode: FilterMode) -> Vec<f32> {
+        rank_filter(input, 5, 3, support, 3, 3, mode, Sampling::DENSE)
+    }
+
+    #[test]
+    fn rank_filters_read_only_taps_inside_the_image() {
+        let mut input = vec![1.0; 15];
+        // A speck on the middle row.
+        input[7] = 9.0;
+        let square = [true; 9];
+        assert_eq!(filter(&input, &square, FilterMode::Median), vec![1.0; 15]);
+        // Erosion does not darken the border, dilation spreads the speck.
+        assert_eq!(filter(&input, &square, FilterMode::Min), vec![1.0; 15]);
+        let dilated = filter(&input, &square, FilterMode::Max);
+        let spread: Vec<usize> = (0..15).filter(|&i| dilated[i] == 9.0).collect();
+        assert_eq!(spread, [1, 2, 3, 6, 7, 8, 11, 12, 13]);
+        // A cross leaves the diagonal neighbours out.
+        let cross: Vec<bool> = (0..9).map(|i| i % 2 == 1 || i == 4).collect();
+        let dilated = filter(&input, &cross, FilterMode::Max);
+        let spread: Vec<usize> = (0..15).filter(|&i| dilated[i] == 9.0).collect();
+        assert_eq!(spread, [2, 6, 7, 8, 12]);
+        // Weighted sums are not rank filters.
+        assert_eq!(filter(&input, &square, FilterMode::Correlation), input);
+    }
+
+    #[test]
+    fn even_counts_take_the_middle_mean() {
+        assert_eq!(median(&mut [4.0, 1.0, 3.0, 2.0]), 2.5);
+        assert_eq!(median(&mut [5.0, -1.0, 2.0]), 2.0);
+    }
+}
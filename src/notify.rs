@@ -0,0 +1,35 @@
+/// Asks for permission to show notifications. Browsers only honor this from
+/// a user gesture, so call it when the user enables notifications.
+#[cfg(target_arch = "wasm32")]
+pub fn request_permission() {
+    if web_sys::Notification::permission() == web_sys::NotificationPermission::Default {
+        let _ = web_sys::Notification::request_permission();
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn request_permission() {}
+
+/// Shows a desktop notification through the platform notification service.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn notify(title: &str, body: &str) -> Result<(), String> {
+    notify_rust::Notification::new()
+        .summary(title)
+        .body(body)
+        .show()
+        .map(drop)
+        .map_err(|e| format!("Notification failed: {e}"))
+}
+
+/// Shows a browser notification if the page was granted permission.
+#[cfg(target_arch = "wasm32")]
+pub fn notify(title: &str, body: &str) -> Result<(), String> {
+    if web_sys::Notification::permission() != web_sys::NotificationPermission::Granted {
+        return Err("Notification permission not granted".to_owned());
+    }
+    let options = web_sys::NotificationOptions::new();
+    options.set_body(body);
+    web_sys::Notification::new_with_options(title, &options)
+        .map(drop)
+        .map_err(|e| format!("Notification failed: {e:?}"))
+}
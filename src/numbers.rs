@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+
+/// How scores and statistics are printed in the UI and, optionally, in the
+/// scores CSV.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NumberFormat {
+    pub significant_digits: usize,
+    /// Nonzero magnitudes below this switch to scientific notation, so
+    /// small normalized metrics do not print as 0.00000.
+    pub scientific_below: f32,
+    /// Magnitudes at or above this switch to scientific notation too.
+    pub scientific_above: f32,
+    /// The scores CSV uses this format instead of full precision.
+    pub in_exports: bool,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        Self {
+            significant_digits: 5,
+            scientific_below: 1e-3,
+            scientific_above: 1e6,
+            in_exports: false,
+        }
+    }
+}
+
+impl NumberFormat {
+    pub const MAX_DIGITS: usize = 9;
+
+    pub fn format(&self, value: f32) -> String {
+        if value == 0.0 || !value.is_finite() {
+            return value.to_string();
+        }
+        let digits = self.significant_digits.clamp(1, Self::MAX_DIGITS);
+        let magnitude = value.abs();
+        if magnitude < self.scientific_below || magnitude >= self.scientific_above {
+            return format!("{value:.*e}", digits - 1);
+        }
+        let leading = magnitude.log10().floor() as i32;
+        let decimals = (digits as i32 - 1 - leading).max(0) as usize;
+        format!("{value:.decimals$}")
+    }
+
+    /// Full precision unless the format is meant for exports as well.
+    pub fn export(&self, value: f32) -> String {
+        if self.in_exports {
+            self.format(value)
+        } else {
+            value.to_string()
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use crate::imaging::downsample_box;
+use crate::stats;
+
+/// Gradient magnitudes above this percentile saturate the map's brightness.
+const MAGNITUDE_PERCENTILE: f32 = 0.99;
+
+/// Orientation field of a slide from the responses of an x and a y
+/// derivative kernel, reduced to a color map.
+pub struct OrientationMap {
+    pub width: usize,
+    pub height: usize,
+    /// Row-major RGB: hue is the local edge orientation, saturation its
+    /// coherence and brightness the gradient magnitude.
+    pub rgb: Vec<u8>,
+    /// Coherence of the whole slide's structure tensor: 0 for isotropic
+    /// texture, 1 when every edge runs the same way.
+    pub coherence: f32,
+    /// Edge orientation of the whole slide, in degrees counterclockwise from
+    /// the horizontal within `[0, 180)`.
+    pub orientation: f32,
+    pub mean_magnitude: f32,
+}
+
+/// Builds the orientation map of `gx` and `gy`, which hold the x and y
+/// derivative responses of a `width` x `height` slide, at most `max_side`
+/// pixels on a side. Each map pixel pools the structure tensor of the slide
+/// pixels it covers, so opposite gradients of a ridge reinforce instead of
+/// cancelling.
+pub fn orientation_map(
+    gx: &[f32],
+    gy: &[f32],
+    width: usize,
+    height: usize,
+    max_side: usize,
+) -> OrientationMap {
+    // Rows run downwards; flip them so angles turn counterclockwise.
+    let jxx: Vec<f32> = gx.iter().map(|x| x * x).collect();
+    let jyy: Vec<f32> = gy.iter().map(|y| y * y).collect();
+    let jxy: Vec<f32> = gx.iter().zip(gy).map(|(x, y)| -x * y).collect();
+    let sum = |values: &[f32]| stats::compensated_sum(values.iter().map(|&v| v as f64));
+    let (orientation, coherence) = stats::tensor_orientation(sum(&jxx), sum(&jyy), sum(&jxy));
+    let mean_magnitude = (stats::compensated_sum(
+        jxx.iter()
+            .zip(&jyy)
+            .map(|(xx, yy)| ((xx + yy) as f64).sqrt()),
+    ) / jxx.len().max(1) as f64) as f32;
+
+    let factor = width.max(height).div_ceil(max_side.max(1)).max(1);
+    let (jxx, map_width, map_height) = downsample_box(&jxx, width, height, factor);
+    let (jyy, _, _) = downsample_box(&jyy, width, height, factor);
+    let (jxy, _, _) = downsample_box(&jxy, width, height, factor);
+    let magnitudes: Vec<f32> = jxx
+        .iter()
+        .zip(&jyy)
+        .map(|(xx, yy)| (xx + yy).sqrt())
+        .collect();
+    let mut sorted = magnitudes.clone();
+    sorted.sort_by(f32::total_cmp);
+    let bright = sorted
+        .get(
+            ((sorted.len() as f32 * MAGNITUDE_PERCENTILE) as usize)
+                .min(sorted.len().saturating_sub(1)),
+        )
+        .copied()
+        .unwrap_or(0.0)
+        .max(f32::MIN_POSITIVE);
+    let rgb = (0..magnitudes.len())
+        .flat_map(|i| {
+            let (degrees, coherence) =
+                stats::tensor_orientation(jxx[i] as f64, jyy[i] as f64, jxy[i] as f64);
+            // Orientations are axial, so the wheel goes round once per 180°.
+            hsv_to_rgb(2.0 * degrees, coherence, (magnitudes[i] / bright).min(1.0))
+        })
+        .collect();
+    OrientationMap {
+        width: map_width,
+        height: map_height,
+        rgb,
+        coherence,
+        orientation,
+        mean_magnitude,
+    }
+}
+
+/// `hue` in degrees; saturation and value in `[0, 1]`.
+pub fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> [u8; 3] {
+    let sector = hue.rem_euclid(360.0) / 60.0;
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (r, g, b) = match sector as usize {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = value - chroma;
+    [r, g, b].map(|c| ((c + m) * 255.0).round() as u8)
+}
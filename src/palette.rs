@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize};
+
+/// Ramp response values are drawn with: previews, tile heatmaps, response
+/// overlays and flythroughs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Colormap {
+    /// Black-red-yellow-white.
+    #[default]
+    Heat,
+    /// Perceptually uniform and readable with every common color vision
+    /// deficiency.
+    Viridis,
+    /// Viridis variant tuned to look the same to deuteranopes and to
+    /// viewers with normal color vision.
+    Cividis,
+    Magma,
+    /// Blue for negative, white at zero and red for positive values, for
+    /// signed responses.
+    Diverging,
+    Gray,
+}
+
+const VIRIDIS: [[u8; 3]; 9] = [
+    [68, 1, 84],
+    [72, 36, 117],
+    [59, 82, 139],
+    [44, 114, 142],
+    [33, 145, 140],
+    [39, 173, 129],
+    [94, 201, 98],
+    [170, 220, 50],
+    [253, 231, 37],
+];
+
+const MAGMA: [[u8; 3]; 9] = [
+    [0, 0, 4],
+    [28, 16, 68],
+    [79, 18, 123],
+    [129, 37, 129],
+    [181, 54, 122],
+    [229, 80, 100],
+    [251, 135, 97],
+    [254, 194, 135],
+    [252, 253, 191],
+];
+
+const DIVERGING: [[u8; 3]; 5] = [
+    [33, 102, 172],
+    [146, 197, 222],
+    [247, 247, 247],
+    [244, 165, 130],
+    [178, 24, 43],
+];
+
+const CIVIDIS: [[u8; 3]; 11] = [
+    [0, 34, 78],
+    [18, 53, 112],
+    [59, 73, 108],
+    [87, 93, 109],
+    [112, 113, 115],
+    [138, 134, 120],
+    [165, 156, 116],
+    [195, 179, 105],
+    [225, 204, 85],
+    [249, 225, 62],
+    [254, 232, 56],
+];
+
+impl Colormap {
+    pub const ALL: [Self; 6] = [
+        Self::Heat,
+        Self::Viridis,
+        Self::Cividis,
+        Self::Magma,
+        Self::Diverging,
+        Self::Gray,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Heat => "Heat",
+            Self::Viridis => "Viridis (color-blind safe)",
+            Self::Cividis => "Cividis (color-blind safe)",
+            Self::Magma => "Magma",
+            Self::Diverging => "Blue-red, centered on zero",
+            Self::Gray => "Gray",
+        }
+    }
+
+    /// Color at `t` in `[0, 1]`, as RGB in `[0, 1]`.
+    pub fn sample(self, t: f32) -> [f32; 3] {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::Heat => [
+                (3.0 * t).min(1.0),
+                (3.0 * t - 1.0).clamp(0.0, 1.0),
+                (3.0 * t - 2.0).clamp(0.0, 1.0),
+            ],
+            Self::Viridis => interpolate(&VIRIDIS, t),
+            Self::Cividis => interpolate(&CIVIDIS, t),
+            Self::Magma => interpolate(&MAGMA, t),
+            Self::Diverging => interpolate(&DIVERGING, t),
+            Self::Gray => [t; 3],
+        }
+    }
+
+    pub fn sample_rgb(self, t: f32) -> [u8; 3] {
+        self.sample(t).map(|c| (c * 255.0).round() as u8)
+    }
+
+    /// Position on the ramp of `value` from the interval `(min, max)`. The
+    /// diverging map keeps zero at its center, so the larger magnitude
+    /// reaches an end and the other side stops short; the others stretch
+    /// the interval over the whole ramp.
+    pub fn position(self, value: f32, (min, max): (f32, f32)) -> f32 {
+        if self == Self::Diverging {
+            let magnitude = min.abs().max(max.abs()).max(1e-12);
+            0.5 + 0.5 * value / magnitude
+        } else {
+            (value - min) / (max - min).max(1e-12)
+        }
+    }
+}
+
+/// Linear interpolation between evenly spaced `stops`.
+fn interpolate(stops: &[[u8; 3]], t: f32) -> [f32; 3] {
+    let position = t * (stops.len() - 1) as f32;
+    let low = (position as usize).min(stops.len() - 2);
+    let frac = position - low as f32;
+    let (a, b) = (stops[low], stops[low + 1]);
+    [0, 1, 2].map(|c| (a[c] as f32 + (b[c] as f32 - a[c] as f32) * frac) / 255.0)
+}
+
+/// Colors of the marks drawn over slides and kernels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OverlayColors {
+    /// Outlines of flagged tiles.
+    pub marker: [u8; 3],
+    /// Tile boundaries of the seam check.
+    pub grid: [u8; 3],
+    /// Fill of tiles that fail the seam check.
+    pub mismatch: [u8; 3],
+    /// Outline of the region of interest.
+    pub roi: [u8; 3],
+    /// Markers of detected peaks.
+    pub peak: [u8; 3],
+    /// Positive and negative weights in the weights grid, at full
+    /// magnitude; smaller weights fade towards white.
+    pub positive: [u8; 3],
+    pub negative: [u8; 3],
+}
+
+impl Default for OverlayColors {
+    fn default() -> Self {
+        Self {
+            marker: [255, 64, 64],
+            grid: [0, 200, 255],
+            mismatch: [255, 0, 0],
+            roi: [255, 220, 0],
+            peak: [0, 255, 128],
+            positive: [255, 0, 0],
+            negative: [0, 0, 255],
+        }
+    }
+}
+
+impl OverlayColors {
+    /// Okabe-Ito colors, which stay distinct for protanopes, deuteranopes
+    /// and tritanopes and do not vanish into pink H&E tissue.
+    pub fn color_blind_safe() -> Self {
+        Self {
+            marker: [230, 159, 0],
+            grid: [86, 180, 233],
+            mismatch: [213, 94, 0],
+            roi: [240, 228, 66],
+            peak: [0, 158, 115],
+            positive: [230, 159, 0],
+            negative: [0, 114, 178],
+        }
+    }
+}
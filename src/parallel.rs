@@ -0,0 +1,93 @@
+use rayon::prelude::*;
+
+use crate::backend::Backend;
+
+/// Convolutions below this many pixel-tap products stay on one thread, where
+/// splitting would cost more than it saves.
+const PARALLEL_MIN_WORK: usize = 1 << 22;
+
+/// Threads in rayon's pool: one per available core.
+pub fn threads() -> usize {
+    rayon::current_num_threads()
+}
+
+/// [`Backend::convolve_serial`] split into one band of rows per core. Each
+/// band is convolved with the rows its kernel reaches above and below, so
+/// direct backends give bit-identical maps and the FFT agrees up to
+/// rounding. Called from a parallel loop over kernels, the bands are stolen
+/// by whichever threads are idle.
+pub fn convolve_rows(
+    backend: Backend,
+    input: &[f32],
+    width: usize,
+    height: usize,
+    kernel: &[f32],
+    kw: usize,
+    kh: usize,
+) -> Vec<f32> {
+    let bands = threads().min(height);
+    if bands <= 1 || (width * height).saturating_mul(kw * kh) < PARALLEL_MIN_WORK {
+        return backend.convolve_serial(input, width, height, kernel, kw, kh);
+    }
+    let rows = height.div_ceil(bands);
+    let mut output = vec![0.0; width * height];
+    output
+        .par_chunks_mut(rows * width)
+        .enumerate()
+        .for_each(|(band, out)| {
+            let first = band * rows;
+            let end = first + out.len() / width;
+            let top = first.saturating_sub(kh / 2);
+            let bottom = (end + kh - 1 - kh / 2).min(height);
+            let response = backend.convolve_serial(
+                &input[top * width..bottom * width],
+                width,
+                bottom - top,
+                kernel,
+                kw,
+                kh,
+            );
+            out.copy_from_slice(&response[(first - top) * width..(end - top) * width]);
+        });
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::border::{self, BorderMode};
+
+    #[test]
+    fn banded_maps_match_serial_for_every_border() {
+        // Enough work to split, over a height the bands do not divide.
+        let (width, height, kw, kh) = (241, 203, 11, 9);
+        let input: Vec<f32> = (0..width * height)
+            .map(|i| ((i * 7919) % 251) as f32 / 250.0 - 0.3)
+            .collect();
+        let kernel: Vec<f32> = (0..kw * kh)
+            .map(|i| ((i * 31) % 17) as f32 / 8.0 - 1.0)
+            .collect();
+        assert!(width * height * kw * kh >= PARALLEL_MIN_WORK);
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+        for backend in [Backend::Scalar, Backend::Vectorized, Backend::Gemm] {
+            for mode in BorderMode::ALL {
+                let filter = |banded: bool| {
+                    border::filter_image(mode, &input, width, height, (kw, kh), |i, w, h| {
+                        if banded {
+                            convolve_rows(backend, i, w, h, &kernel, kw, kh)
+                        } else {
+                            backend.convolve_serial(i, w, h, &kernel, kw, kh)
+                        }
+                    })
+                };
+                let banded = pool.install(|| filter(true));
+                let serial = filter(false);
+                let bits = |map: &[f32]| map.iter().map(|v| v.to_bits()).collect::<Vec<_>>();
+                assert_eq!(bits(&banded), bits(&serial), "{backend:?}, {mode:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Shape peaks are marked with on the slide.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PeakMarker {
+    #[default]
+    Circle,
+    Cross,
+}
+
+impl PeakMarker {
+    pub const ALL: [Self; 2] = [Self::Circle, Self::Cross];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Circle => "Circles",
+            Self::Cross => "Crosses",
+        }
+    }
+}
+
+/// Local maximum of a response map.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Peak {
+    pub x: usize,
+    pub y: usize,
+    pub value: f32,
+}
+
+/// Pixels of the `width` x `height` `response` none of whose eight
+/// neighbours is higher, strongest first and in row-major order among
+/// equals, so every pixel of a plateau counts. Non-finite pixels are
+/// neither maxima nor higher than their neighbours.
+pub fn local_maxima(response: &[f32], width: usize, height: usize) -> Vec<Peak> {
+    let mut maxima: Vec<Peak> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .filter_map(|(x, y)| {
+            let value = response[y * width + x];
+            let higher = (y.saturating_sub(1)..(y + 2).min(height)).any(|ny| {
+                (x.saturating_sub(1)..(x + 2).min(width)).any(|nx| {
+                    let neighbour = response[ny * width + nx];
+                    neighbour.is_finite() && neighbour > value
+                })
+            });
+            (value.is_finite() && !higher).then_some(Peak { x, y, value })
+        })
+        .collect();
+    maxima.sort_by(|a, b| b.value.total_cmp(&a.value));
+    maxima
+}
+
+/// Non-maximum suppression: the first `count` of `maxima`, as
+/// [`local_maxima`] orders them, that reach `threshold` and lie at least
+/// `spacing` pixels in x or in y from every stronger peak taken, so plateaus
+/// and the ripples around a strong response give one peak.
+pub fn suppress(maxima: &[Peak], threshold: f32, spacing: usize, count: usize) -> Vec<Peak> {
+    let spacing = spacing.max(1);
+    // Peaks taken, by `spacing`-sided cell: only the eight cells around a
+    // candidate's can hold one too close to it.
+    let mut cells: HashMap<(usize, usize), Vec<Peak>> = HashMap::new();
+    let mut peaks = Vec::new();
+    for &peak in maxima.iter().take_while(|p| p.value >= threshold) {
+        if peaks.len() == count {
+            break;
+        }
+        let (cx, cy) = (peak.x / spacing, peak.y / spacing);
+        let near = (cy.saturating_sub(1)..=cy + 1).any(|y| {
+            (cx.saturating_sub(1)..=cx + 1).any(|x| {
+                cells.get(&(x, y)).is_some_and(|taken| {
+                    taken
+                        .iter()
+                        .any(|t| t.x.abs_diff(peak.x) < spacing && t.y.abs_diff(peak.y) < spacing)
+                })
+            })
+        });
+        if !near {
+            cells.entry((cx, cy)).or_default().push(peak);
+            peaks.push(peak);
+        }
+    }
+    peaks
+}
+
+/// Table of `peaks`, one row per peak in the order given.
+pub fn peaks_csv(peaks: &[Peak]) -> String {
+    let mut csv = String::from("rank,x,y,value\n");
+    for (rank, peak) in peaks.iter().enumerate() {
+        csv.push_str(&format!(
+            "{},{},{},{}\n",
+            rank + 1,
+            peak.x,
+            peak.y,
+            peak.value
+        ));
+    }
+    csv
+}
@@ -0,0 +1,511 @@
+use serde::{Deserialize, Serialize};
+
+use crate::app::KernelShape;
+use crate::backend::{Backend, Sampling};
+use crate::border::BorderMode;
+use crate::calibration::Calibration;
+use crate::channels::ChannelMode;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::events::Event;
+use crate::export::CsvFormat;
+use crate::imaging::Roi;
+use crate::kernel::{KernelNormalization, SheetRemainder};
+use crate::morphology::FilterMode;
+use crate::scoring::ScoreNormalization;
+use crate::stain::{StainNormalization, StainReference};
+
+/// Bumped whenever a field changes meaning, so old documents are rejected
+/// instead of silently running differently.
+pub const PIPELINE_VERSION: u32 = 1;
+
+/// Everything needed to repeat a run: inputs, kernel bank, convolution and
+/// scoring parameters, and the outputs to write. Exported from the app and
+/// executed verbatim by `--pipeline <file>`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pipeline {
+    pub version: u32,
+    pub slide: InputRef,
+    pub kernels: KernelBank,
+    pub convolution: ConvolutionParams,
+    pub scoring: ScoringParams,
+    pub outputs: Outputs,
+}
+
+/// An input file. Relative paths are resolved against the pipeline file's
+/// folder; the hash guards against running on a different file of the
+/// same name.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InputRef {
+    pub path: String,
+    /// Hex FNV-1a hash of the file contents; empty to skip the check.
+    #[serde(default)]
+    pub hash: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KernelBank {
+    pub sheet: InputRef,
+    pub shape: KernelShape,
+    /// Normalization of the tiles when the sheet is split; none for older
+    /// documents.
+    #[serde(default)]
+    pub normalization: KernelNormalization,
+    /// Kernels are the blocks between the sheet's separator lines instead
+    /// of tiles of `shape`.
+    #[serde(default)]
+    pub separators: bool,
+    /// What became of a sheet's partial tiles.
+    #[serde(default)]
+    pub remainder: SheetRemainder,
+    /// Indices of sheet tiles that are skipped.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub muted: Vec<usize>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConvolutionParams {
+    pub backend: Backend,
+    /// Forces the scalar backend, as in the app.
+    pub strict_reproducibility: bool,
+    /// Convolution unless a rank filter is given.
+    #[serde(default)]
+    pub mode: FilterMode,
+    /// Normalization of the slide to `stain_reference` before filtering.
+    #[serde(default)]
+    pub stain: StainNormalization,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stain_reference: Option<StainReference>,
+    /// Color channels convolved; luma for older documents.
+    #[serde(default)]
+    pub channels: ChannelMode,
+    /// What kernels read past the slide's edges; zero for older documents.
+    #[serde(default)]
+    pub border: BorderMode,
+    /// Slide region convolved; the whole slide when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roi: Option<Roi>,
+    /// Stride and dilation; every pixel and adjacent taps for older
+    /// documents.
+    #[serde(default)]
+    pub sampling: Sampling,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScoringParams {
+    pub normalization: ScoreNormalization,
+    pub activation_k: f32,
+    /// Weight shuffles of the permutation test; 0 skips the test.
+    pub permutations: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Outputs {
+    /// Relative to the pipeline file's folder.
+    pub dir: String,
+    pub scores_csv: bool,
+    /// Full-resolution PNG maps of the best-scoring kernels.
+    pub top_maps: usize,
+    /// File name template of those maps, with placeholders such as
+    /// `{kernel_name}`; `kernel{kernel_index}` when empty.
+    #[serde(default)]
+    pub map_names: String,
+    /// Gain, offset and fixed scale of the maps; off when absent.
+    #[serde(default)]
+    pub calibration: Calibration,
+    /// Delimiter and decimal separator of the scores CSV; commas and
+    /// points when absent.
+    #[serde(default)]
+    pub csv: CsvFormat,
+}
+
+impl Pipeline {
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|e| format!("Cannot serialize pipeline: {e}"))
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        let pipeline: Self =
+            serde_json::from_str(text).map_err(|e| format!("Invalid pipeline: {e}"))?;
+        if pipeline.version != PIPELINE_VERSION {
+            return Err(format!(
+                "Pipeline version {} is not supported (expected {PIPELINE_VERSION}).",
+                pipeline.version
+            ));
+        }
+        Ok(pipeline)
+    }
+}
+
+pub fn format_hash(hash: u64) -> String {
+    format!("{hash:016x}")
+}
+
+/// Reads an input relative to `base` and checks its hash.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_input(input: &InputRef, base: &std::path::Path) -> Result<Vec<u8>, String> {
+    let path = base.join(&input.path);
+    let bytes = std::fs::read(&path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+    let hash = format_hash(crate::stats::content_hash(&bytes));
+    if !input.hash.is_empty() && input.hash != hash {
+        return Err(format!(
+            "{} has hash {hash}, but the pipeline expects {}.",
+            path.display(),
+            input.hash
+        ));
+    }
+    Ok(bytes)
+}
+
+/// What a headless run reports back.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct RunOutcome {
+    /// One-line summary.
+    pub summary: String,
+    /// Scores table of the enabled kernels with commas and decimal points,
+    /// whether or not it was written.
+    pub scores: String,
+    /// Content hash of the slide, as recorded in the written pipeline.
+    pub slide_hash: String,
+}
+
+/// Executes `pipeline` without a window and writes its outputs, reporting
+/// progress to `events`. Failures are returned, not reported.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run(
+    pipeline: &Pipeline,
+    base: &std::path::Path,
+    events: &(dyn Fn(&Event) + Sync),
+) -> Result<RunOutcome, String> {
+    use crate::app::{BaselineSupport, baseline_support};
+    use crate::border;
+    use crate::export::{self, MapNameFields, ScoreRow};
+    use crate::kernel::Kernel;
+    use crate::numbers::NumberFormat;
+    use crate::scoring::{self, ScoreBaseline};
+    use crate::stain::normalized_input;
+    use crate::stats;
+
+    let decode = |bytes: &[u8], what: &str| {
+        image::load_from_memory(bytes).map_err(|e| format!("Failed to decode {what}: {e}"))
+    };
+    let slide_bytes = read_input(&pipeline.slide, base)?;
+    let slide_hash = format_hash(stats::content_hash(&slide_bytes));
+    let slide_image = decode(&slide_bytes, &pipeline.slide.path)?;
+    drop(slide_bytes);
+    let mut slide = slide_image.to_luma8();
+    let mut slide_rgb = slide_image
+        .color()
+        .has_color()
+        .then(|| slide_image.to_rgb8());
+    if let Some(roi) = pipeline.convolution.roi {
+        if !roi.fits((slide.width() as usize, slide.height() as usize)) {
+            return Err(format!(
+                "The {}x{} region at ({}, {}) does not fit the {}x{} slide {}.",
+                roi.width,
+                roi.height,
+                roi.x,
+                roi.y,
+                slide.width(),
+                slide.height(),
+                pipeline.slide.path
+            ));
+        }
+        slide = roi.crop(&slide);
+        slide_rgb = slide_rgb.map(|rgb| roi.crop(&rgb));
+    }
+    let sheet_ref = &pipeline.kernels.sheet;
+    let sheet_bytes = read_input(sheet_ref, base)?;
+    let sheet_hash = format_hash(stats::content_hash(&sheet_bytes));
+    let sheet_image = decode(&sheet_bytes, &sheet_ref.path)?;
+    drop(sheet_bytes);
+    let sheet = sheet_image.to_luma8();
+    let sheet_name = std::path::Path::new(&sheet_ref.path)
+        .file_name()
+        .map_or(sheet_ref.path.clone(), |n| n.to_string_lossy().into_owned());
+
+    let shape = pipeline.kernels.shape;
+    let (kw, kh) = (shape.width(), shape.height());
+    let separated = if pipeline.kernels.separators {
+        crate::kernel::split_at_separators(&sheet, &sheet_name, usize::MAX)?
+    } else {
+        None
+    };
+    let (mut kernels, _, _) = match separated {
+        Some(split) => split,
+        None => {
+            let size = (kw as u32, kh as u32);
+            crate::kernel::split_sheet(&sheet, &sheet_name, size, pipeline.kernels.remainder)?
+        }
+    };
+    let channels = pipeline.convolution.channels;
+    if channels == ChannelMode::AllChannels && sheet_image.color().has_color() {
+        crate::kernel::add_color_weights(&mut kernels, &sheet_image.to_rgb8());
+    }
+    let normalization = pipeline.kernels.normalization;
+    let unscaled = normalization.apply(&mut kernels);
+    if unscaled > 0 {
+        log::warn!(
+            "{unscaled} kernels sum to about zero and were left as they are by {}.",
+            normalization.label()
+        );
+    }
+    drop(sheet_image);
+    for &index in &pipeline.kernels.muted {
+        if let Some(kernel) = kernels.get_mut(index) {
+            kernel.enabled = false;
+        }
+    }
+    let input = match (channels, &slide_rgb) {
+        (ChannelMode::Luma, _) | (_, None) => normalized_input(
+            &slide,
+            slide_rgb.as_ref(),
+            pipeline.convolution.stain,
+            pipeline.convolution.stain_reference.as_ref(),
+        ),
+        (channels, Some(rgb)) => crate::channels::intensity(rgb, channels),
+    };
+    let planes = match &slide_rgb {
+        Some(rgb) if channels.is_multichannel() => crate::channels::planes(rgb),
+        _ => Vec::new(),
+    };
+    let (width, height) = (slide.width() as usize, slide.height() as usize);
+    let sampling = pipeline.convolution.sampling.checked()?;
+    // Maps hold the pixels the sampling keeps, and are scored against the
+    // slide's pixels there.
+    let (map_width, map_height) = sampling.output_size(width, height);
+    let map_input = sampling.subsample(&input, width, height);
+    let backend = if pipeline.convolution.strict_reproducibility {
+        Backend::Scalar
+    } else {
+        pipeline.convolution.backend
+    };
+    let slide_path = &pipeline.slide.path;
+    let total = kernels.iter().filter(|k| k.enabled).count();
+    events(&Event::Started {
+        slide: slide_path.clone(),
+        kernels: total,
+        width,
+        height,
+        backend: backend.label().to_owned(),
+    });
+    let (mode, border) = (pipeline.convolution.mode, pipeline.convolution.border);
+    // Kernels split at separators each have their own size.
+    let convolve = |weights: &[f32], kw: usize, kh: usize| {
+        let (extent, stride) = (sampling.extent(kw, kh), sampling.stride);
+        border::filter_image_strided(border, &input, width, height, extent, stride, |i, w, h| {
+            backend.convolve_sampled(i, w, h, weights, kw, kh, sampling)
+        })
+    };
+    let respond = |kernel: &Kernel| {
+        if planes.is_empty() {
+            kernel.filter_sampled(mode, backend, border, sampling, &input, width, height)
+        } else {
+            crate::channels::filter(
+                kernel, channels, mode, backend, border, sampling, &planes, width, height,
+            )
+        }
+    };
+
+    let params = &pipeline.scoring;
+    let normalization = params.normalization.for_mode(mode);
+    let image_std = stats::mean_std(&input).1 as f32;
+    // One baseline per window size and mask, as in the app.
+    let mut baselines: Vec<(BaselineSupport, ScoreBaseline)> = Vec::new();
+    if normalization == ScoreNormalization::BaselineZ {
+        for kernel in kernels.iter().filter(|k| k.enabled) {
+            let support = baseline_support(kernel);
+            if baselines.iter().any(|(s, _)| *s == support) {
+                continue;
+            }
+            let baseline = ScoreBaseline::measure(kernel.support(), |weights| {
+                let weights = kernel.scatter_support(weights);
+                scoring::raw_score(&convolve(&weights, kernel.width, kernel.height))
+            });
+            baselines.push((support, baseline));
+        }
+    }
+
+    struct Scored {
+        index: usize,
+        stats: stats::ResponseStats,
+        score: f32,
+        interval: [f32; 2],
+        significance: Option<scoring::Significance>,
+    }
+    let mut results = Vec::with_capacity(kernels.len());
+    for (index, kernel) in kernels.iter().enumerate().filter(|(_, k)| k.enabled) {
+        let response = respond(kernel);
+        let support = baseline_support(kernel);
+        let baseline = baselines
+            .iter()
+            .find(|(s, _)| *s == support)
+            .map(|(_, b)| b);
+        let stats = stats::response_stats(&response, &map_input, params.activation_k);
+        let normalize = |raw: f32| normalization.apply(raw, &kernel.weights, image_std, baseline);
+        let score = normalization.score(&stats, &kernel.weights, image_std, baseline);
+        let interval = if normalization.is_tissue() {
+            [score; 2]
+        } else {
+            let tiles = scoring::tile_sums(&response, map_width, map_height);
+            let raw = scoring::bootstrap_interval(&tiles);
+            raw.map(normalize)
+        };
+        let significance = (params.permutations > 0 && mode.is_linear()).then(|| {
+            let seed = index as u64;
+            scoring::permutation_test(kernel, mode, params.permutations, seed, |w| {
+                scoring::raw_score(&convolve(&w, kernel.width, kernel.height))
+            })
+        });
+        results.push(Scored {
+            index,
+            score,
+            interval,
+            stats,
+            significance,
+        });
+        events(&Event::Kernel {
+            slide: slide_path.clone(),
+            kernel: index,
+            done: results.len(),
+            total,
+            score,
+        });
+    }
+
+    let out_dir = base.join(&pipeline.outputs.dir);
+    let out_dir = out_dir.to_string_lossy();
+    let mut written = Vec::new();
+    let mut write = |name: &str, bytes: &[u8]| {
+        let path = export::save_file(&out_dir, name, bytes)?;
+        events(&Event::Written {
+            slide: slide_path.clone(),
+            path: path.clone(),
+        });
+        written.push(path);
+        Ok::<_, String>(())
+    };
+    let scores = export::scores_csv(
+        results.iter().map(|r| ScoreRow {
+            index: r.index,
+            kernel: &kernels[r.index],
+            score: r.score,
+            exact: true,
+            stats: &r.stats,
+            significance: r.significance,
+            interval: r.interval,
+        }),
+        NumberFormat::default(),
+    );
+    if pipeline.outputs.scores_csv {
+        let csv = pipeline.outputs.csv.apply(&scores);
+        write("scores.csv", csv.as_bytes())?;
+    }
+    let mut ranked: Vec<&Scored> = results.iter().collect();
+    ranked.sort_by(|a, b| b.score.total_cmp(&a.score));
+    let best = &ranked[..ranked.len().min(pipeline.outputs.top_maps)];
+    let template = match pipeline.outputs.map_names.as_str() {
+        "" => "kernel{kernel_index}",
+        template => template,
+    };
+    let names = export::map_file_stems(
+        template,
+        best.iter().map(|r| MapNameFields {
+            slide: slide_path,
+            index: r.index,
+            kernel: &kernels[r.index],
+            score: r.score,
+        }),
+    )?;
+    for (&&Scored { index, .. }, name) in best.iter().zip(names) {
+        let png = pipeline.outputs.calibration.map_png(
+            &respond(&kernels[index]),
+            map_width,
+            map_height,
+        )?;
+        write(&format!("{name}.png"), &png)?;
+    }
+    // The written pipeline pins the inputs it ran on, so a rerun fails
+    // rather than scoring different images.
+    let mut recorded = pipeline.clone();
+    recorded.slide.hash = slide_hash.clone();
+    recorded.kernels.sheet.hash = sheet_hash.clone();
+    write("pipeline.json", recorded.to_json()?.as_bytes())?;
+
+    let top = ranked.first().map_or(String::new(), |r| {
+        format!(" Top kernel: {} (score {:.5}).", r.index, r.score)
+    });
+    let same = if slide_hash == sheet_hash {
+        " Warning: the slide and the kernels sheet are the same image."
+    } else {
+        ""
+    };
+    let summary = format!(
+        "Scored {} kernels with the {} backend; wrote {} file(s) to {out_dir}.{top}{same}",
+        results.len(),
+        backend.label(),
+        written.len()
+    );
+    events(&Event::Finished {
+        slide: slide_path.clone(),
+        kernels: results.len(),
+        top_kernel: ranked.first().map(|r| r.index),
+        top_score: ranked.first().map(|r| r.score),
+        summary: summary.clone(),
+    });
+    Ok(RunOutcome {
+        summary,
+        scores,
+        slide_hash,
+    })
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use image::GrayImage;
+
+    use super::*;
+
+    /// Runs a z-scored pipeline with permutation tests on a small slide and
+    /// a sheet of two 2x2 kernels between separator lines, with `shape` as
+    /// the bank's tile size.
+    fn run_separated(shape: &str) -> RunOutcome {
+        let dir = std::env::temp_dir().join(format!(
+            "convolution_pipeline_{shape}_{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        GrayImage::from_fn(24, 18, |x, y| {
+            image::Luma([((x * 37 + y * 91) % 251) as u8])
+        })
+        .save(dir.join("slide.png"))
+        .unwrap();
+        // Column 2 is the blank separator between the two kernels.
+        let sheet = [[0, 255, 128, 40, 200], [255, 0, 128, 220, 10]];
+        GrayImage::from_fn(5, 2, |x, y| image::Luma([sheet[y as usize][x as usize]]))
+            .save(dir.join("sheet.png"))
+            .unwrap();
+        let pipeline = Pipeline::from_json(&format!(
+            r#"{{
+                "version": {PIPELINE_VERSION},
+                "slide": {{ "path": "slide.png" }},
+                "kernels": {{ "sheet": {{ "path": "sheet.png" }}, "shape": "{shape}", "separators": true }},
+                "convolution": {{ "backend": "Scalar", "strict_reproducibility": true }},
+                "scoring": {{ "normalization": "BaselineZ", "activation_k": 2.0, "permutations": 19 }},
+                "outputs": {{ "dir": "out", "scores_csv": false, "top_maps": 0 }}
+            }}"#
+        ))
+        .unwrap();
+        let outcome = run(&pipeline, &dir, &|_| {});
+        let _ = std::fs::remove_dir_all(&dir);
+        outcome.unwrap()
+    }
+
+    #[test]
+    fn separated_kernels_run_at_their_own_size() {
+        let declared = run_separated("5x5");
+        let matching = run_separated("2x2");
+        assert_eq!(declared.scores.lines().count(), 3);
+        assert_eq!(declared.scores, matching.scores);
+    }
+}
@@ -0,0 +1,198 @@
+use std::f32::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+use crate::kernel::{self, Kernel, Provenance};
+
+/// Gaussian envelopes are cut at this many standard deviations.
+const SIGMA_REACH: f32 = 3.0;
+/// Gabor envelope width per wavelength, about one octave of bandwidth.
+const GABOR_SIGMA_PER_WAVELENGTH: f32 = 0.56;
+/// Gabor envelope length across the stripes relative to along them.
+const GABOR_ASPECT: f32 = 0.5;
+
+/// Classical filters the kernel presets add, editable in the side panel.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PresetOptions {
+    /// Sobel X and Y gradients.
+    pub sobel: bool,
+    /// A Gaussian blur at each of `sigmas`.
+    pub gaussian: bool,
+    /// A Laplacian of Gaussian at each of `sigmas`.
+    pub laplacian_of_gaussian: bool,
+    /// Scales of the Gaussian and LoG kernels, in pixels, as typed.
+    pub sigmas: String,
+    /// Gabor filters at `gabor_orientations` angles for each wavelength.
+    pub gabor: bool,
+    pub gabor_orientations: usize,
+    /// Stripe periods of the Gabor filters, in pixels, as typed.
+    pub gabor_wavelengths: String,
+}
+
+impl Default for PresetOptions {
+    fn default() -> Self {
+        Self {
+            sobel: true,
+            gaussian: false,
+            laplacian_of_gaussian: true,
+            sigmas: "1, 2, 4".to_owned(),
+            gabor: true,
+            gabor_orientations: 4,
+            gabor_wavelengths: "4, 8".to_owned(),
+        }
+    }
+}
+
+impl PresetOptions {
+    /// The selected filters, named, in a fixed order. Fails on unparsable or
+    /// non-positive scales, and on kernels wider than `max_side`.
+    pub fn kernels(&self, max_side: usize) -> Result<Vec<Kernel>, String> {
+        let scales = |text: &str, what: &str| -> Result<Vec<f32>, String> {
+            let values = kernel::parse_taps(text).map_err(|e| format!("{what}: {e}"))?;
+            match values.iter().find(|&&v| v.is_nan() || v <= 0.0) {
+                Some(v) => Err(format!("{what}: {v} is not a positive number")),
+                None => Ok(values),
+            }
+        };
+        let mut kernels = Vec::new();
+        if self.sobel {
+            kernels.push(named(
+                Kernel::separable(
+                    vec![-1.0, 0.0, 1.0],
+                    vec![1.0, 2.0, 1.0],
+                    Provenance::Preset,
+                ),
+                "Sobel X".to_owned(),
+            ));
+            kernels.push(named(
+                Kernel::separable(
+                    vec![1.0, 2.0, 1.0],
+                    vec![-1.0, 0.0, 1.0],
+                    Provenance::Preset,
+                ),
+                "Sobel Y".to_owned(),
+            ));
+        }
+        if self.gaussian || self.laplacian_of_gaussian {
+            let sigmas = scales(&self.sigmas, "Sigmas")?;
+            for &sigma in &sigmas {
+                check_side(odd_side(SIGMA_REACH * sigma), max_side)?;
+            }
+            if self.gaussian {
+                kernels.extend(
+                    sigmas
+                        .iter()
+                        .map(|&s| named(gaussian(s), format!("Gaussian sigma {s}"))),
+                );
+            }
+            if self.laplacian_of_gaussian {
+                kernels.extend(
+                    sigmas
+                        .iter()
+                        .map(|&s| named(laplacian_of_gaussian(s), format!("LoG sigma {s}"))),
+                );
+            }
+        }
+        if self.gabor && self.gabor_orientations > 0 {
+            for wavelength in scales(&self.gabor_wavelengths, "Wavelengths")? {
+                check_side(gabor_side(wavelength), max_side)?;
+                for step in 0..self.gabor_orientations {
+                    let degrees = 180.0 * step as f32 / self.gabor_orientations as f32;
+                    kernels.push(named(
+                        gabor(degrees.to_radians(), wavelength),
+                        format!("Gabor {degrees:.0} deg wavelength {wavelength}"),
+                    ));
+                }
+            }
+        }
+        Ok(kernels)
+    }
+}
+
+fn named(mut kernel: Kernel, name: String) -> Kernel {
+    kernel.name = name;
+    kernel
+}
+
+/// Smallest odd side reaching `reach` pixels on both sides of the centre.
+fn odd_side(reach: f32) -> usize {
+    2 * reach.ceil().max(1.0) as usize + 1
+}
+
+fn check_side(side: usize, max_side: usize) -> Result<(), String> {
+    if side > max_side {
+        return Err(format!(
+            "A {side}x{side} kernel exceeds the {max_side}-pixel limit; use a smaller scale"
+        ));
+    }
+    Ok(())
+}
+
+/// Unit-sum Gaussian blur, separable.
+fn gaussian(sigma: f32) -> Kernel {
+    let side = odd_side(SIGMA_REACH * sigma);
+    let centre = (side / 2) as f32;
+    let mut taps: Vec<f32> = (0..side)
+        .map(|i| (-(i as f32 - centre).powi(2) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let sum: f32 = taps.iter().sum();
+    taps.iter_mut().for_each(|t| *t /= sum);
+    Kernel::separable(taps.clone(), taps, Provenance::Preset)
+}
+
+/// Scale-normalized Laplacian of Gaussian (sigma^2 times the Laplacian), so
+/// blobs of about `sigma * sqrt(2)` radius respond equally at every scale;
+/// dark blobs such as nuclei on a bright slide respond positively. It is
+/// shifted to zero mean, so flat regions give no response.
+fn laplacian_of_gaussian(sigma: f32) -> Kernel {
+    let side = odd_side(SIGMA_REACH * sigma);
+    let weights = centred_window(side, |x, y| {
+        let r2 = (x * x + y * y) / (2.0 * sigma * sigma);
+        -(1.0 - r2) * (-r2).exp() / (PI * sigma * sigma)
+    });
+    Kernel::new(zero_mean(weights), (side, side), Provenance::Preset)
+}
+
+fn gabor_side(wavelength: f32) -> usize {
+    let sigma = GABOR_SIGMA_PER_WAVELENGTH * wavelength;
+    // The envelope is longest along the stripes.
+    odd_side(SIGMA_REACH * sigma / GABOR_ASPECT.min(1.0))
+}
+
+/// Even (cosine) Gabor filter whose stripes run at `angle` radians from the
+/// vertical, zero-mean and of unit L2 norm.
+fn gabor(angle: f32, wavelength: f32) -> Kernel {
+    let sigma = GABOR_SIGMA_PER_WAVELENGTH * wavelength;
+    let side = gabor_side(wavelength);
+    let (sin, cos) = angle.sin_cos();
+    let weights = zero_mean(centred_window(side, |x, y| {
+        let (across, along) = (x * cos + y * sin, -x * sin + y * cos);
+        let envelope =
+            (-(across * across + (GABOR_ASPECT * along).powi(2)) / (2.0 * sigma * sigma)).exp();
+        envelope * (2.0 * PI * across / wavelength).cos()
+    }));
+    let norm = weights
+        .iter()
+        .map(|w| w * w)
+        .sum::<f32>()
+        .sqrt()
+        .max(f32::EPSILON);
+    let weights = weights.iter().map(|w| w / norm).collect();
+    Kernel::new(weights, (side, side), Provenance::Preset)
+}
+
+/// Row-major `side` x `side` window of `f` at offsets from its centre.
+fn centred_window(side: usize, f: impl Fn(f32, f32) -> f32) -> Vec<f32> {
+    let centre = (side / 2) as f32;
+    (0..side)
+        .flat_map(|y| (0..side).map(move |x| (x as f32 - centre, y as f32 - centre)))
+        .map(|(x, y)| f(x, y))
+        .collect()
+}
+
+fn zero_mean(mut weights: Vec<f32>) -> Vec<f32> {
+    let mean = weights.iter().sum::<f32>() / weights.len() as f32;
+    weights.iter_mut().for_each(|w| *w -= mean);
+    weights
+}
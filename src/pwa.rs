@@ -0,0 +1,64 @@
+use std::sync::mpsc::{self, Receiver};
+
+use js_sys::{Array, Function, Promise, Reflect};
+use wasm_bindgen::JsCast;
+use wasm_bindgen::prelude::{Closure, JsValue};
+use wasm_bindgen_futures::JsFuture;
+
+use crate::file_dialog::{self, PickedFile};
+
+/// Files the operating system opened with the installed app, read into
+/// memory as they arrive.
+pub type Launches = Receiver<Result<PickedFile, String>>;
+
+/// Listens for the files the operating system's file manager opens with the
+/// installed app, through the manifest's file handlers. Browsers without
+/// `launchQueue`, and pages opened in a tab, never send any.
+pub fn launched_files(ctx: egui::Context) -> Launches {
+    let (sender, receiver) = mpsc::channel();
+    let queue = web_sys::window()
+        .and_then(|w| Reflect::get(&w, &"launchQueue".into()).ok())
+        .filter(|q| !q.is_undefined() && !q.is_null());
+    let Some(queue) = queue else {
+        return receiver;
+    };
+    let consumer = Closure::<dyn FnMut(JsValue)>::new(move |params: JsValue| {
+        let files = Reflect::get(&params, &"files".into())
+            .map(|files| Array::from(&files))
+            .unwrap_or_else(|_| Array::new());
+        for handle in files.iter() {
+            let (sender, ctx) = (sender.clone(), ctx.clone());
+            wasm_bindgen_futures::spawn_local(async move {
+                let _ = sender.send(open(handle).await);
+                ctx.request_repaint();
+            });
+        }
+    });
+    let registered = Reflect::get(&queue, &"setConsumer".into())
+        .and_then(|f| f.dyn_into::<Function>())
+        .and_then(|set| set.call1(&queue, consumer.as_ref()));
+    if let Err(e) = registered {
+        log::warn!("Cannot receive launched files: {e:?}");
+    }
+    // The queue holds on to the consumer for the life of the page.
+    consumer.forget();
+    receiver
+}
+
+/// Contents of the file behind a launched file handle.
+async fn open(handle: JsValue) -> Result<PickedFile, String> {
+    let promise = Reflect::get(&handle, &"getFile".into())
+        .and_then(|f| f.dyn_into::<Function>())
+        .and_then(|get_file| get_file.call0(&handle))
+        .and_then(|p| p.dyn_into::<Promise>())
+        .map_err(|e| format!("Cannot open launched file: {e:?}"))?;
+    let file = JsFuture::from(promise)
+        .await
+        .and_then(|f| f.dyn_into::<web_sys::File>())
+        .map_err(|e| format!("Cannot open launched file: {e:?}"))?;
+    let bytes = file_dialog::read(&file).await?;
+    Ok(PickedFile {
+        name: file.name(),
+        bytes,
+    })
+}
@@ -0,0 +1,64 @@
+use crate::backend::Backend;
+use crate::border::{self, BorderMode};
+use crate::kernel::Kernel;
+use crate::morphology::FilterMode;
+use crate::scoring::SplitMix64;
+
+/// Edge length of the patches a quick score is computed on.
+pub const PATCH_SIZE: usize = 64;
+/// Seed of the patch positions, fixed so quick scores are reproducible.
+const PATCH_SEED: u64 = 0x0C1C_5C0E_2B7A_4E19;
+
+/// Top-left corners of `count` seeded random patches of [`PATCH_SIZE`]
+/// (the whole image when it is smaller).
+pub fn patch_corners(width: usize, height: usize, count: usize) -> Vec<(usize, usize)> {
+    let mut rng = SplitMix64(PATCH_SEED);
+    let (span_x, span_y) = (
+        width.saturating_sub(PATCH_SIZE) + 1,
+        height.saturating_sub(PATCH_SIZE) + 1,
+    );
+    (0..count)
+        .map(|_| {
+            let x = (rng.next_f32() * span_x as f32) as usize;
+            let y = (rng.next_f32() * span_y as f32) as usize;
+            (x.min(span_x - 1), y.min(span_y - 1))
+        })
+        .collect()
+}
+
+/// Mean |r| of `kernel` over the patches at `corners`, an estimate of the
+/// full-slide raw score. Each patch is filtered together with the pixels
+/// its kernel reaches around it, so patch pixels get their exact response.
+#[allow(clippy::too_many_arguments)]
+pub fn quick_mean_abs(
+    kernel: &Kernel,
+    mode: FilterMode,
+    backend: Backend,
+    border: BorderMode,
+    input: &[f32],
+    width: usize,
+    height: usize,
+    corners: &[(usize, usize)],
+) -> f32 {
+    let (mut sum, mut pixels) = (0.0f64, 0usize);
+    for &(x, y) in corners {
+        let (pw, ph) = (PATCH_SIZE.min(width - x), PATCH_SIZE.min(height - y));
+        let response = border::filter_region(
+            border,
+            input,
+            width,
+            height,
+            x..x + pw,
+            y..y + ph,
+            (kernel.width, kernel.height),
+            |crop, cw, ch| kernel.filter(mode, backend, BorderMode::Zero, crop, cw, ch),
+        );
+        sum += response.iter().map(|v| v.abs() as f64).sum::<f64>();
+        pixels += pw * ph;
+    }
+    if pixels > 0 {
+        (sum / pixels as f64) as f32
+    } else {
+        0.0
+    }
+}
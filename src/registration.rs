@@ -0,0 +1,255 @@
+use rustfft::FftPlanner;
+use rustfft::num_complex::Complex;
+
+use crate::imaging::downsample_box;
+use crate::stats::compensated_sum;
+
+/// Images are downsampled so their larger side is at most this before
+/// phase correlation.
+const REGISTRATION_SIZE: usize = 256;
+/// Rotations tried, in degrees, around the identity.
+const ROTATION_RANGE_DEG: f32 = 10.0;
+const ROTATION_STEP_DEG: f32 = 1.0;
+
+/// Rigid transform mapping fixed-image coordinates `p` to moving-image
+/// coordinates `R(angle) * (p + shift - center) + center`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RigidTransform {
+    pub angle_deg: f32,
+    pub shift: [f32; 2],
+    pub center: [f32; 2],
+    /// Height of the normalized phase-correlation peak; close to 1 for a
+    /// clean match.
+    pub confidence: f32,
+}
+
+impl RigidTransform {
+    pub fn apply(&self, x: f32, y: f32) -> (f32, f32) {
+        let (sin, cos) = self.angle_deg.to_radians().sin_cos();
+        let px = x + self.shift[0] - self.center[0];
+        let py = y + self.shift[1] - self.center[1];
+        (
+            cos * px - sin * py + self.center[0],
+            sin * px + cos * py + self.center[1],
+        )
+    }
+}
+
+/// Estimates the rigid transform aligning `moving` onto `fixed`: phase
+/// correlation for the translation, repeated over a small range of
+/// rotations keeping the sharpest peak.
+pub fn register(
+    fixed: &[f32],
+    fixed_w: usize,
+    fixed_h: usize,
+    moving: &[f32],
+    moving_w: usize,
+    moving_h: usize,
+) -> RigidTransform {
+    let factor = fixed_w
+        .max(fixed_h)
+        .max(moving_w)
+        .max(moving_h)
+        .div_ceil(REGISTRATION_SIZE)
+        .max(1);
+    let (a, aw, ah) = downsample_box(fixed, fixed_w, fixed_h, factor);
+    let (b, bw, bh) = downsample_box(moving, moving_w, moving_h, factor);
+    let w = aw.max(bw);
+    let h = ah.max(bh);
+    let a = prepare(&a, aw, ah, w, h);
+    let b = pad(&b, bw, bh, w, h);
+    let center = [w as f32 / 2.0, h as f32 / 2.0];
+
+    let mut planner = FftPlanner::new();
+    let fa = fft2(&mut planner, a, w, h, false);
+
+    let steps = (ROTATION_RANGE_DEG / ROTATION_STEP_DEG).round() as i32;
+    let mut best = RigidTransform::default();
+    let mut best_peak = f32::NEG_INFINITY;
+    for step in -steps..=steps {
+        let angle_deg = step as f32 * ROTATION_STEP_DEG;
+        let rotated = rotate(&b, w, h, angle_deg, center);
+        let fb = fft2(&mut planner, prepare(&rotated, w, h, w, h), w, h, false);
+        let (dx, dy, peak) = correlation_peak(&mut planner, &fa, &fb, w, h);
+        if peak > best_peak {
+            best_peak = peak;
+            best = RigidTransform {
+                angle_deg,
+                shift: [dx as f32, dy as f32],
+                center,
+                confidence: peak,
+            };
+        }
+    }
+
+    let scale = factor as f32;
+    RigidTransform {
+        shift: [best.shift[0] * scale, best.shift[1] * scale],
+        center: [best.center[0] * scale, best.center[1] * scale],
+        ..best
+    }
+}
+
+/// Resamples `moving` into the fixed image's pixel grid (nearest neighbor),
+/// zero outside the moving image.
+pub fn warp_into(
+    moving: &[f32],
+    moving_w: usize,
+    moving_h: usize,
+    transform: &RigidTransform,
+    fixed_w: usize,
+    fixed_h: usize,
+) -> Vec<f32> {
+    let mut out = vec![0.0; fixed_w * fixed_h];
+    for y in 0..fixed_h {
+        for x in 0..fixed_w {
+            let (mx, my) = transform.apply(x as f32, y as f32);
+            let (mx, my) = (mx.round(), my.round());
+            if mx >= 0.0 && my >= 0.0 && (mx as usize) < moving_w && (my as usize) < moving_h {
+                out[y * fixed_w + x] = moving[my as usize * moving_w + mx as usize];
+            }
+        }
+    }
+    out
+}
+
+/// Location of the normalized cross-power spectrum peak, as a signed shift
+/// `d` such that `b(x) ~ a(x - d)`, together with its height.
+fn correlation_peak(
+    planner: &mut FftPlanner<f32>,
+    fa: &[Complex<f32>],
+    fb: &[Complex<f32>],
+    w: usize,
+    h: usize,
+) -> (isize, isize, f32) {
+    let cross: Vec<Complex<f32>> = fa
+        .iter()
+        .zip(fb)
+        .map(|(a, b)| {
+            let c = b * a.conj();
+            let norm = c.norm();
+            if norm > 1e-12 {
+                c / norm
+            } else {
+                Complex::default()
+            }
+        })
+        .collect();
+    let surface = fft2(planner, cross, w, h, true);
+    let n = (w * h) as f32;
+    let (index, peak) = surface
+        .iter()
+        .enumerate()
+        .map(|(i, c)| (i, c.re / n))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap_or((0, 0.0));
+    let wrap = |v: usize, size: usize| {
+        if v > size / 2 {
+            v as isize - size as isize
+        } else {
+            v as isize
+        }
+    };
+    (wrap(index % w, w), wrap(index / w, h), peak)
+}
+
+/// Row-then-column 2-D FFT of a `w` x `h` row-major buffer.
+pub fn fft2(
+    planner: &mut FftPlanner<f32>,
+    mut data: Vec<Complex<f32>>,
+    w: usize,
+    h: usize,
+    inverse: bool,
+) -> Vec<Complex<f32>> {
+    let (row_fft, col_fft) = if inverse {
+        (planner.plan_fft_inverse(w), planner.plan_fft_inverse(h))
+    } else {
+        (planner.plan_fft_forward(w), planner.plan_fft_forward(h))
+    };
+    row_fft.process(&mut data);
+    let mut column = vec![Complex::default(); h];
+    for x in 0..w {
+        for y in 0..h {
+            column[y] = data[y * w + x];
+        }
+        col_fft.process(&mut column);
+        for y in 0..h {
+            data[y * w + x] = column[y];
+        }
+    }
+    data
+}
+
+/// Mean-removes and Hann-windows an image padded to `w` x `h`, reducing the
+/// edge discontinuities that would otherwise dominate the correlation.
+fn prepare(src: &[f32], sw: usize, sh: usize, w: usize, h: usize) -> Vec<Complex<f32>> {
+    let mean = (compensated_sum(src.iter().map(|&v| v as f64)) / src.len().max(1) as f64) as f32;
+    let hann = |i: usize, n: usize| {
+        if n <= 1 {
+            1.0
+        } else {
+            0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / (n - 1) as f32).cos()
+        }
+    };
+    let mut out = vec![Complex::default(); w * h];
+    for y in 0..sh {
+        for x in 0..sw {
+            let v = (src[y * sw + x] - mean) * hann(x, sw) * hann(y, sh);
+            out[y * w + x] = Complex::new(v, 0.0);
+        }
+    }
+    out
+}
+
+fn pad(src: &[f32], sw: usize, sh: usize, w: usize, h: usize) -> Vec<f32> {
+    let mut out = vec![0.0; w * h];
+    for y in 0..sh {
+        out[y * w..y * w + sw].copy_from_slice(&src[y * sw..(y + 1) * sw]);
+    }
+    out
+}
+
+/// Samples `src` at `R(angle) * (p - center) + center` for every pixel `p`.
+fn rotate(src: &[f32], w: usize, h: usize, angle_deg: f32, center: [f32; 2]) -> Vec<f32> {
+    let transform = RigidTransform {
+        angle_deg,
+        shift: [0.0, 0.0],
+        center,
+        confidence: 0.0,
+    };
+    warp_into(src, w, h, &transform, w, h)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Texture without repeats, defined off the image too.
+    fn texture(x: i64, y: i64) -> f32 {
+        let h = (x.wrapping_mul(73_856_093) ^ y.wrapping_mul(19_349_663)).rem_euclid(1000);
+        h as f32 / 1000.0
+    }
+
+    #[test]
+    fn translation_is_recovered() {
+        let (w, h) = (120, 90);
+        let (dx, dy) = (7, -4);
+        let image = |offset: (i64, i64)| -> Vec<f32> {
+            (0..w * h)
+                .map(|i| texture((i % w) as i64 - offset.0, (i / w) as i64 - offset.1))
+                .collect()
+        };
+        let (fixed, moving) = (image((0, 0)), image((dx, dy)));
+        let transform = register(&fixed, w, h, &moving, w, h);
+        assert_eq!(transform.angle_deg, 0.0);
+        assert_eq!(transform.shift, [dx as f32, dy as f32]);
+        assert!(transform.confidence > 0.5, "{}", transform.confidence);
+
+        let warped = warp_into(&moving, w, h, &transform, w, h);
+        for y in 10..h - 10 {
+            for x in 10..w - 10 {
+                assert_eq!(warped[y * w + x], fixed[y * w + x], "({x}, {y})");
+            }
+        }
+    }
+}
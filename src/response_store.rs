@@ -0,0 +1,116 @@
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use memmap2::Mmap;
+
+/// Distinguishes the stores of one process.
+static NEXT_STORE: AtomicUsize = AtomicUsize::new(0);
+
+/// Full-resolution response maps of a run, kept in memory-mapped temporary
+/// files. The pages are backed by the files rather than by RAM or swap, so
+/// the OS pages maps in while they are read and drops them again under
+/// memory pressure; hundreds of maps of a large slide do not have to fit in
+/// memory. The folder is deleted with the store.
+pub struct ResponseStore {
+    dir: PathBuf,
+    maps: Vec<Option<Mmap>>,
+    len: usize,
+}
+
+impl ResponseStore {
+    /// Room for `kernels` maps of `len` values each; more are added as
+    /// kernels join the bank.
+    pub fn new(kernels: usize, len: usize) -> Result<Self, String> {
+        let dir = std::env::temp_dir().join(format!(
+            "convolution-{}-{}",
+            std::process::id(),
+            NEXT_STORE.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| format!("Cannot create {}: {e}", dir.display()))?;
+        Ok(Self {
+            dir,
+            maps: (0..kernels).map(|_| None).collect(),
+            len,
+        })
+    }
+
+    pub fn insert(&mut self, index: usize, response: &[f32]) -> Result<(), String> {
+        if response.len() != self.len {
+            return Err("Response does not fit the store".to_owned());
+        }
+        if index >= self.maps.len() {
+            self.maps.resize_with(index + 1, || None);
+        }
+        let path = self.dir.join(format!("kernel{index}.f32"));
+        let error = |e: std::io::Error| format!("Cannot write {}: {e}", path.display());
+        // Mapping needs read access as well.
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .map_err(error)?;
+        let mut writer = BufWriter::new(file);
+        for v in response {
+            writer.write_all(&v.to_le_bytes()).map_err(error)?;
+        }
+        let file = writer.into_inner().map_err(|e| error(e.into_error()))?;
+        // SAFETY: the file lives in this store's private folder and is never
+        // written again while mapped.
+        let map = unsafe { Mmap::map(&file) }.map_err(error)?;
+        self.maps[index] = Some(map);
+        Ok(())
+    }
+
+    /// Forgets a map that no longer matches its kernel.
+    pub fn remove(&mut self, index: usize) {
+        if let Some(map) = self.maps.get_mut(index) {
+            *map = None;
+        }
+    }
+
+    /// Copy of a stored map, or `None` if it was never stored.
+    pub fn get(&self, index: usize) -> Option<Vec<f32>> {
+        let map = self.maps.get(index)?.as_ref()?;
+        Some(
+            map.chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+        )
+    }
+}
+
+impl Drop for ResponseStore {
+    fn drop(&mut self) {
+        self.maps.clear();
+        if let Err(e) = std::fs::remove_dir_all(&self.dir) {
+            log::warn!("Cannot remove {}: {e}", self.dir.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_read_back_until_removed() {
+        let mut store = ResponseStore::new(2, 3).unwrap();
+        let dir = store.dir.clone();
+        store.insert(0, &[1.0, -0.5, f32::MIN_POSITIVE]).unwrap();
+        // Kernels joining the bank get room as they are stored.
+        store.insert(4, &[2.0, 3.0, 4.0]).unwrap();
+        assert!(store.insert(1, &[1.0]).is_err());
+        assert_eq!(store.get(0).unwrap(), [1.0, -0.5, f32::MIN_POSITIVE]);
+        assert_eq!(store.get(4).unwrap(), [2.0, 3.0, 4.0]);
+        assert_eq!(store.get(1), None);
+        store.remove(0);
+        assert_eq!(store.get(0), None);
+        drop(store);
+        assert!(!dir.exists());
+    }
+}
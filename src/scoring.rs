@@ -0,0 +1,463 @@
+use serde::{Deserialize, Serialize};
+
+use crate::kernel::Kernel;
+use crate::morphology::FilterMode;
+use crate::stats::{CompensatedSum, ResponseStats, compensated_sum, mean_std};
+
+/// Random kernels convolved to estimate the baseline score distribution.
+pub const BASELINE_KERNELS: usize = 16;
+/// Seed of the baseline kernels, fixed so baselines are reproducible.
+const BASELINE_SEED: u64 = 0x5EED_0FBA_5E11_E5ED;
+/// Tiles per side of the grid resampled for score confidence intervals.
+pub const BOOTSTRAP_TILES: usize = 8;
+const BOOTSTRAP_RESAMPLES: usize = 500;
+const BOOTSTRAP_SEED: u64 = 0xB007_5724_9C1E_0A3D;
+/// Seed of the permutation tests, combined with a per-kernel seed.
+const PERMUTATION_SEED: u64 = 0x9E2B_11C4_70A3_D2F1;
+
+/// How the raw mean-abs response is turned into the score used for
+/// ranking. Raw scores grow with the kernel's weight magnitude, so kernels
+/// are only comparable once normalized.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScoreNormalization {
+    #[default]
+    Raw,
+    KernelL1,
+    KernelL2,
+    ImageStd,
+    /// Z-score of the L2-normalized score against random unit-norm kernels
+    /// of the same size.
+    BaselineZ,
+    /// Mean |r| on tissue minus mean |r| on the background glass: whether a
+    /// kernel tells tissue structure from empty glass.
+    TissueContrast,
+    /// Mean |r| on tissue over mean |r| on the background glass.
+    TissueRatio,
+}
+
+impl ScoreNormalization {
+    pub const ALL: [Self; 7] = [
+        Self::Raw,
+        Self::KernelL1,
+        Self::KernelL2,
+        Self::ImageStd,
+        Self::BaselineZ,
+        Self::TissueContrast,
+        Self::TissueRatio,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Raw => "Raw mean |r|",
+            Self::KernelL1 => "/ kernel L1 norm",
+            Self::KernelL2 => "/ kernel L2 norm",
+            Self::ImageStd => "/ image std",
+            Self::BaselineZ => "z vs random kernels",
+            Self::TissueContrast => "Tissue - glass mean |r|",
+            Self::TissueRatio => "Tissue / glass mean |r|",
+        }
+    }
+
+    /// Whether the score compares tissue and background rather than
+    /// normalizing the mean |r|, so it has no bootstrap interval.
+    pub fn is_tissue(self) -> bool {
+        matches!(self, Self::TissueContrast | Self::TissueRatio)
+    }
+
+    /// Normalization of responses computed in `mode`. Rank filters ignore
+    /// the weights, so the weight-based normalizations give the raw score.
+    pub fn for_mode(self, mode: FilterMode) -> Self {
+        match self {
+            Self::KernelL1 | Self::KernelL2 | Self::BaselineZ if !mode.is_linear() => Self::Raw,
+            other => other,
+        }
+    }
+
+    /// Score of a response with `stats`: the tissue scores from its tissue
+    /// and background means, the others by normalizing its mean |r|.
+    pub fn score(
+        self,
+        stats: &ResponseStats,
+        weights: &[f32],
+        image_std: f32,
+        baseline: Option<&ScoreBaseline>,
+    ) -> f32 {
+        let (tissue, background) = (stats.tissue_mean_abs, stats.background_mean_abs);
+        match self {
+            Self::TissueContrast => tissue - background,
+            Self::TissueRatio if background > 1e-12 => tissue / background,
+            Self::TissueRatio => 0.0,
+            _ => self.apply(stats.mean_abs, weights, image_std, baseline),
+        }
+    }
+
+    /// Normalizes `raw`; `baseline` is required for `BaselineZ` and the raw
+    /// score is returned unchanged without it, as it is for the tissue
+    /// scores, which need the whole map (see [`ScoreNormalization::score`]).
+    pub fn apply(
+        self,
+        raw: f32,
+        weights: &[f32],
+        image_std: f32,
+        baseline: Option<&ScoreBaseline>,
+    ) -> f32 {
+        let divide = |norm: f32| if norm > 1e-12 { raw / norm } else { 0.0 };
+        match self {
+            Self::Raw => raw,
+            Self::KernelL1 => divide(l1_norm(weights)),
+            Self::KernelL2 => divide(l2_norm(weights)),
+            Self::ImageStd => divide(image_std),
+            Self::BaselineZ => match baseline {
+                Some(b) => b.z_score(divide(l2_norm(weights))),
+                None => raw,
+            },
+            Self::TissueContrast | Self::TissueRatio => raw,
+        }
+    }
+}
+
+/// Distribution of L2-normalized scores of random kernels on one image.
+#[derive(Clone, Copy, Debug)]
+pub struct ScoreBaseline {
+    pub mean: f32,
+    pub std_dev: f32,
+}
+
+impl ScoreBaseline {
+    /// Scores [`BASELINE_KERNELS`] random kernels with `len` weights using
+    /// `score`, which convolves the image and returns the raw score.
+    pub fn measure(len: usize, mut score: impl FnMut(&[f32]) -> f32) -> Self {
+        let scores: Vec<f32> = baseline_kernels(len).iter().map(|k| score(k)).collect();
+        Self::from_scores(&scores)
+    }
+
+    pub fn from_scores(scores: &[f32]) -> Self {
+        let (mean, std_dev) = mean_std(scores);
+        Self {
+            mean: mean as f32,
+            std_dev: std_dev as f32,
+        }
+    }
+
+    pub fn z_score(&self, score: f32) -> f32 {
+        if self.std_dev > 1e-12 {
+            (score - self.mean) / self.std_dev
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Zero-mean, unit-L2-norm random kernels with `len` weights.
+fn baseline_kernels(len: usize) -> Vec<Vec<f32>> {
+    let mut rng = SplitMix64(BASELINE_SEED);
+    (0..BASELINE_KERNELS)
+        .map(|_| {
+            let mut weights: Vec<f32> = (0..len).map(|_| rng.next_f32() * 2.0 - 1.0).collect();
+            let mean = weights.iter().sum::<f32>() / len.max(1) as f32;
+            weights.iter_mut().for_each(|w| *w -= mean);
+            let norm = l2_norm(&weights).max(1e-12);
+            weights.iter_mut().for_each(|w| *w /= norm);
+            weights
+        })
+        .collect()
+}
+
+/// Mean absolute response, the unnormalized score.
+pub fn raw_score(response: &[f32]) -> f32 {
+    (compensated_sum(response.iter().map(|&v| v.abs() as f64)) / response.len().max(1) as f64)
+        as f32
+}
+
+pub fn l1_norm(weights: &[f32]) -> f32 {
+    weights.iter().map(|w| w.abs()).sum()
+}
+
+pub fn l2_norm(weights: &[f32]) -> f32 {
+    weights.iter().map(|w| w * w).sum::<f32>().sqrt()
+}
+
+/// Small deterministic generator; results must not depend on the platform.
+pub struct SplitMix64(pub u64);
+
+impl SplitMix64 {
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+/// Where a kernel's score falls among the scores of its own weights
+/// shuffled over the kernel window.
+#[derive(Clone, Copy, Debug)]
+pub struct Significance {
+    pub permutations: usize,
+    /// Observed raw score, at full resolution.
+    pub score: f32,
+    /// One-sided: `(1 + #{permuted >= observed}) / (1 + permutations)`.
+    pub p_value: f32,
+    pub z_score: f32,
+}
+
+/// Permutation test of `kernel`'s raw score in `mode`. Shuffling keeps the
+/// weight distribution, and thereby its norms, while destroying the spatial
+/// structure, so a low p-value means the arrangement of the weights matters.
+/// `score` convolves the image with a full window of the kernel's size.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn permutation_test(
+    kernel: &Kernel,
+    mode: FilterMode,
+    permutations: usize,
+    seed: u64,
+    score: impl FnMut(Vec<f32>) -> f32,
+) -> Significance {
+    let scores: Vec<f32> = permutation_windows(kernel, mode, permutations, seed)
+        .map(score)
+        .collect();
+    Significance::from_null(scores[0], &scores[1..])
+}
+
+/// The full windows a permutation test of `kernel` scores, in order: the
+/// kernel as `mode` weights it, then `permutations` shuffles of its in-mask
+/// taps within the mask. `seed` makes the shuffles reproducible per kernel.
+pub fn permutation_windows(
+    kernel: &Kernel,
+    mode: FilterMode,
+    permutations: usize,
+    seed: u64,
+) -> impl Iterator<Item = Vec<f32>> + use<> {
+    let kernel = kernel.oriented(mode).into_owned();
+    let support = kernel.support_weights();
+    let shuffles = shuffles(&support, permutations, seed);
+    std::iter::once(support)
+        .chain(shuffles)
+        .map(move |weights| kernel.scatter_support(&weights))
+}
+
+fn shuffles(
+    weights: &[f32],
+    permutations: usize,
+    seed: u64,
+) -> impl Iterator<Item = Vec<f32>> + use<> {
+    let mut rng = SplitMix64(PERMUTATION_SEED ^ seed);
+    let mut shuffled = weights.to_vec();
+    (0..permutations).map(move |_| {
+        shuffle(&mut shuffled, &mut rng);
+        shuffled.clone()
+    })
+}
+
+impl Significance {
+    /// Where `observed` falls among the scores of the shuffles.
+    pub fn from_null(observed: f32, null: &[f32]) -> Self {
+        let exceeding = null.iter().filter(|&&s| s >= observed).count();
+        Self {
+            permutations: null.len(),
+            score: observed,
+            p_value: (1 + exceeding) as f32 / (1 + null.len()) as f32,
+            z_score: ScoreBaseline::from_scores(null).z_score(observed),
+        }
+    }
+}
+
+/// Fisher-Yates shuffle.
+fn shuffle(values: &mut [f32], rng: &mut SplitMix64) {
+    for i in (1..values.len()).rev() {
+        let j = (rng.next_u64() % (i as u64 + 1)) as usize;
+        values.swap(i, j);
+    }
+}
+
+/// Sum and count of absolute responses over each cell of a
+/// [`BOOTSTRAP_TILES`] x [`BOOTSTRAP_TILES`] grid laid over a response map.
+pub fn tile_sums(response: &[f32], width: usize, height: usize) -> Vec<(f64, usize)> {
+    let mut tiles = vec![(0.0, 0); BOOTSTRAP_TILES * BOOTSTRAP_TILES];
+    for y in 0..height {
+        let ty = y * BOOTSTRAP_TILES / height.max(1);
+        for x in 0..width {
+            let tile = &mut tiles[ty * BOOTSTRAP_TILES + x * BOOTSTRAP_TILES / width.max(1)];
+            tile.0 += response[y * width + x].abs() as f64;
+            tile.1 += 1;
+        }
+    }
+    tiles
+}
+
+/// Mean |r| over each `tile` x `tile` block of a response map, row-major,
+/// with partial blocks along the right and bottom edges. Returns the grid's
+/// columns and rows with the means.
+pub fn block_mean_abs(
+    response: &[f32],
+    width: usize,
+    height: usize,
+    tile: usize,
+) -> (usize, usize, Vec<f32>) {
+    let tile = tile.max(1);
+    let (cols, rows) = (width.div_ceil(tile), height.div_ceil(tile));
+    let mut sums = vec![(0.0f64, 0usize); cols * rows];
+    for y in 0..height {
+        let row = &mut sums[y / tile * cols..(y / tile + 1) * cols];
+        for (x, v) in response[y * width..(y + 1) * width].iter().enumerate() {
+            let block = &mut row[x / tile];
+            block.0 += v.abs() as f64;
+            block.1 += 1;
+        }
+    }
+    let means = sums
+        .iter()
+        .map(|&(sum, count)| (sum / count.max(1) as f64) as f32)
+        .collect();
+    (cols, rows, means)
+}
+
+/// 95% percentile interval of the raw score, resampling the tiles of
+/// [`tile_sums`] with replacement. Spatially clustered responses vary a lot
+/// between tiles and get wide intervals. Every kernel draws the same
+/// resamples, so intervals of different kernels are directly comparable.
+pub fn bootstrap_interval(tiles: &[(f64, usize)]) -> [f32; 2] {
+    if tiles.is_empty() {
+        return [0.0, 0.0];
+    }
+    let mut rng = SplitMix64(BOOTSTRAP_SEED);
+    let mut scores: Vec<f32> = (0..BOOTSTRAP_RESAMPLES)
+        .map(|_| {
+            let mut sum = CompensatedSum::default();
+            let mut count = 0;
+            for _ in 0..tiles.len() {
+                let (s, c) = tiles[(rng.next_u64() % tiles.len() as u64) as usize];
+                sum.add(s);
+                count += c;
+            }
+            (sum.value() / count.max(1) as f64) as f32
+        })
+        .collect();
+    scores.sort_by(f32::total_cmp);
+    let at = |q: f32| scores[(q * (scores.len() - 1) as f32).round() as usize];
+    [at(0.025), at(0.975)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::Provenance;
+
+    /// A 3x3 kernel whose mask leaves out the corners.
+    fn masked_kernel() -> Kernel {
+        let mut kernel = Kernel::new(
+            (1..=9).map(|w| w as f32).collect(),
+            (3, 3),
+            Provenance::Entered,
+        );
+        kernel.mask = Some((0..9).map(|i| i % 2 == 1 || i == 4).collect());
+        kernel
+    }
+
+    #[test]
+    fn shuffles_stay_inside_the_mask() {
+        let kernel = masked_kernel();
+        let windows: Vec<Vec<f32>> =
+            permutation_windows(&kernel, FilterMode::Correlation, 20, 7).collect();
+        assert_eq!(windows.len(), 21);
+        assert_eq!(windows[0], kernel.masked_weights().to_vec());
+        let mut support = kernel.support_weights();
+        support.sort_by(f32::total_cmp);
+        for window in &windows {
+            for corner in [0, 2, 6, 8] {
+                assert_eq!(window[corner], 0.0);
+            }
+            let mut kept: Vec<f32> = [1, 3, 4, 5, 7].iter().map(|&i| window[i]).collect();
+            kept.sort_by(f32::total_cmp);
+            assert_eq!(kept, support);
+        }
+        assert!(windows[1..].iter().any(|w| *w != windows[0]));
+    }
+
+    #[test]
+    fn headless_and_app_tests_share_the_null() {
+        let kernel = masked_kernel();
+        // Any score that depends on where the weights sit.
+        let score = |window: &[f32]| window.iter().enumerate().map(|(i, w)| i as f32 * w).sum();
+        for mode in [FilterMode::Correlation, FilterMode::Convolution] {
+            let headless = permutation_test(&kernel, mode, 30, 3, |w| score(&w));
+            // The app scores the same windows on its run's thread.
+            let scores: Vec<f32> = permutation_windows(&kernel, mode, 30, 3)
+                .map(|w| score(&w))
+                .collect();
+            let app = Significance::from_null(scores[0], &scores[1..]);
+            assert_eq!(headless.score, app.score);
+            assert_eq!(headless.p_value, app.p_value);
+            assert_eq!(headless.z_score, app.z_score);
+        }
+    }
+
+    #[test]
+    fn normalized_scores_ignore_the_weight_scale() {
+        let weights = [3.0, -4.0, 0.0, 1.0];
+        // Mean |r| grows with the weights' gain.
+        let raw = |gain: f32| 0.8 * gain;
+        let baseline = ScoreBaseline::from_scores(&[0.1, 0.2, 0.3]);
+        for normalization in [
+            ScoreNormalization::KernelL1,
+            ScoreNormalization::KernelL2,
+            ScoreNormalization::BaselineZ,
+        ] {
+            let score = |gain: f32| {
+                let scaled: Vec<f32> = weights.iter().map(|w| w * gain).collect();
+                normalization.apply(raw(gain), &scaled, 1.0, Some(&baseline))
+            };
+            assert!((score(1.0) - score(25.0)).abs() < 1e-5, "{normalization:?}");
+        }
+        assert_eq!(
+            ScoreNormalization::KernelL1.apply(0.8, &weights, 1.0, None),
+            0.1
+        );
+        assert_eq!(
+            ScoreNormalization::ImageStd.apply(0.8, &weights, 0.5, None),
+            1.6
+        );
+        assert_eq!(
+            ScoreNormalization::KernelL2.apply(0.8, &[0.0; 4], 1.0, None),
+            0.0
+        );
+    }
+
+    #[test]
+    fn tiles_count_every_pixel_once() {
+        // A size the grid does not divide.
+        let (width, height) = (21, 13);
+        let response: Vec<f32> = (0..width * height).map(|i| i as f32 - 100.0).collect();
+        let tiles = tile_sums(&response, width, height);
+        assert_eq!(tiles.len(), BOOTSTRAP_TILES * BOOTSTRAP_TILES);
+        assert_eq!(tiles.iter().map(|t| t.1).sum::<usize>(), width * height);
+        let total: f64 = tiles.iter().map(|t| t.0).sum();
+        let expected: f64 = response.iter().map(|v| v.abs() as f64).sum();
+        assert_eq!(total, expected);
+    }
+
+    #[test]
+    fn clustered_responses_get_wider_intervals() {
+        let size = 8 * BOOTSTRAP_TILES;
+        let uniform = vec![0.5; size * size];
+        assert_eq!(
+            bootstrap_interval(&tile_sums(&uniform, size, size)),
+            [0.5, 0.5]
+        );
+        // The same mean |r|, all of it on the top-left tile.
+        let mut clustered = vec![0.0; size * size];
+        for y in 0..8 {
+            clustered[y * size..y * size + 8].fill(32.0);
+        }
+        let [low, high] = bootstrap_interval(&tile_sums(&clustered, size, size));
+        assert!(low < 0.5 && high > 0.5, "{low}..{high}");
+        assert!(high - low > 1.0, "{low}..{high}");
+        assert_eq!(bootstrap_interval(&[]), [0.0, 0.0]);
+    }
+}
@@ -0,0 +1,70 @@
+use crate::border::{self, BorderMode};
+
+/// Comparison of a map computed tile by tile with the same map computed in
+/// one piece.
+pub struct SeamReport {
+    /// Tile side in pixels; edge tiles are cut at the image.
+    pub tile: usize,
+    pub cols: usize,
+    pub rows: usize,
+    /// Largest |tiled - whole| on each tile, row-major.
+    pub discrepancies: Vec<f32>,
+}
+
+impl SeamReport {
+    pub fn max(&self) -> f32 {
+        self.discrepancies.iter().fold(0.0, |m, &d| m.max(d))
+    }
+
+    /// Tiles whose pixels do not all match the untiled map exactly.
+    pub fn mismatched(&self) -> usize {
+        self.discrepancies.iter().filter(|&&d| d != 0.0).count()
+    }
+}
+
+/// Recomputes `whole`, the untiled response of a `kernel_size` filter over
+/// the `width` x `height` `input`, on a grid of `tile`-pixel tiles, each
+/// with the halo its kernel reaches read through `border` as the tiled
+/// paths do, and measures where the two disagree. `filter` is the zero-padded
+/// filter [`border::filter_region`] expects.
+pub fn check_seams(
+    border: BorderMode,
+    input: &[f32],
+    (width, height): (usize, usize),
+    kernel_size: (usize, usize),
+    tile: usize,
+    whole: &[f32],
+    filter: impl Fn(&[f32], usize, usize) -> Vec<f32>,
+) -> SeamReport {
+    let tile = tile.max(1);
+    let (cols, rows) = (width.div_ceil(tile), height.div_ceil(tile));
+    let mut discrepancies = Vec::with_capacity(cols * rows);
+    for row in 0..rows {
+        for col in 0..cols {
+            let xs = col * tile..((col + 1) * tile).min(width);
+            let ys = row * tile..((row + 1) * tile).min(height);
+            let tiled = border::filter_region(
+                border,
+                input,
+                width,
+                height,
+                xs.clone(),
+                ys.clone(),
+                kernel_size,
+                &filter,
+            );
+            let worst = ys
+                .flat_map(|y| xs.clone().map(move |x| y * width + x))
+                .zip(&tiled)
+                .map(|(i, &t)| (t - whole[i]).abs())
+                .fold(0.0f32, f32::max);
+            discrepancies.push(worst);
+        }
+    }
+    SeamReport {
+        tile,
+        cols,
+        rows,
+        discrepancies,
+    }
+}
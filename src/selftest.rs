@@ -0,0 +1,357 @@
+use crate::backend::Backend;
+use crate::border::BorderMode;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::gpu::GpuConvolver;
+use crate::kernel::{Kernel, Provenance};
+use crate::morphology::FilterMode;
+use crate::stats;
+
+pub const WIDTH: usize = 16;
+pub const HEIGHT: usize = 12;
+
+/// Largest accepted discrepancy, relative to the expected magnitude
+/// (absolute below 1). Fixture pixels and weights are short binary
+/// fractions, so direct paths land on the expected values exactly and only
+/// the FFT rounds.
+const TOLERANCE: f64 = 1e-4;
+
+/// Expected outputs of a fixture, worked out independently with exact
+/// rational arithmetic: the sum and sum of squares of the response, then
+/// its top-left, bottom-right and (7, 5) pixels.
+type Signature = [f64; 5];
+
+struct Fixture {
+    name: &'static str,
+    mode: FilterMode,
+    border: BorderMode,
+    kernel: fn() -> Kernel,
+    expected: Signature,
+}
+
+const FIXTURES: [Fixture; 8] = [
+    Fixture {
+        name: "Identity 3x3",
+        mode: FilterMode::Correlation,
+        border: BorderMode::Zero,
+        kernel: || weights(&[0, 0, 0, 0, 8, 0, 0, 0, 0], (3, 3)),
+        expected: [96.125, 66.125, 0.0, 0.625, 0.75],
+    },
+    Fixture {
+        name: "Sobel x",
+        mode: FilterMode::Correlation,
+        border: BorderMode::Zero,
+        kernel: sobel_x,
+        expected: [0.125, 201.375, 1.0625, -0.8125, 0.3125],
+    },
+    Fixture {
+        name: "Sobel x, reflected border",
+        mode: FilterMode::Correlation,
+        border: BorderMode::Reflect,
+        kernel: sobel_x,
+        expected: [1.0, 162.15625, 0.6875, 1.75, 0.3125],
+    },
+    Fixture {
+        // Sobel x flipped in both axes is its negation.
+        name: "Sobel x, convolution",
+        mode: FilterMode::Convolution,
+        border: BorderMode::Zero,
+        kernel: sobel_x,
+        expected: [-0.125, 201.375, -1.0625, 0.8125, -0.3125],
+    },
+    Fixture {
+        // Rational up to the final square root, taken to 50 digits.
+        name: "Sobel x, normalized cross-correlation",
+        mode: FilterMode::NormalizedCorrelation,
+        border: BorderMode::Zero,
+        kernel: sobel_x,
+        expected: [
+            -1.096935827925653,
+            18.09130144357872,
+            0.3783699307442565,
+            -0.2498768169425091,
+            0.09568319307746789,
+        ],
+    },
+    Fixture {
+        name: "Separable box 4x4",
+        mode: FilterMode::Correlation,
+        border: BorderMode::Zero,
+        kernel: || {
+            let mut kernel = Kernel::new(vec![1.0 / 16.0; 16], (4, 4), Provenance::Preset);
+            kernel.separable = Some((vec![0.25; 4], vec![0.25; 4]));
+            kernel
+        },
+        expected: [
+            82.41015625,
+            37.33558654785156,
+            0.08984375,
+            0.24609375,
+            0.5234375,
+        ],
+    },
+    Fixture {
+        name: "Ramp 5x2",
+        mode: FilterMode::Correlation,
+        border: BorderMode::Zero,
+        kernel: || weights(&[1, 2, 3, 4, 5, -5, -4, -3, -2, -1], (5, 2)),
+        expected: [-14.421875, 26.28759765625, -0.21875, -0.3984375, 0.1015625],
+    },
+    Fixture {
+        name: "Median 3x3",
+        mode: FilterMode::Median,
+        border: BorderMode::Zero,
+        kernel: || weights(&[1; 9], (3, 3)),
+        expected: [96.09375, 48.9248046875, 0.3125, 0.53125, 0.5],
+    },
+];
+
+/// Mean and standard deviation of the fixture image.
+const IMAGE_STATS: [f64; 2] = [0.5006510416666666, 0.30618552569438723];
+
+/// Kernel of `eighths` / 8.
+fn weights(eighths: &[i8], size: (usize, usize)) -> Kernel {
+    let weights = eighths.iter().map(|&w| w as f32 / 8.0).collect();
+    Kernel::new(weights, size, Provenance::Preset)
+}
+
+fn sobel_x() -> Kernel {
+    weights(&[-8, 0, 8, -16, 0, 16, -8, 0, 8], (3, 3))
+}
+
+/// Sixteenths arranged so neighbouring pixels differ, `WIDTH` x `HEIGHT`.
+pub fn fixture_image() -> Vec<f32> {
+    (0..WIDTH * HEIGHT)
+        .map(|i| ((i % WIDTH * 7 + i / WIDTH * 13) % 17) as f32 / 16.0)
+        .collect()
+}
+
+fn signature(response: &[f32]) -> Signature {
+    let sum = stats::compensated_sum(response.iter().map(|&v| v as f64));
+    let sum_sq = stats::compensated_sum(response.iter().map(|&v| v as f64 * v as f64));
+    let at = |x: usize, y: usize| response[y * WIDTH + x] as f64;
+    [sum, sum_sq, at(0, 0), at(WIDTH - 1, HEIGHT - 1), at(7, 5)]
+}
+
+/// Worst discrepancy of `actual` from `expected`, relative to magnitudes
+/// above 1.
+fn discrepancy(actual: &[f64], expected: &[f64]) -> f64 {
+    actual
+        .iter()
+        .zip(expected)
+        .map(|(a, e)| (a - e).abs() / e.abs().max(1.0))
+        .map(|d| if d.is_nan() { f64::INFINITY } else { d })
+        .fold(0.0, f64::max)
+}
+
+/// What a check ran on.
+#[derive(Clone, PartialEq)]
+pub enum Path {
+    Cpu(Backend),
+    /// A GPU path, by its short label.
+    Gpu(&'static str),
+    /// Checks that do not go through a backend.
+    Statistics,
+}
+
+impl Path {
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Cpu(backend) => backend.label(),
+            Self::Gpu(label) => label,
+            Self::Statistics => "Statistics",
+        }
+    }
+}
+
+/// One fixture on one backend.
+pub struct Check {
+    pub name: String,
+    pub path: Path,
+    pub discrepancy: f64,
+}
+
+impl Check {
+    pub fn passed(&self) -> bool {
+        self.discrepancy <= TOLERANCE
+    }
+}
+
+pub struct SelfTestReport {
+    pub checks: Vec<Check>,
+    /// Why no GPU path was checked.
+    pub gpu_skipped: Option<String>,
+}
+
+impl SelfTestReport {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(Check::passed)
+    }
+
+    /// One line per backend with its pass count, or why the GPU was
+    /// skipped, then one per failure.
+    pub fn summary(&self) -> String {
+        let mut lines = Vec::new();
+        let mut groups: Vec<Path> = Backend::ALL.map(Path::Cpu).into();
+        for check in &self.checks {
+            if matches!(check.path, Path::Gpu(_)) && !groups.contains(&check.path) {
+                groups.push(check.path.clone());
+            }
+        }
+        groups.push(Path::Statistics);
+        for path in groups {
+            let checks: Vec<&Check> = self.checks.iter().filter(|c| c.path == path).collect();
+            let passed = checks.iter().filter(|c| c.passed()).count();
+            let label = path.label();
+            let verdict = if passed == checks.len() {
+                "PASS"
+            } else {
+                "FAIL"
+            };
+            lines.push(format!(
+                "{verdict} {label}: {passed}/{} fixtures",
+                checks.len()
+            ));
+        }
+        if let Some(reason) = &self.gpu_skipped {
+            lines.push(format!("SKIP GPU: {reason}"));
+        }
+        for check in self.checks.iter().filter(|c| !c.passed()) {
+            let error = if check.discrepancy.is_finite() {
+                format!("off by {:.3e}", check.discrepancy)
+            } else {
+                "no usable output".to_owned()
+            };
+            lines.push(format!(
+                "  {} ({}): {error}",
+                check.name,
+                check.path.label()
+            ));
+        }
+        lines.join("\n")
+    }
+
+    /// Checks `maps`, the maps of the GPU path `label` of [`gpu_kernels`]
+    /// over [`fixture_image`] in order, or the error that stopped it.
+    pub fn add_gpu(&mut self, label: &'static str, maps: Result<Vec<Vec<f32>>, String>) {
+        let maps = match maps {
+            Ok(maps) => maps,
+            Err(e) => {
+                self.checks.push(Check {
+                    name: format!("GPU run ({e})"),
+                    path: Path::Gpu(label),
+                    discrepancy: f64::INFINITY,
+                });
+                return;
+            }
+        };
+        let mut maps = maps.iter();
+        for fixture in FIXTURES.iter().filter(|f| runs_on_gpu(f)) {
+            self.checks.push(Check {
+                name: fixture.name.to_owned(),
+                path: Path::Gpu(label),
+                discrepancy: maps.next().map_or(f64::INFINITY, |map| check(map, fixture)),
+            });
+        }
+    }
+}
+
+/// [`run`] and then the native GPU when one is usable, waiting for it to
+/// start.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run_with_gpu() -> SelfTestReport {
+    let mut report = run();
+    match pollster::block_on(GpuConvolver::new()) {
+        Ok(gpu) => {
+            let image = fixture_image();
+            let maps = gpu_kernels()
+                .iter()
+                .map(|(weights, (kw, kh))| gpu.convolve(&image, WIDTH, HEIGHT, weights, *kw, *kh))
+                .collect();
+            report.add_gpu("GPU", maps);
+        }
+        Err(e) => report.gpu_skipped = Some(e),
+    }
+    report
+}
+
+/// Whether the GPU paths take `fixture`: they compute the weighted sums
+/// with the zero border.
+fn runs_on_gpu(fixture: &Fixture) -> bool {
+    fixture.mode.is_linear() && fixture.border == BorderMode::Zero
+}
+
+/// Oriented weights and shape of the fixtures the GPU paths take, which
+/// [`SelfTestReport::add_gpu`] expects the maps of in this order.
+pub fn gpu_kernels() -> Vec<(Vec<f32>, (usize, usize))> {
+    FIXTURES
+        .iter()
+        .filter(|f| runs_on_gpu(f))
+        .map(|fixture| {
+            let kernel = (fixture.kernel)();
+            let oriented = kernel.oriented(fixture.mode);
+            (
+                oriented.masked_weights().into_owned(),
+                (kernel.width, kernel.height),
+            )
+        })
+        .collect()
+}
+
+/// Discrepancy of `response` from `fixture`'s expected signature.
+fn check(response: &[f32], fixture: &Fixture) -> f64 {
+    if response.len() == WIDTH * HEIGHT {
+        discrepancy(&signature(response), &fixture.expected)
+    } else {
+        f64::INFINITY
+    }
+}
+
+/// Runs every fixture on every CPU backend and checks the statistics of the
+/// fixture image. GPU paths are added with [`SelfTestReport::add_gpu`].
+pub fn run() -> SelfTestReport {
+    let image = fixture_image();
+    let mut checks = Vec::new();
+    for backend in Backend::ALL {
+        for fixture in &FIXTURES {
+            let kernel = (fixture.kernel)();
+            let response =
+                kernel.filter(fixture.mode, backend, fixture.border, &image, WIDTH, HEIGHT);
+            checks.push(Check {
+                name: fixture.name.to_owned(),
+                path: Path::Cpu(backend),
+                discrepancy: check(&response, fixture),
+            });
+        }
+    }
+    let (mean, std) = stats::mean_std(&image);
+    checks.push(Check {
+        name: "Mean and standard deviation".to_owned(),
+        path: Path::Statistics,
+        discrepancy: discrepancy(&[mean, std], &IMAGE_STATS),
+    });
+    SelfTestReport {
+        checks,
+        gpu_skipped: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpu_backends_pass_the_fixtures() {
+        let report = run();
+        assert!(report.passed(), "{}", report.summary());
+    }
+
+    /// Without a usable GPU only the CPU is checked.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn gpu_passes_the_fixtures_when_present() {
+        let report = run_with_gpu();
+        assert!(report.passed(), "{}", report.summary());
+        let checked = report.checks.iter().any(|c| c.path == Path::Gpu("GPU"));
+        assert_ne!(checked, report.gpu_skipped.is_some());
+    }
+}
@@ -0,0 +1,150 @@
+use std::io::Cursor;
+
+use image::{DynamicImage, GrayImage, ImageFormat, RgbImage};
+use serde::{Deserialize, Serialize};
+
+use crate::kernel::Kernel;
+use crate::pipeline::Pipeline;
+use crate::share;
+
+pub const SESSION_VERSION: u32 = 1;
+/// Extension of session files.
+pub const SESSION_EXTENSION: &str = ".convsession";
+
+/// An analysis saved to be resumed later or elsewhere: the images
+/// themselves rather than their paths, the kernel bank as it was edited,
+/// the parameters of a pipeline and, optionally, the full-resolution maps
+/// of the last run.
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    pub version: u32,
+    /// Parameters of the analysis; its inputs name the images below.
+    pub pipeline: Pipeline,
+    pub slide: SessionImage,
+    pub kernels_sheet: SessionImage,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub second_slide: Option<SessionImage>,
+    pub kernels: Vec<Kernel>,
+    /// Rows and columns of the sheet's tiles; 0 for kernel files.
+    #[serde(default)]
+    pub grid: (usize, usize),
+    #[serde(default)]
+    pub selected: usize,
+    /// Map of each kernel, in bank order, when the session keeps the
+    /// results.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub maps: Vec<String>,
+}
+
+/// A loaded image under the name, path and content hash it was loaded with,
+/// so pipelines exported after resuming still name the original file.
+#[derive(Serialize, Deserialize)]
+pub struct SessionImage {
+    pub name: String,
+    pub source: String,
+    pub hash: u64,
+    /// The pixels as a PNG, in unpadded base64url; empty for kernel files,
+    /// whose kernels the session holds.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub png: String,
+}
+
+impl SessionImage {
+    /// `rgb`, or `gray` for gray images, encoded into the session.
+    pub fn new(
+        name: &str,
+        source: &str,
+        hash: u64,
+        gray: &GrayImage,
+        rgb: Option<&RgbImage>,
+    ) -> Result<Self, String> {
+        let image = match rgb {
+            Some(rgb) => DynamicImage::ImageRgb8(rgb.clone()),
+            None => DynamicImage::ImageLuma8(gray.clone()),
+        };
+        let mut png = Cursor::new(Vec::new());
+        image
+            .write_to(&mut png, ImageFormat::Png)
+            .map_err(|e| format!("PNG encoding of {name} failed: {e}"))?;
+        Ok(Self {
+            name: name.to_owned(),
+            source: source.to_owned(),
+            hash,
+            png: share::encode(&png.into_inner()),
+        })
+    }
+
+    /// A kernel file's name and hash, without pixels.
+    pub fn named(name: &str, source: &str, hash: u64) -> Self {
+        Self {
+            name: name.to_owned(),
+            source: source.to_owned(),
+            hash,
+            png: String::new(),
+        }
+    }
+
+    /// The PNG bytes, `None` when the session holds no pixels.
+    pub fn png(&self) -> Result<Option<Vec<u8>>, String> {
+        if self.png.is_empty() {
+            return Ok(None);
+        }
+        share::decode(&self.png)
+            .map(Some)
+            .ok_or_else(|| format!("The session's copy of {} is damaged.", self.name))
+    }
+}
+
+impl Session {
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string(self).map_err(|e| format!("Cannot serialize session: {e}"))
+    }
+
+    pub fn from_json(bytes: &[u8]) -> Result<Self, String> {
+        let session: Self =
+            serde_json::from_slice(bytes).map_err(|e| format!("Invalid session: {e}"))?;
+        if session.version != SESSION_VERSION {
+            return Err(format!(
+                "Session version {} is not supported (expected {SESSION_VERSION}).",
+                session.version
+            ));
+        }
+        if session.kernels.is_empty() {
+            return Err("The session holds no kernels.".to_owned());
+        }
+        if !session.maps.is_empty() && session.maps.len() != session.kernels.len() {
+            return Err(format!(
+                "The session holds {} maps for {} kernels.",
+                session.maps.len(),
+                session.kernels.len()
+            ));
+        }
+        Ok(session)
+    }
+}
+
+/// `response` for a session: its little-endian floats deflated, in
+/// unpadded base64url.
+pub fn encode_map(response: &[f32]) -> String {
+    let bytes: Vec<u8> = response.iter().flat_map(|v| v.to_le_bytes()).collect();
+    share::encode(&miniz_oxide::deflate::compress_to_vec(&bytes, 6))
+}
+
+/// Map `index` of a session, which must hold `len` values.
+pub fn decode_map(text: &str, index: usize, len: usize) -> Result<Vec<f32>, String> {
+    let damaged = || format!("The session's map of kernel {index} is damaged.");
+    let deflated = share::decode(text).ok_or_else(damaged)?;
+    // A map inflating past its size is not inflated further.
+    let bytes = miniz_oxide::inflate::decompress_to_vec_with_limit(&deflated, len * 4)
+        .map_err(|_| damaged())?;
+    if bytes.len() != len * 4 {
+        return Err(format!(
+            "The session's map of kernel {index} has {} values; its slide gives {len}.",
+            bytes.len() / 4
+        ));
+    }
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect())
+}
@@ -0,0 +1,175 @@
+use serde::{Deserialize, Serialize};
+
+use crate::pipeline::{PIPELINE_VERSION, Pipeline};
+
+/// Starts the fragment of share links: `#share=<payload>`.
+const FRAGMENT_KEY: &str = "share=";
+/// Longest link made, well within what browsers, mail and chat clients keep
+/// intact.
+const MAX_LINK_LEN: usize = 8000;
+/// Payloads that inflate past this are rejected rather than decompressed.
+const MAX_PAYLOAD_LEN: usize = 1 << 20;
+/// Best kernels whose scores a link carries.
+pub const MAX_SHARED_KERNELS: usize = 25;
+
+const BASE64URL: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// A run's setup and its best kernels' scores, carried in the fragment of a
+/// link. The images stay with whoever made it: the link only names and
+/// hashes them, and the recipient loads their own copies.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SharedRun {
+    pub pipeline: Pipeline,
+    /// Kernels in the bank the scores come from.
+    pub kernels: usize,
+    /// Best-scoring enabled kernels, best first.
+    pub scores: Vec<SharedScore>,
+    /// Kernel selected when the link was made.
+    pub selected: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SharedScore {
+    pub index: usize,
+    pub name: String,
+    pub score: f32,
+}
+
+impl SharedRun {
+    /// `base`, the app's address, with the run in its fragment as deflated
+    /// JSON in unpadded base64url, which no part of a URL escapes.
+    pub fn link(&self, base: &str) -> Result<String, String> {
+        let json = serde_json::to_vec(self).map_err(|e| format!("Cannot serialize run: {e}"))?;
+        let deflated = miniz_oxide::deflate::compress_to_vec(&json, 9);
+        let base = base.split('#').next().unwrap_or(base);
+        let link = format!("{base}#{FRAGMENT_KEY}{}", encode(&deflated));
+        if link.len() > MAX_LINK_LEN {
+            return Err(format!(
+                "The share link would be {} characters long, more than links reliably carry; \
+                 export the pipeline instead.",
+                link.len()
+            ));
+        }
+        Ok(link)
+    }
+
+    /// Run in the fragment of `link`, a whole link or only its fragment.
+    /// `Ok(None)` when it has no share fragment.
+    pub fn from_link(link: &str) -> Result<Option<Self>, String> {
+        let fragment = link.trim().rsplit('#').next().unwrap_or_default();
+        let Some(payload) = fragment.strip_prefix(FRAGMENT_KEY) else {
+            return Ok(None);
+        };
+        let invalid = |e: String| format!("Invalid share link: {e}");
+        let deflated = decode(payload).ok_or_else(|| invalid("not base64url".to_owned()))?;
+        let json = miniz_oxide::inflate::decompress_to_vec_with_limit(&deflated, MAX_PAYLOAD_LEN)
+            .map_err(|e| invalid(format!("cannot inflate it ({:?})", e.status)))?;
+        let run: Self = serde_json::from_slice(&json).map_err(|e| invalid(e.to_string()))?;
+        if run.pipeline.version != PIPELINE_VERSION {
+            return Err(format!(
+                "Share link version {} is not supported (expected {PIPELINE_VERSION}).",
+                run.pipeline.version
+            ));
+        }
+        Ok(Some(run))
+    }
+}
+
+/// `bytes` in unpadded base64url.
+pub fn encode(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |bits, (i, &b)| bits | (b as u32) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            text.push(BASE64URL[(bits >> (18 - 6 * i) & 63) as usize] as char);
+        }
+    }
+    text
+}
+
+/// Bytes of unpadded base64url `text`; `None` when it is not.
+pub fn decode(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    for chunk in text.as_bytes().chunks(4) {
+        if chunk.len() == 1 {
+            return None;
+        }
+        let mut bits = 0u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            let value = BASE64URL.iter().position(|&b| b == c)? as u32;
+            bits |= value << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            bytes.push((bits >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shared_run() -> SharedRun {
+        let pipeline = Pipeline::from_json(&format!(
+            r#"{{
+                "version": {PIPELINE_VERSION},
+                "slide": {{ "path": "slide.png", "hash": "00ff" }},
+                "kernels": {{ "sheet": {{ "path": "sheet.png" }}, "shape": "3x3" }},
+                "convolution": {{ "backend": "Vectorized", "strict_reproducibility": false }},
+                "scoring": {{ "normalization": "Raw", "activation_k": 2.0, "permutations": 0 }},
+                "outputs": {{ "dir": "out", "scores_csv": true, "top_maps": 3 }}
+            }}"#
+        ))
+        .unwrap();
+        SharedRun {
+            pipeline,
+            kernels: 40,
+            scores: vec![SharedScore {
+                index: 12,
+                name: "edge, vertical".to_owned(),
+                score: 0.375,
+            }],
+            selected: 12,
+        }
+    }
+
+    #[test]
+    fn links_round_trip() {
+        let link = shared_run().link("https://example.org/app/#old").unwrap();
+        let payload = link
+            .strip_prefix("https://example.org/app/#share=")
+            .unwrap();
+        assert!(payload.bytes().all(|b| BASE64URL.contains(&b)));
+        // A pasted fragment works as well as the whole link.
+        for pasted in [link.clone(), format!(" #share={payload}\n")] {
+            let run = SharedRun::from_link(&pasted).unwrap().unwrap();
+            assert_eq!((run.kernels, run.selected), (40, 12));
+            assert_eq!(run.pipeline.slide.hash, "00ff");
+            assert_eq!(run.scores[0].name, "edge, vertical");
+            assert_eq!(run.scores[0].score, 0.375);
+        }
+        assert!(
+            SharedRun::from_link("https://example.org/app/")
+                .unwrap()
+                .is_none()
+        );
+        assert!(SharedRun::from_link("#share=AAAA").is_err());
+        assert!(SharedRun::from_link("#share=not+base64").is_err());
+    }
+
+    #[test]
+    fn base64url_round_trips_every_length() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        for len in 0..8 {
+            let text = encode(&bytes[250 - len..250]);
+            assert_eq!(text.len(), (len * 4).div_ceil(3));
+            assert_eq!(decode(&text).unwrap(), &bytes[250 - len..250]);
+        }
+        assert_eq!(decode(&encode(&bytes)).unwrap(), bytes);
+        assert_eq!(decode("A"), None);
+    }
+}
@@ -0,0 +1,158 @@
+use crate::export::csv_field;
+use crate::stats;
+
+/// Bytes a kept pixel takes: its index and its value.
+const PIXEL_BYTES: usize = 8;
+
+/// The active pixels of a map, those deviating from its mean by more than
+/// k standard deviations as the activation rate counts them, as row-major
+/// indices and values; every other pixel is dropped. Indices are 32-bit,
+/// which covers maps of up to 4 gigapixels.
+pub struct SparseMap {
+    pub width: usize,
+    indices: Vec<u32>,
+    values: Vec<f32>,
+}
+
+impl SparseMap {
+    /// Active pixels of `response`, `width` pixels wide, at `k` standard
+    /// deviations. Non-finite pixels are never active.
+    pub fn active(response: &[f32], width: usize, k: f32) -> Self {
+        let (mean, std) = stats::mean_std(response);
+        let (mean, band) = (mean as f32, k * std as f32);
+        let (indices, values) = response
+            .iter()
+            .enumerate()
+            .filter(|&(_, &r)| r.is_finite() && (r - mean).abs() > band)
+            .map(|(i, &r)| (i as u32, r))
+            .unzip();
+        Self {
+            width,
+            indices,
+            values,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// Column, row and value of each active pixel, in row-major order.
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize, f32)> + '_ {
+        let width = self.width.max(1);
+        self.indices
+            .iter()
+            .zip(&self.values)
+            .map(move |(&i, &r)| (i as usize % width, i as usize / width, r))
+    }
+}
+
+/// Active pixels of a run's maps, by kernel index, kept in memory in place
+/// of the full maps.
+#[derive(Default)]
+pub struct SparseStore {
+    maps: Vec<Option<SparseMap>>,
+}
+
+impl SparseStore {
+    pub fn insert(&mut self, index: usize, map: SparseMap) {
+        if index >= self.maps.len() {
+            self.maps.resize_with(index + 1, || None);
+        }
+        self.maps[index] = Some(map);
+    }
+
+    /// Forgets a map that no longer matches its kernel.
+    pub fn remove(&mut self, index: usize) {
+        if let Some(map) = self.maps.get_mut(index) {
+            *map = None;
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&SparseMap> {
+        self.maps.get(index)?.as_ref()
+    }
+
+    /// Maps kept and their active pixels together.
+    pub fn count(&self) -> (usize, usize) {
+        self.maps
+            .iter()
+            .flatten()
+            .fold((0, 0), |(maps, pixels), map| (maps + 1, pixels + map.len()))
+    }
+
+    /// Memory the kept pixels take, in bytes.
+    pub fn bytes(&self) -> usize {
+        self.count().1 * PIXEL_BYTES
+    }
+}
+
+/// Table of the active pixels of `maps` (kernel index, name and map), one
+/// row per pixel, at the slide positions `position` gives map pixels.
+pub fn active_csv<'a>(
+    maps: impl Iterator<Item = (usize, &'a str, &'a SparseMap)>,
+    position: impl Fn(usize, usize) -> (usize, usize),
+) -> String {
+    let mut csv = String::from("kernel,name,x,y,value\n");
+    for (index, name, map) in maps {
+        let name = csv_field(name);
+        for (x, y, value) in map.pixels() {
+            let (x, y) = position(x, y);
+            csv.push_str(&format!("{index},{name},{x},{y},{value}\n"));
+        }
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 6 x 4 map, flat but for three spikes and two non-finite pixels.
+    fn map() -> Vec<f32> {
+        let mut map = vec![0.0; 24];
+        for (x, y, value) in [(1, 0, 5.0), (4, 2, -6.0), (5, 3, 4.0)] {
+            map[y * 6 + x] = value;
+        }
+        map[3 * 6] = f32::NAN;
+        map[2 * 6 + 2] = f32::INFINITY;
+        map
+    }
+
+    #[test]
+    fn active_pixels_round_trip_through_the_store() {
+        let sparse = SparseMap::active(&map(), 6, 1.0);
+        let spikes = [(1, 0, 5.0), (4, 2, -6.0), (5, 3, 4.0)];
+        assert_eq!(sparse.pixels().collect::<Vec<_>>(), spikes);
+
+        let mut store = SparseStore::default();
+        store.insert(2, sparse);
+        store.insert(0, SparseMap::active(&[1.0; 24], 6, 1.0));
+        assert_eq!(store.count(), (2, 3));
+        assert_eq!(store.bytes(), 3 * PIXEL_BYTES);
+        assert!(store.get(1).is_none());
+        let kept = store.get(2).unwrap();
+        let mut dense = vec![0.0; 24];
+        for (x, y, value) in kept.pixels() {
+            dense[y * kept.width + x] = value;
+        }
+        let finite = map()
+            .into_iter()
+            .map(|v| if v.is_finite() { v } else { 0.0 });
+        assert!(dense.into_iter().eq(finite));
+
+        let maps = [(2, "edge, left", kept)];
+        let csv = active_csv(maps.into_iter(), |x, y| (x + 100, y * 2));
+        assert_eq!(
+            csv,
+            "kernel,name,x,y,value\n\
+             2,\"edge, left\",101,0,5\n\
+             2,\"edge, left\",104,4,-6\n\
+             2,\"edge, left\",105,6,4\n"
+        );
+
+        store.remove(2);
+        assert!(store.get(2).is_none());
+        assert_eq!(store.count(), (1, 0));
+    }
+}
@@ -0,0 +1,385 @@
+use image::{GrayImage, RgbImage};
+use serde::{Deserialize, Serialize};
+
+use crate::imaging::gray_to_f32;
+
+/// Gray levels of an 8-bit slide.
+const LEVELS: usize = 256;
+/// Transmitted light intensity of Macenko's optical density, the brightness
+/// of empty glass.
+const BACKGROUND_INTENSITY: f32 = 240.0;
+/// Pixels with an optical density below this on any channel are treated as
+/// glass and left out of the stain estimate.
+const MIN_OPTICAL_DENSITY: f32 = 0.15;
+/// Percentile of the stain angles taken as the extreme stain vectors, which
+/// makes the estimate robust to a few outliers.
+const ANGLE_PERCENTILE: f64 = 1.0;
+/// Pixels sampled at most for the stain estimate of a large slide.
+const STAIN_SAMPLES: usize = 1 << 20;
+/// Name recorded for runs normalized to [`StainBasis::STANDARD`].
+pub const STANDARD_TARGET: &str = "standard H&E target";
+
+/// How a slide's intensities are mapped onto a reference slide's before
+/// convolution, so that scores of differently stained slides are comparable.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StainNormalization {
+    #[default]
+    None,
+    /// Each gray level goes to the reference level of the same quantile, so
+    /// the slide takes on the reference's whole histogram.
+    HistogramMatching,
+    /// Reinhard normalization: the slide's mean and standard deviation are
+    /// shifted and scaled to the reference's. Slides are loaded as gray
+    /// levels, so it applies to lightness only.
+    MeanStd,
+    /// Macenko normalization of color H&E slides: the hematoxylin and eosin
+    /// stain vectors are estimated in optical density, and the stain
+    /// concentrations rescaled to the reference's before the slide is
+    /// recomposed in the reference's stains and converted to gray. Gray
+    /// slides are left unchanged.
+    Macenko,
+}
+
+impl StainNormalization {
+    pub const ALL: [Self; 4] = [
+        Self::None,
+        Self::HistogramMatching,
+        Self::MeanStd,
+        Self::Macenko,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::None => "None",
+            Self::HistogramMatching => "Histogram matching",
+            Self::MeanStd => "Mean/std (Reinhard)",
+            Self::Macenko => "Macenko (H&E)",
+        }
+    }
+}
+
+/// Gray-level histogram of the slide other slides are normalized to.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StainReference {
+    /// File name of the reference slide.
+    pub name: String,
+    /// Pixel count of each of the 256 gray levels.
+    pub histogram: Vec<u64>,
+    /// Stains of a color reference, the target of Macenko normalization.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stains: Option<StainBasis>,
+}
+
+impl StainReference {
+    /// Reference of the slide `gray`, with its stains when the color pixels
+    /// `rgb` are given and show enough tissue.
+    pub fn new(name: String, gray: &GrayImage, rgb: Option<&RgbImage>) -> Self {
+        let stains = rgb.and_then(|rgb| {
+            StainBasis::estimate(rgb)
+                .inspect_err(|e| log::warn!("{e}; Macenko will use the {STANDARD_TARGET}."))
+                .ok()
+        });
+        Self {
+            name,
+            histogram: histogram(gray).to_vec(),
+            stains,
+        }
+    }
+
+    /// Mean and standard deviation in `[0, 1]` intensities.
+    fn mean_std(&self) -> (f64, f64) {
+        mean_std(&self.histogram)
+    }
+
+    /// Intensity below which a fraction `p` of the reference's pixels lie,
+    /// interpolated within gray levels.
+    fn quantile(&self, p: f64) -> f32 {
+        let total: u64 = self.histogram.iter().sum();
+        let target = p * total as f64;
+        let mut below = 0u64;
+        for (level, &count) in self.histogram.iter().enumerate() {
+            if count > 0 && (below + count) as f64 >= target {
+                let within = ((target - below as f64) / count as f64).clamp(0.0, 1.0);
+                return ((level as f64 - 0.5 + within) / 255.0).clamp(0.0, 1.0) as f32;
+            }
+            below += count;
+        }
+        1.0
+    }
+}
+
+/// Hematoxylin and eosin optical-density vectors of a slide, with the 99th
+/// percentile of each stain's concentration.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StainBasis {
+    /// Unit optical-density vectors over red, green and blue, hematoxylin
+    /// first.
+    pub stains: [[f32; 3]; 2],
+    pub max_concentrations: [f32; 2],
+}
+
+impl StainBasis {
+    /// Reference stains published with the method, the target when no color
+    /// reference slide is set.
+    pub const STANDARD: Self = Self {
+        stains: [[0.5626, 0.7201, 0.4062], [0.2159, 0.8012, 0.5581]],
+        max_concentrations: [1.9705, 1.0308],
+    };
+
+    /// Macenko's estimate: the plane of the two main directions of the
+    /// tissue's optical densities, and the vectors at the extreme angles of
+    /// the densities projected onto it.
+    pub fn estimate(rgb: &RgbImage) -> Result<Self, String> {
+        let pixels = rgb.width() as usize * rgb.height() as usize;
+        let step = pixels.div_ceil(STAIN_SAMPLES).max(1);
+        let tissue: Vec<[f32; 3]> = rgb
+            .pixels()
+            .step_by(step)
+            .map(|p| optical_density(p.0))
+            .filter(|od| od.iter().all(|&v| v >= MIN_OPTICAL_DENSITY))
+            .collect();
+        if tissue.len() < 100 {
+            return Err("Too little stained tissue to estimate H&E stain vectors".to_owned());
+        }
+        let count = tissue.len() as f64;
+        let mean: [f64; 3] =
+            std::array::from_fn(|c| tissue.iter().map(|od| od[c] as f64).sum::<f64>() / count);
+        let mut covariance = [[0.0f64; 3]; 3];
+        for od in &tissue {
+            for (i, row) in covariance.iter_mut().enumerate() {
+                for (j, cell) in row.iter_mut().enumerate() {
+                    *cell += (od[i] as f64 - mean[i]) * (od[j] as f64 - mean[j]);
+                }
+            }
+        }
+        let (values, vectors) = symmetric_eigen(covariance);
+        let mut order = [0, 1, 2];
+        order.sort_by(|&a, &b| values[b].total_cmp(&values[a]));
+        // Eigenvectors have no sign; densities are positive, so are the axes.
+        let axis = |k: usize| {
+            let v = [vectors[0][k], vectors[1][k], vectors[2][k]];
+            let sign = if v.iter().sum::<f64>() < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            v.map(|c| c * sign)
+        };
+        let (first, second) = (axis(order[0]), axis(order[1]));
+        let dot = |a: [f64; 3], od: &[f32; 3]| (0..3).map(|c| a[c] * od[c] as f64).sum::<f64>();
+        let mut angles: Vec<f64> = tissue
+            .iter()
+            .map(|od| dot(second, od).atan2(dot(first, od)))
+            .collect();
+        angles.sort_by(f64::total_cmp);
+        let vector = |angle: f64| {
+            let v: [f64; 3] =
+                std::array::from_fn(|c| first[c] * angle.cos() + second[c] * angle.sin());
+            let norm = v
+                .iter()
+                .map(|c| c * c)
+                .sum::<f64>()
+                .sqrt()
+                .max(f64::EPSILON);
+            v.map(|c| (c / norm) as f32)
+        };
+        let low = vector(percentile(&angles, ANGLE_PERCENTILE));
+        let high = vector(percentile(&angles, 100.0 - ANGLE_PERCENTILE));
+        // Hematoxylin absorbs more red than eosin does.
+        let stains = if low[0] > high[0] {
+            [low, high]
+        } else {
+            [high, low]
+        };
+        let unmix = unmixing(&stains).ok_or("The slide shows a single stain")?;
+        let mut concentrations: [Vec<f64>; 2] = [Vec::new(), Vec::new()];
+        for p in rgb.pixels().step_by(step) {
+            let c = unmix(optical_density(p.0));
+            concentrations[0].push(c[0] as f64);
+            concentrations[1].push(c[1] as f64);
+        }
+        let max_concentrations = concentrations.map(|mut c| {
+            c.sort_by(f64::total_cmp);
+            percentile(&c, 99.0) as f32
+        });
+        Ok(Self {
+            stains,
+            max_concentrations,
+        })
+    }
+}
+
+/// Intensities of `gray` in `[0, 1]`, row-major, normalized with `mode` to
+/// `reference`. Without a reference the intensities are unchanged, except
+/// that Macenko then targets [`StainBasis::STANDARD`]; it needs the slide's
+/// color pixels `rgb`.
+pub fn normalized_input(
+    gray: &GrayImage,
+    rgb: Option<&RgbImage>,
+    mode: StainNormalization,
+    reference: Option<&StainReference>,
+) -> Vec<f32> {
+    if mode == StainNormalization::Macenko {
+        let target = reference
+            .and_then(|r| r.stains)
+            .unwrap_or(StainBasis::STANDARD);
+        let Some(rgb) = rgb else {
+            return gray_to_f32(gray);
+        };
+        return match StainBasis::estimate(rgb) {
+            Ok(source) => macenko(rgb, &source, &target),
+            Err(e) => {
+                log::warn!("{e}; the slide is not normalized.");
+                gray_to_f32(gray)
+            }
+        };
+    }
+    let reference = match reference {
+        Some(r) if mode != StainNormalization::None && r.histogram.iter().any(|&c| c > 0) => r,
+        _ => return gray_to_f32(gray),
+    };
+    let counts = histogram(gray);
+    let lut: Vec<f32> = match mode {
+        StainNormalization::None
+        | StainNormalization::HistogramMatching
+        | StainNormalization::Macenko => {
+            let total: u64 = counts.iter().sum();
+            let mut below = 0u64;
+            counts
+                .iter()
+                .map(|&count| {
+                    // Mid-rank, so a level is mapped to the middle of the
+                    // reference range its pixels cover.
+                    let p = (below as f64 + count as f64 / 2.0) / total.max(1) as f64;
+                    below += count;
+                    reference.quantile(p)
+                })
+                .collect()
+        }
+        StainNormalization::MeanStd => {
+            let (mean, std) = mean_std(&counts);
+            let (ref_mean, ref_std) = reference.mean_std();
+            let scale = if std > 0.0 { ref_std / std } else { 1.0 };
+            (0..LEVELS)
+                .map(|level| {
+                    let v = level as f64 / 255.0;
+                    ((v - mean) * scale + ref_mean).clamp(0.0, 1.0) as f32
+                })
+                .collect()
+        }
+    };
+    gray.pixels().map(|p| lut[p[0] as usize]).collect()
+}
+
+/// Gray intensities of `rgb` recomposed from its stain concentrations in
+/// `source`, each rescaled to `target`'s maximum, and `target`'s stains.
+fn macenko(rgb: &RgbImage, source: &StainBasis, target: &StainBasis) -> Vec<f32> {
+    let Some(unmix) = unmixing(&source.stains) else {
+        return rgb.pixels().map(|p| luma(p.0.map(f32::from))).collect();
+    };
+    let scale: [f32; 2] = std::array::from_fn(|s| {
+        target.max_concentrations[s] / source.max_concentrations[s].max(f32::EPSILON)
+    });
+    rgb.pixels()
+        .map(|p| {
+            let c = unmix(optical_density(p.0));
+            let (h, e) = (c[0] * scale[0], c[1] * scale[1]);
+            let channel = |k: usize| {
+                let od = target.stains[0][k] * h + target.stains[1][k] * e;
+                (BACKGROUND_INTENSITY * (-od).exp()).clamp(0.0, 255.0)
+            };
+            luma([channel(0), channel(1), channel(2)])
+        })
+        .collect()
+}
+
+fn optical_density(rgb: [u8; 3]) -> [f32; 3] {
+    rgb.map(|v| -((v as f32 + 1.0) / BACKGROUND_INTENSITY).ln())
+}
+
+/// Least-squares stain concentrations of an optical density in `stains`, or
+/// `None` when the two stains are parallel.
+fn unmixing(stains: &[[f32; 3]; 2]) -> Option<impl Fn([f32; 3]) -> [f32; 2]> {
+    let dot = |a: &[f32; 3], b: &[f32; 3]| (0..3).map(|c| a[c] * b[c]).sum::<f32>();
+    let [h, e] = stains;
+    let (hh, he, ee) = (dot(h, h), dot(h, e), dot(e, e));
+    let det = hh * ee - he * he;
+    (det.abs() > 1e-6).then_some(move |od: [f32; 3]| {
+        let (hd, ed) = (dot(h, &od), dot(e, &od));
+        [(ee * hd - he * ed) / det, (hh * ed - he * hd) / det]
+    })
+}
+
+/// Intensity in `[0, 1]` of 8-bit-range channels, weighted as the slide's
+/// own gray conversion.
+fn luma([r, g, b]: [f32; 3]) -> f32 {
+    (0.2126 * r + 0.7152 * g + 0.0722 * b) / 255.0
+}
+
+/// Percentile `p` of ascending `sorted`, interpolated between samples.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = p / 100.0 * (sorted.len() - 1) as f64;
+    let (below, above) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[below] + (sorted[above] - sorted[below]) * (rank - below as f64)
+}
+
+/// Eigenvalues and eigenvectors (as columns) of a symmetric 3x3 matrix, by
+/// cyclic Jacobi rotations.
+fn symmetric_eigen(mut a: [[f64; 3]; 3]) -> ([f64; 3], [[f64; 3]; 3]) {
+    let mut v = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    for _ in 0..32 {
+        let off = a[0][1].powi(2) + a[0][2].powi(2) + a[1][2].powi(2);
+        if off < 1e-30 {
+            break;
+        }
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q] == 0.0 {
+                continue;
+            }
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
+            // A <- J^T A J and V <- V J for the rotation J in the p-q plane.
+            let rotate_columns = |m: &mut [[f64; 3]; 3]| {
+                for row in m {
+                    let (mp, mq) = (row[p], row[q]);
+                    row[p] = c * mp - s * mq;
+                    row[q] = s * mp + c * mq;
+                }
+            };
+            rotate_columns(&mut a);
+            let (row_p, row_q) = (a[p], a[q]);
+            a[p] = std::array::from_fn(|k| c * row_p[k] - s * row_q[k]);
+            a[q] = std::array::from_fn(|k| s * row_p[k] + c * row_q[k]);
+            rotate_columns(&mut v);
+        }
+    }
+    ([a[0][0], a[1][1], a[2][2]], v)
+}
+
+fn histogram(gray: &GrayImage) -> [u64; LEVELS] {
+    let mut counts = [0u64; LEVELS];
+    for p in gray.pixels() {
+        counts[p[0] as usize] += 1;
+    }
+    counts
+}
+
+fn mean_std(counts: &[u64]) -> (f64, f64) {
+    let total = counts.iter().sum::<u64>().max(1) as f64;
+    let level = |i: usize| i as f64 / 255.0;
+    let mean = counts
+        .iter()
+        .enumerate()
+        .map(|(i, &c)| level(i) * c as f64)
+        .sum::<f64>()
+        / total;
+    let variance = counts
+        .iter()
+        .enumerate()
+        .map(|(i, &c)| (level(i) - mean).powi(2) * c as f64)
+        .sum::<f64>()
+        / total;
+    (mean, variance.sqrt())
+}
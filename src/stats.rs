@@ -0,0 +1,390 @@
+/// Histogram resolution used for entropy and mutual information.
+const INFO_BINS: usize = 64;
+/// Histogram resolution of the tissue threshold.
+const TISSUE_BINS: usize = 256;
+/// Gradient coherence below which a response has no dominant orientation.
+const MIN_COHERENCE: f32 = 0.2;
+
+/// Neumaier-compensated accumulator. Summing millions of response values
+/// naively loses precision as the running total grows, which would make
+/// scores drift with image size.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CompensatedSum {
+    sum: f64,
+    compensation: f64,
+}
+
+impl CompensatedSum {
+    pub fn add(&mut self, value: f64) {
+        let t = self.sum + value;
+        if self.sum.abs() >= value.abs() {
+            self.compensation += (self.sum - t) + value;
+        } else {
+            self.compensation += (value - t) + self.sum;
+        }
+        self.sum = t;
+    }
+
+    pub fn value(&self) -> f64 {
+        self.sum + self.compensation
+    }
+}
+
+pub fn compensated_sum(values: impl IntoIterator<Item = f64>) -> f64 {
+    let mut acc = CompensatedSum::default();
+    for v in values {
+        acc.add(v);
+    }
+    acc.value()
+}
+
+/// Number of NaN and infinite values.
+pub fn non_finite(values: &[f32]) -> usize {
+    values.iter().filter(|v| !v.is_finite()).count()
+}
+
+/// Replaces NaN and infinite values with 0, returning how many there were.
+pub fn replace_non_finite(values: &mut [f32]) -> usize {
+    let mut replaced = 0;
+    for v in values.iter_mut().filter(|v| !v.is_finite()) {
+        *v = 0.0;
+        replaced += 1;
+    }
+    replaced
+}
+
+/// Population mean and standard deviation of the finite values, `(0, 0)`
+/// when there are none.
+pub fn mean_std(values: &[f32]) -> (f64, f64) {
+    if non_finite(values) > 0 {
+        let finite: Vec<f32> = values.iter().copied().filter(|v| v.is_finite()).collect();
+        return mean_std(&finite);
+    }
+    if values.is_empty() {
+        return (0.0, 0.0);
+    }
+    let n = values.len() as f64;
+    let mean = compensated_sum(values.iter().map(|&v| v as f64)) / n;
+    let sum_sq = compensated_sum(values.iter().map(|&v| {
+        let d = v as f64 - mean;
+        d * d
+    }));
+    (mean, (sum_sq / n).sqrt())
+}
+
+/// Summary statistics of one response map.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ResponseStats {
+    pub mean: f32,
+    pub std_dev: f32,
+    pub mean_abs: f32,
+    pub max_abs: f32,
+    /// Mean squared response.
+    pub energy: f32,
+    /// Hoyer sparsity of the response, from the ratio of its L1 and L2
+    /// norms: 0 when every pixel responds equally, 1 when a single pixel
+    /// carries the whole response.
+    pub sparsity: f32,
+    /// Fraction of pixels deviating from the mean by more than k standard
+    /// deviations.
+    pub activation_rate: f32,
+    /// Gini index of the absolute response: 0 when every pixel responds
+    /// equally, approaching 1 when a few pixels carry the whole response.
+    pub gini: f32,
+    /// Shannon entropy of the response histogram, in bits.
+    pub entropy: f32,
+    /// Mutual information between the response and the input intensity,
+    /// in bits.
+    pub mutual_information: f32,
+    /// Mutual information divided by the input entropy: close to 1 when the
+    /// response merely reproduces brightness.
+    pub brightness_share: f32,
+    /// Mean |r| over tissue, the pixels darker than [`tissue_threshold`].
+    pub tissue_mean_abs: f32,
+    /// Mean |r| over the brighter background of empty glass.
+    pub background_mean_abs: f32,
+    /// NaN and infinite pixels, which the other statistics leave out.
+    pub non_finite: usize,
+}
+
+/// `input` holds the intensities the response was computed from, in `[0, 1]`
+/// and with the same length as `values`. NaN and infinite responses are
+/// counted and otherwise left out.
+pub fn response_stats(values: &[f32], input: &[f32], k: f32) -> ResponseStats {
+    let non_finite = non_finite(values);
+    if non_finite > 0 {
+        let (values, input): (Vec<f32>, Vec<f32>) = values
+            .iter()
+            .zip(input)
+            .filter(|(v, _)| v.is_finite())
+            .unzip();
+        return ResponseStats {
+            non_finite,
+            ..response_stats(&values, &input, k)
+        };
+    }
+    if values.is_empty() {
+        return ResponseStats::default();
+    }
+    let n = values.len() as f64;
+
+    let (mean, std_dev) = mean_std(values);
+    let sum_abs = compensated_sum(values.iter().map(|&v| v.abs() as f64));
+    let sum_sq = compensated_sum(values.iter().map(|&v| v as f64 * v as f64));
+    let max_abs = values.iter().fold(0.0f32, |m, v| m.max(v.abs()));
+
+    let threshold = k as f64 * std_dev;
+    let active = values
+        .iter()
+        .filter(|&&v| (v as f64 - mean).abs() > threshold)
+        .count();
+
+    let (entropy, mutual_information, input_entropy) = information(values, input);
+
+    let threshold = tissue_threshold(input);
+    let (mut tissue, mut background) = (CompensatedSum::default(), CompensatedSum::default());
+    let mut tissue_pixels = 0usize;
+    for (&v, &i) in values.iter().zip(input) {
+        if i < threshold {
+            tissue.add(v.abs() as f64);
+            tissue_pixels += 1;
+        } else {
+            background.add(v.abs() as f64);
+        }
+    }
+    let region_mean = |sum: CompensatedSum, pixels: usize| {
+        if pixels > 0 {
+            (sum.value() / pixels as f64) as f32
+        } else {
+            0.0
+        }
+    };
+
+    ResponseStats {
+        mean: mean as f32,
+        std_dev: std_dev as f32,
+        mean_abs: (sum_abs / n) as f32,
+        max_abs,
+        energy: (sum_sq / n) as f32,
+        sparsity: hoyer_sparsity(sum_abs, sum_sq, n),
+        activation_rate: active as f32 / values.len() as f32,
+        gini: gini(values, sum_abs),
+        entropy,
+        mutual_information,
+        brightness_share: if input_entropy > 0.0 {
+            mutual_information / input_entropy
+        } else {
+            0.0
+        },
+        tissue_mean_abs: region_mean(tissue, tissue_pixels),
+        background_mean_abs: region_mean(background, values.len() - tissue_pixels),
+        non_finite: 0,
+    }
+}
+
+/// Hoyer sparsity of `n` values with absolute sum `sum_abs` and sum of
+/// squares `sum_sq`.
+fn hoyer_sparsity(sum_abs: f64, sum_sq: f64, n: f64) -> f32 {
+    if n <= 1.0 || sum_sq <= 0.0 {
+        return 0.0;
+    }
+    let root_n = n.sqrt();
+    ((root_n - sum_abs / sum_sq.sqrt()) / (root_n - 1.0)).clamp(0.0, 1.0) as f32
+}
+
+/// Otsu threshold of `input` intensities in `[0, 1]`. In brightfield slides
+/// stained tissue is darker than the empty glass around it, so pixels below
+/// the threshold count as tissue. Uniform inputs give 0: all glass.
+pub fn tissue_threshold(input: &[f32]) -> f32 {
+    let mut histogram = [0usize; TISSUE_BINS];
+    for &v in input {
+        histogram[(v.clamp(0.0, 1.0) * (TISSUE_BINS - 1) as f32) as usize] += 1;
+    }
+    let total = input.len() as f64;
+    let sum_all: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(bin, &count)| bin as f64 * count as f64)
+        .sum();
+    let (mut below, mut sum_below) = (0.0, 0.0);
+    let (mut best, mut best_variance) = (0, 0.0);
+    for (bin, &count) in histogram.iter().enumerate() {
+        below += count as f64;
+        sum_below += bin as f64 * count as f64;
+        let above = total - below;
+        if below == 0.0 || above == 0.0 {
+            continue;
+        }
+        let difference = sum_below / below - (sum_all - sum_below) / above;
+        let variance = below * above * difference * difference;
+        if variance > best_variance {
+            (best, best_variance) = (bin + 1, variance);
+        }
+    }
+    best as f32 / (TISSUE_BINS - 1) as f32
+}
+
+/// Returns `(H(response), I(response; input), H(input))` from a joint
+/// histogram with [`INFO_BINS`] bins per axis.
+fn information(values: &[f32], input: &[f32]) -> (f32, f32, f32) {
+    let (min_v, max_v) = values
+        .iter()
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &v| {
+            (lo.min(v), hi.max(v))
+        });
+    let range = (max_v - min_v).max(1e-12);
+    let bin = |v: f32, lo: f32, range: f32| {
+        (((v - lo) / range) * INFO_BINS as f32).clamp(0.0, INFO_BINS as f32 - 1.0) as usize
+    };
+
+    let mut joint = vec![0u32; INFO_BINS * INFO_BINS];
+    for (&r, &i) in values.iter().zip(input) {
+        joint[bin(r, min_v, range) * INFO_BINS + bin(i, 0.0, 1.0)] += 1;
+    }
+    let total = values.len().min(input.len()) as f64;
+    if total == 0.0 {
+        return (0.0, 0.0, 0.0);
+    }
+
+    let mut response_marginal = vec![0u32; INFO_BINS];
+    let mut input_marginal = vec![0u32; INFO_BINS];
+    for r in 0..INFO_BINS {
+        for i in 0..INFO_BINS {
+            let c = joint[r * INFO_BINS + i];
+            response_marginal[r] += c;
+            input_marginal[i] += c;
+        }
+    }
+
+    let entropy_of = |counts: &[u32]| -> f64 {
+        counts
+            .iter()
+            .filter(|&&c| c > 0)
+            .map(|&c| {
+                let p = c as f64 / total;
+                -p * p.log2()
+            })
+            .sum()
+    };
+    let h_response = entropy_of(&response_marginal);
+    let h_input = entropy_of(&input_marginal);
+    let h_joint = entropy_of(&joint);
+    let mi = (h_response + h_input - h_joint).max(0.0);
+    (h_response as f32, mi as f32, h_input as f32)
+}
+
+/// Gini index of `|values|`, given their precomputed sum.
+fn gini(values: &[f32], sum_abs: f64) -> f32 {
+    if sum_abs <= 0.0 {
+        return 0.0;
+    }
+    let mut sorted: Vec<f32> = values.iter().map(|v| v.abs()).collect();
+    sorted.sort_unstable_by(f32::total_cmp);
+    let n = sorted.len() as f64;
+    let weighted = compensated_sum(
+        sorted
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| (i as f64 + 1.0) * v as f64),
+    );
+    ((2.0 * weighted) / (n * sum_abs) - (n + 1.0) / n) as f32
+}
+
+/// Strongest pixel and prevailing direction of a response, for captions.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ResponseHighlights {
+    /// Response of largest magnitude, with its sign.
+    pub peak: f32,
+    /// Column and row of `peak`.
+    pub peak_at: (usize, usize),
+    /// Direction of the response's edges and ridges, in degrees
+    /// counterclockwise from the horizontal within `[0, 180)`, when the
+    /// gradients agree on one.
+    pub orientation: Option<f32>,
+}
+
+/// Peak and dominant orientation of a `width` x `height` response. The
+/// orientation comes from the structure tensor of the central-difference
+/// gradients summed over the map.
+pub fn highlights(values: &[f32], width: usize, height: usize) -> ResponseHighlights {
+    let Some((peak_index, &peak)) = values
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))
+    else {
+        return ResponseHighlights::default();
+    };
+    let (mut jxx, mut jyy, mut jxy) = (0.0f64, 0.0f64, 0.0f64);
+    for y in 1..height.saturating_sub(1) {
+        for x in 1..width.saturating_sub(1) {
+            let i = y * width + x;
+            let gx = (values[i + 1] - values[i - 1]) as f64;
+            // Rows run downwards; flip them so angles turn counterclockwise.
+            let gy = (values[i - width] - values[i + width]) as f64;
+            jxx += gx * gx;
+            jyy += gy * gy;
+            jxy += gx * gy;
+        }
+    }
+    let (degrees, coherence) = tensor_orientation(jxx, jyy, jxy);
+    ResponseHighlights {
+        peak,
+        peak_at: (peak_index % width.max(1), peak_index / width.max(1)),
+        orientation: (coherence >= MIN_COHERENCE).then_some(degrees),
+    }
+}
+
+/// Edge orientation, in degrees counterclockwise from the horizontal within
+/// `[0, 180)`, and coherence in `[0, 1]` of the structure tensor
+/// `[[jxx, jxy], [jxy, jyy]]` of gradients with upward y.
+pub fn tensor_orientation(jxx: f64, jyy: f64, jxy: f64) -> (f32, f32) {
+    let spread = ((jxx - jyy).powi(2) + 4.0 * jxy * jxy).sqrt();
+    let coherence = spread / (jxx + jyy).max(f64::MIN_POSITIVE);
+    // Edges run across the dominant gradient.
+    let gradient = 0.5 * (2.0 * jxy).atan2(jxx - jyy);
+    let degrees = (gradient.to_degrees() + 90.0).rem_euclid(180.0);
+    (degrees as f32, coherence.min(1.0) as f32)
+}
+
+/// FNV-1a hash of the exact bit patterns of `values`, used to verify that
+/// two runs produced bit-identical responses.
+pub fn checksum(values: &[f32]) -> u64 {
+    fnv1a(values.iter().flat_map(|v| v.to_bits().to_le_bytes()))
+}
+
+/// FNV-1a hash of a file's bytes, identifying an input independently of
+/// its path.
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    fnv1a(bytes.iter().copied())
+}
+
+fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compensated_sums_keep_small_terms() {
+        assert_eq!([1e16, 1.0, -1e16].iter().sum::<f64>(), 0.0);
+        assert_eq!(compensated_sum([1e16, 1.0, -1e16]), 1.0);
+        let tenths = compensated_sum(std::iter::repeat_n(0.1, 1_000_000));
+        assert!((tenths - 100_000.0).abs() < 1e-9, "{tenths}");
+    }
+
+    #[test]
+    fn statistics_leave_out_non_finite_values() {
+        let mut values = [1.0, f32::NAN, 3.0, f32::INFINITY];
+        assert_eq!(mean_std(&values), (2.0, 1.0));
+        assert_eq!(replace_non_finite(&mut values), 2);
+        assert_eq!(values, [1.0, 0.0, 3.0, 0.0]);
+        assert_eq!(mean_std(&[]), (0.0, 0.0));
+    }
+}
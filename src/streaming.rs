@@ -0,0 +1,612 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use web_time::Instant;
+
+use crate::backend::Backend;
+use crate::border::BorderMode;
+use crate::export;
+use crate::kernel::Kernel;
+use crate::morphology::FilterMode;
+use crate::scoring::ScoreNormalization;
+use crate::stats::CompensatedSum;
+
+/// Dimensions of a PNG, read from its header without decoding any pixels.
+pub fn png_size(path: &Path) -> Result<(u32, u32), String> {
+    let reader = open(path)?;
+    let info = reader.info();
+    Ok((info.width, info.height))
+}
+
+fn open(path: &Path) -> Result<png::Reader<BufReader<File>>, String> {
+    let file = File::open(path).map_err(|e| format!("Cannot open {}: {e}", path.display()))?;
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    decoder.set_transformations(png::Transformations::EXPAND);
+    decoder
+        .read_info()
+        .map_err(|e| format!("Cannot read {}: {e}", path.display()))
+}
+
+/// Longest side of the downsampled mosaic kept of each map.
+pub const MOSAIC_SIDE: usize = 512;
+
+/// Full-resolution map of one kernel, written to disk as it is computed.
+struct KernelOutput {
+    raw: BufWriter<File>,
+    raw_path: PathBuf,
+    sum_abs: CompensatedSum,
+    min: f32,
+    max: f32,
+    /// Sum of the map over each mosaic cell, for the rows written so far.
+    mosaic: Vec<f32>,
+    rows_done: usize,
+}
+
+/// Convolution of a slide that is never held in memory as a whole. Row bands
+/// are decoded with enough halo rows above and below for the tallest kernel
+/// and cut into tiles of columns. Each kernel is applied to each tile with
+/// the halo it reaches read through the border mode, the tiles are put
+/// together and only the band's own rows are appended to that kernel's raw
+/// map, so the result matches an in-memory run exactly. A downsampled mosaic
+/// of every map grows as its bands are written.
+/// The work is split into steps of one kernel on one tile so the window
+/// stays responsive; afterwards every raw map is turned into a PNG, one per
+/// step.
+pub struct StreamJob {
+    pub slide: String,
+    pub started: Instant,
+    reader: png::Reader<BufReader<File>>,
+    color: png::ColorType,
+    depth: png::BitDepth,
+    pub width: usize,
+    pub height: usize,
+    /// Enabled kernels of the bank, with their bank indices; muted ones are
+    /// not streamed.
+    kernels: Vec<Kernel>,
+    indices: Vec<usize>,
+    /// Rows the tallest kernels reach above and below an output row.
+    halo: (usize, usize),
+    pub backend: Backend,
+    pub mode: FilterMode,
+    border: BorderMode,
+    band_rows: usize,
+    tile_cols: usize,
+    /// Decoded rows from `buffer_top` on, `width` values per row.
+    buffer: Vec<f32>,
+    buffer_top: usize,
+    /// First and last rows of the slide, which a wrapped border reads past
+    /// the opposite edge long after or before they are buffered.
+    head: Vec<f32>,
+    tail: Vec<f32>,
+    row_bytes: Vec<u8>,
+    /// First output row of the band being convolved.
+    band_top: usize,
+    next_kernel: usize,
+    next_tile: usize,
+    /// Response of the kernel being applied to the band, tile by tile.
+    band_output: Vec<f32>,
+    /// Slide pixels per side of a mosaic cell.
+    mosaic_scale: usize,
+    maps_written: usize,
+    pixel_sum: CompensatedSum,
+    pixel_sum_sq: CompensatedSum,
+    outputs: Vec<KernelOutput>,
+    out_dir: PathBuf,
+}
+
+impl StreamJob {
+    /// Job streaming `path` in bands of `band_rows` rows and tiles of
+    /// `tile_cols` columns.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        path: &Path,
+        kernels: &[Kernel],
+        backend: Backend,
+        mode: FilterMode,
+        border: BorderMode,
+        (band_rows, tile_cols): (usize, usize),
+        out_dir: &str,
+    ) -> Result<Self, String> {
+        let (indices, kernels): (Vec<usize>, Vec<Kernel>) = kernels
+            .iter()
+            .enumerate()
+            .filter(|(_, k)| k.enabled)
+            .map(|(i, k)| (i, k.clone()))
+            .unzip();
+        if kernels.is_empty() {
+            return Err("Every kernel is muted; enable one to stream.".to_owned());
+        }
+        let reader = open(path)?;
+        let info = reader.info();
+        if info.interlaced {
+            return Err(format!(
+                "{} is interlaced and cannot be streamed; save it without interlacing.",
+                path.display()
+            ));
+        }
+        let (width, height) = (info.width as usize, info.height as usize);
+        if width == 0 || height == 0 {
+            return Err(format!("{} has no pixels.", path.display()));
+        }
+        let (color, depth) = reader.output_color_type();
+        let row_bytes = reader
+            .output_line_size(info.width)
+            .ok_or_else(|| format!("{} is too wide to decode.", path.display()))?;
+        let slide = path.file_name().map_or(path.display().to_string(), |n| {
+            n.to_string_lossy().into_owned()
+        });
+        let stem = slide.rsplit_once('.').map_or(slide.as_str(), |(s, _)| s);
+        let out_dir = Path::new(out_dir).join(format!("{stem}_streamed"));
+        std::fs::create_dir_all(&out_dir)
+            .map_err(|e| format!("Cannot create {}: {e}", out_dir.display()))?;
+        let mosaic_scale = width.max(height).div_ceil(MOSAIC_SIDE);
+        let mosaic_cells = width.div_ceil(mosaic_scale) * height.div_ceil(mosaic_scale);
+        let outputs = indices
+            .iter()
+            .map(|index| {
+                let raw_path = out_dir.join(format!("kernel{index}.f32"));
+                let raw = File::create(&raw_path)
+                    .map_err(|e| format!("Cannot create {}: {e}", raw_path.display()))?;
+                Ok(KernelOutput {
+                    raw: BufWriter::new(raw),
+                    raw_path,
+                    sum_abs: CompensatedSum::default(),
+                    min: f32::INFINITY,
+                    max: f32::NEG_INFINITY,
+                    mosaic: vec![0.0; mosaic_cells],
+                    rows_done: 0,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        let halo = kernels.iter().fold((0, 0), |(up, down), k| {
+            (up.max(k.height / 2), down.max(k.height - 1 - k.height / 2))
+        });
+        let mut row_bytes = vec![0; row_bytes];
+        let tail = if border.same_kind(BorderMode::Wrap) && halo.0 > 0 {
+            last_rows(path, &slide, halo.0, &mut row_bytes)?
+        } else {
+            Vec::new()
+        };
+        Ok(Self {
+            slide,
+            started: Instant::now(),
+            reader,
+            color,
+            depth,
+            width,
+            height,
+            kernels,
+            indices,
+            halo,
+            backend,
+            mode,
+            border,
+            band_rows: band_rows.max(1),
+            tile_cols: tile_cols.max(1),
+            buffer: Vec::new(),
+            buffer_top: 0,
+            head: Vec::new(),
+            tail,
+            row_bytes,
+            band_top: 0,
+            next_kernel: 0,
+            next_tile: 0,
+            band_output: Vec::new(),
+            mosaic_scale,
+            maps_written: 0,
+            pixel_sum: CompensatedSum::default(),
+            pixel_sum_sq: CompensatedSum::default(),
+            outputs,
+            out_dir,
+        })
+    }
+
+    fn band_end(&self) -> usize {
+        (self.band_top + self.band_rows).min(self.height)
+    }
+
+    fn tiles(&self) -> usize {
+        self.width.div_ceil(self.tile_cols)
+    }
+
+    /// Fraction of the total work done.
+    pub fn progress(&self) -> f32 {
+        let kernels = self.kernels.len().max(1);
+        let band = (self.band_end() - self.band_top.min(self.height)) as f32;
+        let tiles = self.next_kernel * self.tiles() + self.next_tile;
+        let convolved =
+            (self.band_top * kernels) as f32 + band * tiles as f32 / self.tiles() as f32;
+        let total = self.height * kernels;
+        (convolved + (self.maps_written * self.height) as f32) / (total * 2).max(1) as f32
+    }
+
+    /// Kernels streamed, leaving out the muted ones.
+    pub fn kernel_count(&self) -> usize {
+        self.kernels.len()
+    }
+
+    /// Output of bank kernel `index`, unless it is muted.
+    fn output(&self, index: usize) -> Option<&KernelOutput> {
+        let position = self.indices.iter().position(|&i| i == index)?;
+        self.outputs.get(position)
+    }
+
+    /// Rows of the slide in kernel `index`'s map so far.
+    pub fn rows_done(&self, index: usize) -> usize {
+        self.output(index).map_or(0, |o| o.rows_done)
+    }
+
+    /// Downsampled map of kernel `index`, each pixel the mean of a cell of
+    /// at most [`MOSAIC_SIDE`] per side, rescaled over the map's range so
+    /// far. Cells with no rows written yet are transparent.
+    pub fn mosaic(&self, index: usize) -> Option<image::GrayAlphaImage> {
+        let output = self.output(index)?;
+        let scale = self.mosaic_scale;
+        let (mw, mh) = (self.width.div_ceil(scale), self.height.div_ceil(scale));
+        let range = (output.max - output.min).max(1e-6);
+        Some(image::GrayAlphaImage::from_fn(
+            mw as u32,
+            mh as u32,
+            |cx, cy| {
+                let (x0, y0) = (cx as usize * scale, cy as usize * scale);
+                let cell_width = scale.min(self.width - x0);
+                let cell_height = scale.min(self.height - y0);
+                let rows = output.rows_done.min(y0 + cell_height).saturating_sub(y0);
+                if rows == 0 {
+                    return image::LumaA([0, 0]);
+                }
+                let mean =
+                    output.mosaic[cy as usize * mw + cx as usize] / (rows * cell_width) as f32;
+                let gray = (((mean - output.min) / range) * 255.0).clamp(0.0, 255.0) as u8;
+                image::LumaA([gray, 255])
+            },
+        ))
+    }
+
+    /// Pixel-tap products of the whole run.
+    pub fn work(&self) -> u64 {
+        let taps: usize = self.kernels.iter().map(Kernel::taps).sum();
+        (self.width * self.height * taps) as u64
+    }
+
+    /// Performs one step. Returns `false` once everything is on disk and
+    /// [`StreamJob::finish`] can be called.
+    pub fn step(&mut self) -> Result<bool, String> {
+        if self.band_top < self.height && !self.kernels.is_empty() {
+            if self.next_kernel == 0 && self.next_tile == 0 {
+                self.fill_band()?;
+            }
+            self.convolve_tile()?;
+            return Ok(true);
+        }
+        if self.maps_written < self.outputs.len() {
+            self.write_map(self.maps_written)?;
+            self.maps_written += 1;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Drops rows above the current band's halo and decodes rows down to the
+    /// bottom of its halo.
+    fn fill_band(&mut self) -> Result<(), String> {
+        let halo_top = self.band_top.saturating_sub(self.halo.0);
+        let halo_bottom = (self.band_end() + self.halo.1).min(self.height);
+        let dropped = (halo_top - self.buffer_top) * self.width;
+        self.buffer.drain(..dropped.min(self.buffer.len()));
+        self.buffer_top = halo_top;
+        while self.buffer_top + self.buffer.len() / self.width < halo_bottom {
+            let decoded = self.buffer_top + self.buffer.len() / self.width;
+            let row = read_row(&mut self.reader, &mut self.row_bytes, &self.slide)?;
+            for gray in row_to_gray(row, self.width as u32, self.color, self.depth) {
+                let value = gray as f32 / 255.0;
+                self.pixel_sum.add(value as f64);
+                self.pixel_sum_sq.add((value * value) as f64);
+                self.buffer.push(value);
+            }
+            if !self.tail.is_empty() && decoded < self.halo.1 {
+                let start = self.buffer.len() - self.width;
+                self.head.extend_from_slice(&self.buffer[start..]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Row `row` of the slide, which must be buffered or, for a wrapped
+    /// border, among the first or last rows kept.
+    fn row(&self, row: usize) -> &[f32] {
+        let width = self.width;
+        let buffered = self.buffer.len() / width;
+        let tail_top = self.height - self.tail.len() / width;
+        if (self.buffer_top..self.buffer_top + buffered).contains(&row) {
+            let start = (row - self.buffer_top) * width;
+            &self.buffer[start..start + width]
+        } else if row < self.head.len() / width {
+            &self.head[row * width..(row + 1) * width]
+        } else {
+            let start = (row - tail_top) * width;
+            &self.tail[start..start + width]
+        }
+    }
+
+    /// Applies the current kernel to the current tile of the band, and
+    /// appends the band to the kernel's map after its last tile.
+    fn convolve_tile(&mut self) -> Result<(), String> {
+        let rows = self.band_top..self.band_end();
+        if self.next_tile == 0 {
+            self.band_output.clear();
+            self.band_output.resize(rows.len() * self.width, 0.0);
+        }
+        let start = self.next_tile * self.tile_cols;
+        let cols = start..(start + self.tile_cols).min(self.width);
+        let response = self.kernels[self.next_kernel].filter_rows(
+            self.mode,
+            self.backend,
+            self.border,
+            |row| self.row(row),
+            (self.width, self.height),
+            cols.clone(),
+            rows,
+        );
+        for (row, line) in response.chunks_exact(cols.len()).enumerate() {
+            let start = row * self.width + cols.start;
+            self.band_output[start..start + cols.len()].copy_from_slice(line);
+        }
+        self.next_tile += 1;
+        if self.next_tile < self.tiles() {
+            return Ok(());
+        }
+        self.next_tile = 0;
+        self.append_band()?;
+        self.next_kernel += 1;
+        if self.next_kernel == self.kernels.len() {
+            self.next_kernel = 0;
+            self.band_top = self.band_end();
+        }
+        Ok(())
+    }
+
+    /// Appends the current kernel's response to the band to its raw map and
+    /// mosaic.
+    fn append_band(&mut self) -> Result<(), String> {
+        let (width, scale, done) = (self.width, self.mosaic_scale, self.band_end());
+        let mosaic_width = width.div_ceil(scale);
+        let output = &mut self.outputs[self.next_kernel];
+        for (row, line) in self.band_output.chunks_exact(width).enumerate() {
+            let cells = (self.band_top + row) / scale * mosaic_width;
+            for (x, &v) in line.iter().enumerate() {
+                output.sum_abs.add(v.abs() as f64);
+                output.min = output.min.min(v);
+                output.max = output.max.max(v);
+                output.mosaic[cells + x / scale] += v;
+                output
+                    .raw
+                    .write_all(&v.to_le_bytes())
+                    .map_err(|e| format!("Cannot write {}: {e}", output.raw_path.display()))?;
+            }
+        }
+        output.rows_done = done;
+        Ok(())
+    }
+
+    /// Rescales the `position`-th raw map to 8 bits, as
+    /// [`export::response_png`] does, reading and encoding it a row at a
+    /// time.
+    fn write_map(&mut self, position: usize) -> Result<(), String> {
+        let index = self.indices[position];
+        let output = &mut self.outputs[position];
+        output
+            .raw
+            .flush()
+            .map_err(|e| format!("Cannot write {}: {e}", output.raw_path.display()))?;
+        let png_path = self.out_dir.join(format!("kernel{index}.png"));
+        let write_error =
+            |e: png::EncodingError| format!("Cannot write {}: {e}", png_path.display());
+        let file = File::create(&png_path).map_err(|e| write_error(e.into()))?;
+        let mut encoder =
+            png::Encoder::new(BufWriter::new(file), self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(write_error)?;
+        let mut stream = writer.stream_writer().map_err(write_error)?;
+
+        let mut raw = File::open(&output.raw_path)
+            .map(BufReader::new)
+            .map_err(|e| format!("Cannot read {}: {e}", output.raw_path.display()))?;
+        let range = (output.max - output.min).max(1e-6);
+        let mut row = vec![0; self.width * 4];
+        let mut pixels = vec![0; self.width];
+        for _ in 0..self.height {
+            raw.read_exact(&mut row)
+                .map_err(|e| format!("Cannot read {}: {e}", output.raw_path.display()))?;
+            for (pixel, bytes) in pixels.iter_mut().zip(row.chunks_exact(4)) {
+                let v = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                *pixel = (((v - output.min) / range) * 255.0).clamp(0.0, 255.0) as u8;
+            }
+            stream
+                .write_all(&pixels)
+                .map_err(|e| write_error(e.into()))?;
+        }
+        stream.finish().map_err(write_error)?;
+        writer.finish().map_err(write_error)?;
+
+        let mosaic_path = self.out_dir.join(format!("kernel{index}_mosaic.png"));
+        let mosaic = self
+            .mosaic(index)
+            .map(|m| image::DynamicImage::from(m).to_luma8());
+        mosaic
+            .unwrap_or_default()
+            .save(&mosaic_path)
+            .map_err(|e| format!("Cannot write {}: {e}", mosaic_path.display()))
+    }
+
+    /// Writes the scores CSV in `csv_format` and returns a summary for the
+    /// status line. The `BaselineZ` normalization needs random kernels
+    /// convolved over the whole slide and the tissue scores a threshold of
+    /// the whole slide, so they fall back to the raw score here, as do the
+    /// weight-based normalizations of rank filters.
+    pub fn finish(
+        self,
+        normalization: ScoreNormalization,
+        csv_format: export::CsvFormat,
+    ) -> Result<String, String> {
+        let normalization = normalization.for_mode(self.mode);
+        let pixels = (self.width * self.height).max(1) as f64;
+        let mean = self.pixel_sum.value() / pixels;
+        let image_std = (self.pixel_sum_sq.value() / pixels - mean * mean)
+            .max(0.0)
+            .sqrt() as f32;
+        let mut csv = String::from("kernel,name,score,mean_abs,min,max\n");
+        for ((kernel, output), index) in self.kernels.iter().zip(&self.outputs).zip(&self.indices) {
+            let mean_abs = (output.sum_abs.value() / pixels) as f32;
+            let score = normalization.apply(mean_abs, &kernel.masked_weights(), image_std, None);
+            csv.push_str(&format!(
+                "{index},{},{score},{mean_abs},{},{}\n",
+                export::csv_field(&kernel.name),
+                output.min,
+                output.max
+            ));
+        }
+        let path = export::save_file(
+            &self.out_dir.to_string_lossy(),
+            "scores.csv",
+            csv_format.apply(&csv).as_bytes(),
+        )?;
+        Ok(format!(
+            "Streamed {} kernels over {} ({}x{}); raw maps, PNGs and {path} written.",
+            self.kernels.len(),
+            self.slide,
+            self.width,
+            self.height
+        ))
+    }
+}
+
+fn read_row<'a>(
+    reader: &mut png::Reader<BufReader<File>>,
+    row_bytes: &'a mut [u8],
+    slide: &str,
+) -> Result<&'a [u8], String> {
+    reader
+        .read_row(row_bytes)
+        .map_err(|e| format!("Cannot decode {slide}: {e}"))?
+        .ok_or_else(|| format!("{slide} ended early"))?;
+    Ok(row_bytes)
+}
+
+/// Grey values of the last `rows` rows of the slide at `path`, decoding it
+/// once through.
+fn last_rows(
+    path: &Path,
+    slide: &str,
+    rows: usize,
+    row_bytes: &mut [u8],
+) -> Result<Vec<f32>, String> {
+    let mut reader = open(path)?;
+    let info = reader.info();
+    let (width, height) = (info.width, info.height as usize);
+    let (color, depth) = reader.output_color_type();
+    let mut last = Vec::new();
+    for row in 0..height {
+        let bytes = read_row(&mut reader, row_bytes, slide)?;
+        if row + rows >= height {
+            let gray = row_to_gray(bytes, width, color, depth);
+            last.extend(gray.into_iter().map(|g| g as f32 / 255.0));
+        }
+    }
+    Ok(last)
+}
+
+/// Grey values of one decoded row, converted by the `image` crate exactly
+/// as a slide loaded in memory is.
+fn row_to_gray(bytes: &[u8], width: u32, color: png::ColorType, depth: png::BitDepth) -> Vec<u8> {
+    use image::{DynamicImage, ImageBuffer};
+    use png::ColorType;
+
+    let image = if depth == png::BitDepth::Sixteen {
+        let samples: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .collect();
+        match color {
+            ColorType::GrayscaleAlpha => {
+                ImageBuffer::from_raw(width, 1, samples).map(DynamicImage::ImageLumaA16)
+            }
+            ColorType::Rgb => {
+                ImageBuffer::from_raw(width, 1, samples).map(DynamicImage::ImageRgb16)
+            }
+            ColorType::Rgba => {
+                ImageBuffer::from_raw(width, 1, samples).map(DynamicImage::ImageRgba16)
+            }
+            _ => ImageBuffer::from_raw(width, 1, samples).map(DynamicImage::ImageLuma16),
+        }
+    } else {
+        let samples = bytes.to_vec();
+        match color {
+            ColorType::GrayscaleAlpha => {
+                ImageBuffer::from_raw(width, 1, samples).map(DynamicImage::ImageLumaA8)
+            }
+            ColorType::Rgb => ImageBuffer::from_raw(width, 1, samples).map(DynamicImage::ImageRgb8),
+            ColorType::Rgba => {
+                ImageBuffer::from_raw(width, 1, samples).map(DynamicImage::ImageRgba8)
+            }
+            _ => return samples,
+        }
+    };
+    image.map_or_else(Vec::new, |image| image.to_luma8().into_raw())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::Provenance;
+
+    #[test]
+    fn muted_kernels_are_not_streamed() {
+        let dir = std::env::temp_dir().join(format!("convolution_stream_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let slide = dir.join("slide.png");
+        image::GrayImage::from_fn(9, 7, |x, y| image::Luma([(x * 20 + y * 7) as u8]))
+            .save(&slide)
+            .unwrap();
+        let mut kernels: Vec<Kernel> = (0..3)
+            .map(|i| Kernel::new(vec![i as f32 + 1.0; 9], (3, 3), Provenance::Entered))
+            .collect();
+        kernels[1].enabled = false;
+        let out = dir.to_string_lossy().into_owned();
+        let mut job = StreamJob::new(
+            &slide,
+            &kernels,
+            Backend::Scalar,
+            FilterMode::Correlation,
+            BorderMode::Zero,
+            (3, 4),
+            &out,
+        )
+        .unwrap();
+        assert_eq!(job.kernel_count(), 2);
+        while job.step().unwrap() {}
+        assert_eq!(job.rows_done(2), 7);
+        assert!(job.mosaic(1).is_none());
+        let streamed = dir.join("slide_streamed");
+        job.finish(ScoreNormalization::Raw, export::CsvFormat::default())
+            .unwrap();
+        let csv = std::fs::read_to_string(streamed.join("scores.csv")).unwrap();
+        let exists = |name: &str| streamed.join(name).exists();
+        let result = (
+            csv.lines()
+                .skip(1)
+                .map(|l| l.split(',').next().unwrap().to_owned())
+                .collect::<Vec<_>>(),
+            exists("kernel0.png"),
+            exists("kernel1.png"),
+            exists("kernel2.png"),
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(
+            result,
+            (vec!["0".to_owned(), "2".to_owned()], true, false, true)
+        );
+    }
+}
@@ -0,0 +1,156 @@
+//! Run progress on the window's taskbar or dock entry: `ITaskbarList3` on
+//! Windows and the Unity launcher API (read by Ubuntu's dock, KDE Plasma,
+//! Plank and Dash to Dock) on Linux. Other platforms get a no-op.
+
+/// Desktop entry the Linux launcher API matches the progress against; the
+/// app installs no `.desktop` file itself, so docks only show the bar when
+/// one with this name exists.
+pub const APP_ID: &str = "convolution_wasm";
+
+#[derive(Default)]
+pub struct TaskbarProgress {
+    /// Percentage last sent, so unchanged progress is not resent every frame.
+    shown: Option<Option<u32>>,
+    #[cfg(windows)]
+    taskbar: Option<windows_taskbar::Taskbar>,
+    #[cfg(all(unix, not(target_os = "macos")))]
+    updates: Option<std::sync::mpsc::Sender<Option<u32>>>,
+}
+
+impl TaskbarProgress {
+    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        let mut progress = Self::default();
+        #[cfg(windows)]
+        {
+            progress.taskbar = windows_taskbar::Taskbar::new(cc)
+                .inspect_err(|e| log::warn!("Taskbar progress unavailable: {e}"))
+                .ok();
+        }
+        #[cfg(all(unix, not(target_os = "macos")))]
+        {
+            progress.updates = Some(launcher_entry::spawn());
+        }
+        // Only Windows needs the window handle.
+        let _ = cc;
+        progress
+    }
+
+    /// Shows `fraction` (0..=1) on the taskbar entry, or hides the bar.
+    pub fn set(&mut self, fraction: Option<f32>) {
+        let percent = fraction.map(|f| (f.clamp(0.0, 1.0) * 100.0).round() as u32);
+        if self.shown == Some(percent) {
+            return;
+        }
+        self.shown = Some(percent);
+        #[cfg(windows)]
+        if let Some(taskbar) = &self.taskbar
+            && let Err(e) = taskbar.set(percent)
+        {
+            log::warn!("Taskbar progress failed: {e}");
+        }
+        #[cfg(all(unix, not(target_os = "macos")))]
+        if let Some(updates) = &self.updates
+            && updates.send(percent).is_err()
+        {
+            self.updates = None;
+        }
+    }
+}
+
+#[cfg(windows)]
+mod windows_taskbar {
+    use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+    use windows::Win32::Foundation::HWND;
+    use windows::Win32::System::Com::{
+        CLSCTX_INPROC_SERVER, COINIT_APARTMENTTHREADED, CoCreateInstance, CoInitializeEx,
+    };
+    use windows::Win32::UI::Shell::{ITaskbarList3, TBPF_NOPROGRESS, TBPF_NORMAL, TaskbarList};
+
+    pub struct Taskbar {
+        list: ITaskbarList3,
+        window: HWND,
+    }
+
+    impl Taskbar {
+        pub fn new(cc: &eframe::CreationContext<'_>) -> Result<Self, String> {
+            let handle = cc.window_handle().map_err(|e| e.to_string())?;
+            let RawWindowHandle::Win32(handle) = handle.as_raw() else {
+                return Err("not a Win32 window".to_owned());
+            };
+            let window = HWND(handle.hwnd.get() as *mut _);
+            // SAFETY: plain COM calls on the UI thread, which winit has
+            // already initialized as an apartment; the extra init is a no-op.
+            unsafe {
+                let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+                let list: ITaskbarList3 =
+                    CoCreateInstance(&TaskbarList, None, CLSCTX_INPROC_SERVER)
+                        .map_err(|e| e.to_string())?;
+                list.HrInit().map_err(|e| e.to_string())?;
+                Ok(Self { list, window })
+            }
+        }
+
+        pub fn set(&self, percent: Option<u32>) -> windows::core::Result<()> {
+            // SAFETY: `window` is this app's top-level window, which
+            // outlives the app.
+            unsafe {
+                match percent {
+                    Some(percent) => {
+                        self.list.SetProgressState(self.window, TBPF_NORMAL)?;
+                        self.list
+                            .SetProgressValue(self.window, u64::from(percent), 100)
+                    }
+                    None => self.list.SetProgressState(self.window, TBPF_NOPROGRESS),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod launcher_entry {
+    use std::collections::HashMap;
+    use std::sync::mpsc::{self, Sender};
+
+    use zbus::zvariant::Value;
+
+    use super::APP_ID;
+
+    /// Starts a thread that owns the session bus connection and turns each
+    /// percentage it receives into a `LauncherEntry.Update` signal. Without
+    /// a session bus the thread logs once and exits.
+    pub fn spawn() -> Sender<Option<u32>> {
+        let (sender, receiver) = mpsc::channel::<Option<u32>>();
+        std::thread::spawn(move || {
+            let connection = match zbus::blocking::Connection::session() {
+                Ok(connection) => connection,
+                Err(e) => {
+                    log::info!("Taskbar progress unavailable: {e}");
+                    return;
+                }
+            };
+            let uri = format!("application://{APP_ID}.desktop");
+            let path = format!("/com/canonical/unity/launcherentry/{APP_ID}");
+            for percent in receiver {
+                let properties = HashMap::from([
+                    ("progress-visible", Value::from(percent.is_some())),
+                    (
+                        "progress",
+                        Value::from(f64::from(percent.unwrap_or(0)) / 100.0),
+                    ),
+                ]);
+                if let Err(e) = connection.emit_signal(
+                    None::<&str>,
+                    path.as_str(),
+                    "com.canonical.Unity.LauncherEntry",
+                    "Update",
+                    &(uri.as_str(), properties),
+                ) {
+                    log::warn!("Taskbar progress failed: {e}");
+                    return;
+                }
+            }
+        });
+        sender
+    }
+}
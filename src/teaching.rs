@@ -0,0 +1,140 @@
+use crate::border::BorderMode;
+use crate::imaging::Roi;
+use crate::kernel::Kernel;
+
+/// Largest side of the region a lesson walks over, so its pixels stay
+/// large enough to read.
+pub const MAX_REGION_SIDE: usize = 24;
+
+/// Convolution of one kernel over a small region of the slide, taken one
+/// tap at a time for teaching. Output pixels are visited in reading order
+/// and, at each, the kernel's taps in reading order too, exactly as the
+/// scalar backend sums them.
+pub struct Lesson {
+    pub region: Roi,
+    kernel_size: (usize, usize),
+    weights: Vec<f32>,
+    /// The region grown by the kernel's reach on every side, read through
+    /// the border mode where it passes the slide's edges.
+    window: Vec<f32>,
+    /// Taps summed so far over the whole region.
+    step: usize,
+}
+
+impl Lesson {
+    /// Lesson of `kernel` over `region` of a `size` image whose pixels
+    /// `pixel` returns.
+    pub fn new(
+        kernel: &Kernel,
+        size: (usize, usize),
+        region: Roi,
+        border: BorderMode,
+        pixel: impl Fn(usize, usize) -> f32,
+    ) -> Self {
+        let (kw, kh) = (kernel.width, kernel.height);
+        let (left, top) = (
+            region.x as isize - (kw / 2) as isize,
+            region.y as isize - (kh / 2) as isize,
+        );
+        let (ww, wh) = (region.width + kw - 1, region.height + kh - 1);
+        let window = (0..ww * wh)
+            .map(|i| {
+                let at = (left + (i % ww) as isize, top + (i / ww) as isize);
+                border.sample(at, size, &pixel)
+            })
+            .collect();
+        Self {
+            region,
+            kernel_size: (kw, kh),
+            weights: kernel.masked_weights().into_owned(),
+            window,
+            step: 0,
+        }
+    }
+
+    pub fn kernel_size(&self) -> (usize, usize) {
+        self.kernel_size
+    }
+
+    fn taps(&self) -> usize {
+        self.kernel_size.0 * self.kernel_size.1
+    }
+
+    pub fn steps(&self) -> usize {
+        self.region.width * self.region.height * self.taps()
+    }
+
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.step == self.steps()
+    }
+
+    /// Moves `taps` taps forward, stopping at the end.
+    pub fn advance(&mut self, taps: usize) {
+        self.step = (self.step + taps).min(self.steps());
+    }
+
+    /// Moves to the end of the output pixel being summed, or through the
+    /// next one when the current one is complete.
+    pub fn finish_pixel(&mut self) {
+        let taps = self.taps();
+        self.step = ((self.step / taps + 1) * taps).min(self.steps());
+    }
+
+    pub fn restart(&mut self) {
+        self.step = 0;
+    }
+
+    /// Output pixel being summed, by index in the region, and how many of
+    /// its taps are in; `None` before the first step.
+    pub fn current(&self) -> Option<(usize, usize)> {
+        let taps = self.taps();
+        (self.step > 0).then(|| ((self.step - 1) / taps, (self.step - 1) % taps + 1))
+    }
+
+    /// Pixel read by each tap of the kernel at region pixel `index`, in
+    /// kernel order; the constant of the border mode past the edges.
+    pub fn inputs(&self, index: usize) -> Vec<f32> {
+        let (kw, kh) = self.kernel_size;
+        let ww = self.region.width + kw - 1;
+        let (x, y) = (index % self.region.width, index / self.region.width);
+        (0..kw * kh)
+            .map(|tap| self.window[(y + tap / kw) * ww + x + tap % kw])
+            .collect()
+    }
+
+    /// Weight times pixel for each tap at region pixel `index`.
+    pub fn products(&self, index: usize) -> Vec<f32> {
+        self.inputs(index)
+            .iter()
+            .zip(&self.weights)
+            .map(|(pixel, weight)| pixel * weight)
+            .collect()
+    }
+
+    pub fn weights(&self) -> &[f32] {
+        &self.weights
+    }
+
+    /// Response at each region pixel, for the pixels fully summed so far.
+    pub fn outputs(&self) -> Vec<Option<f32>> {
+        let done = self.step / self.taps();
+        (0..self.region.width * self.region.height)
+            .map(|i| (i < done).then(|| self.products(i).iter().sum()))
+            .collect()
+    }
+
+    /// Pixels the lesson reads, with their width and height: the region
+    /// and the kernel's reach around it.
+    pub fn window(&self) -> (&[f32], usize, usize) {
+        let (kw, kh) = self.kernel_size;
+        (
+            &self.window,
+            self.region.width + kw - 1,
+            self.region.height + kh - 1,
+        )
+    }
+}
@@ -0,0 +1,165 @@
+use std::io::Cursor;
+
+use image::{DynamicImage, GrayImage, ImageFormat, RgbImage, imageops};
+
+use crate::export::csv_field;
+
+/// Slide block whose score passed the triage threshold for at least one
+/// kernel.
+#[derive(Clone, Debug)]
+pub struct FlaggedTile {
+    pub col: usize,
+    pub row: usize,
+    /// Top-left slide pixel and size; edge tiles are cut at the slide.
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    /// Highest score on the tile and the kernel that gave it.
+    pub score: f32,
+    pub kernel: usize,
+    /// Every kernel above the threshold on the tile, highest score first.
+    pub kernels: Vec<usize>,
+}
+
+/// Tiles of a `cols`-column grid of `tile`-pixel blocks over the `width` x
+/// `height` region at slide pixel `origin` where any kernel of `grids`
+/// (kernel index and row-major tile scores) scores above `threshold`,
+/// highest score first.
+pub fn flag_tiles(
+    grids: &[(usize, Vec<f32>)],
+    cols: usize,
+    tile: usize,
+    (width, height): (usize, usize),
+    origin: (usize, usize),
+    threshold: f32,
+) -> Vec<FlaggedTile> {
+    let count = grids.first().map_or(0, |(_, scores)| scores.len());
+    let mut flagged: Vec<FlaggedTile> = (0..count)
+        .filter_map(|i| {
+            let mut above: Vec<(usize, f32)> = grids
+                .iter()
+                .map(|(kernel, scores)| (*kernel, scores[i]))
+                .filter(|&(_, score)| score > threshold)
+                .collect();
+            above.sort_by(|a, b| b.1.total_cmp(&a.1));
+            let &(kernel, score) = above.first()?;
+            let (col, row) = (i % cols, i / cols);
+            let (x, y) = (col * tile, row * tile);
+            Some(FlaggedTile {
+                col,
+                row,
+                x: origin.0 + x,
+                y: origin.1 + y,
+                width: tile.min(width - x),
+                height: tile.min(height - y),
+                score,
+                kernel,
+                kernels: above.into_iter().map(|(k, _)| k).collect(),
+            })
+        })
+        .collect();
+    flagged.sort_by(|a, b| b.score.total_cmp(&a.score));
+    flagged
+}
+
+/// Ranked table of the flagged tiles, one row per tile, naming the crop
+/// file written for it next to the table.
+pub fn flagged_csv(tiles: &[FlaggedTile], crop_name: impl Fn(usize) -> String) -> String {
+    let mut csv = String::from("rank,col,row,x,y,width,height,score,top_kernel,kernels,crop\n");
+    for (rank, t) in tiles.iter().enumerate() {
+        let kernels: Vec<String> = t.kernels.iter().map(usize::to_string).collect();
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{},{},{}\n",
+            rank + 1,
+            t.col,
+            t.row,
+            t.x,
+            t.y,
+            t.width,
+            t.height,
+            t.score,
+            t.kernel,
+            csv_field(&kernels.join(" ")),
+            csv_field(&crop_name(rank)),
+        ));
+    }
+    csv
+}
+
+/// PNG of the slide pixels under `tile`, from the color pixels `rgb` when
+/// the slide has them.
+pub fn crop_png(
+    gray: &GrayImage,
+    rgb: Option<&RgbImage>,
+    tile: &FlaggedTile,
+) -> Result<Vec<u8>, String> {
+    let (x, y) = (tile.x as u32, tile.y as u32);
+    let (width, height) = (tile.width as u32, tile.height as u32);
+    let crop: DynamicImage = match rgb {
+        Some(rgb) => imageops::crop_imm(rgb, x, y, width, height)
+            .to_image()
+            .into(),
+        None => imageops::crop_imm(gray, x, y, width, height)
+            .to_image()
+            .into(),
+    };
+    let mut bytes = Cursor::new(Vec::new());
+    crop.write_to(&mut bytes, ImageFormat::Png)
+        .map_err(|e| format!("PNG encoding failed: {e}"))?;
+    Ok(bytes.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_are_placed_in_slide_pixels() {
+        // A 3x2 grid of 4-pixel tiles over a 10x7 region at (20, 30).
+        let grids = vec![
+            (0, vec![0.0, 5.0, 0.0, 0.0, 0.0, 2.0]),
+            (3, vec![0.0, 1.0, 0.0, 4.0, 0.0, 3.0]),
+        ];
+        let tiles = flag_tiles(&grids, 3, 4, (10, 7), (20, 30), 1.5);
+        let placed: Vec<_> = tiles
+            .iter()
+            .map(|t| (t.col, t.row, t.x, t.y, t.width, t.height))
+            .collect();
+        assert_eq!(
+            placed,
+            [
+                (1, 0, 24, 30, 4, 4),
+                (0, 1, 20, 34, 4, 3),
+                (2, 1, 28, 34, 2, 3),
+            ]
+        );
+        assert_eq!(tiles[2].kernels, [3, 0]);
+        assert_eq!((tiles[2].kernel, tiles[2].score), (3, 3.0));
+    }
+
+    #[test]
+    fn crops_are_cut_from_the_slide_pixels() {
+        let gray = GrayImage::from_fn(40, 40, |x, y| image::Luma([(x + 2 * y) as u8]));
+        let grids = vec![(0, vec![0.0, 1.0])];
+        let tile = &flag_tiles(&grids, 2, 4, (8, 4), (20, 30), 0.5)[0];
+        let png = crop_png(&gray, None, tile).unwrap();
+        let crop = image::load_from_memory(&png).unwrap().to_luma8();
+        assert_eq!(crop.dimensions(), (4, 4));
+        assert_eq!(crop.get_pixel(0, 0)[0], 24 + 2 * 30);
+    }
+
+    #[test]
+    fn table_ranks_tiles_above_the_threshold() {
+        // The threshold itself does not flag a tile.
+        let grids = vec![(2, vec![1.0, 0.5, 3.0]), (5, vec![2.0, 0.0, 0.75])];
+        let tiles = flag_tiles(&grids, 3, 8, (24, 8), (0, 0), 0.75);
+        assert_eq!(
+            flagged_csv(&tiles, |rank| format!("tile_{}.png", rank + 1)),
+            "rank,col,row,x,y,width,height,score,top_kernel,kernels,crop\n\
+             1,2,0,16,0,8,8,3,2,2,tile_1.png\n\
+             2,0,0,0,0,8,8,2,5,5 2,tile_2.png\n"
+        );
+        assert!(flag_tiles(&grids, 3, 8, (24, 8), (0, 0), 3.0).is_empty());
+    }
+}
@@ -0,0 +1,128 @@
+use serde::{Deserialize, Serialize};
+use web_time::Instant;
+
+use crate::backend::Backend;
+
+/// Oldest records are dropped beyond this many.
+const MAX_RECORDS: usize = 2000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Stage {
+    LoadImage,
+    SplitKernels,
+    Convolution,
+    DraftRefinement,
+    PermutationTest,
+    Registration,
+    Autosave,
+    StreamedRun,
+    QuickScore,
+}
+
+impl Stage {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::LoadImage => "Load image",
+            Self::SplitKernels => "Split kernels",
+            Self::Convolution => "Convolution run",
+            Self::DraftRefinement => "Draft refinement",
+            Self::PermutationTest => "Permutation test",
+            Self::Registration => "Registration",
+            Self::Autosave => "Autosave",
+            Self::StreamedRun => "Streamed run",
+            Self::QuickScore => "Quick score",
+        }
+    }
+}
+
+/// One timed execution of a pipeline stage.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StageTiming {
+    pub stage: Stage,
+    /// Unix time at which the stage finished.
+    pub finished_at: u64,
+    pub seconds: f64,
+    pub backend: Option<Backend>,
+    /// Pixel-tap products processed, 0 when the stage does not convolve.
+    pub work: u64,
+}
+
+/// Aggregate of the records sharing a stage and backend.
+pub struct StageSummary {
+    pub stage: Stage,
+    pub backend: Option<Backend>,
+    pub runs: usize,
+    pub mean_seconds: f64,
+    pub last_seconds: f64,
+    /// Mean throughput in millions of pixel-tap products per second, when the
+    /// stage convolves.
+    pub mega_taps_per_second: Option<f64>,
+}
+
+/// Stage timings kept in the local settings, only while enabled. Nothing is
+/// sent anywhere.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UsageLog {
+    pub enabled: bool,
+    records: Vec<StageTiming>,
+}
+
+impl UsageLog {
+    pub fn record(&mut self, stage: Stage, started: Instant, backend: Option<Backend>, work: u64) {
+        if !self.enabled {
+            return;
+        }
+        let finished_at = web_time::SystemTime::now()
+            .duration_since(web_time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self.records.push(StageTiming {
+            stage,
+            finished_at,
+            seconds: started.elapsed().as_secs_f64(),
+            backend,
+            work,
+        });
+        let excess = self.records.len().saturating_sub(MAX_RECORDS);
+        self.records.drain(..excess);
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    pub fn records(&self) -> &[StageTiming] {
+        &self.records
+    }
+
+    /// One summary per stage and backend, in stage order.
+    pub fn summaries(&self) -> Vec<StageSummary> {
+        let mut groups: std::collections::BTreeMap<
+            (Stage, Option<&'static str>),
+            Vec<&StageTiming>,
+        > = Default::default();
+        for record in &self.records {
+            groups
+                .entry((record.stage, record.backend.map(Backend::label)))
+                .or_default()
+                .push(record);
+        }
+        groups
+            .into_values()
+            .map(|records| {
+                let seconds: f64 = records.iter().map(|r| r.seconds).sum();
+                let work: u64 = records.iter().map(|r| r.work).sum();
+                StageSummary {
+                    stage: records[0].stage,
+                    backend: records[0].backend,
+                    runs: records.len(),
+                    mean_seconds: seconds / records.len() as f64,
+                    last_seconds: records.last().map_or(0.0, |r| r.seconds),
+                    mega_taps_per_second: (work > 0 && seconds > 0.0)
+                        .then(|| work as f64 / seconds / 1e6),
+                }
+            })
+            .collect()
+    }
+}
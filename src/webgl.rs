@@ -0,0 +1,246 @@
+use std::sync::Arc;
+
+use eframe::glow::{self, HasContext};
+
+/// Most kernel taps the fragment shader holds as uniforms.
+const MAX_TAPS: usize = 256;
+
+/// Full-screen triangle without vertex buffers.
+const VERTEX_SHADER: &str = "#version 300 es
+void main() {
+    vec2 p = vec2(float((gl_VertexID << 1) & 2), float(gl_VertexID & 2));
+    gl_Position = vec4(p * 2.0 - 1.0, 0.0, 1.0);
+}
+";
+
+/// Zero-padded "same" convolution, visiting taps in the order of the CPU
+/// backends.
+const FRAGMENT_SHADER: &str = "#version 300 es
+precision highp float;
+precision highp int;
+precision highp sampler2D;
+
+uniform sampler2D u_input;
+uniform int u_kw;
+uniform int u_kh;
+uniform float u_kernel[MAX_TAPS];
+out vec4 out_value;
+
+void main() {
+    ivec2 p = ivec2(gl_FragCoord.xy);
+    ivec2 size = textureSize(u_input, 0);
+    float acc = 0.0;
+    for (int ky = 0; ky < u_kh; ky++) {
+        int iy = p.y + ky - u_kh / 2;
+        if (iy < 0 || iy >= size.y) {
+            continue;
+        }
+        for (int kx = 0; kx < u_kw; kx++) {
+            int ix = p.x + kx - u_kw / 2;
+            if (ix < 0 || ix >= size.x) {
+                continue;
+            }
+            acc += texelFetch(u_input, ivec2(ix, iy), 0).r * u_kernel[ky * u_kw + kx];
+        }
+    }
+    out_value = vec4(acc, 0.0, 0.0, 1.0);
+}
+";
+
+/// Convolution drawn as a fragment shader into a float framebuffer, for
+/// browsers with WebGL2 but without WebGPU. Shares eframe's context; the
+/// slide goes up as a float texture and every pixel of the framebuffer
+/// computes one output value. Results agree with the CPU backends up to
+/// float rounding.
+pub struct GlConvolver {
+    gl: Arc<glow::Context>,
+    program: glow::Program,
+    vertex_array: glow::VertexArray,
+    max_size: usize,
+}
+
+impl GlConvolver {
+    /// Compiles the shaders; `Err` without WebGL2 float render targets.
+    pub fn new(gl: Arc<glow::Context>) -> Result<Self, String> {
+        let version = gl.version();
+        if !version.is_embedded || version.major < 3 {
+            return Err("WebGL2 is not available".to_owned());
+        }
+        if !gl.supported_extensions().contains("EXT_color_buffer_float") {
+            return Err("WebGL2 cannot render to float textures".to_owned());
+        }
+        // SAFETY: plain GL calls on eframe's context from the UI thread; the
+        // state they change is restored before returning.
+        unsafe {
+            let program = link_program(&gl)?;
+            let vertex_array = gl.create_vertex_array()?;
+            let max_size = gl.get_parameter_i32(glow::MAX_TEXTURE_SIZE).max(0) as usize;
+            Ok(Self {
+                gl,
+                program,
+                vertex_array,
+                max_size,
+            })
+        }
+    }
+
+    pub fn convolve(
+        &self,
+        input: &[f32],
+        width: usize,
+        height: usize,
+        kernel: &[f32],
+        kw: usize,
+        kh: usize,
+    ) -> Result<Vec<f32>, String> {
+        if width > self.max_size || height > self.max_size {
+            return Err(format!(
+                "Slides above {0}x{0} do not fit in a WebGL texture",
+                self.max_size
+            ));
+        }
+        if kernel.len() > MAX_TAPS {
+            return Err(format!("Kernels above {MAX_TAPS} taps do not fit in WebGL"));
+        }
+        let gl = &self.gl;
+        let (w, h) = (width as i32, height as i32);
+        // SAFETY: as in `new`; the buffers passed to GL outlive the calls.
+        unsafe {
+            let input_texture = float_texture(gl, glow::R32F, glow::RED, w, h, Some(input))?;
+            let output_texture = float_texture(gl, glow::RGBA32F, glow::RGBA, w, h, None)?;
+            let framebuffer = gl.create_framebuffer()?;
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+            gl.framebuffer_texture_2d(
+                glow::FRAMEBUFFER,
+                glow::COLOR_ATTACHMENT0,
+                glow::TEXTURE_2D,
+                Some(output_texture),
+                0,
+            );
+            let complete =
+                gl.check_framebuffer_status(glow::FRAMEBUFFER) == glow::FRAMEBUFFER_COMPLETE;
+
+            let mut output = vec![0.0f32; width * height * 4];
+            if complete {
+                gl.disable(glow::BLEND);
+                gl.disable(glow::SCISSOR_TEST);
+                gl.viewport(0, 0, w, h);
+                gl.use_program(Some(self.program));
+                gl.active_texture(glow::TEXTURE0);
+                gl.bind_texture(glow::TEXTURE_2D, Some(input_texture));
+                let uniform = |name| gl.get_uniform_location(self.program, name);
+                gl.uniform_1_i32(uniform("u_input").as_ref(), 0);
+                gl.uniform_1_i32(uniform("u_kw").as_ref(), kw as i32);
+                gl.uniform_1_i32(uniform("u_kh").as_ref(), kh as i32);
+                gl.uniform_1_f32_slice(uniform("u_kernel").as_ref(), kernel);
+                gl.bind_vertex_array(Some(self.vertex_array));
+                gl.draw_arrays(glow::TRIANGLES, 0, 3);
+                gl.read_pixels(
+                    0,
+                    0,
+                    w,
+                    h,
+                    glow::RGBA,
+                    glow::FLOAT,
+                    glow::PixelPackData::Slice(Some(as_bytes_mut(&mut output))),
+                );
+            }
+
+            gl.bind_vertex_array(None);
+            gl.bind_texture(glow::TEXTURE_2D, None);
+            gl.use_program(None);
+            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+            gl.delete_framebuffer(framebuffer);
+            gl.delete_texture(input_texture);
+            gl.delete_texture(output_texture);
+            if !complete {
+                return Err("WebGL float framebuffer is incomplete".to_owned());
+            }
+            Ok(output.chunks_exact(4).map(|rgba| rgba[0]).collect())
+        }
+    }
+}
+
+impl Drop for GlConvolver {
+    fn drop(&mut self) {
+        // SAFETY: the objects were created on this context and are unused.
+        unsafe {
+            self.gl.delete_program(self.program);
+            self.gl.delete_vertex_array(self.vertex_array);
+        }
+    }
+}
+
+unsafe fn link_program(gl: &glow::Context) -> Result<glow::Program, String> {
+    unsafe {
+        let program = gl.create_program()?;
+        let fragment = FRAGMENT_SHADER.replace("MAX_TAPS", &MAX_TAPS.to_string());
+        let mut shaders = Vec::new();
+        for (kind, source) in [
+            (glow::VERTEX_SHADER, VERTEX_SHADER),
+            (glow::FRAGMENT_SHADER, fragment.as_str()),
+        ] {
+            let shader = gl.create_shader(kind)?;
+            gl.shader_source(shader, source);
+            gl.compile_shader(shader);
+            if !gl.get_shader_compile_status(shader) {
+                return Err(gl.get_shader_info_log(shader));
+            }
+            gl.attach_shader(program, shader);
+            shaders.push(shader);
+        }
+        gl.link_program(program);
+        for shader in shaders {
+            gl.detach_shader(program, shader);
+            gl.delete_shader(shader);
+        }
+        if !gl.get_program_link_status(program) {
+            return Err(gl.get_program_info_log(program));
+        }
+        Ok(program)
+    }
+}
+
+unsafe fn float_texture(
+    gl: &glow::Context,
+    internal_format: u32,
+    format: u32,
+    width: i32,
+    height: i32,
+    data: Option<&[f32]>,
+) -> Result<glow::Texture, String> {
+    unsafe {
+        let texture = gl.create_texture()?;
+        gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+        // Float textures cannot be filtered in WebGL2.
+        for parameter in [glow::TEXTURE_MIN_FILTER, glow::TEXTURE_MAG_FILTER] {
+            gl.tex_parameter_i32(glow::TEXTURE_2D, parameter, glow::NEAREST as i32);
+        }
+        gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 4);
+        gl.tex_image_2d(
+            glow::TEXTURE_2D,
+            0,
+            internal_format as i32,
+            width,
+            height,
+            0,
+            format,
+            glow::FLOAT,
+            glow::PixelUnpackData::Slice(data.map(as_bytes)),
+        );
+        gl.bind_texture(glow::TEXTURE_2D, None);
+        Ok(texture)
+    }
+}
+
+fn as_bytes(values: &[f32]) -> &[u8] {
+    // SAFETY: any f32 bit pattern is valid as bytes, and u8 has no alignment.
+    unsafe { std::slice::from_raw_parts(values.as_ptr().cast(), std::mem::size_of_val(values)) }
+}
+
+fn as_bytes_mut(values: &mut [f32]) -> &mut [u8] {
+    // SAFETY: as in `as_bytes`; every byte pattern is a valid f32 as well.
+    unsafe {
+        std::slice::from_raw_parts_mut(values.as_mut_ptr().cast(), std::mem::size_of_val(values))
+    }
+}
@@ -0,0 +1,149 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::io::Cursor;
+
+use image::{ImageFormat, RgbImage};
+
+use crate::export::csv_field;
+use crate::orientation::hsv_to_rgb;
+
+/// Magnitudes above this percentile saturate the map's brightness.
+const MAGNITUDE_PERCENTILE: f32 = 0.99;
+/// Hue step between consecutive kernels, the golden angle, so neighbours in
+/// the bank never get similar colors however many kernels it has.
+const HUE_STEP: f32 = 137.507_77;
+/// Below 1, so winners stay apart from the pure colors of other overlays.
+const SATURATION: f32 = 0.85;
+
+/// Which kernel responds most strongly at each pixel, folded in one kernel's
+/// map at a time so only the winner so far is kept.
+pub struct Winners {
+    pub width: usize,
+    pub height: usize,
+    /// Winning kernel of each pixel; `None` where every kernel gave 0 or a
+    /// non-finite value.
+    winner: Vec<Option<u32>>,
+    /// Winning magnitude of each pixel, after `scale`.
+    strength: Vec<f32>,
+}
+
+/// Pixels one kernel won.
+pub struct Share {
+    pub index: usize,
+    pub pixels: usize,
+    pub fraction: f32,
+}
+
+impl Winners {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            winner: vec![None; width * height],
+            strength: vec![0.0; width * height],
+        }
+    }
+
+    /// Folds in kernel `index`'s map, its magnitudes multiplied by `scale`
+    /// (1 to compare raw |r|). Ties keep the earlier kernel.
+    pub fn fold(&mut self, index: usize, response: &[f32], scale: f32) {
+        for ((winner, strength), &r) in self.winner.iter_mut().zip(&mut self.strength).zip(response)
+        {
+            let magnitude = r.abs() * scale;
+            if magnitude.is_finite() && magnitude > *strength {
+                (*winner, *strength) = (Some(index as u32), magnitude);
+            }
+        }
+    }
+
+    /// Pixels each kernel won, most first.
+    pub fn shares(&self) -> Vec<Share> {
+        let mut pixels = BTreeMap::new();
+        for index in self.winner.iter().flatten() {
+            *pixels.entry(*index as usize).or_insert(0) += 1;
+        }
+        let total = self.winner.len().max(1) as f32;
+        let mut shares: Vec<Share> = pixels
+            .into_iter()
+            .map(|(index, pixels)| Share {
+                index,
+                pixels,
+                fraction: pixels as f32 / total,
+            })
+            .collect();
+        shares.sort_by_key(|share| Reverse(share.pixels));
+        shares
+    }
+
+    /// Row-major RGB of the map at `width` x `height`, sampling the nearest
+    /// pixel: hue names the winning kernel (see [`kernel_color`]) and
+    /// brightness its magnitude. Pixels nobody won are black.
+    pub fn rgb(&self, width: usize, height: usize) -> Vec<u8> {
+        let sampled: Vec<usize> = (0..height)
+            .flat_map(|y| {
+                let sy = (y * self.height / height).min(self.height - 1);
+                (0..width)
+                    .map(move |x| sy * self.width + (x * self.width / width).min(self.width - 1))
+            })
+            .collect();
+        let mut sorted: Vec<f32> = sampled.iter().map(|&i| self.strength[i]).collect();
+        sorted.sort_by(f32::total_cmp);
+        let bright = sorted
+            .get(
+                ((sorted.len() as f32 * MAGNITUDE_PERCENTILE) as usize)
+                    .min(sorted.len().saturating_sub(1)),
+            )
+            .copied()
+            .unwrap_or(0.0)
+            .max(f32::MIN_POSITIVE);
+        sampled
+            .iter()
+            .flat_map(|&i| match self.winner[i] {
+                Some(index) => hsv_to_rgb(
+                    kernel_hue(index as usize),
+                    SATURATION,
+                    (self.strength[i] / bright).min(1.0),
+                ),
+                None => [0, 0, 0],
+            })
+            .collect()
+    }
+
+    /// The map at full resolution as an RGB PNG.
+    pub fn png(&self) -> Result<Vec<u8>, String> {
+        let rgb = self.rgb(self.width, self.height);
+        let image = RgbImage::from_raw(self.width as u32, self.height as u32, rgb)
+            .ok_or("Winner map size does not match its dimensions")?;
+        let mut bytes = Cursor::new(Vec::new());
+        image
+            .write_to(&mut bytes, ImageFormat::Png)
+            .map_err(|e| format!("PNG encoding failed: {e}"))?;
+        Ok(bytes.into_inner())
+    }
+}
+
+fn kernel_hue(index: usize) -> f32 {
+    (index as f32 * HUE_STEP).rem_euclid(360.0)
+}
+
+/// Color kernel `index` wins in, at full brightness, as the map's legend
+/// draws it.
+pub fn kernel_color(index: usize) -> [u8; 3] {
+    hsv_to_rgb(kernel_hue(index), SATURATION, 1.0)
+}
+
+/// Legend of the map, one line per kernel that won any pixel.
+pub fn shares_csv(shares: &[Share], name: impl Fn(usize) -> String) -> String {
+    let mut csv = String::from("kernel,name,color,pixels,fraction\n");
+    for share in shares {
+        let [r, g, b] = kernel_color(share.index);
+        csv.push_str(&format!(
+            "{},{},#{r:02x}{g:02x}{b:02x},{},{}\n",
+            share.index,
+            csv_field(&name(share.index)),
+            share.pixels,
+            share.fraction
+        ));
+    }
+    csv
+}
@@ -0,0 +1,237 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use js_sys::{Array, Float32Array};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsCast;
+use wasm_bindgen::prelude::{Closure, JsValue};
+use web_sys::{DedicatedWorkerGlobalScope, ErrorEvent, Event, MessageEvent, Worker};
+
+use crate::backend::{Backend, Sampling};
+use crate::border::BorderMode;
+use crate::channels::{self, ChannelMode};
+use crate::kernel::{Kernel, Provenance};
+use crate::morphology::FilterMode;
+
+/// Loader shim Trunk generates for the `convolution_worker` binary.
+const WORKER_SCRIPT: &str = "./convolution_worker_loader.js";
+/// Posted by the worker once it listens, since messages sent while its wasm
+/// is still loading would be dropped.
+const READY: &str = "ready";
+/// Prefix of the worker's error replies.
+const ERROR: &str = "error: ";
+
+/// What the worker convolves, sent as JSON next to the input pixels.
+#[derive(Serialize, Deserialize)]
+struct WorkerJob {
+    width: usize,
+    height: usize,
+    backend: Backend,
+    mode: FilterMode,
+    border: BorderMode,
+    sampling: Sampling,
+    channels: ChannelMode,
+    kernels: Vec<WorkerKernel>,
+}
+
+/// The parts of a [`Kernel`] its maps depend on.
+#[derive(Serialize, Deserialize)]
+struct WorkerKernel {
+    weights: Vec<f32>,
+    width: usize,
+    height: usize,
+    separable: Option<(Vec<f32>, Vec<f32>)>,
+    mask: Option<Vec<bool>>,
+    color: Option<[Vec<f32>; 3]>,
+}
+
+impl From<&Kernel> for WorkerKernel {
+    fn from(kernel: &Kernel) -> Self {
+        Self {
+            weights: kernel.weights.clone(),
+            width: kernel.width,
+            height: kernel.height,
+            separable: kernel.separable.clone(),
+            mask: kernel.mask.clone(),
+            color: kernel.color.clone(),
+        }
+    }
+}
+
+impl From<WorkerKernel> for Kernel {
+    fn from(kernel: WorkerKernel) -> Self {
+        Self {
+            separable: kernel.separable,
+            mask: kernel.mask,
+            color: kernel.color,
+            ..Kernel::new(
+                kernel.weights,
+                (kernel.width, kernel.height),
+                Provenance::Entered,
+            )
+        }
+    }
+}
+
+/// Maps received from the worker, in kernel order.
+#[derive(Default)]
+struct Inbox {
+    maps: Vec<Vec<f32>>,
+    error: Option<String>,
+}
+
+/// Full-resolution maps computed by a web worker, so long runs leave the
+/// browser's main thread free to draw. Dropping it stops the worker.
+pub struct WorkerRun {
+    worker: Worker,
+    inbox: Rc<RefCell<Inbox>>,
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+    _on_error: Closure<dyn FnMut(Event)>,
+}
+
+impl WorkerRun {
+    /// Starts a worker filtering `input`, or the color `planes` of a
+    /// multi-channel run, with `kernels` in order. Fails when the browser
+    /// cannot create it; a worker script that fails to load is reported by
+    /// [`WorkerRun::receive`].
+    pub fn start(
+        kernels: &[(usize, Kernel)],
+        input: &[f32],
+        planes: &[Vec<f32>],
+        (width, height): (usize, usize),
+        backend: Backend,
+        mode: FilterMode,
+        (border, sampling, channels): (BorderMode, Sampling, ChannelMode),
+    ) -> Result<Self, String> {
+        let job = WorkerJob {
+            width,
+            height,
+            backend,
+            mode,
+            border,
+            sampling,
+            channels,
+            kernels: kernels.iter().map(|(_, k)| k.into()).collect(),
+        };
+        let header = serde_json::to_string(&job).map_err(|e| e.to_string())?;
+        let message = Array::of2(&JsValue::from_str(&header), &Float32Array::from(input));
+        for plane in planes {
+            message.push(&Float32Array::from(plane.as_slice()));
+        }
+
+        let worker = Worker::new(WORKER_SCRIPT).map_err(js_error)?;
+        let inbox = Rc::new(RefCell::new(Inbox::default()));
+        let mut job = Some(message);
+        let (sender, received) = (worker.clone(), inbox.clone());
+        let on_message = Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+            let data = event.data();
+            let mut inbox = received.borrow_mut();
+            if let Some(map) = data.dyn_ref::<Float32Array>() {
+                inbox.maps.push(map.to_vec());
+            } else if let Some(text) = data.as_string() {
+                if text == READY {
+                    if let Some(job) = job.take()
+                        && let Err(e) = sender.post_message(&job)
+                    {
+                        inbox.error = Some(js_error(e));
+                    }
+                } else {
+                    let text = text.strip_prefix(ERROR).unwrap_or(&text);
+                    inbox.error = Some(text.to_owned());
+                }
+            }
+        });
+        let failed = inbox.clone();
+        let on_error = Closure::<dyn FnMut(Event)>::new(move |event: Event| {
+            let detail = event
+                .dyn_ref::<ErrorEvent>()
+                .map(|e| format!(": {}", e.message()))
+                .unwrap_or_default();
+            failed.borrow_mut().error = Some(format!("The convolution worker failed{detail}"));
+        });
+        worker.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        worker.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+        Ok(Self {
+            worker,
+            inbox,
+            _on_message: on_message,
+            _on_error: on_error,
+        })
+    }
+
+    /// Maps that arrived since the last call, in kernel order, or the
+    /// worker's error once it has failed.
+    pub fn receive(&mut self) -> Result<Vec<Vec<f32>>, String> {
+        let mut inbox = self.inbox.borrow_mut();
+        match inbox.error.take() {
+            Some(error) => Err(error),
+            None => Ok(std::mem::take(&mut inbox.maps)),
+        }
+    }
+}
+
+impl Drop for WorkerRun {
+    fn drop(&mut self) {
+        self.worker.terminate();
+    }
+}
+
+/// Entry point of the worker: answers each job with its maps, one message
+/// per kernel, as they are computed.
+pub fn worker_main() {
+    console_error_panic_hook::set_once();
+    let scope: DedicatedWorkerGlobalScope = js_sys::global().unchecked_into();
+    let reply = scope.clone();
+    let on_message = Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+        if let Err(e) = answer(&reply, event.data()) {
+            let _ = reply.post_message(&JsValue::from_str(&format!("{ERROR}{e}")));
+        }
+    });
+    scope.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    on_message.forget();
+    let _ = scope.post_message(&JsValue::from_str(READY));
+}
+
+fn answer(scope: &DedicatedWorkerGlobalScope, data: JsValue) -> Result<(), String> {
+    let message: Array = data.dyn_into().map_err(|_| "Malformed worker job")?;
+    let header = message.get(0).as_string().ok_or("Malformed worker job")?;
+    let job: WorkerJob =
+        serde_json::from_str(&header).map_err(|e| format!("Malformed worker job: {e}"))?;
+    let input = message
+        .get(1)
+        .dyn_into::<Float32Array>()
+        .map_err(|_| "Malformed worker job")?
+        .to_vec();
+    let planes = (2..message.length())
+        .map(|i| {
+            message
+                .get(i)
+                .dyn_into::<Float32Array>()
+                .map(|plane| plane.to_vec())
+                .map_err(|_| "Malformed worker job")
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let (mode, backend, border, sampling) = (job.mode, job.backend, job.border, job.sampling);
+    for kernel in job.kernels {
+        let (width, height) = (job.width, job.height);
+        let kernel = Kernel::from(kernel);
+        let response = if planes.is_empty() {
+            kernel.filter_sampled(mode, backend, border, sampling, &input, width, height)
+        } else {
+            let channels = job.channels;
+            channels::filter(
+                &kernel, channels, mode, backend, border, sampling, &planes, width, height,
+            )
+        };
+        // The map's buffer is moved to the main thread rather than copied.
+        let map = Float32Array::from(response.as_slice());
+        scope
+            .post_message_with_transfer(&map, &Array::of1(&map.buffer()))
+            .map_err(js_error)?;
+    }
+    Ok(())
+}
+
+fn js_error(err: JsValue) -> String {
+    format!("{err:?}")
+}